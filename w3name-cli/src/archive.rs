@@ -0,0 +1,761 @@
+//! Record archives: a signed record bundled with its name and where and when it came from, so it
+//! can be handed around as a single file.
+//!
+//! Archives are JSON objects (conventionally in `.w3r` files), written by `resolve --archive` and
+//! `publish --archive`, and read by `parse --archive`:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "name": "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu",
+//!   "record": "<base64-encoded IPNS record>",
+//!   "timestamp": "2022-10-01T12:00:00+00:00",
+//!   "source": "https://name.web3.storage/"
+//! }
+//! ```
+//!
+//! New optional fields may be added without changing `version`, and readers ignore fields they
+//! don't know. `version` is only bumped for changes that older readers can't handle.
+//!
+//! `publish --archive --countersign-key <key>` adds a `countersignature`, which attributes the
+//! archive to an operator key other than the name's, and makes changes to the archive evident:
+//!
+//! ```json
+//! "countersignature": {
+//!   "signer": "k51qzi5uqu5dhduwnl2t0mx3qi0o5o3gsbq7thy9nsr6snk3hbcjtznyk01241",
+//!   "signature": "<base64-encoded Ed25519 signature>"
+//! }
+//! ```
+//!
+//! The signature covers [COUNTERSIGNATURE_PREFIX] followed by the JSON array
+//! `[version, name, record, timestamp, source, signer]`, serialized without whitespace. Fields
+//! added later aren't covered.
+//!
+//! Given a directory, `--archive` writes the archive to a file named
+//! `<name>-seq<sequence>-<timestamp>.w3r` in it, e.g.
+//! `k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu-seq3-20221001T120000Z.w3r`, so
+//! that archives pile up side by side. `record ls` lists the archives in a directory.
+//!
+//! `resolve --verify-against` compares resolved records with archived ones, to detect a service
+//! (or mirror) serving an older record than one known to exist.
+
+use std::{
+  cmp::Ordering,
+  error::Error,
+  fmt::Display,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use error_stack::{report, AttachmentKind, FrameKind, IntoReport, Report, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use w3name::{
+  ipns::{deserialize_ipns_entry_untrusted, revision_from_ipns_entry_unchecked},
+  Name, Revision, WritableName,
+};
+
+use crate::{clock, platform, CliError};
+
+/// The extension of record archive files, which `--verify-against` and `record ls` look for in
+/// directories.
+const EXTENSION: &str = "w3r";
+
+/// A resolved record is older than an archived record for the same name, which means the
+/// resolver rolled the name back, or is a stale mirror.
+#[derive(Debug)]
+pub struct RolledBack;
+
+impl Display for RolledBack {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "resolved record is older than an archived record")
+  }
+}
+
+impl Error for RolledBack {}
+
+/// The newest archive format version this build can read, and the one it writes.
+const FORMAT_VERSION: u32 = 1;
+
+/// Prepended to the message that a [Countersignature] signs, so that it can't be mistaken for the
+/// signature of an IPNS record (`ipns-signature:`) or a proof (`w3name-proof:`), or vice versa.
+const COUNTERSIGNATURE_PREFIX: &str = "w3name-archive-countersignature:";
+
+/// An operator's signature over an archive, made with a key other than the name's.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Countersignature {
+  /// The name of the operator key that signed the archive.
+  pub signer: String,
+  /// The base64-encoded Ed25519 signature.
+  pub signature: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordArchive {
+  pub version: u32,
+  pub name: String,
+  /// The base64-encoded, signed IPNS record.
+  pub record: String,
+  /// When the record was fetched or published, as an RFC3339 timestamp.
+  pub timestamp: String,
+  /// Where the record came from or was published to, e.g. the w3name endpoint or a gateway URL.
+  pub source: String,
+  /// The operator's countersignature, if the archive was countersigned.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub countersignature: Option<Countersignature>,
+}
+
+impl RecordArchive {
+  /// Archives the signed `record` for `name`, timestamped now.
+  pub fn new(name: &Name, record: &[u8], source: &str) -> RecordArchive {
+    RecordArchive {
+      version: FORMAT_VERSION,
+      name: name.to_string(),
+      record: base64::encode(record),
+      timestamp: clock::now().to_rfc3339(),
+      source: source.to_string(),
+      countersignature: None,
+    }
+  }
+
+  /// Countersigns the archive with `operator`'s key, replacing any earlier countersignature.
+  pub fn countersign(&mut self, operator: &WritableName) -> Result<(), CliError> {
+    let signer = operator.to_name().to_string();
+    let signature = operator
+      .keypair()
+      .sign(&self.countersigned_message(&signer))
+      .report()
+      .change_context(CliError::Other)
+      .attach_printable("failed to countersign the archive")?;
+    self.countersignature = Some(Countersignature {
+      signer,
+      signature: base64::encode(signature),
+    });
+    Ok(())
+  }
+
+  /// Returns the name of the operator key that countersigned the archive, or `None` if it isn't
+  /// countersigned.
+  ///
+  /// Fails if the countersignature doesn't match the archive, i.e. the archive or the
+  /// countersignature were altered after signing.
+  pub fn countersigner(&self) -> Result<Option<Name>, CliError> {
+    let countersignature = match &self.countersignature {
+      Some(countersignature) => countersignature,
+      None => return Ok(None),
+    };
+    let signer = Name::parse(&countersignature.signer)
+      .change_context(CliError::Parse)
+      .attach_printable("the archive's countersigner is invalid")?;
+    let signature = base64::decode(&countersignature.signature)
+      .report()
+      .change_context(CliError::Parse)
+      .attach_printable("the archive's countersignature isn't valid base64")?;
+    let message = self.countersigned_message(&countersignature.signer);
+    if !signer.public_key().verify(&message, &signature) {
+      return Err(
+        report!(CliError::Parse)
+          .attach_printable("the archive's countersignature doesn't match its contents"),
+      );
+    }
+    Ok(Some(signer))
+  }
+
+  /// The bytes that a countersignature by `signer` signs (see the [module docs](self)).
+  fn countersigned_message(&self, signer: &str) -> Vec<u8> {
+    let fields = (
+      self.version,
+      &self.name,
+      &self.record,
+      &self.timestamp,
+      &self.source,
+      signer,
+    );
+    let mut message = COUNTERSIGNATURE_PREFIX.as_bytes().to_vec();
+    serde_json::to_writer(&mut message, &fields).expect("strings are always serializable");
+    message
+  }
+
+  pub fn from_json(bytes: &[u8]) -> Result<RecordArchive, CliError> {
+    let archive: RecordArchive = serde_json::from_slice(bytes)
+      .report()
+      .change_context(CliError::Parse)
+      .attach_printable("not a valid record archive")?;
+    if archive.version > FORMAT_VERSION {
+      return Err(report!(CliError::Parse).attach_printable(format!(
+        "unsupported record archive version {} (this version of w3name reads up to {})",
+        archive.version, FORMAT_VERSION
+      )));
+    }
+    Ok(archive)
+  }
+
+  pub fn read(path: &Path) -> Result<RecordArchive, CliError> {
+    let bytes = fs::read(path)
+      .report()
+      .change_context(CliError::Usage)
+      .attach_printable(format!("failed to read archive {}", path.display()))?;
+    RecordArchive::from_json(&bytes).attach_printable(format!("archive: {}", path.display()))
+  }
+
+  /// Writes the archive to `path`, or to a file named after it (see [file_name](Self::file_name))
+  /// if `path` is a directory. Returns the path of the file written.
+  pub fn write(&self, path: &Path) -> Result<PathBuf, CliError> {
+    let path = if path.is_dir() {
+      path.join(self.file_name()?)
+    } else {
+      path.to_path_buf()
+    };
+    let json = serde_json::to_vec_pretty(self)
+      .report()
+      .change_context(CliError::Other)?;
+    platform::atomic_write(&path, &json)
+      .report()
+      .change_context(CliError::Other)
+      .attach_printable(format!("failed to write archive {}", path.display()))?;
+    Ok(path)
+  }
+
+  /// The file name for the archive in a directory of archives:
+  /// `<name>-seq<sequence>-<timestamp>.w3r`, with the timestamp in UTC and without separators, so
+  /// that it's a valid file name everywhere.
+  pub fn file_name(&self) -> Result<String, CliError> {
+    let sequence = self.revision()?.sequence();
+    let timestamp = DateTime::parse_from_rfc3339(&self.timestamp)
+      .report()
+      .change_context(CliError::Parse)
+      .attach_printable("the archive's timestamp is invalid")?
+      .with_timezone(&Utc);
+    Ok(format!(
+      "{}-seq{}-{}.{}",
+      self.name,
+      sequence,
+      timestamp.format("%Y%m%dT%H%M%SZ"),
+      EXTENSION
+    ))
+  }
+
+  pub fn name(&self) -> Result<Name, CliError> {
+    Name::parse(&self.name)
+      .change_context(CliError::Parse)
+      .attach_printable("the archive's name is invalid")
+  }
+
+  /// The signed record. It isn't validated; use [crate::inspect::inspect] for that.
+  pub fn record_bytes(&self) -> Result<Vec<u8>, CliError> {
+    base64::decode(&self.record)
+      .report()
+      .change_context(CliError::Parse)
+      .attach_printable("the archive's record isn't valid base64")
+  }
+
+  /// The archived revision, after validating the record against the archive's name.
+  pub fn revision(&self) -> Result<Revision, CliError> {
+    Revision::from_signed_bytes(&self.name()?, &self.record_bytes()?)
+      .change_context(CliError::Parse)
+      .attach_printable("the archive's record is invalid")
+  }
+}
+
+/// Returns the newest archived revision of `name` in `path`, which is either an archive file or a
+/// directory of `.w3r` archives. Archives of other names in a directory are ignored.
+pub fn newest_archived(path: &Path, name: &Name) -> Result<Revision, CliError> {
+  if !path.is_dir() {
+    let archive = RecordArchive::read(path)?;
+    if archive.name()? != *name {
+      return Err(report!(CliError::Usage).attach_printable(format!(
+        "{} is an archive of {}, not {}",
+        path.display(),
+        archive.name,
+        name
+      )));
+    }
+    return archive
+      .revision()
+      .attach_printable(format!("archive: {}", path.display()));
+  }
+
+  let entries = fs::read_dir(path)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!("failed to read {}", path.display()))?;
+  let mut newest: Option<Revision> = None;
+  for entry in entries {
+    let file = entry
+      .report()
+      .change_context(CliError::Usage)
+      .attach_printable(format!("failed to read {}", path.display()))?
+      .path();
+    if file.extension().is_none_or(|ext| ext != EXTENSION) {
+      continue;
+    }
+    let archive = RecordArchive::read(&file)?;
+    if archive.name != name.to_string() {
+      continue;
+    }
+    let revision = archive
+      .revision()
+      .attach_printable(format!("archive: {}", file.display()))?;
+    if newest
+      .as_ref()
+      .is_none_or(|newest| revision.supersedes(newest))
+    {
+      newest = Some(revision);
+    }
+  }
+  newest.ok_or_else(|| {
+    report!(CliError::Usage).attach_printable(format!(
+      "no archived records of {} in {}",
+      name,
+      path.display()
+    ))
+  })
+}
+
+/// Fails with [RolledBack] if `archived` supersedes `resolved`. The same or a newer record is fine.
+pub fn check_not_rolled_back(resolved: &Revision, archived: &Revision) -> Result<(), CliError> {
+  if archived.supersedes(resolved) {
+    return Err(
+      report!(RolledBack)
+        .attach_printable(format!(
+          "resolved sequence {} (validity {}), but sequence {} (validity {}) is archived",
+          resolved.sequence(),
+          resolved.validity_string(),
+          archived.sequence(),
+          archived.validity_string()
+        ))
+        .change_context(CliError::Resolve),
+    );
+  }
+  Ok(())
+}
+
+/// Returns the files that `arg` stands for: the `.w3r` files in it if it's a directory, or the
+/// files matching it if its last component has `*` or `?` wildcards (for shells that don't expand
+/// them), or else `arg` itself. The files are sorted by path.
+pub fn expand_path(arg: &Path) -> Result<Vec<PathBuf>, CliError> {
+  let pattern = arg
+    .file_name()
+    .and_then(|name| name.to_str())
+    .filter(|name| name.contains(['*', '?']));
+  let (dir, pattern) = match pattern {
+    Some(pattern) => (
+      arg
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new(".")),
+      pattern,
+    ),
+    None if arg.is_dir() => (arg, "*.w3r"),
+    None if arg.exists() => return Ok(vec![arg.to_path_buf()]),
+    None => {
+      return Err(
+        report!(CliError::Usage).attach_printable(format!("{} doesn't exist", arg.display())),
+      )
+    }
+  };
+
+  let entries = fs::read_dir(dir)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!("failed to read {}", dir.display()))?;
+  let mut files = vec![];
+  for entry in entries {
+    let file = entry
+      .report()
+      .change_context(CliError::Usage)
+      .attach_printable(format!("failed to read {}", dir.display()))?
+      .path();
+    let matches = file
+      .file_name()
+      .and_then(|name| name.to_str())
+      .is_some_and(|name| wildcard_match(pattern, name));
+    if matches && file.is_file() {
+      files.push(file);
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+/// Returns `true` if `name` matches `pattern`, where `*` matches any run of characters and `?`
+/// any one character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+  let pattern: Vec<char> = pattern.chars().collect();
+  let name: Vec<char> = name.chars().collect();
+  let (mut p, mut n) = (0, 0);
+  // where the last `*` was, and how much of the name it has matched, to backtrack to
+  let mut star: Option<(usize, usize)> = None;
+  while n < name.len() {
+    match pattern.get(p) {
+      Some('*') => {
+        star = Some((p, n));
+        p += 1;
+      }
+      Some(&c) if c == '?' || c == name[n] => {
+        p += 1;
+        n += 1;
+      }
+      _ => match star {
+        Some((star_p, star_n)) => {
+          p = star_p + 1;
+          n = star_n + 1;
+          star = Some((star_p, star_n + 1));
+        }
+        None => return false,
+      },
+    }
+  }
+  pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// A file listed by `record ls`, with as much as could be read from it.
+#[derive(Debug)]
+pub struct ArchiveListing {
+  pub path: PathBuf,
+  /// The archive's name, if the file is an archive.
+  pub name: Option<String>,
+  /// The archived revision, if the record could be decoded, even if it isn't valid.
+  pub revision: Option<Revision>,
+  /// Whether the record is valid for the archive's name, and its countersignature, if any, is valid.
+  pub verified: bool,
+  /// The name of the operator key that countersigned the archive, if it has a valid countersignature.
+  pub countersigner: Option<String>,
+  /// What's wrong with the file, if anything.
+  pub error: Option<String>,
+}
+
+impl ArchiveListing {
+  /// Reads the archive at `path`. Files that aren't valid archives are listed with an error
+  /// rather than failing.
+  pub fn read(path: PathBuf) -> ArchiveListing {
+    let mut listing = ArchiveListing {
+      path,
+      name: None,
+      revision: None,
+      verified: false,
+      countersigner: None,
+      error: None,
+    };
+    if let Err(err_report) = listing.fill() {
+      listing.error = Some(describe(&err_report));
+    }
+    listing
+  }
+
+  fn fill(&mut self) -> Result<(), CliError> {
+    // the file's path is in the listing already, so it's not attached like `read` does
+    let bytes = fs::read(&self.path)
+      .report()
+      .change_context(CliError::Usage)
+      .attach_printable("failed to read the file")?;
+    let archive = RecordArchive::from_json(&bytes)?;
+    self.name = Some(archive.name.clone());
+    let name = archive.name()?;
+    let record = archive.record_bytes()?;
+    match archive.revision() {
+      Ok(revision) => {
+        self.revision = Some(revision);
+        self.countersigner = archive.countersigner()?.map(|signer| signer.to_string());
+        self.verified = true;
+        Ok(())
+      }
+      Err(err_report) => {
+        // show what the record claims anyway, since it's marked as unverified
+        self.revision = deserialize_ipns_entry_untrusted(&record)
+          .ok()
+          .and_then(|entry| revision_from_ipns_entry_unchecked(&entry, &name).ok());
+        Err(err_report)
+      }
+    }
+  }
+
+  pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+    self
+      .revision
+      .as_ref()
+      .is_some_and(|revision| revision.is_expired_at(now))
+  }
+}
+
+/// Describes what went wrong in one line: the last message attached to `err_report`, which
+/// [RecordArchive]'s methods always attach.
+fn describe(err_report: &Report<CliError>) -> String {
+  err_report
+    .frames()
+    .find_map(|frame| match frame.kind() {
+      FrameKind::Attachment(AttachmentKind::Printable(attachment)) => Some(attachment.to_string()),
+      _ => None,
+    })
+    .unwrap_or_else(|| err_report.current_context().to_string())
+}
+
+/// How `record ls` sorts archives. Ties are broken by path, and unless they're sorted by path,
+/// files whose records can't be read are listed last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ListOrder {
+  /// By name, then by sequence.
+  Name,
+  /// By sequence, then by name.
+  Sequence,
+  /// By when the records expire.
+  Validity,
+  /// By path.
+  Path,
+}
+
+/// Sorts `listings` by `order`, stably.
+pub fn sort_listings(listings: &mut [ArchiveListing], order: ListOrder) {
+  listings.sort_by(|a, b| {
+    if order == ListOrder::Path {
+      return a.path.cmp(&b.path);
+    }
+    let by_order = match (&a.revision, &b.revision) {
+      (Some(ra), Some(rb)) => match order {
+        ListOrder::Name => a.name.cmp(&b.name).then(ra.sequence().cmp(&rb.sequence())),
+        ListOrder::Sequence => ra.sequence().cmp(&rb.sequence()).then(a.name.cmp(&b.name)),
+        ListOrder::Validity | ListOrder::Path => ra.validity().cmp(rb.validity()),
+      },
+      (Some(_), None) => Ordering::Less,
+      (None, Some(_)) => Ordering::Greater,
+      (None, None) => Ordering::Equal,
+    };
+    by_order.then_with(|| a.path.cmp(&b.path))
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use w3name::WritableName;
+
+  #[test]
+  fn round_trip() {
+    let name = WritableName::new().to_name();
+    let archive = RecordArchive::new(&name, b"record bytes", "https://name.web3.storage/");
+    let path = std::env::temp_dir().join(format!("w3name-archive-{}.w3r", std::process::id()));
+    archive.write(&path).unwrap();
+
+    let read = RecordArchive::read(&path).unwrap();
+    assert_eq!(read, archive);
+    assert_eq!(read.name().unwrap(), name);
+    assert_eq!(read.record_bytes().unwrap(), b"record bytes");
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn rollback_detection() {
+    let dir = std::env::temp_dir().join(format!("w3name-archive-rollback-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let key = WritableName::new();
+    let name = key.to_name();
+    let v0 = Revision::v0(&name, "/ipfs/v0");
+    let v1 = v0.increment("/ipfs/v1");
+    let v2 = v1.increment("/ipfs/v2");
+    for (file, revision) in [("v0.w3r", &v0), ("v1.w3r", &v1)] {
+      let record = revision.to_signed_bytes(&key).unwrap();
+      RecordArchive::new(&name, &record, "test")
+        .write(&dir.join(file))
+        .unwrap();
+    }
+    // archives of other names, and other files, are ignored
+    let other = WritableName::new();
+    let record = Revision::v0(&other.to_name(), "/ipfs/other")
+      .increment("/ipfs/other")
+      .to_signed_bytes(&other)
+      .unwrap();
+    RecordArchive::new(&other.to_name(), &record, "test")
+      .write(&dir.join("other.w3r"))
+      .unwrap();
+    fs::write(dir.join("notes.txt"), "not an archive").unwrap();
+
+    let archived = newest_archived(&dir, &name).unwrap();
+    assert_eq!(archived, v1);
+    assert_eq!(newest_archived(&dir.join("v0.w3r"), &name).unwrap(), v0);
+    assert_eq!(
+      newest_archived(&dir, &other.to_name()).unwrap().sequence(),
+      1
+    );
+    let unknown = WritableName::new().to_name();
+    assert!(newest_archived(&dir, &unknown).is_err());
+
+    check_not_rolled_back(&v1, &archived).unwrap();
+    check_not_rolled_back(&v2, &archived).unwrap();
+    let err = check_not_rolled_back(&v0, &archived).unwrap_err();
+    assert!(err.contains::<RolledBack>());
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn file_names() {
+    let dir = std::env::temp_dir().join(format!("w3name-archive-names-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let key = WritableName::new();
+    let name = key.to_name();
+    let revision = Revision::v0(&name, "/ipfs/v0").increment("/ipfs/v1");
+    let mut archive = RecordArchive::new(&name, &revision.to_signed_bytes(&key).unwrap(), "test");
+    archive.timestamp = "2022-10-01T14:00:00+02:00".to_string();
+    let expected = format!("{}-seq1-20221001T120000Z.w3r", name);
+    assert_eq!(archive.file_name().unwrap(), expected);
+
+    // a directory gets a file named after the archive, anything else is the file
+    assert_eq!(archive.write(&dir).unwrap(), dir.join(&expected));
+    assert_eq!(RecordArchive::read(&dir.join(&expected)).unwrap(), archive);
+    let file = dir.join("chosen.w3r");
+    assert_eq!(archive.write(&file).unwrap(), file);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn wildcards() {
+    assert!(wildcard_match("*.w3r", "k51-seq0.w3r"));
+    assert!(wildcard_match(
+      "k51*-seq?-*",
+      "k51abc-seq3-20221001T120000Z.w3r"
+    ));
+    assert!(wildcard_match("*", ""));
+    assert!(wildcard_match("a*b*c", "abbbc"));
+    assert!(!wildcard_match("*.w3r", "notes.txt"));
+    assert!(!wildcard_match(
+      "k51*-seq?-*",
+      "k51abc-seq12-20221001T120000Z.w3r"
+    ));
+    assert!(!wildcard_match("?", ""));
+  }
+
+  #[test]
+  fn listing_order() {
+    let name = WritableName::new().to_name();
+    let listing = |path: &str, sequence: u64, days: i64| {
+      let mut previous = Revision::v0(&name, "/ipfs/value");
+      for _ in 1..sequence {
+        previous = previous.increment("/ipfs/value");
+      }
+      let revision = w3name::RevisionBuilder::next(&previous, "/ipfs/value")
+        .validity_for(chrono::Duration::days(days))
+        .build()
+        .unwrap();
+      ArchiveListing {
+        path: PathBuf::from(path),
+        name: Some(name.to_string()),
+        revision: Some(revision),
+        verified: true,
+        countersigner: None,
+        error: None,
+      }
+    };
+    let broken = |path: &str| ArchiveListing {
+      path: PathBuf::from(path),
+      name: None,
+      revision: None,
+      verified: false,
+      countersigner: None,
+      error: Some("not a valid record archive".to_string()),
+    };
+    let paths = |order: ListOrder| {
+      let mut listings = vec![
+        broken("a.w3r"),
+        listing("d.w3r", 1, 5),
+        listing("c.w3r", 2, 1),
+        listing("b.w3r", 1, 3),
+        broken("0.w3r"),
+      ];
+      sort_listings(&mut listings, order);
+      listings
+        .iter()
+        .map(|listing| listing.path.display().to_string())
+        .collect::<Vec<_>>()
+    };
+
+    // ties are broken by path, and unreadable files go last, in path order
+    assert_eq!(
+      paths(ListOrder::Name),
+      ["b.w3r", "d.w3r", "c.w3r", "0.w3r", "a.w3r"]
+    );
+    assert_eq!(
+      paths(ListOrder::Sequence),
+      ["b.w3r", "d.w3r", "c.w3r", "0.w3r", "a.w3r"]
+    );
+    assert_eq!(
+      paths(ListOrder::Validity),
+      ["c.w3r", "b.w3r", "d.w3r", "0.w3r", "a.w3r"]
+    );
+    assert_eq!(
+      paths(ListOrder::Path),
+      ["0.w3r", "a.w3r", "b.w3r", "c.w3r", "d.w3r"]
+    );
+  }
+
+  #[test]
+  fn countersignatures() {
+    let key = WritableName::new();
+    let name = key.to_name();
+    let record = Revision::v0(&name, "/ipfs/v0")
+      .to_signed_bytes(&key)
+      .unwrap();
+    let operator = WritableName::new();
+
+    // archives aren't countersigned by default
+    let mut archive = RecordArchive::new(&name, &record, "https://name.web3.storage/");
+    assert_eq!(archive.countersigner().unwrap(), None);
+    let json = serde_json::to_string(&archive).unwrap();
+    assert!(!json.contains("countersignature"));
+
+    archive.countersign(&operator).unwrap();
+    assert_eq!(archive.countersigner().unwrap(), Some(operator.to_name()));
+    let json = serde_json::to_vec(&archive).unwrap();
+    let read = RecordArchive::from_json(&json).unwrap();
+    assert_eq!(read.countersigner().unwrap(), Some(operator.to_name()));
+
+    // changing any field, or the signer, breaks the countersignature
+    let tampered: [fn(&mut RecordArchive); 5] = [
+      |archive| archive.record = base64::encode(b"another record"),
+      |archive| archive.timestamp = "2022-10-01T12:00:00+00:00".to_string(),
+      |archive| archive.source = "https://mirror.example/".to_string(),
+      |archive| archive.name = WritableName::new().to_name().to_string(),
+      |archive| {
+        let signer = WritableName::new().to_name().to_string();
+        archive.countersignature.as_mut().unwrap().signer = signer;
+      },
+    ];
+    for tamper in tampered {
+      let mut copy = RecordArchive::from_json(&json).unwrap();
+      tamper(&mut copy);
+      let err = copy.countersigner().unwrap_err();
+      assert!(format!("{:?}", err).contains("doesn't match its contents"));
+    }
+
+    // the signature is domain-separated, so the operator's other signatures don't verify
+    let message = archive.countersigned_message(&operator.to_name().to_string());
+    assert!(message.starts_with(b"w3name-archive-countersignature:["));
+    let unprefixed = operator
+      .keypair()
+      .sign(&message[COUNTERSIGNATURE_PREFIX.len()..])
+      .unwrap();
+    archive.countersignature.as_mut().unwrap().signature = base64::encode(unprefixed);
+    assert!(archive.countersigner().is_err());
+  }
+
+  #[test]
+  fn forward_compatibility() {
+    let json = br#"{
+      "version": 1,
+      "name": "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu",
+      "record": "cmVjb3Jk",
+      "timestamp": "2022-10-01T12:00:00+00:00",
+      "source": "https://name.web3.storage/",
+      "added_later": {"nested": true}
+    }"#;
+    let archive = RecordArchive::from_json(json).unwrap();
+    assert_eq!(archive.record_bytes().unwrap(), b"record");
+
+    let newer = String::from_utf8_lossy(json).replace("\"version\": 1", "\"version\": 2");
+    let err = RecordArchive::from_json(newer.as_bytes()).unwrap_err();
+    assert!(format!("{:?}", err).contains("unsupported record archive version 2"));
+  }
+}
@@ -0,0 +1,419 @@
+//! The hidden `bench` subcommand, for operators of self-hosted w3name services to measure their
+//! service's latency and throughput.
+//!
+//! A run sends a fixed number of requests through a fixed number of concurrent workers, using
+//! the same client as the other commands, and reports latency percentiles over the successful
+//! requests, failed requests by error class, and throughput. Failed requests aren't retried
+//! unless asked to, so the service's raw behavior is measured.
+
+use std::{
+  collections::BTreeMap,
+  future::Future,
+  num::NonZeroU32,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use error_stack::{report, Report, Result, ResultExt};
+use w3name::{
+  error::{APIError, ClientError, NetworkCause, RecordTooLarge},
+  Name, Revision, W3NameClient, WritableName,
+};
+
+use crate::{
+  error_output::{self, InvolvedName},
+  service::{self, Network},
+  table::{Cell, Style, Table},
+  CliError,
+};
+
+/// Options shared by the benchmarks.
+pub struct BenchOptions {
+  /// The number of requests to send.
+  pub requests: usize,
+  /// The number of requests in flight at once.
+  pub concurrency: usize,
+  /// How many times a request that failed with a network error, 429 Too Many Requests or a 5xx
+  /// status is retried. Only the last attempt's outcome counts, but the latency includes every
+  /// attempt.
+  pub retries: u32,
+  /// Fails requests that take longer than this.
+  pub timeout: Duration,
+  /// The client's rate limit, in requests per second.
+  pub rate_limit: NonZeroU32,
+  pub json: bool,
+  /// Whether network access is allowed. Fails immediately if it isn't.
+  pub network: Network,
+}
+
+/// What `bench publish` uploads.
+pub enum PublishMode {
+  /// Successors of the current record, with the same value, so the name's value doesn't change
+  /// but its sequence number does.
+  Successors,
+  /// The current record itself, unchanged, every time, so the name isn't changed at all.
+  DryUpload,
+}
+
+/// Latency percentiles and error counts of a benchmark run.
+#[derive(Debug, PartialEq)]
+pub struct BenchStats {
+  pub requests: usize,
+  pub succeeded: usize,
+  /// Failed requests by error class, e.g. `http_503` or `network_timeout`.
+  pub errors: BTreeMap<String, usize>,
+  /// Latency percentiles of the successful requests, or `None` if none succeeded.
+  pub latency: Option<Latency>,
+  /// How long the whole run took.
+  pub elapsed: Duration,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Latency {
+  pub min: Duration,
+  pub mean: Duration,
+  pub p50: Duration,
+  pub p90: Duration,
+  pub p99: Duration,
+  pub max: Duration,
+}
+
+impl BenchStats {
+  /// Summarizes a run from the latencies of its successful requests and the error class of each
+  /// failed one.
+  pub fn new(mut latencies: Vec<Duration>, failures: Vec<String>, elapsed: Duration) -> BenchStats {
+    latencies.sort();
+    let mut errors = BTreeMap::new();
+    for class in failures.iter() {
+      *errors.entry(class.clone()).or_insert(0) += 1;
+    }
+    BenchStats {
+      requests: latencies.len() + failures.len(),
+      succeeded: latencies.len(),
+      errors,
+      latency: Latency::of_sorted(&latencies),
+      elapsed,
+    }
+  }
+
+  pub fn failed(&self) -> usize {
+    self.requests - self.succeeded
+  }
+
+  /// Completed requests per second, successful or not.
+  pub fn throughput(&self) -> f64 {
+    match self.elapsed.as_secs_f64() {
+      secs if secs > 0.0 => self.requests as f64 / secs,
+      _ => 0.0,
+    }
+  }
+}
+
+impl Latency {
+  fn of_sorted(sorted: &[Duration]) -> Option<Latency> {
+    let total: Duration = sorted.iter().sum();
+    Some(Latency {
+      min: *sorted.first()?,
+      mean: total / sorted.len() as u32,
+      p50: percentile(sorted, 50.0)?,
+      p90: percentile(sorted, 90.0)?,
+      p99: percentile(sorted, 99.0)?,
+      max: *sorted.last()?,
+    })
+  }
+}
+
+/// Returns the `p`th percentile of `sorted` by the nearest-rank method: the smallest value that
+/// at least `p` percent of the values are less than or equal to. `None` if `sorted` is empty.
+pub fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+  if sorted.is_empty() {
+    return None;
+  }
+  let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+  Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Benchmarks resolving `name`.
+pub async fn resolve(name: &Name, opts: &BenchOptions) -> Result<(), CliError> {
+  let client = client(opts, "bench resolve")?;
+  let stats = run(opts, |_| client.resolve(name)).await;
+  print(&stats, "resolve", opts);
+  Ok(())
+}
+
+/// Benchmarks publishing records for `key`'s name, which must have a record already.
+///
+/// Fails without sending a request if the client would talk to the public w3name service, unless
+/// `own_endpoint` is set.
+pub async fn publish(
+  key: &WritableName,
+  mode: PublishMode,
+  own_endpoint: bool,
+  opts: &BenchOptions,
+) -> Result<(), CliError> {
+  let client = client(opts, "bench publish")?;
+  let public = w3name::default_endpoint();
+  if client.endpoint().host_str() == public.host_str() && !own_endpoint {
+    return Err(
+      report!(CliError::Usage)
+        .attach_printable(format!(
+          "refusing to benchmark publishing against the public w3name service at {}",
+          public
+        ))
+        .attach_printable(
+          "point $W3NAME_ENDPOINT at your own service, or pass --i-own-this-endpoint",
+        ),
+    );
+  }
+
+  // everything is signed up front, so signing doesn't count towards the latency
+  let name = key.to_name();
+  let current = client
+    .fetch_record(&name)
+    .await
+    .change_context(CliError::Publish)
+    .attach_printable("the name needs a record to benchmark publishing; publish one first")
+    .attach_printable(InvolvedName(name.to_string()))?;
+  let records = match mode {
+    PublishMode::DryUpload => vec![current; opts.requests],
+    PublishMode::Successors => {
+      let mut revision =
+        Revision::from_signed_bytes(&name, &current).change_context(CliError::Publish)?;
+      let mut records = Vec::with_capacity(opts.requests);
+      for _ in 0..opts.requests {
+        revision = revision.increment(revision.value());
+        records.push(
+          revision
+            .to_signed_bytes(key)
+            .change_context(CliError::Publish)?,
+        );
+      }
+      records
+    }
+  };
+
+  let stats = run(opts, |i| client.publish_record(&name, records[i].clone())).await;
+  print(&stats, "publish", opts);
+  Ok(())
+}
+
+fn client(opts: &BenchOptions, purpose: &str) -> Result<W3NameClient, CliError> {
+  service::client_builder(opts.network, purpose)?
+    .timeout(opts.timeout)
+    .rate_limit(opts.rate_limit)
+    .build()
+    .change_context(CliError::Other)
+}
+
+/// Sends `opts.requests` requests with `request`, which is given the index of each, and times
+/// them.
+async fn run<F, Fut, T>(opts: &BenchOptions, request: F) -> BenchStats
+where
+  F: Fn(usize) -> Fut,
+  Fut: Future<Output = std::result::Result<T, Report<ClientError>>>,
+{
+  let next = AtomicUsize::new(0);
+  let latencies = Mutex::new(Vec::with_capacity(opts.requests));
+  let failures = Mutex::new(vec![]);
+  let worker = || async {
+    loop {
+      let i = next.fetch_add(1, Ordering::Relaxed);
+      if i >= opts.requests {
+        break;
+      }
+      let start = Instant::now();
+      let mut attempt = 0;
+      let outcome = loop {
+        match request(i).await {
+          Ok(_) => break Ok(()),
+          Err(err_report) => {
+            let class = error_class(&err_report);
+            if attempt >= opts.retries || !is_retryable(&class) {
+              log::debug!("Request {} failed: {:?}", i, err_report);
+              break Err(class);
+            }
+            attempt += 1;
+          }
+        }
+      };
+      match outcome {
+        Ok(()) => latencies.lock().unwrap().push(start.elapsed()),
+        Err(class) => failures.lock().unwrap().push(class),
+      }
+    }
+  };
+
+  let start = Instant::now();
+  futures::future::join_all((0..opts.concurrency.max(1)).map(|_| worker())).await;
+  BenchStats::new(
+    latencies.into_inner().unwrap(),
+    failures.into_inner().unwrap(),
+    start.elapsed(),
+  )
+}
+
+/// Classifies a failed request: `http_<status>` for an error response, `network_<cause>` for a
+/// request that got no response (see [NetworkCause]), `too_large` for an oversized response, or
+/// `other`.
+fn error_class(err_report: &Report<ClientError>) -> String {
+  if let Some(err) = err_report.downcast_ref::<APIError>() {
+    format!("http_{}", err.status_code.as_u16())
+  } else if let Some(cause) = err_report.downcast_ref::<NetworkCause>() {
+    format!("network_{}", error_output::cause_id(cause))
+  } else if err_report.contains::<RecordTooLarge>() {
+    "too_large".to_string()
+  } else {
+    "other".to_string()
+  }
+}
+
+fn is_retryable(class: &str) -> bool {
+  class.starts_with("network_") || class == "http_429" || class.starts_with("http_5")
+}
+
+fn print(stats: &BenchStats, operation: &str, opts: &BenchOptions) {
+  if opts.json {
+    println!("{}", stats_json(stats, operation, opts));
+    return;
+  }
+  let ms = |d: Option<Duration>| d.map_or("-".to_string(), format_ms);
+  let latency = stats.latency.as_ref();
+  let mut table = Table::new([
+    "OPERATION",
+    "REQUESTS",
+    "ERRORS",
+    "P50",
+    "P90",
+    "P99",
+    "MAX",
+    "THROUGHPUT",
+  ]);
+  let errors_style = if stats.failed() > 0 {
+    Style::Bad
+  } else {
+    Style::Plain
+  };
+  table.row([
+    operation.into(),
+    stats.requests.to_string().into(),
+    Cell::styled(stats.failed().to_string(), errors_style),
+    ms(latency.map(|l| l.p50)).into(),
+    ms(latency.map(|l| l.p90)).into(),
+    ms(latency.map(|l| l.p99)).into(),
+    ms(latency.map(|l| l.max)).into(),
+    format!("{:.1}/s", stats.throughput()).into(),
+  ]);
+  table.print();
+  if !stats.errors.is_empty() {
+    println!();
+    let mut table = Table::new(["ERROR", "COUNT"]);
+    for (class, count) in &stats.errors {
+      table.row([
+        Cell::styled(class.clone(), Style::Bad),
+        count.to_string().into(),
+      ]);
+    }
+    table.print();
+  }
+}
+
+fn format_ms(duration: Duration) -> String {
+  format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn stats_json(stats: &BenchStats, operation: &str, opts: &BenchOptions) -> serde_json::Value {
+  let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+  serde_json::json!({
+    "operation": operation,
+    "requests": stats.requests,
+    "succeeded": stats.succeeded,
+    "failed": stats.failed(),
+    "concurrency": opts.concurrency,
+    "elapsed_ms": ms(stats.elapsed),
+    "throughput": stats.throughput(),
+    "latency_ms": stats.latency.as_ref().map(|l| serde_json::json!({
+      "min": ms(l.min),
+      "mean": ms(l.mean),
+      "p50": ms(l.p50),
+      "p90": ms(l.p90),
+      "p99": ms(l.p99),
+      "max": ms(l.max),
+    })),
+    "errors": stats.errors,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn millis(values: &[u64]) -> Vec<Duration> {
+    values.iter().copied().map(Duration::from_millis).collect()
+  }
+
+  #[test]
+  fn percentiles() {
+    let sorted = millis(&(1..=100).collect::<Vec<_>>());
+    assert_eq!(percentile(&sorted, 50.0), Some(Duration::from_millis(50)));
+    assert_eq!(percentile(&sorted, 90.0), Some(Duration::from_millis(90)));
+    assert_eq!(percentile(&sorted, 99.0), Some(Duration::from_millis(99)));
+    assert_eq!(percentile(&sorted, 100.0), Some(Duration::from_millis(100)));
+    assert_eq!(percentile(&sorted, 0.0), Some(Duration::from_millis(1)));
+
+    // with few values, the percentiles are values that were actually measured
+    let sorted = millis(&[10, 20, 30, 40]);
+    assert_eq!(percentile(&sorted, 50.0), Some(Duration::from_millis(20)));
+    assert_eq!(percentile(&sorted, 90.0), Some(Duration::from_millis(40)));
+    assert_eq!(
+      percentile(&millis(&[7]), 99.0),
+      Some(Duration::from_millis(7))
+    );
+    assert_eq!(percentile(&[], 50.0), None);
+  }
+
+  #[test]
+  fn summarizes_runs() {
+    let stats = BenchStats::new(
+      millis(&[30, 10, 20, 40]),
+      vec![
+        "http_503".into(),
+        "network_timeout".into(),
+        "http_503".into(),
+      ],
+      Duration::from_secs(2),
+    );
+    assert_eq!(stats.requests, 7);
+    assert_eq!(stats.succeeded, 4);
+    assert_eq!(stats.failed(), 3);
+    assert_eq!(stats.errors["http_503"], 2);
+    assert_eq!(stats.errors["network_timeout"], 1);
+    assert_eq!(stats.throughput(), 3.5);
+    assert_eq!(
+      stats.latency,
+      Some(Latency {
+        min: Duration::from_millis(10),
+        mean: Duration::from_millis(25),
+        p50: Duration::from_millis(20),
+        p90: Duration::from_millis(40),
+        p99: Duration::from_millis(40),
+        max: Duration::from_millis(40),
+      })
+    );
+
+    let stats = BenchStats::new(vec![], vec!["other".into()], Duration::ZERO);
+    assert_eq!(stats.latency, None);
+    assert_eq!(stats.throughput(), 0.0);
+  }
+
+  #[test]
+  fn retries_only_transient_failures() {
+    assert!(is_retryable("network_timeout"));
+    assert!(is_retryable("http_503"));
+    assert!(is_retryable("http_429"));
+    assert!(!is_retryable("http_404"));
+    assert!(!is_retryable("too_large"));
+  }
+}
@@ -0,0 +1,550 @@
+//! A disk cache of resolved records, shared between CLI invocations.
+//!
+//! The cache lives in `$XDG_CACHE_HOME/w3name`, or the platform's cache directory (see
+//! [platform]), and is a [FileRecordStore]: each name's signed record is in its own file. Entries are validated again
+//! when they're read, so a tampered or corrupt entry is treated as a cache miss.
+//!
+//! An entry is fresh for the record's TTL after it was fetched, but for no longer than
+//! [MAX_FRESHNESS_SECS] (or for `--max-age`, if given), and never after the record has expired.
+//! When it was fetched is the time it was stored. Publishing a new record for a name removes its
+//! entry (see [RecordCache::forget]), so a `resolve` right after a publish asks the service.
+//!
+//! Next to the records, `<name>.seen` holds when the name's latest sequence was first seen (see
+//! [record_age](crate::record_age)), `stats.json` counts cache hits and misses across runs, and
+//! `.locks/` holds the locks that keep `cache prune` from removing an entry while `resolve`
+//! replaces it:
+//!
+//! ```text
+//! ~/.cache/w3name/
+//!   <name>.ipns
+//!   <name>.seen
+//!   stats.json
+//!   .locks/<name>.lock
+//!   .locks/stats.lock
+//! ```
+
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+  time::Instant,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use error_stack::{IntoReport, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use w3name::{
+  error::IpnsError, FileRecordStore, Name, RecordStore, ResolvedRevision, Revision, SourceInfo,
+  SourceKind, StoredRecord,
+};
+
+use crate::{clock, lock::FileLock, platform, CliError};
+
+const STATS_FILE: &str = "stats.json";
+
+/// How long an entry is fresh for at most, without `--max-age`. A record's TTL is often days long,
+/// so the cache would otherwise hide updates published from other machines for that long.
+const MAX_FRESHNESS_SECS: i64 = 60;
+const LOCKS_DIR: &str = ".locks";
+
+/// How long to wait for another process to finish with a cache entry. Cache writes are quick,
+/// so a lock held longer than this belongs to a stuck process.
+const LOCK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A cached record, as listed by `cache show`.
+#[derive(Debug)]
+pub struct CacheEntry {
+  pub name: Name,
+  pub value: String,
+  pub sequence: u64,
+  /// When the record was fetched.
+  pub fetched_at: DateTime<Utc>,
+  /// When the record's sequence was first seen, if a `resolve` noted it.
+  pub first_seen_at: Option<DateTime<Utc>>,
+  pub ttl: Duration,
+  /// When the record expires.
+  pub validity: DateTime<Utc>,
+}
+
+impl CacheEntry {
+  /// How long ago the record was fetched.
+  pub fn age(&self) -> Duration {
+    // like the store's timestamps, on the system clock
+    Utc::now() - self.fetched_at
+  }
+
+  /// How much of the record's TTL is left, which is negative once it has run out.
+  pub fn ttl_remaining(&self) -> Duration {
+    self.ttl - self.age()
+  }
+
+  /// How much longer `resolve` would use the entry, without `--max-age`, which is negative once
+  /// it's stale.
+  pub fn fresh_remaining(&self) -> Duration {
+    default_max_age(self.ttl) - self.age()
+  }
+
+  /// Returns true if `resolve` would use the entry, without `--max-age`.
+  pub fn is_fresh(&self) -> bool {
+    self.fresh_remaining() > Duration::zero() && self.validity > clock::now()
+  }
+}
+
+/// Cache hits and misses of `resolve`, counted across runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+  pub hits: u64,
+  pub misses: u64,
+}
+
+pub struct RecordCache {
+  store: FileRecordStore,
+}
+
+impl RecordCache {
+  /// Opens the cache in the default location (see [platform::cache_dir]).
+  pub fn open_default() -> Option<RecordCache> {
+    platform::cache_dir().map(RecordCache::at)
+  }
+
+  /// Opens the cache in the default location for storing records, creating its directory.
+  ///
+  /// Returns `None` if there's no default location, or the directory can't be created, e.g. on a
+  /// read-only filesystem: records just aren't cached then.
+  pub fn open_writable() -> Option<RecordCache> {
+    let Some(cache) = RecordCache::open_default() else {
+      log::debug!("No cache directory; not caching records");
+      return None;
+    };
+    match platform::create_private_dir(cache.dir()) {
+      Ok(()) => Some(cache),
+      Err(err) => {
+        log::debug!(
+          "Can't create the cache directory {} ({}); not caching records",
+          cache.dir().display(),
+          err
+        );
+        None
+      }
+    }
+  }
+
+  /// Opens the cache in `dir`. The directory doesn't need to exist.
+  pub fn at(dir: PathBuf) -> RecordCache {
+    RecordCache {
+      store: FileRecordStore::new(dir),
+    }
+  }
+
+  pub fn dir(&self) -> &Path {
+    self.store.dir()
+  }
+
+  /// Returns the cached revision for `name`, if there is a fresh one.
+  ///
+  /// An entry is fresh if it was fetched less than `max_age` ago (defaulting to the record's TTL,
+  /// capped to [MAX_FRESHNESS_SECS]), and the record hasn't expired. Missing, stale and corrupt entries all return `None`.
+  ///
+  /// The revision's `fetched_at` is when the entry was written, and its `first_seen_at` is when its
+  /// sequence was first seen, if that was noted (see [first_seen](Self::first_seen)).
+  pub fn get(&self, name: &Name, max_age: Option<Duration>) -> Option<ResolvedRevision> {
+    let started = Instant::now();
+    let fresh = self
+      .store
+      .get(name)
+      .change_context(IpnsError)
+      .and_then(|stored| match stored {
+        Some(stored) => fresh_revision(name, &stored, max_age),
+        None => Ok(None),
+      });
+    match fresh {
+      Ok(fresh) => fresh.map(|(revision, fetched_at)| {
+        let first_seen_at = self.noted_first_seen(name, revision.sequence());
+        let source = SourceInfo {
+          kind: SourceKind::Cache,
+          url: None,
+          elapsed: started.elapsed(),
+          expiry_checked: true,
+        };
+        ResolvedRevision::cached(revision, source, fetched_at).with_first_seen_at(first_seen_at)
+      }),
+      Err(err_report) => {
+        log::debug!(
+          "Ignoring corrupt cache entry {}: {:?}",
+          self.store.record_path(name).display(),
+          err_report
+        );
+        None
+      }
+    }
+  }
+
+  /// Like [get](Self::get), but also counts the lookup as a hit or a miss in the [stats](Self::stats).
+  pub fn lookup(&self, name: &Name, max_age: Option<Duration>) -> Option<ResolvedRevision> {
+    let resolved = self.get(name, max_age);
+    if let Err(err_report) = self.count_lookup(resolved.is_some()) {
+      log::debug!("Unable to update the cache stats: {:?}", err_report);
+    }
+    resolved
+  }
+
+  /// Stores the signed `record` for `name`, replacing any existing entry.
+  ///
+  /// Concurrent readers never see a partially written entry (see [FileRecordStore]), and the
+  /// entry is locked while it's written, so [prune](Self::prune) can't remove the new record.
+  pub fn put(&self, name: &Name, record: &[u8]) -> Result<(), CliError> {
+    let _lock = self.lock(&format!("{}.lock", name))?;
+    self.store.put(name, record).change_context(CliError::Other)
+  }
+
+  /// Notes that `name`'s record with `sequence` was seen `at`, and returns when that sequence was
+  /// first seen (see [RecordStore::note_seen]).
+  pub fn first_seen(
+    &self,
+    name: &Name,
+    sequence: u64,
+    at: DateTime<Utc>,
+  ) -> Result<DateTime<Utc>, CliError> {
+    let _lock = self.lock(&format!("{}.lock", name))?;
+    self
+      .store
+      .note_seen(name, sequence, at)
+      .change_context(CliError::Other)
+  }
+
+  /// Returns when `name`'s record with `sequence` was first seen, without noting it. Unreadable
+  /// notes are ignored.
+  fn noted_first_seen(&self, name: &Name, sequence: u64) -> Option<DateTime<Utc>> {
+    self
+      .store
+      .first_seen(name, sequence)
+      .unwrap_or_else(|err_report| {
+        log::debug!("Ignoring unreadable first-seen time: {:?}", err_report);
+        None
+      })
+  }
+
+  /// Returns the entry for `name`, if there's a readable one, whether it's fresh or not.
+  pub fn entry(&self, name: &Name) -> Option<CacheEntry> {
+    let stored = self.store.get(name).ok()??;
+    let revision = Revision::from_signed_bytes(name, &stored.record).ok()?;
+    Some(CacheEntry {
+      name: name.clone(),
+      value: revision.value().to_string(),
+      sequence: revision.sequence(),
+      fetched_at: stored.stored_at,
+      first_seen_at: self.noted_first_seen(name, revision.sequence()),
+      ttl: revision.ttl(),
+      validity: *revision.validity(),
+    })
+  }
+
+  /// Returns all readable entries in the cache, sorted by name.
+  pub fn entries(&self) -> Vec<CacheEntry> {
+    let names = self.store.list().unwrap_or_default();
+    names.iter().filter_map(|name| self.entry(name)).collect()
+  }
+
+  /// Returns the number of entries and their total size in bytes.
+  pub fn size(&self) -> (usize, u64) {
+    let names = self.store.list().unwrap_or_default();
+    let bytes = names
+      .iter()
+      .filter_map(|name| fs::metadata(self.store.record_path(name)).ok())
+      .map(|metadata| metadata.len())
+      .sum();
+    (names.len(), bytes)
+  }
+
+  /// Removes the entry for `name` from the default cache, if there is one, after a new record was
+  /// published for it, so that the next `resolve` doesn't return the old one. Failures are only
+  /// logged: the entry goes stale within [MAX_FRESHNESS_SECS] anyway.
+  pub fn forget(name: &Name) {
+    let Some(cache) = RecordCache::open_default() else {
+      return;
+    };
+    if !cache.dir().exists() {
+      return;
+    }
+    match cache.remove(name) {
+      Ok(true) => log::debug!("Removed the cached record for {}", name),
+      Ok(false) => {}
+      Err(err_report) => log::debug!(
+        "Unable to remove the cached record for {}: {:?}",
+        name,
+        err_report
+      ),
+    }
+  }
+
+  /// Removes the entry for `name`. Returns false if there wasn't one.
+  pub fn remove(&self, name: &Name) -> Result<bool, CliError> {
+    let _lock = self.lock(&format!("{}.lock", name))?;
+    self.store.delete(name).change_context(CliError::Other)
+  }
+
+  /// Removes every file from the cache directory but the stats, returning the number of files
+  /// removed.
+  ///
+  /// This includes entries written by older versions of `w3name`, which are otherwise ignored.
+  pub fn clear(&self) -> io::Result<usize> {
+    let dir_entries = match fs::read_dir(self.dir()) {
+      Ok(entries) => entries,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+      Err(err) => return Err(err),
+    };
+    let mut removed = 0;
+    for entry in dir_entries {
+      let path = entry?.path();
+      if path.is_file() && !path.ends_with(STATS_FILE) {
+        fs::remove_file(path)?;
+        removed += 1;
+      }
+    }
+    Ok(removed)
+  }
+
+  /// Removes the entries that `resolve` would no longer use: those whose TTL has run out, whose
+  /// record has expired, or that are corrupt. Returns the names of the removed entries.
+  ///
+  /// Each entry is locked while it's checked and removed, so a record that a concurrent
+  /// `resolve` is writing is never removed. Entries that stay locked are skipped.
+  pub fn prune(&self) -> Result<Vec<Name>, CliError> {
+    let names = self
+      .store
+      .list()
+      .change_context(CliError::Other)
+      .attach_printable(format!("cache directory: {}", self.dir().display()))?;
+    let mut removed = vec![];
+    for name in names {
+      let _lock = match self.lock(&format!("{}.lock", name)) {
+        Ok(lock) => lock,
+        Err(err_report) => {
+          log::warn!("skipping cache entry for {}: {:?}", name, err_report);
+          continue;
+        }
+      };
+      let stale = match self.store.get(&name) {
+        Ok(Some(stored)) => !matches!(fresh_revision(&name, &stored, None), Ok(Some(_))),
+        Ok(None) => false,
+        Err(_) => true,
+      };
+      if stale && self.store.delete(&name).change_context(CliError::Other)? {
+        removed.push(name);
+      }
+    }
+    Ok(removed)
+  }
+
+  /// Returns the hits and misses counted so far. Missing or unreadable stats count as zero.
+  pub fn stats(&self) -> CacheStats {
+    fs::read(self.dir().join(STATS_FILE))
+      .ok()
+      .and_then(|json| serde_json::from_slice(&json).ok())
+      .unwrap_or_default()
+  }
+
+  fn count_lookup(&self, hit: bool) -> Result<(), CliError> {
+    let _lock = self.lock("stats.lock")?;
+    let mut stats = self.stats();
+    if hit {
+      stats.hits += 1;
+    } else {
+      stats.misses += 1;
+    }
+    let path = self.dir().join(STATS_FILE);
+    let json = serde_json::to_vec(&stats).expect("stats can be serialized");
+    platform::atomic_write(&path, &json)
+      .report()
+      .change_context(CliError::Other)
+      .attach_printable(format!("stats file: {}", path.display()))
+  }
+
+  /// Takes the lock called `file` in the cache's lock directory.
+  fn lock(&self, file: &str) -> Result<FileLock, CliError> {
+    let path = self.dir().join(LOCKS_DIR).join(file);
+    FileLock::acquire(&path, LOCK_TIMEOUT, "another use of the cache entry")
+  }
+}
+
+/// How long an entry with `ttl` is fresh for, without `--max-age`.
+fn default_max_age(ttl: Duration) -> Duration {
+  ttl.min(Duration::seconds(MAX_FRESHNESS_SECS))
+}
+
+/// Validates a cache entry, returning its revision and when it was fetched, or `Ok(None)` if it's
+/// valid but no longer fresh.
+fn fresh_revision(
+  name: &Name,
+  stored: &StoredRecord,
+  max_age: Option<Duration>,
+) -> Result<Option<(Revision, DateTime<Utc>)>, IpnsError> {
+  let revision = Revision::from_signed_bytes(name, &stored.record)?;
+
+  // the store's timestamps come from the system clock, so the age is measured with it too
+  let age = Utc::now().signed_duration_since(stored.stored_at);
+  let max_age = max_age.unwrap_or_else(|| default_max_age(revision.ttl()));
+  if age >= max_age || revision.is_expired_at(clock::now()) {
+    return Ok(None);
+  }
+  Ok(Some((revision, stored.stored_at)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use w3name::{
+    ipns::{revision_to_ipns_entry, serialize_ipns_entry},
+    WritableName,
+  };
+
+  fn temp_cache(test: &str) -> RecordCache {
+    let dir = std::env::temp_dir().join(format!("w3name-cache-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    RecordCache::at(dir)
+  }
+
+  fn signed_record(name: &WritableName, revision: &Revision) -> Vec<u8> {
+    let entry = revision_to_ipns_entry(revision, name.keypair()).unwrap();
+    serialize_ipns_entry(&entry).unwrap()
+  }
+
+  #[test]
+  fn round_trip_and_max_age() {
+    let cache = temp_cache("round-trip");
+    let writable = WritableName::new();
+    let name = writable.to_name();
+    assert!(cache.get(&name, None).is_none());
+
+    let revision = Revision::v0(&name, "/ipfs/cached");
+    cache
+      .put(&name, &signed_record(&writable, &revision))
+      .unwrap();
+    let cached = cache.get(&name, None).unwrap();
+    assert!(cached.from_cache && cached.source.kind == SourceKind::Cache);
+    assert!(cached.fetched_at <= Utc::now());
+    assert_eq!(cached.into_revision(), revision);
+    assert!(cache.get(&name, Some(Duration::zero())).is_none());
+
+    assert_eq!(cache.entries().len(), 1);
+    assert_eq!(cache.clear().unwrap(), 1);
+    assert!(cache.get(&name, None).is_none());
+    fs::remove_dir_all(cache.dir()).unwrap();
+  }
+
+  #[test]
+  fn freshness_is_capped() {
+    let writable = WritableName::new();
+    let name = writable.to_name();
+    let revision = Revision::v0(&name, "/ipfs/long-ttl");
+    assert!(revision.ttl() > Duration::days(1));
+    let stored = |age| StoredRecord {
+      record: signed_record(&writable, &revision),
+      stored_at: Utc::now() - age,
+    };
+
+    let fresh = |age, max_age| fresh_revision(&name, &stored(age), max_age).unwrap();
+    assert!(fresh(Duration::seconds(30), None).is_some());
+    assert!(fresh(Duration::minutes(2), None).is_none());
+    assert!(fresh(Duration::minutes(2), Some(Duration::hours(1))).is_some());
+  }
+
+  #[test]
+  fn cached_revisions_carry_first_seen() {
+    let cache = temp_cache("first-seen");
+    let writable = WritableName::new();
+    let name = writable.to_name();
+    let v0 = Revision::v0(&name, "/ipfs/first");
+    cache.put(&name, &signed_record(&writable, &v0)).unwrap();
+    // entries cached before their sequence was noted have no first-seen time
+    assert_eq!(cache.get(&name, None).unwrap().first_seen_at, None);
+    assert_eq!(cache.entry(&name).unwrap().first_seen_at, None);
+
+    let seen = Utc::now() - Duration::hours(1);
+    cache.first_seen(&name, 0, seen).unwrap();
+    assert_eq!(cache.get(&name, None).unwrap().first_seen_at, Some(seen));
+    assert_eq!(cache.entry(&name).unwrap().first_seen_at, Some(seen));
+
+    // a new sequence hasn't been seen yet
+    let v1 = v0.increment("/ipfs/second");
+    cache.put(&name, &signed_record(&writable, &v1)).unwrap();
+    assert_eq!(cache.get(&name, None).unwrap().first_seen_at, None);
+    let now = Utc::now();
+    assert_eq!(cache.first_seen(&name, 1, now).unwrap(), now);
+    assert_eq!(cache.get(&name, None).unwrap().first_seen_at, Some(now));
+    fs::remove_dir_all(cache.dir()).unwrap();
+  }
+
+  #[test]
+  fn corrupt_entries_are_ignored() {
+    let cache = temp_cache("corrupt");
+    let writable = WritableName::new();
+    let name = writable.to_name();
+    fs::create_dir_all(cache.dir()).unwrap();
+
+    fs::write(cache.store.record_path(&name), b"not a record").unwrap();
+    assert!(cache.get(&name, None).is_none());
+    assert!(cache.entries().is_empty());
+
+    // a record signed by a different key doesn't validate
+    let other = WritableName::new();
+    let revision = Revision::v0(&name, "/ipfs/forged");
+    cache.put(&name, &signed_record(&other, &revision)).unwrap();
+    assert!(cache.get(&name, None).is_none());
+
+    // and a corrupt entry is simply overwritten by the next put
+    cache
+      .put(&name, &signed_record(&writable, &revision))
+      .unwrap();
+    assert_eq!(
+      cache.get(&name, None).map(ResolvedRevision::into_revision),
+      Some(revision)
+    );
+    fs::remove_dir_all(cache.dir()).unwrap();
+  }
+
+  #[test]
+  fn prune_and_stats() {
+    let cache = temp_cache("prune");
+    let writable = WritableName::new();
+    let fresh = writable.to_name();
+    let revision = Revision::v0(&fresh, "/ipfs/fresh");
+    cache
+      .put(&fresh, &signed_record(&writable, &revision))
+      .unwrap();
+
+    // a record whose TTL has run out, and a corrupt one
+    let other = WritableName::new();
+    let stale = other.to_name();
+    let revision = Revision::v0_with_validity(
+      &stale,
+      "/ipfs/stale",
+      Utc::now() + Duration::days(1),
+      Duration::zero(),
+    );
+    cache
+      .put(&stale, &signed_record(&other, &revision))
+      .unwrap();
+    let corrupt = WritableName::new().to_name();
+    cache.put(&corrupt, b"not a record").unwrap();
+
+    let mut expected = vec![stale.to_string(), corrupt.to_string()];
+    expected.sort();
+    let removed: Vec<String> = cache.prune().unwrap().iter().map(Name::to_string).collect();
+    assert_eq!(removed, expected);
+    assert_eq!(cache.entries().len(), 1);
+    assert!(cache.entry(&fresh).unwrap().is_fresh());
+
+    assert_eq!(cache.stats(), CacheStats::default());
+    assert!(cache.lookup(&fresh, None).is_some());
+    assert!(cache.lookup(&stale, None).is_none());
+    assert!(cache.lookup(&fresh, None).is_some());
+    assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 1 });
+
+    // clearing keeps the stats
+    assert!(cache.remove(&fresh).unwrap());
+    assert!(!cache.remove(&fresh).unwrap());
+    cache.clear().unwrap();
+    assert_eq!(cache.stats().hits, 2);
+    fs::remove_dir_all(cache.dir()).unwrap();
+  }
+}
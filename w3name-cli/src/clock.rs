@@ -0,0 +1,26 @@
+//! The clock that commands read the current time from: the system clock, or a fixed time given
+//! with the hidden `--frozen-time` flag, so that output involving the time can be tested.
+//!
+//! Log timestamps always come from the system clock.
+
+use std::sync::{Arc, OnceLock};
+
+use chrono::{DateTime, Utc};
+use w3name::{Clock, ManualClock, SystemClock};
+
+static CLOCK: OnceLock<Arc<dyn Clock>> = OnceLock::new();
+
+/// Stops the clock at `at`, for the rest of the process. Has no effect after the clock was read.
+pub fn freeze(at: DateTime<Utc>) {
+  let _ = CLOCK.set(Arc::new(ManualClock::new(at)));
+}
+
+/// Returns the clock, to hand to the library.
+pub fn shared() -> Arc<dyn Clock> {
+  CLOCK.get_or_init(|| Arc::new(SystemClock)).clone()
+}
+
+/// Returns the current time.
+pub fn now() -> DateTime<Utc> {
+  shared().now()
+}
@@ -0,0 +1,150 @@
+//! Shell completion scripts, including dynamic completion of names from the local keystore.
+//!
+//! The static part of each script is generated by clap_complete. For bash, zsh and fish, a small
+//! snippet is appended that calls the hidden `w3name __complete names` helper at completion time,
+//! so names and aliases from the keystore are offered for subcommands that take a name.
+//!
+//! The helper is dispatched by [run_helper] before clap parses the arguments, so it never shows up
+//! in help output or in the generated scripts.
+
+use std::io::Write;
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::{keystore::Keystore, Cli};
+
+/// Subcommands whose first positional argument is a name (or alias).
+const NAME_SUBCOMMANDS: &[&str] = &["resolve", "watch"];
+
+/// Writes the completion script for `shell` to `out`.
+pub fn generate(shell: Shell, out: &mut dyn Write) -> std::io::Result<()> {
+  let mut cmd = Cli::command();
+  clap_complete::generate(shell, &mut cmd, "w3name", out);
+  if let Some(snippet) = dynamic_snippet(shell) {
+    writeln!(out)?;
+    out.write_all(snippet.as_bytes())?;
+  }
+  Ok(())
+}
+
+/// Handles `w3name __complete <kind>`, printing one completion candidate per line.
+///
+/// Unknown kinds print nothing, so old completion scripts degrade gracefully.
+pub fn run_helper(kind: Option<&str>) {
+  if kind == Some("names") {
+    let keystore = Keystore::open_default();
+    for name in complete_names(keystore.as_ref()) {
+      println!("{name}");
+    }
+  }
+}
+
+/// Returns the key names and aliases to offer wherever a name is expected.
+///
+/// Returns an empty list if there's no keystore.
+pub fn complete_names(keystore: Option<&Keystore>) -> Vec<String> {
+  let keystore = match keystore {
+    Some(keystore) => keystore,
+    None => return vec![],
+  };
+  let mut names = keystore.key_names();
+  names.extend(keystore.aliases().into_keys());
+  names
+}
+
+fn dynamic_snippet(shell: Shell) -> Option<String> {
+  let subcommands = NAME_SUBCOMMANDS.join(" ");
+  match shell {
+    Shell::Bash => Some(format!(
+      r#"_w3name_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    if [[ ${{COMP_CWORD}} -eq 2 && ${{cur}} != -* ]]; then
+        case "${{COMP_WORDS[1]}}" in
+            {cases})
+                COMPREPLY=( $(compgen -W "$(w3name __complete names 2>/dev/null)" -- "${{cur}}") )
+                return 0
+                ;;
+        esac
+    fi
+    _w3name "$@"
+}}
+
+complete -F _w3name_dynamic -o bashdefault -o default w3name
+"#,
+      cases = NAME_SUBCOMMANDS.join("|")
+    )),
+    Shell::Zsh => Some(format!(
+      r#"_w3name_dynamic() {{
+    if (( CURRENT == 3 )) && [[ ${{words[2]}} == ({cases}) && ${{words[CURRENT]}} != -* ]]; then
+        local -a names
+        names=(${{(f)"$(w3name __complete names 2>/dev/null)"}})
+        compadd -a names
+        return
+    fi
+    _w3name "$@"
+}}
+
+compdef _w3name_dynamic w3name
+"#,
+      cases = NAME_SUBCOMMANDS.join("|")
+    )),
+    Shell::Fish => Some(format!(
+      "complete -c w3name -n \"__fish_seen_subcommand_from {subcommands}\" -f -a \"(w3name __complete names 2>/dev/null)\"\n"
+    )),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  fn script(shell: Shell) -> String {
+    let mut out = Vec::new();
+    generate(shell, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn dynamic_snippets() {
+    for (shell, snapshot) in [
+      (
+        Shell::Bash,
+        include_str!("../testdata/completions/dynamic.bash"),
+      ),
+      (
+        Shell::Zsh,
+        include_str!("../testdata/completions/dynamic.zsh"),
+      ),
+      (
+        Shell::Fish,
+        include_str!("../testdata/completions/dynamic.fish"),
+      ),
+    ] {
+      let script = script(shell);
+      assert!(script.contains("w3name"));
+      assert!(script.ends_with(snapshot), "{shell} snippet changed");
+    }
+    assert!(dynamic_snippet(Shell::PowerShell).is_none());
+  }
+
+  #[test]
+  fn complete_from_keystore() {
+    let root = std::env::temp_dir().join(format!("w3name-completions-{}", std::process::id()));
+    let keystore = Keystore::at(root.clone());
+    assert!(complete_names(Some(&keystore)).is_empty());
+    assert!(complete_names(None).is_empty());
+
+    fs::create_dir_all(keystore.keys_dir()).unwrap();
+    fs::write(keystore.keys_dir().join("k51bbb.key"), b"").unwrap();
+    fs::write(keystore.keys_dir().join("k51aaa.key"), b"").unwrap();
+    fs::write(keystore.keys_dir().join("notes.txt"), b"").unwrap();
+    fs::write(root.join("aliases.json"), br#"{"blog": "k51aaa"}"#).unwrap();
+
+    let names = complete_names(Some(&keystore));
+    fs::remove_dir_all(&root).unwrap();
+    assert_eq!(names, vec!["k51aaa", "k51bbb", "blog"]);
+  }
+}
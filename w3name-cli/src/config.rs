@@ -0,0 +1,176 @@
+//! The config file, `config.toml` in the state directory (see [platform](crate::platform)), and
+//! the environment variables that override it.
+//!
+//! ```toml
+//! [defaults]
+//! validity = "30d"
+//! ttl = "10m"
+//! ```
+//!
+//! `[defaults]` replaces the built-in validity window and TTL of every record the CLI signs.
+//! `$W3NAME_DEFAULT_VALIDITY` and `$W3NAME_DEFAULT_TTL` take precedence over the file, and flags
+//! like `publish --validity` and `--ttl` over both.
+//!
+//! The config is loaded once, when the CLI starts, so a mistake in it fails every command rather
+//! than the next publish.
+
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+  sync::OnceLock,
+  time::Duration,
+};
+
+use error_stack::{report, Report, Result, ResultExt};
+use serde::Deserialize;
+use w3name::Defaults;
+
+use crate::{chrono_duration, platform, CliError};
+
+pub const FILE_NAME: &str = "config.toml";
+pub const DEFAULT_VALIDITY_VAR: &str = "W3NAME_DEFAULT_VALIDITY";
+pub const DEFAULT_TTL_VAR: &str = "W3NAME_DEFAULT_TTL";
+
+static DEFAULTS: OnceLock<Defaults> = OnceLock::new();
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+  #[serde(default)]
+  defaults: DefaultsSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DefaultsSection {
+  validity: Option<String>,
+  ttl: Option<String>,
+}
+
+/// Returns the path of the config file, if there's a state directory.
+pub fn path() -> Option<PathBuf> {
+  platform::state_dir().map(|dir| dir.join(FILE_NAME))
+}
+
+/// Reads the config file, if there is one, and the environment, failing if either has an invalid
+/// setting. Call it once, at startup; [defaults] returns the result.
+pub fn load() -> Result<(), CliError> {
+  let file = match path() {
+    Some(path) => read(&path)?,
+    None => None,
+  };
+  let defaults = defaults_from(file.as_ref(), &|var| std::env::var(var).ok())?;
+  if defaults != Defaults::default() {
+    log::debug!("Record defaults: {:?}", defaults);
+  }
+  let _ = DEFAULTS.set(defaults);
+  Ok(())
+}
+
+/// Returns the validity window and TTL for new records, from the environment and the config file.
+pub fn defaults() -> Defaults {
+  DEFAULTS.get().copied().unwrap_or_default()
+}
+
+fn read(path: &Path) -> Result<Option<(PathBuf, ConfigFile)>, CliError> {
+  let text = match fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+    Err(err) => {
+      return Err(
+        Report::new(err)
+          .change_context(CliError::Usage)
+          .attach_printable(format!("couldn't read the config file {}", path.display())),
+      )
+    }
+  };
+  let config = toml::from_str(&text).map_err(|err| {
+    report!(CliError::Usage)
+      .attach_printable(err.to_string())
+      .attach_printable(format!("invalid config file {}", path.display()))
+  })?;
+  Ok(Some((path.to_path_buf(), config)))
+}
+
+/// Combines the config file's `[defaults]` with the environment, which takes precedence.
+fn defaults_from(
+  file: Option<&(PathBuf, ConfigFile)>,
+  var: &dyn Fn(&str) -> Option<String>,
+) -> Result<Defaults, CliError> {
+  let setting = |var_name: &str, key: &str, from_file: Option<&String>| {
+    let (value, source) = match var(var_name).filter(|value| !value.is_empty()) {
+      Some(value) => (value, format!("${}", var_name)),
+      None => match (file, from_file) {
+        (Some((path, _)), Some(value)) => (
+          value.clone(),
+          format!("defaults.{} in {}", key, path.display()),
+        ),
+        _ => return Ok(None),
+      },
+    };
+    parse_duration(&value)
+      .attach_printable_lazy(|| format!("invalid duration {:?} in {}", value, source))
+      .map(Some)
+  };
+  let section = file.map(|(_, config)| &config.defaults);
+  Ok(Defaults {
+    validity: setting(
+      DEFAULT_VALIDITY_VAR,
+      "validity",
+      section.and_then(|section| section.validity.as_ref()),
+    )?,
+    ttl: setting(
+      DEFAULT_TTL_VAR,
+      "ttl",
+      section.and_then(|section| section.ttl.as_ref()),
+    )?,
+  })
+}
+
+fn parse_duration(value: &str) -> Result<chrono::Duration, CliError> {
+  let duration: Duration = humantime::parse_duration(value)
+    .map_err(|err| report!(CliError::Usage).attach_printable(err.to_string()))?;
+  chrono_duration(duration)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn file(toml: &str) -> (PathBuf, ConfigFile) {
+    (PathBuf::from("config.toml"), toml::from_str(toml).unwrap())
+  }
+
+  #[test]
+  fn precedence() {
+    let config = file("[defaults]\nvalidity = \"30d\"\nttl = \"10m\"\n");
+    let no_env = |_: &str| None;
+
+    assert_eq!(defaults_from(None, &no_env).unwrap(), Defaults::default());
+    let defaults = defaults_from(Some(&config), &no_env).unwrap();
+    assert_eq!(defaults.validity, Some(chrono::Duration::days(30)));
+    assert_eq!(defaults.ttl, Some(chrono::Duration::minutes(10)));
+
+    // the environment wins over the file, one setting at a time
+    let env = |var: &str| (var == DEFAULT_TTL_VAR).then(|| "1h".to_string());
+    let defaults = defaults_from(Some(&config), &env).unwrap();
+    assert_eq!(defaults.validity, Some(chrono::Duration::days(30)));
+    assert_eq!(defaults.ttl, Some(chrono::Duration::hours(1)));
+    let defaults = defaults_from(None, &env).unwrap();
+    assert_eq!(defaults.validity, None);
+  }
+
+  #[test]
+  fn invalid_settings() {
+    let no_env = |_: &str| None;
+    let err =
+      defaults_from(Some(&file("[defaults]\nttl = \"10 parsecs\"\n")), &no_env).unwrap_err();
+    assert!(format!("{:?}", err).contains("defaults.ttl in config.toml"));
+
+    let env = |_: &str| Some("soon".to_string());
+    let err = defaults_from(None, &env).unwrap_err();
+    assert!(format!("{:?}", err).contains("$W3NAME_DEFAULT_VALIDITY"));
+
+    assert!(toml::from_str::<ConfigFile>("[defaults]\nvalidty = \"30d\"\n").is_err());
+  }
+}
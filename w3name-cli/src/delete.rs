@@ -0,0 +1,277 @@
+//! The `key delete` and `alias rm` subcommands, which remove keys and aliases from the keystore.
+//!
+//! A deleted key can't be recovered from the service, and without it the name can never be
+//! updated again. So `key delete` asks for the key's fingerprint before deleting anything, and by
+//! default moves the key file to the keystore's trash rather than removing it.
+
+use std::{
+  collections::BTreeMap,
+  io::{self, BufRead, Write},
+};
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use w3name::Name;
+
+use crate::{
+  cache::RecordCache,
+  clock,
+  key_json::{self, DeleteJson, KeyJson},
+  keyfile,
+  keystore::Keystore,
+  CliError,
+};
+
+/// The number of trailing characters of a name that make up its fingerprint.
+const FINGERPRINT_LEN: usize = 8;
+
+/// Options for [delete_key].
+pub struct DeleteOptions<'a> {
+  /// Delete without asking for the fingerprint.
+  pub yes: bool,
+
+  /// Remove the key file, instead of moving it to the trash.
+  pub purge: bool,
+
+  /// The record cache, used to warn if the name has a record that's still valid.
+  pub cache: Option<&'a RecordCache>,
+
+  /// Print the result as JSON.
+  pub json: bool,
+}
+
+/// Returns the short fingerprint of a key called `name`: the last few characters of the name,
+/// which differ between keys.
+pub fn fingerprint(name: &str) -> &str {
+  let start = name
+    .char_indices()
+    .rev()
+    .nth(FINGERPRINT_LEN - 1)
+    .map_or(0, |(i, _)| i);
+  &name[start..]
+}
+
+/// Deletes the key called `arg`, or the key an alias called `arg` refers to, along with every
+/// alias that refers to it.
+///
+/// Unless `opts.yes` is set, a line is read from `confirmation`, and nothing is deleted unless
+/// it's the key's fingerprint.
+pub fn delete_key(
+  keystore: &Keystore,
+  arg: &str,
+  opts: DeleteOptions<'_>,
+  confirmation: &mut dyn BufRead,
+) -> Result<(), CliError> {
+  let mut aliases = keystore.aliases();
+  let name = aliases.get(arg).cloned().unwrap_or_else(|| arg.to_string());
+  let path = keystore.key_path(&name);
+  if !path.is_file() {
+    return Err(
+      report!(CliError::Usage)
+        .attach_printable(format!("no key called {} in the keystore", name))
+        .attach_printable(format!("keys directory: {}", keystore.keys_dir().display())),
+    );
+  }
+
+  // the JSON describes the key, so it has to be readable before it's deleted
+  let key = match opts.json {
+    true => Some(keyfile::load_key_file(&path)?),
+    false => None,
+  };
+
+  warn_if_live(&name, opts.cache);
+
+  if !opts.yes {
+    let expected = fingerprint(&name);
+    eprint!(
+      "Deleting the key for {}. Type its fingerprint ({}) to confirm: ",
+      name, expected
+    );
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    confirmation
+      .read_line(&mut answer)
+      .report()
+      .change_context(CliError::Usage)?;
+    if answer.trim() != expected {
+      return Err(
+        report!(CliError::Usage)
+          .attach_printable("the fingerprint didn't match, so nothing was deleted")
+          .attach_printable("pass --yes to delete without confirmation"),
+      );
+    }
+  }
+
+  let trashed = if opts.purge {
+    std::fs::remove_file(&path)
+      .report()
+      .change_context(CliError::Other)
+      .attach_printable(format!("key file: {}", path.display()))?;
+    None
+  } else {
+    let suffix = clock::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let trashed = keystore
+      .trash(&path, &suffix)
+      .report()
+      .change_context(CliError::Other)
+      .attach_printable(format!("key file: {}", path.display()))?;
+    Some(trashed)
+  };
+  if !opts.json {
+    match &trashed {
+      None => println!("deleted key {}", name),
+      Some(trashed) => println!("moved key {} to {}", name, trashed.display()),
+    }
+  }
+
+  let dangling: Vec<String> = aliases
+    .iter()
+    .filter(|(_, target)| **target == name)
+    .map(|(alias, _)| alias.clone())
+    .collect();
+  if !dangling.is_empty() {
+    aliases.retain(|_, target| *target != name);
+    write_aliases(keystore, &aliases)?;
+    if !opts.json {
+      for alias in &dangling {
+        println!("removed alias {}", alias);
+      }
+    }
+  }
+
+  if let Some(key) = key {
+    key_json::print(&DeleteJson {
+      key: KeyJson::new(&key, Some(&path), None),
+      trashed_to: trashed.map(|trashed| trashed.display().to_string()),
+      removed_aliases: dangling,
+    });
+  }
+  Ok(())
+}
+
+/// Removes `alias` from the alias table. The key it refers to is left alone.
+pub fn remove_alias(keystore: &Keystore, alias: &str) -> Result<(), CliError> {
+  let mut aliases = keystore.aliases();
+  let target = aliases
+    .remove(alias)
+    .ok_or_else(|| report!(CliError::Usage).attach_printable(format!("no alias {}", alias)))?;
+  write_aliases(keystore, &aliases)?;
+  println!("removed alias {} (was {})", alias, target);
+  Ok(())
+}
+
+fn write_aliases(keystore: &Keystore, aliases: &BTreeMap<String, String>) -> Result<(), CliError> {
+  keystore
+    .set_aliases(aliases)
+    .report()
+    .change_context(CliError::Other)
+    .attach_printable("unable to update the alias table")
+}
+
+/// Logs a warning if the cache holds a record for `name` that hasn't expired, since that record
+/// can't be replaced once the key is gone.
+fn warn_if_live(name: &str, cache: Option<&RecordCache>) {
+  let (Ok(parsed), Some(cache)) = (Name::parse(name), cache) else {
+    return;
+  };
+  if let Some(resolved) = cache.get(&parsed, None) {
+    log::warn!(
+      "{} has a published record that's valid until {}; it can't be updated or replaced without this key",
+      name,
+      resolved.validity()
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  fn keystore(test: &str) -> Keystore {
+    let root = std::env::temp_dir().join(format!("w3name-delete-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let keystore = Keystore::at(root);
+    fs::create_dir_all(keystore.keys_dir()).unwrap();
+    fs::write(keystore.key_path("k51aaaa1234abcd"), b"key").unwrap();
+    keystore
+      .set_aliases(&BTreeMap::from([
+        ("prod".to_string(), "k51aaaa1234abcd".to_string()),
+        ("other".to_string(), "k51bbbb".to_string()),
+      ]))
+      .unwrap();
+    keystore
+  }
+
+  fn opts(yes: bool, purge: bool) -> DeleteOptions<'static> {
+    DeleteOptions {
+      yes,
+      purge,
+      cache: None,
+      json: false,
+    }
+  }
+
+  #[test]
+  fn fingerprints() {
+    assert_eq!(fingerprint("k51aaaa1234abcd"), "1234abcd");
+    assert_eq!(fingerprint("short"), "short");
+  }
+
+  #[test]
+  fn refuses_without_the_fingerprint() {
+    let keystore = keystore("refuse");
+    let err = delete_key(&keystore, "prod", opts(false, false), &mut &b"abcd\n"[..]).unwrap_err();
+    assert!(matches!(err.current_context(), CliError::Usage));
+    assert!(keystore.key_path("k51aaaa1234abcd").is_file());
+    assert_eq!(keystore.aliases().len(), 2);
+
+    // no key, no confirmation prompt
+    let err = delete_key(&keystore, "k51bbbb", opts(true, false), &mut io::empty()).unwrap_err();
+    assert!(format!("{:?}", err).contains("no key called k51bbbb"));
+    fs::remove_dir_all(keystore.keys_dir().parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn trash_and_purge() {
+    let keystore = keystore("trash");
+    delete_key(
+      &keystore,
+      "prod",
+      opts(false, false),
+      &mut &b"1234abcd\n"[..],
+    )
+    .unwrap();
+    assert!(!keystore.key_path("k51aaaa1234abcd").exists());
+    let trashed: Vec<String> = fs::read_dir(keystore.trash_dir())
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+      .collect();
+    assert_eq!(trashed.len(), 1);
+    assert!(
+      trashed[0].starts_with("k51aaaa1234abcd.key."),
+      "{:?}",
+      trashed
+    );
+    // the alias pointing at the deleted key is gone, others stay
+    assert_eq!(
+      keystore.aliases(),
+      BTreeMap::from([("other".to_string(), "k51bbbb".to_string())])
+    );
+
+    fs::write(keystore.key_path("k51aaaa1234abcd"), b"key").unwrap();
+    delete_key(
+      &keystore,
+      "k51aaaa1234abcd",
+      opts(true, true),
+      &mut io::empty(),
+    )
+    .unwrap();
+    assert!(!keystore.key_path("k51aaaa1234abcd").exists());
+    assert_eq!(fs::read_dir(keystore.trash_dir()).unwrap().count(), 1);
+
+    remove_alias(&keystore, "other").unwrap();
+    assert!(keystore.aliases().is_empty());
+    assert!(remove_alias(&keystore, "other").is_err());
+    fs::remove_dir_all(keystore.keys_dir().parent().unwrap()).unwrap();
+  }
+}
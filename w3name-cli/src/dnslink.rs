@@ -0,0 +1,137 @@
+//! The `dnslink-txt` subcommand: prints the [DNSLink](https://dnslink.dev) TXT record that points a
+//! domain to a name.
+
+use std::path::Path;
+
+use error_stack::{report, Result};
+use w3name::Name;
+
+use crate::{keyfile, keystore::Keystore, CliError};
+
+/// The TTL of the TXT record in zone file lines, in seconds.
+const ZONE_FILE_TTL: u32 = 300;
+const DNSLINK_PREFIX: &str = "_dnslink.";
+
+/// Returns the name for `arg`: the name of a key file, an alias from `keystore`, or a name
+/// identifier or IPNS URI without a path.
+///
+/// Also used by `prove verify` and `--expect-name`, which take the same kinds of name arguments.
+pub fn name_for_arg(arg: &str, keystore: Option<&Keystore>) -> Result<Name, CliError> {
+  let path = Path::new(arg);
+  if path.is_file() {
+    return Ok(keyfile::load_key_file(path)?.to_name());
+  }
+
+  let (name, path) = crate::parse_name_arg_in(keystore, arg)?;
+  if !path.is_empty() {
+    return Err(
+      report!(CliError::Usage)
+        .attach_printable(format!("expected a whole name, not a path: {}", arg)),
+    );
+  }
+  Ok(name)
+}
+
+/// Normalizes `domain` for use in a zone file: lowercase, without a trailing dot or a leading
+/// `_dnslink.` label (which is added back by [zone_file_line]).
+pub fn normalize_domain(domain: &str) -> Result<String, CliError> {
+  let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+  let domain = domain
+    .strip_prefix(DNSLINK_PREFIX)
+    .unwrap_or(&domain)
+    .to_string();
+
+  let valid_label = |label: &str| {
+    (1..=63).contains(&label.len())
+      && !label.starts_with('-')
+      && !label.ends_with('-')
+      && label
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+  };
+  if domain.is_empty() || domain.len() > 253 || !domain.split('.').all(valid_label) {
+    return Err(report!(CliError::Usage).attach_printable(format!("invalid domain: {}", domain)));
+  }
+  Ok(domain)
+}
+
+/// Returns a zone file line for the DNSLink TXT record of `domain`, which must be normalized.
+pub fn zone_file_line(name: &Name, domain: &str) -> String {
+  format!(
+    "{}{}. {} IN TXT \"{}\"",
+    DNSLINK_PREFIX,
+    domain,
+    ZONE_FILE_TTL,
+    name.to_dnslink_txt()
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{env, fs};
+  use w3name::WritableName;
+
+  fn temp_dir(test: &str) -> std::path::PathBuf {
+    let dir = env::temp_dir().join(format!("w3name-dnslink-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn name_from_string() {
+    let name = WritableName::new().to_name();
+    assert_eq!(name_for_arg(&name.to_string(), None).unwrap(), name);
+    assert_eq!(
+      name_for_arg(&format!("ipns://{}", name), None).unwrap(),
+      name
+    );
+    assert!(name_for_arg(&format!("ipns://{}/docs", name), None).is_err());
+    assert!(name_for_arg("not-a-name", None).is_err());
+  }
+
+  #[test]
+  fn name_from_key_file() {
+    let dir = temp_dir("key-file");
+    let writable = WritableName::new();
+    let path = dir.join("site.key");
+    keyfile::write_key_file(&path, &writable).unwrap();
+
+    let name = name_for_arg(path.to_str().unwrap(), None).unwrap();
+    assert_eq!(name, writable.to_name());
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn name_from_alias() {
+    let dir = temp_dir("alias");
+    let name = WritableName::new().to_name();
+    fs::write(
+      dir.join("aliases.json"),
+      format!("{{\"my-site\": \"{}\"}}", name),
+    )
+    .unwrap();
+    let keystore = Keystore::at(dir.clone());
+
+    assert_eq!(name_for_arg("my-site", Some(&keystore)).unwrap(), name);
+    assert!(name_for_arg("other-site", Some(&keystore)).is_err());
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn zone_file_lines() {
+    let name =
+      Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu").unwrap();
+    let expected = "_dnslink.example.com. 300 IN TXT \
+                    \"dnslink=/ipns/k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu\"";
+    for domain in ["example.com", "Example.COM.", "_dnslink.example.com"] {
+      let domain = normalize_domain(domain).unwrap();
+      assert_eq!(zone_file_line(&name, &domain), expected);
+    }
+
+    for domain in ["", "exa mple.com", "-bad.com", "a..b", "\"quoted\".com"] {
+      assert!(normalize_domain(domain).is_err(), "{}", domain);
+    }
+  }
+}
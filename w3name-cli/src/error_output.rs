@@ -0,0 +1,252 @@
+//! Rendering of failed commands, either as the error-stack debug report or as JSON (`--errors json`).
+
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+use error_stack::{AttachmentKind, FrameKind, Report};
+use serde::Serialize;
+use w3name::error::{APIError, NameParseDiagnostic, NetworkCause, UnsupportedKeyType};
+
+use crate::{exit_code, CliError};
+
+/// The format used to print errors to stderr.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ErrorFormat {
+  /// The human-readable error report.
+  Text,
+  /// A single JSON object (see [ErrorJson]).
+  Json,
+}
+
+/// A report attachment naming the name identifier a command was working on.
+///
+/// It prints like any other attachment, but can also be extracted into the `name` field of [ErrorJson].
+#[derive(Debug)]
+pub struct InvolvedName(pub String);
+
+impl Display for InvolvedName {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "name: {}", self.0)
+  }
+}
+
+/// The JSON shape printed by `--errors json`.
+///
+/// Fields may be added over time, but existing fields keep their names and meaning.
+#[derive(Debug, Serialize)]
+pub struct ErrorJson {
+  /// One of `not_found`, `invalid_record`, `expired`, `service`, `usage` or `failure`.
+  pub class: &'static str,
+  /// The process exit code (see `w3name --help`).
+  pub exit_code: i32,
+  /// The top-level error message, e.g. "failed to resolve name".
+  pub message: String,
+  /// Whether retrying the same command later may succeed.
+  pub retryable: bool,
+  /// The HTTP status returned by the service, if any.
+  pub http_status: Option<u16>,
+  /// The error message returned by the service, if any.
+  pub api_message: Option<String>,
+  /// The name identifier involved, if any.
+  pub name: Option<String>,
+  /// The kind of network failure: one of `dns`, `tls`, `connect_refused`, `timeout` or `other`,
+  /// if a request failed to reach the service.
+  pub network_cause: Option<&'static str>,
+  /// Advice on fixing an invalid name argument, an unsupported key type or a network failure, if any.
+  pub hint: Option<String>,
+  /// The underlying errors, from the outermost to the root cause.
+  pub causes: Vec<String>,
+  /// Additional details attached to the error, from the outermost to the innermost.
+  pub attachments: Vec<String>,
+}
+
+impl ErrorJson {
+  pub fn from_report(report: &Report<CliError>) -> ErrorJson {
+    let exit_code = exit_code::for_report(report);
+    let (class, retryable) = match exit_code {
+      exit_code::NOT_FOUND => ("not_found", true),
+      exit_code::INVALID_RECORD => ("invalid_record", false),
+      exit_code::EXPIRED => ("expired", false),
+      exit_code::SERVICE => ("service", true),
+      // not yet, rather than never: the record may still propagate
+      exit_code::SEQUENCE_BEHIND => ("failure", true),
+      exit_code::USAGE => ("usage", false),
+      _ => ("failure", false),
+    };
+    let api_error = report.downcast_ref::<APIError>();
+
+    let mut causes = Vec::new();
+    let mut attachments = Vec::new();
+    for frame in report.frames() {
+      match frame.kind() {
+        FrameKind::Context(context) => causes.push(context.to_string()),
+        FrameKind::Attachment(AttachmentKind::Printable(attachment)) => {
+          attachments.push(attachment.to_string())
+        }
+        FrameKind::Attachment(_) => {}
+      }
+    }
+    // the first context is the report's own message
+    causes.remove(0);
+
+    ErrorJson {
+      class,
+      exit_code,
+      message: report.current_context().to_string(),
+      retryable,
+      http_status: api_error.map(|err| err.status_code.as_u16()),
+      api_message: api_error.map(|err| err.message.clone()),
+      name: report
+        .downcast_ref::<InvolvedName>()
+        .map(|name| name.0.clone()),
+      network_cause: report.downcast_ref::<NetworkCause>().map(cause_id),
+      hint: report
+        .downcast_ref::<NameParseDiagnostic>()
+        .map(|diagnostic| diagnostic.to_string())
+        .or_else(|| hint(report)),
+      causes,
+      attachments,
+    }
+  }
+}
+
+/// The identifier of `cause` in [ErrorJson::network_cause].
+pub fn cause_id(cause: &NetworkCause) -> &'static str {
+  match cause {
+    NetworkCause::Dns => "dns",
+    NetworkCause::Tls => "tls",
+    NetworkCause::ConnectRefused => "connect_refused",
+    NetworkCause::Timeout => "timeout",
+    NetworkCause::Other => "other",
+  }
+}
+
+/// Returns a one-line suggestion for an unsupported key type or a network failure in `report`.
+fn hint(report: &Report<CliError>) -> Option<String> {
+  key_type_hint(report).or_else(|| network_hint(report).map(str::to_string))
+}
+
+/// Explains that a name or record uses a key type this build can't verify.
+fn key_type_hint(report: &Report<CliError>) -> Option<String> {
+  let codec = report.downcast_ref::<UnsupportedKeyType>()?.codec;
+  Some(format!(
+    "the key type ({}) isn't supported by this build of w3name, which verifies RSA, ed25519, \
+     secp256k1 and ECDSA keys; a newer version may support it",
+    codec
+  ))
+}
+
+/// Returns a one-line suggestion for the network failure in `report`, if there was one.
+fn network_hint(report: &Report<CliError>) -> Option<&'static str> {
+  let hint = match report.downcast_ref::<NetworkCause>()? {
+    NetworkCause::Dns => "DNS lookup failed — check your network connection and the service URL",
+    NetworkCause::Tls => {
+      "TLS failure — are you behind an intercepting proxy? its CA certificate must be trusted by the system"
+    }
+    NetworkCause::ConnectRefused => {
+      "connection refused — is the service running at that address, or is a firewall blocking it?"
+    }
+    NetworkCause::Timeout => "timed out — the service or your network may be slow; try again later",
+    NetworkCause::Other => return None,
+  };
+  Some(hint)
+}
+
+/// Prints a failed command's report to stderr in the given format.
+pub fn print(report: &Report<CliError>, format: ErrorFormat) {
+  match format {
+    ErrorFormat::Text => {
+      eprintln!("{report:?}");
+      if let Some(hint) = hint(report) {
+        eprintln!("\nhint: {hint}");
+      }
+    }
+    ErrorFormat::Json => {
+      let json = serde_json::to_string(&ErrorJson::from_report(report))
+        .expect("error JSON is always serializable");
+      eprintln!("{json}");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use error_stack::report;
+  use reqwest::StatusCode;
+  use w3name::error::{ClientError, HttpError, IpnsError};
+
+  const NAME: &str = "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu";
+
+  fn assert_golden(report: Report<CliError>, golden: &str) {
+    let json = serde_json::to_string_pretty(&ErrorJson::from_report(&report)).unwrap();
+    assert_eq!(json, golden.trim_end());
+  }
+
+  fn api_error(status_code: StatusCode, message: &str) -> Report<CliError> {
+    report!(APIError {
+      message: message.to_string(),
+      status_code,
+    })
+    .change_context(ClientError)
+    .change_context(CliError::Resolve)
+    .attach_printable(InvolvedName(NAME.to_string()))
+  }
+
+  #[test]
+  fn not_found() {
+    let report = api_error(StatusCode::NOT_FOUND, "record not found");
+    assert_golden(report, include_str!("../testdata/errors/not_found.json"));
+  }
+
+  #[test]
+  fn service_error() {
+    let report = api_error(StatusCode::INTERNAL_SERVER_ERROR, "internal error");
+    assert_golden(report, include_str!("../testdata/errors/service.json"));
+  }
+
+  #[test]
+  fn validation_failure() {
+    let report = report!(IpnsError)
+      .attach_printable("signature mismatch")
+      .change_context(ClientError)
+      .change_context(CliError::Resolve)
+      .attach_printable(InvolvedName(NAME.to_string()));
+    assert_golden(
+      report,
+      include_str!("../testdata/errors/invalid_record.json"),
+    );
+  }
+
+  #[test]
+  fn network_failure() {
+    let report = report!(HttpError)
+      .attach_printable(NetworkCause::ConnectRefused)
+      .change_context(ClientError)
+      .change_context(CliError::Resolve)
+      .attach_printable(InvolvedName(NAME.to_string()));
+    assert_golden(report, include_str!("../testdata/errors/network.json"));
+  }
+
+  #[test]
+  fn unsupported_key_type() {
+    let mut key_bytes = vec![0x08, 0x09, 0x12, 32];
+    key_bytes.extend_from_slice(&[9; 32]);
+    let report = w3name::Name::from_bytes(&key_bytes)
+      .unwrap_err()
+      .change_context(CliError::Parse);
+    let json = ErrorJson::from_report(&report);
+    assert_eq!(json.class, "invalid_record");
+    assert!(json.hint.unwrap().contains("key type (9) isn't supported"));
+  }
+
+  #[test]
+  fn invalid_name() {
+    let content_cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+    let report = w3name::Name::parse(content_cid)
+      .unwrap_err()
+      .change_context(CliError::Usage)
+      .attach_printable(InvolvedName(content_cid.to_string()));
+    assert_golden(report, include_str!("../testdata/errors/invalid_name.json"));
+  }
+}
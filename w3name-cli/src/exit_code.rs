@@ -0,0 +1,206 @@
+//! Process exit codes for the `w3name` command-line tool.
+//!
+//! Scripts can use the exit code to decide whether to retry, alert, or fix the invocation.
+//! The mapping is part of the CLI's public interface, so existing codes must not change.
+
+use error_stack::Report;
+use w3name::error::{APIError, ClientError, HttpError, IpnsError, RecordExpired};
+
+use crate::{
+  archive::RolledBack, from_record::OutdatedRecord, kubo::NoRoutingRecord,
+  record_age::RecordTooOld, sequence_gate::SequenceBehind, CliError,
+};
+
+/// An error that doesn't fall into any of the more specific classes below.
+pub const FAILURE: i32 = 1;
+
+/// No record exists for the requested name. Retrying later may succeed.
+pub const NOT_FOUND: i32 = 2;
+
+/// A record was found but is malformed or failed signature validation.
+pub const INVALID_RECORD: i32 = 3;
+
+/// A valid record was found, but its validity period has ended.
+pub const EXPIRED: i32 = 4;
+
+/// The network or the w3name service failed. Retrying later may succeed.
+pub const SERVICE: i32 = 5;
+
+/// `publish --skip-unchanged --changed-exit-code` found the value unchanged and published nothing.
+pub const UNCHANGED: i32 = 6;
+
+/// `resolve --verify-against` resolved an older record than an archived one: the service rolled
+/// the name back, or is a stale mirror.
+pub const ROLLED_BACK: i32 = 7;
+
+/// `record fetch` found no record for the name in the routing system (the DHT).
+pub const NO_ROUTING_RECORD: i32 = 8;
+
+/// `publish --from-record` was given an older record than the one the service has.
+pub const OUTDATED_RECORD: i32 = 9;
+
+/// `status` found a problem with the name that doesn't stop it from resolving, e.g. a stale
+/// gateway or a record that expires soon.
+pub const HEALTH_WARNING: i32 = 10;
+
+/// `status` found the name broken: no source has a valid record, the record has expired, or
+/// sources have conflicting records.
+pub const UNHEALTHY: i32 = 11;
+
+/// `resolve --max-record-age` resolved a record that has been current for longer than allowed.
+pub const RECORD_TOO_OLD: i32 = 12;
+
+/// `resolve --require-sequence-at-least` found fewer sources serving the sequence than required.
+/// Retrying later may succeed, once the new record has propagated.
+pub const SEQUENCE_BEHIND: i32 = 13;
+
+/// The command was invoked incorrectly (bad flags, unparseable name, unreadable key file, etc).
+pub const USAGE: i32 = 64;
+
+/// Help text describing the exit codes, shown at the end of `w3name --help`.
+pub const HELP: &str = "EXIT CODES:
+    0     success
+    1     other failure
+    2     no record found for the name
+    3     record is invalid (malformed or bad signature)
+    4     record has expired
+    5     network or service error
+    6     value unchanged, nothing published (publish --changed-exit-code)
+    7     resolved record is older than an archived one (resolve --verify-against)
+    8     no record found in the DHT (record fetch)
+    9     given record is older than the service's (publish --from-record)
+    10    the name has warnings (status)
+    11    the name is unhealthy (status)
+    12    resolved record has been current too long (resolve --max-record-age)
+    13    resolved sequence is lower than required (resolve --require-sequence-at-least)
+    64    usage error (invalid arguments, unreadable key file)";
+
+/// Returns the exit code for a failed command, based on the most specific error found in the report.
+pub fn for_report(report: &Report<CliError>) -> i32 {
+  if let CliError::Usage = report.current_context() {
+    return USAGE;
+  }
+
+  if report.contains::<RecordExpired>() {
+    return EXPIRED;
+  }
+
+  if report.contains::<RolledBack>() {
+    return ROLLED_BACK;
+  }
+
+  if report.contains::<RecordTooOld>() {
+    return RECORD_TOO_OLD;
+  }
+
+  if report.contains::<SequenceBehind>() {
+    return SEQUENCE_BEHIND;
+  }
+
+  if report.contains::<NoRoutingRecord>() {
+    return NO_ROUTING_RECORD;
+  }
+
+  if report.contains::<OutdatedRecord>() {
+    return OUTDATED_RECORD;
+  }
+
+  if let Some(api_err) = report.downcast_ref::<APIError>() {
+    if api_err.status_code == 404 {
+      return NOT_FOUND;
+    }
+    return SERVICE;
+  }
+
+  if report.contains::<IpnsError>() {
+    return INVALID_RECORD;
+  }
+
+  if report.contains::<HttpError>() || report.contains::<ClientError>() {
+    return SERVICE;
+  }
+
+  match report.current_context() {
+    CliError::Parse => INVALID_RECORD,
+    _ => FAILURE,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use error_stack::report;
+  use reqwest::StatusCode;
+
+  fn api_error(status_code: StatusCode) -> Report<CliError> {
+    report!(APIError {
+      message: "oops".to_string(),
+      status_code,
+    })
+    .change_context(ClientError)
+    .change_context(CliError::Resolve)
+  }
+
+  #[test]
+  fn not_found() {
+    assert_eq!(for_report(&api_error(StatusCode::NOT_FOUND)), NOT_FOUND);
+  }
+
+  #[test]
+  fn service_errors() {
+    assert_eq!(
+      for_report(&api_error(StatusCode::INTERNAL_SERVER_ERROR)),
+      SERVICE
+    );
+    let http = report!(HttpError)
+      .change_context(ClientError)
+      .change_context(CliError::Publish);
+    assert_eq!(for_report(&http), SERVICE);
+  }
+
+  #[test]
+  fn invalid_record() {
+    let invalid = report!(IpnsError)
+      .change_context(ClientError)
+      .change_context(CliError::Resolve);
+    assert_eq!(for_report(&invalid), INVALID_RECORD);
+    assert_eq!(for_report(&report!(CliError::Parse)), INVALID_RECORD);
+  }
+
+  #[test]
+  fn expired() {
+    let expired = report!(RecordExpired).change_context(CliError::Resolve);
+    assert_eq!(for_report(&expired), EXPIRED);
+  }
+
+  #[test]
+  fn rolled_back() {
+    let rolled_back = report!(RolledBack).change_context(CliError::Resolve);
+    assert_eq!(for_report(&rolled_back), ROLLED_BACK);
+
+    let too_old = report!(RecordTooOld).change_context(CliError::Resolve);
+    assert_eq!(for_report(&too_old), RECORD_TOO_OLD);
+  }
+
+  #[test]
+  fn no_routing_record() {
+    let missing = report!(NoRoutingRecord).change_context(CliError::Resolve);
+    assert_eq!(for_report(&missing), NO_ROUTING_RECORD);
+
+    let outdated = report!(OutdatedRecord).change_context(CliError::Publish);
+    assert_eq!(for_report(&outdated), OUTDATED_RECORD);
+  }
+
+  #[test]
+  fn usage() {
+    let usage = report!(IpnsError).change_context(CliError::Usage);
+    assert_eq!(for_report(&usage), USAGE);
+  }
+
+  #[test]
+  fn other() {
+    let io = report!(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+      .change_context(CliError::Create);
+    assert_eq!(for_report(&io), FAILURE);
+  }
+}
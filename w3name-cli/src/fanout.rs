@@ -0,0 +1,209 @@
+//! `publish --to`, which publishes the new record to several targets instead of only the w3name
+//! service: the service itself (`w3name`), a Kubo node's RPC API (`kubo=<url>`), or a delegated
+//! routing server (`routing=<url>`).
+//!
+//! The targets are published to concurrently, and each one's result is reported. `--require`
+//! decides whether the publish as a whole succeeded: every target must accept the record (`all`),
+//! or at least one (`any`).
+
+use clap::ValueEnum;
+use error_stack::{report, Result};
+use reqwest::Url;
+use w3name::{
+  error::PublishError, CurrentRevision, DelegatedRoutingClient, FanoutOutcome, FanoutPolicy,
+  FanoutPublisher, NamePublisher, Revision, W3NameClient,
+};
+
+use crate::{error_output::InvolvedName, kubo::KuboClient, CliError};
+
+/// A target of `publish --to`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PublishTarget {
+  /// The w3name service, at `$W3NAME_ENDPOINT` or the default endpoint.
+  W3name,
+  /// The RPC API of a Kubo node.
+  Kubo(Url),
+  /// A delegated routing server.
+  Routing(Url),
+}
+
+/// Parses a `--to` argument: `w3name`, `kubo`, `kubo=<url>` or `routing=<url>`. A bare `kubo`
+/// means the local node's default API address.
+pub fn parse_target(arg: &str) -> std::result::Result<PublishTarget, String> {
+  let (kind, url) = match arg.split_once('=') {
+    Some((kind, url)) => (kind, Some(url)),
+    None => (arg, None),
+  };
+  let parse_url = |url: &str| {
+    Url::parse(url).map_err(|err| format!("invalid URL for the {} target: {}", kind, err))
+  };
+  match (kind, url) {
+    ("w3name", None) => Ok(PublishTarget::W3name),
+    ("kubo", None) => Ok(PublishTarget::Kubo(
+      Url::parse(crate::kubo::DEFAULT_API).unwrap(),
+    )),
+    ("kubo", Some(url)) => parse_url(url).map(PublishTarget::Kubo),
+    ("routing", Some(url)) => parse_url(url).map(PublishTarget::Routing),
+    ("routing", None) => Err("the routing target needs a URL: routing=<url>".to_string()),
+    ("w3name", Some(_)) => {
+      Err("the w3name target takes no URL; set $W3NAME_ENDPOINT to use another service".to_string())
+    }
+    _ => Err(format!(
+      "unknown target {:?}; expected w3name, kubo=<url> or routing=<url>",
+      kind
+    )),
+  }
+}
+
+/// Whether `publish --to` succeeds when every target accepts the record, or any of them.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum RequirePolicy {
+  All,
+  Any,
+}
+
+impl From<RequirePolicy> for FanoutPolicy {
+  fn from(policy: RequirePolicy) -> FanoutPolicy {
+    match policy {
+      RequirePolicy::All => FanoutPolicy::All,
+      RequirePolicy::Any => FanoutPolicy::Any,
+    }
+  }
+}
+
+/// Builds the publisher for `targets`. `client` is used for the `w3name` target.
+pub fn publisher(
+  targets: &[PublishTarget],
+  client: &W3NameClient,
+  policy: RequirePolicy,
+) -> FanoutPublisher {
+  let targets = targets
+    .iter()
+    .map(|target| -> Box<dyn NamePublisher> {
+      match target {
+        PublishTarget::W3name => Box::new(client.clone()),
+        PublishTarget::Kubo(api) => Box::new(KuboClient::new(api.clone())),
+        PublishTarget::Routing(endpoint) => Box::new(DelegatedRoutingClient::new(endpoint.clone())),
+      }
+    })
+    .collect();
+  FanoutPublisher::new(targets).with_policy(policy.into())
+}
+
+/// Prints how each target did, one per line.
+pub fn print_results(outcome: &FanoutOutcome) {
+  for result in &outcome.results {
+    match result.error_message() {
+      None => println!("  {}: published", result.target),
+      Some(message) => println!("  {}: failed: {}", result.target, message),
+    }
+  }
+}
+
+/// The output of `publish --to ... --json`: what `publish --json` prints, with a `targets` array
+/// holding each target's result instead of a single `receipt`.
+pub fn outcome_json(
+  revision: &Revision,
+  previous: Option<&CurrentRevision>,
+  outcome: &FanoutOutcome,
+) -> serde_json::Value {
+  let targets: Vec<serde_json::Value> = outcome
+    .results
+    .iter()
+    .map(|result| match &result.result {
+      Ok(receipt) => serde_json::json!({
+        "target": result.target,
+        "published": true,
+        "receipt": crate::receipt_json(receipt),
+      }),
+      Err(_) => serde_json::json!({
+        "target": result.target,
+        "published": false,
+        "error": result.error_message(),
+      }),
+    })
+    .collect();
+  let record = outcome
+    .results
+    .iter()
+    .find_map(|result| result.result.as_ref().ok())
+    .map(|receipt| base64::encode(&receipt.record));
+  serde_json::json!({
+    "name": revision.name().to_string(),
+    "value": revision.value(),
+    "value_length": revision.value_len(),
+    "sequence": revision.sequence(),
+    "validity": revision.validity_string(),
+    "ttl_ns": revision.ttl().num_nanoseconds(),
+    "published": outcome.succeeded(),
+    "previous": crate::previous_json(previous),
+    "record": record,
+    "targets": targets,
+  })
+}
+
+/// Fails if `outcome` doesn't satisfy its policy, listing the targets that failed.
+pub fn check(outcome: &FanoutOutcome, name: &str) -> Result<(), CliError> {
+  if outcome.succeeded() {
+    return Ok(());
+  }
+  let failures: Vec<String> = outcome
+    .results
+    .iter()
+    .filter_map(|result| {
+      let message = result.error_message()?;
+      Some(format!("{} failed: {}", result.target, message))
+    })
+    .collect();
+  let required = match outcome.policy {
+    FanoutPolicy::All => "every target",
+    FanoutPolicy::Any => "at least one target",
+  };
+  let mut err_report = report!(PublishError)
+    .change_context(CliError::Publish)
+    .attach_printable(format!(
+      "{} of {} targets failed, but {} had to succeed",
+      failures.len(),
+      outcome.results.len(),
+      required
+    ));
+  for failure in failures {
+    err_report = err_report.attach_printable(failure);
+  }
+  Err(err_report.attach_printable(InvolvedName(name.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_targets() {
+    assert_eq!(parse_target("w3name"), Ok(PublishTarget::W3name));
+    assert_eq!(
+      parse_target("kubo"),
+      Ok(PublishTarget::Kubo(
+        Url::parse("http://127.0.0.1:5001").unwrap()
+      ))
+    );
+    assert_eq!(
+      parse_target("kubo=http://ipfs.internal:5001"),
+      Ok(PublishTarget::Kubo(
+        Url::parse("http://ipfs.internal:5001").unwrap()
+      ))
+    );
+    assert_eq!(
+      parse_target("routing=https://delegated-ipfs.dev"),
+      Ok(PublishTarget::Routing(
+        Url::parse("https://delegated-ipfs.dev").unwrap()
+      ))
+    );
+
+    assert!(parse_target("routing").unwrap_err().contains("needs a URL"));
+    assert!(parse_target("kubo=not a url")
+      .unwrap_err()
+      .contains("invalid URL"));
+    assert!(parse_target("w3name=https://name.example").is_err());
+    assert!(parse_target("dht").unwrap_err().contains("unknown target"));
+  }
+}
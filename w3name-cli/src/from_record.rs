@@ -0,0 +1,239 @@
+//! `publish --from-record`, which publishes the successor of a record given in a file instead of
+//! resolving the current record, e.g. to migrate a name whose latest record was exported from
+//! another system.
+//!
+//! The record must be valid for the key's name. Online, the service is asked for its record as a
+//! quick cross-check, and publishing is refused if the service's record is newer, since the
+//! service would reject the successor anyway. With `--offline`, the successor is signed and
+//! written to an archive instead of being published.
+
+use std::{error::Error, fmt::Display, fs, path::Path, time::Duration};
+
+use chrono::{DateTime, Utc};
+use error_stack::{report, IntoReport, Result, ResultExt};
+use w3name::{
+  error::{APIError, InvalidIpnsV1Signature, InvalidIpnsV2Signature},
+  Clock, IncrementPolicy, Revision, RevisionBuilder, W3NameClient, WritableName,
+};
+
+use crate::{clock, config, error_output::InvolvedName, inspect, record, CliError};
+
+/// How long the cross-check waits for the service before publishing without it.
+const CROSS_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The record given to `publish --from-record` is older than the one the service has.
+#[derive(Debug)]
+pub struct OutdatedRecord;
+
+impl Display for OutdatedRecord {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "the given record is older than the service's record")
+  }
+}
+
+impl Error for OutdatedRecord {}
+
+/// Reads the record in `path` (binary or base64) and validates it against `key`'s name.
+pub fn load(path: &Path, key: &WritableName) -> Result<Revision, CliError> {
+  let name = key.to_name();
+  let bytes = fs::read(path)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!("record file: {}", path.display()))?;
+  let record = record::decode_record(bytes);
+  match inspect::inspect(&record, Some(&name), inspect::Verification::Strict, false) {
+    Ok(inspection) => Ok(inspection.revision),
+    Err(err_report) => {
+      let bad_signature = err_report.contains::<InvalidIpnsV1Signature>()
+        || err_report.contains::<InvalidIpnsV2Signature>();
+      let err_report = err_report
+        .attach_printable(format!(
+          "the record in {} isn't a valid record for the key's name",
+          path.display()
+        ))
+        .attach_printable(InvolvedName(name.to_string()));
+      if bad_signature {
+        Err(err_report.attach_printable("it may have been signed by a different key"))
+      } else {
+        Err(err_report)
+      }
+    }
+  }
+}
+
+/// Fails with [OutdatedRecord] if the service has a record that supersedes `from`.
+///
+/// Since the point of `--from-record` is that resolving may not work, the check is best-effort:
+/// if the service has no record, fails, or doesn't answer within a few seconds, that's logged and
+/// the check passes.
+pub async fn cross_check(client: &W3NameClient, from: &Revision) -> Result<(), CliError> {
+  let name = from.name();
+  let resolved = match tokio::time::timeout(CROSS_CHECK_TIMEOUT, client.resolve(name)).await {
+    Ok(Ok(resolved)) => resolved,
+    Ok(Err(err_report)) => {
+      let not_found = err_report
+        .downcast_ref::<APIError>()
+        .is_some_and(|err| err.status_code == 404);
+      if not_found {
+        log::debug!("The service has no record for {} to cross-check", name);
+      } else {
+        log::warn!(
+          "unable to cross-check the record with the service, publishing anyway: {}",
+          err_report
+        );
+      }
+      return Ok(());
+    }
+    Err(_) => {
+      log::warn!(
+        "the service didn't answer the cross-check within {}, publishing anyway",
+        humantime::format_duration(CROSS_CHECK_TIMEOUT)
+      );
+      return Ok(());
+    }
+  };
+
+  if resolved.supersedes(from) {
+    return Err(
+      report!(OutdatedRecord)
+        .change_context(CliError::Publish)
+        .attach_printable(format!(
+          "the given record has sequence {}, the service's has sequence {}",
+          from.sequence(),
+          resolved.sequence()
+        ))
+        .attach_printable(InvolvedName(name.to_string())),
+    );
+  }
+  log::debug!(
+    "The service's record (sequence {}) is no newer than the given one",
+    resolved.sequence()
+  );
+  Ok(())
+}
+
+/// How long the successor signed by [sign_successor] stays valid.
+pub enum Validity {
+  /// For the window that the increment policy picks.
+  Policy,
+  /// For this long from now.
+  For(chrono::Duration),
+  /// Until exactly this instant.
+  At(DateTime<Utc>),
+}
+
+/// Builds the successor of `from` with `value`, as `publish` would, and signs it with `key`.
+pub fn sign_successor(
+  key: &WritableName,
+  from: &Revision,
+  value: &str,
+  validity: Validity,
+  ttl: Option<chrono::Duration>,
+  policy: IncrementPolicy,
+) -> Result<(Revision, Vec<u8>), CliError> {
+  // like the client, the configured defaults win over the policy
+  let defaults = config::defaults();
+  let (window, policy_ttl) = policy.durations_at(from, clock::shared().now());
+  let builder = RevisionBuilder::next(from, value).clock(clock::shared());
+  let revision = match validity {
+    Validity::Policy => builder.validity_for(defaults.validity.unwrap_or(window)),
+    Validity::For(duration) => builder.validity_for(duration),
+    Validity::At(valid_until) => builder.validity_at(valid_until),
+  }
+  .ttl(ttl.or(defaults.ttl).unwrap_or(policy_ttl))
+  .build()
+  .map_err(crate::revision_error)?;
+  let record = revision
+    .to_signed_bytes(key)
+    .change_context(CliError::Publish)?;
+  Ok((revision, record))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{Duration, Utc};
+
+  fn write_temp(test: &str, bytes: &[u8]) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+      "w3name-from-record-{}-{}",
+      test,
+      std::process::id()
+    ));
+    fs::write(&path, bytes).unwrap();
+    path
+  }
+
+  #[test]
+  fn loads_records_of_the_key() {
+    let key = WritableName::new();
+    let revision = Revision::v0(&key.to_name(), "/ipfs/exported").increment("/ipfs/exported");
+    let record = revision.to_signed_bytes(&key).unwrap();
+
+    let path = write_temp("binary", &record);
+    assert_eq!(load(&path, &key).unwrap(), revision);
+    fs::write(&path, base64::encode(&record)).unwrap();
+    assert_eq!(load(&path, &key).unwrap(), revision);
+    fs::remove_file(&path).unwrap();
+
+    let other = WritableName::new();
+    let path = write_temp("other-key", &record);
+    let err = load(&path, &other).unwrap_err();
+    assert!(format!("{:?}", err).contains("signed by a different key"));
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn signs_the_successor() {
+    let key = WritableName::new();
+    let from = Revision::v0_with_validity(
+      &key.to_name(),
+      "/ipfs/exported",
+      Utc::now() + Duration::days(6),
+      Duration::minutes(5),
+    );
+    let (next, record) = sign_successor(
+      &key,
+      &from,
+      "/ipfs/next",
+      Validity::Policy,
+      None,
+      IncrementPolicy::PreserveDurations,
+    )
+    .unwrap();
+    assert_eq!(next.sequence(), 1);
+    assert_eq!(next.ttl(), Duration::minutes(5));
+    // the exported record doesn't carry its window, so its end of life is kept
+    assert!(*next.validity() >= *from.validity());
+    assert!(*next.validity() < Utc::now() + Duration::days(7));
+    assert!(next.supersedes(&from));
+    assert_eq!(
+      Revision::from_signed_bytes(&key.to_name(), &record).unwrap(),
+      next
+    );
+  }
+
+  #[test]
+  fn signs_the_successor_until_an_instant() {
+    let key = WritableName::new();
+    let from = Revision::v0(&key.to_name(), "/ipfs/exported");
+    let valid_until = "2099-12-31T00:00:00.5Z".parse().unwrap();
+    let (next, record) = sign_successor(
+      &key,
+      &from,
+      "/ipfs/next",
+      Validity::At(valid_until),
+      Some(Duration::minutes(10)),
+      IncrementPolicy::PreserveDurations,
+    )
+    .unwrap();
+    assert_eq!(*next.validity(), valid_until);
+    assert_eq!(next.ttl(), Duration::minutes(10));
+    assert_eq!(
+      Revision::from_signed_bytes(&key.to_name(), &record)
+        .unwrap()
+        .validity_string(),
+      "2099-12-31T00:00:00.500000000Z"
+    );
+  }
+}
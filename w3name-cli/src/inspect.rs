@@ -0,0 +1,395 @@
+//! Record inspection for the `parse` command: the record's revision, plus which signatures it
+//! carries and which of them were checked.
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use w3name::{
+  ipns::{
+    deserialize_ipns_entry_untrusted, embedded_public_key, name_from_routing_key,
+    revision_from_ipns_entry_unchecked, signature_info, validate_ipns_entry_lenient,
+    verify_v1_signature, SignatureInfo,
+  },
+  Name, Revision, TtlChecks, TtlNote,
+};
+
+use crate::{clock, keyfile, CliError};
+
+/// How [inspect] checks a record's signatures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verification {
+  /// The record must be valid.
+  Strict,
+  /// Hybrid V1+V2 records whose V1 fields disagree with the signed V2 data are accepted, with a
+  /// warning for each mismatch (`--lenient`).
+  Lenient,
+  /// The record is decoded without checking its signatures (`--no-verify`).
+  Skip,
+}
+
+#[derive(Debug)]
+pub struct RecordInspection {
+  pub revision: Revision,
+  /// Whether the record's signature was checked. Only `false` with [Verification::Skip].
+  pub verified: bool,
+  pub signatures: SignatureInfo,
+  /// Whether the V1 signature of a hybrid record is valid too. Only checked with `--check-all-signatures`.
+  pub v1_signature_valid: Option<bool>,
+  /// What's questionable about the record's TTL, as of the inspection (see [Revision::ttl_notes]).
+  pub ttl_notes: Vec<TtlNote>,
+}
+
+/// Validates the serialized record in `entry_bytes` and describes it.
+///
+/// The record is validated against `name` if given, e.g. from a record archive. Otherwise, the
+/// record must embed its public key. If both are available, they must agree. With
+/// [Verification::Skip], the record is only decoded, and the result is marked as unverified.
+///
+/// With `check_all_signatures`, the V1 signature of a hybrid record is verified in addition to the
+/// V2 signature, and the result is reported in [RecordInspection::v1_signature_valid] rather than
+/// failing the inspection.
+pub fn inspect(
+  entry_bytes: &[u8],
+  name: Option<&Name>,
+  verification: Verification,
+  check_all_signatures: bool,
+) -> Result<RecordInspection, CliError> {
+  let entry = deserialize_ipns_entry_untrusted(entry_bytes).change_context(CliError::Parse)?;
+  let embedded = embedded_public_key(&entry)
+    .change_context(CliError::Parse)?
+    .map(|key| Name::from_public_key(&key));
+  let name = match (name, embedded) {
+    (Some(name), Some(embedded)) if *name != embedded => {
+      return Err(report!(CliError::Parse).attach_printable(format!(
+        "the record embeds the key of {}, not {}",
+        embedded, name
+      )));
+    }
+    (Some(name), _) => name.clone(),
+    (None, Some(embedded)) => embedded,
+    (None, None) => {
+      return Err(
+        report!(CliError::Parse).attach_printable("the record doesn't embed its public key"),
+      );
+    }
+  };
+  let revision = match verification {
+    Verification::Strict => {
+      Revision::try_from_entry(&entry, &name).change_context(CliError::Parse)?
+    }
+    Verification::Lenient => {
+      let mismatches =
+        validate_ipns_entry_lenient(&entry, name.public_key()).change_context(CliError::Parse)?;
+      for mismatch in mismatches {
+        log::warn!("{}", mismatch);
+      }
+      revision_from_ipns_entry_unchecked(&entry, &name).change_context(CliError::Parse)?
+    }
+    Verification::Skip => {
+      log::warn!("The record's signatures were not checked, so it may be forged or corrupted");
+      revision_from_ipns_entry_unchecked(&entry, &name).change_context(CliError::Parse)?
+    }
+  };
+  let verified = verification != Verification::Skip;
+
+  let signatures = signature_info(&entry);
+  let v1_signature_valid = (verified && check_all_signatures && signatures.is_hybrid()).then(
+    || match verify_v1_signature(&entry, name.public_key()) {
+      Ok(()) => true,
+      Err(err_report) => {
+        log::debug!("V1 signature check failed: {:?}", err_report);
+        false
+      }
+    },
+  );
+
+  let ttl_notes = revision.ttl_notes(clock::now(), &TtlChecks::default());
+  Ok(RecordInspection {
+    revision,
+    verified,
+    signatures,
+    v1_signature_valid,
+    ttl_notes,
+  })
+}
+
+impl RecordInspection {
+  /// The signature details, one `key: value` line each.
+  pub fn signature_lines(&self) -> Vec<String> {
+    let validated_with = match self.verified {
+      true => self.signatures.validated_with.to_string(),
+      false => "none (UNVERIFIED)".to_string(),
+    };
+    let mut lines = vec![
+      format!("has_signature_v1: {}", self.signatures.has_signature_v1),
+      format!("has_signature_v2: {}", self.signatures.has_signature_v2),
+      format!("validated_with: {}", validated_with),
+    ];
+    if let Some(valid) = self.v1_signature_valid {
+      lines.push(format!("v1_signature_valid: {}", valid));
+    }
+    lines
+  }
+
+  /// The TTL notes, one `note: ...` line each.
+  pub fn note_lines(&self) -> Vec<String> {
+    self
+      .ttl_notes
+      .iter()
+      .map(|note| format!("note: {}", note))
+      .collect()
+  }
+
+  /// The record as a JSON object, for `parse --json`.
+  ///
+  /// Values that aren't valid UTF-8 are given as base64, with `value_encoding` set to `base64`
+  /// rather than `utf8`. `verified` is `false`, and `validated_with` null, if the signatures
+  /// weren't checked.
+  pub fn to_json(&self) -> serde_json::Value {
+    let (value, value_encoding) = if self.revision.is_value_utf8() {
+      (self.revision.value().to_string(), "utf8")
+    } else {
+      (base64::encode(self.revision.value_bytes()), "base64")
+    };
+    serde_json::json!({
+      "name": self.revision.name().to_string(),
+      "value": value,
+      "value_encoding": value_encoding,
+      "value_length": self.revision.value_len(),
+      "sequence": self.revision.sequence(),
+      "validity": self.revision.validity_string(),
+      "ttl_ns": self.revision.ttl().num_nanoseconds(),
+      "has_signature_v1": self.signatures.has_signature_v1,
+      "has_signature_v2": self.signatures.has_signature_v2,
+      "verified": self.verified,
+      "validated_with": self.verified.then(|| self.signatures.validated_with.to_string()),
+      "v1_signature_valid": self.v1_signature_valid,
+      "ttl_notes": self.ttl_notes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+    })
+  }
+}
+
+/// Returns the name for a binary IPNS routing key given to `--routing-key`, either hex or base64
+/// encoded.
+pub fn name_for_routing_key(arg: &str) -> Result<Name, CliError> {
+  let arg = arg.trim();
+  let key = match keyfile::decode_hex(arg) {
+    Some(key) => key,
+    None => base64::decode(arg)
+      .report()
+      .change_context(CliError::Usage)
+      .attach_printable("routing key is neither hex nor base64")?,
+  };
+  name_from_routing_key(&key)
+    .change_context(CliError::Usage)
+    .attach_printable(format!("invalid routing key: {}", arg))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{DateTime, Duration};
+  use w3name::{
+    ipns::{revision_to_ipns_entry, serialize_ipns_entry},
+    WritableName,
+  };
+
+  enum Shape {
+    V1Only,
+    V2Only,
+    Hybrid { valid_v1: bool },
+  }
+
+  /// Returns a serialized record of the given shape, with the public key embedded.
+  fn fixture(shape: Shape) -> Vec<u8> {
+    let name = WritableName::from_seed(&[1; 32]).unwrap();
+    let validity = DateTime::parse_from_rfc3339("2100-01-01T00:00:00Z")
+      .unwrap()
+      .into();
+    let revision = Revision::v0_with_validity(
+      &name.to_name(),
+      "/ipfs/fixture",
+      validity,
+      Duration::hours(1),
+    );
+    let mut entry = revision_to_ipns_entry(&revision, name.keypair()).unwrap();
+    entry.pub_key = name.to_name().public_key_protobuf_bytes();
+
+    if !matches!(shape, Shape::V2Only) {
+      entry.value = revision.value().as_bytes().to_vec();
+      entry.validity = revision.validity_string().into_bytes();
+      entry.ttl = revision.ttl().num_nanoseconds().unwrap() as u64;
+      let msg = [&entry.value[..], b"EOL", &entry.validity[..]].concat();
+      entry.signature = name.keypair().sign(&msg).unwrap();
+    }
+    match shape {
+      Shape::V2Only => {}
+      Shape::V1Only => {
+        entry.signature_v2.clear();
+        entry.data.clear();
+      }
+      Shape::Hybrid { valid_v1 } => {
+        if !valid_v1 {
+          entry.signature[0] ^= 0xff;
+        }
+      }
+    }
+    serialize_ipns_entry(&entry).unwrap()
+  }
+
+  fn lines(shape: Shape, check_all_signatures: bool) -> Vec<String> {
+    inspect(
+      &fixture(shape),
+      None,
+      Verification::Strict,
+      check_all_signatures,
+    )
+    .unwrap()
+    .signature_lines()
+  }
+
+  #[test]
+  fn record_shapes() {
+    assert_eq!(
+      lines(Shape::V1Only, true),
+      vec![
+        "has_signature_v1: true",
+        "has_signature_v2: false",
+        "validated_with: v1"
+      ]
+    );
+    assert_eq!(
+      lines(Shape::V2Only, true),
+      vec![
+        "has_signature_v1: false",
+        "has_signature_v2: true",
+        "validated_with: v2"
+      ]
+    );
+    assert_eq!(
+      lines(Shape::Hybrid { valid_v1: true }, false),
+      vec![
+        "has_signature_v1: true",
+        "has_signature_v2: true",
+        "validated_with: v2"
+      ]
+    );
+    assert_eq!(
+      lines(Shape::Hybrid { valid_v1: true }, true),
+      vec![
+        "has_signature_v1: true",
+        "has_signature_v2: true",
+        "validated_with: v2",
+        "v1_signature_valid: true"
+      ]
+    );
+  }
+
+  #[test]
+  fn hybrid_with_bad_v1_signature() {
+    // the V1 signature is ignored unless all signatures are checked
+    let bytes = fixture(Shape::Hybrid { valid_v1: false });
+    assert_eq!(
+      inspect(&bytes, None, Verification::Strict, false)
+        .unwrap()
+        .v1_signature_valid,
+      None
+    );
+
+    let inspection = inspect(&bytes, None, Verification::Strict, true).unwrap();
+    assert_eq!(inspection.v1_signature_valid, Some(false));
+    let json = inspection.to_json();
+    assert_eq!(json["validated_with"], "v2");
+    assert_eq!(json["v1_signature_valid"], false);
+    assert_eq!(json["value"], "/ipfs/fixture");
+  }
+
+  #[test]
+  fn unverified_records() {
+    let mut entry = deserialize_ipns_entry_untrusted(&fixture(Shape::V2Only)).unwrap();
+    entry.signature_v2[0] ^= 0xff;
+    let bytes = serialize_ipns_entry(&entry).unwrap();
+
+    assert!(inspect(&bytes, None, Verification::Strict, false).is_err());
+
+    let inspection = inspect(&bytes, None, Verification::Skip, false).unwrap();
+    assert!(!inspection.verified);
+    assert_eq!(inspection.revision.value(), "/ipfs/fixture");
+    assert!(inspection
+      .signature_lines()
+      .contains(&"validated_with: none (UNVERIFIED)".to_string()));
+    let json = inspection.to_json();
+    assert_eq!(json["verified"], false);
+    assert_eq!(json["validated_with"], serde_json::Value::Null);
+
+    let json = inspect(&fixture(Shape::V2Only), None, Verification::Strict, false)
+      .unwrap()
+      .to_json();
+    assert_eq!(json["verified"], true);
+  }
+
+  #[test]
+  fn notes_questionable_ttls() {
+    let name = WritableName::new();
+    let revision = Revision::v0_with_validity(
+      &name.to_name(),
+      "/ipfs/fixture",
+      chrono::Utc::now() + Duration::days(30),
+      Duration::zero(),
+    );
+    let record = revision.to_signed_bytes(&name).unwrap();
+    let inspection = inspect(&record, Some(&name.to_name()), Verification::Strict, false).unwrap();
+    assert_eq!(inspection.ttl_notes, vec![TtlNote::Zero]);
+    assert!(inspection.note_lines()[0].starts_with("note: the TTL is zero"));
+    assert_eq!(
+      inspection.to_json()["ttl_notes"].as_array().unwrap().len(),
+      1
+    );
+
+    let fine = inspect(&fixture(Shape::V2Only), None, Verification::Strict, false).unwrap();
+    assert!(fine.note_lines().is_empty());
+    assert_eq!(fine.to_json()["ttl_notes"], serde_json::json!([]));
+  }
+
+  #[test]
+  fn binary_values_are_shown_as_base64() {
+    let name = WritableName::new();
+    let revision = w3name::RevisionBuilder::new(&name.to_name(), "")
+      .value_bytes(vec![0xde, 0xad, 0xbe, 0xef])
+      .build()
+      .unwrap();
+    let bytes = revision.to_signed_bytes(&name).unwrap();
+
+    let inspection = inspect(&bytes, Some(&name.to_name()), Verification::Strict, false).unwrap();
+    let json = inspection.to_json();
+    assert_eq!(json["value"], "3q2+7w==");
+    assert_eq!(json["value_encoding"], "base64");
+    assert!(format!("{:#}", inspection.revision).contains("value: 3q2+7w== (base64; not UTF-8)"));
+
+    let json = inspect(&fixture(Shape::V2Only), None, Verification::Strict, false)
+      .unwrap()
+      .to_json();
+    assert_eq!(json["value_encoding"], "utf8");
+  }
+
+  #[test]
+  fn name_must_match_embedded_key() {
+    let bytes = fixture(Shape::V2Only);
+    let own = WritableName::from_seed(&[1; 32]).unwrap().to_name();
+    assert!(inspect(&bytes, Some(&own), Verification::Strict, false).is_ok());
+
+    let other = WritableName::new().to_name();
+    let err = inspect(&bytes, Some(&other), Verification::Strict, false).unwrap_err();
+    assert!(format!("{:?}", err).contains("the record embeds the key of"));
+  }
+
+  #[test]
+  fn routing_key_args() {
+    let name = WritableName::new().to_name();
+    let key = w3name::ipns::routing_key_for_name(&name);
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(name_for_routing_key(&hex).unwrap(), name);
+    assert_eq!(name_for_routing_key(&base64::encode(&key)).unwrap(), name);
+
+    assert!(name_for_routing_key("not a key").is_err());
+    assert!(name_for_routing_key(&base64::encode(b"/ipfs/bafy")).is_err());
+  }
+}
@@ -0,0 +1,199 @@
+//! The JSON printed by the `--json` flag of `create`, `name` and the `key` subcommands.
+//!
+//! Every command describes a key with the same [KeyJson] fields, so scripts can treat their
+//! output alike. Secret key material is only ever printed by `key export --json
+//! --include-secret`, in [ExportJson::secret].
+
+use std::path::Path;
+
+use serde::Serialize;
+use w3name::WritableName;
+
+use crate::keyfile::{self, KeyFormat, SourceFormat};
+
+/// A key, as the key commands describe it.
+#[derive(Debug, Serialize)]
+pub struct KeyJson {
+  /// The key's name identifier.
+  pub name: String,
+  /// `ed25519`, `rsa`, `secp256k1` or `ecdsa`.
+  pub key_type: &'static str,
+  /// The key file the command read or wrote, if any.
+  pub path: Option<String>,
+  /// The format the command wrote the key file in, if it wrote one.
+  pub format: Option<KeyFormat>,
+}
+
+impl KeyJson {
+  pub fn new(name: &WritableName, path: Option<&Path>, format: Option<KeyFormat>) -> Self {
+    KeyJson {
+      name: name.to_string(),
+      key_type: keyfile::key_type(name),
+      path: path.map(|path| path.display().to_string()),
+      format,
+    }
+  }
+}
+
+/// The output of `name --json` and `key import --json`: a key that was decoded from a file or
+/// stdin.
+#[derive(Debug, Serialize)]
+pub struct DecodedKeyJson {
+  #[serde(flatten)]
+  pub key: KeyJson,
+  /// The format the key was decoded from.
+  pub source_format: SourceFormat,
+}
+
+/// The output of `key export --json`.
+#[derive(Debug, Serialize)]
+pub struct ExportJson {
+  #[serde(flatten)]
+  pub key: KeyJson,
+  /// The format of the key in the keystore.
+  pub source_format: SourceFormat,
+  /// The base64-encoded keypair, only with `--include-secret`. Along with `name`, this makes
+  /// the output a JSON key envelope that `key import` reads back.
+  #[serde(rename = "key", skip_serializing_if = "Option::is_none")]
+  pub secret: Option<String>,
+}
+
+/// The output of `key rotate --json`.
+#[derive(Debug, Serialize)]
+pub struct RotateJson {
+  pub old: KeyJson,
+  pub new: KeyJson,
+  /// Whether the new key was created by this run, rather than read from its file.
+  pub created: bool,
+  /// The value published under the new name.
+  pub value: String,
+  /// The length of `value` in bytes.
+  pub value_length: usize,
+  /// The value published under the old name, unless `--no-redirect` was given.
+  pub redirect: Option<String>,
+}
+
+/// The output of `key delete --json`.
+#[derive(Debug, Serialize)]
+pub struct DeleteJson {
+  #[serde(flatten)]
+  pub key: KeyJson,
+  /// Where the key file was moved to, or null if it was purged.
+  pub trashed_to: Option<String>,
+  /// The aliases that referred to the key, which were removed with it.
+  pub removed_aliases: Vec<String>,
+}
+
+/// Prints `output` as one line of JSON.
+pub fn print(output: &impl Serialize) {
+  println!(
+    "{}",
+    serde_json::to_string(output).expect("key JSON is always serializable")
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NAME: &str = "k51qzi5uqu5dm0t4vbwri4lkg76q03b4x9tsvekgvbu4zli6454ff7w8wdosa4";
+
+  fn key() -> WritableName {
+    WritableName::from_seed(&[7; 32]).unwrap()
+  }
+
+  fn json(output: &impl Serialize) -> String {
+    serde_json::to_string(output).unwrap()
+  }
+
+  #[test]
+  fn key_snapshots() {
+    let created = KeyJson::new(&key(), Some(Path::new("blog.key")), Some(KeyFormat::Js));
+    assert_eq!(
+      json(&created),
+      format!(
+        r#"{{"name":"{}","key_type":"ed25519","path":"blog.key","format":"js"}}"#,
+        NAME
+      )
+    );
+
+    let decoded = DecodedKeyJson {
+      key: KeyJson::new(&key(), None, None),
+      source_format: SourceFormat::HexSeed,
+    };
+    assert_eq!(
+      json(&decoded),
+      format!(
+        r#"{{"name":"{}","key_type":"ed25519","path":null,"format":null,"source_format":"hex-seed"}}"#,
+        NAME
+      )
+    );
+  }
+
+  #[test]
+  fn export_snapshots() {
+    let mut export = ExportJson {
+      key: KeyJson::new(&key(), None, Some(KeyFormat::Binary)),
+      source_format: SourceFormat::Binary,
+      secret: None,
+    };
+    assert_eq!(
+      json(&export),
+      format!(
+        r#"{{"name":"{}","key_type":"ed25519","path":null,"format":"binary","source_format":"binary"}}"#,
+        NAME
+      )
+    );
+
+    let secret = base64::encode(key().encode().unwrap());
+    export.secret = Some(secret.clone());
+    assert_eq!(
+      json(&export),
+      format!(
+        r#"{{"name":"{}","key_type":"ed25519","path":null,"format":"binary","source_format":"binary","key":"{}"}}"#,
+        NAME, secret
+      )
+    );
+    // the output is a key envelope
+    assert_eq!(
+      keyfile::decode_key(json(&export).as_bytes()).unwrap(),
+      key()
+    );
+  }
+
+  #[test]
+  fn rotate_and_delete_snapshots() {
+    let rotate = RotateJson {
+      old: KeyJson::new(&key(), Some(Path::new("old.key")), None),
+      new: KeyJson::new(&key(), Some(Path::new("new.key")), Some(KeyFormat::Binary)),
+      created: true,
+      value: "/ipfs/bafy".to_string(),
+      value_length: 10,
+      redirect: Some(format!("/ipns/{}", NAME)),
+    };
+    assert_eq!(
+      json(&rotate),
+      format!(
+        concat!(
+          r#"{{"old":{{"name":"{0}","key_type":"ed25519","path":"old.key","format":null}},"#,
+          r#""new":{{"name":"{0}","key_type":"ed25519","path":"new.key","format":"binary"}},"#,
+          r#""created":true,"value":"/ipfs/bafy","value_length":10,"redirect":"/ipns/{0}"}}"#
+        ),
+        NAME
+      )
+    );
+
+    let delete = DeleteJson {
+      key: KeyJson::new(&key(), Some(Path::new("keys/a.key")), None),
+      trashed_to: None,
+      removed_aliases: vec!["blog".to_string()],
+    };
+    assert_eq!(
+      json(&delete),
+      format!(
+        r#"{{"name":"{}","key_type":"ed25519","path":"keys/a.key","format":null,"trashed_to":null,"removed_aliases":["blog"]}}"#,
+        NAME
+      )
+    );
+  }
+}
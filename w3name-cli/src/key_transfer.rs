@@ -0,0 +1,128 @@
+//! The `key import` and `key export` subcommands, which move keys into and out of the keystore.
+
+use std::{
+  fs,
+  io::{self, Read, Write},
+  path::Path,
+};
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+
+use crate::{
+  key_json::{self, DecodedKeyJson, ExportJson, KeyJson},
+  keyfile::{self, KeyFormat},
+  keystore::Keystore,
+  CliError,
+};
+
+/// Options for [export_key].
+pub struct ExportOptions<'a> {
+  /// The format to write the key in.
+  pub format: KeyFormat,
+
+  /// Where to write the key. Without it, the key is written to stdout, unless `json` is set.
+  pub output: Option<&'a Path>,
+
+  /// Print a description of the key as JSON.
+  pub json: bool,
+
+  /// Include the key itself in the JSON.
+  pub include_secret: bool,
+}
+
+/// Adds the key in `input` (or stdin, if it's `None` or `-`) to the keystore, in any format
+/// [keyfile::decode_key] reads. A key that's already in the keystore is never overwritten.
+pub fn import_key(keystore: &Keystore, input: Option<&Path>, json: bool) -> Result<(), CliError> {
+  let (bytes, source) = match input.filter(|path| *path != Path::new("-")) {
+    Some(path) => (
+      fs::read(path)
+        .report()
+        .change_context(CliError::Usage)
+        .attach_printable(format!("key file: {}", path.display()))?,
+      format!("key file: {}", path.display()),
+    ),
+    None => {
+      let mut bytes = vec![];
+      io::stdin()
+        .read_to_end(&mut bytes)
+        .report()
+        .change_context(CliError::Usage)?;
+      (bytes, "key read from stdin".to_string())
+    }
+  };
+  let (name, source_format) = keyfile::decode_key_with_format(&bytes).attach_printable(source)?;
+
+  let path = keystore.key_path(&name.to_string());
+  if path.exists() {
+    return Err(report!(CliError::Usage).attach_printable(format!(
+      "the key for {} is already in the keystore, at {}",
+      name,
+      path.display()
+    )));
+  }
+  keyfile::create_key_file(&path, &name, KeyFormat::Binary, true)?;
+
+  if json {
+    key_json::print(&DecodedKeyJson {
+      key: KeyJson::new(&name, Some(&path), Some(KeyFormat::Binary)),
+      source_format,
+    });
+  } else {
+    println!("imported key {} to {}", name, path.display());
+  }
+  Ok(())
+}
+
+/// Writes the key called `arg`, or the key an alias called `arg` refers to, out of the keystore.
+pub fn export_key(keystore: &Keystore, arg: &str, opts: ExportOptions<'_>) -> Result<(), CliError> {
+  let name = keystore
+    .aliases()
+    .get(arg)
+    .cloned()
+    .unwrap_or_else(|| arg.to_string());
+  let path = keystore.key_path(&name);
+  if !path.is_file() {
+    return Err(
+      report!(CliError::Usage)
+        .attach_printable(format!("no key called {} in the keystore", name))
+        .attach_printable(format!("keys directory: {}", keystore.keys_dir().display())),
+    );
+  }
+  let bytes = fs::read(&path)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!("key file: {}", path.display()))?;
+  let (key, source_format) = keyfile::decode_key_with_format(&bytes)
+    .attach_printable(format!("key file: {}", path.display()))?;
+
+  if let Some(output) = opts.output {
+    keyfile::create_key_file(output, &key, opts.format, true)?;
+  }
+
+  if opts.json {
+    let secret = match opts.include_secret {
+      true => Some(base64::encode(
+        key.encode().change_context(CliError::Other)?,
+      )),
+      false => None,
+    };
+    key_json::print(&ExportJson {
+      key: KeyJson::new(&key, opts.output, Some(opts.format)),
+      source_format,
+      secret,
+    });
+  } else if let Some(output) = opts.output {
+    println!("wrote key {} to {}", key, output.display());
+  } else {
+    let encoded = key.encode().change_context(CliError::Other)?;
+    let encoded = match opts.format {
+      KeyFormat::Binary => encoded,
+      KeyFormat::Js => base64::encode(encoded).into_bytes(),
+    };
+    io::stdout()
+      .write_all(&encoded)
+      .report()
+      .change_context(CliError::Other)?;
+  }
+  Ok(())
+}
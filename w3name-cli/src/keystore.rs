@@ -0,0 +1,112 @@
+//! The local keystore: a directory of key files, plus a table of aliases for names.
+//!
+//! The keystore lives in the state directory, `$W3NAME_HOME` or `~/.w3name` (see [platform]):
+//!
+//! ```text
+//! ~/.w3name/
+//!   keys/<name>.key   key files, in the same format written by `w3name create`
+//!   aliases.json      a JSON object mapping alias -> name identifier
+//!   trash/            deleted key files, as <name>.key.<timestamp>
+//! ```
+
+use std::{
+  collections::BTreeMap,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use crate::platform;
+
+const KEYS_DIR: &str = "keys";
+const ALIASES_FILE: &str = "aliases.json";
+const TRASH_DIR: &str = "trash";
+
+pub struct Keystore {
+  root: PathBuf,
+}
+
+impl Keystore {
+  /// Opens the keystore in the default location (see [platform::state_dir]).
+  pub fn open_default() -> Option<Keystore> {
+    platform::state_dir().map(Keystore::at)
+  }
+
+  /// Opens the keystore rooted at `root`. The directory doesn't need to exist.
+  pub fn at(root: PathBuf) -> Keystore {
+    Keystore { root }
+  }
+
+  /// The directory the keystore is rooted at. It may not exist.
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  pub fn keys_dir(&self) -> PathBuf {
+    self.root.join(KEYS_DIR)
+  }
+
+  /// Returns the path of the key file for the key called `name`. The file may not exist.
+  pub fn key_path(&self, name: &str) -> PathBuf {
+    self.keys_dir().join(format!("{}.key", name))
+  }
+
+  /// The directory that deleted key files are moved to, unless they're purged.
+  pub fn trash_dir(&self) -> PathBuf {
+    self.root.join(TRASH_DIR)
+  }
+
+  /// Returns the names of all keys in the keystore, sorted.
+  ///
+  /// Returns an empty list if the keystore doesn't exist or can't be read.
+  pub fn key_names(&self) -> Vec<String> {
+    let entries = match fs::read_dir(self.keys_dir()) {
+      Ok(entries) => entries,
+      Err(_) => return vec![],
+    };
+    let mut names: Vec<String> = entries
+      .filter_map(|entry| {
+        let path = entry.ok()?.path();
+        if path.extension()? != "key" {
+          return None;
+        }
+        Some(path.file_stem()?.to_string_lossy().into_owned())
+      })
+      .collect();
+    names.sort();
+    names
+  }
+
+  /// Returns the alias table, mapping alias to name identifier.
+  ///
+  /// Returns an empty table if the alias file doesn't exist or can't be parsed.
+  pub fn aliases(&self) -> BTreeMap<String, String> {
+    fs::read(self.root.join(ALIASES_FILE))
+      .ok()
+      .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+      .unwrap_or_default()
+  }
+
+  /// Replaces the alias table.
+  pub fn set_aliases(&self, aliases: &BTreeMap<String, String>) -> io::Result<()> {
+    platform::create_private_dir(&self.root)?;
+    let json = serde_json::to_vec_pretty(aliases)?;
+    platform::atomic_write(&self.root.join(ALIASES_FILE), &json)
+  }
+
+  /// Moves the key file at `path` to the trash, appending `suffix` to its file name so earlier
+  /// deletions of the same key aren't overwritten. Returns the new path.
+  pub fn trash(&self, path: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let file_name = path
+      .file_name()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    let mut trashed = file_name.to_os_string();
+    trashed.push(".");
+    trashed.push(suffix);
+
+    let trash_dir = self.trash_dir();
+    platform::create_private_dir(&trash_dir)?;
+    let dest = trash_dir.join(trashed);
+    fs::rename(path, &dest)?;
+    Ok(dest)
+  }
+}
@@ -0,0 +1,307 @@
+//! A client for the parts of Kubo's RPC API (`/api/v0` on a local IPFS node) that w3name uses to
+//! read and write records in the DHT. As a [NamePublisher], it's a target of `publish --to kubo`.
+//!
+//! Routing commands answer with a stream of JSON query events, one per line:
+//!
+//! ```text
+//! {"Extra":"","ID":"12D3KooW...","Responses":null,"Type":0}
+//! {"Extra":"CkQvaXBm...","ID":"","Responses":null,"Type":5}
+//! ```
+//!
+//! Only events of type 5 carry a value (base64-encoded in `Extra`), and events of type 3 are
+//! query errors. The rest report the progress of the query, and are ignored.
+
+use std::{error::Error, fmt::Display};
+
+use async_trait::async_trait;
+use error_stack::{report, IntoReport, Result, ResultExt};
+use reqwest::{multipart, StatusCode, Url};
+use serde::Deserialize;
+use w3name::{
+  error::{HttpError, PublishError},
+  Name, NamePublisher, PublishReceipt, Revision, SignatureMode, WritableName,
+};
+
+use crate::CliError;
+
+pub const DEFAULT_API: &str = "http://127.0.0.1:5001";
+
+const EVENT_QUERY_ERROR: u32 = 3;
+const EVENT_VALUE: u32 = 5;
+
+/// The routing system has no record for the name: no peer that was asked had one.
+#[derive(Debug)]
+pub struct NoRoutingRecord;
+
+impl Display for NoRoutingRecord {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "no record found in the routing system")
+  }
+}
+
+impl Error for NoRoutingRecord {}
+
+/// An event of a routing query, as streamed by Kubo.
+#[derive(Debug, Deserialize)]
+struct QueryEvent {
+  #[serde(rename = "Type")]
+  kind: u32,
+  #[serde(rename = "Extra", default)]
+  extra: String,
+}
+
+/// The body of an RPC error response.
+#[derive(Debug, Deserialize)]
+struct RpcError {
+  #[serde(rename = "Message")]
+  message: String,
+}
+
+pub struct KuboClient {
+  api: Url,
+  http: reqwest::Client,
+}
+
+impl KuboClient {
+  /// Creates a client for the RPC API at `api`, e.g. `http://127.0.0.1:5001`.
+  pub fn new(api: Url) -> KuboClient {
+    KuboClient {
+      api,
+      http: reqwest::Client::new(),
+    }
+  }
+
+  pub fn api(&self) -> &Url {
+    &self.api
+  }
+
+  /// Returns the URL of an RPC command, e.g. `routing/get`, with a single argument.
+  fn rpc_url(&self, command: &str, arg: &str) -> Url {
+    let mut url = self.api.clone();
+    url.set_path(&format!("/api/v0/{}", command));
+    url.query_pairs_mut().append_pair("arg", arg);
+    url
+  }
+
+  /// Fetches the serialized record that the node finds for `name` in the routing system.
+  ///
+  /// The record isn't validated. Fails with [NoRoutingRecord] if the node found none.
+  pub async fn get_record(&self, name: &Name) -> Result<Vec<u8>, CliError> {
+    let url = self.rpc_url("routing/get", &format!("/ipns/{}", name));
+    log::debug!("Fetching record from Kubo: POST {}", url);
+
+    // the RPC API only accepts POST requests
+    let res = self
+      .http
+      .post(url.clone())
+      .send()
+      .await
+      .report()
+      .change_context(HttpError)
+      .change_context(CliError::Resolve)
+      .attach_printable(format!("Kubo API: {}", self.api))?;
+    let status = res.status();
+    let body = res
+      .text()
+      .await
+      .report()
+      .change_context(HttpError)
+      .change_context(CliError::Resolve)?;
+
+    if !status.is_success() {
+      return Err(rpc_error(status, &body));
+    }
+    record_from_events(&body)
+  }
+
+  /// Puts the serialized, signed `record` for `name` into the routing system. The node validates
+  /// it first, and rejects it if it's older than the record it already has.
+  pub async fn put_record(&self, name: &Name, record: Vec<u8>) -> Result<PublishReceipt, CliError> {
+    let url = self.rpc_url("routing/put", &format!("/ipns/{}", name));
+    log::debug!("Putting record with Kubo: POST {}", url);
+
+    let form = multipart::Form::new().part("file", multipart::Part::bytes(record.clone()));
+    let res = self
+      .http
+      .post(url)
+      .multipart(form)
+      .send()
+      .await
+      .report()
+      .change_context(HttpError)
+      .change_context(CliError::Publish)
+      .attach_printable(format!("Kubo API: {}", self.api))?;
+    let status = res.status();
+    let headers = res.headers().clone();
+    let body = res
+      .text()
+      .await
+      .report()
+      .change_context(HttpError)
+      .change_context(CliError::Publish)?;
+
+    if !status.is_success() {
+      return Err(
+        report!(HttpError)
+          .change_context(CliError::Publish)
+          .attach_printable(format!(
+            "Kubo responded with {}: {}",
+            status,
+            rpc_message(&body)
+          )),
+      );
+    }
+    // the events only report the progress of the put, but they're kept for the receipt
+    let events: Vec<serde_json::Value> = body
+      .lines()
+      .filter_map(|line| serde_json::from_str(line).ok())
+      .collect();
+    Ok(PublishReceipt {
+      status,
+      headers,
+      body: Some(events.into()),
+      record,
+      idempotency_key: None,
+    })
+  }
+}
+
+#[async_trait]
+impl NamePublisher for KuboClient {
+  async fn publish(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+  ) -> Result<PublishReceipt, PublishError> {
+    let record = revision
+      .to_signed_bytes(name)
+      .change_context(PublishError)?;
+    self
+      .put_record(&name.to_name(), record)
+      .await
+      .change_context(PublishError)
+  }
+
+  async fn publish_record(
+    &self,
+    name: &WritableName,
+    _revision: &Revision,
+    record: &[u8],
+  ) -> Result<PublishReceipt, PublishError> {
+    self
+      .put_record(&name.to_name(), record.to_vec())
+      .await
+      .change_context(PublishError)
+  }
+
+  /// Publishes a hybrid record in [SignatureMode::Hybrid], and a V2-only one otherwise. Kubo's RPC
+  /// API reports a rejected record like any other failed put, so it isn't retried as a hybrid one.
+  async fn publish_as(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    mode: SignatureMode,
+  ) -> Result<PublishReceipt, PublishError> {
+    let record = match mode {
+      SignatureMode::Hybrid => revision.to_hybrid_signed_bytes(name),
+      SignatureMode::Auto | SignatureMode::V2Only => revision.to_signed_bytes(name),
+    }
+    .change_context(PublishError)?;
+    self
+      .put_record(&name.to_name(), record)
+      .await
+      .change_context(PublishError)
+  }
+
+  fn target(&self) -> String {
+    format!("kubo {}", self.api)
+  }
+}
+
+/// Picks the record out of the query events of a `routing/get` response.
+fn record_from_events(body: &str) -> Result<Vec<u8>, CliError> {
+  let mut errors = vec![];
+  for line in body.lines().filter(|line| !line.trim().is_empty()) {
+    let event: QueryEvent = serde_json::from_str(line)
+      .report()
+      .change_context(HttpError)
+      .change_context(CliError::Resolve)
+      .attach_printable(format!("unexpected response line from Kubo: {}", line))?;
+    match event.kind {
+      EVENT_VALUE => {
+        return base64::decode(&event.extra)
+          .report()
+          .change_context(CliError::Parse)
+          .attach_printable("the record in Kubo's response isn't valid base64");
+      }
+      EVENT_QUERY_ERROR => errors.push(event.extra),
+      _ => {}
+    }
+  }
+
+  let mut err_report = report!(NoRoutingRecord).change_context(CliError::Resolve);
+  for error in errors {
+    err_report = err_report.attach_printable(format!("query error: {}", error));
+  }
+  Err(err_report)
+}
+
+/// Turns an RPC error response into a report. "Not found" errors become [NoRoutingRecord].
+fn rpc_error(status: StatusCode, body: &str) -> error_stack::Report<CliError> {
+  let message = rpc_message(body);
+  let err_report = if message.contains("not found") {
+    report!(NoRoutingRecord).change_context(CliError::Resolve)
+  } else {
+    report!(HttpError).change_context(CliError::Resolve)
+  };
+  err_report.attach_printable(format!("Kubo responded with {}: {}", status, message))
+}
+
+/// Returns the message of an RPC error response, or the whole body if it isn't one.
+fn rpc_message(body: &str) -> String {
+  serde_json::from_str::<RpcError>(body)
+    .map(|err| err.message)
+    .unwrap_or_else(|_| body.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_query_events() {
+    let body = concat!(
+      "{\"Extra\":\"\",\"ID\":\"12D3KooWA\",\"Responses\":null,\"Type\":0}\n",
+      "{\"Extra\":\"failed to dial\",\"ID\":\"12D3KooWB\",\"Responses\":null,\"Type\":3}\n",
+      "{\"Extra\":\"cmVjb3Jk\",\"ID\":\"\",\"Responses\":null,\"Type\":5}\n",
+    );
+    assert_eq!(record_from_events(body).unwrap(), b"record");
+
+    let body = "{\"Extra\":\"failed to dial\",\"ID\":\"12D3KooWB\",\"Type\":3}\n";
+    let err = record_from_events(body).unwrap_err();
+    assert!(err.contains::<NoRoutingRecord>());
+    assert!(format!("{:?}", err).contains("query error: failed to dial"));
+
+    assert!(record_from_events("not json").is_err());
+  }
+
+  #[test]
+  fn classifies_rpc_errors() {
+    let body = r#"{"Message":"routing: not found","Code":0,"Type":"error"}"#;
+    let err = rpc_error(StatusCode::INTERNAL_SERVER_ERROR, body);
+    assert!(err.contains::<NoRoutingRecord>());
+
+    let err = rpc_error(StatusCode::FORBIDDEN, "403 - Forbidden");
+    assert!(!err.contains::<NoRoutingRecord>());
+    assert!(err.contains::<HttpError>());
+  }
+
+  #[test]
+  fn builds_rpc_urls() {
+    let client = KuboClient::new(Url::parse("http://127.0.0.1:5001").unwrap());
+    assert_eq!(
+      client.rpc_url("routing/get", "/ipns/k51abc").as_str(),
+      "http://127.0.0.1:5001/api/v0/routing/get?arg=%2Fipns%2Fk51abc"
+    );
+  }
+}
@@ -0,0 +1,206 @@
+//! Advisory file locks, so that concurrent `w3name` processes on the same machine take turns
+//! writing state for a name instead of clobbering each other.
+//!
+//! Per-name locks live in the w3name state directory (see [platform::state_dir]):
+//!
+//! ```text
+//! ~/.w3name/
+//!   locks/<name>.lock
+//! ```
+//!
+//! The locks are advisory (`flock` on Unix, `LockFileEx` on Windows): they only exclude other
+//! processes that take the same lock, and are released by the OS if the process dies.
+
+use std::{
+  fs::{self, File, OpenOptions},
+  path::{Path, PathBuf},
+  thread,
+  time::{Duration, Instant},
+};
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use fs2::FileExt;
+use w3name::Name;
+
+use crate::{platform, CliError};
+
+const LOCKS_DIR: &str = "locks";
+
+/// How often a waiting [FileLock::acquire] tries the lock again.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An exclusive lock on a file, held until it's dropped.
+#[derive(Debug)]
+pub struct FileLock {
+  file: File,
+  path: PathBuf,
+}
+
+impl FileLock {
+  /// Locks `path`, creating the file and its parent directories if needed.
+  ///
+  /// If another process holds the lock, waits up to `timeout` for it to be released. `holder`
+  /// describes what the other process is doing in the error returned on timeout, e.g. "another
+  /// publish". A timeout too long to add to the current time, like `Duration::MAX`, waits forever.
+  pub fn acquire(path: &Path, timeout: Duration, holder: &str) -> Result<FileLock, CliError> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent)
+        .report()
+        .change_context(CliError::Other)
+        .attach_printable(format!("lock file: {}", path.display()))?;
+    }
+    let file = OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(path)
+      .report()
+      .change_context(CliError::Other)
+      .attach_printable(format!("lock file: {}", path.display()))?;
+
+    // `None` if the deadline is too far off to represent, which is as good as never
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+      match file.try_lock_exclusive() {
+        Ok(()) => {
+          log::debug!("Acquired lock {}", path.display());
+          return Ok(FileLock {
+            file,
+            path: path.to_path_buf(),
+          });
+        }
+        Err(err) if err.kind() == fs2::lock_contended_error().kind() => {
+          if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(
+              report!(CliError::Other)
+                .attach_printable(format!("{} is in progress", holder))
+                .attach_printable(format!(
+                  "gave up waiting for {} after {}",
+                  path.display(),
+                  humantime::format_duration(timeout)
+                )),
+            );
+          }
+          thread::sleep(POLL_INTERVAL);
+        }
+        Err(err) => {
+          return Err(err)
+            .report()
+            .change_context(CliError::Other)
+            .attach_printable(format!("lock file: {}", path.display()))
+        }
+      }
+    }
+  }
+}
+
+impl Drop for FileLock {
+  fn drop(&mut self) {
+    // closing the file releases the lock too; unlocking explicitly just makes it prompt
+    let _ = self.file.unlock();
+    log::debug!("Released lock {}", self.path.display());
+  }
+}
+
+/// Returns the path of the lock file for `name`, if the w3name state directory can be determined.
+pub fn name_lock_path(name: &Name) -> Option<PathBuf> {
+  platform::state_dir().map(|home| home.join(LOCKS_DIR).join(format!("{}.lock", name)))
+}
+
+/// Locks `name` against other local processes, waiting up to `timeout` (see [FileLock::acquire]).
+///
+/// The wait happens on a blocking thread, so it doesn't hold up the async runtime.
+///
+/// Returns `None`, without locking, if there's no w3name home directory to keep the lock in, or
+/// its locks directory can't be created, e.g. on a read-only filesystem. Locks only matter when
+/// several processes share the state directory, so a process without one has nothing to exclude.
+pub async fn lock_name(
+  name: &Name,
+  timeout: Duration,
+  holder: &str,
+) -> Result<Option<FileLock>, CliError> {
+  let path = match name_lock_path(name) {
+    Some(path) => path,
+    None => {
+      log::debug!(
+        "No w3name home directory to keep a lock in; not locking {}",
+        name
+      );
+      return Ok(None);
+    }
+  };
+  if let Some(Err(err)) = path.parent().map(platform::create_private_dir) {
+    log::debug!(
+      "Can't create the locks directory for {} ({}); not locking it",
+      path.display(),
+      err
+    );
+    return Ok(None);
+  }
+  acquire_off_runtime(path, timeout, holder).await.map(Some)
+}
+
+/// Like [FileLock::acquire], waiting on a blocking thread, so the async runtime isn't held up.
+async fn acquire_off_runtime(
+  path: PathBuf,
+  timeout: Duration,
+  holder: &str,
+) -> Result<FileLock, CliError> {
+  let holder = holder.to_string();
+  tokio::task::spawn_blocking(move || FileLock::acquire(&path, timeout, &holder))
+    .await
+    .report()
+    .change_context(CliError::Other)?
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn lock_is_exclusive_until_dropped() {
+    let dir = std::env::temp_dir().join(format!("w3name-lock-test-{}", std::process::id()));
+    let path = dir.join("locks").join("test.lock");
+
+    let lock = FileLock::acquire(&path, Duration::ZERO, "a test").unwrap();
+    let err = FileLock::acquire(&path, Duration::from_millis(120), "another test").unwrap_err();
+    assert!(format!("{:?}", err).contains("another test is in progress"));
+
+    drop(lock);
+    FileLock::acquire(&path, Duration::ZERO, "a test").unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn unrepresentable_timeouts_wait_forever() {
+    let dir = std::env::temp_dir().join(format!("w3name-lock-max-{}", std::process::id()));
+    let path = dir.join("test.lock");
+    let lock = FileLock::acquire(&path, Duration::ZERO, "a test").unwrap();
+
+    let release = thread::spawn(move || {
+      thread::sleep(Duration::from_millis(100));
+      drop(lock);
+    });
+    FileLock::acquire(&path, Duration::MAX, "another test").unwrap();
+    release.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[tokio::test(flavor = "current_thread")]
+  async fn waiting_doesnt_block_the_runtime() {
+    let dir = std::env::temp_dir().join(format!("w3name-lock-async-{}", std::process::id()));
+    let path = dir.join("test.lock");
+    let lock = FileLock::acquire(&path, Duration::ZERO, "a test").unwrap();
+
+    // the runtime has one thread, so the lock can only be released if waiting doesn't block it
+    let release = tokio::spawn(async move {
+      tokio::time::sleep(Duration::from_millis(100)).await;
+      drop(lock);
+    });
+    acquire_off_runtime(path, Duration::from_secs(5), "another test")
+      .await
+      .unwrap();
+    release.await.unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,282 @@
+//! Logging setup: timestamped log lines on stderr, optionally copied to a size-rotated log file.
+
+use std::{
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  path::{Path, PathBuf},
+};
+
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use error_stack::{IntoReport, Result, ResultExt};
+use log::{LevelFilter, Record};
+
+use crate::CliError;
+
+/// Log files are rotated once they reach this size.
+const MAX_LOG_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The number of rotated log files to keep, named `<path>.1` (newest) to `<path>.<N>` (oldest).
+const LOG_FILE_BACKUPS: usize = 3;
+
+/// The format of log lines.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+  /// `[timestamp LEVEL target] message`
+  Text,
+  /// One JSON object per line, with `timestamp`, `level`, `target` and `message` fields.
+  Json,
+}
+
+/// Installs the global logger.
+///
+/// Logs at `Debug` level if `verbose` is set and at `Warn` level otherwise, to stderr and,
+/// if `log_file` is given, to that file as well. The `RUST_LOG` environment variable can raise
+/// the level, and set levels for modules, e.g. `RUST_LOG=hyper=trace` (see [LogFilter]).
+pub fn init(verbose: bool, format: LogFormat, log_file: Option<&Path>) -> Result<(), CliError> {
+  let level = if verbose {
+    LevelFilter::Debug
+  } else {
+    LevelFilter::Warn
+  };
+  let filter = std::env::var("RUST_LOG")
+    .map(|spec| LogFilter::parse(&spec))
+    .unwrap_or_default();
+
+  let mut dispatch = fern::Dispatch::new().level(filter.level.map_or(level, |own| own.max(level)));
+  for (module, level) in &filter.modules {
+    dispatch = dispatch.level_for(module.clone(), *level);
+  }
+  let mut dispatch = dispatch
+    .format(move |out, _message, record| {
+      out.finish(format_args!("{}", format_line(format, record)))
+    })
+    .chain(io::stderr());
+
+  if let Some(path) = log_file {
+    let file = RotatingFile::open(path, MAX_LOG_FILE_SIZE, LOG_FILE_BACKUPS)
+      .report()
+      .change_context(CliError::Usage)
+      .attach_printable(format!("log file: {}", path.display()))?;
+    dispatch = dispatch.chain(Box::new(file) as Box<dyn Write + Send>);
+  }
+
+  dispatch.apply().report().change_context(CliError::Other)?;
+  for directive in &filter.invalid {
+    log::warn!("Ignoring invalid RUST_LOG directive {:?}", directive);
+  }
+  Ok(())
+}
+
+/// Log levels from `RUST_LOG`, in the comma-separated `[module=]level` form of `env_logger`,
+/// e.g. `info,hyper=trace,w3name::client=debug`. A module without a level is logged at every
+/// level. Regular expression filters (`/regex`) aren't supported.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LogFilter {
+  /// The level for all modules, if given.
+  pub level: Option<LevelFilter>,
+  /// Levels for modules and their submodules.
+  pub modules: Vec<(String, LevelFilter)>,
+  /// Directives that couldn't be parsed.
+  pub invalid: Vec<String>,
+}
+
+impl LogFilter {
+  pub fn parse(spec: &str) -> LogFilter {
+    let mut filter = LogFilter::default();
+    let directives = spec.split(',').map(str::trim).filter(|d| !d.is_empty());
+    for directive in directives {
+      match directive.split_once('=') {
+        Some((module, level)) => match level.trim().parse() {
+          Ok(level) if !module.trim().is_empty() => {
+            filter.modules.push((module.trim().to_string(), level))
+          }
+          _ => filter.invalid.push(directive.to_string()),
+        },
+        None => match directive.parse() {
+          Ok(level) => filter.level = Some(level),
+          Err(_) if directive.contains('/') => filter.invalid.push(directive.to_string()),
+          // a bare module name, like env_logger
+          Err(_) => filter
+            .modules
+            .push((directive.to_string(), LevelFilter::Trace)),
+        },
+      }
+    }
+    filter
+  }
+}
+
+fn format_line(format: LogFormat, record: &Record) -> String {
+  let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+  match format {
+    LogFormat::Text => format!(
+      "[{} {} {}] {}",
+      timestamp,
+      record.level(),
+      record.target(),
+      record.args()
+    ),
+    LogFormat::Json => serde_json::json!({
+      "timestamp": timestamp,
+      "level": record.level().as_str(),
+      "target": record.target(),
+      "message": record.args().to_string(),
+    })
+    .to_string(),
+  }
+}
+
+/// A log file that is rotated when it grows past a maximum size.
+///
+/// On rotation, `<path>` becomes `<path>.1`, `<path>.1` becomes `<path>.2`, and so on,
+/// dropping the oldest file once there are `backups` of them.
+pub struct RotatingFile {
+  path: PathBuf,
+  max_size: u64,
+  backups: usize,
+  file: File,
+  size: u64,
+}
+
+impl RotatingFile {
+  /// Opens `path` for appending, creating it if needed.
+  pub fn open(path: &Path, max_size: u64, backups: usize) -> io::Result<RotatingFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok(RotatingFile {
+      path: path.to_path_buf(),
+      max_size,
+      backups,
+      file,
+      size,
+    })
+  }
+
+  fn backup_path(&self, n: usize) -> PathBuf {
+    let mut path = self.path.clone().into_os_string();
+    path.push(format!(".{}", n));
+    PathBuf::from(path)
+  }
+
+  fn rotate(&mut self) -> io::Result<()> {
+    self.file.flush()?;
+    if self.backups == 0 {
+      self.file = File::create(&self.path)?;
+    } else {
+      for n in (1..self.backups).rev() {
+        let from = self.backup_path(n);
+        if from.exists() {
+          fs::rename(from, self.backup_path(n + 1))?;
+        }
+      }
+      fs::rename(&self.path, self.backup_path(1))?;
+      self.file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&self.path)?;
+    }
+    self.size = 0;
+    Ok(())
+  }
+}
+
+impl Write for RotatingFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    // fern writes each log line with a single call, so lines are never split across files
+    if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+      self.rotate()?;
+    }
+    let written = self.file.write(buf)?;
+    self.size += written as u64;
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.file.flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use log::Level;
+
+  #[test]
+  fn parse_rust_log() {
+    assert_eq!(LogFilter::parse(""), LogFilter::default());
+    let filter = LogFilter::parse("info, hyper=trace,w3name::client=debug,reqwest,x=loud,a/b");
+    assert_eq!(filter.level, Some(LevelFilter::Info));
+    assert_eq!(
+      filter.modules,
+      vec![
+        ("hyper".to_string(), LevelFilter::Trace),
+        ("w3name::client".to_string(), LevelFilter::Debug),
+        ("reqwest".to_string(), LevelFilter::Trace),
+      ]
+    );
+    assert_eq!(filter.invalid, vec!["x=loud", "a/b"]);
+  }
+
+  fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("w3name-logging-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn rotates_at_size_threshold() {
+    let dir = temp_dir("rotate");
+    let path = dir.join("w3name.log");
+    let mut file = RotatingFile::open(&path, 20, 2).unwrap();
+    assert!(path.exists());
+
+    for line in [
+      "first line\n",
+      "second line\n",
+      "third line\n",
+      "fourth line\n",
+    ] {
+      file.write_all(line.as_bytes()).unwrap();
+    }
+    file.flush().unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\n");
+    assert_eq!(
+      fs::read_to_string(dir.join("w3name.log.1")).unwrap(),
+      "third line\n"
+    );
+    assert_eq!(
+      fs::read_to_string(dir.join("w3name.log.2")).unwrap(),
+      "second line\n"
+    );
+    assert!(!dir.join("w3name.log.3").exists());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  fn format_test_line(format: LogFormat) -> String {
+    format_line(
+      format,
+      &Record::builder()
+        .level(Level::Debug)
+        .target("w3name::client")
+        .args(format_args!("HTTP GET {}", "https://name.web3.storage"))
+        .build(),
+    )
+  }
+
+  #[test]
+  fn json_lines() {
+    let line = format_test_line(LogFormat::Json);
+
+    let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(json["level"], "DEBUG");
+    assert_eq!(json["target"], "w3name::client");
+    assert_eq!(json["message"], "HTTP GET https://name.web3.storage");
+    assert!(json["timestamp"].as_str().unwrap().ends_with('Z'));
+
+    let line = format_test_line(LogFormat::Text);
+    assert!(line.ends_with(" DEBUG w3name::client] HTTP GET https://name.web3.storage"));
+  }
+}
@@ -1,250 +1,2684 @@
-use std::{error::Error, fmt::Display, fs, io, path::PathBuf, process::exit};
+use std::{
+  borrow::Cow,
+  error::Error,
+  fmt::Display,
+  io::{self, Read},
+  path::{Path, PathBuf},
+  process::exit,
+  time::Duration,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use error_stack::{report, Context, IntoReport, Report, Result, ResultExt};
+
+use w3name::{
+  error::{APIError, ClientError, RecordExpired, ValidityOutOfRange, ValidityTooShort},
+  ChainResolver, CurrentRevision, IncrementPolicy, Name, PreparedRevision, PublishOptions,
+  PublishOutcome, PublishReceipt, PublishedRevision, ResolvedRevision, Resolver, Revision,
+  RevisionBuilder, RevisionSource, SignatureMode, SourceKind, TtlChecks, Value, WritableName,
+};
+
+mod archive;
+mod bench;
+mod cache;
+mod clock;
+mod completions;
+mod config;
+mod delete;
+mod dnslink;
+mod error_output;
+mod exit_code;
+mod fanout;
+mod from_record;
+mod inspect;
+mod key_json;
+mod key_transfer;
+mod keyfile;
+mod keystore;
+mod kubo;
+mod lock;
+mod logging;
+mod manifest;
+mod metrics;
+mod platform;
+mod prompt;
+mod prove;
+mod published;
+mod record;
+mod record_age;
+mod rotate;
+mod sequence_gate;
+mod service;
+mod status;
+mod table;
+mod timefmt;
+mod value_source;
+mod watch;
+
+use archive::RecordArchive;
+use cache::RecordCache;
+use error_output::{ErrorFormat, InvolvedName};
+use keystore::Keystore;
+use logging::LogFormat;
+use record_age::AgeLimits;
+use table::{Cell, Style, Table};
+use value_source::ValueSource;
+
+/// How far in the future `publish --valid-until` must be, so that clock skew between here and
+/// resolvers can't make the record expire as soon as it's published.
+const VALID_UNTIL_MIN_LEAD: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Parser)]
+#[clap(name = "w3name", version, about, long_about = None, after_help = exit_code::HELP)]
+/// A tool for creating verifiable names in a web3 world.
+struct Cli {
+  /// Enable verbose debug logging
+  #[clap(long, global = true)]
+  verbose: bool,
+
+  /// How to print errors: a human-readable report, or a single JSON object on stderr.
+  #[clap(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+  errors: ErrorFormat,
+
+  /// Also write logs to this file. It is rotated once it reaches 10 MiB, keeping 3 old files.
+  #[clap(long, global = true, value_parser = platform::path_arg, value_name = "PATH")]
+  log_file: Option<PathBuf>,
+
+  /// The format of log lines, on stderr and in the `--log-file`.
+  #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+  log_format: LogFormat,
+
+  /// Forbid network access. Commands that need the network fail immediately instead.
+  #[clap(long, global = true)]
+  offline: bool,
+
+  /// Log each request to the w3name service and its response in full, with credentials redacted.
+  ///
+  /// Implies `--verbose`.
+  #[clap(long, global = true)]
+  trace_http: bool,
+
+  /// Only resolve names from the w3name service. By default, `resolve`, `watch`, `status` and
+  /// `publish` also ask the trustless gateways when the service fails or has no record for a name.
+  #[clap(long, global = true)]
+  no_fallback: bool,
+
+  /// Show times in text output in UTC. This is the default; JSON output always uses UTC.
+  #[clap(long, global = true, conflicts_with = "local")]
+  utc: bool,
+
+  /// Show times in text output in local time rather than UTC.
+  #[clap(long, global = true)]
+  local: bool,
+
+  /// Never ask for missing arguments, even on a terminal: fail as if run from a script.
+  ///
+  /// On a terminal, `publish` asks for a missing key and value, and `create` asks where to save
+  /// the key if no `--output` is given.
+  #[clap(long, global = true)]
+  no_input: bool,
+
+  /// Use this RFC 3339 time as the current time, e.g. for reproducible output in tests.
+  #[clap(long, global = true, hide = true, value_parser, value_name = "TIME")]
+  frozen_time: Option<chrono::DateTime<chrono::Utc>>,
+
+  /// When to color tables and statuses in text output. `auto` colors them on a terminal, unless
+  /// `NO_COLOR` is set.
+  #[clap(long, global = true, value_enum, default_value_t = table::ColorChoice::Auto)]
+  color: table::ColorChoice,
+
+  /// Never color output; the same as `--color never`.
+  #[clap(long, global = true)]
+  no_color: bool,
+
+  /// Don't truncate long names, paths and values in tables to fit the terminal's width.
+  #[clap(long, global = true)]
+  wide: bool,
+
+  #[clap(subcommand)]
+  command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+  /// Lookup the current value for a name record.
+  Resolve {
+    /// The name identifier, e.g. "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu", an alias from the keystore,
+    /// or an IPNS URL like "ipns://<name>/path" or "https://<name>.ipns.dweb.link/path".
+    ///
+    /// A path after the name is appended to the resolved value.
+    #[clap(value_parser, required_unless_present_any = &["manifest", "manifest-file"])]
+    name: Option<String>,
+
+    /// Resolve every name in the manifest at this URL: a JSON object mapping name identifiers to labels.
+    ///
+    /// Names are resolved concurrently, and a name that fails to resolve doesn't stop the others.
+    /// The cache isn't used.
+    #[clap(long, value_parser, value_name = "URL", conflicts_with_all = &["name", "manifest-file"])]
+    manifest: Option<String>,
+
+    /// Like `--manifest`, but reads the manifest from a local file.
+    #[clap(long, value_parser = platform::path_arg, value_name = "PATH", conflicts_with = "name")]
+    manifest_file: Option<PathBuf>,
+
+    /// Print manifest results as JSON lines rather than a table.
+    #[clap(long)]
+    jsonl: bool,
+
+    /// Print the resolved record as a JSON object, including where it came from: the service
+    /// endpoint or the cache, how long resolving took, and when the record was fetched.
+    #[clap(long, conflicts_with_all = &["manifest", "manifest-file", "jsonl"])]
+    json: bool,
+
+    /// Print the resolved record's details along with the value: its sequence, when it expires,
+    /// its TTL, where it came from and when it was fetched.
+    #[clap(long, conflicts_with_all = &["manifest", "manifest-file", "jsonl", "json"])]
+    long: bool,
+
+    /// Parse the value as a JSON document, as published with `publish --value-json`, and
+    /// pretty-print it. Fails if the value isn't JSON.
+    #[clap(long, conflicts_with_all = &["manifest", "manifest-file", "jsonl", "json", "long", "format"])]
+    json_value: bool,
+
+    /// Also write the resolved record to this file, as a record archive (see `parse --archive`).
+    /// Given a directory, the archive is written to a file named
+    /// `<name>-seq<sequence>-<timestamp>.w3r` in it.
+    ///
+    /// The record is always fetched from the service, rather than the cache.
+    #[clap(long, value_parser = platform::path_arg, value_name = "PATH", conflicts_with_all = &["manifest", "manifest-file"])]
+    archive: Option<PathBuf>,
+
+    /// Fail with exit code 7 if the resolved record is older than the record archived in this file,
+    /// or the newest one for the name among the `.w3r` archives in this directory.
+    ///
+    /// This detects a service or mirror serving a rolled back record. The record is always fetched
+    /// from the service, rather than the cache.
+    #[clap(long, value_parser = platform::path_arg, value_name = "PATH", conflicts_with_all = &["manifest", "manifest-file"])]
+    verify_against: Option<PathBuf>,
+
+    /// Fail with exit code 12 if the resolved record has been current for longer than this, e.g. "30d".
+    ///
+    /// Records don't say when they were signed, so this is approximated by when this machine first
+    /// saw the record's sequence number, which `resolve` notes in the cache directory. A name
+    /// resolved for the first time, or after `cache clear`, always passes.
+    #[clap(long, value_parser, value_name = "DURATION", conflicts_with_all = &["manifest", "manifest-file", "no-cache"])]
+    max_record_age: Option<humantime::Duration>,
+
+    /// Like `--max-record-age`, but only log a warning.
+    #[clap(long, value_parser, value_name = "DURATION", conflicts_with_all = &["manifest", "manifest-file", "no-cache"])]
+    warn_record_age: Option<humantime::Duration>,
+
+    /// Fail with exit code 13 unless the resolved record's sequence is at least this, e.g. to
+    /// wait in a deploy pipeline until a new record is served.
+    ///
+    /// The record is always fetched from the service, rather than the cache. With `--gateway`,
+    /// the gateways must serve the sequence too.
+    #[clap(long, value_parser, value_name = "SEQUENCE", conflicts_with_all = &["manifest", "manifest-file"])]
+    require_sequence_at_least: Option<u64>,
+
+    /// Also require this trustless gateway to serve the sequence, e.g. "https://ipfs.io". May be
+    /// given more than once.
+    #[clap(
+      long = "gateway",
+      value_parser,
+      value_name = "URL",
+      requires = "require-sequence-at-least"
+    )]
+    gateways: Vec<reqwest::Url>,
+
+    /// How many of the sources (the service and each `--gateway`) must serve the sequence. All of
+    /// them by default.
+    #[clap(
+      long,
+      value_parser,
+      value_name = "N",
+      requires = "require-sequence-at-least"
+    )]
+    quorum: Option<usize>,
+
+    #[clap(flatten)]
+    format: FormatArgs,
+
+    #[clap(flatten)]
+    cache: CacheArgs,
+  },
+
+  /// Poll a name and print its value whenever the record changes.
+  Watch {
+    /// The name identifier, e.g. "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu", an alias from the keystore,
+    /// or an IPNS URL like "ipns://<name>/path" or "https://<name>.ipns.dweb.link/path".
+    ///
+    /// A path after the name is appended to the resolved value.
+    #[clap(value_parser)]
+    name: String,
+
+    /// Seconds to wait between polls.
+    #[clap(long, value_parser, default_value_t = 60)]
+    interval: u64,
+
+    /// Shell command to run whenever the sequence number advances.
+    ///
+    /// The new value, sequence and name are available to the command as the
+    /// `W3NAME_VALUE`, `W3NAME_SEQUENCE` and `W3NAME_NAME` environment variables, and when
+    /// the sequence was first seen as `W3NAME_FIRST_SEEN_AT`.
+    /// The command runs to completion before the next poll, so executions never overlap;
+    /// changes made while it runs are picked up (coalesced) by the next poll.
+    /// It doesn't run for the record found when watching starts, unless `--exec-on-start` is given.
+    #[clap(long, value_parser)]
+    exec: Option<String>,
+
+    /// Also run the `--exec` command for the record found when watching starts.
+    #[clap(long, requires = "exec")]
+    exec_on_start: bool,
+
+    /// Stop watching if the `--exec` command fails, instead of logging the failure.
+    #[clap(long, requires = "exec")]
+    exec_fatal: bool,
+
+    /// Serve Prometheus metrics about the name on this address, e.g. "127.0.0.1:9153", at `/metrics`.
+    ///
+    /// The metrics include the time of the last successful resolve, the current sequence number,
+    /// the seconds until the record expires, and counts of failed resolves.
+    #[clap(long, value_parser, value_name = "ADDR")]
+    prometheus: Option<std::net::SocketAddr>,
+
+    /// Report a record with a lower sequence number than one seen before as a change.
+    ///
+    /// By default such records, e.g. from a stale replica of the service, are logged and ignored,
+    /// so the output never goes backwards.
+    #[clap(long)]
+    accept_regression: bool,
+
+    /// Print each new record's sequence, when it expires and when its sequence was first seen after
+    /// its value, on the same line.
+    #[clap(long)]
+    long: bool,
+
+    /// Stop watching, and exit successfully, once the record's sequence is at least this.
+    #[clap(long, value_parser, value_name = "SEQUENCE")]
+    until_sequence: Option<u64>,
+  },
+
+  /// Check the health of a name: fetch its record from the service and each gateway, compare
+  /// them, and print a PASS, WARN or FAIL verdict.
+  ///
+  /// Exits with code 0 for PASS, 10 for WARN and 11 for FAIL.
+  Status {
+    /// The name identifier, e.g. "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu", an alias from the keystore,
+    /// or an IPNS URL like "ipns://<name>".
+    #[clap(value_parser)]
+    name: String,
+
+    /// How long each source is given to answer, e.g. "10s". A source that doesn't answer in time
+    /// is reported as timed out, without holding up the others.
+    #[clap(long, value_parser, value_name = "DURATION", default_value = "10s")]
+    timeout: humantime::Duration,
+
+    /// Warn if the newest record expires within this long, e.g. "7d".
+    #[clap(long, value_parser, value_name = "DURATION", default_value = "7d")]
+    expiry_warning: humantime::Duration,
+
+    /// Print the report as a JSON object.
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Publish a new value for a name, signed with the name's private key.
+  Publish(PublishArgs),
+
+  /// Create a new public/private keypair and save it to disk.
+  Create {
+    /// Filename to write the key to. An existing file is never overwritten.
+    ///
+    /// If not given, will write to a file named `<name>.key`,
+    /// where `<name>` is the string form of the public key. If it's a directory (or ends with
+    /// `/`), the key is written to `<name>.key` inside it.
+    #[clap(short, long, value_parser = platform::path_arg)]
+    output: Option<PathBuf>,
+
+    /// Fail if the output file's directory doesn't exist, rather than creating it.
+    #[clap(long)]
+    no_create_dirs: bool,
+
+    /// The key file format. `js` writes base64 text, which the JavaScript w3name client can read.
+    #[clap(long, value_enum, default_value_t = keyfile::KeyFormat::Binary)]
+    format: keyfile::KeyFormat,
+
+    /// Print the new key's name, type, path and format as JSON.
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Print the name for an existing key, without writing any files.
+  ///
+  /// Accepts key files written by `create`, ed25519 PKCS#8 PEM keys, and 32-byte ed25519 seeds (raw or hex).
+  Name {
+    /// Path to the key. If neither this nor `--seed` is given, the key is read from stdin.
+    #[clap(value_parser = platform::path_arg, value_name = "KEY_FILE")]
+    key: Option<PathBuf>,
+
+    /// A hex-encoded 32-byte ed25519 seed.
+    #[clap(long, value_parser, conflicts_with = "key")]
+    seed: Option<String>,
+
+    /// Print the name, key type and detected key format as JSON.
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Parse a record
+  Parse {
+    /// base64-encoded record. If neither this nor `--archive` is given, it's read from stdin.
+    #[clap(value_parser)]
+    record: Option<String>,
+
+    /// Read the record from a record archive, written by `resolve --archive` or `publish --archive`.
+    ///
+    /// The record is validated against the name in the archive, and the archive's source and
+    /// timestamp are printed too.
+    #[clap(long, value_parser = platform::path_arg, value_name = "PATH", conflicts_with = "record")]
+    archive: Option<PathBuf>,
+
+    /// Validate the record against this name, for records that don't embed their public key.
+    #[clap(long, value_parser, conflicts_with = "archive")]
+    name: Option<String>,
+
+    /// Like `--name`, given as a binary IPNS routing key (`/ipns/` + multihash), hex or base64 encoded.
+    ///
+    /// This is the key the record is stored under in the DHT.
+    #[clap(
+      long,
+      value_parser,
+      value_name = "KEY",
+      conflicts_with_all = &["archive", "name"]
+    )]
+    routing_key: Option<String>,
+
+    /// Print only the record's value, rendered in the given format, instead of the full record.
+    #[clap(flatten)]
+    format: FormatArgs,
+
+    /// Accept hybrid V1+V2 records whose V1 fields disagree with the signed V2 data.
+    ///
+    /// The V2 data is printed, and each inconsistent field is reported as a warning.
+    #[clap(long)]
+    lenient: bool,
+
+    /// For hybrid V1+V2 records, verify the V1 signature as well as the V2 signature.
+    ///
+    /// Both results are reported, and the command fails if either signature is invalid.
+    #[clap(long)]
+    check_all_signatures: bool,
+
+    /// Decode and print the record without checking its signatures, e.g. to debug a publisher
+    /// that signs records incorrectly.
+    ///
+    /// The output is marked as UNVERIFIED, and `verified` is false in the JSON. Never trust the
+    /// value of a record printed this way.
+    #[clap(long, conflicts_with_all = &["lenient", "check-all-signatures"])]
+    no_verify: bool,
+
+    /// Print the record, and which signatures it has and were checked, as a JSON object.
+    #[clap(long, conflicts_with = "format")]
+    json: bool,
+  },
+
+  /// Print the DNSLink TXT record value that points a domain to a name.
+  DnslinkTxt {
+    /// A key file, an alias from the keystore, or a name identifier.
+    #[clap(value_parser)]
+    name: String,
+
+    /// Print a zone file line for this domain instead, e.g. `_dnslink.example.com. 300 IN TXT "..."`.
+    #[clap(long, value_parser, value_name = "DOMAIN")]
+    zone_file: Option<String>,
+  },
+
+  /// Sign a proof that you control a name, for a challenge string from e.g. a registrar.
+  ///
+  /// Prints a JSON object with the name, challenge, timestamp and base64 signature. The signed
+  /// message is framed by a `w3name-proof:` prefix and suffix, so it can't be mistaken for a name
+  /// record.
+  #[clap(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+  Prove {
+    #[clap(subcommand)]
+    command: Option<ProveCommand>,
+
+    /// Path to the key file of the name.
+    #[clap(short, long, value_parser = platform::path_arg, value_name = "KEY_FILE", required = true)]
+    key: Option<PathBuf>,
+
+    /// The challenge string to sign.
+    #[clap(long, value_parser, required = true)]
+    challenge: Option<String>,
+  },
+
+  /// Work with serialized records and record archives.
+  Record {
+    #[clap(subcommand)]
+    command: RecordCommand,
+  },
+
+  /// Manage name keys.
+  Key {
+    #[clap(subcommand)]
+    command: KeyCommand,
+  },
+
+  /// Manage the aliases in the keystore.
+  Alias {
+    #[clap(subcommand)]
+    command: AliasCommand,
+  },
+
+  /// Inspect or clear the local record cache used by `resolve`.
+  Cache {
+    #[clap(subcommand)]
+    command: CacheCommand,
+  },
+
+  /// Print a shell completion script.
+  ///
+  /// For bash, zsh and fish, the script also completes key names and aliases from the keystore.
+  Completions {
+    #[clap(value_enum)]
+    shell: Shell,
+  },
+
+  /// Measure the latency and throughput of the w3name service at `$W3NAME_ENDPOINT`, for
+  /// operators of self-hosted services.
+  #[clap(hide = true)]
+  Bench {
+    #[clap(subcommand)]
+    command: BenchCommand,
+  },
+}
+
+#[derive(Subcommand)]
+enum BenchCommand {
+  /// Resolve a name repeatedly.
+  Resolve {
+    /// The name to resolve: a name identifier or an alias.
+    #[clap(long, value_parser)]
+    name: String,
+
+    #[clap(flatten)]
+    args: BenchArgs,
+  },
+  /// Publish records for a name repeatedly. The name must have a record already.
+  ///
+  /// Each request publishes a successor of the previous record with the same value, so the
+  /// name's value doesn't change, but its sequence number does.
+  Publish {
+    /// Path to the name's key file.
+    #[clap(short, long, value_parser = platform::path_arg, value_name = "KEY_FILE")]
+    key: PathBuf,
+
+    /// Upload the name's current record as is, every time, instead of successors of it, so the
+    /// name isn't changed at all.
+    #[clap(long)]
+    dry_upload: bool,
+
+    /// Benchmark publishing against the public w3name service, which is refused otherwise.
+    #[clap(long)]
+    i_own_this_endpoint: bool,
+
+    #[clap(flatten)]
+    args: BenchArgs,
+  },
+}
+
+#[derive(Args)]
+struct BenchArgs {
+  /// The number of requests to send.
+  #[clap(long, value_parser, default_value_t = 100)]
+  requests: usize,
+
+  /// The number of requests in flight at once.
+  #[clap(long, value_parser, default_value_t = 10)]
+  concurrency: usize,
+
+  /// Retry requests that fail with a network error, 429 or a 5xx status up to this many times.
+  /// By default, failures aren't retried, so they're measured as they are.
+  #[clap(long, value_parser, default_value_t = 0)]
+  retries: u32,
+
+  /// Fail requests that take longer than this, e.g. "5s".
+  #[clap(long, value_parser, value_name = "DURATION", default_value = "30s")]
+  timeout: humantime::Duration,
+
+  /// The most requests sent per second. The client's usual limit is 30.
+  #[clap(long, value_parser, default_value = "30")]
+  rate_limit: std::num::NonZeroU32,
+
+  /// Print the results as a JSON object.
+  #[clap(long)]
+  json: bool,
+}
+
+impl BenchArgs {
+  fn options(&self, network: service::Network) -> bench::BenchOptions {
+    bench::BenchOptions {
+      requests: self.requests,
+      concurrency: self.concurrency,
+      retries: self.retries,
+      timeout: self.timeout.into(),
+      rate_limit: self.rate_limit,
+      json: self.json,
+      network,
+    }
+  }
+}
+
+#[derive(Subcommand)]
+enum AliasCommand {
+  /// Remove an alias. The key it refers to is kept.
+  Rm {
+    /// The alias to remove.
+    #[clap(value_parser)]
+    alias: String,
+  },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+  /// List the cached records, with their age and how much longer `resolve` would use them.
+  Show {
+    /// Only show the record for this name: a name identifier, an alias or a key file.
+    #[clap(value_parser)]
+    name: Option<String>,
+
+    /// Print the entries as a JSON array.
+    #[clap(long)]
+    json: bool,
+  },
+  /// Remove the cached record for a name, or all cached records.
+  Clear {
+    /// Only remove the record for this name: a name identifier, an alias or a key file.
+    #[clap(value_parser)]
+    name: Option<String>,
+
+    /// Print the result as JSON.
+    #[clap(long)]
+    json: bool,
+  },
+  /// Remove the cached records that `resolve` would no longer use: those that are stale, that
+  /// have expired, or that are corrupt.
+  Prune {
+    /// Print the result as JSON.
+    #[clap(long)]
+    json: bool,
+  },
+  /// Print how many lookups found a fresh record in the cache, and the size of the cache.
+  Stats {
+    /// Print the stats as JSON.
+    #[clap(long)]
+    json: bool,
+  },
+}
+
+#[derive(Subcommand)]
+enum ProveCommand {
+  /// Check a proof written by `w3name prove`, and print its challenge and age.
+  Verify {
+    /// The name the proof should be for: a name identifier, an alias from the keystore, or a key file.
+    #[clap(long, value_parser)]
+    name: String,
+
+    /// Path to the proof JSON.
+    #[clap(long, value_parser = platform::path_arg, value_name = "FILE")]
+    proof: PathBuf,
+
+    /// Reject proofs signed longer ago than this, e.g. "10m", so that old proofs can't be replayed.
+    #[clap(long, value_parser)]
+    max_age: Option<humantime::Duration>,
+  },
+}
+
+#[derive(Subcommand)]
+enum RecordCommand {
+  /// Re-sign a record with a fresh validity, keeping its value.
+  ///
+  /// Reads the record from stdin, as binary or base64, and writes the new record to stdout. The new
+  /// record has the next sequence number and the same TTL, so it supersedes the input. Records that
+  /// weren't signed by the key are refused.
+  Resign {
+    /// Path to the key file of the record's name.
+    #[clap(short, long, value_parser = platform::path_arg, value_name = "KEY_FILE")]
+    key: PathBuf,
+
+    /// How long the new record stays valid, e.g. "30days" or "1y".
+    ///
+    /// If not given, the library default of one year is used.
+    #[clap(long, value_parser)]
+    validity: Option<humantime::Duration>,
+
+    /// Write the new record as base64 text instead of binary.
+    #[clap(long)]
+    base64: bool,
+
+    /// Abort before signing unless the key is for this name: a name identifier or an alias.
+    #[clap(long, value_parser, value_name = "NAME")]
+    expect_name: Option<String>,
+  },
+
+  /// Fetch a name's record from the DHT, through the RPC API of a Kubo (go-ipfs) node.
+  ///
+  /// Validates the record against the name and writes it to stdout, or to `--output`. Use this to
+  /// check what the DHT holds when it seems to disagree with the service or gateways. Exits with
+  /// code 8 if the node finds no record.
+  Fetch {
+    /// The name identifier, or an alias from the keystore.
+    #[clap(value_parser)]
+    name: String,
 
-use clap::{Parser, Subcommand};
-use error_stack::{IntoReport, Report, Result, ResultExt};
+    /// The URL of the Kubo RPC API.
+    #[clap(long, value_parser, default_value = kubo::DEFAULT_API, value_name = "URL")]
+    kubo_api: reqwest::Url,
+
+    /// Write the record to this file instead of stdout.
+    #[clap(short, long, value_parser = platform::path_arg, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Write the record as base64 text instead of binary.
+    #[clap(long)]
+    base64: bool,
+  },
+
+  /// List record archives, with their names, sequence numbers, validity and values.
+  ///
+  /// Each record is validated against the archive's name; invalid records are listed as unverified,
+  /// and files that aren't record archives with an error, rather than failing the listing.
+  Ls {
+    /// Directories to list the `.w3r` archives of, or archive files. Wildcards (`*` and `?`) in
+    /// the last component are expanded, e.g. `archives/k51*.w3r`.
+    #[clap(value_parser = platform::path_arg, value_name = "PATH", required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Only list the archives of this name: a name identifier, an alias or a key file.
+    #[clap(long, value_parser)]
+    name: Option<String>,
+
+    /// Only list archives whose records have expired.
+    #[clap(long)]
+    expired_only: bool,
+
+    /// How to sort the archives. Ties are broken by path.
+    #[clap(long, value_enum, default_value = "name")]
+    sort: archive::ListOrder,
+
+    /// Print the archives as a JSON array.
+    #[clap(long)]
+    json: bool,
+  },
+}
+
+#[derive(Subcommand)]
+enum KeyCommand {
+  /// Move a name's value to a new key, and redirect the old name to the new one.
+  ///
+  /// Resolves the current value of the old name, publishes it under the new name, and then
+  /// publishes `/ipns/<new name>` under the old name so followers are redirected. If a step fails,
+  /// the completed steps are reported, and running the same command again resumes the rotation.
+  Rotate {
+    /// Key file of the name being retired.
+    #[clap(long, value_parser = platform::path_arg, value_name = "KEY_FILE")]
+    old: PathBuf,
+
+    /// Key file of the new name. If it doesn't exist, a new key is created and written there.
+    #[clap(long, value_parser = platform::path_arg, value_name = "KEY_FILE")]
+    new: PathBuf,
+
+    /// Publish this value under the new name, instead of the old name's current value.
+    #[clap(long, value_parser)]
+    value: Option<String>,
+
+    /// Don't publish the redirect record under the old name.
+    #[clap(long)]
+    no_redirect: bool,
+
+    /// How long the redirect record under the old name stays valid, e.g. "30days".
+    #[clap(
+      long,
+      value_parser,
+      default_value = "30days",
+      conflicts_with = "no-redirect"
+    )]
+    tombstone_validity: humantime::Duration,
+
+    /// Print the old and new keys, the value and the redirect as JSON.
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Delete a key from the keystore, along with the aliases that refer to it.
+  ///
+  /// Asks for the key's fingerprint (the last 8 characters of its name) before deleting anything.
+  /// The key file is moved to the keystore's trash directory, unless `--purge` is given.
+  Delete {
+    /// The key to delete: its name, or an alias that refers to it.
+    #[clap(value_parser)]
+    name: String,
+
+    /// Delete without asking for the fingerprint.
+    #[clap(long)]
+    yes: bool,
+
+    /// Remove the key file for good, instead of moving it to the trash.
+    #[clap(long)]
+    purge: bool,
+
+    /// Print the deleted key, where it was moved to and the removed aliases as JSON.
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Add an existing key to the keystore.
+  ///
+  /// Accepts the same key formats as `name`. A key that's already in the keystore is never
+  /// overwritten.
+  Import {
+    /// Path to the key, or `-` to read it from stdin, which is also the default.
+    #[clap(value_parser = platform::path_arg, value_name = "KEY_FILE")]
+    key: Option<PathBuf>,
+
+    /// Print the imported key's name, type, path and detected format as JSON.
+    #[clap(long)]
+    json: bool,
+  },
+
+  /// Write a key out of the keystore.
+  ///
+  /// Writes the key to `--output`, or else to stdout. With `--json`, a description of the key is
+  /// printed instead, which only includes the key itself if `--include-secret` is given.
+  Export {
+    /// The key to export: its name, or an alias that refers to it.
+    #[clap(value_parser)]
+    name: String,
+
+    /// The format to write the key in.
+    #[clap(long, value_enum, default_value_t = keyfile::KeyFormat::Binary)]
+    format: keyfile::KeyFormat,
+
+    /// File to write the key to. An existing file is never overwritten.
+    #[clap(short, long, value_parser = platform::path_arg)]
+    output: Option<PathBuf>,
+
+    /// Print the key's name, type, path and format as JSON.
+    #[clap(long)]
+    json: bool,
+
+    /// Include the base64-encoded key in the JSON, as `key`. The output can be imported again.
+    #[clap(long, requires = "json")]
+    include_secret: bool,
+  },
+}
+
+#[derive(Args)]
+struct PublishArgs {
+  /// Path to a key file (see the `create` command to make one).
+  #[clap(short, long, value_parser = platform::path_arg, value_name = "KEY_FILE")]
+  key: PathBuf,
+
+  /// The value to publish.
+  #[clap(
+    short,
+    long,
+    value_parser,
+    required_unless_present_any = &["value-from-file", "value-from-command", "value-json"],
+    conflicts_with_all = &["value-from-file", "value-from-command", "value-json"]
+  )]
+  value: Option<String>,
+
+  /// Publish the CID or path in this file, e.g. one written by a build step.
+  ///
+  /// The file is read once the name's lock is taken, so a publish never races the step that
+  /// writes it. Its contents are trimmed, and a bare CID is published as an /ipfs/ path. A
+  /// missing or empty file, or one that isn't a CID or an /ipfs/ or /ipns/ path, is an error.
+  #[clap(
+    long,
+    value_parser = platform::path_arg,
+    value_name = "FILE",
+    conflicts_with_all = &["value-from-command", "value-json"]
+  )]
+  value_from_file: Option<PathBuf>,
+
+  /// Publish the output of this shell command, checked like `--value-from-file`. The publish
+  /// fails if the command does.
+  #[clap(
+    long,
+    value_parser,
+    value_name = "COMMAND",
+    conflicts_with = "value-json"
+  )]
+  value_from_command: Option<String>,
+
+  /// Publish the JSON document in this file as the value, e.g. a manifest of release CIDs.
+  ///
+  /// The document is published in canonical form, with its keys sorted and no whitespace, so the
+  /// same document always makes the same record. A file that isn't JSON, or a document larger
+  /// than 4 KiB in canonical form, is an error. See `resolve --json-value`.
+  #[clap(long, value_parser = platform::path_arg, value_name = "FILE")]
+  value_json: Option<PathBuf>,
+
+  /// Publish a value containing control characters, or zero-width or bidirectional control
+  /// characters like U+200B ZERO WIDTH SPACE.
+  ///
+  /// Those are invisible, or change how the text around them is displayed, so they're almost
+  /// always pasted in by accident, and a value with one is refused by default.
+  #[clap(long)]
+  allow_unusual_characters: bool,
+
+  /// Publish the value in Unicode Normalization Form C, so e.g. a path typed with a combining
+  /// accent publishes the same as one with a precomposed accent. A warning is printed if this
+  /// changes the value.
+  #[clap(long)]
+  normalize_unicode: bool,
+
+  /// Abort before signing unless the value is exactly this many bytes long, e.g. to catch a
+  /// generated value that was cut short at a newline on its way here.
+  ///
+  /// The length is counted in bytes of UTF-8, after `--normalize-unicode`, so "ä" is 2 bytes.
+  #[clap(long, value_parser, value_name = "BYTES")]
+  expect_length: Option<usize>,
+
+  /// Abort before signing unless the key is for this name: a name identifier or an alias.
+  ///
+  /// Guards scripts against publishing with the wrong key file.
+  #[clap(long, value_parser, value_name = "NAME")]
+  expect_name: Option<String>,
+
+  /// How long the new record stays valid, e.g. "30days" or "1year".
+  ///
+  /// If not given, the new record is valid for as long as the current one was, counted from now,
+  /// and a new name's first record for one year, unless a default validity is configured.
+  #[clap(long, value_parser)]
+  validity: Option<humantime::Duration>,
+
+  /// The new record's TTL, e.g. "10m": how long resolvers may cache it.
+  ///
+  /// If not given, the new record keeps the current one's TTL, and a new name's first record gets
+  /// 31 days, unless a default TTL is configured.
+  #[clap(long, value_parser, value_name = "DURATION")]
+  ttl: Option<humantime::Duration>,
+
+  /// Give the new record the library defaults (valid for one year) instead of the current
+  /// record's validity window. The TTL is kept either way.
+  #[clap(long, conflicts_with = "validity")]
+  reset_durations: bool,
+
+  /// Make the new record valid until exactly this instant, an RFC 3339 timestamp like
+  /// "2025-12-31T00:00:00Z", e.g. for a cutover shared by several names.
+  ///
+  /// It must be at least 5 minutes in the future, so clock skew can't make the record expire as
+  /// it's published. The timestamp is stored in the record as given, down to the nanosecond.
+  #[clap(
+    long,
+    value_parser = parse_timestamp,
+    value_name = "TIMESTAMP",
+    conflicts_with_all = &["validity", "reset-durations"]
+  )]
+  valid_until: Option<DateTime<Utc>>,
+
+  /// Refuse to publish a record that would expire sooner than this, unless `--yes` is given.
+  ///
+  /// Use `--min-validity 0` to disable the check.
+  #[clap(long, value_parser, default_value = "24h")]
+  min_validity: humantime::Duration,
+
+  /// Publish even if the validity is shorter than `--min-validity`.
+  #[clap(long)]
+  yes: bool,
+
+  /// Warn if the new record's TTL is longer than this, e.g. "48h". Use `--max-ttl 0` to disable
+  /// the check.
+  ///
+  /// The default is w3name's default TTL.
+  #[clap(long, value_parser, value_name = "DURATION", default_value = "31days")]
+  max_ttl: humantime::Duration,
+
+  /// Don't warn if the new record's TTL is zero, which stops resolvers from caching it.
+  #[clap(long)]
+  allow_zero_ttl: bool,
+
+  /// Don't warn if the new record's TTL is longer than it stays valid, which lets caches serve it
+  /// after it expires.
+  #[clap(long)]
+  allow_ttl_over_validity: bool,
+
+  /// Count the validity from the w3name service's clock rather than the local one.
+  ///
+  /// Use this if the local clock is known to be off. A large difference between the two clocks is
+  /// reported as a warning either way.
+  #[clap(long)]
+  server_time: bool,
+
+  /// Publish the successor of the record in this file (binary or base64), instead of resolving
+  /// the current record, e.g. one exported from another system.
+  ///
+  /// The record must be valid for the key's name. The service is asked for its record as a quick
+  /// cross-check, and nothing is published if it's newer than the given one. With `--offline`,
+  /// the new record is signed and written to `--archive`, but not published.
+  #[clap(long, value_parser = platform::path_arg, value_name = "FILE")]
+  from_record: Option<PathBuf>,
+
+  /// Don't publish if the current value is equivalent to the new one.
+  ///
+  /// Trailing slashes and differences in CID multibase encoding are ignored when comparing.
+  #[clap(long)]
+  skip_unchanged: bool,
+
+  /// Exit with code 6 instead of 0 when `--skip-unchanged` skips publishing.
+  #[clap(long, requires = "skip-unchanged")]
+  changed_exit_code: bool,
+
+  /// Print the outcome as a JSON object, including the service's response to the publish.
+  #[clap(long)]
+  json: bool,
+
+  /// Publish the new record to this target instead of only the w3name service. Repeat it to
+  /// publish to several targets at once.
+  ///
+  /// A target is `w3name` (the service), `kubo=<url>` (the RPC API of a Kubo node; a bare `kubo`
+  /// is the local node), or `routing=<url>` (a delegated routing server). The current record is
+  /// still resolved from the w3name service, and each target's result is reported.
+  #[clap(long, value_parser = fanout::parse_target, value_name = "TARGET")]
+  to: Vec<fanout::PublishTarget>,
+
+  /// With `--to`, whether every target must accept the new record for the publish to succeed,
+  /// or any of them.
+  #[clap(
+    long,
+    value_enum,
+    default_value = "all",
+    requires = "to",
+    value_name = "POLICY"
+  )]
+  require: fanout::RequirePolicy,
+
+  /// Which signatures the record carries. `auto` publishes a V2-only record, and if it's rejected
+  /// with 400 Bad Request, retries once with a hybrid V1+V2 record; `v2-only` never retries, and
+  /// `hybrid` always publishes hybrid records.
+  #[clap(long, value_enum, default_value = "auto", value_name = "MODE")]
+  signature_mode: SignatureModeArg,
+
+  /// Also write the published record to this file, as a record archive (see `parse --archive`).
+  /// Given a directory, the archive is written to a file named
+  /// `<name>-seq<sequence>-<timestamp>.w3r` in it.
+  #[clap(long, value_parser = platform::path_arg, value_name = "PATH")]
+  archive: Option<PathBuf>,
+
+  /// Countersign the archive with this operator key, another key than the name's, so the archive
+  /// is attributable to whoever published, and changes to it are evident. `parse --archive` and
+  /// `record ls` check the countersignature and show the operator's name.
+  #[clap(long, value_parser = platform::path_arg, value_name = "KEY_FILE", requires = "archive")]
+  countersign_key: Option<PathBuf>,
+
+  /// Don't take the per-name lock that stops concurrent publishes on this machine from racing.
+  #[clap(long)]
+  no_lock: bool,
+
+  /// How long to wait for another publish to the same name to finish, e.g. "2m".
+  #[clap(long, value_parser, default_value = "30s", conflicts_with = "no-lock")]
+  lock_timeout: humantime::Duration,
+}
+
+#[derive(Args)]
+struct CacheArgs {
+  /// Always ask the w3name service, ignoring (and not updating) the local record cache.
+  #[clap(long)]
+  no_cache: bool,
+
+  /// Use a cached record only if it was fetched less than this long ago, e.g. "5m".
+  ///
+  /// By default, cached records are used for as long as their TTL, but at most a minute.
+  #[clap(long, value_parser, conflicts_with = "no-cache")]
+  max_age: Option<humantime::Duration>,
+}
+
+#[derive(Args)]
+struct FormatArgs {
+  /// How to render the value: as-is, as a gateway URL, or as an ipfs:// / ipns:// URI.
+  ///
+  /// Values that aren't IPFS or IPNS paths are always printed as-is.
+  #[clap(long, value_enum)]
+  format: Option<ValueFormat>,
+
+  /// Base URL of the HTTP gateway used by `--format url`.
+  #[clap(long, value_parser, default_value = "https://ipfs.io")]
+  gateway_base: String,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ValueFormat {
+  Plain,
+  Url,
+  Uri,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SignatureModeArg {
+  Auto,
+  V2Only,
+  Hybrid,
+}
+
+impl From<SignatureModeArg> for SignatureMode {
+  fn from(mode: SignatureModeArg) -> SignatureMode {
+    match mode {
+      SignatureModeArg::Auto => SignatureMode::Auto,
+      SignatureModeArg::V2Only => SignatureMode::V2Only,
+      SignatureModeArg::Hybrid => SignatureMode::Hybrid,
+    }
+  }
+}
+
+#[tokio::main]
+async fn main() {
+  let args: Vec<String> = std::env::args().collect();
+  if args.get(1).map(String::as_str) == Some("__complete") {
+    completions::run_helper(args.get(2).map(String::as_str));
+    return;
+  }
+
+  let cli = match Cli::try_parse_from(&args) {
+    Ok(cli) => cli,
+    Err(err) => match ask_for_missing_args(&args, &err) {
+      Some(Ok(extra)) => {
+        Cli::try_parse_from(args.iter().chain(&extra)).unwrap_or_else(|err| usage_error(err))
+      }
+      Some(Err(err_report)) => {
+        error_output::print(&err_report, requested_error_format(&args));
+        exit(exit_code::for_report(&err_report));
+      }
+      None => usage_error(err),
+    },
+  };
+
+  let verbose = cli.verbose || cli.trace_http;
+  if let Err(err_report) = logging::init(verbose, cli.log_format, cli.log_file.as_deref()) {
+    error_output::print(&err_report, cli.errors);
+    exit(exit_code::for_report(&err_report));
+  }
+
+  if let Err(err_report) = config::load() {
+    error_output::print(&err_report, cli.errors);
+    exit(exit_code::for_report(&err_report));
+  }
+
+  let network = service::Network::from_offline_flag(cli.offline);
+  service::trace_http(cli.trace_http);
+  service::disable_fallback(cli.no_fallback);
+  timefmt::use_local_time(cli.local);
+  let color = if cli.no_color {
+    table::ColorChoice::Never
+  } else {
+    cli.color
+  };
+  table::configure(color, cli.wide);
+  if let Some(frozen_time) = cli.frozen_time {
+    clock::freeze(frozen_time);
+  }
+
+  use Commands::*;
+  let res = match &cli.command {
+    Resolve {
+      name: Some(name),
+      format,
+      cache,
+      jsonl: false,
+      json,
+      long,
+      json_value,
+      archive,
+      verify_against,
+      max_record_age,
+      warn_record_age,
+      require_sequence_at_least,
+      gateways,
+      quorum,
+      ..
+    } => {
+      let sequence_gate = require_sequence_at_least
+        .map(|required| sequence_gate::SequenceGate::new(required, gateways.clone(), *quorum))
+        .transpose();
+      match sequence_gate {
+        Ok(sequence_gate) => {
+          let opts = ResolveOptions {
+            archive: archive.as_deref(),
+            verify_against: verify_against.as_deref(),
+            age_limits: AgeLimits {
+              warn: warn_record_age.map(Into::into),
+              max: max_record_age.map(Into::into),
+            },
+            sequence_gate,
+            json: *json,
+            long: *long,
+            json_value: *json_value,
+          };
+          resolve(name, format, cache, opts, network).await
+        }
+        Err(err_report) => Err(err_report),
+      }
+    }
+
+    Resolve { name: Some(_), .. } => {
+      Err(report!(CliError::Usage).attach_printable("--jsonl can only be used with a manifest"))
+    }
+
+    Resolve {
+      name: None,
+      manifest,
+      manifest_file,
+      jsonl,
+      format,
+      ..
+    } => {
+      resolve_manifest(
+        manifest.as_deref(),
+        manifest_file.as_deref(),
+        *jsonl,
+        format,
+        network,
+      )
+      .await
+    }
+
+    Watch {
+      name,
+      interval,
+      exec,
+      exec_fatal,
+      exec_on_start,
+      prometheus,
+      accept_regression,
+      long,
+      until_sequence,
+    } => match parse_name_arg(name) {
+      Ok((name, path)) => {
+        let opts = watch::WatchOptions {
+          interval: Duration::from_secs(*interval),
+          path: &path,
+          exec: exec.as_deref(),
+          exec_fatal: *exec_fatal,
+          exec_on_start: *exec_on_start,
+          prometheus: *prometheus,
+          accept_regression: *accept_regression,
+          long: *long,
+          until_sequence: *until_sequence,
+          network,
+        };
+        watch::watch(&name, opts).await
+      }
+      Err(err_report) => Err(err_report),
+    },
+
+    Status {
+      name,
+      timeout,
+      expiry_warning,
+      json,
+    } => {
+      let res = parse_name_arg(name).and_then(|(name, _)| {
+        let opts = status::StatusOptions {
+          timeout: (*timeout).into(),
+          expiry_warning: chrono_duration((*expiry_warning).into())?,
+          json: *json,
+          network,
+        };
+        Ok((name, opts))
+      });
+      match res {
+        Ok((name, opts)) => match status::status(&name, &opts).await {
+          Ok(status::Verdict::Pass) => Ok(()),
+          Ok(verdict) => exit(verdict.exit_code()),
+          Err(err_report) => Err(err_report),
+        },
+        Err(err_report) => Err(err_report),
+      }
+    }
+
+    Publish(args) => {
+      let res = publish(args, network).await;
+      if let Ok(false) = res {
+        if args.changed_exit_code {
+          exit(exit_code::UNCHANGED);
+        }
+      }
+      res.map(|_| ())
+    }
+
+    Create {
+      output,
+      format,
+      no_create_dirs,
+      json,
+    } => create(
+      output.as_deref(),
+      *format,
+      !no_create_dirs,
+      prompt::is_interactive(cli.no_input),
+      *json,
+    ),
+
+    Name { key, seed, json } => print_name(key.as_deref(), seed.as_deref(), *json),
+
+    Parse {
+      record,
+      format,
+      lenient,
+      check_all_signatures,
+      no_verify,
+      json,
+      archive,
+      name,
+      routing_key,
+    } => {
+      let verification = match (*lenient, *no_verify) {
+        (_, true) => inspect::Verification::Skip,
+        (true, false) => inspect::Verification::Lenient,
+        (false, false) => inspect::Verification::Strict,
+      };
+      match (archive, parse_expected_name(name, routing_key)) {
+        (Some(path), _) => parse_archive(path, format, verification, *check_all_signatures, *json),
+        (None, Ok(name)) => parse_record(
+          record,
+          name.as_ref(),
+          format,
+          verification,
+          *check_all_signatures,
+          *json,
+        ),
+        (None, Err(err_report)) => Err(err_report),
+      }
+    }
+
+    Key {
+      command:
+        KeyCommand::Rotate {
+          old,
+          new,
+          value,
+          no_redirect,
+          tombstone_validity,
+          json,
+        },
+    } => match chrono_duration((*tombstone_validity).into()) {
+      Ok(tombstone_validity) => {
+        let opts = rotate::RotateOptions {
+          old_key: old,
+          new_key: new,
+          value: value.as_deref(),
+          redirect: !no_redirect,
+          tombstone_validity,
+          network,
+          json: *json,
+        };
+        rotate::rotate(opts).await
+      }
+      Err(err_report) => Err(err_report),
+    },
+
+    Key {
+      command: KeyCommand::Delete {
+        name,
+        yes,
+        purge,
+        json,
+      },
+    } => open_keystore().and_then(|keystore| {
+      let cache = RecordCache::open_default();
+      let opts = delete::DeleteOptions {
+        yes: *yes,
+        purge: *purge,
+        cache: cache.as_ref(),
+        json: *json,
+      };
+      delete::delete_key(&keystore, name, opts, &mut io::stdin().lock())
+    }),
+
+    Key {
+      command: KeyCommand::Import { key, json },
+    } => open_keystore()
+      .and_then(|keystore| key_transfer::import_key(&keystore, key.as_deref(), *json)),
+
+    Key {
+      command:
+        KeyCommand::Export {
+          name,
+          format,
+          output,
+          json,
+          include_secret,
+        },
+    } => open_keystore().and_then(|keystore| {
+      let opts = key_transfer::ExportOptions {
+        format: *format,
+        output: output.as_deref(),
+        json: *json,
+        include_secret: *include_secret,
+      };
+      key_transfer::export_key(&keystore, name, opts)
+    }),
+
+    Alias {
+      command: AliasCommand::Rm { alias },
+    } => open_keystore().and_then(|keystore| delete::remove_alias(&keystore, alias)),
+
+    DnslinkTxt { name, zone_file } => print_dnslink_txt(name, zone_file.as_deref()),
+
+    Prove {
+      command: None,
+      key: Some(key),
+      challenge: Some(challenge),
+    } => prove::sign(key, challenge),
+
+    Prove {
+      command: Some(ProveCommand::Verify {
+        name,
+        proof,
+        max_age,
+      }),
+      ..
+    } => match max_age
+      .map(|max_age| chrono_duration(max_age.into()))
+      .transpose()
+    {
+      Ok(max_age) => prove::verify(name, proof, max_age),
+      Err(err_report) => Err(err_report),
+    },
+
+    Prove { .. } => unreachable!("clap requires --key and --challenge without a subcommand"),
+
+    Record {
+      command:
+        RecordCommand::Resign {
+          key,
+          validity,
+          base64,
+          expect_name,
+        },
+    } => match validity
+      .map(|validity| chrono_duration(validity.into()))
+      .transpose()
+    {
+      Ok(validity) => record::resign(key, validity, *base64, expect_name.as_deref()),
+      Err(err_report) => Err(err_report),
+    },
+
+    Record {
+      command:
+        RecordCommand::Fetch {
+          name,
+          kubo_api,
+          output,
+          base64,
+        },
+    } => match parse_name_arg(name) {
+      Ok((name, _)) => record::fetch(&name, kubo_api, output.as_deref(), *base64, network).await,
+      Err(err_report) => Err(err_report),
+    },
+
+    Record {
+      command:
+        RecordCommand::Ls {
+          paths,
+          name,
+          expired_only,
+          sort,
+          json,
+        },
+    } => match name
+      .as_deref()
+      .map(|name| dnslink::name_for_arg(name, Keystore::open_default().as_ref()))
+      .transpose()
+    {
+      Ok(name) => record::ls(paths, name.as_ref(), *expired_only, *sort, *json),
+      Err(err_report) => Err(err_report),
+    },
+
+    Cache { command } => cache_command(command),
+
+    Completions { shell } => completions::generate(*shell, &mut io::stdout())
+      .report()
+      .change_context(CliError::Other),
+
+    Bench {
+      command: BenchCommand::Resolve { name, args },
+    } => match parse_name_arg(name) {
+      Ok((name, _)) => bench::resolve(&name, &args.options(network)).await,
+      Err(err_report) => Err(err_report),
+    },
+
+    Bench {
+      command:
+        BenchCommand::Publish {
+          key,
+          dry_upload,
+          i_own_this_endpoint,
+          args,
+        },
+    } => match keyfile::load_key_file(key) {
+      Ok(key) => {
+        let mode = if *dry_upload {
+          bench::PublishMode::DryUpload
+        } else {
+          bench::PublishMode::Successors
+        };
+        bench::publish(&key, mode, *i_own_this_endpoint, &args.options(network)).await
+      }
+      Err(err_report) => Err(err_report),
+    },
+  };
+
+  if let Err(err_report) = res {
+    error_output::print(&err_report, cli.errors);
+    exit(exit_code::for_report(&err_report));
+  }
+}
+
+/// Prints a command-line parsing error from clap, and exits.
+fn usage_error(err: clap::Error) -> ! {
+  // --help and --version are reported as "errors" by clap, but should exit normally
+  if !err.use_stderr() {
+    err.exit();
+  }
+  let args: Vec<String> = std::env::args().collect();
+  if let ErrorFormat::Json = requested_error_format(&args) {
+    let message = err.to_string();
+    let message = message.lines().next().unwrap_or_default();
+    let message = message.trim_start_matches("error: ").to_string();
+    let report = report!(CliError::Usage).attach_printable(message);
+    error_output::print(&report, ErrorFormat::Json);
+  } else {
+    let _ = err.print();
+  }
+  exit(exit_code::USAGE);
+}
+
+/// If `err` is about arguments missing from `publish`, and the user can be asked for them (see
+/// [prompt::is_interactive]), asks for them, and returns them as arguments to add to `args`.
+fn ask_for_missing_args(
+  args: &[String],
+  err: &clap::Error,
+) -> Option<Result<Vec<String>, CliError>> {
+  if err.kind() != clap::ErrorKind::MissingRequiredArgument
+    || !prompt::is_interactive(prompt::no_input_requested(args))
+  {
+    return None;
+  }
+  let matches = Cli::command()
+    .ignore_errors(true)
+    .try_get_matches_from(args)
+    .ok()?;
+  let publish = matches.subcommand_matches("publish")?;
+  let given = |id: &str| publish.value_source(id) == Some(clap::ValueSource::CommandLine);
+  let key = publish.get_one::<PathBuf>("key").filter(|_| given("key"));
+
+  let keys = Keystore::open_default()
+    .map(|keystore| prompt::keystore_choices(&keystore))
+    .unwrap_or_default();
+  let questions = prompt::PublishQuestions {
+    key: key.map(PathBuf::as_path),
+    ask_value: ![
+      "value",
+      "value-from-file",
+      "value-from-command",
+      "value-json",
+    ]
+    .iter()
+    .any(|id| given(id)),
+    keys: &keys,
+  };
+  let name_of = |path: &Path| {
+    keyfile::load_key_file(path)
+      .map(|key| key.to_name())
+      .map_err(|_| "can't read a key from this file".to_string())
+  };
+  Some(
+    prompt::publish(&mut prompt::StdTerminal, &questions, &name_of)
+      .map(|answers| answers.to_args()),
+  )
+}
+
+/// Returns the `--errors` format given in `args`, for errors that happen when they don't parse.
+///
+/// clap parses them again, ignoring errors, so that `--` and option values are handled like in a
+/// normal parse. Only if even that fails are the raw arguments scanned, up to any `--`.
+fn requested_error_format(args: &[String]) -> ErrorFormat {
+  let matches = Cli::command()
+    .ignore_errors(true)
+    .try_get_matches_from(args);
+  if let Ok(matches) = matches {
+    // values aren't parsed when errors are ignored
+    let format = matches
+      .get_raw("errors")
+      .and_then(|mut values| values.next_back())
+      .and_then(|value| ErrorFormat::from_str(&value.to_string_lossy(), false).ok());
+    return format.unwrap_or(ErrorFormat::Text);
+  }
+  let args: Vec<&str> = args
+    .iter()
+    .map(String::as_str)
+    .take_while(|arg| *arg != "--")
+    .collect();
+  let json =
+    args.windows(2).any(|pair| pair == ["--errors", "json"]) || args.contains(&"--errors=json");
+  match json {
+    true => ErrorFormat::Json,
+    false => ErrorFormat::Text,
+  }
+}
+
+/// Parses a name argument: an alias from the keystore, a name identifier, or an IPNS URI or gateway URL.
+///
+/// Returns the name and the path that followed it in the URI, if any (see [Name::parse_uri]).
+fn parse_name_arg(arg: &str) -> Result<(Name, String), CliError> {
+  parse_name_arg_in(Keystore::open_default().as_ref(), arg)
+}
+
+/// Like [parse_name_arg], looking up aliases in `keystore`.
+fn parse_name_arg_in(keystore: Option<&Keystore>, arg: &str) -> Result<(Name, String), CliError> {
+  let alias_target = keystore.and_then(|keystore| keystore.aliases().remove(arg));
+  if let Some(target) = &alias_target {
+    log::debug!("Alias {} refers to name {}", arg, target);
+  }
+
+  Name::parse_uri(alias_target.as_deref().unwrap_or(arg))
+    .change_context(CliError::Usage)
+    .attach_printable(InvolvedName(arg.to_string()))
+}
+
+/// Appends the `path` from a name argument to a resolved `value`, e.g. `/ipfs/<cid>` + `/docs`.
+fn join_path(value: &str, path: &str) -> String {
+  if path.is_empty() {
+    value.to_string()
+  } else {
+    format!("{}{}", value.trim_end_matches('/'), path)
+  }
+}
+
+/// Options of a single-name `resolve`.
+struct ResolveOptions<'a> {
+  /// Write the fetched record to this archive file.
+  archive: Option<&'a Path>,
+  /// Fail if the resolved record is older than the archive(s) at this path.
+  verify_against: Option<&'a Path>,
+  /// Warn or fail if the resolved record has been current for too long.
+  age_limits: AgeLimits,
+  /// Fail unless the record, and the gateways' records, have at least a sequence.
+  sequence_gate: Option<sequence_gate::SequenceGate>,
+  /// Print JSON rather than the bare value.
+  json: bool,
+  /// Print the record's details along with the value.
+  long: bool,
+  /// Print the value as a pretty-printed JSON document.
+  json_value: bool,
+}
+
+async fn resolve(
+  name_arg: &str,
+  format: &FormatArgs,
+  cache_args: &CacheArgs,
+  opts: ResolveOptions<'_>,
+  network: service::Network,
+) -> Result<(), CliError> {
+  let client = service::client(network, "resolve")?;
+  let resolver = service::resolver(&client, network, "resolve")?;
+
+  log::debug!("Resolving name: {}", name_arg);
+
+  let (name, path) = parse_name_arg(name_arg)?;
+  let name_str = name.to_string();
+  let archived = opts
+    .verify_against
+    .map(|path| archive::newest_archived(path, &name))
+    .transpose()?;
+
+  let cache = if cache_args.no_cache {
+    None
+  } else {
+    RecordCache::open_writable()
+  };
+  let max_age = cache_args
+    .max_age
+    .map(|max_age| chrono_duration(max_age.into()))
+    .transpose()?;
+
+  let cached = match (opts.archive, &archived, &opts.sequence_gate) {
+    (None, None, None) => cache
+      .as_ref()
+      .and_then(|cache| cache.lookup(&name, max_age)),
+    _ => None,
+  };
+  let mut resolved = match cached {
+    Some(resolved) => {
+      log::debug!("Using cached record with sequence {}", resolved.sequence());
+      resolved
+    }
+    None => match fetch_and_cache(&resolver, &name, cache.as_ref()).await {
+      Ok((resolved, record)) => {
+        if let Some(path) = opts.archive {
+          let url = resolved.source.url.as_ref().unwrap_or(client.endpoint());
+          RecordArchive::new(&name, &record, url.as_str()).write(path)?;
+        }
+        resolved
+      }
+      Err(err_report) => {
+        let err_report = if is_404(&err_report) {
+          err_report.attach_printable(format!("no record found for key {}", name_str))
+        } else {
+          err_report
+        };
+        return Err(
+          err_report
+            .change_context(CliError::Resolve)
+            .attach_printable(InvolvedName(name_str.to_string())),
+        );
+      }
+    },
+  };
 
-use w3name::{
-  error::{APIError, ClientError},
-  ipns::{deserialize_ipns_entry, revision_from_ipns_entry, validate_ipns_entry},
-  Name, Revision, W3NameClient, WritableName,
-};
+  if resolved.is_expired_at(clock::now()) {
+    return Err(
+      report!(RecordExpired)
+        .attach_printable(format!("validity: {}", resolved.validity_string()))
+        .change_context(CliError::Resolve)
+        .attach_printable(InvolvedName(name_str.to_string())),
+    );
+  }
+  resolved.source.expiry_checked = true;
+  log::debug!("Successfully resolved to: {}", resolved.value());
+  log::info!("{}", describe_source(&resolved));
 
-#[derive(Parser)]
-#[clap(name = "w3name", version, about, long_about = None)]
-/// A tool for creating verifiable names in a web3 world.
-struct Cli {
-  /// Enable verbose debug logging
-  #[clap(long, global = true)]
-  verbose: bool,
+  if let Some(archived) = &archived {
+    archive::check_not_rolled_back(&resolved, archived)
+      .attach_printable(InvolvedName(name_str.to_string()))?;
+  }
+  if let Some(gate) = &opts.sequence_gate {
+    gate
+      .check(&name, client.endpoint(), &resolved)
+      .await
+      .attach_printable(InvolvedName(name_str.to_string()))?;
+  }
 
-  #[clap(subcommand)]
-  command: Commands,
-}
+  // every resolve notes the sequence it saw, so that the age is known when a limit is given
+  match &cache {
+    Some(cache) => {
+      let checked = record_age::check(cache, &resolved, &opts.age_limits, clock::now());
+      match checked {
+        Ok((first_seen, _)) => resolved.first_seen_at = Some(first_seen),
+        Err(err_report) if opts.age_limits.is_empty() => {
+          log::debug!("Unable to note the record's sequence: {:?}", err_report);
+        }
+        Err(err_report) => {
+          return Err(err_report.attach_printable(InvolvedName(name_str.to_string())));
+        }
+      }
+    }
+    None if !opts.age_limits.is_empty() => {
+      return Err(
+        report!(CliError::Usage)
+          .attach_printable("--max-record-age and --warn-record-age need a cache directory"),
+      );
+    }
+    None => {}
+  }
 
-#[derive(Subcommand)]
-enum Commands {
-  /// Lookup the current value for a name record.
-  Resolve {
-    /// The name identifier, e.g. "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu"
-    #[clap(value_parser)]
-    name: String,
-  },
+  if opts.json_value {
+    return print_json_value(&resolved, &path, &name_str);
+  }
 
-  /// Publish a new value for a name, signed with the name's private key.
-  Publish {
-    /// Path to a key file (see the `create` command to make one).
-    #[clap(short, long, value_parser, value_name = "KEY_FILE")]
-    key: PathBuf,
+  let value = render_value(&join_path(resolved.value(), &path), format);
+  if opts.json {
+    println!("{}", resolved_json(&resolved, &value));
+  } else if opts.long {
+    let now = clock::now();
+    for line in revision_lines(&resolved, now) {
+      // a --format or path changes the value, so give the length of the one that's printed
+      if line.starts_with("value: ") {
+        println!("value: {}", value);
+      } else if line.starts_with("value_length: ") {
+        println!("value_length: {} bytes", value.len());
+      } else {
+        println!("{}", line);
+      }
+    }
+    println!("source: {}", describe_source_url(&resolved));
+    println!("fetched: {}", timefmt::moment(resolved.fetched_at, now));
+    if let Some(first_seen) = resolved.first_seen_at {
+      println!("first seen: {}", timefmt::moment(first_seen, now));
+    }
+  } else {
+    println!("{}", value);
+  }
+  Ok(())
+}
 
-    /// The value to publish.
-    #[clap(short, long, value_parser)]
-    value: String,
-  },
+/// Prints the value of `revision` as a pretty-printed JSON document, for `resolve --json-value`.
+fn print_json_value(revision: &Revision, path: &str, name: &str) -> Result<(), CliError> {
+  if !path.is_empty() {
+    return Err(
+      report!(CliError::Usage)
+        .attach_printable("--json-value can't be used with a path after the name"),
+    );
+  }
+  let document: serde_json::Value = revision
+    .value_json()
+    .change_context(CliError::Resolve)
+    .attach_printable(format!("value: {}", revision.value()))
+    .attach_printable(InvolvedName(name.to_string()))?;
+  println!("{:#}", document);
+  Ok(())
+}
 
-  /// Create a new public/private keypair and save it to disk.
-  Create {
-    /// Filename to write the key to.
-    ///
-    /// If not given, will write to a file named `<name>.key`,
-    /// where `<name>` is the string form of the public key.
-    #[clap(short, long, value_parser)]
-    output: Option<PathBuf>,
-  },
+/// The lines of the text form of `revision`, as in `parse`, with its validity shown relative to
+/// `now` (see [timefmt]).
+///
+/// The value is one entry, even if it has newlines in it; the `value_length` line after it tells
+/// where it ends.
+fn revision_lines(revision: &Revision, now: chrono::DateTime<chrono::Utc>) -> Vec<String> {
+  // the library's alternate format has one "field: value" per line, but the value can span
+  // several, up to the value_length line that follows it
+  let text = format!("{:#}", revision);
+  let (head, tail) = text
+    .rsplit_once("\nvalue_length: ")
+    .expect("the alternate format has a value_length line");
+  let (name, value) = head.split_once('\n').unwrap_or((head, ""));
+  let fields = format!("value_length: {}", tail);
+  let mut lines = vec![name.to_string(), value.to_string()];
+  lines.extend(
+    fields
+      .lines()
+      .map(|line| match line.strip_prefix("validity: ") {
+        Some(_) => format!("validity: {}", timefmt::expiry(*revision.validity(), now)),
+        None => line.to_string(),
+      }),
+  );
+  lines
+}
 
-  /// Parse a record
-  Parse {
-    /// base64-encoded record
-    #[clap(value_parser)]
-    record: Option<String>,
-  },
+/// Describes where a resolved record came from, for the log.
+fn describe_source(resolved: &ResolvedRevision) -> String {
+  format!(
+    "Record with sequence {} came from {} in {:?}, fetched at {}",
+    resolved.sequence(),
+    describe_source_url(resolved),
+    resolved.source.elapsed,
+    resolved.fetched_at.to_rfc3339()
+  )
 }
 
-#[tokio::main]
-async fn main() {
-  let cli = Cli::parse();
+/// The kind of source a resolved record came from, and its URL if it has one.
+fn describe_source_url(resolved: &ResolvedRevision) -> String {
+  match &resolved.source.url {
+    Some(url) => format!("{} {}", source_kind(resolved.source.kind), url),
+    None => source_kind(resolved.source.kind).to_string(),
+  }
+}
 
-  // Initialize logging based on verbose flag
-  if cli.verbose {
-    env_logger::Builder::from_default_env()
-      .filter_level(log::LevelFilter::Debug)
-      .init();
-  } else {
-    env_logger::Builder::from_default_env()
-      .filter_level(log::LevelFilter::Warn)
-      .init();
+fn source_kind(kind: SourceKind) -> &'static str {
+  match kind {
+    SourceKind::Service => "w3name",
+    SourceKind::Gateway => "gateway",
+    SourceKind::Cache => "cache",
   }
+}
 
-  use Commands::*;
-  let res = match &cli.command {
-    Resolve { name } => {
-      resolve(name).await
-    }
+/// The output of `resolve --json`. Fields shared with `parse --json` have the same names and formats.
+fn resolved_json(resolved: &ResolvedRevision, value: &str) -> serde_json::Value {
+  serde_json::json!({
+    "name": resolved.name().to_string(),
+    "value": value,
+    "value_length": value.len(),
+    "sequence": resolved.sequence(),
+    "validity": resolved.validity_string(),
+    "ttl_ns": resolved.ttl().num_nanoseconds(),
+    "source": {
+      "kind": source_kind(resolved.source.kind),
+      "url": resolved.source.url.as_ref().map(|url| url.as_str()),
+      "elapsed_ms": resolved.source.elapsed.as_millis() as u64,
+      "expiry_checked": resolved.source.expiry_checked,
+    },
+    "fetched_at": resolved.fetched_at.to_rfc3339(),
+    "from_cache": resolved.from_cache,
+    "first_seen_at": resolved.first_seen_at.map(|at| at.to_rfc3339()),
+  })
+}
 
-    Publish { key, value } => {
-      publish(key, value).await
+/// Resolves the names in a manifest, from `url` or else `file`, and prints a row for each.
+///
+/// Fails after printing all rows if any name couldn't be resolved.
+async fn resolve_manifest(
+  url: Option<&str>,
+  file: Option<&Path>,
+  jsonl: bool,
+  format: &FormatArgs,
+  network: service::Network,
+) -> Result<(), CliError> {
+  let client = service::client(network, "resolve")?;
+  let entries = match (url, file) {
+    (Some(url), _) => {
+      let http = service::http_client(network, "fetching a manifest")?;
+      manifest::fetch(&http, url).await?
     }
+    (None, Some(file)) => manifest::read_file(file)?,
+    (None, None) => unreachable!("clap requires a name or a manifest"),
+  };
+  let total = entries.len();
+  let resolver = service::resolver(&client, network, "resolve")?;
+  let rows = manifest::resolve(&resolver, entries).await;
 
-    Create { output } => {
-      create(output)
+  if jsonl {
+    for row in &rows {
+      println!("{}", row.to_json());
     }
+  } else {
+    manifest::table(&rows, |value| render_value(value, format)).print();
+  }
 
-    Parse { record } => parse_record(record),
-  };
-
-  if let Err(err_report) = res {
-    eprintln!("{err_report:?}");
-    exit(1);
+  let failed = rows.iter().filter(|row| row.result.is_err()).count();
+  if failed > 0 {
+    return Err(
+      report!(CliError::Resolve)
+        .attach_printable(format!("{} of {} names failed to resolve", failed, total)),
+    );
   }
+  Ok(())
 }
 
-async fn resolve(name_str: &str) -> Result<(), CliError> {
-  let client = W3NameClient::default();
+/// Resolves `name` with `resolver`, the service and then the fallback gateways, and stores the
+/// record in `cache` if given. Returns the resolved revision and the signed record it came from.
+///
+/// If every source fails, the service's error is returned with the gateways' failures listed in
+/// it, so a name that none of them has a record for still fails with the service's 404. Failing
+/// to write the cache entry is logged, but isn't an error.
+async fn fetch_and_cache(
+  resolver: &ChainResolver,
+  name: &Name,
+  cache: Option<&RecordCache>,
+) -> Result<(ResolvedRevision, Vec<u8>), ClientError> {
+  let (mut resolved, record) = resolver.resolve_with_record(name).await?;
+  let record = record.ok_or_else(|| {
+    report!(ClientError).attach_printable("the resolver didn't return the signed record")
+  })?;
+  if resolved.source.kind == SourceKind::Gateway {
+    if let Some(url) = &resolved.source.url {
+      log::info!(
+        "The service couldn't resolve {}, but gateway {} did",
+        name,
+        url
+      );
+    }
+  }
+  if let Some(cache) = cache {
+    match cache.put(name, &record) {
+      // a later cache hit says the record was fetched when the entry was written, so this
+      // resolve mustn't report an earlier time
+      Ok(()) => resolved.fetched_at = Utc::now(),
+      Err(err_report) => {
+        log::warn!("failed to write cache entry for {}: {:?}", name, err_report);
+      }
+    }
+  }
+  Ok((resolved, record))
+}
 
-  log::debug!("Resolving name: {}", name_str);
+/// Opens the default keystore, creating its directory, for the commands that need one.
+fn open_keystore() -> Result<Keystore, CliError> {
+  let keystore = Keystore::open_default().ok_or_else(|| {
+    report!(CliError::Usage)
+      .attach_printable("the keystore needs a home directory, and none is set")
+      .attach_printable("set W3NAME_HOME to a writable directory to keep the keystore in")
+  })?;
+  platform::create_private_dir(keystore.root())
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!(
+      "can't create the keystore directory {}",
+      keystore.root().display()
+    ))
+    .attach_printable("set W3NAME_HOME to a writable directory to keep the keystore in")?;
+  Ok(keystore)
+}
 
-  let name = Name::parse(name_str)
-    .change_context(CliError::Resolve)
-    .attach_printable(format!("name: {}", name_str))?;
+fn cache_command(command: &CacheCommand) -> Result<(), CliError> {
+  let cache = RecordCache::open_default().ok_or_else(|| {
+    report!(CliError::Usage)
+      .attach_printable("the record cache needs a home directory, and none is set")
+      .attach_printable("set W3NAME_HOME (or XDG_CACHE_HOME) to a directory to keep the cache in")
+  })?;
+  let name_arg = |arg: &Option<String>| {
+    arg
+      .as_deref()
+      .map(|arg| dnslink::name_for_arg(arg, Keystore::open_default().as_ref()))
+      .transpose()
+  };
 
-  match client.resolve(&name).await {
-    Ok(revision) => {
-      log::debug!("Successfully resolved to: {}", revision.value());
-      println!("{}", revision.value());
-      Ok(())
+  match command {
+    CacheCommand::Show { name, json } => {
+      let entries = match name_arg(name)? {
+        Some(name) => cache.entry(&name).into_iter().collect(),
+        None => cache.entries(),
+      };
+      if *json {
+        let entries: Vec<_> = entries.iter().map(cache_entry_json).collect();
+        println!("{}", serde_json::Value::Array(entries));
+        return Ok(());
+      }
+      let mut table = Table::new(["NAME", "SEQ", "FETCHED", "TTL", "VALUE"]).truncate(&[0, 4]);
+      for entry in entries {
+        let ttl = if entry.is_fresh() {
+          Cell::from(format!("{} left", format_seconds(entry.fresh_remaining())))
+        } else {
+          Cell::styled("stale", Style::Warning)
+        };
+        table.row([
+          entry.name.to_string().into(),
+          entry.sequence.to_string().into(),
+          format!("{} ago", format_seconds(entry.age())).into(),
+          ttl,
+          entry.value.as_str().into(),
+        ]);
+      }
+      table.print();
     }
-
-    Err(err_report) => {
-      if is_404(&err_report) {
-        eprintln!("no record found for key {}", name_str);
-        Ok(())
+    CacheCommand::Clear { name, json } => {
+      let removed = match name_arg(name)? {
+        Some(name) => usize::from(cache.remove(&name)?),
+        None => cache
+          .clear()
+          .report()
+          .change_context(CliError::Other)
+          .attach_printable(format!("cache directory: {}", cache.dir().display()))?,
+      };
+      if *json {
+        println!("{}", serde_json::json!({ "removed": removed }));
       } else {
-        Err(err_report
-          .change_context(CliError::Resolve)
-          .attach_printable(format!("name: {}", name_str)))
+        println!("removed {} cache entries", removed);
       }
-    },
+    }
+    CacheCommand::Prune { json } => {
+      let removed = cache.prune()?;
+      if *json {
+        let removed: Vec<String> = removed.iter().map(Name::to_string).collect();
+        println!("{}", serde_json::json!({ "removed": removed }));
+      } else {
+        for name in &removed {
+          println!("removed {}", name);
+        }
+        println!("pruned {} cache entries", removed.len());
+      }
+    }
+    CacheCommand::Stats { json } => {
+      let stats = cache.stats();
+      let (entries, bytes) = cache.size();
+      if *json {
+        let out = serde_json::json!({
+          "hits": stats.hits,
+          "misses": stats.misses,
+          "entries": entries,
+          "bytes": bytes,
+        });
+        println!("{}", out);
+      } else {
+        println!("hits: {}", stats.hits);
+        println!("misses: {}", stats.misses);
+        println!("entries: {} ({} bytes)", entries, bytes);
+      }
+    }
   }
+  Ok(())
 }
 
-fn create(output: &Option<PathBuf>) -> Result<(), CliError> {
-  let name = WritableName::new();
-  let output = output
-    .clone()
-    .unwrap_or_else(|| PathBuf::from(format!("{}.key", name.to_string())));
+fn cache_entry_json(entry: &cache::CacheEntry) -> serde_json::Value {
+  serde_json::json!({
+    "name": entry.name.to_string(),
+    "value": entry.value,
+    "value_length": entry.value.len(),
+    "sequence": entry.sequence,
+    "fetched_at": entry.fetched_at.to_rfc3339(),
+    "first_seen_at": entry.first_seen_at.map(|at| at.to_rfc3339()),
+    "age_seconds": entry.age().num_seconds(),
+    "ttl_seconds": entry.ttl.num_seconds(),
+    "ttl_remaining_seconds": entry.ttl_remaining().num_seconds(),
+    "validity": entry.validity.to_rfc3339(),
+    "fresh": entry.is_fresh(),
+  })
+}
 
-  let bytes = name
-    .keypair()
-    .to_protobuf_encoding()
-    .report()
-    .change_context(CliError::Create)?;
-  fs::write(&output, bytes)
-    .report()
-    .change_context(CliError::Create)?;
-  println!("wrote new keypair to {}", output.display());
-  Ok(())
+/// Formats a duration rounded down to whole seconds, e.g. "1h 2m 3s".
+fn format_seconds(duration: chrono::Duration) -> String {
+  let seconds = duration.num_seconds().max(0) as u64;
+  humantime::format_duration(Duration::from_secs(seconds)).to_string()
 }
 
-async fn resolve_via_trustless_gateway(name_str: &str) -> Result<Revision, CliError> {
-  log::debug!("Fetching IPNS record from trustless gateway for: {}", name_str);
+/// Creates a key and writes it to `output`. Without `output`, if `interactive`, asks where to
+/// write it.
+fn create(
+  output: Option<&Path>,
+  format: keyfile::KeyFormat,
+  create_dirs: bool,
+  interactive: bool,
+  json: bool,
+) -> Result<(), CliError> {
+  let name = WritableName::new();
+  let output = match output {
+    None if interactive => prompt::create_output(
+      &mut prompt::StdTerminal,
+      &name.to_string(),
+      &keyfile::new_key_path(None, &name),
+      Keystore::open_default().as_ref(),
+    )?,
+    output => keyfile::new_key_path(output, &name),
+  };
 
-  let url = format!("https://trustless-gateway.link/ipns/{}", name_str);
-  let client = reqwest::Client::new();
+  keyfile::create_key_file(&output, &name, format, create_dirs)?;
+  if json {
+    key_json::print(&key_json::KeyJson::new(&name, Some(&output), Some(format)));
+  } else {
+    println!("wrote new keypair to {}", output.display());
+  }
+  Ok(())
+}
 
-  let response = client
-    .get(&url)
-    .header("Accept", "application/vnd.ipfs.ipns-record")
-    .send()
-    .await
-    .report()
-    .change_context(CliError::Resolve)
-    .attach_printable("fetching from trustless gateway")?;
+fn print_name(key_file: Option<&Path>, seed: Option<&str>, json: bool) -> Result<(), CliError> {
+  let (writable, source_format) = match (key_file, seed) {
+    (_, Some(seed)) => (
+      keyfile::decode_hex_seed(seed)?,
+      keyfile::SourceFormat::HexSeed,
+    ),
+    (Some(path), None) => {
+      let bytes = std::fs::read(path)
+        .report()
+        .change_context(CliError::Usage)
+        .attach_printable(format!("key file: {}", path.display()))?;
+      keyfile::decode_key_with_format(&bytes)
+        .attach_printable(format!("key file: {}", path.display()))?
+    }
+    (None, None) => {
+      let mut bytes = vec![];
+      io::stdin()
+        .read_to_end(&mut bytes)
+        .report()
+        .change_context(CliError::Usage)?;
+      keyfile::decode_key_with_format(&bytes).attach_printable("key read from stdin")?
+    }
+  };
+  if json {
+    key_json::print(&key_json::DecodedKeyJson {
+      key: key_json::KeyJson::new(&writable, key_file, None),
+      source_format,
+    });
+  } else {
+    println!("{}", writable);
+  }
+  Ok(())
+}
 
-  if !response.status().is_success() {
-    return Err(Report::new(CliError::Resolve)
-      .attach_printable(format!("trustless gateway returned: {}", response.status())));
+fn print_dnslink_txt(arg: &str, zone_file: Option<&str>) -> Result<(), CliError> {
+  let name = dnslink::name_for_arg(arg, Keystore::open_default().as_ref())?;
+  match zone_file {
+    Some(domain) => {
+      let domain = dnslink::normalize_domain(domain)?;
+      println!("{}", dnslink::zone_file_line(&name, &domain));
+    }
+    None => println!("{}", name.to_dnslink_txt()),
   }
+  Ok(())
+}
 
-  let record_bytes = response
-    .bytes()
-    .await
-    .report()
-    .change_context(CliError::Resolve)
-    .attach_printable("reading response from trustless gateway")?;
+impl PublishArgs {
+  fn value_source(&self) -> ValueSource {
+    match (
+      &self.value,
+      &self.value_from_file,
+      &self.value_from_command,
+      &self.value_json,
+    ) {
+      (_, Some(path), ..) => ValueSource::File(path.clone()),
+      (_, _, Some(command), _) => ValueSource::Command(command.clone()),
+      (.., Some(path)) => ValueSource::Json(path.clone()),
+      (value, ..) => ValueSource::Arg(value.clone().unwrap_or_default()),
+    }
+  }
 
-  let entry = deserialize_ipns_entry(&record_bytes).change_context(CliError::Resolve)?;
-  let name = Name::parse(name_str).change_context(CliError::Resolve)?;
+  /// Returns the `--ttl`, if given.
+  fn ttl(&self) -> Result<Option<chrono::Duration>, CliError> {
+    self.ttl.map(|ttl| chrono_duration(ttl.into())).transpose()
+  }
 
-  validate_ipns_entry(&entry, name.public_key()).change_context(CliError::Resolve)?;
+  /// Returns how long the new record stays valid, if it's given by `--validity`, or by
+  /// `--valid-until`, which is checked to be far enough in the future.
+  fn validity_window(&self) -> Result<Option<chrono::Duration>, CliError> {
+    let Some(valid_until) = self.valid_until else {
+      return self
+        .validity
+        .map(|validity| chrono_duration(validity.into()))
+        .transpose();
+    };
+    let window = valid_until - clock::now();
+    if window < chrono_duration(VALID_UNTIL_MIN_LEAD)? {
+      return Err(report!(CliError::Usage).attach_printable(format!(
+        "--valid-until {} must be at least {} in the future",
+        valid_until.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        humantime::format_duration(VALID_UNTIL_MIN_LEAD)
+      )));
+    }
+    Ok(Some(window))
+  }
 
-  let revision = revision_from_ipns_entry(&entry, &name).change_context(CliError::Resolve)?;
+  /// Loads the `--countersign-key`, if given, refusing the key of the name being published.
+  fn operator_key(&self, writable: &WritableName) -> Result<Option<WritableName>, CliError> {
+    let Some(path) = &self.countersign_key else {
+      return Ok(None);
+    };
+    let operator = keyfile::load_key_file(path)?;
+    if operator.to_name() == writable.to_name() {
+      return Err(report!(CliError::Usage).attach_printable(
+        "--countersign-key is the name's own key; countersign with an operator key instead",
+      ));
+    }
+    Ok(Some(operator))
+  }
 
-  log::debug!("Successfully parsed IPNS record from trustless gateway: sequence={}", revision.sequence());
+  /// Reads the value to publish, normalizes it if `--normalize-unicode` is given, and checks it
+  /// for unusual characters unless `--allow-unusual-characters` is.
+  async fn read_value(&self) -> Result<String, CliError> {
+    let mut value = self.value_source().read().await?;
+    if self.normalize_unicode {
+      if let Cow::Owned(normalized) = Value::to_nfc(&value) {
+        log::warn!(
+          "the value was normalized to Unicode NFC: {:?} is published as {:?}",
+          value,
+          normalized
+        );
+        value = normalized;
+      }
+    }
+    if !self.allow_unusual_characters {
+      Value::check_text(&value)
+        .change_context(CliError::Usage)
+        .attach_printable("pass --allow-unusual-characters to publish it anyway")?;
+    }
+    if let Some(expected) = self.expect_length {
+      check_length(&value, expected)?;
+    }
+    Ok(value)
+  }
+}
 
-  Ok(revision)
+/// Fails unless `value` is `expected` bytes long, for `publish --expect-length`.
+fn check_length(value: &str, expected: usize) -> Result<(), CliError> {
+  if value.len() == expected {
+    return Ok(());
+  }
+  Err(
+    report!(CliError::Usage)
+      .attach_printable(format!("value: {:?}", value))
+      .attach_printable(format!(
+        "the value is {} bytes long ({} characters), but --expect-length is {}",
+        value.len(),
+        value.chars().count(),
+        expected
+      )),
+  )
 }
 
-async fn publish(key_file: &PathBuf, value: &str) -> Result<(), CliError> {
-  let client = W3NameClient::default();
-  let key_bytes = fs::read(key_file).report().change_context(CliError::Other)?;
-  let writable = WritableName::decode(&key_bytes).change_context(CliError::Other)?;
+/// Publishes `value`, returning `Ok(false)` if nothing was published because of `skip_unchanged`.
+async fn publish(args: &PublishArgs, network: service::Network) -> Result<bool, CliError> {
+  let PublishArgs {
+    key: key_file,
+    valid_until,
+    reset_durations,
+    min_validity,
+    yes,
+    server_time,
+    skip_unchanged,
+    json,
+    archive,
+    expect_name,
+    from_record,
+    to,
+    require,
+    signature_mode,
+    ..
+  } = args;
+  let increment_policy = if *reset_durations {
+    IncrementPolicy::Defaults
+  } else {
+    IncrementPolicy::PreserveDurations
+  };
+  if let (Some(path), service::Network::Denied) = (from_record, network) {
+    return publish_offline(args, path, increment_policy).await;
+  }
+
+  let client = service::client(network, "publish")?;
+  let fallback_resolvers = service::fallback_resolvers(network, "publish")?;
+  let writable = keyfile::load_expected_key_file(key_file, expect_name.as_deref())?;
+  let operator = args.operator_key(&writable)?;
+  let current_revision = match from_record {
+    Some(path) => {
+      let from = from_record::load(path, &writable)?;
+      from_record::cross_check(&client, &from).await?;
+      Some(from)
+    }
+    None => None,
+  };
 
   let name_str = writable.to_string();
 
   log::debug!("Publishing to name: {}", name_str);
-  log::debug!("New value: {}", value);
   log::debug!("Key file: {}", key_file.display());
 
-  // to avoid having to keep old revisions around, we first try to resolve and increment any existing records
-  let new_revision = match client.resolve(&writable.to_name()).await {
-    Ok(revision) => {
-      log::debug!("Found existing revision via w3name, incrementing from sequence {}", revision.sequence());
-      revision.increment(value)
-    },
+  let validity = args.validity_window()?;
+  let defaults = config::defaults();
 
-    // If w3name resolve fails, try trustless gateway fallback
-    Err(err_report) => {
-      if is_404(&err_report) {
-        log::debug!("No existing record found (404), creating initial revision (v0)");
-        Revision::v0(&writable.to_name(), value)
-      } else {
-        // Try trustless gateway fallback for other errors (500, network issues, etc)
-        let error_msg = if let Some(api_err) = err_report.downcast_ref::<APIError>() {
-          format!("{} - {}", api_err.status_code, api_err.message)
-        } else {
-          format!("{:?}", err_report)
-        };
-        log::warn!("w3name resolve failed ({}) - trying trustless gateway fallback", error_msg);
-
-        match resolve_via_trustless_gateway(&name_str).await {
-          Ok(revision) => {
-            log::debug!("Found existing revision via trustless gateway, incrementing from sequence {}", revision.sequence());
-            revision.increment(value)
-          },
-          Err(_gateway_err) => {
-            log::debug!("Trustless gateway also failed, creating initial revision (v0)");
-            Revision::v0(&writable.to_name(), value)
-          }
+  // held until the new record is published, so another local publish can't resolve the same
+  // current revision and race us to the next sequence number
+  let _lock = lock_for_publish(args, &writable.to_name()).await?;
+  // read under the lock, so a value file is read after any build step that holds it
+  let value = &args.read_value().await?;
+  log::debug!("New value: {:?} ({} bytes)", value, value.len());
+
+  // if the w3name service can't tell us the current revision, ask a trustless gateway instead
+  let opts = PublishOptions {
+    skip_unchanged: *skip_unchanged,
+    fallback_resolvers,
+    validity,
+    valid_until: *valid_until,
+    ttl: args.ttl()?,
+    increment_policy,
+    last_published: published::last(&writable.to_name()),
+    defaults,
+    current_revision,
+    validity_from_server_time: *server_time,
+    ..Default::default()
+  };
+  let prepared = client
+    .prepare_publish(&writable, value, opts)
+    .await
+    .map_err(revision_error)
+    .attach_printable(InvolvedName(name_str.to_string()))
+    .attach_printable(format!("value: {}", value))?;
+  let (revision, previous) = match prepared {
+    PreparedRevision::Publish(revision, previous) => (revision, previous),
+    PreparedRevision::Unchanged(current) => {
+      return print_publish_outcome(PublishOutcome::Unchanged(current), &name_str, args)
+    }
+  };
+  check_min_validity(&revision, (*min_validity).into(), *yes)?;
+  warn_about_ttl(&revision, args)?;
+
+  if !to.is_empty() {
+    let publisher =
+      fanout::publisher(to, &client, *require).with_signature_mode((*signature_mode).into());
+    let outcome = publisher.publish_each(&writable, &revision).await;
+    RecordCache::forget(&writable.to_name());
+    if outcome.results.iter().any(|result| result.result.is_ok()) {
+      published::remember(&revision);
+    }
+    if let Some(path) = archive {
+      let published = outcome.results.iter().find_map(|result| {
+        let receipt = result.result.as_ref().ok()?;
+        Some((&result.target, receipt))
+      });
+      match published {
+        Some((target, receipt)) => {
+          write_archive(
+            RecordArchive::new(&writable.to_name(), &receipt.record, target),
+            path,
+            operator.as_ref(),
+          )?;
         }
+        None => log::warn!("no target accepted the record, so no archive was written"),
       }
-    },
-  };
+    }
+
+    if *json {
+      println!(
+        "{}",
+        fanout::outcome_json(&revision, previous.as_deref(), &outcome)
+      );
+    } else {
+      println!(
+        "published new value for key {} to {} of {} targets: {}",
+        name_str,
+        outcome
+          .results
+          .iter()
+          .filter(|result| result.result.is_ok())
+          .count(),
+        outcome.results.len(),
+        value
+      );
+      fanout::print_results(&outcome);
+    }
+    fanout::check(&outcome, &name_str)?;
+    return Ok(true);
+  }
 
-  client
-    .publish(&writable, &new_revision)
+  let receipt = client
+    .publish_with_mode(&writable, &revision, (*signature_mode).into())
     .await
     .change_context(CliError::Publish)
-    .attach_printable(format!("name: {}", name_str))
+    .attach_printable(InvolvedName(name_str.to_string()))
     .attach_printable(format!("value: {}", value))?;
+  RecordCache::forget(&writable.to_name());
+  published::remember(&revision);
+  let outcome = PublishOutcome::Published(PublishedRevision::new(revision, receipt, previous));
 
-  println!(
-    "published new value for key {}: {}",
-    name_str,
-    value
-  );
+  if let Some(receipt) = outcome.receipt() {
+    log::debug!("Publish receipt: {}", receipt_json(receipt));
+  }
+
+  if let Some(path) = archive {
+    match outcome.receipt() {
+      Some(receipt) => {
+        let source = client.endpoint().as_str();
+        write_archive(
+          RecordArchive::new(&writable.to_name(), &receipt.record, source),
+          path,
+          operator.as_ref(),
+        )?;
+      }
+      None => log::warn!("nothing was published, so no archive was written"),
+    }
+  }
+
+  print_publish_outcome(outcome, &name_str, args)
+}
+
+/// Prints the outcome of publishing to the w3name service, returning `Ok(false)` if nothing was
+/// published.
+fn print_publish_outcome(
+  outcome: PublishOutcome,
+  name_str: &str,
+  args: &PublishArgs,
+) -> Result<bool, CliError> {
+  if args.json {
+    println!("{}", publish_json(&outcome));
+    return Ok(outcome.receipt().is_some());
+  }
+
+  match outcome {
+    PublishOutcome::Unchanged(current) => {
+      log::debug!("Current value is unchanged: {}", current.revision.value());
+      println!("unchanged, nothing to do");
+      Ok(false)
+    }
+    PublishOutcome::Published(revision) => {
+      println!(
+        "published new value for key {}: {}",
+        name_str,
+        revision.value()
+      );
+      Ok(true)
+    }
+  }
+}
+
+/// Writes `archive` to `path` (see [RecordArchive::write]), countersigned by `operator` if given.
+fn write_archive(
+  mut archive: RecordArchive,
+  path: &Path,
+  operator: Option<&WritableName>,
+) -> Result<PathBuf, CliError> {
+  if let Some(operator) = operator {
+    archive.countersign(operator)?;
+  }
+  archive.write(path)
+}
+
+/// Locks `name` for `publish`, unless `--no-lock` is given, waiting up to `--lock-timeout`.
+async fn lock_for_publish(
+  args: &PublishArgs,
+  name: &Name,
+) -> Result<Option<lock::FileLock>, CliError> {
+  if args.no_lock {
+    return Ok(None);
+  }
+  lock::lock_name(name, args.lock_timeout.into(), "another publish")
+    .await
+    .change_context(CliError::Publish)
+    .attach_printable(InvolvedName(name.to_string()))
+}
+
+/// `publish --from-record --offline`: signs the successor of the given record and writes it to the
+/// archive, without publishing it. Returns `Ok(false)` if nothing was signed because of
+/// `skip_unchanged`.
+async fn publish_offline(
+  args: &PublishArgs,
+  from_path: &Path,
+  increment_policy: IncrementPolicy,
+) -> Result<bool, CliError> {
+  let archive = args.archive.as_ref().ok_or_else(|| {
+    report!(CliError::Usage).attach_printable(
+      "publish --offline can't publish, so it needs --archive to write the new record to",
+    )
+  })?;
+  let writable = keyfile::load_expected_key_file(&args.key, args.expect_name.as_deref())?;
+  let operator = args.operator_key(&writable)?;
+  // as for an online publish, the value is read under the lock, which is held until the
+  // record is archived
+  let _lock = lock_for_publish(args, &writable.to_name()).await?;
+  let value = &args.read_value().await?;
+  let mut from = from_record::load(from_path, &writable)?;
+  if let Some(last) = published::last(&writable.to_name()) {
+    from = from.with_validity_window_of(&last);
+  }
+
+  if args.skip_unchanged && Value::parse(from.value()).is_equivalent(&Value::parse(value)) {
+    println!("unchanged, nothing to do");
+    return Ok(false);
+  }
+  let validity = match args.valid_until {
+    Some(valid_until) => from_record::Validity::At(valid_until),
+    None => args
+      .validity_window()?
+      .map_or(from_record::Validity::Policy, from_record::Validity::For),
+  };
+  let (revision, record) = from_record::sign_successor(
+    &writable,
+    &from,
+    value,
+    validity,
+    args.ttl()?,
+    increment_policy,
+  )?;
+  check_min_validity(&revision, args.min_validity.into(), args.yes)?;
+  warn_about_ttl(&revision, args)?;
+  let archive = write_archive(
+    RecordArchive::new(&writable.to_name(), &record, "offline"),
+    archive,
+    operator.as_ref(),
+  )?;
+  published::remember(&revision);
+
+  if args.json {
+    let out = serde_json::json!({
+      "name": revision.name().to_string(),
+      "value": revision.value(),
+      "value_length": revision.value_len(),
+      "sequence": revision.sequence(),
+      "validity": revision.validity_string(),
+      "ttl_ns": revision.ttl().num_nanoseconds(),
+      "published": false,
+      "archive": archive.display().to_string(),
+    });
+    println!("{}", out);
+  } else {
+    println!(
+      "signed sequence {} for key {} and wrote it to {}; not published, since --offline was given",
+      revision.sequence(),
+      writable,
+      archive.display()
+    );
+  }
+  Ok(true)
+}
+
+/// The output of `publish --json`. Fields shared with `parse --json` have the same names and formats.
+fn publish_json(outcome: &PublishOutcome) -> serde_json::Value {
+  let revision = outcome.revision();
+  serde_json::json!({
+    "name": revision.name().to_string(),
+    "value": revision.value(),
+    "value_length": revision.value_len(),
+    "sequence": revision.sequence(),
+    "validity": revision.validity_string(),
+    "ttl_ns": revision.ttl().num_nanoseconds(),
+    "published": outcome.receipt().is_some(),
+    "previous": previous_json(outcome.previous()),
+    "record": outcome.receipt().map(|receipt| base64::encode(&receipt.record)),
+    "receipt": outcome.receipt().map(receipt_json),
+  })
+}
+
+/// The `previous` field of `publish --json`: the sequence of the revision that was replaced, and
+/// where it was found.
+fn previous_json(previous: Option<&CurrentRevision>) -> serde_json::Value {
+  match previous {
+    Some(previous) => serde_json::json!({
+      "sequence": previous.revision.sequence(),
+      "resolver": match previous.source {
+        RevisionSource::Service => "w3name",
+        // publish only configures the gateway as a fallback
+        RevisionSource::Fallback(_) => "gateway",
+        RevisionSource::Provided => "record file",
+      },
+    }),
+    None => serde_json::Value::Null,
+  }
+}
+
+fn receipt_json(receipt: &PublishReceipt) -> serde_json::Value {
+  let headers: serde_json::Map<String, serde_json::Value> = receipt
+    .headers
+    .iter()
+    .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.into())))
+    .collect();
+  serde_json::json!({
+    "status": receipt.status.as_u16(),
+    "headers": headers,
+    "body": receipt.body,
+    "record": base64::encode(&receipt.record),
+  })
+}
+
+/// Refuses to publish `revision` if it stops being valid less than `min_validity` from now, unless
+/// `yes` is set.
+///
+/// This checks the revision that's about to be signed, so it covers validity windows picked by the
+/// increment policy or the configured defaults as well as the ones given on the command line. A
+/// zero `min_validity` disables the check.
+fn check_min_validity(
+  revision: &Revision,
+  min_validity: Duration,
+  yes: bool,
+) -> Result<(), CliError> {
+  if min_validity.is_zero() {
+    return Ok(());
+  }
+
+  // the guard only looks at the validity, so any value will do
+  let guard = RevisionBuilder::new(revision.name(), "")
+    .clock(clock::shared())
+    .validity_at(*revision.validity())
+    .min_validity(chrono_duration(min_validity)?)
+    .build();
+  match guard {
+    Ok(_) => Ok(()),
+    Err(err_report) if err_report.contains::<ValidityTooShort>() => {
+      let min_validity = humantime::format_duration(min_validity);
+      eprintln!();
+      eprintln!("  WARNING: the new record would expire in less than {min_validity}!");
+      if yes {
+        eprintln!("  Publishing anyway, since --yes was given.");
+        eprintln!();
+        Ok(())
+      } else {
+        eprintln!("  Pass --yes to publish it anyway, or --min-validity 0 to disable this check.");
+        eprintln!();
+        Err(err_report.change_context(CliError::Usage))
+      }
+    }
+    Err(err_report) => Err(err_report.change_context(CliError::Publish)),
+  }
+}
+
+/// Changes the context of a failure to build the new revision to [CliError::Publish], or to
+/// [CliError::Usage] if its validity is out of range, since that comes from `--validity` or the
+/// configured default.
+fn revision_error<C: Context>(err_report: Report<C>) -> Report<CliError> {
+  if err_report.contains::<ValidityOutOfRange>() {
+    err_report.change_context(CliError::Usage)
+  } else {
+    err_report.change_context(CliError::Publish)
+  }
+}
+
+/// Warns about a questionable TTL of the new `revision`, unless the flags in `args` silence it.
+fn warn_about_ttl(revision: &Revision, args: &PublishArgs) -> Result<(), CliError> {
+  let max_ttl: Duration = args.max_ttl.into();
+  let checks = TtlChecks {
+    zero: !args.allow_zero_ttl,
+    exceeds_validity: !args.allow_ttl_over_validity,
+    max_ttl: (!max_ttl.is_zero())
+      .then(|| chrono_duration(max_ttl))
+      .transpose()?,
+  };
+  for note in revision.ttl_notes(clock::now(), &checks) {
+    log::warn!("{}", note);
+  }
   Ok(())
 }
 
-fn parse_record(input: &Option<String>) -> Result<(), CliError> {
+/// Parses an RFC 3339 timestamp argument, e.g. "2025-12-31T00:00:00Z", keeping any fraction of a
+/// second.
+fn parse_timestamp(arg: &str) -> std::result::Result<DateTime<Utc>, String> {
+  DateTime::parse_from_rfc3339(arg)
+    .map(|time| time.with_timezone(&Utc))
+    .map_err(|err| {
+      format!(
+        "expected an RFC 3339 timestamp like 2025-12-31T00:00:00Z: {}",
+        err
+      )
+    })
+}
+
+fn chrono_duration(duration: Duration) -> Result<chrono::Duration, CliError> {
+  chrono::Duration::from_std(duration)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!(
+      "duration out of range: {}",
+      humantime::format_duration(duration)
+    ))
+}
+
+/// Returns the name given to `parse` with `--name` or `--routing-key`, if any.
+fn parse_expected_name(
+  name: &Option<String>,
+  routing_key: &Option<String>,
+) -> Result<Option<Name>, CliError> {
+  match (name, routing_key) {
+    (Some(name), _) => Ok(Some(parse_name_arg(name)?.0)),
+    (None, Some(key)) => inspect::name_for_routing_key(key).map(Some),
+    (None, None) => Ok(None),
+  }
+}
+
+fn parse_record(
+  input: &Option<String>,
+  name: Option<&Name>,
+  format: &FormatArgs,
+  verification: inspect::Verification,
+  check_all_signatures: bool,
+  json: bool,
+) -> Result<(), CliError> {
   let record_encoded = match input {
     Some(record) => record.clone(),
     None => io::read_to_string(io::stdin()).map_err(|_| Report::new(CliError::Parse))?,
@@ -252,44 +2686,118 @@ fn parse_record(input: &Option<String>) -> Result<(), CliError> {
   let entry_bytes = base64::decode(record_encoded)
     .report()
     .change_context(CliError::Parse)?;
-  let entry = deserialize_ipns_entry(&entry_bytes).change_context(CliError::Parse)?;
-  // println!("record: {:?}", &entry);
-  let name = Name::from_bytes(&entry.pub_key).change_context(CliError::Parse)?;
-  validate_ipns_entry(&entry, name.public_key()).change_context(CliError::Parse)?;
-
-  let revision = revision_from_ipns_entry(&entry, &name).change_context(CliError::Parse)?;
-  // Ok(revision)
-  println!("{}", revision);
-  Ok(())
+  let inspection = inspect::inspect(&entry_bytes, name, verification, check_all_signatures)?;
+  print_inspection(&inspection, None, format, json)
 }
 
-/// Returns true if the error report contains an [APIError] with a 404 status
-fn is_404(report: &Report<ClientError>) -> bool {
-  let maybe_api_err: Option<&APIError> = report.downcast_ref();
-  if let Some(err) = maybe_api_err {
-    err.status_code == 404
+/// Like [parse_record], for the record in the archive at `path`.
+fn parse_archive(
+  path: &Path,
+  format: &FormatArgs,
+  verification: inspect::Verification,
+  check_all_signatures: bool,
+  json: bool,
+) -> Result<(), CliError> {
+  let archive = RecordArchive::read(path)?;
+  let name = archive.name()?;
+  // a broken countersignature means the archive was altered, so its record isn't shown either
+  archive
+    .countersigner()
+    .attach_printable(format!("archive: {}", path.display()))?;
+  let inspection = inspect::inspect(
+    &archive.record_bytes()?,
+    Some(&name),
+    verification,
+    check_all_signatures,
+  )
+  .attach_printable(InvolvedName(archive.name.clone()))?;
+  print_inspection(&inspection, Some(&archive), format, json)
+}
+
+fn print_inspection(
+  inspection: &inspect::RecordInspection,
+  archive: Option<&RecordArchive>,
+  format: &FormatArgs,
+  json: bool,
+) -> Result<(), CliError> {
+  if json {
+    let mut output = inspection.to_json();
+    if let Some(archive) = archive {
+      output["source"] = archive.source.as_str().into();
+      output["timestamp"] = archive.timestamp.as_str().into();
+      output["countersigner"] = archive.countersigner()?.map(|name| name.to_string()).into();
+    }
+    println!("{}", output);
+  } else if format.format.is_some() {
+    println!("{}", render_value(inspection.revision.value(), format));
   } else {
-    false
+    if !inspection.verified {
+      println!("UNVERIFIED: the record's signatures were not checked");
+    }
+    let now = clock::now();
+    for line in revision_lines(&inspection.revision, now) {
+      println!("{}", line);
+    }
+    for line in inspection.signature_lines() {
+      println!("{}", line);
+    }
+    for line in inspection.note_lines() {
+      println!("{}", line);
+    }
+    if let Some(archive) = archive {
+      println!("source: {}", archive.source);
+      match chrono::DateTime::parse_from_rfc3339(&archive.timestamp) {
+        Ok(timestamp) => println!(
+          "timestamp: {}",
+          timefmt::moment(timestamp.with_timezone(&chrono::Utc), now)
+        ),
+        Err(_) => println!("timestamp: {}", archive.timestamp),
+      }
+      if let Some(countersigner) = archive.countersigner()? {
+        println!("countersigned by: {}", countersigner);
+      }
+    }
+  }
+
+  if inspection.v1_signature_valid == Some(false) {
+    return Err(report!(CliError::Parse).attach_printable("the record's V1 signature is invalid"));
   }
+  Ok(())
+}
+
+/// Renders a record value according to the `--format` flag, falling back to the plain value
+/// (with a warning) if the value isn't an IPFS or IPNS path.
+fn render_value(value: &str, args: &FormatArgs) -> String {
+  let rendered = match args.format.unwrap_or(ValueFormat::Plain) {
+    ValueFormat::Plain => return value.to_string(),
+    ValueFormat::Url => Value::parse(value).to_gateway_url(&args.gateway_base),
+    ValueFormat::Uri => Value::parse(value).to_uri(),
+  };
+  rendered.unwrap_or_else(|| {
+    log::warn!("value is not an IPFS or IPNS path, printing it as-is");
+    value.to_string()
+  })
 }
 
-/// Returns true if the error report contains an [APIError] with a 500 status
-fn is_500(report: &Report<ClientError>) -> bool {
+/// Returns true if the error report contains an [APIError] with a 404 status
+fn is_404(report: &Report<ClientError>) -> bool {
   let maybe_api_err: Option<&APIError> = report.downcast_ref();
   if let Some(err) = maybe_api_err {
-    err.status_code == 500
+    err.status_code == 404
   } else {
     false
   }
 }
 
-
 #[derive(Debug, Clone)]
 enum CliError {
   Resolve,
   Publish,
   Create,
   Parse,
+  Watch,
+  Proof,
+  Usage,
   Other,
 }
 
@@ -300,6 +2808,9 @@ impl Display for CliError {
       CliError::Publish => write!(f, "failed to publish value"),
       CliError::Create => write!(f, "failed to create new keypair"),
       CliError::Parse => write!(f, "failed to parse record"),
+      CliError::Watch => write!(f, "failed to watch name"),
+      CliError::Proof => write!(f, "failed to verify proof"),
+      CliError::Usage => write!(f, "invalid arguments"),
       CliError::Other => write!(f, "operation failed"),
     }
   }
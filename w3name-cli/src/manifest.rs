@@ -0,0 +1,252 @@
+//! Name manifests for `resolve --manifest`: a JSON object mapping name identifiers to labels.
+//!
+//! ```json
+//! {
+//!   "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu": "website",
+//!   "k51qzi5uqu5dlvj2baxnqndepeb86cbk3ng7n3i46uzyxzyqj2xjonzllnv0v8": "docs"
+//! }
+//! ```
+
+use std::{fs, path::Path};
+
+use error_stack::{report, Context, FrameKind, IntoReport, Report, Result, ResultExt};
+use futures::StreamExt;
+use w3name::{ChainResolver, Name, ResolvedRevision, Resolver};
+
+use crate::{
+  clock, source_kind,
+  table::{Cell, Style, Table},
+  CliError,
+};
+
+#[derive(Debug)]
+pub struct ManifestEntry {
+  pub label: String,
+  pub name: Name,
+}
+
+/// Fetches the manifest at `url` and parses it.
+pub async fn fetch(http: &reqwest::Client, url: &str) -> Result<Vec<ManifestEntry>, CliError> {
+  log::debug!("HTTP GET {}", url);
+  let res = http
+    .get(url)
+    .send()
+    .await
+    .and_then(|res| res.error_for_status())
+    .report()
+    .change_context(CliError::Resolve)
+    .attach_printable(format!("failed to fetch manifest from {}", url))?;
+  let body = res
+    .bytes()
+    .await
+    .report()
+    .change_context(CliError::Resolve)
+    .attach_printable(format!("failed to fetch manifest from {}", url))?;
+  parse(&body)
+}
+
+/// Reads the manifest in the file at `path` and parses it.
+pub fn read_file(path: &Path) -> Result<Vec<ManifestEntry>, CliError> {
+  let bytes = fs::read(path)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!("failed to read manifest {}", path.display()))?;
+  parse(&bytes)
+}
+
+/// Parses and validates a manifest. Errors name the offending entry.
+pub fn parse(bytes: &[u8]) -> Result<Vec<ManifestEntry>, CliError> {
+  let json: serde_json::Value = serde_json::from_slice(bytes)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable("manifest is not valid JSON")?;
+  let object = json.as_object().ok_or_else(|| {
+    report!(CliError::Usage)
+      .attach_printable("manifest must be a JSON object mapping name identifiers to labels")
+  })?;
+
+  object
+    .iter()
+    .map(|(name, label)| {
+      let label = label.as_str().ok_or_else(|| {
+        report!(CliError::Usage).attach_printable(format!(
+          "manifest entry \"{}\": label must be a string",
+          name
+        ))
+      })?;
+      let name = Name::parse(name)
+        .change_context(CliError::Usage)
+        .attach_printable(format!("manifest entry \"{}\": invalid name", name))?;
+      Ok(ManifestEntry {
+        label: label.to_string(),
+        name,
+      })
+    })
+    .collect()
+}
+
+/// The outcome of resolving one manifest entry.
+pub struct Resolved {
+  pub entry: ManifestEntry,
+  pub result: std::result::Result<ResolvedRevision, String>,
+}
+
+/// How many names are resolved at once.
+const CONCURRENCY: usize = 8;
+
+/// Resolves all `entries` concurrently, each from the first source in `resolver` that has a
+/// record for it. Failures are recorded per entry, including expired records.
+pub async fn resolve(resolver: &ChainResolver, entries: Vec<ManifestEntry>) -> Vec<Resolved> {
+  let results: Vec<_> = futures::stream::iter(&entries)
+    .map(|entry| resolver.resolve(&entry.name))
+    .buffered(CONCURRENCY)
+    .collect()
+    .await;
+  entries
+    .into_iter()
+    .zip(results)
+    .map(|(entry, result)| {
+      let result = match result {
+        Ok(revision) if revision.is_expired_at(clock::now()) => {
+          Err(format!("record expired at {}", revision.validity_string()))
+        }
+        Ok(revision) => Ok(revision),
+        Err(err_report) => {
+          log::debug!("failed to resolve {}: {:?}", entry.name, err_report);
+          Err(describe(&err_report))
+        }
+      };
+      Resolved { entry, result }
+    })
+    .collect()
+}
+
+/// A one-line description of a resolve failure: its root cause, e.g. the service's error message.
+pub fn describe<C: Context>(err_report: &Report<C>) -> String {
+  err_report
+    .frames()
+    .filter_map(|frame| match frame.kind() {
+      FrameKind::Context(context) => Some(context.to_string()),
+      FrameKind::Attachment(_) => None,
+    })
+    .last()
+    .unwrap_or_else(|| err_report.current_context().to_string())
+}
+
+impl Resolved {
+  pub fn to_json(&self) -> serde_json::Value {
+    let mut json = serde_json::json!({
+      "label": self.entry.label,
+      "name": self.entry.name.to_string(),
+    });
+    match &self.result {
+      Ok(revision) => {
+        json["value"] = revision.value().into();
+        json["value_length"] = revision.value_len().into();
+        json["sequence"] = revision.sequence().into();
+        json["expires"] = revision.validity_string().into();
+        json["source"] = source_kind(revision.source.kind).into();
+      }
+      Err(message) => json["error"] = message.as_str().into(),
+    }
+    json
+  }
+}
+
+/// Returns `rows` as a table, rendering values with `render_value`, with failures in red.
+pub fn table(rows: &[Resolved], render_value: impl Fn(&str) -> String) -> Table<5> {
+  let mut table = Table::new(["LABEL", "NAME", "VALUE", "SEQUENCE", "EXPIRES"]).truncate(&[1, 2]);
+  for row in rows {
+    let (value, sequence, expires) = match &row.result {
+      Ok(revision) => (
+        render_value(revision.value()).into(),
+        revision.sequence().to_string(),
+        revision.validity_string(),
+      ),
+      Err(message) => (
+        Cell::styled(format!("error: {}", message), Style::Bad),
+        "-".into(),
+        "-".into(),
+      ),
+    };
+    table.row([
+      row.entry.label.as_str().into(),
+      row.entry.name.to_string().into(),
+      value,
+      sequence.into(),
+      expires.into(),
+    ]);
+  }
+  table
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::table::Layout;
+  use w3name::{Revision, SourceInfo, SourceKind, WritableName};
+
+  #[test]
+  fn parse_manifest() {
+    let name = WritableName::new().to_name();
+    let json = format!("{{\"{}\": \"website\"}}", name);
+    let entries = parse(json.as_bytes()).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].label, "website");
+    assert_eq!(entries[0].name, name);
+  }
+
+  #[test]
+  fn schema_errors_name_the_entry() {
+    let name = WritableName::new().to_name();
+    let err = parse(format!("{{\"{}\": 1}}", name).as_bytes()).unwrap_err();
+    assert!(format!("{:?}", err).contains(&format!("manifest entry \"{}\"", name)));
+
+    let err = parse(b"{\"not-a-name\": \"docs\"}").unwrap_err();
+    assert!(format!("{:?}", err).contains("manifest entry \"not-a-name\": invalid name"));
+
+    assert!(parse(b"[]").is_err());
+  }
+
+  #[test]
+  fn table_and_json_rows() {
+    let name = WritableName::new().to_name();
+    let rows = vec![
+      Resolved {
+        entry: ManifestEntry {
+          label: "site".into(),
+          name: name.clone(),
+        },
+        result: Ok(ResolvedRevision::fetched(
+          Revision::v0(&name, "/ipfs/value"),
+          SourceInfo::http(
+            SourceKind::Gateway,
+            "https://gateway.example".parse().unwrap(),
+            std::time::Instant::now(),
+          ),
+        )),
+      },
+      Resolved {
+        entry: ManifestEntry {
+          label: "gone".into(),
+          name: name.clone(),
+        },
+        result: Err("not found".into()),
+      },
+    ];
+
+    let lines = table(&rows, str::to_string).render(Layout {
+      color: false,
+      width: None,
+    });
+    assert!(lines[0].starts_with("LABEL  NAME"));
+    assert!(lines[1].starts_with("site   "));
+    assert!(lines[1].contains("/ipfs/value"));
+    assert!(lines[2].contains("error: not found  -"));
+
+    assert_eq!(rows[0].to_json()["sequence"], 0);
+    assert_eq!(rows[0].to_json()["source"], "gateway");
+    assert_eq!(rows[1].to_json()["error"], "not found");
+    assert!(rows[1].to_json().get("value").is_none());
+  }
+}
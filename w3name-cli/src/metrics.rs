@@ -0,0 +1,277 @@
+//! Prometheus metrics for `watch --prometheus`, served from `GET /metrics` in the text exposition
+//! format.
+//!
+//! Every metric is labelled with `name`, the watched name identifier:
+//!
+//! ```text
+//! w3name_last_resolve_success_timestamp_seconds  gauge    Unix time of the last successful resolve
+//! w3name_sequence                                gauge    sequence number of the current record
+//! w3name_validity_remaining_seconds              gauge    seconds until the current record expires
+//!                                                         (negative once it has)
+//! w3name_consecutive_failures                    gauge    failed resolves since the last success
+//! w3name_resolve_errors_total                    counter  failed resolves, also labelled with `class`:
+//!                                                         not_found, invalid_record, expired, service
+//!                                                         or other (as for the exit codes)
+//! w3name_sequence_regressions_total              counter  resolved records older than the current one
+//! ```
+//!
+//! Dashboards and alerts depend on these names and labels, so they must not change.
+//! The gauges are only reported once the name has resolved.
+
+use std::{
+  collections::BTreeMap,
+  convert::Infallible,
+  fmt::Write,
+  net::SocketAddr,
+  sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use error_stack::{IntoReport, Report, Result, ResultExt};
+use hyper::{
+  service::{make_service_fn, service_fn},
+  Body, Method, Request, Response, Server, StatusCode,
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+use w3name::{Name, Revision};
+
+use crate::{clock, exit_code, CliError};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The metrics of the watched names, shared between the watcher and the metrics server.
+#[derive(Clone, Default)]
+pub struct WatchMetrics {
+  names: Arc<Mutex<BTreeMap<String, NameMetrics>>>,
+}
+
+#[derive(Default)]
+struct NameMetrics {
+  last_success: Option<DateTime<Utc>>,
+  sequence: Option<u64>,
+  validity: Option<DateTime<Utc>>,
+  consecutive_failures: u64,
+  errors: BTreeMap<&'static str, u64>,
+  regressions: u64,
+}
+
+impl WatchMetrics {
+  /// Records a successful resolve of `revision`'s name.
+  pub fn resolved(&self, revision: &Revision) {
+    let mut names = self.names.lock().unwrap();
+    let metrics = names.entry(revision.name().to_string()).or_default();
+    metrics.last_success = Some(clock::now());
+    metrics.sequence = Some(revision.sequence());
+    metrics.validity = Some(*revision.validity());
+    metrics.consecutive_failures = 0;
+  }
+
+  /// Records a failed resolve of `name`, counting it under the class of `err_report`.
+  pub fn failed(&self, name: &Name, err_report: &Report<CliError>) {
+    let mut names = self.names.lock().unwrap();
+    let metrics = names.entry(name.to_string()).or_default();
+    metrics.consecutive_failures += 1;
+    *metrics.errors.entry(error_class(err_report)).or_default() += 1;
+  }
+
+  /// Records a resolve of `name` that returned an older record than the current one.
+  pub fn regressed(&self, name: &Name) {
+    let mut names = self.names.lock().unwrap();
+    names.entry(name.to_string()).or_default().regressions += 1;
+  }
+
+  /// Renders the metrics in the Prometheus text format, as of `now`.
+  pub fn render(&self, now: DateTime<Utc>) -> String {
+    let names = self.names.lock().unwrap();
+    let mut out = String::new();
+
+    let mut gauge = |metric: &str, help: &str, value: &dyn Fn(&NameMetrics) -> Option<String>| {
+      let _ = writeln!(out, "# HELP {} {}", metric, help);
+      let _ = writeln!(out, "# TYPE {} gauge", metric);
+      for (name, metrics) in names.iter() {
+        if let Some(value) = value(metrics) {
+          let _ = writeln!(out, "{}{{name=\"{}\"}} {}", metric, name, value);
+        }
+      }
+    };
+    gauge(
+      "w3name_last_resolve_success_timestamp_seconds",
+      "Unix time of the last successful resolve.",
+      &|metrics| Some(metrics.last_success?.timestamp().to_string()),
+    );
+    gauge(
+      "w3name_sequence",
+      "Sequence number of the current record.",
+      &|metrics| Some(metrics.sequence?.to_string()),
+    );
+    gauge(
+      "w3name_validity_remaining_seconds",
+      "Seconds until the current record expires.",
+      &|metrics| Some((metrics.validity? - now).num_seconds().to_string()),
+    );
+    gauge(
+      "w3name_consecutive_failures",
+      "Failed resolves since the last successful one.",
+      &|metrics| Some(metrics.consecutive_failures.to_string()),
+    );
+
+    let metric = "w3name_resolve_errors_total";
+    let _ = writeln!(out, "# HELP {} Failed resolves, by error class.", metric);
+    let _ = writeln!(out, "# TYPE {} counter", metric);
+    for (name, metrics) in names.iter() {
+      for (class, count) in &metrics.errors {
+        let _ = writeln!(
+          out,
+          "{}{{name=\"{}\",class=\"{}\"}} {}",
+          metric, name, class, count
+        );
+      }
+    }
+
+    let metric = "w3name_sequence_regressions_total";
+    let _ = writeln!(
+      out,
+      "# HELP {} Resolved records older than the current one.",
+      metric
+    );
+    let _ = writeln!(out, "# TYPE {} counter", metric);
+    for (name, metrics) in names.iter() {
+      let _ = writeln!(
+        out,
+        "{}{{name=\"{}\"}} {}",
+        metric, name, metrics.regressions
+      );
+    }
+    out
+  }
+}
+
+/// Classifies a failed resolve like [exit_code::for_report] does.
+fn error_class(err_report: &Report<CliError>) -> &'static str {
+  match exit_code::for_report(err_report) {
+    exit_code::NOT_FOUND => "not_found",
+    exit_code::INVALID_RECORD => "invalid_record",
+    exit_code::EXPIRED => "expired",
+    exit_code::SERVICE => "service",
+    _ => "other",
+  }
+}
+
+/// A running metrics endpoint. Call [shutdown](Self::shutdown) to stop it.
+pub struct MetricsServer {
+  addr: SocketAddr,
+  shutdown: oneshot::Sender<()>,
+  task: JoinHandle<()>,
+}
+
+impl MetricsServer {
+  /// Starts serving `metrics` on `addr`.
+  pub fn start(addr: SocketAddr, metrics: WatchMetrics) -> Result<MetricsServer, CliError> {
+    let builder = Server::try_bind(&addr)
+      .report()
+      .change_context(CliError::Usage)
+      .attach_printable(format!("can't serve metrics on {}", addr))?;
+    let make_svc = make_service_fn(move |_| {
+      let metrics = metrics.clone();
+      async move {
+        Ok::<_, Infallible>(service_fn(move |req| {
+          let res = respond(&metrics, &req);
+          async move { Ok::<_, Infallible>(res) }
+        }))
+      }
+    });
+    let server = builder.serve(make_svc);
+    let addr = server.local_addr();
+
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    let task = tokio::spawn(async move {
+      let graceful = server.with_graceful_shutdown(async {
+        let _ = stopped.await;
+      });
+      if let Err(err) = graceful.await {
+        log::error!("metrics server failed: {}", err);
+      }
+    });
+    log::info!("Serving metrics on http://{}/metrics", addr);
+    Ok(MetricsServer {
+      addr,
+      shutdown,
+      task,
+    })
+  }
+
+  /// Stops accepting connections, and waits for requests in progress to finish.
+  pub async fn shutdown(self) {
+    let _ = self.shutdown.send(());
+    let _ = self.task.await;
+    log::debug!("Stopped serving metrics on {}", self.addr);
+  }
+}
+
+fn respond(metrics: &WatchMetrics, req: &Request<Body>) -> Response<Body> {
+  if req.method() != Method::GET || req.uri().path() != "/metrics" {
+    return Response::builder()
+      .status(StatusCode::NOT_FOUND)
+      .body(Body::from("not found\n"))
+      .unwrap();
+  }
+  Response::builder()
+    .header("Content-Type", CONTENT_TYPE)
+    .body(Body::from(metrics.render(clock::now())))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::Duration;
+  use error_stack::report;
+  use w3name::{error::APIError, RevisionBuilder, WritableName};
+
+  #[test]
+  fn renders_gauges_and_error_counts() {
+    let metrics = WatchMetrics::default();
+    let name = WritableName::new().to_name();
+    let now = Utc::now();
+
+    // nothing but the metric descriptions until the name has been seen
+    assert!(!metrics.render(now).contains(&name.to_string()));
+
+    let not_found = report!(APIError {
+      message: "not found".to_string(),
+      status_code: StatusCode::NOT_FOUND,
+    })
+    .change_context(CliError::Watch);
+    metrics.failed(&name, &not_found);
+    metrics.failed(&name, &not_found);
+    let text = metrics.render(now);
+    assert!(text.contains(&format!(
+      "w3name_resolve_errors_total{{name=\"{}\",class=\"not_found\"}} 2",
+      name
+    )));
+    assert!(text.contains(&format!(
+      "w3name_consecutive_failures{{name=\"{}\"}} 2",
+      name
+    )));
+    assert!(!text.contains(&format!("w3name_sequence{{name=\"{}\"}}", name)));
+
+    let revision = RevisionBuilder::new(&name, "/ipfs/metrics")
+      .validity_at(now + Duration::hours(1))
+      .build()
+      .unwrap();
+    metrics.resolved(&revision);
+    let text = metrics.render(now);
+    assert!(text.contains(&format!("w3name_sequence{{name=\"{}\"}} 0", name)));
+    assert!(text.contains(&format!(
+      "w3name_validity_remaining_seconds{{name=\"{}\"}} 3600",
+      name
+    )));
+    assert!(text.contains(&format!(
+      "w3name_consecutive_failures{{name=\"{}\"}} 0",
+      name
+    )));
+    // the error counter keeps counting
+    assert!(text.contains("class=\"not_found\"} 2"));
+    assert!(text.contains("# TYPE w3name_resolve_errors_total counter"));
+  }
+}
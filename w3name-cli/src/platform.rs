@@ -0,0 +1,442 @@
+//! The differences between platforms in where w3name keeps its files and how it protects them.
+//!
+//! Everything that locates or writes w3name's own files (the keystore, the cache, locks and key
+//! files) goes through this module, so the rest of the CLI needn't care whether it runs on Linux,
+//! macOS or Windows, or as a static musl binary.
+//!
+//! | directory | Linux and other Unix     | macOS                     | Windows                          |
+//! |-----------|--------------------------|---------------------------|----------------------------------|
+//! | state     | `~/.w3name`              | `~/.w3name`               | `%USERPROFILE%\.w3name`          |
+//! | cache     | `~/.cache/w3name`        | `~/Library/Caches/w3name` | `%LOCALAPPDATA%\w3name\cache`    |
+//!
+//! `$W3NAME_HOME` overrides the state directory, and `$XDG_CACHE_HOME` the cache directory, on
+//! every platform. Both may start with `~`, which is expanded to the home directory.
+//!
+//! Without a home directory, e.g. in a container with no `$HOME`, there's only a state directory
+//! if `$W3NAME_HOME` is set, and the cache is then kept in its `cache` subdirectory (unless
+//! `$XDG_CACHE_HOME` is set). Commands that don't need either work without them.
+//!
+//! Files are written atomically, so a crash never leaves a partial key or state file behind.
+//!
+//! Key files are private: on Unix they're created readable by their owner only (`0600`), in
+//! directories created with `0700`. Windows has no permission bits; files there inherit the ACL
+//! of their directory, which for the defaults above is the user's profile.
+
+use std::{
+  convert::Infallible,
+  env,
+  ffi::OsString,
+  fs, io,
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The platforms that lay out their directories differently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Platform {
+  Unix,
+  MacOs,
+  Windows,
+}
+
+impl Platform {
+  fn current() -> Platform {
+    if cfg!(windows) {
+      Platform::Windows
+    } else if cfg!(target_os = "macos") {
+      Platform::MacOs
+    } else {
+      Platform::Unix
+    }
+  }
+}
+
+/// The number of temporary files created by [atomic_write] so far, to tell them apart.
+static TMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// True if the platform has Unix permission bits, so private files can be made unreadable by
+/// other users.
+pub const HAS_PERMISSION_BITS: bool = cfg!(unix);
+
+/// Returns the user's home directory, if it can be determined.
+pub fn home_dir() -> Option<PathBuf> {
+  home_dir_for(Platform::current(), &env_var)
+}
+
+/// Returns the directory for w3name's local state (the keystore and locks), if one can be
+/// determined.
+pub fn state_dir() -> Option<PathBuf> {
+  state_dir_for(Platform::current(), &env_var)
+}
+
+/// Returns the directory for w3name's cache, if one can be determined.
+pub fn cache_dir() -> Option<PathBuf> {
+  cache_dir_for(Platform::current(), &env_var)
+}
+
+/// Replaces a leading `~` in `path` with the home directory. Other paths are returned as is, and
+/// so is `path` if there's no home directory.
+///
+/// Shells expand `~` in arguments, but not in environment variables set from files, like
+/// systemd units or `.env` files, and the Windows shells don't expand it at all.
+pub fn expand_tilde(path: impl Into<PathBuf>) -> PathBuf {
+  expand_tilde_with(path.into(), home_dir())
+}
+
+/// Parses a path argument, expanding a leading `~` (see [expand_tilde]).
+pub fn path_arg(arg: &str) -> Result<PathBuf, Infallible> {
+  Ok(expand_tilde(arg))
+}
+
+/// Writes `bytes` to a new or existing file at `path` that only its owner can read, creating the
+/// file's directory if needed.
+///
+/// The file is written atomically (see [atomic_write]). On Unix, it ends up with permissions
+/// `0600`, which it has before any bytes are written. On Windows, this is a plain atomic write.
+pub fn write_private(path: &Path, bytes: &[u8]) -> io::Result<()> {
+  if let Some(parent) = path
+    .parent()
+    .filter(|parent| !parent.as_os_str().is_empty())
+  {
+    create_private_dir(parent)?;
+  }
+  write_atomically(path, bytes, true, &|from, to| fs::rename(from, to))
+}
+
+/// Replaces the file at `path` with `bytes`, or creates it, such that a crash or a failure never
+/// leaves a partially written file at `path`: it either has its old contents or the new ones.
+///
+/// The bytes are written to a temporary file in the same directory, which is synced and then
+/// renamed to `path`. On Unix, the directory is synced too, so the rename survives a crash.
+pub fn atomic_write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+  write_atomically(path, bytes, false, &|from, to| fs::rename(from, to))
+}
+
+/// Implements [atomic_write], renaming with `rename` so tests can make it fail.
+fn write_atomically(
+  path: &Path,
+  bytes: &[u8],
+  private: bool,
+  rename: &dyn Fn(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+  use std::io::Write;
+
+  let file_name = path
+    .file_name()
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+  let dir = path
+    .parent()
+    .filter(|parent| !parent.as_os_str().is_empty())
+    .unwrap_or_else(|| Path::new("."));
+  let mut tmp_name = OsString::from(".");
+  tmp_name.push(file_name);
+  // every write gets its own temporary file, so concurrent writes can't interleave their bytes
+  let n = TMP_FILES.fetch_add(1, Ordering::Relaxed);
+  tmp_name.push(format!(".{}-{}.tmp", std::process::id(), n));
+  let tmp_path = dir.join(tmp_name);
+
+  let written = new_file(&tmp_path, private).and_then(|mut file| {
+    file.write_all(bytes)?;
+    file.sync_all()
+  });
+  if let Err(err) = written.and_then(|_| rename(&tmp_path, path)) {
+    let _ = fs::remove_file(&tmp_path);
+    return Err(err);
+  }
+
+  #[cfg(unix)]
+  fs::File::open(dir)?.sync_all()?;
+  Ok(())
+}
+
+/// Creates the file at `path`, replacing a leftover one. On Unix, a `private` file is created
+/// readable by its owner only.
+fn new_file(path: &Path, private: bool) -> io::Result<fs::File> {
+  let mut options = fs::OpenOptions::new();
+  options.write(true).create(true).truncate(true);
+
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    if private {
+      options.mode(0o600);
+    }
+    let file = options.open(path)?;
+    // the mode only applies to new files, and a leftover file may have had other permissions
+    if private {
+      file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(file)
+  }
+
+  #[cfg(not(unix))]
+  {
+    let _ = private;
+    options.open(path)
+  }
+}
+
+/// Creates `path` and its missing parents. On Unix, the directories that are created are only
+/// accessible by their owner (`0700`); existing directories are left alone.
+pub fn create_private_dir(path: &Path) -> io::Result<()> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new()
+      .recursive(true)
+      .mode(0o700)
+      .create(path)
+  }
+
+  #[cfg(not(unix))]
+  {
+    fs::create_dir_all(path)
+  }
+}
+
+fn env_var(key: &str) -> Option<OsString> {
+  env::var_os(key).filter(|value| !value.is_empty())
+}
+
+fn home_dir_for(platform: Platform, var: &dyn Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+  let home = match platform {
+    Platform::Windows => var("USERPROFILE").or_else(|| var("HOME")),
+    Platform::Unix | Platform::MacOs => var("HOME").or_else(|| var("USERPROFILE")),
+  };
+  home.map(PathBuf::from)
+}
+
+fn state_dir_for(platform: Platform, var: &dyn Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+  let home = home_dir_for(platform, var);
+  if let Some(dir) = var("W3NAME_HOME") {
+    return Some(expand_tilde_with(dir.into(), home));
+  }
+  Some(home?.join(".w3name"))
+}
+
+fn cache_dir_for(platform: Platform, var: &dyn Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+  let home = home_dir_for(platform, var);
+  if let Some(dir) = var("XDG_CACHE_HOME") {
+    return Some(expand_tilde_with(dir.into(), home).join("w3name"));
+  }
+  default_cache_dir(platform, home, var).or_else(|| {
+    // without a home directory, the cache can still go with the state
+    Some(state_dir_for(platform, var)?.join("cache"))
+  })
+}
+
+fn default_cache_dir(
+  platform: Platform,
+  home: Option<PathBuf>,
+  var: &dyn Fn(&str) -> Option<OsString>,
+) -> Option<PathBuf> {
+  match platform {
+    Platform::Unix => Some(home?.join(".cache").join("w3name")),
+    Platform::MacOs => Some(home?.join("Library").join("Caches").join("w3name")),
+    Platform::Windows => {
+      let local = var("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .or_else(|| Some(home?.join("AppData").join("Local")))?;
+      Some(local.join("w3name").join("cache"))
+    }
+  }
+}
+
+fn expand_tilde_with(path: PathBuf, home: Option<PathBuf>) -> PathBuf {
+  let Some(home) = home else {
+    return path;
+  };
+  let Some(text) = path.to_str() else {
+    return path;
+  };
+  if text == "~" {
+    return home;
+  }
+  match text.strip_prefix("~/").or_else(|| text.strip_prefix("~\\")) {
+    Some(rest) => home.join(rest),
+    None => path,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+    let vars: HashMap<String, OsString> = pairs
+      .iter()
+      .map(|(key, value)| (key.to_string(), OsString::from(value)))
+      .collect();
+    move |key| vars.get(key).cloned()
+  }
+
+  #[test]
+  fn unix_directories() {
+    let var = vars(&[("HOME", "/home/ada")]);
+    assert_eq!(
+      state_dir_for(Platform::Unix, &var),
+      Some(PathBuf::from("/home/ada/.w3name"))
+    );
+    assert_eq!(
+      cache_dir_for(Platform::Unix, &var),
+      Some(PathBuf::from("/home/ada/.cache/w3name"))
+    );
+    assert_eq!(
+      cache_dir_for(Platform::MacOs, &var),
+      Some(PathBuf::from("/home/ada/Library/Caches/w3name"))
+    );
+
+    let var = vars(&[
+      ("HOME", "/home/ada"),
+      ("W3NAME_HOME", "~/state"),
+      ("XDG_CACHE_HOME", "/var/cache"),
+    ]);
+    assert_eq!(
+      state_dir_for(Platform::Unix, &var),
+      Some(PathBuf::from("/home/ada/state"))
+    );
+    assert_eq!(
+      cache_dir_for(Platform::MacOs, &var),
+      Some(PathBuf::from("/var/cache/w3name"))
+    );
+
+    assert_eq!(state_dir_for(Platform::Unix, &vars(&[])), None);
+    assert_eq!(cache_dir_for(Platform::Unix, &vars(&[])), None);
+
+    // without a home directory, the cache goes with the state
+    let var = vars(&[("W3NAME_HOME", "/srv/w3name")]);
+    assert_eq!(
+      state_dir_for(Platform::Unix, &var),
+      Some(PathBuf::from("/srv/w3name"))
+    );
+    assert_eq!(
+      cache_dir_for(Platform::Unix, &var),
+      Some(PathBuf::from("/srv/w3name/cache"))
+    );
+  }
+
+  #[test]
+  fn windows_directories() {
+    let var = vars(&[
+      ("USERPROFILE", "C:\\Users\\ada"),
+      ("LOCALAPPDATA", "C:\\Users\\ada\\AppData\\Local"),
+    ]);
+    assert_eq!(
+      home_dir_for(Platform::Windows, &var),
+      Some(PathBuf::from("C:\\Users\\ada"))
+    );
+    assert_eq!(
+      state_dir_for(Platform::Windows, &var),
+      Some(PathBuf::from("C:\\Users\\ada").join(".w3name"))
+    );
+    assert_eq!(
+      cache_dir_for(Platform::Windows, &var),
+      Some(
+        PathBuf::from("C:\\Users\\ada\\AppData\\Local")
+          .join("w3name")
+          .join("cache")
+      )
+    );
+
+    // without LOCALAPPDATA, the cache is where it would be by default
+    let var = vars(&[("USERPROFILE", "C:\\Users\\ada")]);
+    assert_eq!(
+      cache_dir_for(Platform::Windows, &var),
+      Some(
+        PathBuf::from("C:\\Users\\ada")
+          .join("AppData")
+          .join("Local")
+          .join("w3name")
+          .join("cache")
+      )
+    );
+  }
+
+  #[test]
+  fn expands_tilde() {
+    let home = Some(PathBuf::from("/home/ada"));
+    let expand = |path: &str| expand_tilde_with(PathBuf::from(path), home.clone());
+    assert_eq!(expand("~"), PathBuf::from("/home/ada"));
+    assert_eq!(
+      expand("~/keys/a.key"),
+      PathBuf::from("/home/ada/keys/a.key")
+    );
+    assert_eq!(expand("~\\keys"), PathBuf::from("/home/ada").join("keys"));
+    assert_eq!(expand("/srv/~/keys"), PathBuf::from("/srv/~/keys"));
+    assert_eq!(expand("~ada/keys"), PathBuf::from("~ada/keys"));
+    assert_eq!(
+      expand_tilde_with(PathBuf::from("~/keys"), None),
+      PathBuf::from("~/keys")
+    );
+  }
+
+  #[test]
+  fn private_files() {
+    let dir = env::temp_dir().join(format!("w3name-platform-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let path = dir.join("keys").join("a.key");
+    write_private(&path, b"secret").unwrap();
+    write_private(&path, b"new").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"new");
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+
+      let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+      assert_eq!(mode(&path), 0o600);
+      assert_eq!(mode(path.parent().unwrap()), 0o700);
+
+      // an existing file loses its other permissions when it's rewritten
+      fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+      write_private(&path, b"newer").unwrap();
+      assert_eq!(mode(&path), 0o600);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn atomic_writes() {
+    let dir = env::temp_dir().join(format!("w3name-platform-atomic-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("state.json");
+    atomic_write(&path, b"old").unwrap();
+    atomic_write(&path, b"new").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"new");
+
+    // a failed rename leaves the old file alone, and no temporary file behind
+    let fail = |_: &Path, _: &Path| Err(io::Error::other("rename failed"));
+    assert!(write_atomically(&path, b"newer", false, &fail).is_err());
+    assert!(write_atomically(&dir.join("a.key"), b"secret", true, &fail).is_err());
+    assert_eq!(fs::read(&path).unwrap(), b"new");
+    let files: Vec<_> = fs::read_dir(&dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name())
+      .collect();
+    assert_eq!(files, vec![OsString::from("state.json")]);
+
+    assert!(atomic_write(&dir.join("missing").join("state.json"), b"new").is_err());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn windows_has_no_permission_bits() {
+    assert!(!HAS_PERMISSION_BITS);
+    let dir = env::temp_dir().join(format!("w3name-platform-win-{}", std::process::id()));
+    let path = dir.join("keys").join("a.key");
+    write_private(&path, b"secret").unwrap();
+    // the only portable permission is read-only, which a private file isn't
+    assert!(!fs::metadata(&path).unwrap().permissions().readonly());
+    assert_eq!(
+      path.to_str().unwrap(),
+      format!("{}\\keys\\a.key", dir.display())
+    );
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,484 @@
+//! Interactive prompts for `create` and `publish`, when they're run on a terminal without the
+//! arguments they need.
+//!
+//! The flows only talk to the user through a [Terminal], so tests can script the answers instead
+//! of needing a real TTY. Prompts are shown on stderr, so stdout holds the command's usual output.
+//! With `--no-input`, or when stdin or stdout isn't a terminal, nothing is ever asked, and missing
+//! arguments are errors as before.
+
+use std::{
+  io::{self, BufRead, IsTerminal, Write},
+  path::{Path, PathBuf},
+};
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use w3name::Name;
+
+use crate::{keystore::Keystore, value_source, CliError};
+
+/// Where the prompts of an interactive flow are shown and answered.
+pub trait Terminal {
+  /// Shows a line of text.
+  fn say(&mut self, line: &str);
+
+  /// Shows `prompt` and reads the answer, without its line ending. Returns `None` at the end of
+  /// input.
+  fn ask(&mut self, prompt: &str) -> io::Result<Option<String>>;
+}
+
+/// The process's terminal: prompts on stderr, answers from stdin.
+pub struct StdTerminal;
+
+impl Terminal for StdTerminal {
+  fn say(&mut self, line: &str) {
+    eprintln!("{}", line);
+  }
+
+  fn ask(&mut self, prompt: &str) -> io::Result<Option<String>> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+      return Ok(None);
+    }
+    Ok(Some(answer.trim_end_matches(['\r', '\n']).to_string()))
+  }
+}
+
+/// Returns true if missing arguments may be asked for: `--no-input` wasn't given, and stdin and
+/// stdout are both terminals.
+pub fn is_interactive(no_input: bool) -> bool {
+  !no_input && io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Returns true if `--no-input` is on the raw command line, for when it couldn't be parsed.
+pub fn no_input_requested(args: &[String]) -> bool {
+  args.iter().any(|arg| arg == "--no-input")
+}
+
+/// Asks `prompt` until the answer passes `check`, showing why it didn't otherwise.
+fn input<T>(
+  term: &mut dyn Terminal,
+  prompt: &str,
+  check: impl Fn(&str) -> std::result::Result<T, String>,
+) -> Result<T, CliError> {
+  loop {
+    let answer = term
+      .ask(prompt)
+      .report()
+      .change_context(CliError::Usage)?
+      .ok_or_else(|| report!(CliError::Usage).attach_printable("no answer: input ended"))?;
+    match check(answer.trim()) {
+      Ok(checked) => return Ok(checked),
+      Err(problem) => term.say(&format!("  {}", problem)),
+    }
+  }
+}
+
+/// Asks a yes or no question, where an empty answer is `default`.
+fn confirm(term: &mut dyn Terminal, question: &str, default: bool) -> Result<bool, CliError> {
+  let hint = if default { "[Y/n]" } else { "[y/N]" };
+  input(
+    term,
+    &format!("{} {} ", question, hint),
+    |answer| match answer.to_ascii_lowercase().as_str() {
+      "" => Ok(default),
+      "y" | "yes" => Ok(true),
+      "n" | "no" => Ok(false),
+      _ => Err("answer y or n".to_string()),
+    },
+  )
+}
+
+/// An answer to [select]: one of the numbered items, or something else that was typed.
+#[derive(Debug, PartialEq, Eq)]
+enum Choice {
+  Item(usize),
+  Other(String),
+}
+
+/// Shows `items` numbered from 1, and asks for a number, or anything else if `other` says what
+/// else may be typed. An empty answer picks the first item.
+fn select(
+  term: &mut dyn Terminal,
+  question: &str,
+  items: &[String],
+  other: Option<&str>,
+) -> Result<Choice, CliError> {
+  term.say(question);
+  for (n, item) in items.iter().enumerate() {
+    term.say(&format!("  {}) {}", n + 1, item));
+  }
+  let prompt = match other {
+    Some(other) => format!("Enter a number, or {} [1]: ", other),
+    None => "Enter a number [1]: ".to_string(),
+  };
+  input(term, &prompt, |answer| {
+    if answer.is_empty() && !items.is_empty() {
+      return Ok(Choice::Item(0));
+    }
+    match answer.parse::<usize>() {
+      Ok(n) if (1..=items.len()).contains(&n) => Ok(Choice::Item(n - 1)),
+      Ok(_) => Err(format!("enter a number from 1 to {}", items.len())),
+      Err(_) if other.is_some() && !answer.is_empty() => Ok(Choice::Other(answer.to_string())),
+      Err(_) => Err("enter one of the numbers".to_string()),
+    }
+  })
+}
+
+/// A key file that `publish` can offer, e.g. from the keystore.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyChoice {
+  pub path: PathBuf,
+  /// How the key is shown in the list, e.g. its name and aliases.
+  pub label: String,
+}
+
+/// Returns the keys in `keystore`, labelled with their names and aliases.
+pub fn keystore_choices(keystore: &Keystore) -> Vec<KeyChoice> {
+  let aliases = keystore.aliases();
+  keystore
+    .key_names()
+    .into_iter()
+    .map(|name| {
+      let names: Vec<&str> = aliases
+        .iter()
+        .filter(|(_, target)| **target == name)
+        .map(|(alias, _)| alias.as_str())
+        .collect();
+      let label = match names.is_empty() {
+        true => name.clone(),
+        false => format!("{} ({})", name, names.join(", ")),
+      };
+      KeyChoice {
+        path: keystore.key_path(&name),
+        label,
+      }
+    })
+    .collect()
+}
+
+/// What `publish` was run with, as far as the prompts are concerned.
+pub struct PublishQuestions<'a> {
+  /// The key file given with `--key`, if any. Otherwise it's asked for.
+  pub key: Option<&'a Path>,
+  /// Whether the value still has to be asked for.
+  pub ask_value: bool,
+  /// The keys to offer.
+  pub keys: &'a [KeyChoice],
+}
+
+/// The answers of the `publish` prompts: the arguments that were missing.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PublishAnswers {
+  pub key: Option<PathBuf>,
+  pub value: Option<String>,
+}
+
+impl PublishAnswers {
+  /// The answers as command-line arguments, to add to the ones `publish` was run with.
+  pub fn to_args(&self) -> Vec<String> {
+    let mut args = vec![];
+    if let Some(key) = &self.key {
+      args.extend(["--key".to_string(), key.display().to_string()]);
+    }
+    if let Some(value) = &self.value {
+      args.extend(["--value".to_string(), value.clone()]);
+    }
+    args
+  }
+}
+
+/// The steps of the `publish` prompts.
+enum PublishStep {
+  /// Pick a key.
+  Key,
+  /// Confirm the name of the key that was picked.
+  ConfirmName(PathBuf, Name),
+  /// Enter the value.
+  Value(PathBuf, Name),
+  /// Confirm the name and value before publishing.
+  Summary(PathBuf, Name, String),
+}
+
+/// Asks for what `publish` is missing: a key (offering `questions.keys`), confirming the name it's
+/// for, and the value, checked like `--value-from-file`'s. If the value was asked for, it then
+/// shows what's about to be published, and fails if that isn't confirmed.
+///
+/// `name_of` reads a key file and returns its name, or why it can't.
+pub fn publish(
+  term: &mut dyn Terminal,
+  questions: &PublishQuestions<'_>,
+  name_of: &dyn Fn(&Path) -> std::result::Result<Name, String>,
+) -> Result<PublishAnswers, CliError> {
+  let mut step = match questions.key {
+    Some(path) => {
+      let name = name_of(path).map_err(|problem| {
+        report!(CliError::Usage).attach_printable(format!("{}: {}", path.display(), problem))
+      })?;
+      PublishStep::Value(path.to_path_buf(), name)
+    }
+    None => PublishStep::Key,
+  };
+  loop {
+    step = match step {
+      PublishStep::Key => {
+        let (path, name) = ask_key(term, questions.keys, name_of)?;
+        PublishStep::ConfirmName(path, name)
+      }
+      PublishStep::ConfirmName(path, name) => {
+        if confirm(term, &format!("Publish to {}?", name), true)? {
+          PublishStep::Value(path, name)
+        } else {
+          PublishStep::Key
+        }
+      }
+      PublishStep::Value(path, name) if questions.ask_value => {
+        let value = input(
+          term,
+          "Value (a CID or an /ipfs/ or /ipns/ path): ",
+          |answer| {
+            value_source::normalize(answer, "the value").map_err(|err_report| {
+              err_report
+                .frames()
+                .find_map(|frame| frame.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "that's not a value w3name can publish".to_string())
+            })
+          },
+        )?;
+        PublishStep::Summary(path, name, value)
+      }
+      PublishStep::Value(path, _) => {
+        return Ok(PublishAnswers {
+          key: questions.key.is_none().then_some(path),
+          value: None,
+        });
+      }
+      PublishStep::Summary(path, name, value) => {
+        term.say("");
+        term.say(&format!("  name:  {}", name));
+        term.say(&format!("  key:   {}", path.display()));
+        term.say(&format!("  value: {}", value));
+        if !confirm(term, "Publish this?", true)? {
+          return Err(report!(CliError::Usage).attach_printable("nothing was published"));
+        }
+        return Ok(PublishAnswers {
+          key: questions.key.is_none().then_some(path),
+          value: Some(value),
+        });
+      }
+    }
+  }
+}
+
+/// Asks which key to publish with: one of `keys`, or the path of a key file.
+fn ask_key(
+  term: &mut dyn Terminal,
+  keys: &[KeyChoice],
+  name_of: &dyn Fn(&Path) -> std::result::Result<Name, String>,
+) -> Result<(PathBuf, Name), CliError> {
+  loop {
+    let path = if keys.is_empty() {
+      input(term, "Path to a key file: ", |answer| match answer {
+        "" => Err("enter the path of a key file, e.g. one written by `w3name create`".to_string()),
+        path => Ok(PathBuf::from(path)),
+      })?
+    } else {
+      let labels: Vec<String> = keys.iter().map(|key| key.label.clone()).collect();
+      match select(
+        term,
+        "Which key should sign the record?",
+        &labels,
+        Some("the path of a key file"),
+      )? {
+        Choice::Item(n) => keys[n].path.clone(),
+        Choice::Other(path) => PathBuf::from(path),
+      }
+    };
+    match name_of(&path) {
+      Ok(name) => return Ok((path, name)),
+      Err(problem) => term.say(&format!("  {}: {}", path.display(), problem)),
+    }
+  }
+}
+
+/// Asks where `create` should write the key of `name`: `default` (`<name>.key` in the working
+/// directory), the keystore, or a path that's typed in.
+pub fn create_output(
+  term: &mut dyn Terminal,
+  name: &str,
+  default: &Path,
+  keystore: Option<&Keystore>,
+) -> Result<PathBuf, CliError> {
+  term.say(&format!("Created the key for {}.", name));
+  let mut paths = vec![default.to_path_buf()];
+  let mut labels = vec![format!("{} (here)", default.display())];
+  if let Some(keystore) = keystore {
+    let path = keystore.key_path(name);
+    labels.push(format!("{} (the keystore)", path.display()));
+    paths.push(path);
+  }
+  match select(
+    term,
+    "Where should it be saved?",
+    &labels,
+    Some("another path"),
+  )? {
+    Choice::Item(n) => Ok(paths.swap_remove(n)),
+    Choice::Other(path) => Ok(PathBuf::from(path)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::VecDeque;
+  use w3name::WritableName;
+
+  const CID: &str = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+  /// Answers prompts from a script, and keeps everything that was shown.
+  struct Scripted {
+    answers: VecDeque<String>,
+    shown: Vec<String>,
+  }
+
+  impl Scripted {
+    fn new(answers: &[&str]) -> Self {
+      Scripted {
+        answers: answers.iter().map(|answer| answer.to_string()).collect(),
+        shown: vec![],
+      }
+    }
+
+    fn shown(&self, text: &str) -> bool {
+      self.shown.iter().any(|line| line.contains(text))
+    }
+  }
+
+  impl Terminal for Scripted {
+    fn say(&mut self, line: &str) {
+      self.shown.push(line.to_string());
+    }
+
+    fn ask(&mut self, prompt: &str) -> io::Result<Option<String>> {
+      self.shown.push(prompt.to_string());
+      Ok(self.answers.pop_front())
+    }
+  }
+
+  fn keys(names: &[&Name]) -> Vec<KeyChoice> {
+    names
+      .iter()
+      .map(|name| KeyChoice {
+        path: PathBuf::from(format!("keys/{}.key", name)),
+        label: name.to_string(),
+      })
+      .collect()
+  }
+
+  /// Key files named after their names.
+  fn name_of(names: &[&Name]) -> impl Fn(&Path) -> std::result::Result<Name, String> {
+    let names: Vec<Name> = names.iter().map(|name| (*name).clone()).collect();
+    move |path| {
+      names
+        .iter()
+        .find(|name| path.to_string_lossy().contains(&name.to_string()))
+        .cloned()
+        .ok_or_else(|| "not a key file".to_string())
+    }
+  }
+
+  #[test]
+  fn publish_asks_for_key_and_value() {
+    let (first, second) = (WritableName::new().to_name(), WritableName::new().to_name());
+    let keys = keys(&[&first, &second]);
+    let questions = PublishQuestions {
+      key: None,
+      ask_value: true,
+      keys: &keys,
+    };
+    // pick an unknown number, then the first key, decline its name, pick the second key, then a
+    // bad value before a good one
+    let mut term = Scripted::new(&["3", "1", "n", "2", "", "build failed", CID, "y"]);
+    let answers = publish(&mut term, &questions, &name_of(&[&first, &second])).unwrap();
+    assert_eq!(
+      answers,
+      PublishAnswers {
+        key: Some(keys[1].path.clone()),
+        value: Some(format!("/ipfs/{}", CID)),
+      }
+    );
+    assert!(term.shown("enter a number from 1 to 2"));
+    assert!(term.shown(&format!("Publish to {}?", first)));
+    assert!(term.shown("isn't a CID"));
+    assert!(term.shown(&format!("value: /ipfs/{}", CID)));
+    assert!(term.answers.is_empty());
+    assert_eq!(
+      answers.to_args(),
+      vec![
+        "--key".to_string(),
+        keys[1].path.display().to_string(),
+        "--value".to_string(),
+        format!("/ipfs/{}", CID),
+      ]
+    );
+  }
+
+  #[test]
+  fn publish_asks_only_for_whats_missing() {
+    let name = WritableName::new().to_name();
+    let path = PathBuf::from(format!("{}.key", name));
+
+    // with --key, only the value is asked for, and declining the summary publishes nothing
+    let questions = PublishQuestions {
+      key: Some(&path),
+      ask_value: true,
+      keys: &[],
+    };
+    let mut term = Scripted::new(&[CID, "n"]);
+    assert!(publish(&mut term, &questions, &name_of(&[&name])).is_err());
+    assert!(!term.shown("Publish to"));
+
+    // with the value, only the key is asked for, by path when the keystore is empty
+    let questions = PublishQuestions {
+      key: None,
+      ask_value: false,
+      keys: &[],
+    };
+    let path_answer = path.display().to_string();
+    let mut term = Scripted::new(&["missing.key", &path_answer, "yes"]);
+    let answers = publish(&mut term, &questions, &name_of(&[&name])).unwrap();
+    assert_eq!(
+      answers,
+      PublishAnswers {
+        key: Some(path.clone()),
+        value: None,
+      }
+    );
+    assert!(term.shown("missing.key: not a key file"));
+
+    // the end of input is an error, not a loop
+    let mut term = Scripted::new(&[]);
+    assert!(publish(&mut term, &questions, &name_of(&[&name])).is_err());
+  }
+
+  #[test]
+  fn create_asks_where_to_save() {
+    let dir = std::env::temp_dir().join(format!("w3name-prompt-{}", std::process::id()));
+    let keystore = Keystore::at(dir.clone());
+    let default = Path::new("k51example.key");
+
+    let mut term = Scripted::new(&[""]);
+    let path = create_output(&mut term, "k51example", default, Some(&keystore)).unwrap();
+    assert_eq!(path, default);
+
+    let mut term = Scripted::new(&["2"]);
+    let path = create_output(&mut term, "k51example", default, Some(&keystore)).unwrap();
+    assert_eq!(path, keystore.key_path("k51example"));
+
+    let mut term = Scripted::new(&["keys/site.key"]);
+    let path = create_output(&mut term, "k51example", default, None).unwrap();
+    assert_eq!(path, Path::new("keys/site.key"));
+  }
+}
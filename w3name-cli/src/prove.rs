@@ -0,0 +1,54 @@
+//! The `prove` subcommand: signs a proof that the user controls a name, e.g. for a registrar, and
+//! verifies such proofs with `prove verify`.
+
+use std::{fs, path::Path};
+
+use error_stack::{IntoReport, Result, ResultExt};
+use w3name::Proof;
+
+use crate::{
+  clock, dnslink, error_output::InvolvedName, keyfile, keystore::Keystore, timefmt, CliError,
+};
+
+/// Signs `challenge` with the key in `key_file`, and prints the proof as JSON.
+pub fn sign(key_file: &Path, challenge: &str) -> Result<(), CliError> {
+  let name = keyfile::load_key_file(key_file)?;
+  let proof = name
+    .sign_proof(challenge)
+    .change_context(CliError::Other)
+    .attach_printable(InvolvedName(name.to_string()))?;
+  println!("{}", proof.to_json());
+  Ok(())
+}
+
+/// Checks that the proof in `proof_file` was signed by `name_arg`'s key, and no more than
+/// `max_age` ago if given, then prints its challenge and when it was signed.
+pub fn verify(
+  name_arg: &str,
+  proof_file: &Path,
+  max_age: Option<chrono::Duration>,
+) -> Result<(), CliError> {
+  let name = dnslink::name_for_arg(name_arg, Keystore::open_default().as_ref())?;
+  let json = fs::read_to_string(proof_file)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!("proof file: {}", proof_file.display()))?;
+  let proof = Proof::from_json(&json)
+    .change_context(CliError::Proof)
+    .attach_printable(format!("proof file: {}", proof_file.display()))?;
+
+  match max_age {
+    Some(max_age) => name.verify_proof_within(&proof, max_age),
+    None => name.verify_proof(&proof),
+  }
+  .change_context(CliError::Proof)
+  .attach_printable(InvolvedName(name.to_string()))?;
+
+  println!("valid proof of control of {}", name);
+  println!("challenge: {}", proof.challenge());
+  println!(
+    "signed: {}",
+    timefmt::moment(proof.timestamp(), clock::now())
+  );
+  Ok(())
+}
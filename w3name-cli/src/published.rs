@@ -0,0 +1,101 @@
+//! The revision that `publish` signed last for each name, so that the next publish can keep its
+//! validity window: records have no field for it (see [Revision::with_validity_window_of]).
+//!
+//! The revisions live in the state directory, `$W3NAME_HOME` or `~/.w3name` (see [platform]), in
+//! the single-line format of [Revision], which includes the window:
+//!
+//! ```text
+//! ~/.w3name/
+//!   published/<name>
+//! ```
+//!
+//! This is only a hint. A missing or unreadable file means the window isn't known, and the
+//! increment policy keeps the current record's end of life instead.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use w3name::{Name, Revision};
+
+use crate::platform;
+
+const PUBLISHED_DIR: &str = "published";
+
+/// Returns the revision last signed for `name`, if it's known.
+pub fn last(name: &Name) -> Option<Revision> {
+  platform::state_dir().and_then(|dir| last_in(&dir, name))
+}
+
+/// Remembers `revision` as the last one signed for its name. Failures are only logged, since
+/// all that's lost is the window of the next publish.
+pub fn remember(revision: &Revision) {
+  let Some(dir) = platform::state_dir() else {
+    return;
+  };
+  if let Err(err) = remember_in(&dir, revision) {
+    log::debug!(
+      "Unable to remember the published revision for {}: {}",
+      revision.name(),
+      err
+    );
+  }
+}
+
+fn path(dir: &Path, name: &Name) -> PathBuf {
+  dir.join(PUBLISHED_DIR).join(name.to_string())
+}
+
+fn last_in(dir: &Path, name: &Name) -> Option<Revision> {
+  let line = fs::read_to_string(path(dir, name)).ok()?;
+  match line.parse::<Revision>() {
+    Ok(revision) if revision.name() == name => Some(revision),
+    Ok(_) => None,
+    Err(err_report) => {
+      log::debug!(
+        "Ignoring the unreadable published revision: {:?}",
+        err_report
+      );
+      None
+    }
+  }
+}
+
+fn remember_in(dir: &Path, revision: &Revision) -> std::io::Result<()> {
+  let path = path(dir, revision.name());
+  platform::create_private_dir(path.parent().expect("the path has a parent"))?;
+  platform::atomic_write(&path, revision.to_string().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::Duration;
+  use w3name::{RevisionBuilder, WritableName};
+
+  #[test]
+  fn round_trip() {
+    let dir = std::env::temp_dir().join(format!("w3name-published-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let name = WritableName::new().to_name();
+    assert!(last_in(&dir, &name).is_none());
+
+    let revision = RevisionBuilder::new(&name, "/ipfs/published")
+      .validity_for(Duration::days(7))
+      .build()
+      .unwrap();
+    remember_in(&dir, &revision).unwrap();
+    let last = last_in(&dir, &name).unwrap();
+    assert_eq!(last, revision);
+    assert_eq!(last.validity_window(), Some(Duration::days(7)));
+
+    // another name's file, or a corrupt one, is ignored
+    let other = WritableName::new().to_name();
+    fs::copy(path(&dir, &name), path(&dir, &other)).unwrap();
+    assert!(last_in(&dir, &other).is_none());
+    fs::write(path(&dir, &name), "not a revision").unwrap();
+    assert!(last_in(&dir, &name).is_none());
+    let _ = fs::remove_dir_all(&dir);
+  }
+}
@@ -0,0 +1,336 @@
+//! The `record` subcommands, which work on serialized records.
+//!
+//! `resign` reads a record from stdin and writes the result to stdout without talking to the
+//! network, so it can be used as a filter:
+//!
+//! ```text
+//! cat record.bin | w3name record resign --key k.key --validity 1y > new-record.bin
+//! ```
+//!
+//! `fetch` writes the record a local Kubo node finds in the DHT, to compare with what the service
+//! and gateways return.
+//!
+//! `ls` lists the record archives in directories, like those that `--archive` fills:
+//!
+//! ```text
+//! w3name record ls archives/ --expired-only --sort validity
+//! ```
+
+use std::{
+  io::{self, Read, Write},
+  path::{Path, PathBuf},
+};
+
+use error_stack::{IntoReport, Result, ResultExt};
+use reqwest::Url;
+use w3name::{Name, Revision, RevisionBuilder, WritableName};
+
+use crate::{
+  archive::{self, ArchiveListing, ListOrder},
+  clock, config,
+  error_output::InvolvedName,
+  inspect, keyfile,
+  kubo::KuboClient,
+  platform,
+  service::Network,
+  table::{Cell, Style, Table},
+  timefmt, CliError,
+};
+
+/// Reads a record from `input`, as binary or base64 text.
+fn read_record(mut input: impl Read) -> Result<Vec<u8>, CliError> {
+  let mut bytes = vec![];
+  input
+    .read_to_end(&mut bytes)
+    .report()
+    .change_context(CliError::Parse)?;
+  Ok(decode_record(bytes))
+}
+
+/// Returns the record in `bytes`, decoding it first if it's base64 text.
+///
+/// Serialized records start with a protobuf field tag, which is never a base64 character, so a
+/// binary record is never mistaken for base64.
+pub fn decode_record(bytes: Vec<u8>) -> Vec<u8> {
+  std::str::from_utf8(&bytes)
+    .ok()
+    .and_then(|text| base64::decode(text.trim()).ok())
+    .unwrap_or(bytes)
+}
+
+/// Re-signs the record read from stdin with the key in `key_file`, keeping its value and TTL but
+/// incrementing its sequence number and setting a fresh validity, and writes the new record to
+/// stdout.
+///
+/// Records that don't belong to the key are refused, as are keys other than `expect_name`'s.
+pub fn resign(
+  key_file: &Path,
+  validity: Option<chrono::Duration>,
+  base64: bool,
+  expect_name: Option<&str>,
+) -> Result<(), CliError> {
+  let key = keyfile::load_expected_key_file(key_file, expect_name)?;
+  let record = read_record(io::stdin())?;
+  let output = resign_record(&record, &key, validity)?;
+
+  write_record(&output, base64)
+}
+
+/// Writes a serialized record to stdout, as binary or base64 text.
+fn write_record(record: &[u8], base64: bool) -> Result<(), CliError> {
+  let mut stdout = io::stdout();
+  let written = if base64 {
+    writeln!(stdout, "{}", base64::encode(record))
+  } else {
+    stdout.write_all(record)
+  };
+  written
+    .and_then(|_| stdout.flush())
+    .report()
+    .change_context(CliError::Other)
+}
+
+/// Fetches `name`'s record from the DHT through the Kubo RPC API at `kubo_api`, validates it
+/// against the name, and writes it to `output`, or to stdout.
+///
+/// Expired records are still written, with a warning, since they're what the DHT holds.
+pub async fn fetch(
+  name: &Name,
+  kubo_api: &Url,
+  output: Option<&Path>,
+  base64: bool,
+  network: Network,
+) -> Result<(), CliError> {
+  network.check("record fetch")?;
+  let kubo = KuboClient::new(kubo_api.clone());
+  let record = kubo
+    .get_record(name)
+    .await
+    .attach_printable(InvolvedName(name.to_string()))?;
+
+  let revision = Revision::from_signed_bytes(name, &record)
+    .change_context(CliError::Resolve)
+    .attach_printable(format!(
+      "the record Kubo at {} returned is invalid",
+      kubo.api()
+    ))
+    .attach_printable(InvolvedName(name.to_string()))?;
+  if revision.is_expired_at(clock::now()) {
+    log::warn!(
+      "the DHT record for {} expired at {}",
+      name,
+      revision.validity_string()
+    );
+  }
+  log::info!(
+    "DHT record for {}: sequence {}, value {}",
+    name,
+    revision.sequence(),
+    revision.value()
+  );
+
+  match output {
+    Some(path) => {
+      let bytes = if base64 {
+        format!("{}\n", base64::encode(&record)).into_bytes()
+      } else {
+        record
+      };
+      platform::atomic_write(path, &bytes)
+        .report()
+        .change_context(CliError::Other)
+        .attach_printable(format!("output file: {}", path.display()))
+    }
+    None => write_record(&record, base64),
+  }
+}
+
+/// Lists the record archives in `paths` (see [archive::expand_path]) sorted by `order`, as a table
+/// or as JSON, optionally only those of `name` or those that have expired.
+///
+/// The records are validated, but invalid ones are still listed, as unverified, and files that
+/// aren't archives are listed with an error.
+pub fn ls(
+  paths: &[PathBuf],
+  name: Option<&Name>,
+  expired_only: bool,
+  order: ListOrder,
+  json: bool,
+) -> Result<(), CliError> {
+  let mut files = vec![];
+  for path in paths {
+    for file in archive::expand_path(path)? {
+      if !files.contains(&file) {
+        files.push(file);
+      }
+    }
+  }
+  let now = clock::now();
+  let name = name.map(Name::to_string);
+  let mut listings: Vec<ArchiveListing> = files
+    .into_iter()
+    .map(ArchiveListing::read)
+    .filter(|listing| name.is_none() || listing.name == name)
+    .filter(|listing| !expired_only || listing.is_expired(now))
+    .collect();
+  archive::sort_listings(&mut listings, order);
+
+  if json {
+    let listings: Vec<_> = listings
+      .iter()
+      .map(|listing| listing_json(listing, now))
+      .collect();
+    println!("{}", serde_json::Value::Array(listings));
+    return Ok(());
+  }
+  let mut table = Table::new([
+    "PATH",
+    "NAME",
+    "SEQ",
+    "VALIDITY",
+    "VERIFIED",
+    "COUNTERSIGNER",
+    "VALUE",
+    "ERROR",
+  ])
+  .truncate(&[0, 1, 5, 6, 7]);
+  for listing in &listings {
+    let revision = listing.revision.as_ref();
+    let validity_style = if listing.is_expired(now) {
+      Style::Bad
+    } else {
+      Style::Plain
+    };
+    table.row([
+      listing.path.display().to_string().into(),
+      listing.name.as_deref().unwrap_or("-").into(),
+      revision
+        .map_or("-".to_string(), |revision| revision.sequence().to_string())
+        .into(),
+      Cell::styled(
+        revision.map_or("-".to_string(), |revision| {
+          timefmt::absolute(*revision.validity())
+        }),
+        validity_style,
+      ),
+      if listing.verified {
+        Cell::styled("yes", Style::Good)
+      } else {
+        Cell::styled("no", Style::Bad)
+      },
+      listing.countersigner.as_deref().unwrap_or("-").into(),
+      revision
+        .map_or("-".to_string(), |revision| revision.value().to_string())
+        .into(),
+      Cell::styled(listing.error.clone().unwrap_or_default(), Style::Bad),
+    ]);
+  }
+  table.print();
+  Ok(())
+}
+
+fn listing_json(listing: &ArchiveListing, now: chrono::DateTime<chrono::Utc>) -> serde_json::Value {
+  let revision = listing.revision.as_ref();
+  serde_json::json!({
+    "path": listing.path.display().to_string(),
+    "name": listing.name,
+    "sequence": revision.map(Revision::sequence),
+    "value": revision.map(Revision::value),
+    "value_length": revision.map(Revision::value_len),
+    "validity": revision.map(Revision::validity_string),
+    "expired": revision.map(|revision| revision.is_expired_at(now)),
+    "verified": listing.verified,
+    "countersigner": listing.countersigner,
+    "error": listing.error,
+  })
+}
+
+fn resign_record(
+  record: &[u8],
+  key: &WritableName,
+  validity: Option<chrono::Duration>,
+) -> Result<Vec<u8>, CliError> {
+  let name = key.to_name();
+  let previous = inspect::inspect(record, Some(&name), inspect::Verification::Strict, false)
+    .attach_printable("refusing to resign a record that doesn't belong to the key")
+    .attach_printable(InvolvedName(name.to_string()))?
+    .revision;
+  log::debug!(
+    "Resigning record with sequence {} and value {}",
+    previous.sequence(),
+    previous.value()
+  );
+
+  let mut builder = RevisionBuilder::next(&previous, "")
+    .clock(clock::shared())
+    .value_bytes(previous.value_bytes())
+    .with_defaults(&config::defaults());
+  if let Some(validity) = validity {
+    builder = builder.validity_for(validity);
+  }
+  let revision = builder.build().change_context(CliError::Other)?;
+  revision
+    .to_signed_bytes(key)
+    .change_context(CliError::Other)
+    .attach_printable(InvolvedName(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use w3name::Revision;
+
+  use super::*;
+
+  #[test]
+  fn resigned_records_supersede_the_input() {
+    let key = WritableName::new();
+    let previous = RevisionBuilder::new(&key.to_name(), "/ipfs/bafyresign")
+      .ttl(chrono::Duration::minutes(10))
+      .validity_for(chrono::Duration::hours(1))
+      .build()
+      .unwrap();
+    let record = previous.to_signed_bytes(&key).unwrap();
+
+    for input in [record.clone(), base64::encode(&record).into_bytes()] {
+      let output = resign_record(
+        &decode_record(input),
+        &key,
+        Some(chrono::Duration::days(365)),
+      )
+      .unwrap();
+      let resigned = Revision::from_signed_bytes(&key.to_name(), &output).unwrap();
+      assert_eq!(resigned.value(), previous.value());
+      assert_eq!(resigned.ttl(), previous.ttl());
+      assert_eq!(resigned.sequence(), 1);
+      assert!(resigned.supersedes(&previous));
+    }
+  }
+
+  #[test]
+  fn resigning_keeps_binary_values() {
+    let key = WritableName::new();
+    let value = vec![0x00, 0xff, 0xfe, b'/'];
+    let record = RevisionBuilder::new(&key.to_name(), "")
+      .value_bytes(value.clone())
+      .build()
+      .unwrap()
+      .to_signed_bytes(&key)
+      .unwrap();
+
+    let output = resign_record(&record, &key, None).unwrap();
+    let resigned = Revision::from_signed_bytes(&key.to_name(), &output).unwrap();
+    assert_eq!(resigned.value_bytes(), &value[..]);
+    assert_eq!(resigned.sequence(), 1);
+  }
+
+  #[test]
+  fn refuses_records_of_other_keys() {
+    let key = WritableName::new();
+    let other = WritableName::new();
+    let record = Revision::v0(&other.to_name(), "/ipfs/bafyother")
+      .to_signed_bytes(&other)
+      .unwrap();
+    let err = resign_record(&record, &key, None).unwrap_err();
+    assert!(format!("{:?}", err).contains("refusing to resign"));
+  }
+}
@@ -0,0 +1,162 @@
+//! How long a resolved revision has been live, for `resolve --max-record-age` and
+//! `--warn-record-age`.
+//!
+//! Records don't say when they were signed, and a long validity says nothing about how recently
+//! the publisher re-signed, so the age is approximated by when this machine first saw the
+//! revision's sequence number. Single-name resolves that use the cache note each sequence they
+//! see in the cache directory (see [RecordStore::note_seen](w3name::RecordStore::note_seen)), so
+//! the age is how long the sequence has been current as far as this machine knows:
+//!
+//! - a name resolved for the first time, or after the cache was cleared, has an age of zero, so
+//!   the check passes;
+//! - the age is never longer than the true one, unless the clock is wrong or the publisher
+//!   re-signed a revision without incrementing its sequence number;
+//! - an older sequence than the one seen before also has an age of zero, so use
+//!   `--verify-against` to catch rollbacks.
+
+use std::{error::Error, fmt::Display, time::Duration};
+
+use chrono::{DateTime, Utc};
+use error_stack::{report, Result};
+use w3name::Revision;
+
+use crate::{cache::RecordCache, timefmt, CliError};
+
+/// The resolved revision has been live for longer than `--max-record-age`.
+#[derive(Debug)]
+pub struct RecordTooOld;
+
+impl Display for RecordTooOld {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "resolved record has been live for too long")
+  }
+}
+
+impl Error for RecordTooOld {}
+
+/// How long a revision may have been live before `resolve` warns or fails.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AgeLimits {
+  pub warn: Option<Duration>,
+  pub max: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+  Fresh,
+  Warn,
+  TooOld,
+}
+
+impl AgeLimits {
+  pub fn is_empty(&self) -> bool {
+    self.warn.is_none() && self.max.is_none()
+  }
+
+  /// Judges a revision that has been live for `live_for`. Reaching a limit is fine: only going
+  /// past it counts.
+  pub fn judge(&self, live_for: Duration) -> Verdict {
+    if self.max.is_some_and(|max| live_for > max) {
+      Verdict::TooOld
+    } else if self.warn.is_some_and(|warn| live_for > warn) {
+      Verdict::Warn
+    } else {
+      Verdict::Fresh
+    }
+  }
+}
+
+/// Notes `revision` as seen `now` in `cache`, and checks how long it has been live against
+/// `limits`, logging a warning past `limits.warn` and failing with [RecordTooOld] past
+/// `limits.max`.
+///
+/// Returns when the revision was first seen, along with the verdict.
+pub fn check(
+  cache: &RecordCache,
+  revision: &Revision,
+  limits: &AgeLimits,
+  now: DateTime<Utc>,
+) -> Result<(DateTime<Utc>, Verdict), CliError> {
+  let first_seen = cache.first_seen(revision.name(), revision.sequence(), now)?;
+  // a first-seen time in the future means the clock went back, so the revision is new
+  let live_for = (now - first_seen).to_std().unwrap_or_default();
+  let verdict = limits.judge(live_for);
+  let describe = || {
+    format!(
+      "sequence {} of {} was first seen {}",
+      revision.sequence(),
+      revision.name(),
+      timefmt::moment(first_seen, now)
+    )
+  };
+  match verdict {
+    Verdict::Fresh => log::debug!("{}", describe()),
+    Verdict::Warn => log::warn!(
+      "{}, more than --warn-record-age ({}) ago",
+      describe(),
+      humantime::format_duration(limits.warn.unwrap_or_default())
+    ),
+    Verdict::TooOld => {
+      return Err(
+        report!(RecordTooOld)
+          .attach_printable(describe())
+          .attach_printable(format!(
+            "--max-record-age is {}",
+            humantime::format_duration(limits.max.unwrap_or_default())
+          ))
+          .change_context(CliError::Resolve),
+      )
+    }
+  }
+  Ok((first_seen, verdict))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::Duration as ChronoDuration;
+  use w3name::WritableName;
+
+  const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+  #[test]
+  fn passes_warns_and_fails() {
+    let dir = std::env::temp_dir().join(format!("w3name-record-age-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let cache = RecordCache::at(dir.clone());
+    let name = WritableName::new().to_name();
+    let revision = Revision::v0(&name, "/ipfs/aged").increment("/ipfs/aged");
+    let limits = AgeLimits {
+      warn: Some(7 * DAY),
+      max: Some(30 * DAY),
+    };
+    let now = Utc::now();
+
+    // first seen 3 days ago
+    cache
+      .first_seen(&name, revision.sequence(), now - ChronoDuration::days(3))
+      .unwrap();
+    assert_eq!(
+      check(&cache, &revision, &limits, now).unwrap(),
+      (now - ChronoDuration::days(3), Verdict::Fresh)
+    );
+    assert_eq!(
+      check(&cache, &revision, &limits, now + ChronoDuration::days(10))
+        .unwrap()
+        .1,
+      Verdict::Warn
+    );
+    let err_report = check(&cache, &revision, &limits, now + ChronoDuration::days(30)).unwrap_err();
+    assert!(err_report.contains::<RecordTooOld>());
+
+    // a newer sequence starts over
+    let next = revision.increment("/ipfs/newer");
+    let later = now + ChronoDuration::days(30);
+    assert_eq!(
+      check(&cache, &next, &limits, later).unwrap(),
+      (later, Verdict::Fresh)
+    );
+    assert_eq!(AgeLimits::default().judge(3650 * DAY), Verdict::Fresh);
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,234 @@
+//! The `key rotate` subcommand: moves a name's value to a new key, and redirects followers of the
+//! old name to the new one.
+//!
+//! Each step is idempotent, so a rotation that failed halfway can be resumed by running the same
+//! command again: the new key file is reused, and values that are already published are skipped.
+
+use std::{path::Path, sync::Arc};
+
+use error_stack::{report, Report, Result, ResultExt};
+use w3name::{Name, PublishOptions, PublishOutcome, Resolver, W3NameClient, WritableName};
+
+use crate::{
+  cache::RecordCache,
+  config,
+  error_output::InvolvedName,
+  key_json::{self, KeyJson, RotateJson},
+  keyfile::{self, KeyFormat},
+  service::{self, Network},
+  CliError,
+};
+
+/// Options for [rotate].
+pub struct RotateOptions<'a> {
+  /// The key file of the name being retired.
+  pub old_key: &'a Path,
+
+  /// The key file of the new name. A new key is created here if the file doesn't exist.
+  pub new_key: &'a Path,
+
+  /// The value to publish under the new name, instead of the old name's current value.
+  pub value: Option<&'a str>,
+
+  /// Whether to publish a final record under the old name that points to the new one.
+  pub redirect: bool,
+
+  /// How long the redirect record stays valid.
+  pub tombstone_validity: chrono::Duration,
+
+  /// Whether network access is allowed. Rotating fails immediately if it isn't.
+  pub network: Network,
+
+  /// Print the result as JSON.
+  pub json: bool,
+}
+
+/// The steps of a rotation that have completed so far, reported if a later step fails.
+#[derive(Default)]
+struct Progress {
+  steps: Vec<String>,
+}
+
+impl Progress {
+  fn done(&mut self, step: String) {
+    log::info!("{}", step);
+    self.steps.push(step);
+  }
+
+  /// Attaches the completed steps to `err_report`, so the user knows where the rotation stopped.
+  fn attach(&self, err_report: Report<CliError>) -> Report<CliError> {
+    let completed = if self.steps.is_empty() {
+      "none".to_string()
+    } else {
+      self.steps.join("; ")
+    };
+    err_report
+      .attach_printable(format!("completed steps: {}", completed))
+      .attach_printable("run the same command again to resume; completed steps are skipped")
+  }
+}
+
+/// The value of the redirect record published under the old name.
+fn redirect_value(new_name: &Name) -> String {
+  format!("/ipns/{}", new_name)
+}
+
+pub async fn rotate(opts: RotateOptions<'_>) -> Result<(), CliError> {
+  let client = service::client(opts.network, "key rotate")?;
+  let fallback_resolvers = service::fallback_resolvers(opts.network, "key rotate")?;
+  let mut progress = Progress::default();
+
+  let old = keyfile::load_key_file(opts.old_key)?;
+  let created = !opts.new_key.exists();
+  let new = if !created {
+    keyfile::load_key_file(opts.new_key)?
+  } else {
+    let new = WritableName::new();
+    keyfile::write_key_file(opts.new_key, &new)?;
+    progress.done(format!(
+      "created new key {} in {}",
+      new,
+      opts.new_key.display()
+    ));
+    new
+  };
+  if old.to_name() == new.to_name() {
+    return Err(report!(CliError::Usage).attach_printable("the old and new keys are the same"));
+  }
+
+  let value = match opts.value {
+    Some(value) => value.to_string(),
+    None => current_value(&client, &old.to_name(), &new.to_name())
+      .await
+      .map_err(|err_report| progress.attach(err_report))?,
+  };
+
+  let outcome = client
+    .publish_value(&new, &value, publish_options(&fallback_resolvers, None))
+    .await
+    .change_context(CliError::Publish)
+    .attach_printable(InvolvedName(new.to_string()))
+    .map_err(|err_report| progress.attach(err_report))?;
+  RecordCache::forget(&new.to_name());
+  progress.done(describe(&outcome, &new));
+
+  if opts.redirect {
+    let redirect = redirect_value(&new.to_name());
+    let outcome = client
+      .publish_value(
+        &old,
+        &redirect,
+        publish_options(&fallback_resolvers, Some(opts.tombstone_validity)),
+      )
+      .await
+      .change_context(CliError::Publish)
+      .attach_printable(InvolvedName(old.to_string()))
+      .map_err(|err_report| progress.attach(err_report))?;
+    RecordCache::forget(&old.to_name());
+    progress.done(describe(&outcome, &old));
+  }
+
+  if opts.json {
+    key_json::print(&RotateJson {
+      old: KeyJson::new(&old, Some(opts.old_key), None),
+      new: KeyJson::new(
+        &new,
+        Some(opts.new_key),
+        created.then_some(KeyFormat::Binary),
+      ),
+      created,
+      value_length: value.len(),
+      value,
+      redirect: opts.redirect.then(|| redirect_value(&new.to_name())),
+    });
+    return Ok(());
+  }
+
+  println!("{} -> {}", old, new);
+  println!("value: {}", value);
+  if opts.redirect {
+    println!("redirect: {} now points to /ipns/{}", old, new);
+  } else {
+    println!("redirect: skipped");
+  }
+  Ok(())
+}
+
+/// Resolves the value to carry over from the old name.
+///
+/// If the old name already redirects to the new one (because an earlier run got that far), the
+/// value is taken from the new name instead.
+async fn current_value(client: &W3NameClient, old: &Name, new: &Name) -> Result<String, CliError> {
+  let revision = client
+    .resolve(old)
+    .await
+    .change_context(CliError::Resolve)
+    .attach_printable(InvolvedName(old.to_string()))
+    .attach_printable("pass --value to rotate without resolving the old name")?;
+  if revision.value() != redirect_value(new) {
+    return Ok(revision.value().to_string());
+  }
+
+  log::info!("{} already redirects to {}", old, new);
+  let revision = client
+    .resolve(new)
+    .await
+    .change_context(CliError::Resolve)
+    .attach_printable(InvolvedName(new.to_string()))?;
+  Ok(revision.value().to_string())
+}
+
+/// Skips republishing unchanged values, so that re-running a rotation is harmless.
+fn publish_options(
+  fallback_resolvers: &[Arc<dyn Resolver>],
+  validity: Option<chrono::Duration>,
+) -> PublishOptions {
+  PublishOptions {
+    skip_unchanged: true,
+    fallback_resolvers: fallback_resolvers.to_vec(),
+    validity,
+    defaults: config::defaults(),
+    ..Default::default()
+  }
+}
+
+fn describe(outcome: &PublishOutcome, name: &WritableName) -> String {
+  match outcome {
+    PublishOutcome::Published(revision) => format!(
+      "published {} under {} (sequence {})",
+      revision.value(),
+      name,
+      revision.sequence()
+    ),
+    PublishOutcome::Unchanged(current) => {
+      format!(
+        "{} was already published under {}",
+        current.revision.value(),
+        name
+      )
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn progress_is_attached_to_errors() {
+    let mut progress = Progress::default();
+    let err = progress.attach(report!(CliError::Resolve));
+    assert!(format!("{:?}", err).contains("completed steps: none"));
+
+    let new = WritableName::new().to_name();
+    progress.done(format!("published {} under new", redirect_value(&new)));
+    progress.done("created new key".to_string());
+    let err = progress.attach(report!(CliError::Publish));
+    let debug = format!("{:?}", err);
+    assert!(debug.contains(&format!(
+      "completed steps: published /ipns/{} under new; created new key",
+      new
+    )));
+    assert!(debug.contains("run the same command again to resume"));
+  }
+}
@@ -0,0 +1,201 @@
+//! Deployment gates on a name's sequence number, for pipelines that publish sequence N and must
+//! wait until resolvers serve it before going on:
+//!
+//! - `resolve --require-sequence-at-least <n>` fails with [SEQUENCE_BEHIND] unless the service,
+//!   and every `--gateway` (or `--quorum` of the sources), serves sequence `n` or later;
+//! - `watch --until-sequence <n>` stops once the service does.
+//!
+//! Both compare sequences with [reached]. A source that fails, has no record or an invalid one
+//! counts as not having reached the sequence; only the service's own failures end `resolve` with
+//! their usual exit codes.
+//!
+//! [SEQUENCE_BEHIND]: crate::exit_code::SEQUENCE_BEHIND
+
+use std::{error::Error, fmt::Display, time::Duration};
+
+use error_stack::{report, Result};
+use reqwest::Url;
+use w3name::{Name, Revision};
+
+use crate::{
+  status::{self, ProbeOutcome},
+  CliError,
+};
+
+/// How long each gateway is given to answer.
+const GATEWAY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fewer sources serve the required sequence than `resolve --require-sequence-at-least` needs.
+#[derive(Debug)]
+pub struct SequenceBehind;
+
+impl Display for SequenceBehind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "resolved record's sequence is lower than required")
+  }
+}
+
+impl Error for SequenceBehind {}
+
+/// Whether a record with `sequence` meets a requirement of at least `required`.
+pub fn reached(sequence: u64, required: u64) -> bool {
+  sequence >= required
+}
+
+/// `resolve --require-sequence-at-least`, with the gateways to check besides the service, and how
+/// many of the sources must serve the sequence.
+#[derive(Clone, Debug)]
+pub struct SequenceGate {
+  pub required: u64,
+  pub gateways: Vec<Url>,
+  /// How many sources, the service included, must serve the sequence.
+  pub quorum: usize,
+}
+
+impl SequenceGate {
+  /// A gate on the service and `gateways`, all of which must serve `required` unless a `quorum`
+  /// is given, which must be between 1 and the number of sources.
+  pub fn new(
+    required: u64,
+    gateways: Vec<Url>,
+    quorum: Option<usize>,
+  ) -> Result<SequenceGate, CliError> {
+    let sources = gateways.len() + 1;
+    let quorum = quorum.unwrap_or(sources);
+    if quorum == 0 || quorum > sources {
+      return Err(report!(CliError::Usage).attach_printable(format!(
+        "--quorum must be between 1 and the number of sources, {} (the service and {} gateways)",
+        sources,
+        gateways.len()
+      )));
+    }
+    Ok(SequenceGate {
+      required,
+      gateways,
+      quorum,
+    })
+  }
+
+  /// Checks the `resolved` revision from the service (at `endpoint`), and asks each gateway for
+  /// the name's record.
+  pub async fn check(
+    &self,
+    name: &Name,
+    endpoint: &Url,
+    resolved: &Revision,
+  ) -> Result<(), CliError> {
+    let mut sources = vec![Source {
+      description: format!("service {}", endpoint),
+      sequence: Ok(resolved.sequence()),
+    }];
+    let probes = self.gateways.iter().map(|url| async move {
+      let gateway = w3name::GatewayResolver::new(url.clone());
+      let (_, outcome) = status::probe(name, GATEWAY_TIMEOUT, gateway.fetch_record(name)).await;
+      Source {
+        description: format!("gateway {}", url),
+        sequence: match outcome {
+          ProbeOutcome::Found { revision, .. } => Ok(revision.sequence()),
+          ProbeOutcome::NotFound => Err("no record".to_string()),
+          ProbeOutcome::Invalid(message) => Err(format!("invalid record: {}", message)),
+          ProbeOutcome::Failed(message) => Err(format!("failed: {}", message)),
+          ProbeOutcome::TimedOut => Err("timed out".to_string()),
+        },
+      }
+    });
+    sources.extend(futures::future::join_all(probes).await);
+    judge(&sources, self.required, self.quorum)
+  }
+}
+
+/// What a source served: a valid record's sequence, or why it served none.
+#[derive(Debug)]
+struct Source {
+  description: String,
+  sequence: std::result::Result<u64, String>,
+}
+
+/// Passes if at least `quorum` of the `sources` have reached the `required` sequence.
+fn judge(sources: &[Source], required: u64, quorum: usize) -> Result<(), CliError> {
+  let reached_by = sources
+    .iter()
+    .filter(|source| matches!(source.sequence, Ok(sequence) if reached(sequence, required)))
+    .count();
+  for source in sources {
+    log::debug!("{}: {:?}", source.description, source.sequence);
+  }
+  if reached_by >= quorum {
+    return Ok(());
+  }
+  let mut err_report = report!(SequenceBehind).attach_printable(format!(
+    "{} of {} sources serve sequence {} or later, {} required",
+    reached_by,
+    sources.len(),
+    required,
+    quorum
+  ));
+  for source in sources {
+    err_report = err_report.attach_printable(match &source.sequence {
+      Ok(sequence) => format!("{}: sequence {}", source.description, sequence),
+      Err(reason) => format!("{}: {}", source.description, reason),
+    });
+  }
+  Err(err_report.change_context(CliError::Resolve))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::exit_code;
+
+  fn source(description: &str, sequence: std::result::Result<u64, &str>) -> Source {
+    Source {
+      description: description.to_string(),
+      sequence: sequence.map_err(str::to_string),
+    }
+  }
+
+  #[test]
+  fn comparator() {
+    assert!(reached(5, 5));
+    assert!(reached(6, 5));
+    assert!(!reached(4, 5));
+    assert!(reached(0, 0));
+  }
+
+  #[test]
+  fn quorum_of_sources() {
+    let sources = [
+      source("service", Ok(5)),
+      source("gateway a", Ok(4)),
+      source("gateway b", Err("timed out")),
+    ];
+    assert!(judge(&sources, 5, 1).is_ok());
+    assert!(judge(&sources, 4, 2).is_ok());
+
+    let err = judge(&sources, 5, 2).unwrap_err();
+    assert_eq!(exit_code::for_report(&err), exit_code::SEQUENCE_BEHIND);
+    let message = format!("{:?}", err);
+    assert!(
+      message.contains("1 of 3 sources serve sequence 5 or later, 2 required"),
+      "{}",
+      message
+    );
+    assert!(message.contains("gateway a: sequence 4"), "{}", message);
+    assert!(message.contains("gateway b: timed out"), "{}", message);
+  }
+
+  #[test]
+  fn quorum_bounds() {
+    let gateway = Url::parse("https://gateway.example").unwrap();
+    assert_eq!(
+      SequenceGate::new(1, vec![gateway.clone()], None)
+        .unwrap()
+        .quorum,
+      2
+    );
+    for quorum in [0, 3] {
+      let err = SequenceGate::new(1, vec![gateway.clone()], Some(quorum)).unwrap_err();
+      assert_eq!(exit_code::for_report(&err), exit_code::USAGE);
+    }
+  }
+}
@@ -0,0 +1,202 @@
+//! The w3name service and gateway used by commands.
+//!
+//! They default to the public w3name service and trustless gateway, and can be pointed elsewhere
+//! (e.g. a self-hosted service, or a mock in tests) with the `W3NAME_ENDPOINT` and `W3NAME_GATEWAY`
+//! environment variables.
+//!
+//! Every helper here that creates an HTTP client takes a [Network], so that nothing reaches the
+//! network with `--offline`.
+//!
+//! Commands that resolve names fall back to the gateways when the service fails, or has no record
+//! for a name, unless `--no-fallback` is given (see [fallback]).
+
+use std::{
+  env,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+};
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use reqwest::Url;
+use w3name::{ChainResolver, GatewayResolver, Resolver, W3NameClient, W3NameClientBuilder};
+
+use crate::{clock, CliError};
+
+const ENDPOINT_VAR: &str = "W3NAME_ENDPOINT";
+const GATEWAY_VAR: &str = "W3NAME_GATEWAY";
+
+/// Set by `--trace-http`, before any client is created.
+static TRACE_HTTP: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--no-fallback`, before any command runs.
+static NO_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+/// Makes clients created by [client] log their HTTP exchanges in full (see
+/// [W3NameClientBuilder::debug_http](w3name::W3NameClientBuilder::debug_http)).
+pub fn trace_http(enabled: bool) {
+  TRACE_HTTP.store(enabled, Ordering::Relaxed);
+}
+
+/// Makes [fallback] return no gateways, so that names are only resolved from the service.
+pub fn disable_fallback(disabled: bool) {
+  NO_FALLBACK.store(disabled, Ordering::Relaxed);
+}
+
+/// Whether commands may access the network. It's denied with the global `--offline` flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+  Allowed,
+  Denied,
+}
+
+impl Network {
+  pub fn from_offline_flag(offline: bool) -> Network {
+    if offline {
+      Network::Denied
+    } else {
+      Network::Allowed
+    }
+  }
+
+  /// Fails if network access is denied. `purpose` says what needed the network, e.g. "resolve".
+  pub fn check(self, purpose: &str) -> Result<(), CliError> {
+    match self {
+      Network::Allowed => Ok(()),
+      Network::Denied => Err(report!(CliError::Usage).attach_printable(format!(
+        "{} needs network access, which --offline forbids",
+        purpose
+      ))),
+    }
+  }
+}
+
+/// Returns a client for the w3name service at `$W3NAME_ENDPOINT`, or the public service.
+pub fn client(network: Network, purpose: &str) -> Result<W3NameClient, CliError> {
+  client_builder(network, purpose)?
+    .build()
+    .change_context(CliError::Other)
+}
+
+/// Returns a builder for the client that [client] returns, for commands that tune it.
+pub fn client_builder(network: Network, purpose: &str) -> Result<W3NameClientBuilder, CliError> {
+  network.check(purpose)?;
+  let endpoint = url_from_env(ENDPOINT_VAR)?.unwrap_or_else(w3name::default_endpoint);
+  Ok(
+    W3NameClient::builder(endpoint)
+      .debug_http(TRACE_HTTP.load(Ordering::Relaxed))
+      .clock(clock::shared()),
+  )
+}
+
+/// Returns a resolver for the trustless gateways in `$W3NAME_GATEWAY`, or the public gateways.
+///
+/// `$W3NAME_GATEWAY` is a comma-separated list of URLs, tried in order, or `none` to never ask a
+/// gateway.
+pub fn gateway(network: Network, purpose: &str) -> Result<GatewayResolver, CliError> {
+  network.check(purpose)?;
+  Ok(match env::var(GATEWAY_VAR) {
+    Ok(list) if !list.is_empty() => GatewayResolver::with_gateways(parse_gateways(&list)?),
+    _ => GatewayResolver::default(),
+  })
+}
+
+/// Returns the gateways to try when the service fails to resolve a name, or has no record for it:
+/// those of [gateway], unless `--no-fallback` was given or `$W3NAME_GATEWAY` is `none`.
+pub fn fallback(network: Network, purpose: &str) -> Result<Option<GatewayResolver>, CliError> {
+  if NO_FALLBACK.load(Ordering::Relaxed) {
+    network.check(purpose)?;
+    return Ok(None);
+  }
+  let gateway = gateway(network, purpose)?;
+  Ok((!gateway.gateway_status().is_empty()).then_some(gateway))
+}
+
+/// Returns the [fallback] gateways as the fallback resolvers of a publish.
+pub fn fallback_resolvers(
+  network: Network,
+  purpose: &str,
+) -> Result<Vec<Arc<dyn Resolver>>, CliError> {
+  Ok(
+    fallback(network, purpose)?
+      .into_iter()
+      .map(|gateway| Arc::new(gateway) as Arc<dyn Resolver>)
+      .collect(),
+  )
+}
+
+/// Returns a resolver that asks `client`, and then the [fallback] gateways.
+pub fn resolver(
+  client: &W3NameClient,
+  network: Network,
+  purpose: &str,
+) -> Result<ChainResolver, CliError> {
+  let chain = ChainResolver::new(Arc::new(client.clone()));
+  Ok(match fallback(network, purpose)? {
+    Some(gateway) => chain.with_fallback(Arc::new(gateway)),
+    None => chain,
+  })
+}
+
+/// Returns a plain HTTP client, e.g. for fetching manifests.
+pub fn http_client(network: Network, purpose: &str) -> Result<reqwest::Client, CliError> {
+  network.check(purpose)?;
+  Ok(reqwest::Client::new())
+}
+
+fn url_from_env(var: &str) -> Result<Option<Url>, CliError> {
+  match env::var(var) {
+    Ok(url) if !url.is_empty() => parse_url(var, &url).map(Some),
+    _ => Ok(None),
+  }
+}
+
+fn parse_gateways(list: &str) -> Result<Vec<Url>, CliError> {
+  if list.trim() == "none" {
+    return Ok(vec![]);
+  }
+  list
+    .split(',')
+    .map(|url| parse_url(GATEWAY_VAR, url.trim()))
+    .collect()
+}
+
+fn parse_url(var: &str, url: &str) -> Result<Url, CliError> {
+  Url::parse(url)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!("${} is not a valid URL: {}", var, url))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_urls() {
+    let url = parse_url(ENDPOINT_VAR, "http://127.0.0.1:8787").unwrap();
+    assert_eq!(url.as_str(), "http://127.0.0.1:8787/");
+
+    let err = parse_url(ENDPOINT_VAR, "not a url").unwrap_err();
+    assert!(format!("{:?}", err).contains("$W3NAME_ENDPOINT is not a valid URL: not a url"));
+
+    let gateways = parse_gateways("http://127.0.0.1:8080, https://ipfs.io").unwrap();
+    assert_eq!(gateways.len(), 2);
+    assert_eq!(gateways[1].as_str(), "https://ipfs.io/");
+    assert!(parse_gateways("none").unwrap().is_empty());
+    assert!(parse_gateways("http://127.0.0.1:8080,").is_err());
+  }
+
+  #[test]
+  fn offline_denies_clients() {
+    assert!(client(Network::Allowed, "resolve").is_ok());
+    assert!(http_client(Network::Allowed, "fetching a manifest").is_ok());
+
+    let err = client(Network::Denied, "resolve").err().unwrap();
+    assert!(matches!(err.current_context(), CliError::Usage));
+    assert!(format!("{:?}", err).contains("resolve needs network access, which --offline forbids"));
+    assert!(gateway(Network::Denied, "publish").is_err());
+    assert!(http_client(Network::Denied, "fetching a manifest").is_err());
+  }
+}
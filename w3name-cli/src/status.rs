@@ -0,0 +1,629 @@
+//! The `status` subcommand: a one-shot health summary of a name.
+//!
+//! The record is fetched from the service and from each configured gateway concurrently, each
+//! probe with its own timeout, and every record is validated (see [inspect]). With
+//! `--no-fallback`, only the service is asked. The results are
+//! compared to reach a verdict:
+//!
+//! - FAIL if no source has a valid record, if the newest record has expired, or if two sources
+//!   have different records with the same sequence number, which resolvers can't choose between.
+//! - WARN if any source fails, times out, has no record or an invalid one, or is stale (has an
+//!   older record than the newest), if the newest record expires soon, or if it has no V2
+//!   signature.
+//! - PASS otherwise.
+
+use std::{
+  fmt::Display,
+  time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use error_stack::Result;
+use reqwest::Url;
+use w3name::{error::APIError, ipns::SignatureVersion, Name, Revision};
+
+use crate::{
+  clock, exit_code, inspect, manifest,
+  service::{self, Network},
+  table::{Cell, Layout, Style, Table},
+  timefmt, CliError,
+};
+
+/// Options for [status].
+pub struct StatusOptions {
+  /// How long each source is given to answer.
+  pub timeout: Duration,
+
+  /// A record that expires sooner than this is reported as a warning.
+  pub expiry_warning: chrono::Duration,
+
+  pub json: bool,
+
+  /// Whether network access is allowed. Fails immediately if it isn't.
+  pub network: Network,
+}
+
+/// How healthy a name is, from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+  Pass,
+  Warn,
+  Fail,
+}
+
+impl Verdict {
+  /// The process exit code for the verdict.
+  pub fn exit_code(self) -> i32 {
+    match self {
+      Verdict::Pass => 0,
+      Verdict::Warn => exit_code::HEALTH_WARNING,
+      Verdict::Fail => exit_code::UNHEALTHY,
+    }
+  }
+
+  fn style(self) -> Style {
+    match self {
+      Verdict::Pass => Style::Good,
+      Verdict::Warn => Style::Warning,
+      Verdict::Fail => Style::Bad,
+    }
+  }
+}
+
+impl Display for Verdict {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Verdict::Pass => write!(f, "PASS"),
+      Verdict::Warn => write!(f, "WARN"),
+      Verdict::Fail => write!(f, "FAIL"),
+    }
+  }
+}
+
+/// What a source returned for the name.
+#[derive(Debug)]
+pub enum ProbeOutcome {
+  /// A valid record, of `size` bytes.
+  Found {
+    revision: Box<Revision>,
+    size: usize,
+    signature: SignatureVersion,
+  },
+  NotFound,
+  Invalid(String),
+  Failed(String),
+  TimedOut,
+}
+
+/// The result of asking one source for the name's record.
+#[derive(Debug)]
+pub struct Probe {
+  /// "service" or "gateway".
+  pub kind: &'static str,
+  pub url: Url,
+  pub elapsed: Duration,
+  pub outcome: ProbeOutcome,
+}
+
+impl Probe {
+  fn source(&self) -> String {
+    format!("{} {}", self.kind, self.url)
+  }
+
+  fn revision(&self) -> Option<&Revision> {
+    match &self.outcome {
+      ProbeOutcome::Found { revision, .. } => Some(revision.as_ref()),
+      _ => None,
+    }
+  }
+}
+
+/// Something wrong with the name, and how bad it is.
+#[derive(Debug)]
+pub struct Finding {
+  pub verdict: Verdict,
+  pub message: String,
+}
+
+/// The health of a name: what each source returned, and what's wrong.
+#[derive(Debug)]
+pub struct StatusReport {
+  pub name: Name,
+  pub probes: Vec<Probe>,
+  pub findings: Vec<Finding>,
+}
+
+/// Probes every source for `name` and prints the report, returning its verdict.
+pub async fn status(name: &Name, opts: &StatusOptions) -> Result<Verdict, CliError> {
+  let client = service::client(opts.network, "status")?;
+  let gateways: Vec<Url> = service::fallback(opts.network, "status")?
+    .map(|gateway| gateway.gateway_status())
+    .unwrap_or_default()
+    .into_iter()
+    .map(|status| status.url)
+    .collect();
+
+  let mut tasks = vec![];
+  let service_name = name.clone();
+  let timeout = opts.timeout;
+  tasks.push((
+    "service",
+    client.endpoint().clone(),
+    tokio::spawn(
+      async move { probe(&service_name, timeout, client.fetch_record(&service_name)).await },
+    ),
+  ));
+  for url in gateways {
+    let gateway = w3name::GatewayResolver::new(url.clone());
+    let gateway_name = name.clone();
+    tasks.push((
+      "gateway",
+      url,
+      tokio::spawn(async move {
+        probe(&gateway_name, timeout, gateway.fetch_record(&gateway_name)).await
+      }),
+    ));
+  }
+
+  let mut probes = vec![];
+  for (kind, url, task) in tasks {
+    let (elapsed, outcome) = task
+      .await
+      .map_err(|join_err| error_stack::report!(CliError::Other).attach_printable(join_err))?;
+    probes.push(Probe {
+      kind,
+      url,
+      elapsed,
+      outcome,
+    });
+  }
+
+  let now = clock::now();
+  let report = evaluate(name, probes, now, opts.expiry_warning);
+  if opts.json {
+    println!("{}", report.to_json());
+  } else {
+    for line in report.lines(now, Layout::stdout()) {
+      println!("{}", line);
+    }
+  }
+  Ok(report.verdict())
+}
+
+/// Awaits `fetch`, giving up after `timeout`, and validates the record it returns.
+pub async fn probe(
+  name: &Name,
+  timeout: Duration,
+  fetch: impl std::future::Future<Output = Result<Vec<u8>, w3name::error::ClientError>>,
+) -> (Duration, ProbeOutcome) {
+  let started = Instant::now();
+  let outcome = match tokio::time::timeout(timeout, fetch).await {
+    Err(_) => ProbeOutcome::TimedOut,
+    Ok(Err(err_report)) => {
+      log::debug!("Probe for {} failed: {:?}", name, err_report);
+      let not_found = err_report
+        .downcast_ref::<APIError>()
+        .is_some_and(|err| err.status_code == 404);
+      if not_found {
+        ProbeOutcome::NotFound
+      } else {
+        ProbeOutcome::Failed(manifest::describe(&err_report))
+      }
+    }
+    Ok(Ok(record)) => {
+      match inspect::inspect(&record, Some(name), inspect::Verification::Strict, false) {
+        Ok(inspection) => ProbeOutcome::Found {
+          revision: Box::new(inspection.revision),
+          size: record.len(),
+          signature: inspection.signatures.validated_with,
+        },
+        Err(err_report) => {
+          log::debug!("Invalid record for {}: {:?}", name, err_report);
+          ProbeOutcome::Invalid(manifest::describe(&err_report))
+        }
+      }
+    }
+  };
+  (started.elapsed(), outcome)
+}
+
+/// Compares what the sources returned, as of `now`.
+pub fn evaluate(
+  name: &Name,
+  probes: Vec<Probe>,
+  now: DateTime<Utc>,
+  expiry_warning: chrono::Duration,
+) -> StatusReport {
+  let mut findings = vec![];
+  let mut finding = |verdict, message: String| findings.push(Finding { verdict, message });
+
+  for probe in &probes {
+    match &probe.outcome {
+      ProbeOutcome::Found { .. } => {}
+      ProbeOutcome::NotFound => finding(Verdict::Warn, format!("{} has no record", probe.source())),
+      ProbeOutcome::Invalid(message) => finding(
+        Verdict::Warn,
+        format!("{} has an invalid record: {}", probe.source(), message),
+      ),
+      ProbeOutcome::Failed(message) => finding(
+        Verdict::Warn,
+        format!("{} failed: {}", probe.source(), message),
+      ),
+      ProbeOutcome::TimedOut => finding(Verdict::Warn, format!("{} timed out", probe.source())),
+    }
+  }
+
+  match newest(&probes) {
+    None => finding(Verdict::Fail, "no source has a valid record".to_string()),
+    Some(newest) => {
+      for probe in &probes {
+        let Some(revision) = probe.revision() else {
+          continue;
+        };
+        if revision.sequence() < newest.sequence() {
+          finding(
+            Verdict::Warn,
+            format!(
+              "{} is stale: it has sequence {}, the newest is {}",
+              probe.source(),
+              revision.sequence(),
+              newest.sequence()
+            ),
+          );
+        } else if revision != newest {
+          finding(
+            Verdict::Fail,
+            format!(
+              "{} disagrees: it has a different record with sequence {}",
+              probe.source(),
+              revision.sequence()
+            ),
+          );
+        }
+      }
+
+      let validity = *newest.validity();
+      if validity <= now {
+        finding(
+          Verdict::Fail,
+          format!("the newest record {}", timefmt::expiry(validity, now)),
+        );
+      } else if validity - now < expiry_warning {
+        finding(
+          Verdict::Warn,
+          format!("the newest record {}", timefmt::expiry(validity, now)),
+        );
+      }
+
+      let v1_only = probes.iter().any(|probe| {
+        probe.revision() == Some(newest)
+          && matches!(
+            probe.outcome,
+            ProbeOutcome::Found {
+              signature: SignatureVersion::V1,
+              ..
+            }
+          )
+      });
+      if v1_only {
+        finding(
+          Verdict::Warn,
+          "the newest record has no V2 signature, which current IPNS implementations require"
+            .to_string(),
+        );
+      }
+    }
+  }
+
+  StatusReport {
+    name: name.clone(),
+    probes,
+    findings,
+  }
+}
+
+/// The valid record with the highest sequence number. Of records with the same sequence, the one
+/// from the earliest probe wins, so the service's record is preferred.
+fn newest(probes: &[Probe]) -> Option<&Revision> {
+  probes
+    .iter()
+    .filter_map(Probe::revision)
+    .reduce(|newest, revision| {
+      if revision.sequence() > newest.sequence() {
+        revision
+      } else {
+        newest
+      }
+    })
+}
+
+impl StatusReport {
+  /// The worst verdict of the findings, or PASS if there are none.
+  pub fn verdict(&self) -> Verdict {
+    self
+      .findings
+      .iter()
+      .map(|finding| finding.verdict)
+      .max()
+      .unwrap_or(Verdict::Pass)
+  }
+
+  /// The newest valid record any source has.
+  pub fn newest(&self) -> Option<&Revision> {
+    newest(&self.probes)
+  }
+
+  /// The report as text: the verdict, a table of the sources, and a line per finding, with times
+  /// relative to `now`.
+  pub fn lines(&self, now: DateTime<Utc>, layout: Layout) -> Vec<String> {
+    let verdict = self.verdict();
+    let mut lines = vec![format!(
+      "{}: {}",
+      self.name,
+      verdict.style().paint(&verdict.to_string(), layout.color)
+    )];
+    if let Some(newest) = self.newest() {
+      lines.push(format!(
+        "newest: sequence {}, {}",
+        newest.sequence(),
+        timefmt::expiry(*newest.validity(), now)
+      ));
+    }
+    let mut table = Table::new([
+      "SOURCE",
+      "STATUS",
+      "SEQ",
+      "VALUE",
+      "SIZE",
+      "SIGNATURE",
+      "TIME",
+      "ERROR",
+    ])
+    .truncate(&[0, 3, 7]);
+    for probe in &self.probes {
+      let elapsed = format!("{} ms", probe.elapsed.as_millis());
+      let missing = |status: Cell, error: &str| -> [Cell; 8] {
+        [
+          probe.source().into(),
+          status,
+          "-".into(),
+          "-".into(),
+          "-".into(),
+          "-".into(),
+          elapsed.as_str().into(),
+          error.into(),
+        ]
+      };
+      let row = match &probe.outcome {
+        ProbeOutcome::Found {
+          revision,
+          size,
+          signature,
+        } => [
+          probe.source().into(),
+          Cell::styled("found", Style::Good),
+          revision.sequence().to_string().into(),
+          revision.value().into(),
+          size.to_string().into(),
+          signature.to_string().into(),
+          elapsed.as_str().into(),
+          "".into(),
+        ],
+        ProbeOutcome::NotFound => missing(Cell::styled("no record", Style::Warning), ""),
+        ProbeOutcome::Invalid(message) => {
+          missing(Cell::styled("invalid record", Style::Bad), message)
+        }
+        ProbeOutcome::Failed(message) => missing(Cell::styled("failed", Style::Bad), message),
+        ProbeOutcome::TimedOut => missing(Cell::styled("timed out", Style::Bad), ""),
+      };
+      table.row(row);
+    }
+    lines.extend(table.render(layout));
+    for finding in &self.findings {
+      lines.push(format!(
+        "{}: {}",
+        finding
+          .verdict
+          .style()
+          .paint(&finding.verdict.to_string(), layout.color),
+        finding.message
+      ));
+    }
+    lines
+  }
+
+  pub fn to_json(&self) -> serde_json::Value {
+    let sources: Vec<_> = self
+      .probes
+      .iter()
+      .map(|probe| {
+        let mut json = serde_json::json!({
+          "kind": probe.kind,
+          "url": probe.url.to_string(),
+          "elapsed_ms": probe.elapsed.as_millis() as u64,
+        });
+        let status = match &probe.outcome {
+          ProbeOutcome::Found {
+            revision,
+            size,
+            signature,
+          } => {
+            json["sequence"] = revision.sequence().into();
+            json["value"] = revision.value().into();
+            json["value_length"] = revision.value_len().into();
+            json["validity"] = revision.validity_string().into();
+            json["size"] = (*size).into();
+            json["signature"] = signature.to_string().into();
+            "found"
+          }
+          ProbeOutcome::NotFound => "not_found",
+          ProbeOutcome::Invalid(message) => {
+            json["error"] = message.as_str().into();
+            "invalid"
+          }
+          ProbeOutcome::Failed(message) => {
+            json["error"] = message.as_str().into();
+            "failed"
+          }
+          ProbeOutcome::TimedOut => "timed_out",
+        };
+        json["status"] = status.into();
+        json
+      })
+      .collect();
+    let findings: Vec<_> = self
+      .findings
+      .iter()
+      .map(|finding| {
+        serde_json::json!({
+          "verdict": finding.verdict.to_string().to_lowercase(),
+          "message": finding.message,
+        })
+      })
+      .collect();
+    let newest = self.newest();
+    serde_json::json!({
+      "name": self.name.to_string(),
+      "verdict": self.verdict().to_string().to_lowercase(),
+      "sequence": newest.map(|revision| revision.sequence()),
+      "value": newest.map(|revision| revision.value()),
+      "value_length": newest.map(|revision| revision.value_len()),
+      "validity": newest.map(|revision| revision.validity_string()),
+      "sources": sources,
+      "findings": findings,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use w3name::WritableName;
+
+  fn now() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2025-06-28T12:00:00Z")
+      .unwrap()
+      .with_timezone(&Utc)
+  }
+
+  fn probe(kind: &'static str, outcome: ProbeOutcome) -> Probe {
+    Probe {
+      kind,
+      url: Url::parse("http://127.0.0.1:8080").unwrap(),
+      elapsed: Duration::from_millis(5),
+      outcome,
+    }
+  }
+
+  fn found(revision: &Revision) -> ProbeOutcome {
+    ProbeOutcome::Found {
+      revision: Box::new(revision.clone()),
+      size: 300,
+      signature: SignatureVersion::V2,
+    }
+  }
+
+  fn verdict(name: &Name, probes: Vec<Probe>) -> Verdict {
+    evaluate(name, probes, now(), chrono::Duration::days(1)).verdict()
+  }
+
+  #[test]
+  fn verdicts() {
+    let name = WritableName::new().to_name();
+    let validity = now() + chrono::Duration::days(30);
+    let first =
+      Revision::v0_with_validity(&name, "/ipfs/a", validity, chrono::Duration::minutes(5));
+    let second = first.increment_with_validity("/ipfs/b", validity);
+
+    assert_eq!(
+      verdict(
+        &name,
+        vec![
+          probe("service", found(&second)),
+          probe("gateway", found(&second))
+        ]
+      ),
+      Verdict::Pass
+    );
+
+    // a stale or failing source is a warning, as long as another has the record
+    assert_eq!(
+      verdict(
+        &name,
+        vec![
+          probe("service", found(&second)),
+          probe("gateway", found(&first))
+        ]
+      ),
+      Verdict::Warn
+    );
+    assert_eq!(
+      verdict(
+        &name,
+        vec![
+          probe("service", found(&second)),
+          probe("gateway", ProbeOutcome::TimedOut)
+        ]
+      ),
+      Verdict::Warn
+    );
+
+    // different records with the same sequence can't both be right
+    let conflicting = first.increment_with_validity("/ipfs/c", validity);
+    assert_eq!(
+      verdict(
+        &name,
+        vec![
+          probe("service", found(&second)),
+          probe("gateway", found(&conflicting))
+        ]
+      ),
+      Verdict::Fail
+    );
+
+    assert_eq!(
+      verdict(
+        &name,
+        vec![
+          probe("service", ProbeOutcome::NotFound),
+          probe("gateway", ProbeOutcome::Failed("oops".into()))
+        ]
+      ),
+      Verdict::Fail
+    );
+  }
+
+  #[test]
+  fn expiry_verdicts() {
+    let name = WritableName::new().to_name();
+    let revision = |validity| {
+      Revision::v0_with_validity(&name, "/ipfs/a", validity, chrono::Duration::minutes(5))
+    };
+
+    let soon = revision(now() + chrono::Duration::hours(2));
+    let report = evaluate(
+      &name,
+      vec![probe("service", found(&soon))],
+      now(),
+      chrono::Duration::days(1),
+    );
+    assert_eq!(report.verdict(), Verdict::Warn);
+    assert!(report.findings[0].message.contains("expires in 2 hours"));
+
+    let expired = revision(now() - chrono::Duration::hours(2));
+    assert_eq!(
+      verdict(&name, vec![probe("service", found(&expired))]),
+      Verdict::Fail
+    );
+
+    let v1_only = ProbeOutcome::Found {
+      revision: Box::new(revision(now() + chrono::Duration::days(30))),
+      size: 200,
+      signature: SignatureVersion::V1,
+    };
+    assert_eq!(
+      verdict(&name, vec![probe("service", v1_only)]),
+      Verdict::Warn
+    );
+  }
+}
@@ -0,0 +1,348 @@
+//! Tables in text output, like `record ls` and `resolve --manifest`: aligned columns under a
+//! header, with statuses in color on a terminal, and long cells shortened to fit its width.
+//!
+//! Color is used when stdout is a terminal and `NO_COLOR` isn't set, unless `--color` (or
+//! `--no-color`) says otherwise. Columns that may be shortened are middle-truncated with `…`,
+//! keeping both ends of names and paths recognizable, once the table is wider than the terminal
+//! (or `$COLUMNS`). `--wide` turns truncation off; JSON output is never truncated.
+
+use std::{
+  env,
+  io::{self, IsTerminal},
+  sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+use clap::ValueEnum;
+
+/// Columns are never truncated to less than this many characters, or their header's width.
+const MIN_TRUNCATED_WIDTH: usize = 12;
+
+const ELLIPSIS: char = '…';
+
+/// When to color output, from `--color`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+  /// When stdout is a terminal and `NO_COLOR` isn't set.
+  Auto,
+  Always,
+  Never,
+}
+
+/// The `ColorChoice`, as set by [configure], encoded as its index.
+static COLOR: AtomicU8 = AtomicU8::new(0);
+/// Whether `--wide` was given.
+static WIDE: AtomicBool = AtomicBool::new(false);
+
+/// Sets how tables are printed from now on, from `--color` and `--wide`.
+pub fn configure(color: ColorChoice, wide: bool) {
+  let index = match color {
+    ColorChoice::Auto => 0,
+    ColorChoice::Always => 1,
+    ColorChoice::Never => 2,
+  };
+  COLOR.store(index, Ordering::Relaxed);
+  WIDE.store(wide, Ordering::Relaxed);
+}
+
+/// How a cell is colored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Style {
+  #[default]
+  Plain,
+  /// Verified, passing: green.
+  Good,
+  /// Warnings: yellow.
+  Warning,
+  /// Expired, invalid, failing: red.
+  Bad,
+}
+
+impl Style {
+  fn ansi_code(self) -> Option<&'static str> {
+    match self {
+      Style::Plain => None,
+      Style::Good => Some("32"),
+      Style::Warning => Some("33"),
+      Style::Bad => Some("31"),
+    }
+  }
+
+  /// Returns `text` in this style, in color if `color` is set.
+  pub fn paint(self, text: &str, color: bool) -> String {
+    match self.ansi_code() {
+      Some(code) if color => format!("\x1b[{}m{}\x1b[0m", code, text),
+      _ => text.to_string(),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+  text: String,
+  style: Style,
+}
+
+impl Cell {
+  pub fn styled(text: impl Into<String>, style: Style) -> Cell {
+    Cell {
+      text: text.into(),
+      style,
+    }
+  }
+}
+
+impl From<String> for Cell {
+  fn from(text: String) -> Cell {
+    Cell::styled(text, Style::Plain)
+  }
+}
+
+impl From<&str> for Cell {
+  fn from(text: &str) -> Cell {
+    Cell::styled(text, Style::Plain)
+  }
+}
+
+/// How a table is rendered: whether in color, and how wide it may be, if it's limited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+  pub color: bool,
+  pub width: Option<usize>,
+}
+
+impl Layout {
+  /// The layout for stdout, following `--color`, `--wide`, `NO_COLOR` and the terminal's width.
+  pub fn stdout() -> Layout {
+    let is_terminal = io::stdout().is_terminal();
+    let color = match COLOR.load(Ordering::Relaxed) {
+      1 => true,
+      2 => false,
+      _ => is_terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+    };
+    let width = if WIDE.load(Ordering::Relaxed) {
+      None
+    } else {
+      terminal_width(is_terminal)
+    };
+    Layout { color, width }
+  }
+}
+
+/// Returns the width to fit tables in: `$COLUMNS` if it's set, as it is by shells and in tests,
+/// or else the terminal's width if stdout is one. Output that isn't for a terminal isn't limited.
+fn terminal_width(is_terminal: bool) -> Option<usize> {
+  if let Some(columns) = env::var("COLUMNS")
+    .ok()
+    .and_then(|columns| columns.parse().ok())
+  {
+    return Some(columns);
+  }
+  if !is_terminal {
+    return None;
+  }
+  terminal_size::terminal_size().map(|(terminal_size::Width(width), _)| usize::from(width))
+}
+
+/// A table with `N` columns.
+pub struct Table<const N: usize> {
+  header: [&'static str; N],
+  truncatable: [bool; N],
+  rows: Vec<[Cell; N]>,
+}
+
+impl<const N: usize> Table<N> {
+  pub fn new(header: [&'static str; N]) -> Table<N> {
+    Table {
+      header,
+      truncatable: [false; N],
+      rows: vec![],
+    }
+  }
+
+  /// Lets the columns at `columns` be truncated to fit the layout's width, e.g. names, paths and
+  /// values, but not numbers or statuses.
+  pub fn truncate(mut self, columns: &[usize]) -> Table<N> {
+    for &column in columns {
+      self.truncatable[column] = true;
+    }
+    self
+  }
+
+  pub fn row(&mut self, cells: [Cell; N]) {
+    self.rows.push(cells);
+  }
+
+  /// Prints the table to stdout (see [Layout::stdout]).
+  pub fn print(&self) {
+    for line in self.render(Layout::stdout()) {
+      println!("{}", line);
+    }
+  }
+
+  /// Renders the header and the rows as lines, with columns two spaces apart, and without
+  /// trailing spaces.
+  pub fn render(&self, layout: Layout) -> Vec<String> {
+    let widths = self.widths(layout.width);
+    let header = self.header.map(Cell::from);
+    std::iter::once(&header)
+      .chain(&self.rows)
+      .map(|row| {
+        let cells: Vec<String> = row
+          .iter()
+          .zip(widths)
+          .map(|(cell, width)| {
+            let text = truncate_middle(&cell.text, width);
+            let padding = width - text.chars().count();
+            format!(
+              "{}{}",
+              cell.style.paint(&text, layout.color),
+              " ".repeat(padding)
+            )
+          })
+          .collect();
+        cells.join("  ").trim_end().to_string()
+      })
+      .collect()
+  }
+
+  /// Returns the width of each column: its widest cell, narrowing the widest truncatable
+  /// columns until the table fits in `max_width`, if it can.
+  fn widths(&self, max_width: Option<usize>) -> [usize; N] {
+    let mut widths = self.header.map(|header| header.chars().count());
+    for row in &self.rows {
+      for (width, cell) in widths.iter_mut().zip(row) {
+        *width = (*width).max(cell.text.chars().count());
+      }
+    }
+    let Some(max_width) = max_width else {
+      return widths;
+    };
+    let min_widths = self
+      .header
+      .map(|header| header.chars().count().max(MIN_TRUNCATED_WIDTH));
+    let total = |widths: &[usize; N]| widths.iter().sum::<usize>() + 2 * N.saturating_sub(1);
+    while total(&widths) > max_width {
+      let widest = (0..N)
+        .filter(|&column| self.truncatable[column] && widths[column] > min_widths[column])
+        .max_by_key(|&column| (widths[column], std::cmp::Reverse(column)));
+      match widest {
+        Some(column) => widths[column] -= 1,
+        None => break,
+      }
+    }
+    widths
+  }
+}
+
+/// Shortens `text` to `width` characters, if it's longer, by replacing its middle with `…`.
+pub fn truncate_middle(text: &str, width: usize) -> String {
+  let length = text.chars().count();
+  if length <= width {
+    return text.to_string();
+  }
+  if width == 0 {
+    return String::new();
+  }
+  let tail = (width - 1) / 2;
+  let head = width - 1 - tail;
+  let mut truncated: String = text.chars().take(head).collect();
+  truncated.push(ELLIPSIS);
+  truncated.extend(text.chars().skip(length - tail));
+  truncated
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NAME: &str = "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu";
+
+  fn table() -> Table<4> {
+    let mut table = Table::new(["NAME", "SEQ", "VERIFIED", "VALUE"]).truncate(&[0, 3]);
+    table.row([
+      NAME.into(),
+      "3".into(),
+      Cell::styled("yes", Style::Good),
+      "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".into(),
+    ]);
+    table.row([
+      "k51short".into(),
+      "12".into(),
+      Cell::styled("no", Style::Bad),
+      "".into(),
+    ]);
+    table
+  }
+
+  #[test]
+  fn wide_layout() {
+    let lines = table().render(Layout {
+      color: false,
+      width: None,
+    });
+    assert_eq!(
+      lines,
+      [
+        "NAME                                                            SEQ  VERIFIED  VALUE",
+        "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu  3    yes       /ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi",
+        "k51short                                                        12   no",
+      ]
+    );
+  }
+
+  #[test]
+  fn truncated_to_the_width() {
+    let lines = table().render(Layout {
+      color: false,
+      width: Some(80),
+    });
+    for line in &lines {
+      assert!(line.chars().count() <= 80, "{}", line);
+    }
+    assert_eq!(
+      lines,
+      [
+        "NAME                             SEQ  VERIFIED  VALUE",
+        "k51qzi5uqu5dka3…pt1y9y5i8v8xwvu  3    yes       /ipfs/bafybeigdy…3oclgtqy55fbzdi",
+        "k51short                         12   no",
+      ]
+    );
+  }
+
+  #[test]
+  fn too_narrow_to_fit() {
+    // columns don't shrink below their minimum, so the table is as narrow as it gets
+    let lines = table().render(Layout {
+      color: false,
+      width: Some(20),
+    });
+    assert_eq!(
+      lines,
+      [
+        "NAME          SEQ  VERIFIED  VALUE",
+        "k51qzi…8xwvu  3    yes       /ipfs/…fbzdi",
+        "k51short      12   no",
+      ]
+    );
+  }
+
+  #[test]
+  fn colors() {
+    let lines = table().render(Layout {
+      color: true,
+      width: None,
+    });
+    assert!(lines[0].starts_with("NAME "));
+    assert!(lines[1].contains("  \x1b[32myes\x1b[0m       /ipfs/"));
+    assert!(lines[2].ends_with("  \x1b[31mno\x1b[0m"));
+  }
+
+  #[test]
+  fn middle_truncation() {
+    assert_eq!(truncate_middle("abcdefghij", 10), "abcdefghij");
+    assert_eq!(truncate_middle("abcdefghij", 5), "ab…ij");
+    assert_eq!(truncate_middle("abcdefghij", 4), "ab…j");
+    assert_eq!(truncate_middle("abcdefghij", 1), "…");
+    assert_eq!(truncate_middle("ääääää", 3), "ä…ä");
+  }
+}
@@ -0,0 +1,148 @@
+//! How timestamps are shown in text output: absolute, in UTC (the default) or local time with
+//! `--local`, together with how long ago or from now they are, e.g.
+//! `expires in 3 days (2025-07-01T12:00:00Z)`.
+//!
+//! JSON output doesn't go through this module: it always has RFC 3339 timestamps in UTC.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::{DateTime, Duration, Local, SecondsFormat, Utc};
+
+/// Whether timestamps are shown in local time, set from `--local`.
+static LOCAL_TIME: AtomicBool = AtomicBool::new(false);
+
+/// Shows timestamps in local time rather than UTC from now on.
+pub fn use_local_time(enabled: bool) {
+  LOCAL_TIME.store(enabled, Ordering::Relaxed);
+}
+
+/// Renders `time` in UTC, or local time with `--local`, to the second.
+pub fn absolute(time: DateTime<Utc>) -> String {
+  absolute_in(time, LOCAL_TIME.load(Ordering::Relaxed))
+}
+
+fn absolute_in(time: DateTime<Utc>, local: bool) -> String {
+  if local {
+    time
+      .with_timezone(&Local)
+      .to_rfc3339_opts(SecondsFormat::Secs, false)
+  } else {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+  }
+}
+
+/// Describes how far `time` is from `now` in its largest whole unit: "in 3 days", "2 hours ago",
+/// "in 42 seconds", or "just now" if it's less than a second away.
+pub fn relative(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+  let delta = time - now;
+  let amount = amount(if delta < Duration::zero() {
+    -delta
+  } else {
+    delta
+  });
+  if delta.num_seconds() == 0 {
+    "just now".to_string()
+  } else if delta > Duration::zero() {
+    format!("in {}", amount)
+  } else {
+    format!("{} ago", amount)
+  }
+}
+
+/// Renders `time` relative to `now`, followed by the absolute time: "2 hours ago
+/// (2025-06-28T10:00:00Z)".
+pub fn moment(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+  format!("{} ({})", relative(time, now), absolute(time))
+}
+
+/// Describes an event at `time` with the verb for whether it's still ahead of `now` or already
+/// happened: "expires in 3 days (2025-07-01T12:00:00Z)" or "expired 2 hours ago
+/// (2025-06-28T10:00:00Z)".
+pub fn event(time: DateTime<Utc>, now: DateTime<Utc>, future: &str, past: &str) -> String {
+  let verb = if time > now { future } else { past };
+  format!("{} {}", verb, moment(time, now))
+}
+
+/// Describes when a record with this validity expires, or expired.
+pub fn expiry(validity: DateTime<Utc>, now: DateTime<Utc>) -> String {
+  event(validity, now, "expires", "expired")
+}
+
+/// Formats a non-negative duration in its largest whole unit, e.g. "3 days" or "1 minute".
+fn amount(duration: Duration) -> String {
+  let seconds = duration.num_seconds();
+  let (count, unit) = if seconds < 60 {
+    (seconds, "second")
+  } else if seconds < 60 * 60 {
+    (duration.num_minutes(), "minute")
+  } else if seconds < 24 * 60 * 60 {
+    (duration.num_hours(), "hour")
+  } else if seconds < 365 * 24 * 60 * 60 {
+    (duration.num_days(), "day")
+  } else {
+    (duration.num_days() / 365, "year")
+  };
+  if count == 1 {
+    format!("1 {}", unit)
+  } else {
+    format!("{} {}s", count, unit)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn now() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2025-06-28T12:00:00Z")
+      .unwrap()
+      .with_timezone(&Utc)
+  }
+
+  #[test]
+  fn relative_times() {
+    let at = |delta: Duration| relative(now() + delta, now());
+    assert_eq!(at(Duration::zero()), "just now");
+    assert_eq!(at(Duration::milliseconds(400)), "just now");
+    assert_eq!(at(Duration::seconds(1)), "in 1 second");
+    assert_eq!(at(Duration::seconds(42)), "in 42 seconds");
+    assert_eq!(at(-Duration::seconds(59)), "59 seconds ago");
+    assert_eq!(at(Duration::seconds(90)), "in 1 minute");
+    assert_eq!(at(-Duration::minutes(59)), "59 minutes ago");
+    assert_eq!(at(-Duration::minutes(150)), "2 hours ago");
+    assert_eq!(at(Duration::hours(23)), "in 23 hours");
+    assert_eq!(at(Duration::days(3) + Duration::hours(5)), "in 3 days");
+    assert_eq!(at(-Duration::days(364)), "364 days ago");
+    assert_eq!(at(Duration::days(365)), "in 1 year");
+    assert_eq!(at(Duration::days(800)), "in 2 years");
+  }
+
+  #[test]
+  fn expiry_events() {
+    assert_eq!(
+      expiry(now() + Duration::days(3), now()),
+      "expires in 3 days (2025-07-01T12:00:00Z)"
+    );
+    assert_eq!(
+      expiry(now() - Duration::hours(2), now()),
+      "expired 2 hours ago (2025-06-28T10:00:00Z)"
+    );
+    assert_eq!(
+      event(now() - Duration::seconds(5), now(), "signed", "signed"),
+      "signed 5 seconds ago (2025-06-28T11:59:55Z)"
+    );
+  }
+
+  #[test]
+  fn absolute_times() {
+    let time = now() + Duration::milliseconds(250);
+    assert_eq!(absolute_in(time, false), "2025-06-28T12:00:00Z");
+    let local = absolute_in(time, true);
+    assert_eq!(
+      DateTime::parse_from_rfc3339(&local).unwrap(),
+      now(),
+      "{}",
+      local
+    );
+  }
+}
@@ -0,0 +1,127 @@
+//! Where `publish` gets the value to publish: from `--value`, from a file with
+//! `--value-from-file`, from the output of a command with `--value-from-command`, or from a JSON
+//! document with `--value-json`.
+//!
+//! Values read from a file or a command are meant to be written by a build step, so they're
+//! trimmed and checked: a bare CID becomes an `/ipfs/` path, and anything that isn't an IPFS or
+//! IPNS path is refused rather than published.
+
+use std::{fs, path::PathBuf, process::Stdio};
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use w3name::{json_value, Value};
+
+use crate::{watch, CliError};
+
+pub enum ValueSource {
+  Arg(String),
+  File(PathBuf),
+  Command(String),
+  Json(PathBuf),
+}
+
+impl ValueSource {
+  /// Returns the value, reading the file or running the command if it comes from one.
+  pub async fn read(&self) -> Result<String, CliError> {
+    match self {
+      ValueSource::Arg(value) => Ok(value.clone()),
+      ValueSource::File(path) => {
+        let what = format!("value file {}", path.display());
+        let text = fs::read_to_string(path)
+          .report()
+          .change_context(CliError::Usage)
+          .attach_printable(format!("can't read {}", what))?;
+        normalize(&text, &what)
+      }
+      ValueSource::Command(command) => {
+        let what = format!("the output of `{}`", command);
+        normalize(&run(command).await?, &what)
+      }
+      ValueSource::Json(path) => {
+        let what = format!("JSON value file {}", path.display());
+        let text = fs::read_to_string(path)
+          .report()
+          .change_context(CliError::Usage)
+          .attach_printable(format!("can't read {}", what))?;
+        json_value::canonicalize(&text)
+          .change_context(CliError::Usage)
+          .attach_printable(what)
+      }
+    }
+  }
+}
+
+/// Runs `command` with the system shell and returns its output. The command's stderr goes to ours.
+async fn run(command: &str) -> Result<String, CliError> {
+  log::debug!("Running value command: {}", command);
+
+  let output = watch::shell_command(command)
+    .stdin(Stdio::null())
+    .stderr(Stdio::inherit())
+    .output()
+    .await
+    .report()
+    .change_context(CliError::Publish)
+    .attach_printable(format!("value command: {}", command))?;
+  if !output.status.success() {
+    return Err(
+      report!(CliError::Publish)
+        .attach_printable(format!("value command: {}", command))
+        .attach_printable(format!("the value command failed: {}", output.status)),
+    );
+  }
+  String::from_utf8(output.stdout)
+    .report()
+    .change_context(CliError::Usage)
+    .attach_printable(format!("the output of `{}` isn't UTF-8 text", command))
+}
+
+/// Trims `text` and checks that it's a CID or an IPFS or IPNS path, turning a bare CID into an
+/// `/ipfs/` path. `what` says where the text came from, for errors.
+pub fn normalize(text: &str, what: &str) -> Result<String, CliError> {
+  let value = text.trim();
+  if value.is_empty() {
+    return Err(report!(CliError::Usage).attach_printable(format!("{} is empty", what)));
+  }
+  if value.lines().count() > 1 {
+    return Err(
+      report!(CliError::Usage).attach_printable(format!("{} has more than one line", what)),
+    );
+  }
+
+  let bare_cid = format!("/ipfs/{}", value);
+  if !value.contains('/') && matches!(Value::parse(&bare_cid), Value::Ipfs { .. }) {
+    return Ok(bare_cid);
+  }
+  match Value::parse(value) {
+    Value::Other(_) => Err(report!(CliError::Usage).attach_printable(format!(
+      "{} isn't a CID or an /ipfs/ or /ipns/ path: {}",
+      what, value
+    ))),
+    _ => Ok(value.to_string()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CID: &str = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+  #[test]
+  fn normalizes_values() {
+    let ipfs = format!("/ipfs/{}", CID);
+    assert_eq!(normalize(&format!("{}\n", CID), "file").unwrap(), ipfs);
+    assert_eq!(normalize(&format!("  {}  \n", ipfs), "file").unwrap(), ipfs);
+    assert_eq!(
+      normalize("/ipns/example.com\n", "file").unwrap(),
+      "/ipns/example.com"
+    );
+
+    let err = normalize(" \n", "value file latest-cid").unwrap_err();
+    assert!(format!("{:?}", err).contains("value file latest-cid is empty"));
+    assert!(normalize(&format!("{}\n{}\n", CID, CID), "file").is_err());
+    assert!(normalize("build failed", "file").is_err());
+    assert!(normalize("/ipfs/not-a-cid", "file").is_err());
+  }
+}
@@ -0,0 +1,425 @@
+//! The `watch` subcommand: polls a name and reports whenever its record changes.
+
+use std::{fmt::Display, net::SocketAddr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use error_stack::{report, IntoReport, Report, Result, ResultExt};
+use futures::StreamExt;
+use tokio::process::Command;
+use w3name::{
+  error::ClientError, GatewayResolver, Name, ResolvedRevision, Resolver, Revision, W3NameClient,
+};
+
+use crate::{
+  cache::RecordCache,
+  clock, manifest,
+  metrics::{MetricsServer, WatchMetrics},
+  sequence_gate,
+  service::{self, Network},
+  timefmt, CliError,
+};
+
+/// Options for [watch].
+pub struct WatchOptions<'a> {
+  /// Time to wait between polls.
+  pub interval: Duration,
+
+  /// A path to append to each printed value, e.g. from an `ipns://<name>/path` argument. May be empty.
+  pub path: &'a str,
+
+  /// Shell command to run whenever the sequence number advances.
+  pub exec: Option<&'a str>,
+
+  /// If true, a failing `exec` command stops the watch with an error.
+  pub exec_fatal: bool,
+
+  /// If true, `exec` also runs for the record found by the first poll, not only when the sequence
+  /// advances after it.
+  pub exec_on_start: bool,
+
+  /// If set, Prometheus metrics are served on this address while watching (see [crate::metrics]).
+  pub prometheus: Option<SocketAddr>,
+
+  /// If true, a record with a lower sequence number than one seen before is reported as a change,
+  /// rather than ignored (see [Latest]).
+  pub accept_regression: bool,
+
+  /// If true, each printed value is followed by the record's sequence and expiry.
+  pub long: bool,
+
+  /// If set, watching stops once the record's sequence is at least this (see [sequence_gate]).
+  pub until_sequence: Option<u64>,
+
+  /// Whether network access is allowed. Watching fails immediately if it isn't.
+  pub network: Network,
+}
+
+/// Polls `name` forever, or until `opts.until_sequence` is reached, printing the value each time
+/// the record's sequence number advances.
+///
+/// A record older than one seen before, e.g. from a stale replica behind the service's load
+/// balancer, is logged as a [RegressionDetected] and otherwise ignored, unless
+/// `opts.accept_regression` is set.
+///
+/// A poll that the service fails, or has no record for, asks the gateways instead, unless
+/// `--no-fallback` was given.
+///
+/// Each change carries when its sequence was first seen: as noted in the cache directory, like
+/// `resolve` does, or else when this process saw it.
+///
+/// If `opts.exec` is set, the command is run after each change and awaited before the next poll,
+/// so executions never overlap. Changes that happen while the command is running are picked up
+/// by the next poll, which means several quick updates may result in a single execution for the latest one.
+/// The record found by the first poll isn't a change, so the command doesn't run for it, unless
+/// `opts.exec_on_start` is set.
+pub async fn watch(name: &Name, opts: WatchOptions<'_>) -> Result<(), CliError> {
+  let client = service::client(opts.network, "watch")?;
+  let fallback = service::fallback(opts.network, "watch")?;
+  let cache = RecordCache::open_writable();
+  let metrics = WatchMetrics::default();
+  let server = opts
+    .prometheus
+    .map(|addr| MetricsServer::start(addr, metrics.clone()))
+    .transpose()?;
+
+  let res = poll(
+    &client,
+    fallback.as_ref(),
+    cache.as_ref(),
+    name,
+    &opts,
+    &metrics,
+  )
+  .await;
+  if let Some(server) = server {
+    server.shutdown().await;
+  }
+  res
+}
+
+async fn poll(
+  client: &W3NameClient,
+  fallback: Option<&GatewayResolver>,
+  cache: Option<&RecordCache>,
+  name: &Name,
+  opts: &WatchOptions<'_>,
+  metrics: &WatchMetrics,
+) -> Result<(), CliError> {
+  let mut latest = Latest::new(opts.accept_regression);
+  // every poll is yielded, since the metrics count unchanged records too
+  let polls = client.watch(
+    name,
+    w3name::WatchOptions {
+      interval: opts.interval,
+      yield_unchanged: true,
+      ..Default::default()
+    },
+  );
+  futures::pin_mut!(polls);
+
+  while let Some(poll) = polls.next().await {
+    let poll = match (poll, fallback) {
+      (Err(err_report), Some(gateway)) => poll_fallback(gateway, name, err_report).await,
+      (poll, _) => poll,
+    };
+    match poll {
+      Ok(resolved) => {
+        let observed = latest.observe(resolved.into_revision());
+        if let Some(regression) = &observed.regression {
+          metrics.regressed(name);
+          log::warn!("{}", regression);
+        }
+        let revision = latest.revision().expect("a revision was just observed");
+        metrics.resolved(revision);
+        if observed.changed {
+          let now = clock::now();
+          let first_seen = first_seen(cache, revision, now);
+          let value = crate::join_path(revision.value(), opts.path);
+          if opts.long {
+            println!(
+              "{}  sequence {}  {}  first seen {}",
+              value,
+              revision.sequence(),
+              timefmt::expiry(*revision.validity(), now),
+              timefmt::moment(first_seen, now)
+            );
+          } else {
+            println!("{}", value);
+          }
+
+          let exec = opts
+            .exec
+            .filter(|_| !observed.initial || opts.exec_on_start);
+          if let Some(command) = exec {
+            if let Err(err_report) = run_exec_hook(command, revision, first_seen).await {
+              if opts.exec_fatal {
+                return Err(err_report);
+              }
+              log::error!("{err_report:?}");
+            }
+          }
+        }
+        if let Some(required) = opts.until_sequence {
+          if sequence_gate::reached(revision.sequence(), required) {
+            log::info!("{} reached sequence {}", name, revision.sequence());
+            return Ok(());
+          }
+        }
+      }
+
+      Err(err_report) => {
+        let err_report = err_report.change_context(CliError::Watch);
+        metrics.failed(name, &err_report);
+        log::warn!("failed to resolve {}: {err_report:?}", name);
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Notes `revision` as seen `now` in the `cache`, and returns when its sequence was first seen.
+/// Without a usable cache, that's `now`, as the change was just seen.
+fn first_seen(
+  cache: Option<&RecordCache>,
+  revision: &Revision,
+  now: DateTime<Utc>,
+) -> DateTime<Utc> {
+  let noted = cache.map(|cache| cache.first_seen(revision.name(), revision.sequence(), now));
+  match noted {
+    Some(Ok(first_seen)) => first_seen,
+    Some(Err(err_report)) => {
+      log::debug!("Unable to note the record's sequence: {:?}", err_report);
+      now
+    }
+    None => now,
+  }
+}
+
+/// Asks the `gateway` for the record of `name` after the service failed a poll with
+/// `err_report`, which is returned if the gateways fail too.
+async fn poll_fallback(
+  gateway: &GatewayResolver,
+  name: &Name,
+  err_report: Report<ClientError>,
+) -> Result<ResolvedRevision, ClientError> {
+  match gateway.resolve(name).await {
+    Ok(resolved) => {
+      log::info!(
+        "The service couldn't resolve {}, but {} did",
+        name,
+        crate::describe_source_url(&resolved)
+      );
+      Ok(resolved)
+    }
+    Err(gateway_err) => {
+      log::debug!("Gateways failed: {:?}", gateway_err);
+      Err(err_report.attach_printable(format!(
+        "the gateways failed too: {}",
+        manifest::describe(&gateway_err)
+      )))
+    }
+  }
+}
+
+/// A resolved record with a lower sequence number than a record resolved earlier.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegressionDetected {
+  pub name: String,
+  /// The highest sequence number seen so far.
+  pub seen: u64,
+  /// The sequence number of the record just resolved.
+  pub received: u64,
+}
+
+impl Display for RegressionDetected {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "sequence of {} went backwards: seen {}, received {}",
+      self.name, self.seen, self.received
+    )
+  }
+}
+
+/// The result of [Latest::observe].
+#[derive(Debug, PartialEq, Eq)]
+struct Observed {
+  /// Whether the latest revision changed, and should be reported.
+  changed: bool,
+  /// Whether this is the first revision observed, so the sequence didn't advance from an earlier one.
+  initial: bool,
+  regression: Option<RegressionDetected>,
+}
+
+/// Tracks the latest revision of a watched name.
+///
+/// The latest revision is the one with the highest sequence number seen, so a poll answered by a
+/// stale replica doesn't undo a change. If regressions are accepted, the latest revision is
+/// simply the last one resolved.
+struct Latest {
+  revision: Option<Revision>,
+  accept_regression: bool,
+}
+
+impl Latest {
+  fn new(accept_regression: bool) -> Self {
+    Latest {
+      revision: None,
+      accept_regression,
+    }
+  }
+
+  fn revision(&self) -> Option<&Revision> {
+    self.revision.as_ref()
+  }
+
+  fn observe(&mut self, revision: Revision) -> Observed {
+    let seen = self.revision.as_ref().map(Revision::sequence);
+    let received = revision.sequence();
+    let regression = seen
+      .filter(|seen| received < *seen)
+      .map(|seen| RegressionDetected {
+        name: revision.name().to_string(),
+        seen,
+        received,
+      });
+    let changed = match seen {
+      None => true,
+      Some(seen) => received > seen || (received < seen && self.accept_regression),
+    };
+    if changed {
+      self.revision = Some(revision);
+    }
+    Observed {
+      changed,
+      initial: seen.is_none(),
+      regression,
+    }
+  }
+}
+
+/// Runs `command` with the system shell, exposing the revision to it through
+/// the `W3NAME_VALUE`, `W3NAME_SEQUENCE` and `W3NAME_NAME` environment variables, and when its
+/// sequence was first seen through `W3NAME_FIRST_SEEN_AT`, as an RFC 3339 timestamp.
+pub async fn run_exec_hook(
+  command: &str,
+  revision: &Revision,
+  first_seen: DateTime<Utc>,
+) -> Result<(), CliError> {
+  log::debug!("Running exec hook: {}", command);
+
+  let status = shell_command(command)
+    .env("W3NAME_VALUE", revision.value())
+    .env("W3NAME_SEQUENCE", revision.sequence().to_string())
+    .env("W3NAME_NAME", revision.name().to_string())
+    .env("W3NAME_FIRST_SEEN_AT", first_seen.to_rfc3339())
+    .status()
+    .await
+    .report()
+    .change_context(CliError::Watch)
+    .attach_printable(format!("command: {}", command))?;
+
+  if status.success() {
+    Ok(())
+  } else {
+    Err(
+      report!(CliError::Watch)
+        .attach_printable(format!("command: {}", command))
+        .attach_printable(format!("exec command failed: {}", status)),
+    )
+  }
+}
+
+#[cfg(unix)]
+pub fn shell_command(command: &str) -> Command {
+  let mut cmd = Command::new("sh");
+  cmd.arg("-c").arg(command);
+  cmd
+}
+
+#[cfg(windows)]
+pub fn shell_command(command: &str) -> Command {
+  let mut cmd = Command::new("cmd");
+  cmd.arg("/C").arg(command);
+  cmd
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use std::fs;
+  use w3name::WritableName;
+
+  #[test]
+  fn regressions_are_ignored_unless_accepted() {
+    let name = WritableName::new().to_name();
+    let v0 = Revision::v0(&name, "/ipfs/old");
+    let v1 = v0.increment("/ipfs/new");
+    let regression = Some(RegressionDetected {
+      name: name.to_string(),
+      seen: 1,
+      received: 0,
+    });
+
+    let mut latest = Latest::new(false);
+    let observed = latest.observe(v1.clone());
+    assert!(observed.changed && observed.initial);
+    for _ in 0..3 {
+      let observed = latest.observe(v0.clone());
+      assert_eq!(
+        observed,
+        Observed {
+          changed: false,
+          initial: false,
+          regression: regression.clone()
+        }
+      );
+      assert_eq!(latest.revision(), Some(&v1));
+      assert!(!latest.observe(v1.clone()).changed);
+    }
+
+    let mut latest = Latest::new(true);
+    latest.observe(v1.clone());
+    let observed = latest.observe(v0.clone());
+    assert!(observed.changed);
+    assert_eq!(observed.regression, regression);
+    assert_eq!(latest.revision(), Some(&v0));
+    assert!(latest.observe(v1).changed);
+  }
+
+  #[tokio::test]
+  async fn exec_hook_env() {
+    let name = WritableName::new().to_name();
+    let revision = Revision::v0(
+      &name,
+      "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi",
+    )
+    .increment("/ipfs/new value");
+
+    let out = std::env::temp_dir().join(format!("w3name-exec-hook-{}.env", name));
+    let command = format!("env > '{}'", out.display());
+    let first_seen = DateTime::parse_from_rfc3339("2025-06-01T12:00:00Z")
+      .unwrap()
+      .with_timezone(&Utc);
+    run_exec_hook(&command, &revision, first_seen)
+      .await
+      .unwrap();
+
+    let env = fs::read_to_string(&out).unwrap();
+    fs::remove_file(&out).unwrap();
+    assert!(env.contains("W3NAME_VALUE=/ipfs/new value\n"));
+    assert!(env.contains("W3NAME_SEQUENCE=1\n"));
+    assert!(env.contains(&format!("W3NAME_NAME={}\n", name)));
+    assert!(env.contains("W3NAME_FIRST_SEEN_AT=2025-06-01T12:00:00+00:00\n"));
+  }
+
+  #[tokio::test]
+  async fn exec_hook_failure() {
+    let name = WritableName::new().to_name();
+    let revision = Revision::v0(&name, "a value");
+    let err = run_exec_hook("exit 3", &revision, Utc::now())
+      .await
+      .unwrap_err();
+    assert!(matches!(err.current_context(), CliError::Watch));
+  }
+}
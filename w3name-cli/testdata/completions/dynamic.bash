@@ -0,0 +1,14 @@
+_w3name_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ ${COMP_CWORD} -eq 2 && ${cur} != -* ]]; then
+        case "${COMP_WORDS[1]}" in
+            resolve|watch)
+                COMPREPLY=( $(compgen -W "$(w3name __complete names 2>/dev/null)" -- "${cur}") )
+                return 0
+                ;;
+        esac
+    fi
+    _w3name "$@"
+}
+
+complete -F _w3name_dynamic -o bashdefault -o default w3name
@@ -0,0 +1 @@
+complete -c w3name -n "__fish_seen_subcommand_from resolve watch" -f -a "(w3name __complete names 2>/dev/null)"
@@ -0,0 +1,2810 @@
+//! End-to-end tests of the `w3name` binary, run against a local mock of the w3name service.
+
+mod support;
+
+use std::time::Duration;
+
+use hyper::StatusCode;
+use support::{free_port, http_get, stdout, MockW3Name, TestEnv};
+
+const VALUE: &str = "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+/// Exit codes, from `src/exit_code.rs`.
+const FAILURE: i32 = 1;
+const USAGE: i32 = 64;
+const NOT_FOUND: i32 = 2;
+const INVALID_RECORD: i32 = 3;
+const SERVICE: i32 = 5;
+const ROLLED_BACK: i32 = 7;
+const NO_ROUTING_RECORD: i32 = 8;
+const OUTDATED_RECORD: i32 = 9;
+const HEALTH_WARNING: i32 = 10;
+const UNHEALTHY: i32 = 11;
+const RECORD_TOO_OLD: i32 = 12;
+const SEQUENCE_BEHIND: i32 = 13;
+
+#[test]
+fn create_writes_a_key_file() {
+  let env = TestEnv::new("create");
+  let (path, name) = env.create_key("site.key");
+  assert!(path.is_file());
+  assert!(name.starts_with("k51"), "{}", name);
+}
+
+#[test]
+fn create_output_paths() {
+  let env = TestEnv::new("create-paths");
+
+  // `~` is the test's home directory, and missing directories are created
+  let output = env
+    .w3name(&["create", "--output", "~/keys/blog.key"])
+    .assert()
+    .success();
+  let key = env.dir.join("keys").join("blog.key");
+  assert!(key.is_file());
+  assert!(stdout(output.get_output()).contains(&key.display().to_string()));
+
+  // an existing directory gets a file named after the key
+  env
+    .w3name(&["create", "--output", "keys"])
+    .assert()
+    .success();
+  let files: Vec<_> = std::fs::read_dir(env.dir.join("keys"))
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+    .collect();
+  assert_eq!(files.len(), 2);
+  assert!(files
+    .iter()
+    .any(|file| file.starts_with("k51") && file.ends_with(".key")));
+
+  // the key file is never overwritten
+  let before = std::fs::read(&key).unwrap();
+  let output = env
+    .w3name(&["create", "--output", "~/keys/blog.key"])
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(
+    stderr.contains(&format!("{} already exists", key.display())),
+    "{}",
+    stderr
+  );
+  assert_eq!(std::fs::read(&key).unwrap(), before);
+
+  let output = env
+    .w3name(&["create", "--no-create-dirs", "--output", "missing/blog.key"])
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(
+    stderr.contains("directory missing doesn't exist"),
+    "{}",
+    stderr
+  );
+  assert!(!env.dir.join("missing").exists());
+}
+
+#[test]
+fn publish_then_resolve() {
+  let env = TestEnv::new("publish-resolve");
+  let (key, name) = env.create_key("site.key");
+
+  let output = env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+  assert_eq!(
+    stdout(output.get_output()).trim(),
+    format!("published new value for key {}: {}", name, VALUE)
+  );
+  assert!(env.service.record(&name).is_some());
+
+  let output = env.w3name(&["resolve", &name]).assert().success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+
+  // a second publish increments the sequence number
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key.to_str().unwrap(),
+      "--value",
+      "/ipfs/new",
+      "--json",
+    ])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["sequence"], 1);
+  assert_eq!(json["previous"]["sequence"], 0);
+  assert_eq!(json["previous"]["resolver"], "w3name");
+
+  let output = env
+    .w3name(&["resolve", "--no-cache", &name])
+    .assert()
+    .success();
+  assert_eq!(stdout(output.get_output()).trim(), "/ipfs/new");
+}
+
+#[test]
+fn publish_signature_modes() {
+  let env = TestEnv::new("publish-signature-mode");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let has_v1_signature = || {
+    let record = env.service.record(&name).unwrap();
+    let output = env
+      .w3name(&["parse", "--name", &name, &record, "--json"])
+      .assert()
+      .success();
+    let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+    json["has_signature_v1"].as_bool().unwrap()
+  };
+
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  assert!(!has_v1_signature());
+
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      "/ipfs/hybrid",
+      "--signature-mode",
+      "hybrid",
+    ])
+    .assert()
+    .success();
+  assert!(has_v1_signature());
+}
+
+#[test]
+fn resolve_unknown_name() {
+  let env = TestEnv::new("resolve-unknown");
+  let (_, name) = env.create_key("site.key");
+  env.w3name(&["resolve", &name]).assert().code(NOT_FOUND);
+}
+
+#[test]
+fn resolve_server_error_then_success() {
+  let env = TestEnv::new("resolve-500");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+
+  env.service.fail_next(1, StatusCode::INTERNAL_SERVER_ERROR);
+  env
+    .w3name(&["--no-fallback", "resolve", "--no-cache", &name])
+    .assert()
+    .code(SERVICE);
+
+  let output = env
+    .w3name(&["resolve", "--no-cache", &name])
+    .assert()
+    .success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+}
+
+#[test]
+fn resolve_rate_limited() {
+  let env = TestEnv::new("resolve-429");
+  let (_, name) = env.create_key("site.key");
+  env.service.set_rate_limited(true);
+
+  let output = env
+    .w3name(&["--errors", "json", "resolve", &name])
+    .assert()
+    .code(SERVICE);
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  let json: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+  assert_eq!(json["http_status"], 429);
+  assert_eq!(json["retryable"], true);
+}
+
+#[test]
+fn usage_errors_follow_the_errors_flag() {
+  let env = TestEnv::new("usage-errors");
+  let output = env
+    .w3name(&["resolve", "--errors", "json"])
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  let json: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+  assert!(json["message"].is_string(), "{}", json);
+
+  // after --, it's an argument, not the flag
+  let output = env
+    .w3name(&["resolve", "--", "--errors=json", "extra"])
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(serde_json::from_str::<serde_json::Value>(stderr.trim()).is_err());
+}
+
+#[test]
+fn resolve_slow_response() {
+  let env = TestEnv::new("resolve-slow");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+
+  env.service.set_delay(Duration::from_millis(500));
+  let output = env
+    .w3name(&["resolve", "--no-cache", &name])
+    .assert()
+    .success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+}
+
+#[test]
+fn resolve_uses_the_cache() {
+  let env = TestEnv::new("resolve-cache");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+
+  env.w3name(&["resolve", &name]).assert().success();
+  let requests = env.service.requests();
+  let output = env.w3name(&["resolve", &name]).assert().success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+  assert_eq!(env.service.requests(), requests);
+
+  // publishing drops the cached record, so the new value is seen right away
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key.to_str().unwrap(),
+      "--value",
+      "/ipfs/next",
+    ])
+    .assert()
+    .success();
+  let output = env.w3name(&["resolve", &name]).assert().success();
+  assert_eq!(stdout(output.get_output()).trim(), "/ipfs/next");
+}
+
+#[test]
+fn resolve_json_includes_provenance() {
+  let env = TestEnv::new("resolve-json");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+
+  let output = env.w3name(&["resolve", "--json", &name]).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["value"], VALUE);
+  assert_eq!(json["source"]["kind"], "w3name");
+  assert_eq!(json["source"]["url"], format!("{}/", env.service.url()));
+  assert_eq!(json["source"]["expiry_checked"], true);
+  assert_eq!(json["from_cache"], false);
+  let fetched_at = timestamp(&json["fetched_at"]);
+
+  // the second resolve is a cache hit, fetched when the first one was
+  let output = env.w3name(&["resolve", "--json", &name]).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["source"]["kind"], "cache");
+  assert_eq!(json["source"]["url"], serde_json::Value::Null);
+  assert_eq!(json["from_cache"], true);
+  assert!(timestamp(&json["fetched_at"]) <= fetched_at);
+}
+
+#[test]
+fn resolve_json_first_seen_at() {
+  let env = TestEnv::new("resolve-first-seen");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  let resolve_json = |args: &[&str]| {
+    let mut resolve = vec!["resolve", "--json"];
+    resolve.extend_from_slice(args);
+    resolve.push(&name);
+    let output = env.w3name(&resolve).assert().success();
+    serde_json::from_str::<serde_json::Value>(&stdout(output.get_output())).unwrap()
+  };
+
+  // without a cache, nothing remembers when the sequence was seen
+  assert_eq!(
+    resolve_json(&["--no-cache"])["first_seen_at"],
+    serde_json::Value::Null
+  );
+
+  let before = chrono::Utc::now();
+  let first = resolve_json(&[]);
+  assert_eq!(first["from_cache"], false);
+  let first_seen = timestamp(&first["first_seen_at"]);
+  assert!(first_seen >= before);
+
+  // a cache hit, or a fresh fetch of the same sequence, keeps the time it was first seen
+  let cached = resolve_json(&[]);
+  assert_eq!(cached["from_cache"], true);
+  assert_eq!(timestamp(&cached["first_seen_at"]), first_seen);
+  let refetched = resolve_json(&["--max-age", "0s"]);
+  assert_eq!(refetched["from_cache"], false);
+  assert_eq!(timestamp(&refetched["first_seen_at"]), first_seen);
+
+  // a new sequence starts over
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/next"])
+    .assert()
+    .success();
+  let next = resolve_json(&[]);
+  assert_eq!(next["sequence"], 1);
+  assert!(timestamp(&next["first_seen_at"]) > first_seen);
+}
+
+fn timestamp(json: &serde_json::Value) -> chrono::DateTime<chrono::FixedOffset> {
+  chrono::DateTime::parse_from_rfc3339(json.as_str().unwrap()).unwrap()
+}
+
+#[test]
+fn parse_published_record() {
+  let env = TestEnv::new("parse");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+  let record = env.service.record(&name).unwrap();
+
+  // ed25519 records don't embed their public key, so the name is needed to validate them
+  env.w3name(&["parse", &record]).assert().failure();
+  let output = env
+    .w3name(&["parse", "--name", &name, &record])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(text.contains(&format!("name: {}", name)), "{}", text);
+  assert!(text.contains(&format!("value: {}", VALUE)), "{}", text);
+
+  // the record can also be piped in
+  let output = env
+    .w3name(&["parse", "--json", "--name", &name])
+    .write_stdin(record)
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["value"], VALUE);
+
+  env
+    .w3name(&["parse", "--name", &name, "bm90IGEgcmVjb3Jk"])
+    .assert()
+    .failure();
+}
+
+#[test]
+fn parse_no_verify() {
+  let env = TestEnv::new("parse-no-verify");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+  let record = base64::decode(env.service.record(&name).unwrap()).unwrap();
+  let mut entry = w3name::ipns::deserialize_ipns_entry(&record).unwrap();
+  entry.signature_v2[0] ^= 0xff;
+  let corrupted = base64::encode(w3name::ipns::serialize_ipns_entry(&entry).unwrap());
+
+  // by default, nothing is printed for a record with a bad signature
+  let output = env
+    .w3name(&["parse", "--name", &name, &corrupted])
+    .assert()
+    .code(INVALID_RECORD);
+  assert_eq!(stdout(output.get_output()), "");
+
+  let output = env
+    .w3name(&["parse", "--no-verify", "--name", &name, &corrupted])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(
+    text.starts_with("UNVERIFIED: the record's signatures were not checked\n"),
+    "{}",
+    text
+  );
+  assert!(text.contains(&format!("value: {}", VALUE)), "{}", text);
+  assert!(
+    text.contains("validated_with: none (UNVERIFIED)"),
+    "{}",
+    text
+  );
+
+  let output = env
+    .w3name(&[
+      "parse",
+      "--no-verify",
+      "--json",
+      "--name",
+      &name,
+      &corrupted,
+    ])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["verified"], false);
+  assert_eq!(json["value"], VALUE);
+
+  // a valid record is still verified by default
+  let record = env.service.record(&name).unwrap();
+  let output = env
+    .w3name(&["parse", "--json", "--name", &name, &record])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["verified"], true);
+}
+
+#[test]
+fn times_are_shown_relative_to_now() {
+  let env = TestEnv::new("times");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+  let record = env.service.record(&name).unwrap();
+
+  let output = env
+    .w3name(&["parse", "--name", &name, &record])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(text.contains("validity: expires in "), "{}", text);
+  assert!(text.contains("Z)\n"), "{}", text);
+
+  let output = env
+    .w3name(&["resolve", "--long", "--no-cache", &name])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(text.contains(&format!("value: {}", VALUE)), "{}", text);
+  assert!(text.contains("sequence: 0"), "{}", text);
+  assert!(text.contains("validity: expires in"), "{}", text);
+  assert!(text.contains("fetched: just now ("), "{}", text);
+
+  // JSON is UTC whatever the text output uses
+  let output = env
+    .w3name(&["--local", "parse", "--json", "--name", &name, &record])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert!(json["validity"].as_str().unwrap().ends_with('Z'));
+
+  env
+    .w3name(&["--utc", "--local", "parse", "--name", &name, &record])
+    .assert()
+    .failure();
+}
+
+#[test]
+fn frozen_time() {
+  let env = TestEnv::new("frozen-time");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&[
+      "--frozen-time",
+      "2040-01-01T00:00:00Z",
+      "publish",
+      "--key",
+      key.to_str().unwrap(),
+      "--value",
+      VALUE,
+      "--validity",
+      "30d",
+    ])
+    .assert()
+    .success();
+  let record = env.service.record(&name).unwrap();
+
+  // the validity is counted from the frozen time
+  let output = env
+    .w3name(&["parse", "--json", "--name", &name, &record])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(
+    timestamp(&json["validity"]),
+    timestamp(&"2040-01-31T00:00:00Z".into())
+  );
+
+  let output = env
+    .w3name(&[
+      "--frozen-time",
+      "2040-01-30T00:00:00Z",
+      "parse",
+      "--name",
+      &name,
+      &record,
+    ])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(
+    text.contains("validity: expires in 1 day (2040-01-31T00:00:00Z)"),
+    "{}",
+    text
+  );
+
+  // once the frozen time is past the validity, the record has expired
+  let output = env
+    .w3name(&[
+      "--frozen-time",
+      "2040-02-02T00:00:00Z",
+      "parse",
+      "--name",
+      &name,
+      &record,
+    ])
+    .output()
+    .unwrap();
+  let text = stdout(&output);
+  assert!(
+    text.contains("validity: expired 2 days ago (2040-01-31T00:00:00Z)"),
+    "{}",
+    text
+  );
+}
+
+#[test]
+fn resolve_verify_against_archives() {
+  let env = TestEnv::new("verify-against");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  std::fs::create_dir(env.path("archives")).unwrap();
+  for (value, archive) in [(VALUE, "archives/0.w3r"), ("/ipfs/new", "archives/1.w3r")] {
+    env
+      .w3name(&[
+        "publish",
+        "--key",
+        key,
+        "--value",
+        value,
+        "--archive",
+        archive,
+      ])
+      .assert()
+      .success();
+  }
+  let first: serde_json::Value =
+    serde_json::from_slice(&std::fs::read(env.path("archives/0.w3r")).unwrap()).unwrap();
+
+  // the newest archived record is the one being served
+  let output = env
+    .w3name(&["resolve", &name, "--verify-against", "archives"])
+    .assert()
+    .success();
+  assert_eq!(stdout(output.get_output()).trim(), "/ipfs/new");
+
+  // roll the service back to the first record
+  env
+    .service
+    .set_record(&name, first["record"].as_str().unwrap());
+  let output = env
+    .w3name(&["resolve", &name, "--verify-against", "archives"])
+    .assert()
+    .code(ROLLED_BACK);
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(
+    stderr.contains("resolved sequence 0") && stderr.contains("sequence 1"),
+    "{}",
+    stderr
+  );
+
+  // the served record is the same as, or newer than, the first archive
+  env
+    .w3name(&["resolve", &name, "--verify-against", "archives/0.w3r"])
+    .assert()
+    .success();
+}
+
+#[test]
+fn resolve_max_record_age() {
+  let env = TestEnv::new("max-record-age");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+
+  // the first resolve notes the sequence, so it's new
+  let limits = ["--warn-record-age", "7d", "--max-record-age", "30d"];
+  let resolve = |env: &TestEnv| env.w3name(&[&["resolve", name.as_str()][..], &limits].concat());
+  resolve(&env).assert().success();
+  let seen = env
+    .path("cache")
+    .join("w3name")
+    .join(format!("{}.seen", name));
+  assert!(std::fs::read_to_string(&seen).unwrap().starts_with("0 "));
+
+  let first_seen = |days| {
+    let at = chrono::Utc::now() - chrono::Duration::days(days);
+    std::fs::write(&seen, format!("0 {}\n", at.to_rfc3339())).unwrap();
+  };
+  first_seen(10);
+  let output = resolve(&env).assert().success();
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(stderr.contains("--warn-record-age"), "{}", stderr);
+
+  first_seen(40);
+  resolve(&env).assert().code(RECORD_TOO_OLD);
+
+  env
+    .w3name(&["resolve", &name, "--max-record-age", "1d", "--no-cache"])
+    .assert()
+    .code(USAGE);
+}
+
+#[test]
+fn offline_commands_make_no_requests() {
+  let env = TestEnv::new("offline-local");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+  let record = env.service.record(&name).unwrap();
+
+  // the mock counts every request it receives, so it doubles as a network canary
+  let requests = env.service.requests();
+  let key = key.to_str().unwrap();
+  let commands: &[&[&str]] = &[
+    &["create", "--output", "offline.key"],
+    &["name", key],
+    &["parse", "--name", &name, &record],
+    &["dnslink-txt", &name],
+    &["cache", "show"],
+    &["completions", "bash"],
+  ];
+  for args in commands {
+    let mut offline = vec!["--offline"];
+    offline.extend_from_slice(args);
+    env.w3name(&offline).assert().success();
+  }
+  assert_eq!(env.service.requests(), requests);
+}
+
+#[test]
+fn offline_network_commands_fail_fast() {
+  let env = TestEnv::new("offline-network");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  std::fs::write(env.path("names.json"), format!(r#"{{"{}": "site"}}"#, name)).unwrap();
+
+  let commands: &[&[&str]] = &[
+    &["resolve", &name],
+    &["publish", "--key", key, "--value", VALUE],
+    &["watch", &name],
+    &[
+      "key", "rotate", "--old", key, "--new", "new.key", "--value", VALUE,
+    ],
+    &["resolve", "--manifest", &env.service.url()],
+    &["resolve", "--manifest-file", "names.json"],
+  ];
+  for args in commands {
+    let mut offline = vec!["--offline"];
+    offline.extend_from_slice(args);
+    let output = env.w3name(&offline).assert().code(USAGE);
+    let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+    assert!(
+      stderr.contains("needs network access, which --offline forbids"),
+      "{:?}: {}",
+      args,
+      stderr
+    );
+  }
+  assert_eq!(env.service.requests(), 0);
+  assert!(!env.path("new.key").exists());
+}
+
+#[test]
+fn watch_prometheus_metrics() {
+  let env = TestEnv::new("watch-prometheus");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+
+  let port = free_port();
+  let addr = format!("127.0.0.1:{}", port);
+  let mut watcher = env.spawn(&["watch", &name, "--interval", "1", "--prometheus", &addr]);
+  let sequence = |seq: u64| format!("w3name_sequence{{name=\"{}\"}} {}\n", name, seq);
+  let scrape_until = |expected: &str| {
+    for _ in 0..100 {
+      match http_get(port, "/metrics") {
+        Some(metrics) if metrics.contains(expected) => return metrics,
+        _ => std::thread::sleep(Duration::from_millis(100)),
+      }
+    }
+    panic!("metrics never contained {:?}", expected);
+  };
+
+  let metrics = scrape_until(&sequence(0));
+  assert!(metrics.contains(&format!(
+    "w3name_consecutive_failures{{name=\"{}\"}} 0\n",
+    name
+  )));
+  assert!(metrics.contains("# TYPE w3name_validity_remaining_seconds gauge"));
+  assert!(metrics.contains("w3name_last_resolve_success_timestamp_seconds{"));
+
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/new"])
+    .assert()
+    .success();
+  scrape_until(&sequence(1));
+
+  env.service.fail_next(2, StatusCode::INTERNAL_SERVER_ERROR);
+  let metrics = scrape_until(&format!(
+    "w3name_resolve_errors_total{{name=\"{}\",class=\"service\"}}",
+    name
+  ));
+  assert!(metrics.contains(&sequence(1)));
+
+  watcher.kill().unwrap();
+  watcher.wait().unwrap();
+}
+
+#[test]
+fn watch_ignores_sequence_regressions() {
+  let env = TestEnv::new("watch-regression");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/old"])
+    .assert()
+    .success();
+  let old = env.service.record(&name).unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  let new = env.service.record(&name).unwrap();
+  env.service.rotate_records(&name, vec![new, old]);
+
+  let port = free_port();
+  let addr = format!("127.0.0.1:{}", port);
+  let mut watcher = env.spawn(&["watch", &name, "--interval", "1", "--prometheus", &addr]);
+  let regressions = format!("w3name_sequence_regressions_total{{name=\"{}\"}} 2\n", name);
+  let mut metrics = String::new();
+  for _ in 0..100 {
+    metrics = http_get(port, "/metrics").unwrap_or_default();
+    if metrics.contains(&regressions) {
+      break;
+    }
+    std::thread::sleep(Duration::from_millis(100));
+  }
+  watcher.kill().unwrap();
+  let output = watcher.wait_with_output().unwrap();
+
+  assert!(metrics.contains(&regressions), "{}", metrics);
+  // the current record is still the newer one
+  assert!(metrics.contains(&format!("w3name_sequence{{name=\"{}\"}} 1\n", name)));
+  assert_eq!(stdout(&output), format!("{}\n", VALUE));
+}
+
+#[test]
+fn json_values() {
+  let env = TestEnv::new("json-values");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let manifest = env.path("manifest.json");
+  std::fs::write(
+    &manifest,
+    "{\n  \"previous\": \"/ipfs/bafyold\",\n  \"current\": \"/ipfs/bafynew\"\n}\n",
+  )
+  .unwrap();
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value-json",
+      manifest.to_str().unwrap(),
+    ])
+    .assert()
+    .success();
+
+  // the published value is the canonical form
+  let output = env
+    .w3name(&["resolve", "--no-cache", &name])
+    .assert()
+    .success();
+  assert_eq!(
+    stdout(output.get_output()),
+    "{\"current\":\"/ipfs/bafynew\",\"previous\":\"/ipfs/bafyold\"}\n"
+  );
+  let output = env
+    .w3name(&["resolve", "--json-value", &name])
+    .assert()
+    .success();
+  assert_eq!(
+    stdout(output.get_output()),
+    "{\n  \"current\": \"/ipfs/bafynew\",\n  \"previous\": \"/ipfs/bafyold\"\n}\n"
+  );
+
+  // files that aren't JSON, or are too large, are refused before anything is published
+  let published = env.service.record(&name);
+  let invalid = env.path("invalid.json");
+  std::fs::write(&invalid, "/ipfs/bafynotjson").unwrap();
+  let too_large = env.path("large.json");
+  std::fs::write(
+    &too_large,
+    format!("{{\"padding\": \"{}\"}}", "a".repeat(5000)),
+  )
+  .unwrap();
+  for file in [&invalid, &too_large] {
+    env
+      .w3name(&[
+        "publish",
+        "--key",
+        key,
+        "--value-json",
+        file.to_str().unwrap(),
+      ])
+      .assert()
+      .code(USAGE);
+  }
+  assert_eq!(env.service.record(&name), published);
+
+  // a value that isn't JSON can't be shown as JSON
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  let output = env
+    .w3name(&["resolve", "--no-cache", "--json-value", &name])
+    .assert()
+    .code(FAILURE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr);
+  assert!(stderr.contains("value is not valid JSON"), "{}", stderr);
+}
+
+#[test]
+fn publish_value_from_file_and_command() {
+  let env = TestEnv::new("publish-value-from");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let cid = VALUE.trim_start_matches("/ipfs/");
+
+  // a bare CID, as a build step would write it
+  std::fs::write(env.dir.join("latest-cid"), format!("{}\n", cid)).unwrap();
+  let from_file = ["publish", "--key", key, "--value-from-file", "latest-cid"];
+  let output = env.w3name(&from_file).assert().success();
+  assert!(stdout(output.get_output()).contains(VALUE));
+
+  // repeated runs are cheap with --skip-unchanged
+  let output = env
+    .w3name(&[&from_file[..], &["--skip-unchanged"]].concat())
+    .assert()
+    .success();
+  assert_eq!(stdout(output.get_output()), "unchanged, nothing to do\n");
+
+  std::fs::write(env.dir.join("latest-cid"), "  \n").unwrap();
+  let output = env.w3name(&from_file).assert().code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(
+    stderr.contains("value file latest-cid is empty"),
+    "{}",
+    stderr
+  );
+  std::fs::remove_file(env.dir.join("latest-cid")).unwrap();
+  let output = env.w3name(&from_file).assert().code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("latest-cid"), "{}", stderr);
+
+  let command = "echo /ipfs/bafkreigh2akiscaildcqabsyg3dfr6chu3fgpregiymsck7e7aqa4s52zy";
+  env
+    .w3name(&["publish", "--key", key, "--value-from-command", command])
+    .assert()
+    .success();
+  let output = env.w3name(&["resolve", &name]).assert().success();
+  assert!(stdout(output.get_output())
+    .contains("bafkreigh2akiscaildcqabsyg3dfr6chu3fgpregiymsck7e7aqa4s52zy"));
+
+  // the command's failure fails the publish
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value-from-command",
+      "echo oops >&2; exit 3",
+    ])
+    .assert()
+    .code(FAILURE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("oops"), "{}", stderr);
+  assert!(stderr.contains("the value command failed"), "{}", stderr);
+
+  env.w3name(&["publish", "--key", key]).assert().code(USAGE);
+}
+
+#[test]
+fn publish_unusual_characters() {
+  let env = TestEnv::new("publish-unusual-characters");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+
+  // e.g. a zero-width space pasted along with the CID
+  let pasted = format!("{}\u{200b}", VALUE);
+  let output = env
+    .w3name(&["publish", "--key", key, "--value", &pasted])
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("U+200B"), "{}", stderr);
+  assert!(stderr.contains("--allow-unusual-characters"), "{}", stderr);
+  env.w3name(&["resolve", &name]).assert().code(NOT_FOUND);
+
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      &pasted,
+      "--allow-unusual-characters",
+    ])
+    .assert()
+    .success();
+  let output = env.w3name(&["resolve", &name]).assert().success();
+  assert!(stdout(output.get_output()).contains(&pasted));
+
+  // a combining accent is published precomposed
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      "/ipns/example.com/cafe\u{301}",
+      "--normalize-unicode",
+    ])
+    .assert()
+    .success();
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("normalized to Unicode NFC"), "{}", stderr);
+  let output = env
+    .w3name(&["resolve", "--no-cache", &name])
+    .assert()
+    .success();
+  assert!(stdout(output.get_output()).contains("/ipns/example.com/caf\u{e9}\n"));
+}
+
+#[test]
+fn concurrent_publishes_are_serialized() {
+  let env = TestEnv::new("publish-lock");
+  let (key, _) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  // slow enough that both processes resolve before either publishes, unless they take turns
+  env.service.set_delay(Duration::from_millis(300));
+
+  let publish = |value: &str| {
+    let output = env
+      .w3name(&["publish", "--key", key, "--value", value, "--json"])
+      .assert()
+      .success();
+    let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+    json["sequence"].as_u64().unwrap()
+  };
+  let mut sequences = std::thread::scope(|s| {
+    let first = s.spawn(|| publish("/ipfs/first"));
+    let second = s.spawn(|| publish("/ipfs/second"));
+    vec![first.join().unwrap(), second.join().unwrap()]
+  });
+  sequences.sort();
+  assert_eq!(sequences, vec![0, 1]);
+}
+
+#[test]
+fn publish_expect_name() {
+  let env = TestEnv::new("publish-expect-name");
+  let (key, name) = env.create_key("site.key");
+  let (_, scratch) = env.create_key("scratch.key");
+  let key = key.to_str().unwrap();
+
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      VALUE,
+      "--expect-name",
+      &scratch,
+    ])
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(
+    stderr.contains(&format!(
+      "expected the key of {}, but the key is for {}",
+      scratch, name
+    )),
+    "{}",
+    stderr
+  );
+  assert_eq!(env.service.requests(), 0);
+
+  // aliases work too
+  std::fs::create_dir_all(env.path("w3name")).unwrap();
+  std::fs::write(
+    env.path("w3name/aliases.json"),
+    format!("{{\"prod\": \"{}\"}}", name),
+  )
+  .unwrap();
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      VALUE,
+      "--expect-name",
+      "prod",
+    ])
+    .assert()
+    .success();
+}
+
+#[test]
+fn value_lengths() {
+  let env = TestEnv::new("value-lengths");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+
+  // "ä" is two bytes in UTF-8, so this is 9 characters but 10 bytes
+  let multibyte = "/ipns/bär";
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      multibyte,
+      "--expect-length",
+      "9",
+    ])
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(
+    stderr.contains("the value is 10 bytes long (9 characters), but --expect-length is 9"),
+    "{}",
+    stderr
+  );
+  assert_eq!(env.service.requests(), 0);
+
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      multibyte,
+      "--expect-length",
+      "10",
+      "--json",
+    ])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["value_length"], 10);
+
+  // a value with a newline in it is shown whole, with its length after it
+  let multiline = "/ipfs/first\n/ipfs/second";
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      multiline,
+      "--allow-unusual-characters",
+      "--expect-length",
+      "24",
+    ])
+    .assert()
+    .success();
+  let output = env
+    .w3name(&["resolve", "--long", "--no-cache", &name])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(
+    text.starts_with(&format!(
+      "name: {}\nvalue: {}\nvalue_length: 24 bytes\nsequence: 1\n",
+      name, multiline
+    )),
+    "{}",
+    text
+  );
+  assert_eq!(text.matches("/ipfs/second").count(), 1, "{}", text);
+
+  let record = env.service.record(&name).unwrap();
+  let output = env
+    .w3name(&["parse", "--name", &name, &record])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(text.contains("\nvalue_length: 24 bytes\n"), "{}", text);
+  let output = env
+    .w3name(&["parse", "--json", "--name", &name, &record])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["value"], multiline);
+  assert_eq!(json["value_length"], 24);
+  let output = env
+    .w3name(&["resolve", "--json", "--no-cache", &name])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["value_length"], 24);
+}
+
+#[test]
+fn publish_lock_timeout() {
+  let env = TestEnv::new("publish-lock-timeout");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+
+  // hold the name's lock, as a concurrent publish would
+  let lock_path = env.path("w3name/locks").join(format!("{}.lock", name));
+  std::fs::create_dir_all(lock_path.parent().unwrap()).unwrap();
+  let lock = std::fs::File::create(&lock_path).unwrap();
+  fs2::FileExt::lock_exclusive(&lock).unwrap();
+
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      VALUE,
+      "--lock-timeout",
+      "200ms",
+    ])
+    .assert()
+    .failure();
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(
+    stderr.contains("another publish is in progress"),
+    "{}",
+    stderr
+  );
+  assert_eq!(env.service.requests(), 0);
+
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE, "--no-lock"])
+    .assert()
+    .success();
+}
+
+#[test]
+fn connection_refused_hint() {
+  let env = TestEnv::new("refused");
+  let (_, name) = env.create_key("site.key");
+  // nothing listens on a port once its listener is closed
+  let addr = std::net::TcpListener::bind("127.0.0.1:0")
+    .unwrap()
+    .local_addr()
+    .unwrap();
+  let endpoint = format!("http://{}", addr);
+
+  let output = env
+    .w3name(&["resolve", &name])
+    .env("W3NAME_ENDPOINT", &endpoint)
+    .assert()
+    .code(SERVICE);
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(
+    stderr.contains("network cause: connection refused"),
+    "{}",
+    stderr
+  );
+  assert!(stderr.contains("hint: connection refused"), "{}", stderr);
+
+  let output = env
+    .w3name(&["--errors", "json", "resolve", &name])
+    .env("W3NAME_ENDPOINT", &endpoint)
+    .assert()
+    .code(SERVICE);
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  let json: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+  assert_eq!(json["network_cause"], "connect_refused");
+}
+
+#[test]
+fn prove_and_verify() {
+  let env = TestEnv::new("prove");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+
+  let output = env
+    .w3name(&["prove", "--key", key, "--challenge", "registrar-4242"])
+    .assert()
+    .success();
+  let proof = stdout(output.get_output());
+  let json: serde_json::Value = serde_json::from_str(&proof).unwrap();
+  assert_eq!(json["name"], name.as_str());
+  assert_eq!(json["challenge"], "registrar-4242");
+  std::fs::write(env.path("proof.json"), &proof).unwrap();
+
+  let output = env
+    .w3name(&["prove", "verify", "--name", &name, "--proof", "proof.json"])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(
+    text.contains(&format!("valid proof of control of {}", name)),
+    "{}",
+    text
+  );
+  assert!(text.contains("challenge: registrar-4242"), "{}", text);
+
+  // the key file works as the name too
+  env
+    .w3name(&["prove", "verify", "--name", key, "--proof", "proof.json"])
+    .assert()
+    .success();
+
+  let (_, other) = env.create_key("other.key");
+  env
+    .w3name(&["prove", "verify", "--name", &other, "--proof", "proof.json"])
+    .assert()
+    .failure();
+
+  std::fs::write(
+    env.path("tampered.json"),
+    proof.replace("registrar-4242", "registrar-4243"),
+  )
+  .unwrap();
+  let output = env
+    .w3name(&[
+      "prove",
+      "verify",
+      "--name",
+      &name,
+      "--proof",
+      "tampered.json",
+    ])
+    .assert()
+    .failure();
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(stderr.contains("failed to verify proof"), "{}", stderr);
+
+  env
+    .w3name(&[
+      "prove",
+      "verify",
+      "--name",
+      &name,
+      "--proof",
+      "proof.json",
+      "--max-age",
+      "0s",
+    ])
+    .assert()
+    .failure();
+}
+
+#[test]
+fn resign_record_filter() {
+  let env = TestEnv::new("resign");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  let record = base64::decode(env.service.record(&name).unwrap()).unwrap();
+
+  let output = env
+    .w3name(&["record", "resign", "--key", key, "--validity", "1y"])
+    .write_stdin(record)
+    .assert()
+    .success();
+  let resigned = base64::encode(&output.get_output().stdout);
+
+  let output = env
+    .w3name(&["parse", "--json", "--name", &name, &resigned])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["value"], VALUE);
+  assert_eq!(json["sequence"], 1);
+
+  // base64 in, base64 out
+  let output = env
+    .w3name(&["record", "resign", "--key", key, "--base64"])
+    .write_stdin(resigned)
+    .assert()
+    .success();
+  let resigned = stdout(output.get_output());
+  let output = env
+    .w3name(&["parse", "--json", "--name", &name, resigned.trim()])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["sequence"], 2);
+
+  let (other, _) = env.create_key("other.key");
+  env
+    .w3name(&["record", "resign", "--key", other.to_str().unwrap()])
+    .write_stdin(resigned)
+    .assert()
+    .failure();
+}
+
+#[test]
+fn key_delete_and_alias_rm() {
+  let env = TestEnv::new("key-delete");
+  let (key, name) = env.create_key("site.key");
+  std::fs::create_dir_all(env.path("w3name/keys")).unwrap();
+  let stored = env.path(&format!("w3name/keys/{}.key", name));
+  std::fs::copy(&key, &stored).unwrap();
+  std::fs::write(
+    env.path("w3name/aliases.json"),
+    format!("{{\"prod\": \"{}\", \"staging\": \"{}\"}}", name, name),
+  )
+  .unwrap();
+
+  // a record that's still live, as far as the cache knows
+  let key = key.to_str().unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  env.w3name(&["resolve", &name]).assert().success();
+
+  // the wrong fingerprint deletes nothing
+  env
+    .w3name(&["key", "delete", "prod"])
+    .write_stdin("yes\n")
+    .assert()
+    .code(USAGE);
+  assert!(stored.is_file());
+
+  env.w3name(&["alias", "rm", "staging"]).assert().success();
+  env.w3name(&["alias", "rm", "staging"]).assert().code(USAGE);
+
+  let fingerprint = &name[name.len() - 8..];
+  let output = env
+    .w3name(&["key", "delete", "prod"])
+    .write_stdin(format!("{}\n", fingerprint))
+    .assert()
+    .success();
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(stderr.contains("has a published record"), "{}", stderr);
+  assert!(stdout(output.get_output()).contains("removed alias prod"));
+  assert!(!stored.exists());
+  let trashed: Vec<_> = std::fs::read_dir(env.path("w3name/trash"))
+    .unwrap()
+    .collect();
+  assert_eq!(trashed.len(), 1);
+  assert_eq!(
+    std::fs::read_to_string(env.path("w3name/aliases.json")).unwrap(),
+    "{}"
+  );
+
+  // --purge removes the file for good
+  std::fs::copy(key, &stored).unwrap();
+  env
+    .w3name(&["key", "delete", &name, "--yes", "--purge"])
+    .assert()
+    .success();
+  assert!(!stored.exists());
+  assert_eq!(
+    std::fs::read_dir(env.path("w3name/trash")).unwrap().count(),
+    1
+  );
+}
+
+#[test]
+fn key_commands_json() {
+  let env = TestEnv::new("key-json");
+  let json = |args: &[&str]| -> serde_json::Value {
+    let output = env.w3name(args).assert().success();
+    serde_json::from_str(&stdout(output.get_output())).unwrap()
+  };
+
+  let created = json(&["create", "--output", "site.key", "--format", "js", "--json"]);
+  let name = created["name"].as_str().unwrap().to_string();
+  assert_eq!(created["key_type"], "ed25519");
+  assert_eq!(created["format"], "js");
+  assert_eq!(created["path"], "site.key");
+  let key = env.path("site.key");
+
+  let described = json(&["name", key.to_str().unwrap(), "--json"]);
+  assert_eq!(described["name"], name.as_str());
+  assert_eq!(described["source_format"], "base64");
+
+  // importing stores the key in the keystore, once
+  let imported = json(&["key", "import", key.to_str().unwrap(), "--json"]);
+  let stored = env.path(&format!("w3name/keys/{}.key", name));
+  assert_eq!(imported["path"], stored.display().to_string());
+  assert_eq!(imported["format"], "binary");
+  assert_eq!(imported["source_format"], "base64");
+  env
+    .w3name(&["key", "import", key.to_str().unwrap()])
+    .assert()
+    .code(USAGE);
+
+  // no secrets, unless they're asked for
+  let exported = json(&["key", "export", &name, "--json"]);
+  assert_eq!(exported["source_format"], "binary");
+  assert!(exported.get("key").is_none(), "{}", exported);
+  let output = env
+    .w3name(&["key", "export", &name, "--json", "--include-secret"])
+    .assert()
+    .success();
+  let envelope = stdout(output.get_output());
+  env
+    .w3name(&["key", "export", &name, "--include-secret"])
+    .assert()
+    .code(USAGE);
+
+  // the exported envelope imports again, once the key is gone
+  let deleted = json(&["key", "delete", &name, "--yes", "--purge", "--json"]);
+  assert_eq!(deleted["name"], name.as_str());
+  assert_eq!(deleted["trashed_to"], serde_json::Value::Null);
+  assert!(!stored.exists());
+  let output = env
+    .w3name(&["key", "import", "-"])
+    .write_stdin(envelope)
+    .assert()
+    .success();
+  assert!(stdout(output.get_output()).contains(&format!("imported key {}", name)));
+  assert!(stored.is_file());
+}
+
+#[test]
+fn record_fetch_from_kubo() {
+  let env = TestEnv::new("record-fetch");
+  let (key, name) = env.create_key("site.key");
+  let kubo = env.service.url();
+  let fetch = |extra: &[&str]| {
+    let mut args = vec!["record", "fetch", &name, "--kubo-api", &kubo];
+    args.extend_from_slice(extra);
+    env.w3name(&args)
+  };
+
+  fetch(&[]).assert().code(NO_ROUTING_RECORD);
+
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+  let published = env.service.record(&name).unwrap();
+  let output = fetch(&["--base64"]).assert().success();
+  assert_eq!(stdout(output.get_output()).trim(), published);
+
+  let saved = env.path("dht.bin");
+  fetch(&["--output", saved.to_str().unwrap()])
+    .assert()
+    .success();
+  assert_eq!(
+    std::fs::read(&saved).unwrap(),
+    base64::decode(&published).unwrap()
+  );
+
+  // a record for another name is refused
+  let (_, other) = env.create_key("other.key");
+  env.service.set_record(&other, &published);
+  env
+    .w3name(&["record", "fetch", &other, "--kubo-api", &kubo])
+    .assert()
+    .code(INVALID_RECORD);
+}
+
+#[test]
+fn trace_http_logs_exchanges() {
+  let env = TestEnv::new("trace-http");
+  let (key, name) = env.create_key("site.key");
+  let output = env
+    .w3name(&[
+      "publish",
+      "--trace-http",
+      "--key",
+      key.to_str().unwrap(),
+      "--value",
+      VALUE,
+    ])
+    .assert()
+    .success();
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  let record = env.service.record(&name).unwrap();
+  assert!(
+    stderr.contains(&format!(
+      "HTTP request: POST {}/name/{}",
+      env.service.url(),
+      name
+    )),
+    "{}",
+    stderr
+  );
+  assert!(stderr.contains(&format!("body ({} bytes): {}", record.len(), record)));
+  assert!(stderr.contains("HTTP response: 202 Accepted"));
+  assert!(stderr.contains(&format!("{{\"id\":\"{}\"}}", name)));
+}
+
+#[test]
+fn publish_warns_about_ttls() {
+  let env = TestEnv::new("publish-ttl");
+  let (key, _) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let stderr = |output: &std::process::Output| String::from_utf8_lossy(&output.stderr).into_owned();
+
+  // the default TTL is within the default maximum and validity
+  let output = env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/first"])
+    .assert()
+    .success();
+  assert!(!stderr(output.get_output()).contains("TTL"));
+
+  // the default TTL is 31 days
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      "/ipfs/second",
+      "--validity",
+      "10days",
+      "--max-ttl",
+      "48h",
+    ])
+    .assert()
+    .success();
+  let text = stderr(output.get_output());
+  assert!(
+    text.contains("is longer than the record stays valid"),
+    "{}",
+    text
+  );
+  assert!(text.contains("is longer than 2 days"), "{}", text);
+
+  let output = env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      "/ipfs/third",
+      "--validity",
+      "10days",
+      "--max-ttl",
+      "0",
+      "--allow-ttl-over-validity",
+    ])
+    .assert()
+    .success();
+  assert!(!stderr(output.get_output()).contains("TTL"));
+}
+
+#[test]
+fn publish_preserves_the_validity_window() {
+  let env = TestEnv::new("preserve");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let validity_days = |env: &TestEnv| {
+    let record = env.service.record(&name).unwrap();
+    let output = env
+      .w3name(&["parse", "--json", "--name", &name, &record])
+      .assert()
+      .success();
+    let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+    let remaining = timestamp(&json["validity"]).with_timezone(&chrono::Utc) - chrono::Utc::now();
+    (remaining.num_hours() as f64 / 24.0).round() as i64
+  };
+
+  let args = [
+    "publish",
+    "--key",
+    key,
+    "--value",
+    VALUE,
+    "--validity",
+    "7days",
+  ];
+  env.w3name(&args).assert().success();
+  assert_eq!(validity_days(&env), 7);
+
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/second"])
+    .assert()
+    .success();
+  assert_eq!(validity_days(&env), 7);
+
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      "/ipfs/third",
+      "--reset-durations",
+    ])
+    .assert()
+    .success();
+  assert_eq!(validity_days(&env), 364);
+}
+
+#[test]
+fn publish_with_configured_defaults() {
+  let env = TestEnv::new("configured-defaults");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let parsed = w3name::Name::parse(&name).unwrap();
+  let published = || {
+    let record = base64::decode(env.service.record(&name).unwrap()).unwrap();
+    w3name::Revision::from_signed_bytes(&parsed, &record).unwrap()
+  };
+  let publish = |value: &str, args: &[&str]| {
+    env.w3name(&[&["publish", "--key", key, "--value", value], args].concat())
+  };
+
+  // built-in: a 31-day TTL
+  publish(VALUE, &[]).assert().success();
+  assert_eq!(published().ttl(), chrono::Duration::days(31));
+
+  // config file, then the environment, then flags
+  let config = env.path("w3name").join("config.toml");
+  std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+  std::fs::write(&config, "[defaults]\nvalidity = \"30d\"\nttl = \"10m\"\n").unwrap();
+  publish("/ipfs/second", &[]).assert().success();
+  let revision = published();
+  assert_eq!(revision.ttl(), chrono::Duration::minutes(10));
+  let remaining = *revision.validity() - chrono::Utc::now();
+  assert!(
+    remaining > chrono::Duration::days(29) && remaining <= chrono::Duration::days(30),
+    "{}",
+    remaining
+  );
+  publish("/ipfs/third", &[])
+    .env("W3NAME_DEFAULT_TTL", "1h")
+    .assert()
+    .success();
+  assert_eq!(published().ttl(), chrono::Duration::hours(1));
+  publish("/ipfs/fourth", &["--ttl", "5m", "--validity", "2days"])
+    .env("W3NAME_DEFAULT_TTL", "1h")
+    .assert()
+    .success();
+  let revision = published();
+  assert_eq!(revision.ttl(), chrono::Duration::minutes(5));
+  assert!(*revision.validity() - chrono::Utc::now() <= chrono::Duration::days(2));
+
+  // invalid settings fail every command, before it runs
+  let output = env
+    .w3name(&["name", key])
+    .env("W3NAME_DEFAULT_VALIDITY", "a while")
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("W3NAME_DEFAULT_VALIDITY"), "{}", stderr);
+  std::fs::write(&config, "[defaults]\nttl = \"10 parsecs\"\n").unwrap();
+  let output = env.w3name(&["name", key]).assert().code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("defaults.ttl"), "{}", stderr);
+}
+
+#[test]
+fn min_validity_checks_the_preserved_window() {
+  let env = TestEnv::new("min-validity-preserved");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let publish = |value: &str, args: &[&str]| {
+    env.w3name(&[&["publish", "--key", key, "--value", value], args].concat())
+  };
+
+  publish(VALUE, &["--validity", "2h", "--min-validity", "1h"])
+    .assert()
+    .success();
+  // the next revision keeps the 2-hour window, which is below the default minimum of a day
+  let output = publish("/ipfs/second", &[]).assert().code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("would expire in less than"), "{}", stderr);
+  assert_eq!(
+    stdout(
+      env
+        .w3name(&["resolve", "--no-cache", &name])
+        .assert()
+        .success()
+        .get_output()
+    )
+    .trim(),
+    VALUE
+  );
+  publish("/ipfs/second", &["--yes"]).assert().success();
+}
+
+#[test]
+fn publish_rejects_a_validity_past_year_9999() {
+  let env = TestEnv::new("validity-out-of-range");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+
+  // too long for a date at all, and past what RFC 3339 can express
+  for validity in ["300000y", "9000y"] {
+    let output = env
+      .w3name(&[
+        "publish",
+        "--key",
+        key,
+        "--value",
+        VALUE,
+        "--validity",
+        validity,
+      ])
+      .assert()
+      .code(USAGE);
+    let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+    assert!(stderr.contains("after the year 9999"), "{}", stderr);
+  }
+  assert!(env.service.record(&name).is_none());
+}
+
+#[test]
+fn publish_valid_until() {
+  let env = TestEnv::new("valid-until");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let publish = |value: &str, valid_until: &str| {
+    env.w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      value,
+      "--valid-until",
+      valid_until,
+    ])
+  };
+
+  // the instant is kept exactly, and the record verifies
+  publish(VALUE, "2099-12-31T00:00:00Z").assert().success();
+  let record = env.service.record(&name).unwrap();
+  let output = env
+    .w3name(&["parse", "--json", "--name", &name, &record])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["validity"], "2099-12-31T00:00:00.000000000Z");
+
+  // other offsets are converted to UTC, and fractions of a second are kept
+  publish("/ipfs/second", "2099-12-31T02:00:00.25+02:00")
+    .assert()
+    .success();
+  let record = env.service.record(&name).unwrap();
+  let output = env
+    .w3name(&["parse", "--json", "--name", &name, &record])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["validity"], "2099-12-31T00:00:00.250000000Z");
+  assert_eq!(json["sequence"], 1);
+
+  // instants in the past, or too close to now for clock skew, are refused
+  let soon = (chrono::Utc::now() + chrono::Duration::minutes(1)).to_rfc3339();
+  for valid_until in ["2020-01-01T00:00:00Z", soon.as_str()] {
+    let output = publish("/ipfs/third", valid_until).assert().code(USAGE);
+    let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+    assert!(stderr.contains("in the future"), "{}", stderr);
+  }
+  publish("/ipfs/third", "next tuesday").assert().code(USAGE);
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      "/ipfs/third",
+      "--valid-until",
+      "2099-12-31T00:00:00Z",
+      "--validity",
+      "7days",
+    ])
+    .assert()
+    .code(USAGE);
+  assert_eq!(env.service.record(&name).unwrap(), record);
+}
+
+#[cfg(unix)]
+#[test]
+fn runs_without_a_home_directory() {
+  use std::{fs, os::unix::fs::PermissionsExt};
+
+  let env = TestEnv::new("no-home");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  // like a distroless container: no home, and a read-only working directory
+  let read_only = env.path("read-only");
+  fs::create_dir(&read_only).unwrap();
+  fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555)).unwrap();
+  let homeless = |args: &[&str]| {
+    let mut cmd = env.w3name(args);
+    cmd
+      .current_dir(&read_only)
+      .env_remove("HOME")
+      .env_remove("USERPROFILE")
+      .env_remove("W3NAME_HOME")
+      .env_remove("XDG_CACHE_HOME");
+    cmd
+  };
+  let quiet = |output: &std::process::Output| {
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert_eq!(stderr, "", "expected no warnings");
+  };
+
+  // stateless commands work, without warning about the missing state
+  let output = homeless(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  quiet(output.get_output());
+  let output = homeless(&["resolve", &name]).assert().success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+  quiet(output.get_output());
+  let record = env.service.record(&name).unwrap();
+  let output = homeless(&["parse", "--name", &name, &record])
+    .assert()
+    .success();
+  quiet(output.get_output());
+
+  // commands that need the state directory say how to provide one
+  for args in [&["alias", "rm", "site"][..], &["cache", "show"][..]] {
+    let output = homeless(args).assert().code(USAGE);
+    let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+    assert!(stderr.contains("W3NAME_HOME"), "{}", stderr);
+  }
+
+  // a read-only state directory is treated like a missing one by stateless commands, unless
+  // permissions don't apply, as for root
+  if fs::create_dir(read_only.join("probe")).is_ok() {
+    return;
+  }
+  let read_only = read_only.to_str().unwrap();
+  let read_only_home = |args: &[&str]| {
+    let mut cmd = homeless(args);
+    cmd.env("HOME", read_only);
+    cmd
+  };
+  let output = read_only_home(&["publish", "--key", key, "--value", "/ipfs/second"])
+    .assert()
+    .success();
+  quiet(output.get_output());
+  let output = read_only_home(&["resolve", &name]).assert().success();
+  assert_eq!(stdout(output.get_output()).trim(), "/ipfs/second");
+  quiet(output.get_output());
+  let output = read_only_home(&["key", "import", key]).assert().code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("W3NAME_HOME"), "{}", stderr);
+}
+
+#[test]
+fn cache_subcommands() {
+  let env = TestEnv::new("cache-commands");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+
+  // a record with a zero TTL is cached, but is stale straight away
+  let writable = w3name::WritableName::new();
+  let stale = writable.to_string();
+  let revision = w3name::Revision::v0_with_validity(
+    &writable.to_name(),
+    VALUE,
+    chrono::Utc::now() + chrono::Duration::days(1),
+    chrono::Duration::zero(),
+  );
+  let record = base64::encode(revision.to_signed_bytes(&writable).unwrap());
+  env.service.set_record(&stale, &record);
+
+  env.w3name(&["resolve", &name]).assert().success();
+  env.w3name(&["resolve", &name]).assert().success();
+  env.w3name(&["resolve", &stale]).assert().success();
+  let cache_dir = env.path("cache").join("w3name");
+  let records = || {
+    let mut files: Vec<String> = std::fs::read_dir(&cache_dir)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+      .filter(|file| file.ends_with(".ipns"))
+      .collect();
+    files.sort();
+    files
+  };
+  let mut expected = vec![format!("{}.ipns", name), format!("{}.ipns", stale)];
+  expected.sort();
+  assert_eq!(records(), expected);
+
+  let output = env.w3name(&["cache", "show", "--json"]).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  let entries = json.as_array().unwrap();
+  assert_eq!(entries.len(), 2);
+  let entry = |n: &str| entries.iter().find(|entry| entry["name"] == n).unwrap();
+  assert_eq!(entry(&name)["fresh"], true);
+  assert_eq!(entry(&name)["value"], VALUE);
+  assert_eq!(entry(&stale)["fresh"], false);
+
+  let output = env.w3name(&["cache", "show", &name]).assert().success();
+  let text = stdout(output.get_output());
+  let lines: Vec<_> = text.lines().collect();
+  assert_eq!(lines.len(), 2, "{}", text);
+  assert!(
+    lines[0].starts_with("NAME") && lines[0].contains("FETCHED"),
+    "{}",
+    text
+  );
+  assert!(
+    lines[1].starts_with(&*name) && lines[1].contains("left"),
+    "{}",
+    text
+  );
+
+  let output = env.w3name(&["cache", "stats", "--json"]).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["hits"], 1);
+  assert_eq!(json["misses"], 2);
+  assert_eq!(json["entries"], 2);
+
+  let output = env.w3name(&["cache", "prune", "--json"]).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["removed"], serde_json::json!([stale]));
+  assert_eq!(records(), vec![format!("{}.ipns", name)]);
+
+  let output = env
+    .w3name(&["cache", "clear", "--json", &name])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["removed"], 1);
+  assert!(records().is_empty());
+
+  // the stats survive clearing the cache
+  env.w3name(&["resolve", &name]).assert().success();
+  env.w3name(&["cache", "clear"]).assert().success();
+  assert!(records().is_empty());
+  let output = env.w3name(&["cache", "stats"]).assert().success();
+  assert!(stdout(output.get_output()).contains("misses: 3"));
+}
+
+#[test]
+fn record_ls() {
+  let env = TestEnv::new("record-ls");
+  let (key, name) = env.create_key("site.key");
+  let (other_key, other) = env.create_key("other.key");
+  std::fs::create_dir(env.path("archives")).unwrap();
+  let publish = |key: &std::path::Path, value: &str, validity: &str| {
+    env
+      .w3name(&[
+        "--frozen-time",
+        "2040-01-01T00:00:00Z",
+        "publish",
+        "--key",
+        key.to_str().unwrap(),
+        "--value",
+        value,
+        "--validity",
+        validity,
+        "--archive",
+        "archives",
+      ])
+      .assert()
+      .success();
+  };
+  publish(&key, VALUE, "30d");
+  publish(&key, "/ipfs/second", "1y");
+  publish(&other_key, "/ipfs/other", "1y");
+  let first = format!("archives/{}-seq0-20400101T000000Z.w3r", name);
+  assert!(env.path(&first).is_file());
+  assert!(env
+    .path(&format!("archives/{}-seq1-20400101T000000Z.w3r", name))
+    .is_file());
+  std::fs::write(env.path("archives/broken.w3r"), "not an archive").unwrap();
+  // an archive claiming another name's record
+  let mut forged: serde_json::Value =
+    serde_json::from_slice(&std::fs::read(env.path(&first)).unwrap()).unwrap();
+  forged["name"] = other.clone().into();
+  std::fs::write(env.path("archives/forged.w3r"), forged.to_string()).unwrap();
+  std::fs::write(env.path("archives/notes.txt"), "not listed").unwrap();
+
+  let ls = |args: &[&str]| -> Vec<serde_json::Value> {
+    let mut all = vec![
+      "--frozen-time",
+      "2040-06-01T00:00:00Z",
+      "record",
+      "ls",
+      "--json",
+    ];
+    all.extend(args);
+    let output = env.w3name(&all).assert().success();
+    serde_json::from_str(&stdout(output.get_output())).unwrap()
+  };
+  let listed = ls(&["archives"]);
+  assert_eq!(listed.len(), 5);
+  // sorted by name, then sequence, with the unreadable file last
+  let mut names = [name.as_str(), other.as_str()];
+  names.sort();
+  let valid: Vec<_> = listed
+    .iter()
+    .filter(|entry| entry["verified"] == true)
+    .collect();
+  assert_eq!(valid.len(), 3);
+  assert!(valid.windows(2).all(|pair| {
+    let key = |entry: &serde_json::Value| {
+      (
+        entry["name"].as_str().unwrap().to_string(),
+        entry["sequence"].as_u64().unwrap(),
+      )
+    };
+    key(pair[0]) <= key(pair[1])
+  }));
+  let broken = &listed[4];
+  assert!(broken["path"].as_str().unwrap().ends_with("broken.w3r"));
+  assert_eq!(broken["name"], serde_json::Value::Null);
+  assert_eq!(broken["error"], "not a valid record archive");
+  let forged = listed
+    .iter()
+    .find(|entry| entry["path"].as_str().unwrap().ends_with("forged.w3r"))
+    .unwrap();
+  assert_eq!(forged["verified"], false);
+  assert_eq!(forged["name"], other.as_str());
+  assert_eq!(forged["value"], VALUE);
+  assert_eq!(forged["error"], "the archive's record is invalid");
+
+  // filters
+  let of_name = ls(&["archives", "--name", &name]);
+  assert_eq!(of_name.len(), 2);
+  assert_eq!(of_name[0]["sequence"], 0);
+  assert_eq!(of_name[1]["sequence"], 1);
+  let expired = ls(&["archives", "--expired-only"]);
+  assert_eq!(expired.len(), 2);
+  assert!(expired
+    .iter()
+    .all(|entry| entry["sequence"] == 0 && entry["expired"] == true));
+  let by_sequence = ls(&["archives", "--sort", "sequence", "--name", &other]);
+  assert_eq!(by_sequence.len(), 2);
+  assert_eq!(by_sequence[0]["sequence"], 0);
+
+  // wildcards are expanded, even when the shell doesn't
+  let pattern = format!("archives/{}-*.w3r", name);
+  assert_eq!(ls(&[&pattern]).len(), 2);
+  assert_eq!(ls(&["archives/nothing-*.w3r"]).len(), 0);
+  env
+    .w3name(&["record", "ls", "missing"])
+    .assert()
+    .code(USAGE);
+
+  // the table has a header and a row per file
+  let output = env.w3name(&["record", "ls", "archives"]).assert().success();
+  let text = stdout(output.get_output());
+  let lines: Vec<_> = text.lines().collect();
+  assert_eq!(lines.len(), 6);
+  assert!(lines[0].starts_with("PATH"), "{}", text);
+  assert!(lines[0].contains("VERIFIED"));
+  assert!(lines[5].contains("not a valid record archive"));
+  assert!(!text.contains('\x1b'), "{}", text);
+
+  // long names and paths are truncated to fit $COLUMNS, unless the table is --wide
+  let table = |args: &[&str]| {
+    let output = env
+      .w3name(&[&["record", "ls"], args, &["archives"]].concat())
+      .env("COLUMNS", "160")
+      .assert()
+      .success();
+    stdout(output.get_output())
+  };
+  let text = table(&[]);
+  assert!(
+    text.lines().all(|line| line.chars().count() <= 160),
+    "{}",
+    text
+  );
+  assert!(text.contains('…') && !text.contains(&*name), "{}", text);
+  let text = table(&["--wide"]);
+  assert!(text.contains(&*name) && !text.contains('…'), "{}", text);
+
+  // statuses are colored when asked, unless NO_COLOR is set, or --no-color given
+  let output = env
+    .w3name(&["record", "ls", "--color", "always", "archives"])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(text.contains("\x1b[32myes\x1b[0m"), "{}", text);
+  assert!(text.contains("\x1b[31mno\x1b[0m"), "{}", text);
+  for (args, no_color) in [(&["--color", "auto"][..], "1"), (&["--no-color"][..], "")] {
+    let output = env
+      .w3name(&[&["record", "ls"], args, &["archives"]].concat())
+      .env("NO_COLOR", no_color)
+      .assert()
+      .success();
+    assert!(!stdout(output.get_output()).contains('\x1b'));
+  }
+}
+
+#[test]
+fn countersigned_archives() {
+  let env = TestEnv::new("countersigned-archives");
+  let (key, name) = env.create_key("site.key");
+  let (_, operator) = env.create_key("ops.key");
+  let key = key.to_str().unwrap();
+  std::fs::create_dir(env.path("archives")).unwrap();
+  let parse = |archive: &str| env.w3name(&["parse", "--json", "--archive", archive]);
+
+  // missing: archives aren't countersigned unless asked
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      VALUE,
+      "--archive",
+      "plain.w3r",
+    ])
+    .assert()
+    .success();
+  let output = parse("plain.w3r").assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["countersigner"], serde_json::Value::Null);
+  let archive = std::fs::read_to_string(env.path("plain.w3r")).unwrap();
+  assert!(!archive.contains("countersignature"));
+
+  // valid: the operator's name is shown
+  for args in [
+    &["--archive", "signed.w3r", "--countersign-key", "ops.key"][..],
+    &["--archive", "archives", "--countersign-key", "ops.key"][..],
+  ] {
+    let mut publish = vec!["publish", "--key", key, "--value", VALUE];
+    publish.extend(args);
+    env.w3name(&publish).assert().success();
+  }
+  let output = parse("signed.w3r").assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["countersigner"], operator.as_str());
+  assert_eq!(json["name"], name.as_str());
+  let output = env
+    .w3name(&["parse", "--archive", "signed.w3r"])
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(
+    text.contains(&format!("countersigned by: {}\n", operator)),
+    "{}",
+    text
+  );
+
+  // tampered: any change to the archive breaks the countersignature
+  let signed: serde_json::Value =
+    serde_json::from_slice(&std::fs::read(env.path("signed.w3r")).unwrap()).unwrap();
+  let mut tampered = signed.clone();
+  tampered["source"] = "https://mirror.example/".into();
+  std::fs::write(env.path("archives/tampered.w3r"), tampered.to_string()).unwrap();
+  let output = parse("archives/tampered.w3r").assert().code(INVALID_RECORD);
+  assert_eq!(stdout(output.get_output()), "");
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(
+    stderr.contains("countersignature doesn't match"),
+    "{}",
+    stderr
+  );
+
+  let output = env
+    .w3name(&["record", "ls", "--json", "archives"])
+    .assert()
+    .success();
+  let listed: Vec<serde_json::Value> = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(listed.len(), 2);
+  let (valid, tampered): (Vec<_>, Vec<_>) =
+    listed.iter().partition(|entry| entry["verified"] == true);
+  assert_eq!(valid[0]["countersigner"], operator.as_str());
+  assert_eq!(tampered[0]["countersigner"], serde_json::Value::Null);
+  assert_eq!(
+    tampered[0]["error"],
+    "the archive's countersignature doesn't match its contents"
+  );
+  let output = env.w3name(&["record", "ls", "archives"]).assert().success();
+  let text = stdout(output.get_output());
+  assert!(text.lines().next().unwrap().contains("COUNTERSIGNER"));
+  assert!(text.contains(&operator));
+
+  // the name's own key attributes nothing, and a countersignature needs an archive
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      VALUE,
+      "--archive",
+      "own.w3r",
+      "--countersign-key",
+      key,
+    ])
+    .assert()
+    .code(USAGE);
+  env
+    .w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      VALUE,
+      "--countersign-key",
+      "ops.key",
+    ])
+    .assert()
+    .code(USAGE);
+  assert!(!env.path("own.w3r").exists());
+}
+
+#[test]
+fn publish_from_record_offline() {
+  let env = TestEnv::new("from-record-offline");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  std::fs::write(env.path("exported.txt"), env.service.record(&name).unwrap()).unwrap();
+  let requests = env.service.requests();
+
+  let args = [
+    "--offline",
+    "publish",
+    "--key",
+    key,
+    "--value",
+    "/ipfs/next",
+    "--from-record",
+    "exported.txt",
+  ];
+  // it can't publish, so it has to write the record somewhere
+  env.w3name(&args).assert().code(USAGE);
+
+  let mut with_archive = args.to_vec();
+  with_archive.extend(["--archive", "next.w3r"]);
+
+  // it takes the name's lock like an online publish
+  let lock_path = env.path("w3name/locks").join(format!("{}.lock", name));
+  std::fs::create_dir_all(lock_path.parent().unwrap()).unwrap();
+  let lock = std::fs::File::create(&lock_path).unwrap();
+  fs2::FileExt::lock_exclusive(&lock).unwrap();
+  let mut waiting = with_archive.clone();
+  waiting.extend(["--lock-timeout", "200ms"]);
+  let output = env.w3name(&waiting).assert().failure();
+  let stderr = String::from_utf8(output.get_output().stderr.clone()).unwrap();
+  assert!(
+    stderr.contains("another publish is in progress"),
+    "{}",
+    stderr
+  );
+  assert!(!env.path("next.w3r").exists());
+  drop(lock);
+
+  env.w3name(&with_archive).assert().success();
+  assert_eq!(env.service.requests(), requests);
+
+  let output = env
+    .w3name(&["parse", "--json", "--archive", "next.w3r"])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["value"], "/ipfs/next");
+  assert_eq!(json["sequence"], 1);
+}
+
+#[test]
+fn publish_from_record_cross_checks() {
+  let env = TestEnv::new("from-record-online");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  std::fs::write(env.path("old.txt"), env.service.record(&name).unwrap()).unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/second"])
+    .assert()
+    .success();
+  std::fs::write(env.path("latest.txt"), env.service.record(&name).unwrap()).unwrap();
+
+  // the service has a newer record than the old one
+  let publish_from = |record: &str, key: &str| {
+    env.w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      "/ipfs/third",
+      "--from-record",
+      record,
+      "--json",
+    ])
+  };
+  let output = publish_from("old.txt", key).assert().code(OUTDATED_RECORD);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(
+    stderr.contains("older than the service's record"),
+    "{}",
+    stderr
+  );
+
+  // records for another key are refused before anything is published
+  let (other_key, _) = env.create_key("other.key");
+  publish_from("latest.txt", other_key.to_str().unwrap())
+    .assert()
+    .code(INVALID_RECORD);
+
+  let output = publish_from("latest.txt", key).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["sequence"], 2);
+  assert_eq!(json["previous"]["resolver"], "record file");
+  assert_eq!(json["previous"]["sequence"], 1);
+}
+
+#[test]
+fn publish_to_several_targets() {
+  let env = TestEnv::new("publish-to");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  // stands in for a Kubo node and a delegated routing server
+  let dht = MockW3Name::start();
+  let kubo = format!("kubo={}", dht.url());
+  let routing = format!("routing={}", dht.url());
+  let unreachable = format!("routing=http://127.0.0.1:{}", free_port());
+
+  let output = env
+    .w3name(&[
+      "publish", "--key", key, "--value", VALUE, "--to", "w3name", "--to", &kubo, "--json",
+    ])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["published"], true);
+  let targets = json["targets"].as_array().unwrap();
+  assert_eq!(targets.len(), 2);
+  assert!(targets[0]["target"]
+    .as_str()
+    .unwrap()
+    .starts_with("w3name "));
+  assert!(targets[1]["target"].as_str().unwrap().starts_with("kubo "));
+  assert!(targets.iter().all(|target| target["published"] == true));
+  assert_eq!(env.service.record(&name), dht.record(&name));
+
+  // by default, every target must succeed
+  let publish_to = |value: &str, require: &str| {
+    env.w3name(&[
+      "publish",
+      "--key",
+      key,
+      "--value",
+      value,
+      "--to",
+      &routing,
+      "--to",
+      &unreachable,
+      "--require",
+      require,
+    ])
+  };
+  let output = publish_to("/ipfs/second", "all").assert().code(FAILURE);
+  let out = stdout(output.get_output());
+  assert!(out.contains("to 1 of 2 targets"), "{}", out);
+  assert!(out.contains(": published"), "{}", out);
+  assert!(out.contains(": failed: "), "{}", out);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("1 of 2 targets failed"), "{}", stderr);
+
+  publish_to("/ipfs/third", "any").assert().success();
+  let record = dht.record(&name).unwrap();
+  let parsed = env
+    .w3name(&["parse", "--json", "--name", &name, &record])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(parsed.get_output())).unwrap();
+  assert_eq!(json["value"], "/ipfs/third");
+  // the current record is resolved from the service, which didn't get the second one
+  assert_eq!(json["sequence"], 1);
+
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE, "--to", "dht"])
+    .assert()
+    .code(USAGE);
+}
+
+#[test]
+fn bench_resolve_and_publish() {
+  let env = TestEnv::new("bench");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let bench = |args: &[&str]| {
+    let base = ["bench", args[0], "--requests", "20", "--concurrency", "4"];
+    env.w3name(&[&base[..], &args[1..], &["--rate-limit", "1000", "--json"]].concat())
+  };
+
+  // publishing needs a current record
+  bench(&["publish", "--key", key, "--dry-upload"])
+    .assert()
+    .code(NOT_FOUND);
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  let record = env.service.record(&name).unwrap();
+
+  let output = bench(&["resolve", "--name", &name]).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["requests"], 20);
+  assert_eq!(json["succeeded"], 20);
+  assert!(
+    json["latency_ms"]["p99"].as_f64().unwrap() >= json["latency_ms"]["p50"].as_f64().unwrap()
+  );
+
+  // failures are counted by class, and not retried unless asked
+  env.service.fail_next(3, StatusCode::SERVICE_UNAVAILABLE);
+  let output = bench(&["resolve", "--name", &name]).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["failed"], 3);
+  assert_eq!(json["errors"]["http_503"], 3);
+  env.service.fail_next(1, StatusCode::SERVICE_UNAVAILABLE);
+  let output = bench(&["resolve", "--name", &name, "--retries", "1"])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["succeeded"], 20);
+
+  // a dry upload leaves the name's record as it was
+  let output = bench(&["publish", "--key", key, "--dry-upload"])
+    .assert()
+    .success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["succeeded"], 20);
+  assert_eq!(env.service.record(&name).unwrap(), record);
+
+  let output = env
+    .w3name(&["bench", "publish", "--key", key, "--requests", "5"])
+    .assert()
+    .success();
+  assert!(stdout(output.get_output()).starts_with("OPERATION"));
+  // successors change the record, but not the value
+  assert_ne!(env.service.record(&name).unwrap(), record);
+  let output = env
+    .w3name(&["resolve", "--no-cache", &name])
+    .assert()
+    .success();
+  assert!(stdout(output.get_output()).contains(VALUE));
+
+  // the public service is only benchmarked on request, and nothing is sent before refusing
+  let requests = env.service.requests();
+  let output = env
+    .w3name(&["bench", "publish", "--key", key])
+    .env("W3NAME_ENDPOINT", "https://name.web3.storage")
+    .assert()
+    .code(USAGE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("--i-own-this-endpoint"), "{}", stderr);
+  assert_eq!(env.service.requests(), requests);
+}
+
+#[test]
+fn resolve_require_sequence_at_least() {
+  let env = TestEnv::new("require-sequence");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let gateway = MockW3Name::start();
+  let gateway_url = gateway.url();
+  let require = |sequence: &str, args: &[&str]| {
+    env.w3name(
+      &[
+        &["resolve", "--require-sequence-at-least", sequence],
+        args,
+        &[name.as_str()],
+      ]
+      .concat(),
+    )
+  };
+
+  // no record: the usual exit code
+  require("0", &[]).assert().code(NOT_FOUND);
+
+  // the service has sequence 1, the gateway is behind at sequence 0
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/first"])
+    .assert()
+    .success();
+  gateway.set_record(&name, &env.service.record(&name).unwrap());
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+
+  let output = require("1", &[]).assert().success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+  let output = require("2", &[]).assert().code(SEQUENCE_BEHIND);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("sequence 1"), "{}", stderr);
+
+  // every source must serve the sequence, unless a quorum says otherwise
+  require("1", &["--gateway", &gateway_url])
+    .assert()
+    .code(SEQUENCE_BEHIND);
+  require("1", &["--gateway", &gateway_url, "--quorum", "1"])
+    .assert()
+    .success();
+  require("0", &["--gateway", &gateway_url])
+    .assert()
+    .success();
+  require("1", &["--gateway", &gateway_url, "--quorum", "3"])
+    .assert()
+    .code(USAGE);
+  env
+    .w3name(&["resolve", "--quorum", "1", &name])
+    .assert()
+    .code(USAGE);
+
+  // once the gateway catches up, the gate opens
+  gateway.set_record(&name, &env.service.record(&name).unwrap());
+  require("1", &["--gateway", &gateway_url])
+    .assert()
+    .success();
+
+  // watch stops at the same threshold
+  let output = env
+    .w3name(&["watch", "--interval", "1", "--until-sequence", "1", &name])
+    .assert()
+    .success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+}
+
+#[cfg(unix)]
+#[test]
+fn watch_exec_skips_the_initial_record() {
+  let env = TestEnv::new("watch-exec-on-start");
+  let (key, name) = env.create_key("site.key");
+  env
+    .w3name(&["publish", "--key", key.to_str().unwrap(), "--value", VALUE])
+    .assert()
+    .success();
+  let marker = env.path("ran");
+  let exec = format!("touch '{}'", marker.display());
+  let watch = |extra: &[&str]| {
+    let args = [
+      &["watch", "--interval", "1", "--until-sequence", "0"],
+      extra,
+      &["--exec", &exec, &name],
+    ]
+    .concat();
+    let output = env.w3name(&args).assert().success();
+    assert_eq!(stdout(output.get_output()).trim(), VALUE);
+  };
+
+  // the record found at startup isn't a change
+  watch(&[]);
+  assert!(!marker.exists());
+  watch(&["--exec-on-start"]);
+  assert!(marker.exists());
+}
+
+#[test]
+fn resolve_falls_back_to_gateways() {
+  let env = TestEnv::new("resolve-fallback");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let gateway = MockW3Name::start();
+  let command = |args: &[&str]| {
+    let mut command = env.w3name(args);
+    command.env("W3NAME_GATEWAY", gateway.url());
+    command
+  };
+  let resolve =
+    |args: &[&str]| command(&[&["resolve", "--no-cache"], args, &[name.as_str()]].concat());
+
+  // the service has no record, e.g. because it lost it, but the gateway has one
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .env("W3NAME_ENDPOINT", gateway.url())
+    .assert()
+    .success();
+  assert!(env.service.record(&name).is_none());
+  let output = resolve(&["--json"]).assert().success();
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["value"], VALUE);
+  assert_eq!(json["source"]["kind"], "gateway");
+  assert!(json["source"]["url"]
+    .as_str()
+    .unwrap()
+    .starts_with(&gateway.url()));
+  let output = resolve(&["--verbose"]).assert().success();
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("but gateway http://"), "{}", stderr);
+
+  let requests = gateway.requests();
+  resolve(&["--no-fallback"]).assert().code(NOT_FOUND);
+  assert_eq!(gateway.requests(), requests);
+
+  // bulk resolve and watch ask the gateway too
+  std::fs::write(
+    env.path("names.json"),
+    format!("{{\"{}\": \"site\"}}", name),
+  )
+  .unwrap();
+  let output = command(&[
+    "resolve",
+    "--manifest-file",
+    env.path("names.json").to_str().unwrap(),
+    "--jsonl",
+  ])
+  .assert()
+  .success();
+  let json: serde_json::Value = serde_json::from_str(stdout(output.get_output()).trim()).unwrap();
+  assert_eq!(json["value"], VALUE);
+  assert_eq!(json["source"], "gateway");
+  let output = command(&["watch", "--interval", "1", "--until-sequence", "0", &name])
+    .assert()
+    .success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+
+  // status only asks the service with --no-fallback
+  let output = command(&["status", "--json", "--no-fallback", &name])
+    .assert()
+    .code(UNHEALTHY);
+  let json: serde_json::Value = serde_json::from_str(&stdout(output.get_output())).unwrap();
+  assert_eq!(json["sources"].as_array().unwrap().len(), 1);
+
+  // the service is down
+  env.service.set_rate_limited(true);
+  let output = resolve(&[]).assert().success();
+  assert_eq!(stdout(output.get_output()).trim(), VALUE);
+
+  // everything is down: the service's error, with the gateway's
+  gateway.set_rate_limited(true);
+  let output = resolve(&[]).assert().code(SERVICE);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(
+    stderr.contains(&format!("gateway {}", gateway.url())),
+    "{}",
+    stderr
+  );
+
+  // no source has a record: still not found
+  env.service.set_rate_limited(false);
+  gateway.set_rate_limited(false);
+  let (_, other) = env.create_key("other.key");
+  let output = command(&["resolve", "--no-cache", &other])
+    .assert()
+    .code(NOT_FOUND);
+  let stderr = String::from_utf8_lossy(&output.get_output().stderr).into_owned();
+  assert!(stderr.contains("failed: no record (404)"), "{}", stderr);
+}
+
+#[test]
+fn status_verdicts() {
+  let env = TestEnv::new("status");
+  let (key, name) = env.create_key("site.key");
+  let key = key.to_str().unwrap();
+  let gateway = MockW3Name::start();
+  let gateways = format!("{},{}", env.service.url(), gateway.url());
+  let status = |args: &[&str]| {
+    let mut command = env.w3name(&[&["status", "--json"], args, &[name.as_str()]].concat());
+    command.env("W3NAME_GATEWAY", &gateways);
+    command
+  };
+  let report = |output: &std::process::Output| -> serde_json::Value {
+    serde_json::from_str(&stdout(output)).unwrap()
+  };
+
+  // no source has a record
+  let output = status(&[]).assert().code(UNHEALTHY);
+  let json = report(output.get_output());
+  assert_eq!(json["verdict"], "fail");
+  assert_eq!(json["sources"].as_array().unwrap().len(), 3);
+  assert!(json["sources"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .all(|source| source["status"] == "not_found"));
+
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/first"])
+    .assert()
+    .success();
+  let first = env.service.record(&name).unwrap();
+  env
+    .w3name(&["publish", "--key", key, "--value", VALUE])
+    .assert()
+    .success();
+  let second = env.service.record(&name).unwrap();
+
+  // every source agrees
+  gateway.set_record(&name, &second);
+  let output = status(&[]).assert().success();
+  let json = report(output.get_output());
+  assert_eq!(json["verdict"], "pass");
+  assert_eq!(json["sequence"], 1);
+  assert_eq!(json["value"], VALUE);
+  assert_eq!(json["sources"][0]["kind"], "service");
+  assert_eq!(json["sources"][2]["signature"], "v2");
+  assert!(json["findings"].as_array().unwrap().is_empty());
+
+  let output = env
+    .w3name(&["status", &name])
+    .env("W3NAME_GATEWAY", &gateways)
+    .assert()
+    .success();
+  let text = stdout(output.get_output());
+  assert!(text.starts_with(&format!("{}: PASS\n", name)), "{}", text);
+  assert!(text.contains("newest: sequence 1, expires in"), "{}", text);
+
+  // a gateway with an older record is stale
+  gateway.set_record(&name, &first);
+  let output = status(&[]).assert().code(HEALTH_WARNING);
+  let json = report(output.get_output());
+  assert_eq!(json["verdict"], "warn");
+  assert_eq!(json["sources"][2]["sequence"], 0);
+  let finding = json["findings"][0]["message"].as_str().unwrap();
+  assert!(finding.contains("is stale"), "{}", finding);
+
+  // a gateway that fails, or doesn't answer in time, doesn't hold up the report
+  gateway.set_record(&name, &second);
+  gateway.fail_next(1, StatusCode::BAD_GATEWAY);
+  let output = status(&[]).assert().code(HEALTH_WARNING);
+  assert_eq!(
+    report(output.get_output())["sources"][2]["status"],
+    "failed"
+  );
+
+  gateway.set_delay(Duration::from_secs(10));
+  let started = std::time::Instant::now();
+  let output = status(&["--timeout", "1s"]).assert().code(HEALTH_WARNING);
+  assert!(started.elapsed() < Duration::from_secs(8));
+  let json = report(output.get_output());
+  assert_eq!(json["sources"][0]["status"], "found");
+  assert_eq!(json["sources"][2]["status"], "timed_out");
+
+  // a different record with the same sequence, signed with the same key
+  let other = MockW3Name::start();
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/other"])
+    .env("W3NAME_ENDPOINT", other.url())
+    .env("W3NAME_GATEWAY", "none")
+    .assert()
+    .success();
+  env
+    .w3name(&["publish", "--key", key, "--value", "/ipfs/conflict"])
+    .env("W3NAME_ENDPOINT", other.url())
+    .env("W3NAME_GATEWAY", "none")
+    .assert()
+    .success();
+  let output = env
+    .w3name(&["status", "--json", &name])
+    .env("W3NAME_GATEWAY", other.url())
+    .assert()
+    .code(UNHEALTHY);
+  let json = report(output.get_output());
+  assert_eq!(json["verdict"], "fail");
+  let finding = json["findings"][0]["message"].as_str().unwrap();
+  assert!(finding.contains("disagrees"), "{}", finding);
+}
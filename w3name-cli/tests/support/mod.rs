@@ -0,0 +1,404 @@
+//! Test support for running the `w3name` binary against a local mock of the w3name service.
+//!
+//! [MockW3Name] implements the service's record endpoints (`GET` and `POST /name/<name>`), and
+//! serves published records from `GET /ipns/<name>` like a trustless gateway, and from
+//! `POST /api/v0/routing/get` like the RPC API of a Kubo node. Records can also be put with
+//! `POST /api/v0/routing/put` like on a Kubo node, and with `PUT /routing/v1/ipns/<name>` like on a
+//! delegated routing server. Its behavior can be
+//! changed while it runs: failing the next requests, responding slowly, or rate limiting.
+//!
+//! [TestEnv] pairs a mock with a scratch directory, and builds `w3name` commands that use them
+//! instead of the real service and the user's cache and keystore.
+
+#![allow(dead_code)]
+
+use std::{
+  collections::HashMap,
+  convert::Infallible,
+  env, fs,
+  io::{Read, Write},
+  net::{SocketAddr, TcpListener, TcpStream},
+  path::PathBuf,
+  process::{self, Child, Stdio},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+  thread,
+  time::Duration,
+};
+
+use assert_cmd::Command;
+use hyper::{
+  service::{make_service_fn, service_fn},
+  Body, Method, Request, Response, Server, StatusCode,
+};
+
+#[derive(Default)]
+struct State {
+  /// base64-encoded records, keyed by name
+  records: HashMap<String, String>,
+  /// the number of upcoming requests to fail, and the status to fail them with
+  failures: Option<(usize, StatusCode)>,
+  /// if set, every response is delayed by this long
+  delay: Option<Duration>,
+  /// if set, every request is rejected with 429 Too Many Requests
+  rate_limited: bool,
+  /// records served in turn for a name, and the index of the next one, overriding `records`
+  rotations: HashMap<String, (Vec<String>, usize)>,
+}
+
+pub struct MockW3Name {
+  addr: SocketAddr,
+  state: Arc<Mutex<State>>,
+  requests: Arc<AtomicUsize>,
+}
+
+impl MockW3Name {
+  /// Starts the mock on a random local port, on a thread of its own. It runs until the test
+  /// process exits.
+  pub fn start() -> MockW3Name {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let state = Arc::new(Mutex::new(State::default()));
+    let requests = Arc::new(AtomicUsize::new(0));
+
+    let service_state = state.clone();
+    let counter = requests.clone();
+    thread::spawn(move || {
+      let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+      runtime.block_on(async move {
+        let make_svc = make_service_fn(move |_| {
+          let state = service_state.clone();
+          let counter = counter.clone();
+          async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+              counter.fetch_add(1, Ordering::SeqCst);
+              handle(state.clone(), req)
+            }))
+          }
+        });
+        Server::from_tcp(listener)
+          .unwrap()
+          .serve(make_svc)
+          .await
+          .unwrap();
+      });
+    });
+
+    MockW3Name {
+      addr,
+      state,
+      requests,
+    }
+  }
+
+  pub fn url(&self) -> String {
+    format!("http://{}", self.addr)
+  }
+
+  /// Returns the number of requests received so far.
+  pub fn requests(&self) -> usize {
+    self.requests.load(Ordering::SeqCst)
+  }
+
+  /// Makes the next `count` requests fail with `status`. Later requests succeed again.
+  pub fn fail_next(&self, count: usize, status: StatusCode) {
+    self.state.lock().unwrap().failures = Some((count, status));
+  }
+
+  /// Delays every subsequent response by `delay`.
+  pub fn set_delay(&self, delay: Duration) {
+    self.state.lock().unwrap().delay = Some(delay);
+  }
+
+  /// Rejects every subsequent request with 429 Too Many Requests, until called with `false`.
+  pub fn set_rate_limited(&self, rate_limited: bool) {
+    self.state.lock().unwrap().rate_limited = rate_limited;
+  }
+
+  /// Serves `record` (base64-encoded) for `name`, as if it had been published.
+  pub fn set_record(&self, name: &str, record: &str) {
+    let mut state = self.state.lock().unwrap();
+    state.records.insert(name.to_string(), record.to_string());
+  }
+
+  /// Serves `records` (base64-encoded) for `name` in turn, one per request, like replicas of the
+  /// service that are out of sync.
+  pub fn rotate_records(&self, name: &str, records: Vec<String>) {
+    let mut state = self.state.lock().unwrap();
+    state.rotations.insert(name.to_string(), (records, 0));
+  }
+
+  /// Returns the base64-encoded record currently stored for `name`.
+  pub fn record(&self, name: &str) -> Option<String> {
+    self.state.lock().unwrap().records.get(name).cloned()
+  }
+}
+
+async fn handle(
+  state: Arc<Mutex<State>>,
+  req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+  let delay = state.lock().unwrap().delay;
+  if let Some(delay) = delay {
+    tokio::time::sleep(delay).await;
+  }
+
+  {
+    let mut state = state.lock().unwrap();
+    if state.rate_limited {
+      return Ok(json(
+        StatusCode::TOO_MANY_REQUESTS,
+        r#"{"message":"rate limited"}"#,
+      ));
+    }
+    if let Some((count, status)) = state.failures {
+      state.failures = count.checked_sub(1).filter(|c| *c > 0).map(|c| (c, status));
+      return Ok(json(status, r#"{"message":"mock failure"}"#));
+    }
+  }
+
+  let path = req.uri().path().to_string();
+  if path == "/api/v0/routing/get" {
+    return Ok(kubo_routing_get(&state, &req));
+  }
+  if path == "/api/v0/routing/put" {
+    return Ok(kubo_routing_put(&state, req).await);
+  }
+  if let Some(name) = path.strip_prefix("/routing/v1/ipns/") {
+    let name = name.to_string();
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let mut state = state.lock().unwrap();
+    state.records.insert(name, base64::encode(&body));
+    return Ok(Response::new(Body::empty()));
+  }
+  let (name, gateway) = match (path.strip_prefix("/name/"), path.strip_prefix("/ipns/")) {
+    (Some(name), _) => (name.to_string(), false),
+    (_, Some(name)) => (name.to_string(), true),
+    _ => {
+      return Ok(json(
+        StatusCode::NOT_FOUND,
+        r#"{"message":"no such route"}"#,
+      ))
+    }
+  };
+
+  let rotated = state
+    .lock()
+    .unwrap()
+    .rotations
+    .get_mut(&name)
+    .map(|(records, next)| {
+      let record = records[*next % records.len()].clone();
+      *next += 1;
+      record
+    });
+  let res = match (req.method(), gateway) {
+    (&Method::GET, false) if rotated.is_some() => json(
+      StatusCode::OK,
+      &format!(r#"{{"record":"{}"}}"#, rotated.unwrap()),
+    ),
+    (&Method::GET, false) => match state.lock().unwrap().records.get(&name) {
+      Some(record) => json(StatusCode::OK, &format!(r#"{{"record":"{}"}}"#, record)),
+      None => json(StatusCode::NOT_FOUND, r#"{"message":"record not found"}"#),
+    },
+    (&Method::GET, true) => match state.lock().unwrap().records.get(&name) {
+      Some(record) => Response::builder()
+        .header("Content-Type", "application/vnd.ipfs.ipns-record")
+        .body(Body::from(base64::decode(record).unwrap()))
+        .unwrap(),
+      None => json(StatusCode::NOT_FOUND, r#"{"message":"record not found"}"#),
+    },
+    (&Method::POST, false) => {
+      let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+      let record = String::from_utf8(body.to_vec()).unwrap();
+      state.lock().unwrap().records.insert(name.clone(), record);
+      json(StatusCode::ACCEPTED, &format!(r#"{{"id":"{}"}}"#, name))
+    }
+    _ => json(
+      StatusCode::METHOD_NOT_ALLOWED,
+      r#"{"message":"method not allowed"}"#,
+    ),
+  };
+  Ok(res)
+}
+
+/// Answers like Kubo: a stream of query events ending with the record, or a "not found" error.
+fn kubo_routing_get(state: &Mutex<State>, req: &Request<Body>) -> Response<Body> {
+  let arg = req
+    .uri()
+    .query()
+    .and_then(|query| query.strip_prefix("arg=%2Fipns%2F"))
+    .unwrap_or_default();
+  match state.lock().unwrap().records.get(arg) {
+    Some(record) if req.method() == Method::POST => {
+      let events = [
+        r#"{"Extra":"","ID":"12D3KooWMockPeerA","Responses":null,"Type":0}"#.to_string(),
+        r#"{"Extra":"dial backoff","ID":"12D3KooWMockPeerB","Responses":null,"Type":3}"#
+          .to_string(),
+        format!(
+          r#"{{"Extra":"{}","ID":"","Responses":null,"Type":5}}"#,
+          record
+        ),
+      ];
+      Response::builder()
+        .header("Content-Type", "application/json")
+        .header("X-Chunked-Output", "1")
+        .body(Body::from(events.join("\n") + "\n"))
+        .unwrap()
+    }
+    Some(_) => json(StatusCode::METHOD_NOT_ALLOWED, "405 - Method Not Allowed"),
+    None => json(
+      StatusCode::INTERNAL_SERVER_ERROR,
+      r#"{"Message":"routing: not found","Code":0,"Type":"error"}"#,
+    ),
+  }
+}
+
+/// Stores the record in the multipart body of a Kubo `routing/put` request.
+async fn kubo_routing_put(state: &Mutex<State>, req: Request<Body>) -> Response<Body> {
+  let name = req
+    .uri()
+    .query()
+    .and_then(|query| query.strip_prefix("arg=%2Fipns%2F"))
+    .unwrap_or_default()
+    .to_string();
+  let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+  // the record is everything between the part's headers and the closing boundary
+  let start = find(&body, b"\r\n\r\n").unwrap() + 4;
+  let end = start + find(&body[start..], b"\r\n--").unwrap();
+  let record = base64::encode(&body[start..end]);
+  state.lock().unwrap().records.insert(name, record);
+  json(
+    StatusCode::OK,
+    r#"{"Extra":"","ID":"12D3KooWMockPeerA","Responses":null,"Type":6}"#,
+  )
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+  haystack
+    .windows(needle.len())
+    .position(|window| window == needle)
+}
+
+fn json(status: StatusCode, body: &str) -> Response<Body> {
+  Response::builder()
+    .status(status)
+    .header("Content-Type", "application/json")
+    .body(Body::from(body.to_string()))
+    .unwrap()
+}
+
+/// A mock service plus a scratch directory, which is the working directory, home, cache and
+/// keystore of the commands built by [TestEnv::w3name].
+pub struct TestEnv {
+  pub service: MockW3Name,
+  pub dir: PathBuf,
+}
+
+impl TestEnv {
+  /// Creates an environment with a fresh scratch directory. `test` must be unique among the tests.
+  pub fn new(test: &str) -> TestEnv {
+    let dir = env::temp_dir().join(format!("w3name-cli-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    TestEnv {
+      service: MockW3Name::start(),
+      dir,
+    }
+  }
+
+  /// Returns a `w3name` command with the given arguments, isolated in this environment.
+  pub fn w3name(&self, args: &[&str]) -> Command {
+    let mut cmd = Command::from_std(self.std_command(args));
+    cmd.timeout(Duration::from_secs(30));
+    cmd
+  }
+
+  /// Starts `w3name` with the given arguments in the background, for commands that don't exit on
+  /// their own. The caller must kill it.
+  pub fn spawn(&self, args: &[&str]) -> Child {
+    self
+      .std_command(args)
+      .stdout(Stdio::piped())
+      .stderr(Stdio::null())
+      .spawn()
+      .unwrap()
+  }
+
+  fn std_command(&self, args: &[&str]) -> process::Command {
+    let mut cmd = process::Command::new(assert_cmd::cargo::cargo_bin("w3name"));
+    cmd
+      .args(args)
+      .current_dir(&self.dir)
+      .env("HOME", &self.dir)
+      .env("XDG_CACHE_HOME", self.dir.join("cache"))
+      .env("W3NAME_HOME", self.dir.join("w3name"))
+      .env("W3NAME_ENDPOINT", self.service.url())
+      .env("W3NAME_GATEWAY", self.service.url())
+      .env_remove("RUST_LOG")
+      .env_remove("COLUMNS")
+      .env_remove("NO_COLOR")
+      .env_remove("W3NAME_DEFAULT_VALIDITY")
+      .env_remove("W3NAME_DEFAULT_TTL");
+    cmd
+  }
+
+  /// Runs `w3name create` and returns the path of the new key file and its name.
+  pub fn create_key(&self, file: &str) -> (PathBuf, String) {
+    self
+      .w3name(&["create", "--output", file])
+      .assert()
+      .success();
+    let path = self.dir.join(file);
+    let output = self
+      .w3name(&["name", path.to_str().unwrap()])
+      .assert()
+      .success();
+    let name = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    (path, name.trim().to_string())
+  }
+
+  pub fn path(&self, file: &str) -> PathBuf {
+    self.dir.join(file)
+  }
+}
+
+impl Drop for TestEnv {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.dir);
+  }
+}
+
+/// Returns a free local port to listen on.
+pub fn free_port() -> u16 {
+  TcpListener::bind("127.0.0.1:0")
+    .unwrap()
+    .local_addr()
+    .unwrap()
+    .port()
+}
+
+/// Returns the body of `GET http://127.0.0.1:<port><path>`, or `None` if nothing is listening yet.
+pub fn http_get(port: u16, path: &str) -> Option<String> {
+  let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+  write!(
+    stream,
+    "GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\nConnection: close\r\n\r\n",
+    path
+  )
+  .ok()?;
+  let mut response = String::new();
+  stream.read_to_string(&mut response).ok()?;
+  let (_, body) = response.split_once("\r\n\r\n")?;
+  Some(body.to_string())
+}
+
+/// Returns the standard output of a finished command as a string.
+pub fn stdout(output: &std::process::Output) -> String {
+  String::from_utf8(output.stdout.clone()).unwrap()
+}
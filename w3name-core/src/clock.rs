@@ -0,0 +1,81 @@
+//! Where the current time comes from.
+//!
+//! Everything that depends on the current time, like the validity of a new revision or whether a
+//! record has expired, asks a [Clock]. That's the [SystemClock] unless one is given, e.g. a
+//! [ManualClock] in tests, which only moves when it's told to.
+
+use std::{
+  fmt::Debug,
+  sync::{Arc, Mutex},
+};
+
+use crate::timestamp::{self, Duration, Timestamp};
+
+/// A source of the current time.
+pub trait Clock: Debug + Send + Sync {
+  fn now(&self) -> Timestamp;
+}
+
+/// The system's clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> Timestamp {
+    timestamp::now()
+  }
+}
+
+/// A clock that stands still until it's [set](Self::set) or [advanced](Self::advance).
+///
+/// ## Example
+///
+/// ```rust
+/// use w3name_core::{timestamp::Duration, Clock, ManualClock, SystemClock};
+///
+/// let start = SystemClock.now();
+/// let clock = ManualClock::new(start);
+/// assert_eq!(clock.now(), start);
+///
+/// clock.advance(Duration::days(1));
+/// assert_eq!(clock.now(), start + Duration::days(1));
+/// ```
+#[derive(Debug)]
+pub struct ManualClock {
+  now: Mutex<Timestamp>,
+}
+
+impl ManualClock {
+  pub fn new(now: Timestamp) -> ManualClock {
+    ManualClock {
+      now: Mutex::new(now),
+    }
+  }
+
+  /// Sets the time.
+  pub fn set(&self, now: Timestamp) {
+    *self.now.lock().unwrap() = now;
+  }
+
+  /// Moves the time forward by `by`, or backward if it's negative.
+  pub fn advance(&self, by: Duration) {
+    *self.now.lock().unwrap() += by;
+  }
+}
+
+impl Clock for ManualClock {
+  fn now(&self) -> Timestamp {
+    *self.now.lock().unwrap()
+  }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+  fn now(&self) -> Timestamp {
+    (**self).now()
+  }
+}
+
+/// Returns the [SystemClock], shared.
+pub(crate) fn system() -> Arc<dyn Clock> {
+  Arc::new(SystemClock)
+}
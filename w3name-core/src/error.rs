@@ -0,0 +1,461 @@
+//! The errors of the record logic: names, keys, revisions and IPNS records.
+
+use std::error::Error;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct NameError;
+
+impl Display for NameError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "name error")
+  }
+}
+
+impl Error for NameError {}
+
+/// Explains why [Name::parse](crate::Name::parse) rejected a string, with advice on fixing it.
+///
+/// It's attached to the [NameError] report, so it's printed along with the error, and can be
+/// retrieved with `report.downcast_ref::<NameParseDiagnostic>()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NameParseDiagnostic {
+  /// The string is empty.
+  Empty,
+  /// The string is a valid name apart from surrounding whitespace or a trailing slash.
+  ExtraCharacters,
+  /// The first character isn't a known multibase prefix.
+  UnknownMultibase(char),
+  /// A character isn't valid in the string's multibase encoding. `position` is the character's
+  /// zero-based index.
+  InvalidCharacter {
+    position: usize,
+    character: char,
+    base: String,
+  },
+  /// The string decodes, but not to a CID, usually because it was truncated.
+  WrongLength { length: usize },
+  /// The string is a CID, but not of a public key, e.g. the CID of some IPFS content.
+  WrongCodec { codec: u64 },
+  /// The CID holds a hash of the public key rather than the key itself (as for RSA keys), so the
+  /// key can't be recovered from it.
+  HashedKey,
+  /// The CID holds a public key of an unknown type.
+  UnsupportedKeyType(u64),
+  /// The CID holds bytes that aren't a valid public key.
+  InvalidKey,
+}
+
+impl Display for NameParseDiagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    use NameParseDiagnostic::*;
+    match self {
+      Empty => write!(f, "the name is empty"),
+      ExtraCharacters => write!(
+        f,
+        "remove the trailing slash or whitespace around the name"
+      ),
+      UnknownMultibase(c) => write!(
+        f,
+        "'{}' isn't a known multibase prefix; names usually start with k51",
+        c
+      ),
+      InvalidCharacter {
+        position,
+        character,
+        base,
+      } => write!(
+        f,
+        "'{}' at position {} isn't valid in {}; check for typos",
+        character, position, base
+      ),
+      WrongLength { length } => write!(
+        f,
+        "the name looks truncated or corrupted ({} characters; ed25519 names are 62 characters in base36)",
+        length
+      ),
+      WrongCodec { codec } => match codec {
+        0x55 | 0x70 | 0x71 | 0x0129 => write!(
+          f,
+          "this looks like an IPFS content CID, not an IPNS name; it could be a value, as /ipfs/<cid>"
+        ),
+        _ => write!(
+          f,
+          "this is a CID with codec 0x{:x}, but names have the libp2p-key codec (0x72)",
+          codec
+        ),
+      },
+      HashedKey => write!(
+        f,
+        "the name is a hash of the public key (e.g. an RSA key), so the key can't be recovered from it"
+      ),
+      UnsupportedKeyType(key_type) => write!(
+        f,
+        "the name's key type ({}) isn't one of RSA, ed25519, secp256k1 or ECDSA",
+        key_type
+      ),
+      InvalidKey => write!(f, "the name doesn't contain a valid public key"),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct InvalidRoutingKey;
+
+impl Display for InvalidRoutingKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid IPNS routing key")
+  }
+}
+
+impl Error for InvalidRoutingKey {}
+
+#[derive(Debug)]
+pub struct InvalidCidString;
+
+impl Display for InvalidCidString {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid cid string")
+  }
+}
+
+impl Error for InvalidCidString {}
+
+#[derive(Debug)]
+pub struct InvalidMulticodecCode;
+
+impl Display for InvalidMulticodecCode {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid multicodec code")
+  }
+}
+
+impl Error for InvalidMulticodecCode {}
+
+/// A public key is of a type this build can't decode or verify signatures with, e.g. a key type
+/// introduced after this release. `codec` is the key type number from the key's protobuf encoding.
+#[derive(Debug)]
+pub struct UnsupportedKeyType {
+  pub codec: u64,
+}
+
+impl Display for UnsupportedKeyType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "unsupported public key type {}", self.codec)
+  }
+}
+
+impl Error for UnsupportedKeyType {}
+
+/// A record is signed with a key of an [UnsupportedKeyType], so its signature can't be verified.
+#[derive(Debug)]
+pub struct UnsupportedSignatureAlgorithm;
+
+impl Display for UnsupportedSignatureAlgorithm {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "unsupported record signature algorithm")
+  }
+}
+
+impl Error for UnsupportedSignatureAlgorithm {}
+
+#[derive(Debug)]
+pub struct InvalidCryptoKey;
+
+impl Display for InvalidCryptoKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid crypto key")
+  }
+}
+
+impl Error for InvalidCryptoKey {}
+
+#[derive(Debug)]
+pub struct SigningError;
+
+impl Display for SigningError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "signing error")
+  }
+}
+
+impl Error for SigningError {}
+
+#[derive(Debug)]
+pub struct CborError;
+
+impl Display for CborError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "cbor error")
+  }
+}
+
+impl Error for CborError {}
+
+#[derive(Debug)]
+pub struct ProtobufError;
+
+impl Display for ProtobufError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "protobuf error")
+  }
+}
+
+impl Error for ProtobufError {}
+
+#[derive(Debug)]
+pub struct InvalidIpnsV1Signature;
+
+impl Display for InvalidIpnsV1Signature {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid IPNS signature (v1)")
+  }
+}
+
+impl Error for InvalidIpnsV1Signature {}
+
+#[derive(Debug)]
+pub struct InvalidIpnsV2Signature;
+
+impl Display for InvalidIpnsV2Signature {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid IPNS signature (v2)")
+  }
+}
+
+impl Error for InvalidIpnsV2Signature {}
+
+#[derive(Debug)]
+pub struct InvalidIpnsV2SignatureData;
+
+impl Display for InvalidIpnsV2SignatureData {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "invalid IPNS v2 signature data (does not match protobuf values)"
+    )
+  }
+}
+
+impl Error for InvalidIpnsV2SignatureData {}
+
+#[derive(Debug)]
+pub struct InvalidUtf8;
+
+impl Display for InvalidUtf8 {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid utf-8")
+  }
+}
+
+impl Error for InvalidUtf8 {}
+
+#[derive(Debug)]
+pub struct InvalidDateString;
+
+impl Display for InvalidDateString {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid RFC-3339 date string")
+  }
+}
+
+impl Error for InvalidDateString {}
+
+#[derive(Debug)]
+pub struct RevisionError;
+
+impl Display for RevisionError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "revision error")
+  }
+}
+
+impl Error for RevisionError {}
+
+#[derive(Debug)]
+pub struct ValidityTooShort;
+
+impl Display for ValidityTooShort {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "validity period is shorter than the required minimum")
+  }
+}
+
+impl Error for ValidityTooShort {}
+
+/// A validity period ends too far in the future to be written in a record: past the range of
+/// dates altogether, or after the year 9999, the last one an RFC 3339 timestamp can express.
+#[derive(Debug)]
+pub struct ValidityOutOfRange;
+
+impl Display for ValidityOutOfRange {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "validity period ends after the year 9999")
+  }
+}
+
+impl Error for ValidityOutOfRange {}
+
+#[derive(Debug)]
+pub struct RecordExpired;
+
+impl Display for RecordExpired {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "record has expired")
+  }
+}
+
+impl Error for RecordExpired {}
+
+#[derive(Debug)]
+pub struct RecordTooLarge;
+
+impl Display for RecordTooLarge {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "record exceeds the maximum IPNS record size")
+  }
+}
+
+impl Error for RecordTooLarge {}
+
+/// A field of a record from an untrusted source exceeds its limit in
+/// [DecodeLimits](crate::ipns::DecodeLimits).
+#[derive(Debug)]
+pub struct RecordFieldTooLarge;
+
+impl Display for RecordFieldTooLarge {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "record field exceeds its size limit")
+  }
+}
+
+impl Error for RecordFieldTooLarge {}
+
+/// A value that should be a JSON document isn't one. See [json_value](crate::json_value).
+#[derive(Debug)]
+pub struct InvalidJsonValue;
+
+impl Display for InvalidJsonValue {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "value is not valid JSON")
+  }
+}
+
+impl Error for InvalidJsonValue {}
+
+/// What makes an [UnusualCharacter] unusual.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnusualCharacterKind {
+  /// A C0 or C1 control character, like a tab, a newline or `DEL`.
+  Control,
+  /// A character that takes up no space, like U+200B ZERO WIDTH SPACE or a byte order mark.
+  ZeroWidth,
+  /// A bidirectional formatting character, which reorders the text displayed around it.
+  BidiControl,
+}
+
+impl Display for UnusualCharacterKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      UnusualCharacterKind::Control => write!(f, "control character"),
+      UnusualCharacterKind::ZeroWidth => write!(f, "zero-width character"),
+      UnusualCharacterKind::BidiControl => write!(f, "bidirectional control character"),
+    }
+  }
+}
+
+/// A value contains a character that's invisible, or that changes how the text around it is
+/// displayed, which is almost always pasted in by accident. See
+/// [Value::check_text](crate::Value::check_text).
+#[derive(Debug)]
+pub struct UnusualCharacter {
+  pub character: char,
+  /// Where the character starts in the value, in bytes.
+  pub offset: usize,
+  pub kind: UnusualCharacterKind,
+}
+
+impl Display for UnusualCharacter {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "value contains a {} (U+{:04X}) at byte offset {}",
+      self.kind, self.character as u32, self.offset
+    )
+  }
+}
+
+impl Error for UnusualCharacter {}
+
+#[derive(Debug)]
+pub struct UnsupportedValidityType;
+
+impl Display for UnsupportedValidityType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "unsupported IPNS validity type")
+  }
+}
+
+impl Error for UnsupportedValidityType {}
+
+#[derive(Debug)]
+pub struct NameMismatch;
+
+impl Display for NameMismatch {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "record is for a different name than the one requested")
+  }
+}
+
+impl Error for NameMismatch {}
+
+/// The public key embedded in a record doesn't hash to the name it's for.
+///
+/// Names of large keys, like RSA keys, only hold a hash of the key, so their records must embed the
+/// key itself in `pubKey`. A record with a different key (or none) can't be for the name, however
+/// well its signature checks out against that key.
+#[derive(Debug)]
+pub struct EmbeddedKeyMismatch;
+
+impl Display for EmbeddedKeyMismatch {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "record's embedded public key doesn't match the name")
+  }
+}
+
+impl Error for EmbeddedKeyMismatch {}
+
+/// A key isn't the key of the name it was expected to be for.
+#[derive(Debug)]
+pub struct UnexpectedName;
+
+impl Display for UnexpectedName {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "key is for a different name than expected")
+  }
+}
+
+impl Error for UnexpectedName {}
+
+/// A [Proof](crate::Proof) is malformed, or doesn't verify.
+#[derive(Debug)]
+pub struct ProofError;
+
+impl Display for ProofError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "invalid proof of control")
+  }
+}
+
+impl Error for ProofError {}
+
+#[derive(Debug)]
+pub struct IpnsError;
+
+impl Display for IpnsError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "IPNS record error")
+  }
+}
+
+impl Error for IpnsError {}
@@ -0,0 +1,1311 @@
+use crate::{
+  error::{
+    CborError, EmbeddedKeyMismatch, InvalidIpnsV1Signature, InvalidIpnsV2Signature,
+    InvalidIpnsV2SignatureData, InvalidRoutingKey, IpnsError, NameError, NameMismatch,
+    RecordFieldTooLarge, RecordTooLarge, SigningError, UnsupportedKeyType,
+    UnsupportedSignatureAlgorithm, UnsupportedValidityType,
+  },
+  hash::Hasher,
+  ipns_pb::IpnsEntry,
+  name::{decode_public_key, LIBP2P_MULTICODEC, MAX_INLINE_KEY_LENGTH},
+  timestamp, Name, Revision, ValidityType,
+};
+use libp2p_core::identity::{Keypair, PublicKey};
+use multihash::MultihashDigest;
+use prost::Message;
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::str::from_utf8;
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+
+mod batch;
+pub use batch::*;
+
+/// The maximum size of a serialized IPNS record, per the [spec](https://specs.ipfs.tech/ipns/ipns-record/#record-size-limit).
+pub const MAX_RECORD_SIZE: usize = 10 * 1024;
+
+/// The maximum size of a record's value that [deserialize_ipns_entry_untrusted] accepts by default
+/// (see [DecodeLimits::max_value_size]).
+pub const MAX_VALUE_SIZE: usize = 4 * 1024;
+
+/// The prefix of the routing key that a name's records are stored under in the DHT.
+const ROUTING_KEY_PREFIX: &[u8] = b"/ipns/";
+
+/// Returns the binary routing key for `name`, as defined by the
+/// [spec](https://specs.ipfs.tech/ipns/ipns-record/#routing-record): `/ipns/` followed by the
+/// multihash of the name's public key.
+///
+/// This is the same key that Kubo and other libp2p implementations store the name's records under.
+pub fn routing_key_for_name(name: &Name) -> Vec<u8> {
+  let mut key = ROUTING_KEY_PREFIX.to_vec();
+  key.extend_from_slice(&name.to_cid().hash().to_bytes());
+  key
+}
+
+/// Recovers the [Name] from a binary routing key, as returned by [routing_key_for_name].
+///
+/// Fails with [InvalidRoutingKey] if `key` isn't an IPNS routing key, and with a [NameError] if it
+/// doesn't embed a valid public key. Keys that were hashed into the routing key (e.g. RSA keys)
+/// can't be recovered.
+pub fn name_from_routing_key(key: &[u8]) -> Result<Name, NameError> {
+  let hash_bytes = key
+    .strip_prefix(ROUTING_KEY_PREFIX)
+    .ok_or_else(|| {
+      report!(InvalidRoutingKey).attach_printable("routing key doesn't start with /ipns/")
+    })
+    .change_context(NameError)?;
+  let hash = multihash::Multihash::from_bytes(hash_bytes)
+    .report()
+    .change_context(InvalidRoutingKey)
+    .change_context(NameError)?;
+  if hash.code() != u64::from(Hasher::Identity) {
+    return Err(report!(NameError).attach_printable(
+      "routing key contains a hash of the public key, so the key can't be recovered from it",
+    ));
+  }
+  Name::from_bytes(hash.digest())
+}
+
+pub fn revision_to_ipns_entry(
+  revision: &Revision,
+  signer: &Keypair,
+) -> Result<IpnsEntry, IpnsError> {
+  let ttl = revision.ttl_nanos();
+
+  log::debug!(
+    "Creating IPNS entry: value={}, sequence={}, ttl={}ns",
+    revision.value(),
+    revision.sequence(),
+    ttl
+  );
+
+  let validity_type = u64::try_from(revision.validity_type().code())
+    .report()
+    .change_context(IpnsError)?;
+  let data = v2_signature_data(
+    revision.value_bytes(),
+    &timestamp::format_rfc3339(revision.validity()).change_context(IpnsError)?,
+    validity_type,
+    revision.sequence(),
+    ttl,
+  )
+  .change_context(IpnsError)?;
+  let signature_v2 = create_v2_signature(signer, &data).change_context(IpnsError)?;
+
+  // V2-only mode: ONLY set signature_v2 and data fields (and pub_key for keys that names hash)
+  // Leave V1 fields empty - the server expects V2-only records to have empty V1 fields
+  // All actual data is in the CBOR 'data' field
+  let entry = IpnsEntry {
+    signature_v2,
+    data,
+    pub_key: pub_key_field(&signer.public()),
+    ..Default::default()
+  };
+
+  log::debug!(
+    "Created V2-only IPNS entry: data_len={}, sig_len={}",
+    entry.data.len(),
+    entry.signature_v2.len()
+  );
+
+  Ok(entry)
+}
+
+/// Like [revision_to_ipns_entry], but also fills in the V1 fields and the V1 signature, for
+/// endpoints that reject V2-only records.
+///
+/// The V1 fields hold the same values as the V2 data, so [v1_v2_inconsistencies] finds none.
+pub fn revision_to_hybrid_ipns_entry(
+  revision: &Revision,
+  signer: &Keypair,
+) -> Result<IpnsEntry, IpnsError> {
+  let mut entry = revision_to_ipns_entry(revision, signer)?;
+  entry.value = revision.value_bytes().to_vec();
+  entry.validity = timestamp::format_rfc3339(revision.validity())
+    .change_context(IpnsError)?
+    .into_bytes();
+  entry.sequence = revision.sequence();
+  entry.ttl = revision.ttl_nanos();
+  entry.signature =
+    create_v1_signature(signer, &entry.value, &entry.validity).change_context(IpnsError)?;
+  Ok(entry)
+}
+
+pub fn serialize_ipns_entry(entry: &IpnsEntry) -> Result<Vec<u8>, IpnsError> {
+  let mut buf = Vec::with_capacity(entry.encoded_len());
+  entry.encode(&mut buf).report().change_context(IpnsError)?;
+  Ok(buf)
+}
+
+/// Decodes a serialized record without any limits beyond those of the protobuf decoder.
+///
+/// Use this for records from trusted sources only, e.g. ones this process signed. Records from the
+/// network or from files should go through [deserialize_ipns_entry_untrusted].
+pub fn deserialize_ipns_entry(entry_bytes: &[u8]) -> Result<IpnsEntry, IpnsError> {
+  let entry = IpnsEntry::decode(entry_bytes)
+    .report()
+    .change_context(IpnsError)?;
+  Ok(entry)
+}
+
+/// Bounds on the parts of a record from an untrusted source, checked by
+/// [deserialize_ipns_entry_with_limits] before anything else looks at the record.
+///
+/// The defaults accept any record that follows the spec, by a wide margin: real values are paths
+/// well under a kilobyte, and the CBOR `data` of a record is a flat map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+  /// The maximum size of the serialized record, checked before decoding it. Defaults to
+  /// [MAX_RECORD_SIZE].
+  pub max_record_size: usize,
+
+  /// The maximum size of the V2 CBOR `data` field. Defaults to [MAX_RECORD_SIZE].
+  pub max_data_size: usize,
+
+  /// The maximum size of the value, in both the V1 field and the CBOR data. Defaults to 4 KiB.
+  pub max_value_size: usize,
+
+  /// The maximum size of the validity timestamp, in both the V1 field and the CBOR data. An RFC 3339
+  /// timestamp with nanoseconds takes about 30 bytes. Defaults to 64 bytes.
+  pub max_validity_size: usize,
+
+  /// How deeply the items of the CBOR data may be nested: the data of a valid record is a map of
+  /// byte strings and integers, nested 1 deep. Defaults to 4.
+  pub max_cbor_depth: usize,
+}
+
+impl Default for DecodeLimits {
+  fn default() -> Self {
+    DecodeLimits {
+      max_record_size: MAX_RECORD_SIZE,
+      max_data_size: MAX_RECORD_SIZE,
+      max_value_size: MAX_VALUE_SIZE,
+      max_validity_size: 64,
+      max_cbor_depth: 4,
+    }
+  }
+}
+
+/// Decodes a serialized record from an untrusted source, e.g. a gateway, applying the default
+/// [DecodeLimits].
+///
+/// Besides the limits, the CBOR `data` is checked to be a single well-formed item whose declared
+/// lengths fit in it, so that decoding it can't allocate more than its size or recurse deeply.
+/// Malformed or oversized input fails with an [IpnsError]; it never panics.
+pub fn deserialize_ipns_entry_untrusted(entry_bytes: &[u8]) -> Result<IpnsEntry, IpnsError> {
+  deserialize_ipns_entry_with_limits(entry_bytes, &DecodeLimits::default())
+}
+
+/// Like [deserialize_ipns_entry_untrusted], with the given `limits`.
+pub fn deserialize_ipns_entry_with_limits(
+  entry_bytes: &[u8],
+  limits: &DecodeLimits,
+) -> Result<IpnsEntry, IpnsError> {
+  if entry_bytes.len() > limits.max_record_size {
+    return Err(
+      report!(RecordTooLarge)
+        .attach_printable(format!(
+          "record is {} bytes, the limit is {} bytes",
+          entry_bytes.len(),
+          limits.max_record_size
+        ))
+        .change_context(IpnsError),
+    );
+  }
+  let entry = deserialize_ipns_entry(entry_bytes)?;
+
+  check_field_size("data", entry.data.len(), limits.max_data_size)?;
+  check_field_size("value", entry.value.len(), limits.max_value_size)?;
+  check_field_size("validity", entry.validity.len(), limits.max_validity_size)?;
+  if !entry.data.is_empty() {
+    check_cbor_structure(&entry.data, limits.max_cbor_depth).change_context(IpnsError)?;
+    let data: SignatureV2Data = serde_cbor::from_slice(&entry.data[..])
+      .report()
+      .change_context(CborError)
+      .change_context(IpnsError)?;
+    check_field_size("data.Value", data.Value.len(), limits.max_value_size)?;
+    check_field_size(
+      "data.Validity",
+      data.Validity.len(),
+      limits.max_validity_size,
+    )?;
+  }
+  Ok(entry)
+}
+
+fn check_field_size(field: &str, size: usize, limit: usize) -> Result<(), IpnsError> {
+  if size <= limit {
+    return Ok(());
+  }
+  Err(
+    report!(RecordFieldTooLarge)
+      .attach_printable(format!(
+        "the {} field is {} bytes, the limit is {} bytes",
+        field, size, limit
+      ))
+      .change_context(IpnsError),
+  )
+}
+
+/// Checks that `data` is exactly one well-formed CBOR item, nested no more than `max_depth` deep,
+/// whose declared lengths and item counts fit in `data`.
+///
+/// This walks the item headers without decoding anything, using a stack of the items left at each
+/// level rather than recursion.
+fn check_cbor_structure(data: &[u8], max_depth: usize) -> Result<(), CborError> {
+  let malformed = |message: &str, pos: usize| {
+    report!(CborError).attach_printable(format!("{} at byte {} of the CBOR data", message, pos))
+  };
+
+  let mut pos = 0;
+  // the items left to read at each open level, or `None` for indefinite-length items, which end
+  // with a "break" byte; the outermost level is the single top-level item
+  let mut levels: Vec<Option<u64>> = vec![Some(1)];
+  loop {
+    while let Some(Some(0)) = levels.last() {
+      levels.pop();
+    }
+    let Some(level) = levels.last_mut() else {
+      break;
+    };
+    let Some(&initial) = data.get(pos) else {
+      return Err(malformed("truncated item", pos));
+    };
+    let start = pos;
+    pos += 1;
+
+    if initial == 0xff {
+      if level.is_none() {
+        levels.pop();
+        continue;
+      }
+      return Err(malformed("unexpected break", start));
+    }
+    if let Some(remaining) = level {
+      *remaining -= 1;
+    }
+
+    let major = initial >> 5;
+    let argument = match initial & 0x1f {
+      info @ 0..=23 => Some(u64::from(info)),
+      info @ 24..=27 => {
+        let size = 1 << (info - 24);
+        let Some(bytes) = data.get(pos..pos + size) else {
+          return Err(malformed("truncated item header", start));
+        };
+        pos += size;
+        Some(
+          bytes
+            .iter()
+            .fold(0, |acc, byte| (acc << 8) | u64::from(*byte)),
+        )
+      }
+      31 if (2..=5).contains(&major) => None,
+      _ => return Err(malformed("invalid item header", start)),
+    };
+
+    // every item takes at least a byte, so a count that exceeds the bytes left is a lie
+    let left = (data.len() - pos) as u64;
+    match (major, argument) {
+      // integers and simple values are complete
+      (0 | 1 | 7, _) => {}
+      (2 | 3, Some(len)) => {
+        if len > left {
+          return Err(malformed("string longer than the data", start));
+        }
+        pos += len as usize;
+      }
+      (4, Some(count)) | (5, Some(count)) => {
+        let items = if major == 5 {
+          count.checked_mul(2)
+        } else {
+          Some(count)
+        };
+        match items {
+          Some(items) if items <= left => levels.push(Some(items)),
+          _ => return Err(malformed("container with more items than the data", start)),
+        }
+      }
+      (2..=5, None) => levels.push(None),
+      // a tag applies to the next item
+      _ => levels.push(Some(1)),
+    }
+    if levels.len() > max_depth + 1 {
+      return Err(malformed(
+        &format!("items nested more than {} deep", max_depth),
+        start,
+      ));
+    }
+  }
+
+  if pos != data.len() {
+    return Err(malformed("trailing bytes", pos));
+  }
+  Ok(())
+}
+
+/// A field whose value in a hybrid V1+V2 record differs between the V1 protobuf fields and the V2 CBOR data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldMismatch {
+  /// The field name: one of `value`, `validity`, `validityType`, `sequence` or `ttl`.
+  pub field: &'static str,
+  /// The V1 (protobuf) value, formatted for display.
+  pub v1: String,
+  /// The V2 (CBOR) value, formatted for display.
+  pub v2: String,
+}
+
+impl Display for FieldMismatch {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} differs between V1 and V2 data: V1 has {:?}, V2 has {:?}",
+      self.field, self.v1, self.v2
+    )
+  }
+}
+
+/// Returns all fields that differ between the V1 fields and the V2 CBOR data of a hybrid V1+V2 record.
+///
+/// V2-only records (with empty V1 fields) have no inconsistencies. Fails if the record has no V2 data,
+/// or it can't be decoded.
+pub fn v1_v2_inconsistencies(entry: &IpnsEntry) -> Result<Vec<FieldMismatch>, IpnsError> {
+  if entry.data.is_empty() {
+    return Err(report!(InvalidIpnsV2SignatureData).change_context(IpnsError));
+  }
+
+  let data: SignatureV2Data = serde_cbor::from_slice(&entry.data[..])
+    .report()
+    .change_context(InvalidIpnsV2SignatureData)
+    .change_context(IpnsError)?;
+
+  // V2-only records have empty V1 fields - this is the expected format
+  let is_v2_only =
+    entry.value.is_empty() && entry.validity.is_empty() && entry.ttl == 0 && entry.sequence == 0;
+  if is_v2_only {
+    log::debug!("V2-only record detected (empty V1 fields)");
+    return Ok(vec![]);
+  }
+
+  let mut mismatches = vec![];
+  let mut check = |field, v1: String, v2: String| {
+    if v1 != v2 {
+      mismatches.push(FieldMismatch { field, v1, v2 });
+    }
+  };
+  check(
+    "value",
+    String::from_utf8_lossy(&entry.value).into_owned(),
+    String::from_utf8_lossy(&data.Value).into_owned(),
+  );
+  check(
+    "validity",
+    String::from_utf8_lossy(&entry.validity).into_owned(),
+    String::from_utf8_lossy(&data.Validity).into_owned(),
+  );
+  check(
+    "validityType",
+    entry.validity_type.to_string(),
+    data.ValidityType.to_string(),
+  );
+  check(
+    "sequence",
+    entry.sequence.to_string(),
+    data.Sequence.to_string(),
+  );
+  check("ttl", entry.ttl.to_string(), data.TTL.to_string());
+  Ok(mismatches)
+}
+
+/// Like [validate_ipns_entry], but V1/V2 inconsistencies in hybrid records are returned instead of failing validation.
+///
+/// Signatures are still checked. Since [revision_from_ipns_entry_unchecked] prefers the V2 data, the resulting
+/// [Revision] reflects the signed V2 fields, and the returned mismatches can be reported as warnings.
+pub fn validate_ipns_entry_lenient(
+  entry: &IpnsEntry,
+  public_key: &PublicKey,
+) -> Result<Vec<FieldMismatch>, IpnsError> {
+  if !entry.signature_v2.is_empty() && !entry.data.is_empty() {
+    validate_v2_signature(public_key, &entry.signature_v2, &entry.data)
+      .change_context(IpnsError)?;
+    ensure_supported_validity_type(entry)?;
+    return v1_v2_inconsistencies(entry);
+  }
+
+  validate_ipns_entry(entry, public_key)?;
+  Ok(vec![])
+}
+
+/// A version of IPNS record signature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SignatureVersion {
+  /// The legacy signature over the value, validity type and validity.
+  V1,
+  /// The signature over the record's CBOR `data` field.
+  V2,
+}
+
+impl Display for SignatureVersion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SignatureVersion::V1 => write!(f, "v1"),
+      SignatureVersion::V2 => write!(f, "v2"),
+    }
+  }
+}
+
+/// Which signatures a record carries, and which one [validate_ipns_entry] checks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SignatureInfo {
+  pub has_signature_v1: bool,
+  pub has_signature_v2: bool,
+  /// V2 if the record has a V2 signature and data, otherwise V1.
+  pub validated_with: SignatureVersion,
+}
+
+impl SignatureInfo {
+  /// Returns `true` for hybrid records, which carry both signatures.
+  pub fn is_hybrid(&self) -> bool {
+    self.has_signature_v1 && self.has_signature_v2
+  }
+}
+
+/// Returns which signatures `entry` carries, and which one [validate_ipns_entry] checks.
+pub fn signature_info(entry: &IpnsEntry) -> SignatureInfo {
+  let validated_with = if !entry.signature_v2.is_empty() && !entry.data.is_empty() {
+    SignatureVersion::V2
+  } else {
+    SignatureVersion::V1
+  };
+  SignatureInfo {
+    has_signature_v1: !entry.signature.is_empty(),
+    has_signature_v2: !entry.signature_v2.is_empty(),
+    validated_with,
+  }
+}
+
+/// Verifies only the V1 signature of `entry`, even if it has a V2 signature.
+///
+/// [validate_ipns_entry] ignores the V1 signature of hybrid records, so use this to check that it's
+/// valid too, e.g. for compatibility with implementations that only understand V1.
+pub fn verify_v1_signature(entry: &IpnsEntry, public_key: &PublicKey) -> Result<(), IpnsError> {
+  validate_v1_signature(entry, public_key).change_context(IpnsError)
+}
+
+/// Validates the signature(s) of `entry` against `public_key`.
+///
+/// Fails with [UnsupportedValidityType] if the record declares a validity type other than
+/// [ValidityType::Eol], since there's no way to tell whether such a record is still valid.
+pub fn validate_ipns_entry(entry: &IpnsEntry, public_key: &PublicKey) -> Result<(), IpnsError> {
+  if signature_info(entry).validated_with == SignatureVersion::V2 {
+    log::debug!("Validating V2 IPNS entry");
+    validate_v2_signature(public_key, &entry.signature_v2, &entry.data)
+      .change_context(IpnsError)?;
+    ensure_supported_validity_type(entry)?;
+    validate_v2_data_matches_entry_data(entry).change_context(IpnsError)?;
+    log::debug!("V2 validation successful");
+
+    return Ok(());
+  }
+
+  log::debug!("Validating V1 IPNS entry");
+  // the V1 signature covers the validity type as the string "EOL", so check the type first
+  // to report an unsupported type rather than a bad signature
+  ensure_supported_validity_type(entry)?;
+  validate_v1_signature(entry, public_key).change_context(IpnsError)
+}
+
+/// Returns the validity type declared by `entry`: the signed V2 data's type if present, otherwise the V1 field.
+pub fn validity_type_of(entry: &IpnsEntry) -> Result<ValidityType, IpnsError> {
+  if entry.data.is_empty() {
+    return Ok(entry.validity_type.into());
+  }
+  let data: SignatureV2Data = serde_cbor::from_slice(&entry.data[..])
+    .report()
+    .change_context(IpnsError)?;
+  // codes that don't fit the protobuf enum can't be known types either
+  Ok(
+    i32::try_from(data.ValidityType)
+      .map(ValidityType::from)
+      .unwrap_or(ValidityType::Unknown(i32::MAX)),
+  )
+}
+
+fn ensure_supported_validity_type(entry: &IpnsEntry) -> Result<(), IpnsError> {
+  match validity_type_of(entry)? {
+    ValidityType::Eol => Ok(()),
+    validity_type => Err(
+      report!(UnsupportedValidityType)
+        .attach_printable(format!("validity type: {}", validity_type))
+        .change_context(IpnsError),
+    ),
+  }
+}
+
+/// Returns the public key embedded in `entry`, if it has one.
+///
+/// Fails with [UnsupportedSignatureAlgorithm] if the key is of an [UnsupportedKeyType], since the
+/// record's signature can't be verified with it.
+pub fn embedded_public_key(entry: &IpnsEntry) -> Result<Option<PublicKey>, IpnsError> {
+  if entry.pub_key.is_empty() {
+    return Ok(None);
+  }
+  match decode_public_key(&entry.pub_key) {
+    Ok(key) => Ok(Some(key)),
+    Err(err_report) if err_report.contains::<UnsupportedKeyType>() => Err(
+      err_report
+        .change_context(UnsupportedSignatureAlgorithm)
+        .change_context(IpnsError),
+    ),
+    Err(err_report) => Err(err_report.change_context(IpnsError)),
+  }
+}
+
+/// Returns the `pubKey` of records signed with `public_key`: its encoding if names hash it (e.g. RSA
+/// keys), since the key can't be recovered from the name then, and nothing otherwise.
+fn pub_key_field(public_key: &PublicKey) -> Vec<u8> {
+  let encoded = public_key.to_protobuf_encoding();
+  if encoded.len() > MAX_INLINE_KEY_LENGTH {
+    encoded
+  } else {
+    Vec::new()
+  }
+}
+
+/// Returns the [Name] whose string form is `identifier`, to validate `entry` for with
+/// [validate_ipns_entry_for_name].
+///
+/// Names of small keys, like Ed25519 keys, hold the key and are parsed like [Name::parse]. Names
+/// of larger keys, like RSA keys, only hold a sha2-256 hash of the key, so it's taken from the
+/// record's `pubKey`, which must hash to the name: otherwise, this fails with
+/// [EmbeddedKeyMismatch].
+pub fn name_for_entry(identifier: &str, entry: &IpnsEntry) -> Result<Name, IpnsError> {
+  let parse_err = match Name::parse(identifier) {
+    Ok(name) => return Ok(name),
+    Err(err_report) => err_report,
+  };
+  let hash = match cid::Cid::try_from(identifier) {
+    Ok(cid)
+      if cid.codec() == LIBP2P_MULTICODEC && cid.hash().code() == u64::from(Hasher::Sha2_256) =>
+    {
+      *cid.hash()
+    }
+    _ => return Err(parse_err.change_context(IpnsError)),
+  };
+
+  if entry.pub_key.is_empty() {
+    return Err(
+      report!(EmbeddedKeyMismatch)
+        .attach_printable(format!(
+          "{} holds a hash of its key, but the record doesn't embed the key",
+          identifier
+        ))
+        .change_context(IpnsError),
+    );
+  }
+  if Hasher::Sha2_256.digest(&entry.pub_key).digest() != hash.digest() {
+    return Err(
+      report!(EmbeddedKeyMismatch)
+        .attach_printable(format!("the record's key doesn't hash to {}", identifier))
+        .change_context(IpnsError),
+    );
+  }
+  Name::from_bytes(&entry.pub_key).change_context(IpnsError)
+}
+
+/// Validates `entry` against the key of `name`, the name it was requested for.
+///
+/// In addition to [validate_ipns_entry], this rejects records whose embedded `pubKey` doesn't match `name`,
+/// failing with a [NameMismatch] in the report. Use this for records fetched from a service or gateway,
+/// rather than trusting them to return the right record.
+///
+/// Records for names that only hold a hash of their key (e.g. RSA keys) must embed the key
+/// itself, since that's what the record is checked against where the name's key isn't known.
+/// One without it, or with a different encoding of the key, fails with an [EmbeddedKeyMismatch]
+/// too.
+pub fn validate_ipns_entry_for_name(entry: &IpnsEntry, name: &Name) -> Result<(), IpnsError> {
+  if name.is_key_hashed() && entry.pub_key != name.encoded_key() {
+    let problem = if entry.pub_key.is_empty() {
+      format!(
+        "{} holds a hash of its key, but the record doesn't embed the key",
+        name
+      )
+    } else {
+      format!("the record's key doesn't hash to {}", name)
+    };
+    return Err(
+      report!(EmbeddedKeyMismatch)
+        .attach_printable(problem)
+        .change_context(NameMismatch)
+        .change_context(IpnsError),
+    );
+  }
+  // comparing the encodings avoids decoding the embedded key in the common case
+  if entry.pub_key != name.encoded_key() {
+    if let Some(embedded) = embedded_public_key(entry)? {
+      if &embedded != name.public_key() {
+        return Err(
+          report!(NameMismatch)
+            .attach_printable(format!(
+              "record has public key for {}, expected {}",
+              Name::from_public_key(&embedded),
+              name
+            ))
+            .change_context(IpnsError),
+        );
+      }
+    }
+  }
+  validate_ipns_entry(entry, name.public_key())
+}
+
+/// Converts `entry` into a [Revision] without checking its signature or that it belongs to `name`.
+///
+/// Only use this for a record that's already been validated, e.g. with [validate_ipns_entry_lenient];
+/// otherwise use [Revision::try_from_entry], which validates it first.
+///
+/// Fails with [UnsupportedValidityType] if the record's validity isn't an end-of-life timestamp.
+pub fn revision_from_ipns_entry_unchecked(
+  entry: &IpnsEntry,
+  name: &Name,
+) -> Result<Revision, IpnsError> {
+  ensure_supported_validity_type(entry)?;
+
+  // V2 records have data in CBOR format - prefer this if available
+  if !entry.data.is_empty() {
+    log::debug!("Reading V2 IPNS record from CBOR data");
+    // V2 record: Extract data from CBOR
+    let data: SignatureV2Data = serde_cbor::from_slice(&entry.data[..])
+      .report()
+      .change_context(IpnsError)?;
+
+    let validity_str = from_utf8(&data.Validity)
+      .report()
+      .change_context(IpnsError)?;
+    let validity = timestamp::parse_rfc3339(validity_str).change_context(IpnsError)?;
+
+    log::debug!(
+      "V2 record: value={}, sequence={}, ttl={}ns",
+      String::from_utf8_lossy(&data.Value),
+      data.Sequence,
+      data.TTL
+    );
+
+    let rev = Revision::new(name, "", validity, ttl_from_nanos(data.TTL)?, data.Sequence);
+    Ok(rev.with_value_bytes(data.Value))
+  } else {
+    log::debug!("Reading V1 IPNS record from protobuf fields");
+    // V1 record: Use V1 fields
+    let rev = Revision::new(
+      name,
+      "",
+      from_utf8(&entry.validity)
+        .report()
+        .change_context(IpnsError)
+        .and_then(|encoded| timestamp::parse_rfc3339(encoded).change_context(IpnsError))?,
+      ttl_from_nanos(entry.ttl)?,
+      entry.sequence,
+    );
+    Ok(rev.with_value_bytes(entry.value.clone()))
+  }
+}
+
+/// Converts `entry` into a [Revision] without validating it.
+#[deprecated(
+  since = "0.2.7",
+  note = "use `Revision::try_from_entry`, or `revision_from_ipns_entry_unchecked` for a record that's already been validated"
+)]
+pub fn revision_from_ipns_entry(entry: &IpnsEntry, name: &Name) -> Result<Revision, IpnsError> {
+  revision_from_ipns_entry_unchecked(entry, name)
+}
+
+fn ttl_from_nanos(nanos: u64) -> Result<timestamp::Duration, IpnsError> {
+  timestamp::duration_from_nanos(nanos)
+    .ok_or_else(|| report!(IpnsError).attach_printable(format!("TTL too long: {}ns", nanos)))
+}
+
+fn v1_signature_data(value_bytes: &[u8], validity_bytes: &[u8]) -> Vec<u8> {
+  let mut buf = value_bytes.to_vec();
+  buf.extend("EOL".as_bytes()); // validity type (we only support Eol)
+  buf.extend(validity_bytes);
+  buf
+}
+
+fn v2_signature_data(
+  value: &[u8],
+  validity: &str,
+  validity_type: u64,
+  sequence: u64,
+  ttl: u64,
+) -> Result<Vec<u8>, CborError> {
+  let data = SignatureV2Data {
+    Value: value.to_vec(),
+    Validity: validity.as_bytes().to_vec(),
+    ValidityType: validity_type,
+    Sequence: sequence,
+    TTL: ttl,
+  };
+  let encoded = serde_cbor::to_vec(&data)
+    .report()
+    .change_context(CborError)?;
+
+  Ok(encoded)
+}
+
+const V2_SIGNATURE_PREFIX: &[u8] = b"ipns-signature:";
+
+thread_local! {
+  /// Reused between calls to [with_v2_signature_message], so validating many records doesn't allocate for each.
+  static V2_MESSAGE_BUF: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Calls `f` with the message covered by V2 signatures: `"ipns-signature:"` followed by the record's `data`.
+fn with_v2_signature_message<T>(data: &[u8], f: impl FnOnce(&[u8]) -> T) -> T {
+  V2_MESSAGE_BUF.with(|buf| {
+    let mut buf = buf.borrow_mut();
+    buf.clear();
+    buf.extend_from_slice(V2_SIGNATURE_PREFIX);
+    buf.extend_from_slice(data);
+    f(&buf)
+  })
+}
+
+fn validate_v2_signature(
+  public_key: &PublicKey,
+  sig: &[u8],
+  data: &[u8],
+) -> Result<(), InvalidIpnsV2Signature> {
+  if with_v2_signature_message(data, |msg| public_key.verify(msg, sig)) {
+    Ok(())
+  } else {
+    Err(report!(InvalidIpnsV2Signature))
+  }
+}
+
+fn validate_v2_data_matches_entry_data(
+  entry: &IpnsEntry,
+) -> Result<(), InvalidIpnsV2SignatureData> {
+  let mismatches = v1_v2_inconsistencies(entry).change_context(InvalidIpnsV2SignatureData)?;
+  if mismatches.is_empty() {
+    return Ok(());
+  }
+
+  // Hybrid V1+V2 record (for backward compatibility): V1 fields must match the V2 CBOR data
+  log::error!("V1/V2 field mismatch detected");
+  let mut err_report = report!(InvalidIpnsV2SignatureData);
+  for mismatch in mismatches {
+    err_report = err_report.attach_printable(mismatch);
+  }
+  Err(err_report)
+}
+
+fn validate_v1_signature(
+  entry: &IpnsEntry,
+  public_key: &PublicKey,
+) -> Result<(), InvalidIpnsV1Signature> {
+  let data = v1_signature_data(&entry.value, &entry.validity);
+  if public_key.verify(&data, &entry.signature) {
+    Ok(())
+  } else {
+    Err(report!(InvalidIpnsV1Signature))
+  }
+}
+
+fn create_v1_signature(
+  signer: &Keypair,
+  value_bytes: &[u8],
+  validity_bytes: &[u8],
+) -> Result<Vec<u8>, SigningError> {
+  let msg = v1_signature_data(value_bytes, validity_bytes);
+  let sig = signer.sign(&msg).report().change_context(SigningError)?;
+  Ok(sig)
+}
+
+fn create_v2_signature(signer: &Keypair, sig_data: &[u8]) -> Result<Vec<u8>, SigningError> {
+  with_v2_signature_message(sig_data, |msg| signer.sign(msg))
+    .report()
+    .change_context(SigningError)
+}
+
+#[allow(non_snake_case)]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SignatureV2Data {
+  #[serde(with = "serde_bytes")]
+  Value: Vec<u8>,
+  #[serde(with = "serde_bytes")]
+  Validity: Vec<u8>,
+  ValidityType: u64,
+  Sequence: u64,
+  TTL: u64,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{timestamp::Duration, Clock, SystemClock};
+  use crate::{RevisionBuilder, WritableName};
+
+  /// The routing key for k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu: `/ipns/`
+  /// followed by the identity multihash of the protobuf-encoded ed25519 key.
+  const ROUTING_KEY_HEX: &str = "2f69706e732f\
+    002408011220\
+    a4686db22859e3c0e72b88adad35002b5983e5297730817ae1f79357285508ba";
+
+  #[test]
+  fn to_ipns() {
+    let name = WritableName::new();
+    let value = "such value. much wow".to_string();
+    let validity = SystemClock.now() + Duration::weeks(52);
+    let rev = Revision::v0_with_validity(&name.to_name(), &value, validity, Duration::days(31));
+    assert_eq!(rev.sequence(), 0);
+    assert_eq!(rev.name(), &name.to_name());
+    assert_eq!(rev.value(), &value);
+    assert_eq!(rev.validity(), &validity);
+
+    let entry = revision_to_ipns_entry(&rev, name.keypair()).unwrap();
+    // V2-only records have empty V1 fields - data is in CBOR
+    assert!(entry.value.is_empty());
+    assert!(entry.validity.is_empty());
+    assert_eq!(entry.sequence, 0);
+    assert_eq!(entry.ttl, 0);
+    // V2 data should be populated
+    assert!(!entry.signature_v2.is_empty());
+    assert!(!entry.data.is_empty());
+  }
+
+  #[test]
+  fn to_ipns_has_only_spec_fields() {
+    // only the spec's fields are signed, even for a revision that knows its validity window
+    let name = WritableName::new();
+    let rev = Revision::v0(&name.to_name(), "such value. much wow");
+    assert!(rev.validity_window().is_some());
+    let entry = revision_to_ipns_entry(&rev, name.keypair()).unwrap();
+    let data: std::collections::BTreeMap<String, serde_cbor::Value> =
+      serde_cbor::from_slice(&entry.data).unwrap();
+    assert_eq!(
+      data.keys().collect::<Vec<_>>(),
+      ["Sequence", "TTL", "Validity", "ValidityType", "Value"]
+    );
+  }
+
+  type Mutation = fn(&mut IpnsEntry);
+
+  /// Returns a valid hybrid V1+V2 record, with V1 fields that match the V2 data.
+  fn hybrid_entry(name: &WritableName) -> IpnsEntry {
+    let rev = Revision::v0(&name.to_name(), "/ipfs/hybrid").increment("/ipfs/hybrid");
+    revision_to_hybrid_ipns_entry(&rev, name.keypair()).unwrap()
+  }
+
+  #[test]
+  fn hybrid_entries() {
+    let name = WritableName::new();
+    let entry = hybrid_entry(&name);
+    assert!(signature_info(&entry).is_hybrid());
+    assert!(v1_v2_inconsistencies(&entry).unwrap().is_empty());
+    verify_v1_signature(&entry, name.to_name().public_key()).unwrap();
+    validate_ipns_entry(&entry, name.to_name().public_key()).unwrap();
+  }
+
+  #[test]
+  fn hybrid_field_mismatches() {
+    let name = WritableName::new();
+    let public_key = name.keypair().public();
+
+    let entry = hybrid_entry(&name);
+    validate_ipns_entry(&entry, &public_key).unwrap();
+    assert!(v1_v2_inconsistencies(&entry).unwrap().is_empty());
+
+    let fixtures: [(&str, Mutation); 5] = [
+      ("value", |e| e.value = b"/ipfs/other".to_vec()),
+      ("validity", |e| {
+        e.validity = b"2000-01-01T00:00:00.000000000Z".to_vec()
+      }),
+      ("validityType", |e| e.validity_type = 1),
+      ("sequence", |e| e.sequence = 7),
+      ("ttl", |e| e.ttl = 1),
+    ];
+    for (field, mutate) in fixtures {
+      let mut entry = hybrid_entry(&name);
+      mutate(&mut entry);
+
+      let mismatches = validate_ipns_entry_lenient(&entry, &public_key).unwrap();
+      assert_eq!(mismatches.len(), 1, "{}", field);
+      assert_eq!(mismatches[0].field, field);
+
+      let err_report = validate_ipns_entry(&entry, &public_key).unwrap_err();
+      assert!(err_report.contains::<InvalidIpnsV2SignatureData>());
+      assert_eq!(
+        err_report.downcast_ref::<FieldMismatch>(),
+        Some(&mismatches[0])
+      );
+
+      // the revision still reflects the signed V2 data
+      let rev = revision_from_ipns_entry_unchecked(&entry, &name.to_name()).unwrap();
+      assert_eq!(rev.value(), "/ipfs/hybrid");
+      assert_eq!(rev.sequence(), 1);
+    }
+  }
+
+  #[test]
+  fn hybrid_multiple_mismatches() {
+    let name = WritableName::new();
+    let mut entry = hybrid_entry(&name);
+    entry.sequence = 0;
+    entry.value = b"/ipfs/other".to_vec();
+
+    let fields: Vec<_> = v1_v2_inconsistencies(&entry)
+      .unwrap()
+      .into_iter()
+      .map(|m| m.field)
+      .collect();
+    assert_eq!(fields, vec!["value", "sequence"]);
+
+    // a bad V2 signature still fails in lenient mode
+    entry.signature_v2[0] ^= 0xff;
+    assert!(validate_ipns_entry_lenient(&entry, &name.keypair().public()).is_err());
+  }
+
+  #[test]
+  fn unsupported_validity_type() {
+    let name = WritableName::new();
+    let public_key = name.keypair().public();
+
+    // a V2 record whose signed data declares validity type 1
+    let rev = RevisionBuilder::new(&name.to_name(), "/ipfs/future")
+      .validity_type(ValidityType::Unknown(1))
+      .build()
+      .unwrap();
+    let entry = revision_to_ipns_entry(&rev, name.keypair()).unwrap();
+    let entry = deserialize_ipns_entry(&serialize_ipns_entry(&entry).unwrap()).unwrap();
+    assert_eq!(validity_type_of(&entry).unwrap(), ValidityType::Unknown(1));
+    for err_report in [
+      validate_ipns_entry(&entry, &public_key).unwrap_err(),
+      validate_ipns_entry_lenient(&entry, &public_key).unwrap_err(),
+      revision_from_ipns_entry_unchecked(&entry, &name.to_name()).unwrap_err(),
+    ] {
+      assert!(err_report.contains::<UnsupportedValidityType>());
+    }
+
+    // a V1 record with validity type 1, and otherwise valid
+    let mut entry = IpnsEntry {
+      value: b"/ipfs/v1".to_vec(),
+      validity: rev.validity_string().into_bytes(),
+      validity_type: 1,
+      ..Default::default()
+    };
+    entry.signature = create_v1_signature(name.keypair(), &entry.value, &entry.validity).unwrap();
+    let err_report = validate_ipns_entry(&entry, &public_key).unwrap_err();
+    assert!(err_report.contains::<UnsupportedValidityType>());
+    assert!(!err_report.contains::<InvalidIpnsV1Signature>());
+
+    entry.validity_type = 0;
+    validate_ipns_entry(&entry, &public_key).unwrap();
+    let rev = revision_from_ipns_entry_unchecked(&entry, &name.to_name()).unwrap();
+    assert_eq!(rev.validity_type(), ValidityType::Eol);
+  }
+
+  #[test]
+  fn unsupported_signature_algorithm() {
+    let name = WritableName::new();
+    let rev = Revision::v0(&name.to_name(), "/ipfs/future");
+    let mut entry = revision_to_ipns_entry(&rev, name.keypair()).unwrap();
+
+    // a record embedding a key of a made-up type 9, as a future key type would be
+    let mut future_key = vec![0x08, 0x09, 0x12, 32];
+    future_key.extend_from_slice(&[9; 32]);
+    entry.pub_key = future_key;
+
+    let err_report = embedded_public_key(&entry).unwrap_err();
+    assert!(err_report.contains::<UnsupportedSignatureAlgorithm>());
+    assert_eq!(
+      err_report
+        .downcast_ref::<UnsupportedKeyType>()
+        .map(|err| err.codec),
+      Some(9)
+    );
+    let bytes = serialize_ipns_entry(&entry).unwrap();
+    let err_report = Revision::from_signed_bytes(&name.to_name(), &bytes).unwrap_err();
+    assert!(err_report.contains::<UnsupportedSignatureAlgorithm>());
+
+    entry.pub_key = name.to_name().public_key_protobuf_bytes();
+    assert!(embedded_public_key(&entry).unwrap().is_some());
+  }
+
+  #[test]
+  fn round_trip() {
+    let name = WritableName::new();
+    let value = "such value. much wow".to_string();
+    let rev = Revision::v0(&name.to_name(), &value);
+
+    let entry = revision_to_ipns_entry(&rev, name.keypair()).unwrap();
+
+    validate_ipns_entry(&entry, &name.keypair().public()).unwrap();
+
+    let rev2 = revision_from_ipns_entry_unchecked(&entry, &name.to_name()).unwrap();
+    assert_eq!(rev, rev2);
+    assert_eq!(
+      Revision::try_from_entry(&entry, &name.to_name()).unwrap(),
+      rev
+    );
+  }
+
+  #[test]
+  fn validated_conversions_reject_tampered_records() {
+    let name = WritableName::new();
+    let rev = Revision::v0(&name.to_name(), "/ipfs/original");
+    let mut entry = revision_to_ipns_entry(&rev, name.keypair()).unwrap();
+
+    // swap in data for another value, keeping the original signature
+    entry.data = v2_signature_data(
+      b"/ipfs/tampered",
+      &rev.validity_string(),
+      0,
+      rev.sequence(),
+      rev.ttl_nanos(),
+    )
+    .unwrap();
+    let bytes = serialize_ipns_entry(&entry).unwrap();
+    let unchecked = revision_from_ipns_entry_unchecked(&entry, &name.to_name()).unwrap();
+    assert_eq!(unchecked.value(), "/ipfs/tampered");
+
+    let name = name.to_name();
+    assert!(Revision::try_from_entry(&entry, &name).is_err());
+    assert!(Revision::try_from((&entry, &name)).is_err());
+    assert!(Revision::try_from_record_bytes(&bytes, &name).is_err());
+    assert!(Revision::try_from((&bytes[..], &name)).is_err());
+
+    // a record that's valid, but for another name
+    let other = WritableName::new();
+    let entry = revision_to_ipns_entry(
+      &Revision::v0(&other.to_name(), "/ipfs/other"),
+      other.keypair(),
+    )
+    .unwrap();
+    assert!(revision_from_ipns_entry_unchecked(&entry, &name).is_ok());
+    assert!(Revision::try_from_entry(&entry, &name).is_err());
+  }
+
+  fn rsa_keypair() -> Keypair {
+    let mut der = include_bytes!("../../testdata/rsa-2048-private.pk8").to_vec();
+    Keypair::Rsa(libp2p_core::identity::rsa::Keypair::from_pkcs8(&mut der).unwrap())
+  }
+
+  #[test]
+  fn rsa_records_embed_their_key() {
+    let keypair = rsa_keypair();
+    let name = Name::from_public_key(&keypair.public());
+    let revision = Revision::v0(&name, "/ipfs/rsa");
+    let entry = revision_to_ipns_entry(&revision, &keypair).unwrap();
+    assert_eq!(entry.pub_key, name.public_key_protobuf_bytes());
+    validate_ipns_entry_for_name(&entry, &name).unwrap();
+    assert_eq!(name_for_entry(&name.to_string(), &entry).unwrap(), name);
+    assert_eq!(
+      Revision::try_from_entry(&entry, &name).unwrap().value(),
+      "/ipfs/rsa"
+    );
+    // the batch signer embeds the key too
+    let batch = sign_revisions(&keypair, &[revision])
+      .pop()
+      .unwrap()
+      .unwrap();
+    assert_eq!(batch.pub_key, entry.pub_key);
+
+    // ed25519 names hold their key, so their records don't need to
+    let writable = WritableName::new();
+    let entry =
+      revision_to_ipns_entry(&Revision::v0(&writable.to_name(), "v"), writable.keypair()).unwrap();
+    assert!(entry.pub_key.is_empty());
+    assert_eq!(
+      name_for_entry(&writable.to_string(), &entry).unwrap(),
+      writable.to_name()
+    );
+  }
+
+  #[test]
+  fn rsa_records_with_a_substituted_key() {
+    let name = Name::from_public_key(&rsa_keypair().public());
+    // a record signed by someone else, with their key in place of the name's
+    let attacker = WritableName::new();
+    let mut forged =
+      revision_to_ipns_entry(&Revision::v0(&name, "/ipfs/forged"), attacker.keypair()).unwrap();
+    forged.pub_key = attacker.to_name().public_key_protobuf_bytes();
+    validate_ipns_entry(&forged, attacker.to_name().public_key()).unwrap();
+
+    let err_report = validate_ipns_entry_for_name(&forged, &name).unwrap_err();
+    assert!(err_report.contains::<EmbeddedKeyMismatch>());
+    assert!(err_report.contains::<NameMismatch>());
+    let err_report = name_for_entry(&name.to_string(), &forged).unwrap_err();
+    assert!(err_report.contains::<EmbeddedKeyMismatch>());
+
+    // without any key, the record can't be checked where only the name is known
+    forged.pub_key.clear();
+    let err_report = validate_ipns_entry_for_name(&forged, &name).unwrap_err();
+    assert!(err_report.contains::<EmbeddedKeyMismatch>());
+    let err_report = name_for_entry(&name.to_string(), &forged).unwrap_err();
+    assert!(err_report.contains::<EmbeddedKeyMismatch>());
+
+    // other strings still fail to parse
+    assert!(name_for_entry("not a name", &forged).is_err());
+  }
+
+  #[test]
+  fn routing_keys() {
+    let name =
+      Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu").unwrap();
+    let key = routing_key_for_name(&name);
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(hex, ROUTING_KEY_HEX);
+    assert_eq!(name_from_routing_key(&key).unwrap(), name);
+
+    let generated = WritableName::new().to_name();
+    assert_eq!(
+      name_from_routing_key(&routing_key_for_name(&generated)).unwrap(),
+      generated
+    );
+
+    assert!(name_from_routing_key(&key[1..]).is_err());
+    assert!(name_from_routing_key(&key[..key.len() - 1]).is_err());
+    assert!(name_from_routing_key(b"/ipns/").is_err());
+  }
+
+  /// Serializes an otherwise empty entry with the given CBOR `data`.
+  fn entry_with_data(data: Vec<u8>) -> Vec<u8> {
+    serialize_ipns_entry(&IpnsEntry {
+      data,
+      ..Default::default()
+    })
+    .unwrap()
+  }
+
+  #[test]
+  fn untrusted_limits() {
+    let name = WritableName::new();
+    let rev = Revision::v0(&name.to_name(), "/ipfs/limits");
+    let bytes = rev.to_signed_bytes(&name).unwrap();
+    deserialize_ipns_entry_untrusted(&bytes).unwrap();
+
+    let oversized = vec![0; MAX_RECORD_SIZE + 1];
+    let err = deserialize_ipns_entry_untrusted(&oversized).unwrap_err();
+    assert!(err.contains::<RecordTooLarge>());
+
+    let tight = |adjust: fn(&mut DecodeLimits)| {
+      let mut limits = DecodeLimits::default();
+      adjust(&mut limits);
+      deserialize_ipns_entry_with_limits(&bytes, &limits).unwrap_err()
+    };
+    for err in [
+      tight(|limits| limits.max_record_size = 16),
+      tight(|limits| limits.max_data_size = 16),
+      tight(|limits| limits.max_value_size = 4),
+      tight(|limits| limits.max_validity_size = 8),
+    ] {
+      assert!(
+        err.contains::<RecordTooLarge>() || err.contains::<RecordFieldTooLarge>(),
+        "{:?}",
+        err
+      );
+    }
+
+    // the V1 fields are bounded too
+    let long_value = serialize_ipns_entry(&IpnsEntry {
+      value: vec![b'a'; 5 * 1024],
+      ..Default::default()
+    })
+    .unwrap();
+    let err = deserialize_ipns_entry_untrusted(&long_value).unwrap_err();
+    assert!(err.contains::<RecordFieldTooLarge>());
+  }
+
+  #[test]
+  fn malformed_inputs() {
+    let name = WritableName::new();
+    let rev = Revision::v0(&name.to_name(), "/ipfs/malformed");
+    let bytes = rev.to_signed_bytes(&name).unwrap();
+
+    let corpus: Vec<(&str, Vec<u8>)> = vec![
+      ("truncated protobuf", bytes[..bytes.len() - 5].to_vec()),
+      // field 9 (data), with a length prefix of 4 GiB
+      (
+        "huge length prefix",
+        vec![0x4a, 0xff, 0xff, 0xff, 0xff, 0x0f],
+      ),
+      ("overlong varint", [&[0x28][..], &[0xff; 11]].concat()),
+      ("invalid wire type", vec![0x4f, 0x00]),
+      // arrays nested 10,000 deep
+      (
+        "nested CBOR",
+        entry_with_data([vec![0x81; 10_000], vec![0x00]].concat()),
+      ),
+      (
+        "nested indefinite CBOR",
+        entry_with_data([vec![0x9f; 5_000], vec![0xff; 5_000]].concat()),
+      ),
+      // an array claiming 2^64 - 1 items, and a byte string claiming 4 GiB
+      (
+        "huge CBOR array",
+        entry_with_data(vec![0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+      ),
+      (
+        "huge CBOR map",
+        entry_with_data(vec![0xbb, 0x80, 0, 0, 0, 0, 0, 0, 0]),
+      ),
+      (
+        "huge CBOR string",
+        entry_with_data(vec![0x5a, 0xff, 0xff, 0xff, 0xff, 0x00]),
+      ),
+      ("truncated CBOR", entry_with_data(vec![0xa5, 0x45, b'V'])),
+      ("unterminated CBOR", entry_with_data(vec![0x5f, 0x41, 0x00])),
+      ("stray CBOR break", entry_with_data(vec![0xff])),
+      ("reserved CBOR header", entry_with_data(vec![0x1c])),
+      ("trailing CBOR bytes", entry_with_data(vec![0xa0, 0x00])),
+      (
+        "CBOR of the wrong shape",
+        entry_with_data(vec![0x83, 1, 2, 3]),
+      ),
+    ];
+    for (case, input) in corpus {
+      let result = deserialize_ipns_entry_untrusted(&input);
+      assert!(result.is_err(), "{} was accepted", case);
+      assert!(
+        Revision::from_signed_bytes(&name.to_name(), &input).is_err(),
+        "{} was accepted",
+        case
+      );
+    }
+  }
+
+  #[test]
+  fn checks_cbor_structure() {
+    let name = WritableName::new();
+    let rev = Revision::v0(&name.to_name(), "/ipfs/cbor");
+    let entry = revision_to_ipns_entry(&rev, name.keypair()).unwrap();
+    check_cbor_structure(&entry.data, 1).unwrap();
+    assert!(check_cbor_structure(&entry.data, 0).is_err());
+
+    // tags, floats, and indefinite-length strings are well-formed
+    check_cbor_structure(&[0xc1, 0x1a, 0, 0, 0, 0], 1).unwrap();
+    check_cbor_structure(&[0xfb, 0, 0, 0, 0, 0, 0, 0, 0], 0).unwrap();
+    check_cbor_structure(&[0x5f, 0x41, 0x00, 0x42, 0x00, 0x01, 0xff], 1).unwrap();
+    assert!(check_cbor_structure(&[], 4).is_err());
+  }
+
+  /// Every truncation and single-byte corruption of a valid record fails gracefully.
+  #[test]
+  fn corrupted_records_dont_panic() {
+    let name = WritableName::new();
+    let rev = Revision::v0(&name.to_name(), "/ipfs/corrupted");
+    let bytes = rev.to_signed_bytes(&name).unwrap();
+
+    for len in 0..bytes.len() {
+      let _ = Revision::from_signed_bytes(&name.to_name(), &bytes[..len]);
+    }
+    for i in 0..bytes.len() {
+      for flip in [0x01, 0x80, 0xff] {
+        let mut corrupted = bytes.clone();
+        corrupted[i] ^= flip;
+        let _ = Revision::from_signed_bytes(&name.to_name(), &corrupted);
+      }
+    }
+  }
+}
@@ -0,0 +1,146 @@
+//! JSON documents as record values.
+//!
+//! A record's value is usually an IPFS path, but it can be a small JSON document instead, like a
+//! manifest holding the current and previous CIDs of a release. Documents are published in a
+//! canonical form, with object keys sorted and no insignificant whitespace, so that the same
+//! document always gives the same value, and so the same signed record.
+//!
+//! [RevisionBuilder::value_json](crate::RevisionBuilder::value_json) and
+//! [Revision::value_json](crate::Revision::value_json) use these functions.
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+  error::{InvalidJsonValue, RecordTooLarge, RevisionError},
+  ipns::MAX_VALUE_SIZE,
+};
+
+/// Parses `json` and returns its canonical form, failing with [InvalidJsonValue] if it isn't
+/// JSON, or [RecordTooLarge] if the canonical form is longer than [MAX_VALUE_SIZE].
+///
+/// ## Example
+///
+/// ```rust
+/// use w3name_core::json_value;
+///
+/// let canonical = json_value::canonicalize(r#"{ "b": 1, "a": [true, null] }"#).unwrap();
+/// assert_eq!(canonical, r#"{"a":[true,null],"b":1}"#);
+/// ```
+pub fn canonicalize(json: &str) -> Result<String, RevisionError> {
+  let value: Value = serde_json::from_str(json)
+    .report()
+    .change_context(InvalidJsonValue)
+    .change_context(RevisionError)?;
+  to_canonical(&value)
+}
+
+/// Serializes `document` to its canonical JSON form, failing with [RecordTooLarge] if it's longer
+/// than [MAX_VALUE_SIZE].
+pub fn to_canonical(document: &impl Serialize) -> Result<String, RevisionError> {
+  let value = serde_json::to_value(document)
+    .report()
+    .change_context(InvalidJsonValue)
+    .change_context(RevisionError)?;
+  // serde_json sorts object keys unless its `preserve_order` feature is on, which another crate
+  // in the build may turn on, so they're sorted here regardless
+  let canonical = sort_keys(value).to_string();
+  if canonical.len() > MAX_VALUE_SIZE {
+    return Err(
+      report!(RecordTooLarge)
+        .attach_printable(format!(
+          "the value is {} bytes as canonical JSON, the limit is {} bytes",
+          canonical.len(),
+          MAX_VALUE_SIZE
+        ))
+        .change_context(RevisionError),
+    );
+  }
+  Ok(canonical)
+}
+
+/// Parses a record's value as JSON, failing with [InvalidJsonValue] if it isn't JSON, or isn't a
+/// `T`.
+pub fn parse<T: DeserializeOwned>(value: &[u8]) -> Result<T, RevisionError> {
+  serde_json::from_slice(value)
+    .report()
+    .change_context(InvalidJsonValue)
+    .change_context(RevisionError)
+}
+
+fn sort_keys(value: Value) -> Value {
+  match value {
+    Value::Object(object) => {
+      let mut entries: Vec<_> = object.into_iter().collect();
+      entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+      Value::Object(
+        entries
+          .into_iter()
+          .map(|(key, value)| (key, sort_keys(value)))
+          .collect::<Map<_, _>>(),
+      )
+    }
+    Value::Array(items) => Value::Array(items.into_iter().map(sort_keys).collect()),
+    value => value,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn canonical_form() {
+    let canonical = canonicalize(
+      r#"
+      {
+        "previous": "/ipfs/bafyprevious",
+        "current": "/ipfs/bafycurrent",
+        "notes": { "url": "https://example.com/notes", "lang": "en" },
+        "tags": ["z", "a"],
+        "size": 1.5
+      }
+      "#,
+    )
+    .unwrap();
+    assert_eq!(
+      canonical,
+      r#"{"current":"/ipfs/bafycurrent","notes":{"lang":"en","url":"https://example.com/notes"},"previous":"/ipfs/bafyprevious","size":1.5,"tags":["z","a"]}"#
+    );
+
+    // canonicalizing is idempotent, and insensitive to key order and whitespace
+    assert_eq!(canonicalize(&canonical).unwrap(), canonical);
+    let reordered = r#"{"tags":["z","a"],"size":1.5,"previous":"/ipfs/bafyprevious",
+      "notes":{"url":"https://example.com/notes","lang":"en"},"current":"/ipfs/bafycurrent"}"#;
+    assert_eq!(canonicalize(reordered).unwrap(), canonical);
+
+    // strings are escaped the same way however they were written
+    assert_eq!(canonicalize(r#""é\/""#).unwrap(), "\"é/\"");
+  }
+
+  #[test]
+  fn rejects_invalid_json() {
+    for invalid in ["", "/ipfs/bafyvalue", "{\"a\": 1", "{\"a\": 1} trailing"] {
+      let err = canonicalize(invalid).unwrap_err();
+      assert!(err.contains::<InvalidJsonValue>(), "{:?}", invalid);
+    }
+    let err = parse::<Value>(b"/ipfs/bafyvalue").unwrap_err();
+    assert!(err.contains::<InvalidJsonValue>());
+  }
+
+  #[test]
+  fn size_limit() {
+    // a JSON string has its quotes around it
+    let at_limit = Value::String("a".repeat(MAX_VALUE_SIZE - 2));
+    assert_eq!(to_canonical(&at_limit).unwrap().len(), MAX_VALUE_SIZE);
+
+    let over_limit = Value::String("a".repeat(MAX_VALUE_SIZE - 1));
+    let err = to_canonical(&over_limit).unwrap_err();
+    assert!(err.contains::<RecordTooLarge>());
+
+    // the limit applies to the canonical form, not the input
+    let padded = format!("{}\"{}\"", " ".repeat(MAX_VALUE_SIZE), "a".repeat(10));
+    assert_eq!(canonicalize(&padded).unwrap().len(), 12);
+  }
+}
@@ -0,0 +1,61 @@
+//! The record logic of [w3name](https://crates.io/crates/w3name), without the HTTP client.
+//!
+//! This crate contains the types for names and name records, and the [IPNS](https://docs.ipfs.tech/concepts/ipns/)
+//! record format they're stored in, but no network code, so it builds for targets the client
+//! doesn't support, like `wasm32-unknown-unknown`:
+//! - [Name] is a representation of a name identifier. It contains the public verification key.
+//! - [WritableName] contains a private key that can be used to sign name records.
+//! - [Revision] represents an unsigned name record. It contains a string value and some metadata (sequence number, expiration date, etc).
+//!   [RevisionBuilder] can be used to create `Revision`s with custom settings and validation.
+//! - [Value] is a typed view of a record's value, which can render IPFS and IPNS paths as gateway URLs or `ipfs://` URIs.
+//!   [Value::check_text] checks a value for invisible and control characters before it's published.
+//! - [Proof] is a signed proof of control of a name.
+//! - [Clock] is where the current time comes from; [ManualClock] makes time-dependent code testable.
+//!   Times and durations are [timestamp::Timestamp] and [timestamp::Duration], which are chrono's
+//!   types with the default `chrono` feature, and the `time` crate's without it.
+//!
+//! The [ipns] module converts revisions to and from signed IPNS records, and validates them. The
+//! [json_value] module publishes JSON documents as values, in a canonical form.
+//!
+//! The `w3name` crate re-exports everything in this crate at the same paths, so most code should
+//! depend on `w3name` instead, unless it can't use the client.
+//!
+//! ## Errors
+//!
+//! Like `w3name`, this crate uses the [error-stack](https://docs.rs/error-stack/latest/error_stack/) library
+//! for error handling, so all `Err` branches of `Result`s return a `Report<E>`, where `E` is one of the
+//! types defined in this crate's [error] module.
+
+mod clock;
+pub mod error;
+mod hash;
+pub mod ipns;
+pub mod json_value;
+mod name;
+mod proof;
+mod revision;
+pub mod timestamp;
+mod value;
+
+// Include the `ipns_pb` module, which is generated from ipns/ipns_pb.proto.
+mod ipns_pb {
+  include!(concat!(env!("OUT_DIR"), "/ipns_pb.rs"));
+}
+
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use name::{Name, WritableName};
+pub use proof::Proof;
+pub use revision::{
+  Defaults, IncrementPolicy, Revision, RevisionBuilder, TtlChecks, TtlNote, ValidityType,
+};
+pub use value::Value;
+
+/// Items the `w3name` crate needs but that aren't part of the public API.
+#[doc(hidden)]
+pub mod internal {
+  pub use crate::revision::default_validity_duration;
+  pub use crate::timestamp::{
+    checked_add_duration, duration_secs, format_http_date, format_rfc3339, from_std,
+    parse_http_date, parse_rfc3339, to_std, zero,
+  };
+}
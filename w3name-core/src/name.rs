@@ -0,0 +1,949 @@
+use std::{fmt::Display, sync::Arc};
+
+use crate::{error::ProtobufError, hash::Hasher};
+use cid::Cid;
+use libp2p_core::identity::{ed25519, Keypair, PublicKey};
+use multibase::Base;
+use multihash::MultihashDigest;
+use url::Url;
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+
+use crate::error::{
+  InvalidCidString, InvalidCryptoKey, InvalidMulticodecCode, NameError, NameParseDiagnostic,
+  UnexpectedName, UnsupportedKeyType,
+};
+
+pub(crate) const LIBP2P_MULTICODEC: u64 = 0x72;
+
+/// The highest key type number this build supports: RSA (0), ed25519 (1), secp256k1 (2) and ECDSA (3).
+const MAX_SUPPORTED_KEY_TYPE: u64 = 3;
+
+/// Public keys up to this size (when protobuf-encoded) are embedded into the name identifier with the
+/// identity hash. Larger keys (e.g. RSA) are hashed with sha2-256, as for libp2p peer ids.
+pub(crate) const MAX_INLINE_KEY_LENGTH: usize = 42;
+
+/// `Name` is a representation of an IPNS name identifier, which is also a public verification key.
+///
+/// `Name`s can be used to retrieve the latest published value from the w3name service
+/// using `W3NameClient::resolve` in the `w3name` crate.
+///
+/// Note that `Name` contains only the public verification key and does not allow publishing
+/// or updating records. To create or update a record, use the [WritableName] type instead.
+///
+/// To convert from a string representation of a name to a `Name` struct, use the [Name::parse](Self::parse) function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Name {
+  key: PublicKey,
+  /// The protobuf encoding of `key`, kept so that comparing against the `pubKey` field of records
+  /// and formatting the name don't have to re-encode the key every time.
+  encoded_key: Arc<[u8]>,
+}
+
+impl Name {
+  fn new(key: PublicKey) -> Name {
+    let encoded_key = key.to_protobuf_encoding().into();
+    Name { key, encoded_key }
+  }
+
+  /// Parses a `Name` from the string form of a name identifier.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  ///
+  /// let name_str = "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu";
+  /// let name = Name::parse(name_str)?;
+  ///
+  /// assert_eq!(name_str, &name.to_string());
+  ///
+  /// let invalid_name_str = "not a valid public key string";
+  /// assert!(Name::parse(invalid_name_str).is_err());
+  ///
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn parse<S: AsRef<str>>(s: S) -> Result<Name, NameError> {
+    let s = s.as_ref();
+    let c = Cid::try_from(s)
+      .map_err(|_| InvalidCidString)
+      .report()
+      .attach_printable_lazy(|| diagnose_cid_string(s))
+      .change_context(NameError)?;
+    if c.codec() != LIBP2P_MULTICODEC {
+      return Err(
+        report!(InvalidMulticodecCode)
+          .attach_printable(NameParseDiagnostic::WrongCodec { codec: c.codec() })
+          .change_context(NameError),
+      );
+    }
+    if c.hash().code() != u64::from(Hasher::Identity) {
+      return Err(report!(NameError).attach_printable(NameParseDiagnostic::HashedKey));
+    }
+
+    let key_bytes = c.hash().digest();
+    let pk = decode_public_key(key_bytes)
+      .attach_printable_lazy(|| diagnose_key(key_bytes))
+      .change_context(NameError)?;
+    Ok(Name::new(pk))
+  }
+
+  /// Parses a `Name` from a name identifier or an IPNS URI, returning the name and the path that follows it.
+  ///
+  /// Accepts bare names, `/ipns/<name>` paths, `ipns://<name>` URIs, and HTTP gateway URLs, either
+  /// path gateways (`https://ipfs.io/ipns/<name>`) or subdomain gateways (`https://<name>.ipns.dweb.link`).
+  /// The returned path is empty or starts with `/`. Query strings and fragments are dropped.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  ///
+  /// let name_str = "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu";
+  /// let (name, path) = Name::parse_uri(format!("ipns://{}/docs/index.html", name_str))?;
+  /// assert_eq!(name.to_string(), name_str);
+  /// assert_eq!(path, "/docs/index.html");
+  ///
+  /// let (_, path) = Name::parse_uri(format!("https://{}.ipns.dweb.link/", name_str))?;
+  /// assert_eq!(path, "");
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn parse_uri<S: AsRef<str>>(s: S) -> Result<(Name, String), NameError> {
+    let s = s.as_ref().trim();
+    let not_a_name =
+      || report!(NameError).attach_printable(format!("not an IPNS name or URL: {}", s));
+
+    let (name, path) = if s.starts_with("http://") || s.starts_with("https://") {
+      let url = Url::parse(s).report().change_context(NameError)?;
+      let host = url.host_str().unwrap_or_default();
+      match host.split_once('.') {
+        Some((label, domain)) if domain.starts_with("ipns.") => {
+          (label.to_string(), url.path().to_string())
+        }
+        _ => {
+          let rest = url.path().strip_prefix("/ipns/").ok_or_else(not_a_name)?;
+          split_name_and_path(rest)
+        }
+      }
+    } else {
+      let rest = s.split(['?', '#']).next().unwrap_or_default();
+      let rest = rest
+        .strip_prefix("ipns://")
+        .or_else(|| rest.strip_prefix("/ipns/"))
+        .unwrap_or(rest);
+      split_name_and_path(rest)
+    };
+
+    let name = Name::parse(&name).attach_printable_lazy(not_a_name)?;
+    let path = if path == "/" { String::new() } else { path };
+    Ok((name, path))
+  }
+
+  /// Creates a `Name` from a libp2p [PublicKey].
+  ///
+  /// For keys that are embedded in the name identifier (e.g. Ed25519 keys), this is equivalent to parsing
+  /// the name's string form, without the round-trip through a string. See [Name::to_cid] for larger keys.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  /// use libp2p_core::identity::Keypair;
+  ///
+  /// let keypair = Keypair::generate_ed25519();
+  /// let name = Name::from_public_key(&keypair.public());
+  ///
+  /// assert_eq!(name, Name::parse(name.to_string())?);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn from_public_key(public_key: &PublicKey) -> Name {
+    Name::new(public_key.clone())
+  }
+
+  /// Parses a `Name` from a protobuf-encoded public key, as found in the `pubKey` field of IPNS records
+  /// and returned by [Name::public_key_protobuf_bytes].
+  ///
+  /// Note that this is *not* the inverse of [Name::to_bytes], which returns the binary form of the name's [Cid].
+  pub fn from_bytes(key_bytes: &[u8]) -> Result<Name, NameError> {
+    let pk = decode_public_key(key_bytes).change_context(NameError)?;
+    Ok(Name::new(pk))
+  }
+
+  /// Returns a reference to this `Name`'s [PublicKey].
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  /// use libp2p_core::identity::PublicKey;
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  ///
+  /// match name.public_key() {
+  ///   &PublicKey::Ed25519(_) => println!("it's an ed25519 key, alright"),
+  ///   _ => panic!("that's odd, I could have sworn that the key was ed25519..."),
+  /// }
+  ///
+  /// # Ok(())
+  /// # }
+  ///
+  /// ```
+  pub fn public_key(&self) -> &PublicKey {
+    &self.key
+  }
+
+  /// Returns the canonical protobuf encoding of this `Name`'s public key, as used in the `pubKey` field of IPNS records.
+  ///
+  /// This is the encoding accepted by [Name::from_bytes].
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  /// let key_bytes = name.public_key_protobuf_bytes();
+  ///
+  /// assert_eq!(Name::from_bytes(&key_bytes)?, name);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn public_key_protobuf_bytes(&self) -> Vec<u8> {
+    self.encoded_key.to_vec()
+  }
+
+  /// Like [public_key_protobuf_bytes](Self::public_key_protobuf_bytes), but without copying.
+  pub(crate) fn encoded_key(&self) -> &[u8] {
+    &self.encoded_key
+  }
+
+  /// Returns `true` if the identifier holds a hash of the key rather than the key itself (see
+  /// [to_cid](Self::to_cid)).
+  pub(crate) fn is_key_hashed(&self) -> bool {
+    self.encoded_key.len() > MAX_INLINE_KEY_LENGTH
+  }
+
+  /// Returns this `Name` encoded as a [Cid], using the "identity" hash function to embed the key into the Cid itself.
+  ///
+  /// Keys that are too large to embed, like RSA keys, are hashed with sha2-256 instead. Such names can't be
+  /// turned back into a public key with [Name::parse]; the key must come from elsewhere, e.g. the `pubKey`
+  /// field of a record (see [Name::from_bytes]).
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  ///
+  /// let cid = name.to_cid();
+  /// // Cid::to_string() returns a base32-encoded string, but w3name uses base36.
+  ///
+  /// let expected_cid_string = "bafzaajaiaejcbjdinwzcqwpdydtsxcfnvu2qak2zqpsss5zqqf5od54tk4ufkcf2";
+  /// assert_eq!(&cid.to_string(), expected_cid_string);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn to_cid(&self) -> Cid {
+    let key_bytes = self.encoded_key();
+    let hash = if self.is_key_hashed() {
+      Hasher::Sha2_256.digest(key_bytes)
+    } else {
+      Hasher::Identity.digest(key_bytes)
+    };
+    Cid::new_v1(LIBP2P_MULTICODEC, hash)
+  }
+
+  /// Returns the value of a [DNSLink](https://dnslink.dev) TXT record pointing to this `Name`,
+  /// i.e. `dnslink=/ipns/<name>`.
+  ///
+  /// Publish it as a TXT record for `_dnslink.<domain>` to make `/ipns/<domain>` resolve to this name.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  ///
+  /// assert_eq!(
+  ///   name.to_dnslink_txt(),
+  ///   "dnslink=/ipns/k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu"
+  /// );
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn to_dnslink_txt(&self) -> String {
+    format!("dnslink=/ipns/{}", self)
+  }
+
+  /// Returns a `Vec<u8>` containing the binary form of the [Cid] representing this `Name`.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  /// use cid::Cid;
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  ///
+  /// let bytes = name.to_bytes();
+  /// let cid = Cid::read_bytes(&bytes[..]).unwrap();
+  ///
+  /// assert_eq!(cid, name.to_cid());
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn to_bytes(&self) -> Vec<u8> {
+    self.to_cid().to_bytes()
+  }
+
+  /// Returns the public key in the "canonical" string format for name identifiers used by w3name.
+  ///
+  /// The returned string is a base36-encoded representation of [Name::to_cid()].
+  /// This is the same format expected by [Name::parse()].
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::Name;
+  ///
+  /// let name_str = "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu";
+  /// let name = Name::parse(name_str)?;
+  ///
+  /// assert_eq!(name_str, &name.to_string());
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[allow(clippy::inherent_to_string_shadow_display)]
+  pub fn to_string(&self) -> String {
+    self.to_cid().to_string_of_base(Base::Base36Lower).unwrap()
+  }
+}
+
+impl Display for Name {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.to_string())
+  }
+}
+
+/// Splits `name/some/path` into `("name", "/some/path")`.
+fn split_name_and_path(s: &str) -> (String, String) {
+  match s.find('/') {
+    Some(i) => (s[..i].to_string(), s[i..].to_string()),
+    None => (s.to_string(), String::new()),
+  }
+}
+
+/// `WritableName` represnts a public/private keypair that can be used to sign name records for publication.
+///
+/// You can use a `WritableName` to publish a value to the w3name service using `W3NameClient::publish()` in the `w3name` crate.
+///
+#[derive(Clone, Debug)]
+pub struct WritableName(Keypair);
+
+impl WritableName {
+  /// Creates a new `WritableName` by generating an ed25519 keypair.
+  pub fn new() -> WritableName {
+    let kp = Keypair::generate_ed25519();
+    WritableName(kp)
+  }
+
+  /// Creates a `WritableName` from a 32-byte ed25519 private key seed.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::WritableName;
+  ///
+  /// let seed = [7u8; 32];
+  /// let w = WritableName::from_seed(&seed).unwrap();
+  /// assert_eq!(w, WritableName::from_seed(&seed).unwrap());
+  /// ```
+  pub fn from_seed(seed: &[u8]) -> Result<WritableName, InvalidCryptoKey> {
+    if seed.len() != 32 {
+      return Err(
+        report!(InvalidCryptoKey)
+          .attach_printable(format!("expected a 32-byte seed, got {} bytes", seed.len())),
+      );
+    }
+    let secret = ed25519::SecretKey::from_bytes(seed.to_vec())
+      .report()
+      .change_context(InvalidCryptoKey)?;
+    Ok(WritableName(Keypair::Ed25519(secret.into())))
+  }
+
+  /// Creates a `WritableName` from a libp2p [Keypair] the application already holds, e.g. one that
+  /// also signs its pubsub messages, without encoding and decoding it.
+  ///
+  /// The [Name] is the same as that of the keypair's [encoding](Keypair::to_protobuf_encoding)
+  /// passed to [decode](Self::decode). ed25519 and RSA keypairs are supported; keypairs of other
+  /// types, which `libp2p-core` only has if another crate enables them, fail with an
+  /// [UnsupportedKeyType] in the report. Note that libp2p can't encode RSA private keys, so an RSA
+  /// `WritableName` can sign records, but not be [encode](Self::encode)d.
+  ///
+  /// `WritableName` also implements `TryFrom<Keypair>`.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use libp2p_core::identity::Keypair;
+  /// use w3name_core::WritableName;
+  ///
+  /// let keypair = Keypair::generate_ed25519();
+  /// let name = WritableName::from_keypair(keypair.clone()).unwrap();
+  /// assert_eq!(&name.keypair().public(), name.to_name().public_key());
+  /// assert_eq!(name, WritableName::try_from(keypair).unwrap());
+  /// ```
+  pub fn from_keypair(keypair: Keypair) -> Result<WritableName, InvalidCryptoKey> {
+    match keypair {
+      Keypair::Ed25519(_) => Ok(WritableName(keypair)),
+      #[cfg(not(target_arch = "wasm32"))]
+      Keypair::Rsa(_) => Ok(WritableName(keypair)),
+      #[allow(unreachable_patterns)]
+      other => {
+        let codec = key_type(&other.public().to_protobuf_encoding()).unwrap_or_default();
+        Err(
+          report!(UnsupportedKeyType { codec })
+            .attach_printable("only ed25519 and RSA keypairs are supported")
+            .change_context(InvalidCryptoKey),
+        )
+      }
+    }
+  }
+
+  /// Decodes a `WritableName` from a binary encoding of a keypair as produced by [encode](Self::encode).
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::ProtobufError> {
+  ///
+  /// use w3name_core::WritableName;
+  ///
+  /// let w = WritableName::new();
+  /// let bytes = w.encode()?;
+  /// let w2 = WritableName::decode(&bytes)?;
+  ///
+  /// assert_eq!(w, w2);
+  ///
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn decode(key_bytes: &[u8]) -> Result<WritableName, ProtobufError> {
+    let kp = Keypair::from_protobuf_encoding(key_bytes)
+      .report()
+      .change_context(ProtobufError)?;
+    Ok(WritableName(kp))
+  }
+
+  /// Encodes a `WritableName` into a binary representation, suitable for [decode](Self::decode).
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::ProtobufError> {
+  /// use w3name_core::WritableName;
+  ///
+  /// let w = WritableName::new();
+  /// let bytes = w.encode()?;
+  /// let w2 = WritableName::decode(&bytes)?;
+  ///
+  /// assert_eq!(w, w2);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn encode(&self) -> Result<Vec<u8>, ProtobufError> {
+    self
+      .keypair()
+      .to_protobuf_encoding()
+      .report()
+      .change_context(ProtobufError)
+  }
+
+  /// Returns a reference to this `WritableName`'s underlying [Keypair].
+  ///
+  /// The `WritableName` keeps owning the keypair. To use it elsewhere too, e.g. for libp2p, clone it,
+  /// which copies the private key; or take it with [into_keypair](Self::into_keypair) once the
+  /// `WritableName` isn't needed anymore.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::WritableName;
+  /// use libp2p_core::identity::Keypair;
+  ///
+  /// let w = WritableName::new();
+  /// match w.keypair() {
+  ///   &Keypair::Ed25519(_) => println!("it's an ed25519 keypair!"),
+  ///   _ => panic!("only ed25519 keys are supported, so this shouldn't happen..."),
+  /// }
+  /// ```
+  pub fn keypair(&self) -> &Keypair {
+    &self.0
+  }
+
+  /// Returns the underlying [Keypair], consuming this `WritableName`. The inverse of
+  /// [from_keypair](Self::from_keypair).
+  pub fn into_keypair(self) -> Keypair {
+    self.0
+  }
+
+  /// Returns a `Name` that represents the public half of this `WritableName`'s keypair.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::WritableName;
+  ///
+  /// let w = WritableName::new();
+  /// let n = w.to_name();
+  ///
+  /// assert_eq!(&w.keypair().public(), n.public_key());
+  /// ```
+  pub fn to_name(&self) -> Name {
+    Name::new(self.0.public())
+  }
+
+  /// Fails with [UnexpectedName] if this isn't the key of `expected`, e.g. to catch a script
+  /// passing the wrong key file before anything is signed with it.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::WritableName;
+  ///
+  /// let w = WritableName::new();
+  /// assert!(w.assert_name(&w.to_name()).is_ok());
+  /// assert!(w.assert_name(&WritableName::new().to_name()).is_err());
+  /// ```
+  pub fn assert_name(&self, expected: &Name) -> Result<(), UnexpectedName> {
+    let actual = self.to_name();
+    if &actual == expected {
+      return Ok(());
+    }
+    Err(report!(UnexpectedName).attach_printable(format!(
+      "expected the key of {}, but the key is for {}",
+      expected, actual
+    )))
+  }
+
+  /// Convenience wrapper around `Self::to_name().to_cid()` that returns the Cid form of the **public** portion of this `WritableName`'s keypair.
+  ///
+  /// Please note that this does not encode the private key.
+  /// If you want to save the `WritableName`, use [encode](Self::encode).
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::WritableName;
+  ///
+  /// let w = WritableName::new();
+  /// let n = w.to_name();
+  ///
+  /// assert_eq!(w.to_cid(), n.to_cid());
+  /// ```
+  pub fn to_cid(&self) -> Cid {
+    self.to_name().to_cid()
+  }
+
+  /// Convenience wrapper around `Self::to_name().to_string()` that returns a string encoding of the public key (aka the "name identifier").
+  ///
+  /// Please note that this does not encode the private key.
+  /// If you want to save the `WritableName`, use [encode](Self::encode).
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::WritableName;
+  ///
+  /// let w = WritableName::new();
+  /// let n = w.to_name();
+  ///
+  /// assert_eq!(w.to_string(), n.to_string());
+  /// ```
+  #[allow(clippy::inherent_to_string_shadow_display)]
+  pub fn to_string(&self) -> String {
+    self.to_name().to_string()
+  }
+}
+
+impl Default for WritableName {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl TryFrom<Keypair> for WritableName {
+  type Error = error_stack::Report<InvalidCryptoKey>;
+
+  /// See [WritableName::from_keypair].
+  fn try_from(keypair: Keypair) -> Result<WritableName, InvalidCryptoKey> {
+    WritableName::from_keypair(keypair)
+  }
+}
+
+impl Display for WritableName {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.to_string())
+  }
+}
+
+// since Keypair doesn't implement PartialEq, we can't derive it for
+// WritableName and need to implement manually.
+impl PartialEq for WritableName {
+  fn eq(&self, other: &Self) -> bool {
+    use Keypair::Ed25519;
+
+    match (self.keypair(), other.keypair()) {
+      (Ed25519(our_key), Ed25519(their_key)) => {
+        // encode both keys to bytes and return true if they're identical
+        let our_key_bytes = our_key.encode();
+        let their_key_bytes = their_key.encode();
+        our_key_bytes == their_key_bytes
+      }
+
+      // other private keys, like RSA keys, can't be encoded, but are determined by their public
+      // keys
+      (our_key, their_key) => our_key.public() == their_key.public(),
+    }
+  }
+}
+
+impl Eq for WritableName {}
+
+/// Works out why `s` isn't a valid CID string, for [Name::parse].
+fn diagnose_cid_string(s: &str) -> NameParseDiagnostic {
+  let trimmed = s.trim().trim_end_matches('/');
+  if trimmed.is_empty() {
+    return NameParseDiagnostic::Empty;
+  }
+  if trimmed != s && Cid::try_from(trimmed).is_ok() {
+    return NameParseDiagnostic::ExtraCharacters;
+  }
+
+  let prefix = s.chars().next().unwrap_or_default();
+  let base = match Base::from_code(prefix) {
+    Ok(base) => base,
+    Err(_) => return NameParseDiagnostic::UnknownMultibase(prefix),
+  };
+  let (base_name, alphabet) = match base {
+    // base36 is decoded case-insensitively
+    Base::Base36Lower | Base::Base36Upper => (
+      "base36",
+      "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ",
+    ),
+    Base::Base32Lower => ("base32", "abcdefghijklmnopqrstuvwxyz234567"),
+    Base::Base32Upper => ("base32", "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567"),
+    Base::Base58Btc => (
+      "base58btc",
+      "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz",
+    ),
+    _ => {
+      return NameParseDiagnostic::WrongLength {
+        length: s.chars().count(),
+      }
+    }
+  };
+  let invalid = s
+    .chars()
+    .enumerate()
+    .skip(1)
+    .find(|(_, c)| !alphabet.contains(*c));
+  if let Some((position, character)) = invalid {
+    return NameParseDiagnostic::InvalidCharacter {
+      position,
+      character,
+      base: base_name.to_string(),
+    };
+  }
+  NameParseDiagnostic::WrongLength {
+    length: s.chars().count(),
+  }
+}
+
+/// Decodes a protobuf-encoded public key. Keys of a type this build doesn't support fail with an
+/// [UnsupportedKeyType] in the report, rather than a generic decoding error.
+pub(crate) fn decode_public_key(key_bytes: &[u8]) -> Result<PublicKey, InvalidCryptoKey> {
+  PublicKey::from_protobuf_encoding(key_bytes)
+    .report()
+    .map_err(|err_report| match unsupported_key_type(key_bytes) {
+      Some(codec) => report!(UnsupportedKeyType { codec }).change_context(InvalidCryptoKey),
+      None => err_report.change_context(InvalidCryptoKey),
+    })
+}
+
+/// Returns the type of the protobuf-encoded public key in `key_bytes`, if it's one this build
+/// doesn't support.
+fn unsupported_key_type(key_bytes: &[u8]) -> Option<u64> {
+  key_type(key_bytes).filter(|key_type| *key_type > MAX_SUPPORTED_KEY_TYPE)
+}
+
+/// Returns the type of the protobuf-encoded public key in `key_bytes`, e.g. 1 for ed25519.
+fn key_type(key_bytes: &[u8]) -> Option<u64> {
+  // the key type is the first field of the protobuf message: tag 0x08, then the type as a varint
+  let varint = key_bytes.strip_prefix(&[0x08])?;
+  let mut key_type = 0u64;
+  for (i, byte) in varint.iter().take(10).enumerate() {
+    key_type |= u64::from(byte & 0x7f) << (7 * i);
+    if byte & 0x80 == 0 {
+      return Some(key_type);
+    }
+  }
+  None
+}
+
+/// Works out why `key_bytes` isn't a supported, protobuf-encoded public key, for [Name::parse].
+fn diagnose_key(key_bytes: &[u8]) -> NameParseDiagnostic {
+  match unsupported_key_type(key_bytes) {
+    Some(key_type) => NameParseDiagnostic::UnsupportedKeyType(key_type),
+    None => NameParseDiagnostic::InvalidKey,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use super::*;
+  use libp2p_core::identity::rsa;
+
+  #[test]
+  fn assert_name_shows_both_names() {
+    let key = WritableName::new();
+    key.assert_name(&key.to_name()).unwrap();
+
+    let expected = WritableName::new().to_name();
+    let err = key.assert_name(&expected).unwrap_err();
+    assert!(format!("{:?}", err).contains(&format!(
+      "expected the key of {}, but the key is for {}",
+      expected, key
+    )));
+  }
+
+  #[test]
+  fn writable_name_from_ed25519_keypair() {
+    let keypair = Keypair::generate_ed25519();
+    let decoded = WritableName::decode(&keypair.to_protobuf_encoding().unwrap()).unwrap();
+    let name = WritableName::from_keypair(keypair.clone()).unwrap();
+    assert_eq!(name, decoded);
+    assert_eq!(name.to_name(), decoded.to_name());
+    assert_eq!(name.to_string(), decoded.to_string());
+    assert_eq!(WritableName::try_from(keypair).unwrap(), name);
+
+    let keypair = name.clone().into_keypair();
+    assert_eq!(WritableName::from_keypair(keypair).unwrap(), name);
+  }
+
+  #[test]
+  fn writable_name_from_rsa_keypair() {
+    let mut der = include_bytes!("../testdata/rsa-2048-private.pk8").to_vec();
+    let keypair = Keypair::Rsa(rsa::Keypair::from_pkcs8(&mut der).unwrap());
+    let name = WritableName::from_keypair(keypair.clone()).unwrap();
+    assert_eq!(name.to_name(), Name::from_public_key(&keypair.public()));
+    assert_eq!(name, name.clone());
+    assert_ne!(name, WritableName::new());
+    // libp2p can't encode RSA private keys
+    assert!(name.encode().is_err());
+
+    // its records validate against its name
+    let revision = crate::Revision::v0(&name.to_name(), "/ipfs/rsa");
+    let record = revision.to_signed_bytes(&name).unwrap();
+    let parsed = crate::Revision::from_signed_bytes(&name.to_name(), &record).unwrap();
+    assert_eq!(parsed.value(), "/ipfs/rsa");
+  }
+
+  #[test]
+  fn parse_uri_forms() {
+    let name = WritableName::new().to_name();
+    let cases = [
+      (name.to_string(), ""),
+      (format!("{}/docs", name), "/docs"),
+      (format!("/ipns/{}", name), ""),
+      (format!("/ipns/{}/a/b.txt", name), "/a/b.txt"),
+      (format!("ipns://{}", name), ""),
+      (format!("ipns://{}/docs?x=1#top", name), "/docs"),
+      (format!("https://ipfs.io/ipns/{}/docs/", name), "/docs/"),
+      (format!("http://localhost:8080/ipns/{}", name), ""),
+      (format!("https://{}.ipns.dweb.link/x", name), "/x"),
+      (format!("http://{}.ipns.localhost:8080/", name), ""),
+    ];
+    for (input, expected_path) in cases {
+      let (parsed, path) = Name::parse_uri(&input).unwrap();
+      assert_eq!(parsed, name, "{}", input);
+      assert_eq!(path, expected_path, "{}", input);
+    }
+
+    for invalid in [
+      "ipns://not-a-name/docs".to_string(),
+      format!("https://ipfs.io/ipfs/{}", name),
+      format!("https://{}.ipfs.dweb.link/", name),
+      "".to_string(),
+    ] {
+      assert!(Name::parse_uri(&invalid).is_err(), "{}", invalid);
+    }
+  }
+
+  #[test]
+  fn create_writable_name() {
+    let name = WritableName::new();
+
+    let cid = Cid::from_str(name.to_string().as_str()).unwrap();
+    assert_eq!(cid.codec(), LIBP2P_MULTICODEC);
+    assert_eq!(cid.hash().code(), 0x0); // identity hash code
+    assert_eq!(cid, name.to_cid());
+  }
+
+  #[test]
+  fn writable_name_from_private_key() {
+    let name_str = "k51qzi5uqu5dkgso0xihmnkn1sthxgs3nilzmofwy29jrplwdtk6sc14x9f2zv";
+    let private_key_base64 = "CAESQI8NcJgBK+9qfSBz/ZiXNuw4OJkUTn4jWZvd3Sj8W6GLq900cwz32d6ylbqBl81WRgM6QvSEXMwGlEODgEkXCes=";
+    let private_key = base64::decode(private_key_base64).unwrap();
+    let name = WritableName::decode(&private_key).unwrap();
+    assert_eq!(name.to_string(), name_str);
+  }
+
+  fn assert_public_key_conversions(public_key: &PublicKey) {
+    let name = Name::from_public_key(public_key);
+    assert_eq!(name.public_key(), public_key);
+    assert_eq!(
+      name.public_key_protobuf_bytes(),
+      public_key.to_protobuf_encoding()
+    );
+    assert_eq!(
+      name,
+      Name::from_bytes(&name.public_key_protobuf_bytes()).unwrap()
+    );
+  }
+
+  #[test]
+  fn ed25519_public_key_conversions() {
+    let public_key = Keypair::generate_ed25519().public();
+    assert_public_key_conversions(&public_key);
+
+    let name = Name::from_public_key(&public_key);
+    assert_eq!(name, Name::parse(name.to_string()).unwrap());
+  }
+
+  #[test]
+  fn rsa_public_key_conversions() {
+    let der = include_bytes!("../testdata/rsa-2048-public.der");
+    let public_key = PublicKey::Rsa(rsa::PublicKey::decode_x509(der).unwrap());
+    assert_public_key_conversions(&public_key);
+
+    // RSA keys are too large to embed, so the name only contains a hash of the key
+    let name = Name::from_public_key(&public_key);
+    assert_eq!(name.to_cid().hash().code(), 0x12);
+    assert_eq!(Cid::from_str(&name.to_string()).unwrap(), name.to_cid());
+    assert!(Name::parse(name.to_string()).is_err());
+  }
+
+  #[test]
+  fn parse_name() {
+    let name_str = "k51qzi5uqu5dl2hq2hm5m29sdq1lum0kb0lmyqsowicmrmxzxywwgxhy6ymrdv";
+    let name = Name::parse(name_str).expect("parse error");
+    assert_eq!(name_str, name.to_string());
+
+    // it fails to parse a CIDv0
+    let invalid_cidv0 = "QmPFpDRC87jTdSYxjnEZUTjJuYF5yLRWxir3DzJ1XiVZ3t";
+    assert!(Name::parse(invalid_cidv0).is_err());
+
+    // it fails to parse a non libp2p-key codec name
+    let invalid = "k2jmtxx8tc9pv6b9sj5wm71mheawu849x2bzkjuecpwizjwjeufiadl6";
+    assert!(Name::parse(invalid).is_err());
+  }
+
+  fn diagnostic(s: &str) -> NameParseDiagnostic {
+    let err = Name::parse(s).expect_err(s);
+    err
+      .downcast_ref::<NameParseDiagnostic>()
+      .cloned()
+      .unwrap_or_else(|| panic!("no diagnostic for {:?}: {:?}", s, err))
+  }
+
+  #[test]
+  fn parse_diagnostics() {
+    use NameParseDiagnostic::*;
+    let name = "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu";
+
+    assert_eq!(diagnostic(""), Empty);
+    assert_eq!(diagnostic(&format!("{}/", name)), ExtraCharacters);
+    assert_eq!(diagnostic(&format!(" {}\n", name)), ExtraCharacters);
+    assert_eq!(
+      diagnostic(&format!("x{}", &name[1..])),
+      UnknownMultibase('x')
+    );
+    assert_eq!(
+      diagnostic(&name.replace("ka3t", "k-3t")),
+      InvalidCharacter {
+        position: 13,
+        character: '-',
+        base: "base36".to_string()
+      }
+    );
+    assert_eq!(diagnostic(&name[..40]), WrongLength { length: 40 });
+
+    // the CID of some IPFS content, in both CID versions
+    let content_cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+    assert_eq!(diagnostic(content_cid), WrongCodec { codec: 0x70 });
+    let v0_cid = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+    assert_eq!(diagnostic(v0_cid), WrongCodec { codec: 0x70 });
+    let message = format!("{:?}", Name::parse(content_cid).unwrap_err());
+    assert!(message.contains("this looks like an IPFS content CID, not an IPNS name"));
+
+    let der = include_bytes!("../testdata/rsa-2048-public.der");
+    let rsa_key = PublicKey::Rsa(rsa::PublicKey::decode_x509(der).unwrap());
+    let rsa_name = Name::from_public_key(&rsa_key);
+    assert_eq!(diagnostic(&rsa_name.to_string()), HashedKey);
+
+    let key_cid = |key_bytes: &[u8]| {
+      Cid::new_v1(LIBP2P_MULTICODEC, Hasher::Identity.digest(key_bytes))
+        .to_string_of_base(Base::Base36Lower)
+        .unwrap()
+    };
+    let mut unknown_key = vec![0x08, 0x07, 0x12, 32];
+    unknown_key.extend_from_slice(&[7; 32]);
+    assert_eq!(diagnostic(&key_cid(&unknown_key)), UnsupportedKeyType(7));
+    assert_eq!(diagnostic(&key_cid(b"not a key")), InvalidKey);
+  }
+
+  #[test]
+  fn unsupported_key_types() {
+    // a made-up key type, encoded as a one- and a two-byte varint
+    for (varint, codec) in [(vec![0x07], 7), (vec![0x80, 0x24], 0x1200)] {
+      let mut key_bytes = vec![0x08];
+      key_bytes.extend(varint);
+      key_bytes.extend_from_slice(&[0x12, 32]);
+      key_bytes.extend_from_slice(&[7; 32]);
+
+      let err = Name::from_bytes(&key_bytes).unwrap_err();
+      assert_eq!(
+        err
+          .downcast_ref::<crate::error::UnsupportedKeyType>()
+          .map(|err| err.codec),
+        Some(codec)
+      );
+      let cid = Cid::new_v1(LIBP2P_MULTICODEC, Hasher::Identity.digest(&key_bytes))
+        .to_string_of_base(Base::Base36Lower)
+        .unwrap();
+      let err = Name::parse(cid).unwrap_err();
+      assert!(err.contains::<crate::error::UnsupportedKeyType>());
+    }
+
+    // supported key types that fail to decode are just invalid
+    let err = Name::from_bytes(&[0x08, 0x01, 0x12, 0x01, 0x00]).unwrap_err();
+    assert!(!err.contains::<crate::error::UnsupportedKeyType>());
+  }
+}
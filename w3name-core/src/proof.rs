@@ -0,0 +1,327 @@
+use error_stack::{report, IntoReport, Result, ResultExt};
+
+use crate::{
+  error::{ProofError, SigningError},
+  timestamp::{self, Duration, Timestamp},
+  Clock, Name, SystemClock, WritableName,
+};
+
+/// Prepended to every signed proof message, so that a proof signature can't be mistaken for
+/// the signature of an IPNS record (which start with `ipns-signature:`), or vice versa.
+const PROOF_PREFIX: &str = "w3name-proof:";
+
+/// Appended to every signed proof message. V1 record signatures have no prefix, and cover
+/// `<value>EOL<validity>`, so without it a challenge like `xEOL2099-01-01T00:00:00Z` would make
+/// the proof's signature a valid V1 signature of a record. With it, the "validity" is never a
+/// timestamp, so such a record is rejected.
+const PROOF_TRAILER: &str = ":w3name-proof-end";
+
+/// Proofs may be timestamped this far in the future, to allow for clock differences.
+const MAX_CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+/// A signed statement that the holder of a name's private key saw a challenge string at some time,
+/// e.g. to prove control of a name to a registrar.
+///
+/// Create one with [WritableName::sign_proof], and check it with [Name::verify_proof].
+/// [to_json](Self::to_json) and [from_json](Self::from_json) convert proofs to and from a JSON
+/// object with `name`, `challenge`, `timestamp` (RFC3339) and `signature` (base64) fields.
+///
+/// The signature covers `w3name-proof:<timestamp>:<challenge>:w3name-proof-end`, with the
+/// timestamp in RFC3339 format to the second (e.g. `2024-01-02T15:04:05Z`), so neither the
+/// challenge nor the timestamp can be altered, and the signature can't pass for an IPNS record's.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof {
+  name: Name,
+  challenge: String,
+  timestamp: Timestamp,
+  signature: Vec<u8>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ProofJson {
+  name: String,
+  challenge: String,
+  timestamp: String,
+  signature: String,
+}
+
+impl Proof {
+  /// The name whose key signed the proof.
+  pub fn name(&self) -> &Name {
+    &self.name
+  }
+
+  pub fn challenge(&self) -> &str {
+    &self.challenge
+  }
+
+  /// When the proof was signed, according to the signer's clock.
+  pub fn timestamp(&self) -> Timestamp {
+    self.timestamp
+  }
+
+  pub fn signature(&self) -> &[u8] {
+    &self.signature
+  }
+
+  /// Returns how long ago the proof was signed.
+  pub fn age(&self) -> Duration {
+    SystemClock.now() - self.timestamp
+  }
+
+  /// Encodes the proof as a pretty-printed JSON object.
+  pub fn to_json(&self) -> String {
+    let json = ProofJson {
+      name: self.name.to_string(),
+      challenge: self.challenge.clone(),
+      timestamp: format_timestamp(&self.timestamp),
+      signature: base64::encode(&self.signature),
+    };
+    serde_json::to_string_pretty(&json).expect("proofs are always serializable")
+  }
+
+  /// Decodes a proof from the JSON written by [to_json](Self::to_json). The signature isn't
+  /// checked; use [Name::verify_proof] for that.
+  pub fn from_json(json: &str) -> Result<Proof, ProofError> {
+    let json: ProofJson = serde_json::from_str(json)
+      .report()
+      .change_context(ProofError)
+      .attach_printable(
+        "the proof isn't a JSON object with name, challenge, timestamp and signature",
+      )?;
+    let name = Name::parse(&json.name).change_context(ProofError)?;
+    let timestamp = timestamp::parse_rfc3339(&json.timestamp)
+      .change_context(ProofError)
+      .attach_printable(format!("invalid timestamp: {}", json.timestamp))?;
+    let signature = base64::decode(&json.signature)
+      .report()
+      .change_context(ProofError)
+      .attach_printable("the signature isn't valid base64")?;
+    Ok(Proof {
+      name,
+      challenge: json.challenge,
+      timestamp,
+      signature,
+    })
+  }
+
+  fn message(&self) -> Vec<u8> {
+    proof_message(&self.challenge, &self.timestamp)
+  }
+}
+
+fn format_timestamp(timestamp: &Timestamp) -> String {
+  timestamp::format_rfc3339_secs(timestamp)
+    .expect("proofs are only signed now, or parsed from RFC3339 strings")
+}
+
+fn proof_message(challenge: &str, timestamp: &Timestamp) -> Vec<u8> {
+  format!(
+    "{}{}:{}{}",
+    PROOF_PREFIX,
+    format_timestamp(timestamp),
+    challenge,
+    PROOF_TRAILER
+  )
+  .into_bytes()
+}
+
+impl WritableName {
+  /// Signs a [Proof] that the holder of this name's key saw `challenge` just now.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::WritableName;
+  ///
+  /// let w = WritableName::new();
+  /// let proof = w.sign_proof("registrar challenge 1234").unwrap();
+  /// assert!(w.to_name().verify_proof(&proof).is_ok());
+  /// ```
+  pub fn sign_proof(&self, challenge: &str) -> Result<Proof, SigningError> {
+    // the timestamp is signed with whole seconds, so drop the rest before signing
+    let timestamp = timestamp::trunc_subsecs(&SystemClock.now());
+    let signature = self
+      .keypair()
+      .sign(&proof_message(challenge, &timestamp))
+      .report()
+      .change_context(SigningError)?;
+    Ok(Proof {
+      name: self.to_name(),
+      challenge: challenge.to_string(),
+      timestamp,
+      signature,
+    })
+  }
+}
+
+impl Name {
+  /// Checks that `proof` was signed by this name's key, returning how long ago it was signed.
+  ///
+  /// Fails if the proof is for another name, if the challenge or timestamp were altered, or if
+  /// the proof claims to be from more than five minutes in the future. Proofs don't expire; use
+  /// [verify_proof_within](Self::verify_proof_within) to reject old ones.
+  pub fn verify_proof(&self, proof: &Proof) -> Result<Duration, ProofError> {
+    if &proof.name != self {
+      return Err(
+        report!(ProofError)
+          .attach_printable(format!("the proof is for {}, not {}", proof.name, self)),
+      );
+    }
+    if !self.public_key().verify(&proof.message(), &proof.signature) {
+      return Err(
+        report!(ProofError)
+          .attach_printable("the signature doesn't match the challenge and timestamp"),
+      );
+    }
+
+    let age = proof.age();
+    if age < Duration::seconds(-MAX_CLOCK_SKEW_SECS) {
+      return Err(report!(ProofError).attach_printable(format!(
+        "the proof is timestamped in the future: {}",
+        format_timestamp(&proof.timestamp)
+      )));
+    }
+    Ok(age)
+  }
+
+  /// Like [verify_proof](Self::verify_proof), but also fails if the proof was signed more than
+  /// `max_age` ago, so that an old proof can't be replayed.
+  pub fn verify_proof_within(
+    &self,
+    proof: &Proof,
+    max_age: Duration,
+  ) -> Result<Duration, ProofError> {
+    let age = self.verify_proof(proof)?;
+    if age > max_age {
+      return Err(report!(ProofError).attach_printable(format!(
+        "the proof was signed {} seconds ago, more than the maximum of {} seconds",
+        timestamp::duration_secs(&age),
+        timestamp::duration_secs(&max_age)
+      )));
+    }
+    Ok(age)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CHALLENGE: &str = "prove you control this name: 8f2c1e";
+
+  /// Signs a proof with a timestamp of `age` ago.
+  fn proof_from(name: &WritableName, challenge: &str, age: Duration) -> Proof {
+    let mut proof = name.sign_proof(challenge).unwrap();
+    proof.timestamp -= age;
+    proof.signature = name.keypair().sign(&proof.message()).unwrap();
+    proof
+  }
+
+  #[test]
+  fn sign_and_verify() {
+    let name = WritableName::new();
+    let proof = name.sign_proof(CHALLENGE).unwrap();
+    assert_eq!(proof.challenge(), CHALLENGE);
+    let age = name.to_name().verify_proof(&proof).unwrap();
+    assert!(age >= timestamp::zero() && age < Duration::seconds(5));
+
+    let decoded = Proof::from_json(&proof.to_json()).unwrap();
+    assert_eq!(decoded, proof);
+    name.to_name().verify_proof(&decoded).unwrap();
+
+    // the signed message is domain-separated
+    assert!(proof.message().starts_with(b"w3name-proof:"));
+  }
+
+  #[test]
+  fn proofs_dont_sign_v1_records() {
+    use crate::{ipns::verify_v1_signature, ipns_pb::IpnsEntry, Revision};
+
+    // a verifier picks a challenge that makes the proof message look like a V1 record payload,
+    // `<value>EOL<validity>`
+    let name = WritableName::new();
+    let proof = name
+      .sign_proof("/ipfs/hijackedEOL2099-01-01T00:00:00Z")
+      .unwrap();
+    let message = proof.message();
+    let split = message.windows(3).position(|w| w == b"EOL").unwrap();
+    let entry = IpnsEntry {
+      value: message[..split].to_vec(),
+      validity: message[split + 3..].to_vec(),
+      signature: proof.signature().to_vec(),
+      ..Default::default()
+    };
+
+    // the signature covers those bytes, but the trailer makes the validity unparseable, so the
+    // record is rejected
+    verify_v1_signature(&entry, name.to_name().public_key()).unwrap();
+    assert!(Revision::try_from_entry(&entry, &name.to_name()).is_err());
+
+    // without the trailer, the same challenge would have signed a valid record
+    let untrailed = &message[..message.len() - PROOF_TRAILER.len()];
+    let entry = IpnsEntry {
+      validity: untrailed[split + 3..].to_vec(),
+      signature: name.keypair().sign(untrailed).unwrap(),
+      ..entry
+    };
+    let revision = Revision::try_from_entry(&entry, &name.to_name()).unwrap();
+    assert!(revision.value().ends_with("/ipfs/hijacked"));
+  }
+
+  #[test]
+  fn rejects_tampered_proofs() {
+    let name = WritableName::new();
+    let proof = name.sign_proof(CHALLENGE).unwrap();
+
+    let mut tampered = proof.clone();
+    tampered.challenge = "prove you control this name: 000000".to_string();
+    let err = name.to_name().verify_proof(&tampered).unwrap_err();
+    assert!(format!("{:?}", err).contains("the signature doesn't match"));
+
+    let mut tampered = proof.clone();
+    tampered.timestamp = proof.timestamp + Duration::seconds(1);
+    assert!(name.to_name().verify_proof(&tampered).is_err());
+
+    let other = WritableName::new().to_name();
+    let err = other.verify_proof(&proof).unwrap_err();
+    assert!(format!("{:?}", err).contains(&format!("the proof is for {}", name)));
+
+    // claiming another name doesn't help either
+    let mut tampered = proof;
+    tampered.name = other.clone();
+    assert!(other.verify_proof(&tampered).is_err());
+  }
+
+  #[test]
+  fn reports_age_of_old_proofs() {
+    let name = WritableName::new();
+    let old = proof_from(&name, CHALLENGE, Duration::days(30));
+
+    let age = name.to_name().verify_proof(&old).unwrap();
+    assert!(age >= Duration::days(30));
+    let err = name
+      .to_name()
+      .verify_proof_within(&old, Duration::hours(1))
+      .unwrap_err();
+    assert!(format!("{:?}", err).contains("more than the maximum of 3600 seconds"));
+    name
+      .to_name()
+      .verify_proof_within(&old, Duration::days(31))
+      .unwrap();
+
+    let future = proof_from(&name, CHALLENGE, Duration::hours(-1));
+    let err = name.to_name().verify_proof(&future).unwrap_err();
+    assert!(format!("{:?}", err).contains("timestamped in the future"));
+  }
+
+  #[test]
+  fn rejects_malformed_json() {
+    assert!(Proof::from_json("{}").is_err());
+    let proof = WritableName::new().sign_proof(CHALLENGE).unwrap();
+    let json = proof
+      .to_json()
+      .replace(&proof.name().to_string(), "k51nope");
+    assert!(Proof::from_json(&json).is_err());
+  }
+}
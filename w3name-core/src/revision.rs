@@ -0,0 +1,1919 @@
+use crate::{
+  clock::{self, Clock, SystemClock},
+  error::{
+    CborError, IpnsError, NameMismatch, RevisionError, ValidityOutOfRange, ValidityTooShort,
+  },
+  ipns::{
+    deserialize_ipns_entry_untrusted, revision_from_ipns_entry_unchecked,
+    revision_to_hybrid_ipns_entry, revision_to_ipns_entry, serialize_ipns_entry,
+    validate_ipns_entry_for_name,
+  },
+  ipns_pb::IpnsEntry,
+  json_value,
+  name::{Name, WritableName},
+  timestamp::{self, Duration, Timestamp},
+};
+use error_stack::{report, IntoReport, Report, Result, ResultExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+  collections::HashMap,
+  fmt::{self, Display},
+  str::FromStr,
+  sync::Arc,
+};
+
+/// The type of a record's validity field, which determines how the validity is interpreted.
+///
+/// The only type defined by the IPNS spec is [ValidityType::Eol], where the validity is an RFC3339
+/// end-of-life timestamp. Records declaring any other type are rejected by [validate_ipns_entry](crate::ipns::validate_ipns_entry)
+/// with [UnsupportedValidityType](crate::error::UnsupportedValidityType), since their validity can't be evaluated.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ValidityType {
+  /// The record is valid until the end-of-life timestamp in its validity field.
+  #[default]
+  Eol,
+  /// A validity type this crate doesn't know about, with its protobuf enum value.
+  Unknown(i32),
+}
+
+impl ValidityType {
+  /// Returns the protobuf enum value for this validity type.
+  pub fn code(&self) -> i32 {
+    match self {
+      ValidityType::Eol => 0,
+      ValidityType::Unknown(code) => *code,
+    }
+  }
+}
+
+impl From<i32> for ValidityType {
+  fn from(code: i32) -> Self {
+    match code {
+      0 => ValidityType::Eol,
+      code => ValidityType::Unknown(code),
+    }
+  }
+}
+
+impl Display for ValidityType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ValidityType::Eol => write!(f, "EOL"),
+      ValidityType::Unknown(code) => write!(f, "unknown ({})", code),
+    }
+  }
+}
+
+/// How [Revision::increment_with_policy] picks the validity and TTL of the next revision.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IncrementPolicy {
+  /// Keeps the previous revision's TTL, and gives the next revision a validity window of the same
+  /// length as the previous one's, counted from now.
+  ///
+  /// The length of the previous window is its [validity_window](Revision::validity_window), so a
+  /// revision published for 7 days gets 7 days again however long after it's updated. Records
+  /// don't carry the window, so a revision resolved from one only knows it once it's been
+  /// [matched](Revision::with_validity_window_of) with the revision that was published, e.g.
+  /// from local state. Revisions that don't know their window, like one resolved with no local
+  /// state, keep their end of life instead: the next window lasts until the previous revision's
+  /// validity, but at least as long as its TTL.
+  PreserveDurations,
+
+  /// Keeps the previous revision's TTL, and uses the default validity window of one year.
+  ///
+  /// This is what [Revision::increment] does.
+  #[default]
+  Defaults,
+
+  /// Uses the given validity window, counted from now, and TTL.
+  Explicit { validity: Duration, ttl: Duration },
+}
+
+impl IncrementPolicy {
+  /// Returns the validity window and the TTL that this policy gives the successor of `previous`.
+  pub fn durations(&self, previous: &Revision) -> (Duration, Duration) {
+    self.durations_at(previous, SystemClock.now())
+  }
+
+  /// Like [durations](Self::durations), but counts the time left of a window that isn't known
+  /// from `now` rather than the system clock.
+  pub fn durations_at(&self, previous: &Revision, now: Timestamp) -> (Duration, Duration) {
+    match *self {
+      IncrementPolicy::PreserveDurations => (
+        previous
+          .validity_window
+          .unwrap_or_else(|| (previous.validity - now).max(previous.ttl)),
+        previous.ttl,
+      ),
+      IncrementPolicy::Defaults => (default_validity_duration(), previous.ttl),
+      IncrementPolicy::Explicit { validity, ttl } => (validity, ttl),
+    }
+  }
+}
+
+/// A `Revision` represents a single value for a name record.
+///
+/// A `Revision` is essentially an IPNS entry without a signature, and it
+/// contains all the information needed to construct an IPNS entry for signing.
+///
+/// Each `Revision` contains a sequence number, which must be incremented when publishing
+/// updates to an existing `Revision`. To create the initial `Revision` (with sequence number == 0),
+/// use [Revision::v0]. Subsequent `Revision`s are created by calling [increment](Revision::increment)
+/// on an existing `Revision`.
+///
+/// The IPNS spec allows values to be arbitrary bytes. Values that aren't valid UTF-8 are kept
+/// byte-for-byte (see [value_bytes](Self::value_bytes)), so that such records can still be
+/// parsed, validated and re-signed.
+///
+/// Two `Revision`s are equal if they make the same record, so the
+/// [validity_window](Self::validity_window), which records don't carry, isn't compared.
+#[derive(Clone, Debug)]
+pub struct Revision {
+  name: Name,
+  /// The value, or its lossy UTF-8 conversion if it isn't valid UTF-8.
+  value: String,
+  /// The exact bytes of the value, only if it isn't valid UTF-8.
+  binary_value: Option<Vec<u8>>,
+  sequence: u64,
+  validity: Timestamp,
+  validity_type: ValidityType,
+  ttl: Duration,
+  /// The length of the validity window, counted from when the revision was created, if known.
+  validity_window: Option<Duration>,
+}
+
+impl Revision {
+  /// Creates a new `Revision`, specifying all fields.
+  ///
+  /// Note that this isn't part of the public API, and only public for the `w3name` crate; users
+  /// should use [Self::v0] or [Self::increment]
+  #[doc(hidden)]
+  pub fn new<S: AsRef<str>>(
+    name: &Name,
+    value: S,
+    validity: Timestamp,
+    ttl: Duration,
+    sequence: u64,
+  ) -> Revision {
+    let value = value.as_ref().to_string();
+    let name = name.clone();
+    Revision {
+      name,
+      value,
+      binary_value: None,
+      sequence,
+      validity,
+      validity_type: ValidityType::Eol,
+      ttl,
+      validity_window: None,
+    }
+  }
+
+  pub(crate) fn with_validity_type(mut self, validity_type: ValidityType) -> Revision {
+    self.validity_type = validity_type;
+    self
+  }
+
+  pub(crate) fn with_validity_window(mut self, validity_window: Option<Duration>) -> Revision {
+    self.validity_window = validity_window;
+    self
+  }
+
+  /// Replaces the value with `bytes`, which needn't be valid UTF-8.
+  pub(crate) fn with_value_bytes(mut self, bytes: Vec<u8>) -> Revision {
+    match String::from_utf8(bytes) {
+      Ok(value) => {
+        self.value = value;
+        self.binary_value = None;
+      }
+      Err(err) => {
+        self.value = String::from_utf8_lossy(err.as_bytes()).into_owned();
+        self.binary_value = Some(err.into_bytes());
+      }
+    }
+    self
+  }
+
+  /// Creates the initial `Revision` for the given [Name], with a sequence number of 0 and the default validity period (1 year).
+  ///
+  /// ## Example
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::{Name, Revision};
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  /// let rev = Revision::v0(&name, "an initial value");
+  ///
+  /// assert_eq!(&name, rev.name());
+  /// assert_eq!(rev.value(), "an initial value");
+  /// # Ok(())
+  /// # }
+  ///
+  ///
+  /// ```
+  pub fn v0<S: AsRef<str>>(name: &Name, value: S) -> Revision {
+    Revision {
+      name: name.clone(),
+      value: value.as_ref().to_string(),
+      binary_value: None,
+      sequence: 0,
+      validity: default_validity(),
+      validity_type: ValidityType::Eol,
+      ttl: default_ttl(),
+      validity_window: Some(default_validity_duration()),
+    }
+  }
+
+  /// Creates the initial `Revision` for the given [Name], with an explicit validity period.
+  ///
+  /// Note that `validity` is an end-of-life timestamp, not a duration.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main () -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::{timestamp::Duration, Clock, Name, Revision, SystemClock};
+  ///
+  /// // set the expiration date to two weeks from now:
+  /// let expiration_date = SystemClock.now() + Duration::weeks(2);
+  /// let ttl = Duration::days(7); // cache for 7 days
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  /// let rev = Revision::v0_with_validity(&name, "an initial value", expiration_date, ttl);
+  ///
+  /// assert_eq!(&name, rev.name());
+  /// assert_eq!(rev.value(), "an initial value");
+  /// assert_eq!(rev.validity(), &expiration_date);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn v0_with_validity<S: AsRef<str>>(
+    name: &Name,
+    value: S,
+    validity: Timestamp,
+    ttl: Duration,
+  ) -> Revision {
+    Revision::new(name, value, validity, ttl, 0)
+  }
+
+  /// Creates a new `Revision` with the given `value` and an incremented sequence number, using the default validity period (1 year).
+  ///
+  /// The TTL is kept, but the validity window isn't: use [increment_with_policy](Self::increment_with_policy)
+  /// with [IncrementPolicy::PreserveDurations] to keep it too.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::{Name, Revision};
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  /// let rev = Revision::v0(&name, "an initial value");
+  /// let rev2 = rev.increment("a new value");
+  ///
+  /// assert_eq!(&name, rev.name());
+  /// assert_eq!(&name, rev2.name());
+  /// assert_eq!(rev.sequence(), 0);
+  /// assert_eq!(rev2.sequence(), 1);
+  /// assert_eq!(rev.value(), "an initial value");
+  /// assert_eq!(rev2.value(), "a new value");
+  ///
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn increment<S: AsRef<str>>(&self, value: S) -> Revision {
+    Self::increment_with_validity(self, value, default_validity())
+      .with_validity_window(Some(default_validity_duration()))
+  }
+
+  /// Creates a new `Revision` with the given `value` and an incremented sequence number, with the
+  /// validity period and TTL chosen by `policy`.
+  ///
+  /// Fails with [ValidityOutOfRange] if the validity period would end after the year 9999.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::{timestamp::Duration, Clock, IncrementPolicy, Name, RevisionBuilder, SystemClock};
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  /// let rev = RevisionBuilder::new(&name, "an initial value")
+  ///   .validity_for(Duration::weeks(1))
+  ///   .ttl(Duration::hours(1))
+  ///   .build()
+  ///   .expect("a week is long enough");
+  ///
+  /// let rev2 = rev
+  ///   .increment_with_policy("a new value", IncrementPolicy::PreserveDurations)
+  ///   .expect("a week from now is in range");
+  /// assert_eq!(rev2.ttl(), Duration::hours(1));
+  /// assert_eq!(rev2.validity_window(), Some(Duration::weeks(1)));
+  /// assert!(*rev2.validity() <= SystemClock.now() + Duration::weeks(1));
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn increment_with_policy<S: AsRef<str>>(
+    &self,
+    value: S,
+    policy: IncrementPolicy,
+  ) -> Result<Revision, RevisionError> {
+    self.increment_with_policy_at(value, policy, SystemClock.now())
+  }
+
+  /// Like [increment_with_policy](Self::increment_with_policy), but counts the validity period
+  /// from `now` rather than the system clock, e.g. the time of an injected [Clock].
+  pub fn increment_with_policy_at<S: AsRef<str>>(
+    &self,
+    value: S,
+    policy: IncrementPolicy,
+    now: Timestamp,
+  ) -> Result<Revision, RevisionError> {
+    let (validity, ttl) = policy.durations_at(self, now);
+    let mut next = self
+      .increment_with_validity(value, validity_after(now, validity)?)
+      .with_validity_window(Some(validity));
+    next.ttl = ttl;
+    Ok(next)
+  }
+
+  /// Creates a new `Revision` with the given `value` and an incremented sequence number, with an explicit validity period.
+  ///
+  /// Note that `validity` is an end-of-life timestamp, not a duration.
+  pub fn increment_with_validity<S: AsRef<str>>(&self, value: S, validity: Timestamp) -> Revision {
+    let sequence = self.sequence + 1;
+    Revision {
+      name: self.name.clone(),
+      value: value.as_ref().to_string(),
+      binary_value: None,
+      sequence,
+      validity,
+      validity_type: self.validity_type,
+      ttl: self.ttl,
+      validity_window: None,
+    }
+  }
+
+  /// Returns a reference to this `Revision`'s [Name].
+  pub fn name(&self) -> &Name {
+    &self.name
+  }
+
+  /// Returns a reference to this `Revision`'s value.
+  ///
+  /// If the value isn't valid UTF-8, this is a lossy conversion, with invalid sequences replaced
+  /// by `U+FFFD`; use [value_bytes](Self::value_bytes) to get the exact value.
+  pub fn value(&self) -> &str {
+    &self.value
+  }
+
+  /// Returns the exact bytes of this `Revision`'s value, which needn't be valid UTF-8.
+  pub fn value_bytes(&self) -> &[u8] {
+    match &self.binary_value {
+      Some(bytes) => bytes,
+      None => self.value.as_bytes(),
+    }
+  }
+
+  /// Returns the length of this `Revision`'s value in bytes, which is more than its number of
+  /// characters if it has multi-byte UTF-8 characters.
+  pub fn value_len(&self) -> usize {
+    self.value_bytes().len()
+  }
+
+  /// Parses the value as a JSON document, failing with
+  /// [InvalidJsonValue](crate::error::InvalidJsonValue) if it isn't JSON, or isn't a `T`.
+  ///
+  /// See [RevisionBuilder::value_json] for publishing one.
+  pub fn value_json<T: DeserializeOwned>(&self) -> Result<T, RevisionError> {
+    json_value::parse(self.value_bytes())
+  }
+
+  /// Returns true if the value is valid UTF-8, so [value](Self::value) returns it exactly.
+  pub fn is_value_utf8(&self) -> bool {
+    self.binary_value.is_none()
+  }
+
+  /// Returns this `Revision`'s sequence number.
+  pub fn sequence(&self) -> u64 {
+    self.sequence
+  }
+
+  /// Returns this `Revision`'s validity period (end of life date).
+  pub fn validity(&self) -> &Timestamp {
+    &self.validity
+  }
+
+  /// Returns the length of the validity window this `Revision` was created with: how long before
+  /// its [validity](Self::validity) it was created, if that's known.
+  ///
+  /// Revisions created with a validity counted from when they were created, as [Revision::v0],
+  /// [Revision::increment] and [RevisionBuilder::validity_for] do, know it, and it's kept by
+  /// [encode](Self::encode) and the single-line format. Revisions created with an explicit end of
+  /// life don't, and neither do revisions read from records, which have no field for it.
+  pub fn validity_window(&self) -> Option<Duration> {
+    self.validity_window
+  }
+
+  /// Returns this `Revision` with the validity window of `published`, if this one doesn't know
+  /// its window and is the same revision: it has the same name, sequence number and validity.
+  ///
+  /// This recovers the window of a revision resolved from a record, given the revision that was
+  /// published, e.g. as kept in local state in the single-line format.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::IpnsError> {
+  /// use w3name_core::{timestamp::Duration, Revision, RevisionBuilder, WritableName};
+  ///
+  /// let key = WritableName::new();
+  /// let published = RevisionBuilder::new(&key.to_name(), "a value")
+  ///   .validity_for(Duration::weeks(1))
+  ///   .build()
+  ///   .expect("a week is in range");
+  /// let record = published.to_signed_bytes(&key)?;
+  ///
+  /// let resolved = Revision::from_signed_bytes(&key.to_name(), &record)?;
+  /// assert_eq!(resolved.validity_window(), None);
+  /// let resolved = resolved.with_validity_window_of(&published);
+  /// assert_eq!(resolved.validity_window(), Some(Duration::weeks(1)));
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn with_validity_window_of(self, published: &Revision) -> Revision {
+    if self.validity_window.is_some()
+      || self.name != published.name
+      || self.sequence != published.sequence
+      || self.validity != published.validity
+    {
+      return self;
+    }
+    let validity_window = published.validity_window;
+    self.with_validity_window(validity_window)
+  }
+
+  pub(crate) fn validity_window_nanos(&self) -> Option<u64> {
+    self
+      .validity_window
+      .map(|window| timestamp::duration_nanos(&window))
+  }
+
+  /// Returns the type of this `Revision`'s validity field. This is [ValidityType::Eol] unless set
+  /// otherwise with [RevisionBuilder::validity_type].
+  pub fn validity_type(&self) -> ValidityType {
+    self.validity_type
+  }
+
+  /// Returns `true` if this `Revision`'s validity period (end of life date) has already passed.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::NameError> {
+  /// use w3name_core::{timestamp::Duration, Clock, Name, Revision, SystemClock};
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu")?;
+  /// let yesterday = SystemClock.now() - Duration::days(1);
+  /// let rev = Revision::v0_with_validity(&name, "an old value", yesterday, Duration::hours(1));
+  ///
+  /// assert!(rev.is_expired());
+  /// assert!(!Revision::v0(&name, "a fresh value").is_expired());
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn is_expired(&self) -> bool {
+    self.is_expired_at(SystemClock.now())
+  }
+
+  /// Returns `true` if this `Revision`'s validity period had ended by `now`.
+  pub fn is_expired_at(&self, now: Timestamp) -> bool {
+    self.validity < now
+  }
+
+  /// Returns this `Revision`'s validity period as a String, suitable for inclusion in an IPNS record.
+  /// A validity after the year 9999 can't be included in a record, and is written the way its
+  /// type displays it instead.
+  pub fn validity_string(&self) -> String {
+    timestamp::format_rfc3339(&self.validity).unwrap_or_else(|_| self.validity.to_string())
+  }
+
+  /// Returns this `Revision`'s validity period as nanoseconds since the Unix epoch, for callers
+  /// that don't use chrono.
+  pub fn validity_unix_nanos(&self) -> i128 {
+    timestamp::unix_nanos(&self.validity)
+  }
+
+  /// Returns this `Revision`'s TTL (time to live) duration.
+  pub fn ttl(&self) -> Duration {
+    self.ttl
+  }
+
+  /// Returns this `Revision`'s TTL in nanoseconds, as encoded in IPNS records.
+  pub fn ttl_nanos(&self) -> u64 {
+    timestamp::duration_nanos(&self.ttl)
+  }
+
+  /// Checks this `Revision`'s TTL for values that are valid, but likely to make resolvers misbehave,
+  /// as of `now`. The result is empty if the TTL looks sensible.
+  ///
+  /// Publishing tools can show the notes as warnings before signing, and inspection tools as
+  /// information about records authored elsewhere.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::{timestamp::Duration, Clock, Revision, SystemClock, TtlChecks, TtlNote, WritableName};
+  ///
+  /// let name = WritableName::new().to_name();
+  /// let now = SystemClock.now();
+  /// let rev = Revision::v0_with_validity(&name, "value", now + Duration::hours(1), Duration::seconds(0));
+  /// assert_eq!(rev.ttl_notes(now, &TtlChecks::default()), vec![TtlNote::Zero]);
+  ///
+  /// let quiet = TtlChecks { zero: false, ..TtlChecks::default() };
+  /// assert!(rev.ttl_notes(now, &quiet).is_empty());
+  /// ```
+  pub fn ttl_notes(&self, now: Timestamp, checks: &TtlChecks) -> Vec<TtlNote> {
+    let mut notes = vec![];
+    if checks.zero && self.ttl.is_zero() {
+      notes.push(TtlNote::Zero);
+    }
+    // an expired record can't be cached for too long anymore
+    let remaining = self.validity - now;
+    if checks.exceeds_validity && remaining > timestamp::zero() && self.ttl > remaining {
+      notes.push(TtlNote::ExceedsValidity {
+        ttl: self.ttl,
+        remaining,
+      });
+    }
+    if let Some(max_ttl) = checks.max_ttl.filter(|max_ttl| self.ttl > *max_ttl) {
+      notes.push(TtlNote::ExceedsMax {
+        ttl: self.ttl,
+        max_ttl,
+      });
+    }
+    notes
+  }
+
+  /// Returns true if this `Revision` takes precedence over `other`, following the IPNS rules:
+  /// the higher sequence number wins, and for equal sequence numbers the later validity wins.
+  ///
+  /// Revisions for different names never supersede each other.
+  pub fn supersedes(&self, other: &Revision) -> bool {
+    self.name == other.name && (self.sequence, self.validity) > (other.sequence, other.validity)
+  }
+
+  /// Encodes this `Revision` to a binary form, suitable for use with [Revision::decode].
+  ///
+  /// Note that encoded `Revision`s are not signed and cannot be used directly as IPNS records.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::CborError> {
+  /// use w3name_core::{Name, Revision};
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu").unwrap();
+  /// let rev = Revision::v0(&name, "an initial value");
+  ///
+  /// let bytes = rev.encode()?;
+  /// let rev2 = Revision::decode(&bytes)?;
+  ///
+  /// assert_eq!(rev, rev2);
+  ///
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn encode(&self) -> Result<Vec<u8>, CborError> {
+    let data = RevisionCbor {
+      name: self.name.to_string(),
+      value: self.value.clone(),
+      value_bytes: self.binary_value.clone(),
+      sequence: self.sequence,
+      validity: timestamp::format_rfc3339(&self.validity).change_context(CborError)?,
+      validity_type: self.validity_type.code(),
+      ttl: timestamp::checked_duration_nanos(&self.ttl)
+        .ok_or(CborError)?
+        .try_into()
+        .report()
+        .change_context(CborError)?,
+      validity_window: self.validity_window_nanos(),
+    };
+    let bytes = serde_cbor::to_vec(&data)
+      .report()
+      .change_context(CborError)?;
+    Ok(bytes)
+  }
+
+  /// Decodes a `Revision` from a binary form as produced by [Revision::encode].
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::CborError> {
+  /// use w3name_core::{Name, Revision};
+  ///
+  /// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu").unwrap();
+  /// let rev = Revision::v0(&name, "an initial value");
+  ///
+  /// let bytes = rev.encode()?;
+  /// let rev2 = Revision::decode(&bytes)?;
+  ///
+  /// assert_eq!(rev, rev2);
+  ///
+  /// # Ok(())
+  /// # }
+  pub fn decode(bytes: &[u8]) -> Result<Revision, CborError> {
+    let data: RevisionCbor = serde_cbor::from_slice(bytes)
+      .report()
+      .change_context(CborError)?;
+    let name = Name::parse(data.name).change_context(CborError)?;
+    let validity = timestamp::parse_rfc3339(&data.validity).change_context(CborError)?;
+    let ttl = timestamp::duration_from_nanos(data.ttl).ok_or_else(|| report!(CborError))?;
+
+    let validity_window = data
+      .validity_window
+      .map(|nanos| timestamp::duration_from_nanos(nanos).ok_or_else(|| report!(CborError)))
+      .transpose()?;
+
+    let rev = Revision {
+      name,
+      value: data.value,
+      binary_value: None,
+      sequence: data.sequence,
+      validity,
+      validity_type: data.validity_type.into(),
+      ttl,
+      validity_window,
+    };
+
+    Ok(match data.value_bytes {
+      Some(bytes) => rev.with_value_bytes(bytes),
+      None => rev,
+    })
+  }
+
+  /// Signs this `Revision` with `key` and returns the serialized IPNS record, ready to be published
+  /// or stored.
+  ///
+  /// Fails with [NameMismatch] if `key` isn't the key of this revision's name.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::IpnsError> {
+  /// use w3name_core::{Revision, WritableName};
+  ///
+  /// let key = WritableName::new();
+  /// let rev = Revision::v0(&key.to_name(), "an initial value");
+  ///
+  /// let bytes = rev.to_signed_bytes(&key)?;
+  /// assert_eq!(Revision::from_signed_bytes(&key.to_name(), &bytes)?, rev);
+  ///
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn to_signed_bytes(&self, key: &WritableName) -> Result<Vec<u8>, IpnsError> {
+    if key.to_name() != self.name {
+      return Err(
+        report!(NameMismatch)
+          .attach_printable(format!("expected the key of {}, got {}", self.name, key))
+          .change_context(IpnsError),
+      );
+    }
+    let entry = revision_to_ipns_entry(self, key.keypair())?;
+    serialize_ipns_entry(&entry)
+  }
+
+  /// Like [to_signed_bytes](Self::to_signed_bytes), but the record also carries the V1 fields
+  /// and signature, for endpoints that only accept V1+V2 records.
+  pub fn to_hybrid_signed_bytes(&self, key: &WritableName) -> Result<Vec<u8>, IpnsError> {
+    if key.to_name() != self.name {
+      return Err(
+        report!(NameMismatch)
+          .attach_printable(format!("expected the key of {}, got {}", self.name, key))
+          .change_context(IpnsError),
+      );
+    }
+    let entry = revision_to_hybrid_ipns_entry(self, key.keypair())?;
+    serialize_ipns_entry(&entry)
+  }
+
+  /// Parses a serialized IPNS record for `name`, as produced by [to_signed_bytes](Self::to_signed_bytes)
+  /// or fetched from the network, and returns its `Revision`.
+  ///
+  /// This is the same as [try_from_record_bytes](Self::try_from_record_bytes).
+  pub fn from_signed_bytes(name: &Name, bytes: &[u8]) -> Result<Revision, IpnsError> {
+    Revision::try_from_record_bytes(bytes, name)
+  }
+
+  /// Parses a serialized IPNS record for `name` and returns its `Revision`.
+  ///
+  /// The record is [validated](crate::ipns::validate_ipns_entry_for_name) first, so records with
+  /// an invalid signature, or that belong to a different name, are rejected. Since the bytes may
+  /// come from anywhere, they're decoded with the [default limits](crate::ipns::DecodeLimits).
+  pub fn try_from_record_bytes(bytes: &[u8], name: &Name) -> Result<Revision, IpnsError> {
+    let entry = deserialize_ipns_entry_untrusted(bytes)?;
+    Revision::try_from_entry(&entry, name)
+  }
+
+  /// Validates a decoded IPNS record against `name`, and returns its `Revision`.
+  ///
+  /// Like [try_from_record_bytes](Self::try_from_record_bytes), this rejects records with an
+  /// invalid signature or that belong to a different name. To read a record that's been validated
+  /// some other way, e.g. [leniently](crate::ipns::validate_ipns_entry_lenient), use
+  /// [revision_from_ipns_entry_unchecked](crate::ipns::revision_from_ipns_entry_unchecked).
+  pub fn try_from_entry(entry: &IpnsEntry, name: &Name) -> Result<Revision, IpnsError> {
+    validate_ipns_entry_for_name(entry, name)?;
+    revision_from_ipns_entry_unchecked(entry, name)
+  }
+}
+
+impl PartialEq for Revision {
+  fn eq(&self, other: &Revision) -> bool {
+    self.name == other.name
+      && self.value == other.value
+      && self.binary_value == other.binary_value
+      && self.sequence == other.sequence
+      && self.validity == other.validity
+      && self.validity_type == other.validity_type
+      && self.ttl == other.ttl
+  }
+}
+
+impl Eq for Revision {}
+
+/// Validates the record against the name, like [Revision::try_from_entry].
+impl TryFrom<(&IpnsEntry, &Name)> for Revision {
+  type Error = Report<IpnsError>;
+
+  fn try_from((entry, name): (&IpnsEntry, &Name)) -> Result<Revision, IpnsError> {
+    Revision::try_from_entry(entry, name)
+  }
+}
+
+/// Decodes and validates the record against the name, like [Revision::try_from_record_bytes].
+impl TryFrom<(&[u8], &Name)> for Revision {
+  type Error = Report<IpnsError>;
+
+  fn try_from((bytes, name): (&[u8], &Name)) -> Result<Revision, IpnsError> {
+    Revision::try_from_record_bytes(bytes, name)
+  }
+}
+
+/// Which of the TTL checks of [Revision::ttl_notes] to make.
+///
+/// By default, every check is made, with a `max_ttl` of 31 days: w3name's default TTL, so that
+/// records with the default TTL don't trip it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TtlChecks {
+  /// Note a zero TTL ([TtlNote::Zero]).
+  pub zero: bool,
+  /// Note a TTL longer than the remaining validity ([TtlNote::ExceedsValidity]).
+  pub exceeds_validity: bool,
+  /// Note a TTL longer than this ([TtlNote::ExceedsMax]), if set.
+  pub max_ttl: Option<Duration>,
+}
+
+impl Default for TtlChecks {
+  fn default() -> Self {
+    TtlChecks {
+      zero: true,
+      exceeds_validity: true,
+      max_ttl: Some(default_ttl()),
+    }
+  }
+}
+
+/// A TTL that's valid, but likely to make resolvers misbehave. See [Revision::ttl_notes].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TtlNote {
+  /// The TTL is zero, so resolvers can't cache the record and ask for it on every lookup.
+  Zero,
+  /// The TTL is longer than the time until the record expires, so caches may keep serving the
+  /// record after it has expired.
+  ExceedsValidity { ttl: Duration, remaining: Duration },
+  /// The TTL is longer than the configured maximum, so a new value may take that long to reach
+  /// resolvers that cached the old one.
+  ExceedsMax { ttl: Duration, max_ttl: Duration },
+}
+
+impl Display for TtlNote {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let human = |duration: &Duration| timestamp::humanize(*duration, false);
+    match self {
+      TtlNote::Zero => write!(
+        f,
+        "the TTL is zero, so resolvers can't cache the record and will ask for it on every lookup"
+      ),
+      TtlNote::ExceedsValidity { ttl, remaining } => write!(
+        f,
+        "the TTL ({}) is longer than the record stays valid ({}), so caches may serve it after it expires",
+        human(ttl),
+        human(remaining)
+      ),
+      TtlNote::ExceedsMax { ttl, max_ttl } => write!(
+        f,
+        "the TTL ({}) is longer than {}, so new values may take that long to reach resolvers",
+        human(ttl),
+        human(max_ttl)
+      ),
+    }
+  }
+}
+
+/// `RevisionBuilder` creates a [Revision] step by step, with optional validation of the result.
+///
+/// Use [RevisionBuilder::new] to build the initial revision for a name, or [RevisionBuilder::next]
+/// to build the successor of an existing revision. Fields that aren't set use the same defaults
+/// as [Revision::v0] and [Revision::increment].
+///
+/// ## Example
+///
+/// ```rust
+/// # fn main() -> error_stack::Result<(), w3name_core::error::RevisionError> {
+/// use w3name_core::{timestamp::Duration, Name, RevisionBuilder};
+///
+/// let name = Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu").unwrap();
+/// let rev = RevisionBuilder::new(&name, "an initial value")
+///   .validity_for(Duration::days(30))
+///   .ttl(Duration::minutes(10))
+///   .min_validity(Duration::days(1))
+///   .build()?;
+///
+/// let rev2 = RevisionBuilder::next(&rev, "a new value").build()?;
+/// assert_eq!(rev2.sequence(), 1);
+/// assert_eq!(rev2.ttl(), Duration::minutes(10));
+///
+/// // a validity window shorter than the minimum is rejected
+/// let too_short = RevisionBuilder::next(&rev2, "oops")
+///   .validity_for(Duration::minutes(5))
+///   .min_validity(Duration::hours(24))
+///   .build();
+/// assert!(too_short.is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RevisionBuilder {
+  name: Name,
+  value: Vec<u8>,
+  sequence: u64,
+  validity: Option<Validity>,
+  validity_type: ValidityType,
+  ttl: Duration,
+  min_validity: Option<Duration>,
+  clock: Arc<dyn Clock>,
+}
+
+/// The validity window and TTL that [RevisionBuilder::with_defaults] gives revisions in place of
+/// the built-in ones, e.g. to apply an organization's policy to every record. Fields that aren't
+/// set keep the built-in defaults.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Defaults {
+  /// How long revisions stay valid, counted from when they're built, instead of one year.
+  pub validity: Option<Duration>,
+  /// The TTL of revisions, instead of 31 days, or the previous revision's TTL for a successor.
+  pub ttl: Option<Duration>,
+}
+
+/// The validity period given to a [RevisionBuilder].
+#[derive(Clone, Copy, Debug)]
+enum Validity {
+  At(Timestamp),
+  /// Counted from when the revision is built.
+  For(Duration),
+}
+
+impl RevisionBuilder {
+  /// Starts building the initial revision (sequence number 0) for the given [Name].
+  pub fn new<S: AsRef<str>>(name: &Name, value: S) -> RevisionBuilder {
+    RevisionBuilder {
+      name: name.clone(),
+      value: value.as_ref().as_bytes().to_vec(),
+      sequence: 0,
+      validity: None,
+      validity_type: ValidityType::Eol,
+      ttl: default_ttl(),
+      min_validity: None,
+      clock: clock::system(),
+    }
+  }
+
+  /// Starts building the successor of `previous`, with an incremented sequence number and the same TTL.
+  pub fn next<S: AsRef<str>>(previous: &Revision, value: S) -> RevisionBuilder {
+    RevisionBuilder {
+      name: previous.name.clone(),
+      value: value.as_ref().as_bytes().to_vec(),
+      sequence: previous.sequence + 1,
+      validity: None,
+      validity_type: previous.validity_type,
+      ttl: previous.ttl,
+      min_validity: None,
+      clock: clock::system(),
+    }
+  }
+
+  /// Uses `defaults` for the validity period and the TTL, unless they're set explicitly.
+  ///
+  /// Unlike the built-in default, a default TTL applies to the successor of a revision too,
+  /// rather than the previous revision's TTL. Call [ttl](Self::ttl) afterwards to override it.
+  pub fn with_defaults(mut self, defaults: &Defaults) -> RevisionBuilder {
+    if let Some(validity) = defaults.validity {
+      self.validity.get_or_insert(Validity::For(validity));
+    }
+    if let Some(ttl) = defaults.ttl {
+      self.ttl = ttl;
+    }
+    self
+  }
+
+  /// Replaces the value with `bytes`, which needn't be valid UTF-8.
+  ///
+  /// To re-sign a revision byte-for-byte, use `RevisionBuilder::next(&previous, "")
+  /// .value_bytes(previous.value_bytes())`.
+  pub fn value_bytes<B: Into<Vec<u8>>>(mut self, bytes: B) -> RevisionBuilder {
+    self.value = bytes.into();
+    self
+  }
+
+  /// Replaces the value with `document` in [canonical JSON form](crate::json_value), failing with
+  /// [RecordTooLarge](crate::error::RecordTooLarge) if that's too large for a record.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// # fn main() -> error_stack::Result<(), w3name_core::error::RevisionError> {
+  /// use w3name_core::{RevisionBuilder, WritableName};
+  /// use serde_json::json;
+  ///
+  /// let name = WritableName::new().to_name();
+  /// let manifest = json!({ "previous": "/ipfs/bafyold", "current": "/ipfs/bafynew" });
+  /// let rev = RevisionBuilder::new(&name, "").value_json(&manifest)?.build()?;
+  /// assert_eq!(rev.value(), r#"{"current":"/ipfs/bafynew","previous":"/ipfs/bafyold"}"#);
+  ///
+  /// let resolved: serde_json::Value = rev.value_json()?;
+  /// assert_eq!(resolved, manifest);
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn value_json(mut self, document: &impl Serialize) -> Result<RevisionBuilder, RevisionError> {
+    self.value = json_value::to_canonical(document)?.into_bytes();
+    Ok(self)
+  }
+
+  /// Sets the validity period (end of life date) to an absolute timestamp.
+  pub fn validity_at(mut self, validity: Timestamp) -> RevisionBuilder {
+    self.validity = Some(Validity::At(validity));
+    self
+  }
+
+  /// Sets the validity period (end of life date) to `duration` from when the revision is built.
+  pub fn validity_for(mut self, duration: Duration) -> RevisionBuilder {
+    self.validity = Some(Validity::For(duration));
+    self
+  }
+
+  /// Sets the [Clock] that "now" is read from, for the validity period and the
+  /// [min_validity](Self::min_validity) guard. Defaults to the [SystemClock].
+  pub fn clock(mut self, clock: Arc<dyn Clock>) -> RevisionBuilder {
+    self.clock = clock;
+    self
+  }
+
+  /// Sets the validity type. Defaults to [ValidityType::Eol], or the type of the previous revision.
+  ///
+  /// Note that records with any other type are rejected when validated, by this crate and by the w3name service.
+  pub fn validity_type(mut self, validity_type: ValidityType) -> RevisionBuilder {
+    self.validity_type = validity_type;
+    self
+  }
+
+  /// Sets the TTL (time to live) duration.
+  pub fn ttl(mut self, ttl: Duration) -> RevisionBuilder {
+    self.ttl = ttl;
+    self
+  }
+
+  /// Opts in to a guard that makes [build](Self::build) fail with [ValidityTooShort] if the revision
+  /// would stop being valid less than `min_validity` from now.
+  ///
+  /// This catches mistakes like a unit mix-up that would make a name expire within minutes,
+  /// including absolute validity timestamps that are already in the past.
+  pub fn min_validity(mut self, min_validity: Duration) -> RevisionBuilder {
+    self.min_validity = Some(min_validity);
+    self
+  }
+
+  /// Builds the [Revision], applying any validation that was opted in to.
+  ///
+  /// Fails with [ValidityOutOfRange] if the validity period ends after the year 9999, which can't
+  /// be written in a record.
+  ///
+  /// A TTL that's merely questionable doesn't fail the build; check the result with
+  /// [Revision::ttl_notes] to warn about it.
+  pub fn build(self) -> Result<Revision, RevisionError> {
+    let now = self.clock.now();
+    let (validity, validity_window) = match self.validity {
+      Some(Validity::At(validity)) => (validity, None),
+      Some(Validity::For(duration)) => (validity_after(now, duration)?, Some(duration)),
+      None => (
+        validity_after(now, default_validity_duration())?,
+        Some(default_validity_duration()),
+      ),
+    };
+    if !timestamp::is_rfc3339_representable(&validity) {
+      return Err(
+        report!(ValidityOutOfRange)
+          .attach_printable(format!("validity: {}", validity))
+          .change_context(RevisionError),
+      );
+    }
+
+    if let Some(min_validity) = self.min_validity {
+      let remaining = validity - now;
+      if remaining < min_validity {
+        return Err(
+          report!(ValidityTooShort)
+            .attach_printable(format!(
+              "validity ends at {}, which is {} from now",
+              timestamp::format_rfc3339_secs(&validity).change_context(RevisionError)?,
+              timestamp::humanize(remaining, false)
+            ))
+            .attach_printable(format!(
+              "minimum validity: {}",
+              timestamp::humanize(min_validity, true)
+            ))
+            .change_context(RevisionError),
+        );
+      }
+    }
+
+    Ok(
+      Revision::new(&self.name, "", validity, self.ttl, self.sequence)
+        .with_value_bytes(self.value)
+        .with_validity_type(self.validity_type)
+        .with_validity_window(validity_window),
+    )
+  }
+}
+
+/// Returns the end of a validity window of `duration` that starts at `now`, failing with
+/// [ValidityOutOfRange] if it's too far in the future to write in a record.
+fn validity_after(now: Timestamp, duration: Duration) -> Result<Timestamp, RevisionError> {
+  timestamp::checked_add(&now, duration)
+    .filter(timestamp::is_rfc3339_representable)
+    .ok_or_else(|| {
+      report!(ValidityOutOfRange)
+        .attach_printable(format!(
+          "validity window: {}",
+          timestamp::humanize(duration, false)
+        ))
+        .change_context(RevisionError)
+    })
+}
+
+fn default_validity() -> Timestamp {
+  timestamp::checked_add(&SystemClock.now(), default_validity_duration()).unwrap()
+}
+pub fn default_validity_duration() -> Duration {
+  Duration::weeks(52)
+}
+fn default_ttl() -> Duration {
+  // 1 hour, according to spec: https://specs.ipfs.tech/ipns/ipns-record/#ttl-uint64
+  // but 31 days is w3name default - so we stick with that for now - details: https://github.com/yusefnapora/w3name-rust-client/issues/33
+  Duration::days(31)
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct RevisionCbor {
+  name: String,
+  value: String,
+  /// The exact value, if it isn't valid UTF-8. `value` is then its lossy conversion.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  value_bytes: Option<Vec<u8>>,
+  sequence: u64,
+  validity: String,
+  #[serde(default)]
+  validity_type: i32,
+  ttl: u64,
+  /// The validity window in nanoseconds, if known.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  validity_window: Option<u64>,
+}
+
+/// Formats a `Revision` on a single line, in a stable format that [Revision::from_str] parses:
+///
+/// ```text
+/// name=k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu value="/ipfs/bafy..." seq=3 validity=2023-10-01T12:00:00.000000000Z ttl=3600s
+/// ```
+///
+/// The value is quoted, with `"`, `\`, newlines, carriage returns and tabs escaped by a backslash.
+/// Values that aren't valid UTF-8 are written as `value_base64=<base64>` instead.
+/// The TTL is given in whole seconds (`3600s`) or, if it isn't a whole number of seconds, in
+/// nanoseconds (`1500000000ns`). The validity window, if the revision has one, follows as
+/// `window=<duration>` in the same format. The validity type isn't included.
+///
+/// The alternate form (`{:#}`) is a multi-line rendering for people, with a `key: value` line
+/// per field and a humanized TTL. Values that aren't valid UTF-8 are shown as base64, marked as
+/// such. The value's length in bytes follows it, so a value with a newline in it can be told
+/// apart from one that was cut short. It isn't meant to be parsed.
+impl Display for Revision {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if f.alternate() {
+      let value = match &self.binary_value {
+        Some(bytes) => format!("{} (base64; not UTF-8)", base64::encode(bytes)),
+        None => self.value.clone(),
+      };
+      return write!(
+        f,
+        "name: {}\nvalue: {}\nvalue_length: {} bytes\nsequence: {}\nvalidity: {}\nttl: {}",
+        self.name,
+        value,
+        self.value_len(),
+        self.sequence,
+        self.validity_string(),
+        timestamp::humanize(self.ttl, true),
+      );
+    }
+
+    write!(f, "name={} ", self.name)?;
+    if let Some(bytes) = &self.binary_value {
+      write!(f, "value_base64={}", base64::encode(bytes))?;
+    } else {
+      f.write_str("value=\"")?;
+      for c in self.value.chars() {
+        match c {
+          '"' => f.write_str("\\\"")?,
+          '\\' => f.write_str("\\\\")?,
+          '\n' => f.write_str("\\n")?,
+          '\r' => f.write_str("\\r")?,
+          '\t' => f.write_str("\\t")?,
+          c => write!(f, "{}", c)?,
+        }
+      }
+      f.write_str("\"")?;
+    }
+    write!(
+      f,
+      " seq={} validity={} ttl=",
+      self.sequence,
+      self.validity_string()
+    )?;
+    write_duration_field(f, self.ttl_nanos())?;
+    if let Some(nanos) = self.validity_window_nanos() {
+      f.write_str(" window=")?;
+      write_duration_field(f, nanos)?;
+    }
+    Ok(())
+  }
+}
+
+/// Writes a duration field of the single-line format, in whole seconds if it's a whole number of
+/// them, or else in nanoseconds.
+fn write_duration_field(f: &mut fmt::Formatter<'_>, nanos: u64) -> fmt::Result {
+  if nanos.is_multiple_of(1_000_000_000) {
+    write!(f, "{}s", nanos / 1_000_000_000)
+  } else {
+    write!(f, "{}ns", nanos)
+  }
+}
+
+/// Parses a duration field written by [write_duration_field].
+fn parse_duration_field(field: &str) -> Option<Duration> {
+  match field.strip_suffix("ns") {
+    Some(nanos) => nanos.parse().ok().map(Duration::nanoseconds),
+    None => field
+      .strip_suffix('s')
+      .and_then(|secs| secs.parse().ok())
+      .map(Duration::seconds),
+  }
+}
+
+/// Parses the single-line format written by `Display`.
+impl FromStr for Revision {
+  type Err = Report<RevisionError>;
+
+  fn from_str(s: &str) -> Result<Revision, RevisionError> {
+    let invalid = |msg: String| report!(RevisionError).attach_printable(msg);
+
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut chars = s.trim().chars().peekable();
+    while chars.peek().is_some() {
+      let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+      let mut field = String::new();
+      if chars.peek() == Some(&'"') {
+        chars.next();
+        loop {
+          match chars.next() {
+            Some('"') => break,
+            Some('\\') => field.push(match chars.next() {
+              Some('n') => '\n',
+              Some('r') => '\r',
+              Some('t') => '\t',
+              Some(c @ ('"' | '\\')) => c,
+              other => return Err(invalid(format!("invalid escape in {}: {:?}", key, other))),
+            }),
+            Some(c) => field.push(c),
+            None => return Err(invalid(format!("unterminated quoted {}", key))),
+          }
+        }
+        if chars.next().is_some_and(|c| c != ' ') {
+          return Err(invalid(format!("expected a space after {}", key)));
+        }
+      } else {
+        field = chars.by_ref().take_while(|c| *c != ' ').collect();
+      }
+
+      if ![
+        "name",
+        "value",
+        "value_base64",
+        "seq",
+        "validity",
+        "ttl",
+        "window",
+      ]
+      .contains(&key.as_str())
+      {
+        return Err(invalid(format!("unknown field: {}", key)));
+      }
+      if fields.insert(key.clone(), field).is_some() {
+        return Err(invalid(format!("duplicate field: {}", key)));
+      }
+    }
+
+    let mut field = |key: &str| {
+      fields
+        .remove(key)
+        .ok_or_else(|| invalid(format!("missing field: {}", key)))
+    };
+    let name = Name::parse(field("name")?).change_context(RevisionError)?;
+    let value = match (field("value"), field("value_base64")) {
+      (Ok(value), Err(_)) => value.into_bytes(),
+      (Err(_), Ok(encoded)) => base64::decode(&encoded)
+        .report()
+        .change_context(RevisionError)
+        .attach_printable("invalid value_base64")?,
+      (Ok(_), Ok(_)) => return Err(invalid("both value and value_base64 given".to_string())),
+      (Err(err), Err(_)) => return Err(err),
+    };
+    let sequence: u64 = field("seq")?
+      .parse()
+      .report()
+      .change_context(RevisionError)
+      .attach_printable("invalid seq")?;
+    let validity = timestamp::parse_rfc3339(&field("validity")?)
+      .change_context(RevisionError)
+      .attach_printable("invalid validity")?;
+    let ttl_field = field("ttl")?;
+    let ttl = parse_duration_field(&ttl_field)
+      .ok_or_else(|| invalid(format!("invalid ttl: {}", ttl_field)))?;
+    let validity_window = match field("window") {
+      Ok(window) => Some(
+        parse_duration_field(&window)
+          .ok_or_else(|| invalid(format!("invalid window: {}", window)))?,
+      ),
+      Err(_) => None,
+    };
+
+    Ok(
+      Revision::new(&name, "", validity, ttl, sequence)
+        .with_value_bytes(value)
+        .with_validity_window(validity_window),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{ManualClock, WritableName};
+
+  use super::*;
+
+  fn make_test_revision(value: &str) -> Revision {
+    let w = WritableName::new();
+    Revision::v0(&w.to_name(), value)
+  }
+
+  fn test_now() -> Timestamp {
+    timestamp::parse_rfc3339("2024-01-01T00:00:00Z").unwrap()
+  }
+
+  #[test]
+  fn increment_policies() {
+    let key = WritableName::new();
+    let name = key.to_name();
+    // published for a week
+    let first = Revision::new(&name, "first", test_now(), Duration::hours(1), 2);
+    let previous = RevisionBuilder::next(&first, "previous")
+      .validity_for(Duration::weeks(1))
+      .ttl(Duration::minutes(5))
+      .build()
+      .unwrap();
+    assert_eq!(previous.validity_window(), Some(Duration::weeks(1)));
+
+    let preserved = previous
+      .increment_with_policy("next", IncrementPolicy::PreserveDurations)
+      .unwrap();
+    assert_eq!(preserved.sequence(), 4);
+    assert_eq!(preserved.value(), "next");
+    assert_eq!(preserved.ttl(), Duration::minutes(5));
+    assert_eq!(preserved.validity_window(), Some(Duration::weeks(1)));
+    let at = previous
+      .increment_with_policy_at("next", IncrementPolicy::PreserveDurations, test_now())
+      .unwrap();
+    assert_eq!(*at.validity(), test_now() + Duration::weeks(1));
+    assert_eq!(
+      IncrementPolicy::PreserveDurations.durations(&previous),
+      (Duration::weeks(1), Duration::minutes(5))
+    );
+
+    // records don't carry the window, but it's recovered from the published revision, so it
+    // survives any number of updates, however late they come
+    let record = preserved.to_signed_bytes(&key).unwrap();
+    let resolved = Revision::from_signed_bytes(&name, &record).unwrap();
+    assert_eq!(resolved.validity_window(), None);
+    assert_eq!(resolved, preserved);
+    let resolved = resolved.with_validity_window_of(&preserved);
+    assert_eq!(resolved.validity_window(), Some(Duration::weeks(1)));
+    let unmatched = Revision::from_signed_bytes(&name, &record).unwrap();
+    assert_eq!(
+      unmatched
+        .with_validity_window_of(&previous)
+        .validity_window(),
+      None
+    );
+    let third = resolved
+      .increment_with_policy("third", IncrementPolicy::PreserveDurations)
+      .unwrap();
+    assert_eq!(third.validity_window(), Some(Duration::weeks(1)));
+
+    let defaults = previous
+      .increment_with_policy("next", IncrementPolicy::Defaults)
+      .unwrap();
+    assert_eq!(defaults.ttl(), Duration::minutes(5));
+    assert_eq!(defaults.ttl(), previous.increment("next").ttl());
+    assert_eq!(
+      IncrementPolicy::Defaults.durations(&previous),
+      (Duration::weeks(52), Duration::minutes(5))
+    );
+
+    let explicit = IncrementPolicy::Explicit {
+      validity: Duration::hours(6),
+      ttl: Duration::seconds(30),
+    };
+    assert_eq!(
+      previous
+        .increment_with_policy("next", explicit)
+        .unwrap()
+        .ttl(),
+      Duration::seconds(30)
+    );
+    assert_eq!(
+      explicit.durations(&previous),
+      (Duration::hours(6), Duration::seconds(30))
+    );
+
+    // a revision with an explicit end of life, like a record signed elsewhere, doesn't know its
+    // window, so it keeps its end of life and its TTL
+    let unknown = Revision::new(
+      &name,
+      "unknown",
+      test_now() + Duration::days(5),
+      Duration::minutes(5),
+      3,
+    );
+    assert_eq!(unknown.validity_window(), None);
+    assert_eq!(
+      IncrementPolicy::PreserveDurations.durations_at(&unknown, test_now()),
+      (Duration::days(5), Duration::minutes(5))
+    );
+    // but one that has expired, or is about to, is still valid for at least its TTL
+    assert_eq!(
+      IncrementPolicy::PreserveDurations.durations_at(&unknown, test_now() + Duration::days(6)),
+      (Duration::minutes(5), Duration::minutes(5))
+    );
+
+    // a window that ends past the year 9999 is an error, not a panic
+    let huge = IncrementPolicy::Explicit {
+      validity: Duration::weeks(1_000_000),
+      ttl: Duration::minutes(5),
+    };
+    let err = previous.increment_with_policy("next", huge).unwrap_err();
+    assert!(err.contains::<ValidityOutOfRange>());
+  }
+
+  #[test]
+  fn preserve_durations_of_resolved_record() {
+    // a record published for 30 days, resolved 10 days later by someone without the published
+    // revision
+    let key = WritableName::new();
+    let name = key.to_name();
+    let published = RevisionBuilder::new(&name, "published")
+      .clock(Arc::new(ManualClock::new(test_now())))
+      .validity_for(Duration::days(30))
+      .ttl(Duration::minutes(5))
+      .build()
+      .unwrap();
+    let record = published.to_signed_bytes(&key).unwrap();
+    let resolved = Revision::from_signed_bytes(&name, &record).unwrap();
+    assert_eq!(resolved.validity_window(), None);
+
+    let now = test_now() + Duration::days(10);
+    let next = resolved
+      .increment_with_policy_at("next", IncrementPolicy::PreserveDurations, now)
+      .unwrap();
+    assert_eq!(*next.validity(), test_now() + Duration::days(30));
+    assert_eq!(next.validity_window(), Some(Duration::days(20)));
+    assert_eq!(next.ttl(), Duration::minutes(5));
+  }
+
+  #[test]
+  fn builder_validity_uses_clock() {
+    let name = WritableName::new().to_name();
+    let clock = Arc::new(ManualClock::new(test_now()));
+
+    let revision = RevisionBuilder::new(&name, "value")
+      .clock(clock.clone())
+      .validity_for(Duration::hours(6))
+      .build()
+      .unwrap();
+    assert_eq!(*revision.validity(), test_now() + Duration::hours(6));
+
+    // the validity is counted from when the revision is built
+    let builder = RevisionBuilder::new(&name, "value").clock(clock.clone());
+    clock.advance(Duration::days(1));
+    let revision = builder.build().unwrap();
+    assert_eq!(
+      *revision.validity(),
+      test_now() + Duration::days(1) + default_validity_duration()
+    );
+  }
+
+  #[test]
+  fn builder_defaults() {
+    let name = WritableName::new().to_name();
+    let clock = Arc::new(ManualClock::new(test_now()));
+    let policy = Defaults {
+      validity: Some(Duration::days(30)),
+      ttl: Some(Duration::minutes(10)),
+    };
+
+    let first = RevisionBuilder::new(&name, "first")
+      .clock(clock.clone())
+      .with_defaults(&policy)
+      .build()
+      .unwrap();
+    assert_eq!(*first.validity(), test_now() + Duration::days(30));
+    assert_eq!(first.ttl(), Duration::minutes(10));
+
+    // they apply to successors too, but explicit settings win
+    let previous = RevisionBuilder::new(&name, "first")
+      .ttl(Duration::hours(1))
+      .build()
+      .unwrap();
+    let second = RevisionBuilder::next(&previous, "second")
+      .clock(clock.clone())
+      .validity_for(Duration::days(2))
+      .with_defaults(&policy)
+      .build()
+      .unwrap();
+    assert_eq!(*second.validity(), test_now() + Duration::days(2));
+    assert_eq!(second.ttl(), Duration::minutes(10));
+    let third = RevisionBuilder::next(&second, "third")
+      .clock(clock)
+      .with_defaults(&policy)
+      .ttl(Duration::minutes(1))
+      .build()
+      .unwrap();
+    assert_eq!(third.ttl(), Duration::minutes(1));
+
+    // unset fields keep the built-in defaults
+    let only_validity = Defaults {
+      validity: Some(Duration::days(30)),
+      ttl: None,
+    };
+    let fourth = RevisionBuilder::next(&previous, "fourth")
+      .with_defaults(&only_validity)
+      .build()
+      .unwrap();
+    assert_eq!(fourth.ttl(), Duration::hours(1));
+  }
+
+  #[test]
+  fn expiry() {
+    let revision = Revision::new(
+      &WritableName::new().to_name(),
+      "value",
+      test_now(),
+      Duration::hours(1),
+      0,
+    );
+    assert!(!revision.is_expired_at(test_now() - Duration::seconds(1)));
+    assert!(!revision.is_expired_at(test_now()));
+    assert!(revision.is_expired_at(test_now() + Duration::seconds(1)));
+  }
+
+  #[test]
+  fn ttl_notes() {
+    let name = WritableName::new().to_name();
+    let now = SystemClock.now();
+    let checks = TtlChecks::default();
+    let revision = |validity: Duration, ttl: Duration| {
+      Revision::v0_with_validity(&name, "v", now + validity, ttl)
+    };
+
+    assert!(revision(Duration::days(30), Duration::hours(1))
+      .ttl_notes(now, &checks)
+      .is_empty());
+    assert_eq!(
+      revision(Duration::days(30), timestamp::zero()).ttl_notes(now, &checks),
+      vec![TtlNote::Zero]
+    );
+
+    // a TTL as long as the remaining validity is fine, a longer one isn't
+    let window = Duration::hours(6);
+    assert!(revision(window, window).ttl_notes(now, &checks).is_empty());
+    let over = revision(window, window + Duration::seconds(1));
+    assert_eq!(
+      over.ttl_notes(now, &checks),
+      vec![TtlNote::ExceedsValidity {
+        ttl: window + Duration::seconds(1),
+        remaining: window
+      }]
+    );
+    let expired = revision(-Duration::hours(1), Duration::days(1));
+    assert!(expired.ttl_notes(now, &checks).is_empty());
+
+    // the default maximum is the default TTL
+    assert!(revision(Duration::weeks(52), default_ttl())
+      .ttl_notes(now, &checks)
+      .is_empty());
+    let long = revision(Duration::weeks(52), Duration::days(60));
+    assert_eq!(
+      long.ttl_notes(now, &checks),
+      vec![TtlNote::ExceedsMax {
+        ttl: Duration::days(60),
+        max_ttl: default_ttl()
+      }]
+    );
+    let capped = TtlChecks {
+      max_ttl: Some(Duration::hours(48)),
+      ..checks
+    };
+    assert_eq!(
+      revision(Duration::days(30), Duration::hours(49))
+        .ttl_notes(now, &capped)
+        .len(),
+      1
+    );
+    assert!(revision(Duration::days(30), Duration::hours(48))
+      .ttl_notes(now, &capped)
+      .is_empty());
+
+    // both at once, and each can be turned off
+    let both = revision(Duration::days(10), Duration::days(40));
+    assert_eq!(both.ttl_notes(now, &checks).len(), 2);
+    let quiet = TtlChecks {
+      zero: false,
+      exceeds_validity: false,
+      max_ttl: None,
+    };
+    assert!(both.ttl_notes(now, &quiet).is_empty());
+    assert!(revision(Duration::days(30), timestamp::zero())
+      .ttl_notes(now, &quiet)
+      .is_empty());
+
+    assert!(TtlNote::Zero.to_string().contains("every lookup"));
+    assert!(over.ttl_notes(now, &checks)[0]
+      .to_string()
+      .contains("is longer than the record stays valid"));
+  }
+
+  #[test]
+  fn min_validity_guard() {
+    let name = WritableName::new().to_name();
+    let min = Duration::hours(24);
+    let clock: Arc<dyn Clock> = Arc::new(ManualClock::new(test_now()));
+
+    let just_above = RevisionBuilder::new(&name, "value")
+      .clock(clock.clone())
+      .validity_for(min + Duration::minutes(1))
+      .min_validity(min)
+      .build();
+    assert!(just_above.is_ok());
+
+    let just_below = RevisionBuilder::new(&name, "value")
+      .clock(clock.clone())
+      .validity_for(min - Duration::minutes(1))
+      .min_validity(min)
+      .build()
+      .unwrap_err();
+    assert!(just_below.contains::<ValidityTooShort>());
+
+    let in_the_past = RevisionBuilder::new(&name, "value")
+      .clock(clock.clone())
+      .validity_at(test_now() - Duration::hours(1))
+      .min_validity(timestamp::zero())
+      .build();
+    assert!(in_the_past.is_err());
+
+    let guard_disabled = RevisionBuilder::new(&name, "value")
+      .validity_for(Duration::minutes(5))
+      .build();
+    assert!(guard_disabled.is_ok());
+  }
+
+  #[test]
+  fn validity_out_of_range() {
+    let name = WritableName::new().to_name();
+    let clock: Arc<dyn Clock> = Arc::new(ManualClock::new(test_now()));
+    let build = |builder: RevisionBuilder| builder.clock(clock.clone()).build();
+
+    // too long for a date at all, and past the year 9999
+    for validity in [Duration::weeks(52 * 300_000), Duration::weeks(52 * 9_000)] {
+      let err = build(RevisionBuilder::new(&name, "value").validity_for(validity)).unwrap_err();
+      assert!(err.contains::<ValidityOutOfRange>(), "{:?}", err);
+    }
+    let last = timestamp::parse_rfc3339("9999-12-31T23:59:59.999999999Z").unwrap();
+    let rev = build(RevisionBuilder::new(&name, "value").validity_at(last)).unwrap();
+    assert_eq!(rev.validity_string(), "9999-12-31T23:59:59.999999999Z");
+
+    // only chrono has dates after the year 9999
+    if let Some(year_10000) = timestamp::checked_add(&last, Duration::nanoseconds(1)) {
+      let err = build(RevisionBuilder::new(&name, "value").validity_at(year_10000)).unwrap_err();
+      assert!(err.contains::<ValidityOutOfRange>());
+      let rev = Revision::v0_with_validity(&name, "value", year_10000, Duration::hours(1));
+      assert!(rev.encode().is_err());
+      assert!(rev.to_signed_bytes(&WritableName::new()).is_err());
+      assert!(rev.to_hybrid_signed_bytes(&WritableName::new()).is_err());
+    }
+  }
+
+  #[test]
+  fn builder_next() {
+    let first = RevisionBuilder::new(&WritableName::new().to_name(), "first")
+      .ttl(Duration::minutes(10))
+      .build()
+      .unwrap();
+    let second = RevisionBuilder::next(&first, "second").build().unwrap();
+    assert_eq!(second.sequence(), 1);
+    assert_eq!(second.ttl(), Duration::minutes(10));
+    assert_eq!(second.name(), first.name());
+  }
+
+  #[test]
+  fn precedence() {
+    let name = WritableName::new().to_name();
+    let first = RevisionBuilder::new(&name, "first").build().unwrap();
+    let second = RevisionBuilder::next(&first, "second")
+      .validity_for(Duration::hours(1))
+      .build()
+      .unwrap();
+    assert!(second.supersedes(&first));
+    assert!(!first.supersedes(&second));
+    assert!(!first.supersedes(&first));
+
+    let longer = RevisionBuilder::next(&first, "longer")
+      .validity_for(Duration::days(1))
+      .build()
+      .unwrap();
+    assert!(longer.supersedes(&second));
+
+    let other_name = Revision::v0(&WritableName::new().to_name(), "other");
+    assert!(!second.supersedes(&other_name));
+    assert!(!other_name.supersedes(&first));
+  }
+
+  #[test]
+  fn json_values() {
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Release {
+      current: String,
+      previous: Option<String>,
+    }
+
+    let release = Release {
+      current: "/ipfs/bafynew".to_string(),
+      previous: Some("/ipfs/bafyold".to_string()),
+    };
+    let name = WritableName::new().to_name();
+    let revision = RevisionBuilder::new(&name, "")
+      .value_json(&release)
+      .unwrap()
+      .build()
+      .unwrap();
+    assert_eq!(
+      revision.value(),
+      r#"{"current":"/ipfs/bafynew","previous":"/ipfs/bafyold"}"#
+    );
+    assert_eq!(revision.value_json::<Release>().unwrap(), release);
+
+    let path = Revision::v0(&name, "/ipfs/bafyvalue");
+    let err = path.value_json::<Release>().unwrap_err();
+    assert!(err.contains::<crate::error::InvalidJsonValue>());
+
+    let too_large = RevisionBuilder::new(&name, "").value_json(&"a".repeat(5000));
+    assert!(too_large
+      .unwrap_err()
+      .contains::<crate::error::RecordTooLarge>());
+  }
+
+  #[test]
+  fn serde_roundtrip() {
+    let rev = make_test_revision("it's a test");
+
+    let rev_bytes = rev.encode().expect("encoding error");
+    let rev2 = Revision::decode(&rev_bytes).expect("decode error");
+
+    assert_eq!(rev, rev2);
+  }
+
+  #[test]
+  fn serde_roundtrip_keeps_validity_window() {
+    let rev = make_test_revision("it's a test");
+    let rev2 = Revision::decode(&rev.encode().unwrap()).unwrap();
+    assert_eq!(rev2.validity_window(), Some(default_validity_duration()));
+  }
+
+  #[test]
+  fn serde_roundtrip_keeps_validity_type() {
+    let rev = make_test_revision("it's a test");
+    let rev = RevisionBuilder::next(&rev, "unknown type")
+      .validity_type(ValidityType::Unknown(3))
+      .build()
+      .unwrap();
+    let rev2 = Revision::decode(&rev.encode().unwrap()).unwrap();
+    assert_eq!(rev2.validity_type(), ValidityType::Unknown(3));
+    assert_eq!(
+      RevisionBuilder::next(&rev2, "next")
+        .build()
+        .unwrap()
+        .validity_type(),
+      ValidityType::Unknown(3)
+    );
+  }
+
+  #[test]
+  fn signed_bytes_roundtrip() {
+    let key = WritableName::new();
+    let rev = Revision::v0(&key.to_name(), "/ipfs/signed");
+    let bytes = rev.to_signed_bytes(&key).unwrap();
+    assert_eq!(
+      Revision::from_signed_bytes(&key.to_name(), &bytes).unwrap(),
+      rev
+    );
+
+    // a record for one name doesn't validate as a record for another
+    let other = WritableName::new();
+    assert!(Revision::from_signed_bytes(&other.to_name(), &bytes).is_err());
+    let err = rev.to_signed_bytes(&other).unwrap_err();
+    assert!(err.contains::<NameMismatch>());
+  }
+
+  #[test]
+  fn binary_values_round_trip() {
+    let key = WritableName::new();
+    let bytes = vec![0xff, 0x00, b'/', 0xc3, 0x28, 0x80];
+    let rev = RevisionBuilder::new(&key.to_name(), "")
+      .value_bytes(bytes.clone())
+      .build()
+      .unwrap();
+    assert!(!rev.is_value_utf8());
+    assert_eq!(rev.value_bytes(), &bytes[..]);
+    assert_eq!(rev.value_len(), bytes.len());
+    assert!(rev.value().contains('\u{FFFD}'));
+
+    let signed = rev.to_signed_bytes(&key).unwrap();
+    let parsed = Revision::from_signed_bytes(&key.to_name(), &signed).unwrap();
+    assert_eq!(parsed, rev);
+    assert_eq!(parsed.value_bytes(), &bytes[..]);
+
+    // re-signing keeps the exact bytes
+    let resigned = RevisionBuilder::next(&parsed, "")
+      .value_bytes(parsed.value_bytes())
+      .build()
+      .unwrap();
+    let signed = resigned.to_signed_bytes(&key).unwrap();
+    let parsed = Revision::from_signed_bytes(&key.to_name(), &signed).unwrap();
+    assert_eq!(parsed.value_bytes(), &bytes[..]);
+    assert_eq!(parsed.sequence(), 1);
+
+    assert_eq!(Revision::decode(&rev.encode().unwrap()).unwrap(), rev);
+    let line = rev.to_string();
+    assert!(line.contains(&format!("value_base64={} ", base64::encode(&bytes))));
+    assert_eq!(line.parse::<Revision>().unwrap(), rev);
+    assert!(format!("{:#}", rev).contains("(base64; not UTF-8)"));
+
+    // valid UTF-8 given as bytes is just a string value
+    let text = RevisionBuilder::new(&key.to_name(), "")
+      .value_bytes("/ipfs/text")
+      .build()
+      .unwrap();
+    assert!(text.is_value_utf8());
+    assert_eq!(text.value(), "/ipfs/text");
+  }
+
+  #[test]
+  fn tampered_signed_bytes_are_rejected() {
+    let key = WritableName::new();
+    let rev = Revision::v0(&key.to_name(), "/ipfs/signed");
+    let bytes = rev.to_signed_bytes(&key).unwrap();
+
+    let mut forged = bytes.clone();
+    for i in 0..forged.len() - 6 {
+      if &forged[i..i + 6] == b"signed" {
+        forged[i..i + 6].copy_from_slice(b"forged");
+      }
+    }
+    assert_ne!(forged, bytes);
+    assert!(Revision::from_signed_bytes(&key.to_name(), &forged).is_err());
+
+    let mut truncated = bytes;
+    truncated.truncate(truncated.len() / 2);
+    assert!(Revision::from_signed_bytes(&key.to_name(), &truncated).is_err());
+  }
+
+  fn fixed_revision(value: &str) -> Revision {
+    let name =
+      Name::parse("k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu").unwrap();
+    let validity = timestamp::parse_rfc3339("2023-10-01T12:00:00Z").unwrap();
+    Revision::new(&name, value, validity, Duration::hours(1), 3)
+  }
+
+  #[test]
+  fn display_format() {
+    let rev = fixed_revision("/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi");
+    assert_eq!(
+      rev.to_string(),
+      "name=k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu \
+       value=\"/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi\" seq=3 \
+       validity=2023-10-01T12:00:00.000000000Z ttl=3600s"
+    );
+    assert_eq!(
+      format!("{:#}", rev),
+      "name: k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu\n\
+       value: /ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi\n\
+       value_length: 65 bytes\n\
+       sequence: 3\n\
+       validity: 2023-10-01T12:00:00.000000000Z\n\
+       ttl: 1 hour"
+    );
+  }
+
+  #[test]
+  fn value_len_counts_bytes() {
+    assert_eq!(fixed_revision("").value_len(), 0);
+    assert_eq!(fixed_revision("a\nb").value_len(), 3);
+    // "ä" and "€" are two and three bytes in UTF-8
+    let rev = fixed_revision("ä€");
+    assert_eq!(rev.value().chars().count(), 2);
+    assert_eq!(rev.value_len(), 5);
+    assert!(format!("{:#}", rev).contains("\nvalue_length: 5 bytes\n"));
+  }
+
+  #[test]
+  fn display_round_trip() {
+    for value in [
+      "",
+      "plain",
+      "with spaces",
+      "quote \" and \\ backslash",
+      "multi\nline\ttab\r",
+    ] {
+      let rev = fixed_revision(value);
+      let line = rev.to_string();
+      assert!(!line.contains('\n'), "{}", line);
+      assert_eq!(line.parse::<Revision>().unwrap(), rev);
+    }
+
+    let mut rev = fixed_revision("value");
+    rev.ttl = Duration::milliseconds(1500);
+    assert!(rev.to_string().ends_with("ttl=1500000000ns"));
+    assert_eq!(rev.to_string().parse::<Revision>().unwrap(), rev);
+
+    // the validity window isn't in records, but it is in the line
+    let rev = rev.with_validity_window(Some(Duration::days(7)));
+    assert!(rev.to_string().ends_with(" window=604800s"));
+    let parsed = rev.to_string().parse::<Revision>().unwrap();
+    assert_eq!(parsed.validity_window(), Some(Duration::days(7)));
+  }
+
+  #[test]
+  fn parse_display_errors() {
+    let line = fixed_revision("value").to_string();
+    assert!(line.replace(" seq=3", "").parse::<Revision>().is_err());
+    assert!(format!("{} seq=4", line).parse::<Revision>().is_err());
+    assert!(format!("{} extra=1", line).parse::<Revision>().is_err());
+    assert!(line
+      .replace("ttl=3600s", "ttl=1h")
+      .parse::<Revision>()
+      .is_err());
+    assert!(line
+      .replace("value=\"value\"", "value=\"value")
+      .parse::<Revision>()
+      .is_err());
+    assert!(line.replace("seq=3", "seq=-1").parse::<Revision>().is_err());
+  }
+}
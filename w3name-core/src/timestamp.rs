@@ -0,0 +1,438 @@
+//! The [Timestamp] and [Duration] types of the crate's API, and the parsing and formatting of the
+//! timestamps and durations in IPNS records.
+//!
+//! All of the crate's record handling goes through this module, so it's the one place that knows
+//! how validity dates are encoded. RFC 3339 strings are handled by chrono, or by the
+//! `time` crate when the `time` feature is enabled; both produce the same strings.
+//!
+//! With the default `chrono` feature, the crate's times are chrono's `DateTime<Utc>` and its
+//! durations are chrono's `Duration`. Without it, they're the `time` crate's `OffsetDateTime` and
+//! `Duration`, and RFC 3339 strings are handled by the `time` crate too, as if the `time` feature
+//! was enabled. So the crate builds with no features at all.
+
+use error_stack::{report, Result};
+
+use crate::error::InvalidDateString;
+
+pub(crate) use repr::{humanize, now};
+pub use repr::{Duration, Timestamp};
+
+/// Parses an RFC 3339 date string, e.g. the validity of an IPNS record.
+#[doc(hidden)]
+pub fn parse_rfc3339(s: &str) -> Result<Timestamp, InvalidDateString> {
+  let (secs, nanos) = backend::parse(s)
+    .ok_or_else(|| report!(InvalidDateString).attach_printable(format!("date: {:?}", s)))?;
+  repr::from_unix(secs, nanos).ok_or_else(|| {
+    report!(InvalidDateString).attach_printable(format!("date out of range: {:?}", s))
+  })
+}
+
+/// Formats `t` as an RFC 3339 string in UTC with nanosecond precision, as IPNS records use.
+/// Fails if `t` is after the year 9999, which RFC 3339 can't express.
+#[doc(hidden)]
+pub fn format_rfc3339(t: &Timestamp) -> Result<String, InvalidDateString> {
+  format(t, true)
+}
+
+/// Formats `t` as an RFC 3339 string in UTC to the second, e.g. `2024-01-02T15:04:05Z`.
+pub(crate) fn format_rfc3339_secs(t: &Timestamp) -> Result<String, InvalidDateString> {
+  format(t, false)
+}
+
+fn format(t: &Timestamp, subsecs: bool) -> Result<String, InvalidDateString> {
+  let (secs, nanos) = repr::to_unix(t);
+  if !RFC3339_RANGE.contains(&secs) {
+    return Err(report!(InvalidDateString).attach_printable(format!("date out of range: {}", t)));
+  }
+  Ok(backend::format(secs, nanos, subsecs))
+}
+
+/// The first second of the year 0000 and the last second of the year 9999, in seconds since the
+/// Unix epoch: the range of dates with a four-digit year, which RFC 3339 requires.
+const RFC3339_RANGE: std::ops::RangeInclusive<i64> = -62_167_219_200..=253_402_300_799;
+
+/// Returns true if `t` can be written as an RFC 3339 string, i.e. its year has four digits.
+pub(crate) fn is_rfc3339_representable(t: &Timestamp) -> bool {
+  RFC3339_RANGE.contains(&repr::to_unix(t).0)
+}
+
+/// Returns `t` as nanoseconds since the Unix epoch.
+pub(crate) fn unix_nanos(t: &Timestamp) -> i128 {
+  let (secs, nanos) = repr::to_unix(t);
+  i128::from(secs) * 1_000_000_000 + i128::from(nanos)
+}
+
+/// Returns `t` without its fraction of a second.
+pub(crate) fn trunc_subsecs(t: &Timestamp) -> Timestamp {
+  repr::from_unix(repr::to_unix(t).0, 0).expect("truncating keeps the date in range")
+}
+
+/// Returns `t + d`, or `None` if that's out of range.
+pub(crate) fn checked_add(t: &Timestamp, d: Duration) -> Option<Timestamp> {
+  repr::checked_add(t, d)
+}
+
+/// Returns `a + b`, or `None` if that's too long for a [Duration].
+#[doc(hidden)]
+pub fn checked_add_duration(a: Duration, b: Duration) -> Option<Duration> {
+  repr::checked_add_duration(a, b)
+}
+
+/// Returns a duration of zero.
+#[doc(hidden)]
+pub fn zero() -> Duration {
+  Duration::seconds(0)
+}
+
+/// Returns the length of `d` in nanoseconds, as IPNS records encode TTLs. Negative durations are
+/// zero, and durations too long to count in nanoseconds are capped at `i64::MAX`.
+pub(crate) fn duration_nanos(d: &Duration) -> u64 {
+  checked_duration_nanos(d).map_or(i64::MAX as u64, |nanos| nanos.max(0) as u64)
+}
+
+/// Returns the length of `d` in nanoseconds, or `None` if that doesn't fit in an `i64`.
+pub(crate) fn checked_duration_nanos(d: &Duration) -> Option<i64> {
+  repr::nanos(d)
+}
+
+/// Returns the length of `d` in whole seconds.
+#[doc(hidden)]
+pub fn duration_secs(d: &Duration) -> i64 {
+  repr::secs(d)
+}
+
+/// Converts `d` to a `std::time::Duration`, or returns `None` if it's negative.
+#[doc(hidden)]
+pub fn to_std(d: Duration) -> Option<std::time::Duration> {
+  repr::to_std(d)
+}
+
+/// Converts `d` to a [Duration], or returns `None` if it's too long for one.
+#[doc(hidden)]
+pub fn from_std(d: std::time::Duration) -> Option<Duration> {
+  repr::from_std(d)
+}
+
+/// Parses the date of an HTTP `Date` header, e.g. `Mon, 01 Jan 2024 00:00:00 GMT`.
+#[doc(hidden)]
+pub fn parse_http_date(s: &str) -> Option<Timestamp> {
+  let (secs, nanos) = backend::parse_rfc2822(s)?;
+  repr::from_unix(secs, nanos)
+}
+
+/// Formats `t` for an HTTP `Date` header, e.g. `Mon, 01 Jan 2024 00:00:00 GMT`.
+#[doc(hidden)]
+pub fn format_http_date(t: &Timestamp) -> String {
+  let (secs, _) = repr::to_unix(t);
+  backend::format_http_date(secs)
+}
+
+/// Returns a duration of `nanos` nanoseconds, or `None` if that's too long for a [Duration].
+pub(crate) fn duration_from_nanos(nanos: u64) -> Option<Duration> {
+  i64::try_from(nanos).ok().map(Duration::nanoseconds)
+}
+
+#[cfg(feature = "chrono")]
+mod repr {
+  use chrono::{DateTime, TimeZone, Utc};
+  use chrono_humanize::{Accuracy, HumanTime, Tense};
+
+  /// A point in time, such as the validity of a revision: chrono's `DateTime<Utc>`.
+  pub type Timestamp = DateTime<Utc>;
+  /// A length of time, such as the TTL of a revision: chrono's `Duration`.
+  pub type Duration = chrono::Duration;
+
+  pub fn now() -> Timestamp {
+    Utc::now()
+  }
+
+  pub fn from_unix(secs: i64, nanos: u32) -> Option<Timestamp> {
+    Utc.timestamp_opt(secs, nanos).single()
+  }
+
+  pub fn to_unix(t: &Timestamp) -> (i64, u32) {
+    (t.timestamp(), t.timestamp_subsec_nanos())
+  }
+
+  pub fn checked_add(t: &Timestamp, d: Duration) -> Option<Timestamp> {
+    t.checked_add_signed(d)
+  }
+
+  pub fn checked_add_duration(a: Duration, b: Duration) -> Option<Duration> {
+    a.checked_add(&b)
+  }
+
+  pub fn nanos(d: &Duration) -> Option<i64> {
+    d.num_nanoseconds()
+  }
+
+  pub fn secs(d: &Duration) -> i64 {
+    d.num_seconds()
+  }
+
+  pub fn to_std(d: Duration) -> Option<std::time::Duration> {
+    d.to_std().ok()
+  }
+
+  pub fn from_std(d: std::time::Duration) -> Option<Duration> {
+    Duration::from_std(d).ok()
+  }
+
+  /// Describes `d` in English, e.g. "an hour" roughly or "1 hour and 30 minutes" precisely.
+  pub fn humanize(d: Duration, precise: bool) -> String {
+    let accuracy = if precise {
+      Accuracy::Precise
+    } else {
+      Accuracy::Rough
+    };
+    HumanTime::from(d).to_text_en(accuracy, Tense::Present)
+  }
+}
+
+#[cfg(not(feature = "chrono"))]
+mod repr {
+  use time::OffsetDateTime;
+
+  /// A point in time, such as the validity of a revision: the `time` crate's `OffsetDateTime`.
+  pub type Timestamp = OffsetDateTime;
+  /// A length of time, such as the TTL of a revision: the `time` crate's `Duration`.
+  pub type Duration = time::Duration;
+
+  pub fn now() -> Timestamp {
+    OffsetDateTime::now_utc()
+  }
+
+  pub fn from_unix(secs: i64, nanos: u32) -> Option<Timestamp> {
+    let nanos = i128::from(secs) * 1_000_000_000 + i128::from(nanos);
+    OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()
+  }
+
+  pub fn to_unix(t: &Timestamp) -> (i64, u32) {
+    (t.unix_timestamp(), t.nanosecond())
+  }
+
+  pub fn checked_add(t: &Timestamp, d: Duration) -> Option<Timestamp> {
+    t.checked_add(d)
+  }
+
+  pub fn checked_add_duration(a: Duration, b: Duration) -> Option<Duration> {
+    a.checked_add(b)
+  }
+
+  pub fn nanos(d: &Duration) -> Option<i64> {
+    i64::try_from(d.whole_nanoseconds()).ok()
+  }
+
+  pub fn secs(d: &Duration) -> i64 {
+    d.whole_seconds()
+  }
+
+  pub fn to_std(d: Duration) -> Option<std::time::Duration> {
+    d.try_into().ok()
+  }
+
+  pub fn from_std(d: std::time::Duration) -> Option<Duration> {
+    d.try_into().ok()
+  }
+
+  /// Describes `d` in English, e.g. "1 hour" roughly or "1 hour and 30 minutes" precisely.
+  /// chrono-humanize isn't available without chrono, so this only counts whole units.
+  pub fn humanize(d: Duration, precise: bool) -> String {
+    const UNITS: [(&str, i64); 5] = [
+      ("week", 7 * 24 * 60 * 60),
+      ("day", 24 * 60 * 60),
+      ("hour", 60 * 60),
+      ("minute", 60),
+      ("second", 1),
+    ];
+    let mut secs = d.whole_seconds().unsigned_abs();
+    let mut parts = Vec::new();
+    for (unit, len) in UNITS {
+      let count = secs / len as u64;
+      if count > 0 {
+        parts.push(format!(
+          "{} {}{}",
+          count,
+          unit,
+          if count == 1 { "" } else { "s" }
+        ));
+        secs %= len as u64;
+        if !precise {
+          break;
+        }
+      }
+    }
+    match parts.split_last() {
+      None => "0 seconds".to_string(),
+      Some((last, [])) => last.clone(),
+      Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+    }
+  }
+}
+
+#[cfg(all(feature = "chrono", not(feature = "time")))]
+mod backend {
+  use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+
+  pub fn parse(s: &str) -> Option<(i64, u32)> {
+    let t = DateTime::parse_from_rfc3339(s).ok()?;
+    Some((t.timestamp(), t.timestamp_subsec_nanos()))
+  }
+
+  pub fn format(secs: i64, nanos: u32, subsecs: bool) -> String {
+    let format = if subsecs {
+      SecondsFormat::Nanos
+    } else {
+      SecondsFormat::Secs
+    };
+    Utc.timestamp(secs, nanos).to_rfc3339_opts(format, true)
+  }
+
+  pub fn parse_rfc2822(s: &str) -> Option<(i64, u32)> {
+    let t = DateTime::parse_from_rfc2822(s).ok()?;
+    Some((t.timestamp(), t.timestamp_subsec_nanos()))
+  }
+
+  pub fn format_http_date(secs: i64) -> String {
+    Utc
+      .timestamp(secs, 0)
+      .format("%a, %d %b %Y %H:%M:%S GMT")
+      .to_string()
+  }
+}
+
+#[cfg(any(feature = "time", not(feature = "chrono")))]
+mod backend {
+  use time::{
+    format_description::well_known::{Rfc2822, Rfc3339},
+    OffsetDateTime,
+  };
+
+  pub fn parse(s: &str) -> Option<(i64, u32)> {
+    let t = OffsetDateTime::parse(s, &Rfc3339).ok()?;
+    Some((t.unix_timestamp(), t.nanosecond()))
+  }
+
+  /// Formats a time within RFC 3339's range, which is also the `time` crate's.
+  pub fn format(secs: i64, nanos: u32, subsecs: bool) -> String {
+    let t = OffsetDateTime::from_unix_timestamp(secs)
+      .expect("the caller checked that the date is within RFC 3339's range");
+    let date = format!(
+      "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+      t.year(),
+      u8::from(t.month()),
+      t.day(),
+      t.hour(),
+      t.minute(),
+      t.second(),
+    );
+    if subsecs {
+      format!("{}.{:09}Z", date, nanos)
+    } else {
+      format!("{}Z", date)
+    }
+  }
+
+  pub fn parse_rfc2822(s: &str) -> Option<(i64, u32)> {
+    let t = OffsetDateTime::parse(s, &Rfc2822).ok()?;
+    Some((t.unix_timestamp(), t.nanosecond()))
+  }
+
+  pub fn format_http_date(secs: i64) -> String {
+    let t = OffsetDateTime::from_unix_timestamp(secs).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    format!(
+      "{:.3}, {:02} {:.3} {} {:02}:{:02}:{:02} GMT",
+      t.weekday().to_string(),
+      t.day(),
+      t.month().to_string(),
+      t.year(),
+      t.hour(),
+      t.minute(),
+      t.second()
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rfc3339_round_trip() {
+    let t = parse_rfc3339("2023-10-01T12:00:00.123456789Z").unwrap();
+    assert_eq!(unix_nanos(&t), 1_696_161_600_123_456_789);
+    assert_eq!(
+      format_rfc3339(&t).unwrap(),
+      "2023-10-01T12:00:00.123456789Z"
+    );
+    assert_eq!(format_rfc3339_secs(&t).unwrap(), "2023-10-01T12:00:00Z");
+    assert_eq!(unix_nanos(&trunc_subsecs(&t)), 1_696_161_600_000_000_000);
+
+    // offsets are converted to UTC, and whole seconds still get nanoseconds
+    let t = parse_rfc3339("2023-10-01T14:00:00+02:00").unwrap();
+    assert_eq!(
+      format_rfc3339(&t).unwrap(),
+      "2023-10-01T12:00:00.000000000Z"
+    );
+
+    for invalid in ["", "2023-10-01", "2023-13-01T12:00:00Z", "yesterday"] {
+      assert!(parse_rfc3339(invalid).is_err(), "{}", invalid);
+    }
+  }
+
+  #[test]
+  fn rfc3339_range() {
+    let last = parse_rfc3339("9999-12-31T23:59:59.999999999Z").unwrap();
+    assert!(is_rfc3339_representable(&last));
+    assert!(format_rfc3339(&last).is_ok());
+    let first = parse_rfc3339("0000-01-01T00:00:00Z").unwrap();
+    assert!(is_rfc3339_representable(&first));
+    assert!(format_rfc3339(&first).is_ok());
+
+    // chrono goes past the year 9999, but RFC 3339 doesn't
+    if let Some(after) = checked_add(&last, Duration::nanoseconds(1)) {
+      assert!(!is_rfc3339_representable(&after));
+      assert!(format_rfc3339(&after).is_err());
+    }
+    if let Some(before) = checked_add(&first, Duration::nanoseconds(-1)) {
+      assert!(!is_rfc3339_representable(&before));
+      assert!(format_rfc3339_secs(&before).is_err());
+    }
+  }
+
+  #[test]
+  fn durations() {
+    let d = Duration::minutes(5);
+    assert_eq!(duration_nanos(&d), 300_000_000_000);
+    assert_eq!(duration_secs(&d), 300);
+    assert_eq!(duration_from_nanos(300_000_000_000), Some(d));
+    assert_eq!(duration_nanos(&Duration::seconds(-1)), 0);
+    assert_eq!(duration_nanos(&Duration::weeks(1_000_000)), i64::MAX as u64);
+    assert_eq!(checked_duration_nanos(&Duration::weeks(1_000_000)), None);
+    assert_eq!(duration_from_nanos(u64::MAX), None);
+  }
+
+  #[test]
+  fn http_dates() {
+    let t = parse_http_date("Mon, 01 Jan 2024 00:00:00 GMT").unwrap();
+    assert_eq!(format_rfc3339_secs(&t).unwrap(), "2024-01-01T00:00:00Z");
+    assert_eq!(format_http_date(&t), "Mon, 01 Jan 2024 00:00:00 GMT");
+    assert_eq!(parse_http_date("Mon, 01 Jan 2024 02:00:00 +0200"), Some(t));
+    assert!(parse_http_date("2024-01-01T00:00:00Z").is_none());
+  }
+
+  #[test]
+  fn std_durations() {
+    let d = std::time::Duration::from_millis(1500);
+    assert_eq!(from_std(d), Some(Duration::milliseconds(1500)));
+    assert_eq!(to_std(Duration::milliseconds(1500)), Some(d));
+    assert_eq!(to_std(Duration::seconds(-1)), None);
+  }
+
+  #[test]
+  fn humanized() {
+    let d = Duration::hours(1) + Duration::minutes(30);
+    assert!(humanize(d, false).contains("hour"));
+    assert_eq!(humanize(d, true), "1 hour and 30 minutes");
+  }
+}
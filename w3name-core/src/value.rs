@@ -0,0 +1,415 @@
+use std::{
+  borrow::Cow,
+  fmt::{self, Display},
+};
+
+use cid::Cid;
+use error_stack::{report, Result};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::error::{UnusualCharacter, UnusualCharacterKind};
+
+/// `Value` is a typed view of a name record's value.
+///
+/// Record values are plain strings, but in practice they are almost always IPFS or IPNS paths
+/// like `/ipfs/bafybeib.../index.html`. `Value` splits those paths into their parts, so they can
+/// be rendered in other forms (gateway URLs, `ipfs://` URIs) without string mangling.
+///
+/// Values that aren't recognizable IPFS or IPNS paths are kept as-is in [Value::Other].
+///
+/// ## Example
+///
+/// ```rust
+/// use w3name_core::Value;
+///
+/// let value = Value::parse("/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi/docs/index.html");
+///
+/// assert_eq!(
+///   value.to_gateway_url("https://ipfs.io").unwrap(),
+///   "https://ipfs.io/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi/docs/index.html"
+/// );
+/// assert_eq!(
+///   value.to_uri().unwrap(),
+///   "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi/docs/index.html"
+/// );
+///
+/// let other = Value::parse("just some text");
+/// assert_eq!(other, Value::Other("just some text".to_string()));
+/// assert!(other.to_gateway_url("https://ipfs.io").is_none());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+  /// An immutable content path, e.g. `/ipfs/<cid>/some/path`.
+  ///
+  /// `path` is either empty or starts with a `/`.
+  Ipfs { cid: String, path: String },
+
+  /// A mutable name path, e.g. `/ipns/<name>/some/path`, where `<name>` is a name identifier or a DNSLink domain.
+  ///
+  /// `path` is either empty or starts with a `/`.
+  Ipns { name: String, path: String },
+
+  /// Any value that isn't an IPFS or IPNS path.
+  Other(String),
+}
+
+impl Value {
+  /// Parses a record value.
+  ///
+  /// Accepts `/ipfs/<cid>[/path]` and `/ipns/<name>[/path]`, as well as the equivalent
+  /// `ipfs://` and `ipns://` URI forms. Anything else (including `/ipfs/` paths whose
+  /// CID is invalid) is returned as [Value::Other].
+  pub fn parse<S: AsRef<str>>(s: S) -> Value {
+    let s = s.as_ref();
+    let other = || Value::Other(s.to_string());
+
+    let (namespace, rest) = if let Some(rest) = s.strip_prefix("/ipfs/") {
+      ("ipfs", rest)
+    } else if let Some(rest) = s.strip_prefix("ipfs://") {
+      ("ipfs", rest)
+    } else if let Some(rest) = s.strip_prefix("/ipns/") {
+      ("ipns", rest)
+    } else if let Some(rest) = s.strip_prefix("ipns://") {
+      ("ipns", rest)
+    } else {
+      return other();
+    };
+
+    let (root, path) = match rest.find('/') {
+      Some(idx) => rest.split_at(idx),
+      None => (rest, ""),
+    };
+    if root.is_empty() {
+      return other();
+    }
+
+    let root = root.to_string();
+    let path = path.to_string();
+    if namespace == "ipfs" {
+      if Cid::try_from(root.as_str()).is_err() {
+        return other();
+      }
+      Value::Ipfs { cid: root, path }
+    } else {
+      Value::Ipns { name: root, path }
+    }
+  }
+
+  /// Returns a URL for this value on the given HTTP gateway, e.g. `https://ipfs.io/ipfs/<cid>/path`.
+  ///
+  /// Path segments are percent-encoded, so characters like `?`, `#` and spaces can't be mistaken
+  /// for URL syntax. Returns `None` if this is not an IPFS or IPNS path.
+  pub fn to_gateway_url(&self, gateway_base: &str) -> Option<String> {
+    let (namespace, root, path) = self.parts()?;
+    Some(format!(
+      "{}/{}/{}{}",
+      gateway_base.trim_end_matches('/'),
+      namespace,
+      root,
+      encode_path(path)
+    ))
+  }
+
+  /// Returns an `ipfs://` or `ipns://` URI for this value, e.g. `ipfs://<cid>/path`.
+  ///
+  /// Path segments are percent-encoded. Returns `None` if this is not an IPFS or IPNS path.
+  pub fn to_uri(&self) -> Option<String> {
+    let (namespace, root, path) = self.parts()?;
+    Some(format!("{}://{}{}", namespace, root, encode_path(path)))
+  }
+
+  /// Returns `true` if `self` and `other` refer to the same content, even if they're spelled differently.
+  ///
+  /// IPFS CIDs are compared by their binary form, so the same CID in different multibase encodings
+  /// is equivalent. Trailing slashes on paths are ignored, as are the `/ipfs/` vs `ipfs://` forms.
+  /// [Value::Other] values are compared as strings, ignoring trailing slashes.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::Value;
+  ///
+  /// let base32 = Value::parse("/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi/");
+  /// let base58 = Value::parse("ipfs://zdj7Wic6KcJAfWz1c9o4M6kq9Lwd5BfbxkVafnrojaaGiSFxM");
+  /// assert!(base32.is_equivalent(&base58));
+  /// ```
+  pub fn is_equivalent(&self, other: &Value) -> bool {
+    match (self, other) {
+      (Value::Ipfs { cid: a, path: pa }, Value::Ipfs { cid: b, path: pb }) => {
+        let same_cid = match (Cid::try_from(a.as_str()), Cid::try_from(b.as_str())) {
+          (Ok(a), Ok(b)) => a == b,
+          _ => a == b,
+        };
+        same_cid && trim_slashes(pa) == trim_slashes(pb)
+      }
+      (Value::Ipns { name: a, path: pa }, Value::Ipns { name: b, path: pb }) => {
+        a == b && trim_slashes(pa) == trim_slashes(pb)
+      }
+      (Value::Other(a), Value::Other(b)) => trim_slashes(a) == trim_slashes(b),
+      _ => false,
+    }
+  }
+
+  /// Checks that `text` is safe to publish as a value: that it has no control characters, and no
+  /// zero-width or bidirectional control characters. Those are invisible, or change how the text
+  /// around them is displayed, so a value with one pasted in by accident looks right but resolves
+  /// to something else, if anything.
+  ///
+  /// Fails with [UnusualCharacter] for the first such character, with its byte offset in `text`.
+  ///
+  /// ## Example
+  ///
+  /// ```rust
+  /// use w3name_core::{error::UnusualCharacter, Value};
+  ///
+  /// assert!(Value::check_text("/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi").is_ok());
+  ///
+  /// let err = Value::check_text("/ipfs/bafy\u{200b}beig").unwrap_err();
+  /// let unusual = err.downcast_ref::<UnusualCharacter>().unwrap();
+  /// assert_eq!((unusual.character, unusual.offset), ('\u{200b}', 10));
+  /// ```
+  pub fn check_text(text: &str) -> Result<(), UnusualCharacter> {
+    for (offset, character) in text.char_indices() {
+      if let Some(kind) = unusual_kind(character) {
+        return Err(report!(UnusualCharacter {
+          character,
+          offset,
+          kind
+        }));
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns `text` in Unicode Normalization Form C, in which characters that can be written
+  /// either precomposed or with combining marks, like `é`, are always precomposed. Text that's
+  /// already in NFC, like all ASCII text, is returned as is.
+  pub fn to_nfc(text: &str) -> Cow<'_, str> {
+    if is_nfc(text) {
+      Cow::Borrowed(text)
+    } else {
+      Cow::Owned(text.nfc().collect())
+    }
+  }
+
+  fn parts(&self) -> Option<(&'static str, &str, &str)> {
+    match self {
+      Value::Ipfs { cid, path } => Some(("ipfs", cid, path)),
+      Value::Ipns { name, path } => Some(("ipns", name, path)),
+      Value::Other(_) => None,
+    }
+  }
+}
+
+impl Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Value::Ipfs { cid, path } => write!(f, "/ipfs/{}{}", cid, path),
+      Value::Ipns { name, path } => write!(f, "/ipns/{}{}", name, path),
+      Value::Other(s) => write!(f, "{}", s),
+    }
+  }
+}
+
+/// Returns what's unusual about `c` as part of a value, if anything.
+fn unusual_kind(c: char) -> Option<UnusualCharacterKind> {
+  match c {
+    c if c.is_control() => Some(UnusualCharacterKind::Control),
+    // zero width space, non-joiner and joiner, word joiner, and zero width no-break space (BOM)
+    '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => Some(UnusualCharacterKind::ZeroWidth),
+    // arabic letter mark, left-to-right and right-to-left marks, embeddings and overrides, and
+    // isolates
+    '\u{061c}' | '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => {
+      Some(UnusualCharacterKind::BidiControl)
+    }
+    _ => None,
+  }
+}
+
+fn trim_slashes(s: &str) -> &str {
+  s.trim_end_matches('/')
+}
+
+/// Percent-encodes everything in `path` except `/` separators and the characters that RFC 3986 allows in path segments.
+///
+/// Valid `%XX` escapes are kept as they are, so an already encoded path isn't encoded twice.
+fn encode_path(path: &str) -> String {
+  let bytes = path.as_bytes();
+  let mut encoded = String::with_capacity(path.len());
+  for (i, &b) in bytes.iter().enumerate() {
+    match b {
+      b'%' if is_escape(&bytes[i..]) => encoded.push('%'),
+      b'A'..=b'Z'
+      | b'a'..=b'z'
+      | b'0'..=b'9'
+      | b'-'
+      | b'.'
+      | b'_'
+      | b'~'
+      | b'!'
+      | b'$'
+      | b'&'
+      | b'\''
+      | b'('
+      | b')'
+      | b'*'
+      | b'+'
+      | b','
+      | b';'
+      | b'='
+      | b':'
+      | b'@'
+      | b'/' => encoded.push(b as char),
+      _ => encoded.push_str(&format!("%{:02X}", b)),
+    }
+  }
+  encoded
+}
+
+/// Returns `true` if `bytes` starts with a percent escape, `%` and two hex digits.
+fn is_escape(bytes: &[u8]) -> bool {
+  matches!(bytes, [b'%', hi, lo, ..] if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CID: &str = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+  const NAME: &str = "k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu";
+
+  #[test]
+  fn parse_paths() {
+    assert_eq!(
+      Value::parse(format!("/ipfs/{}", CID)),
+      Value::Ipfs {
+        cid: CID.to_string(),
+        path: "".to_string()
+      }
+    );
+    assert_eq!(
+      Value::parse(format!("ipns://{}/docs/", NAME)),
+      Value::Ipns {
+        name: NAME.to_string(),
+        path: "/docs/".to_string()
+      }
+    );
+    assert_eq!(
+      Value::parse(format!("ipfs://{}/a/b", CID)).to_string(),
+      format!("/ipfs/{}/a/b", CID)
+    );
+  }
+
+  #[test]
+  fn parse_other() {
+    assert!(matches!(Value::parse("hello"), Value::Other(_)));
+    assert!(matches!(Value::parse("/ipfs/"), Value::Other(_)));
+    assert!(matches!(Value::parse("/ipfs/not-a-cid/x"), Value::Other(_)));
+    assert!(matches!(Value::parse(CID), Value::Other(_)));
+  }
+
+  #[test]
+  fn equivalent_values() {
+    let value = Value::parse(format!("/ipfs/{}/docs", CID));
+    assert!(value.is_equivalent(&Value::parse(format!("ipfs://{}/docs/", CID))));
+    assert!(!value.is_equivalent(&Value::parse(format!("/ipfs/{}/other", CID))));
+    assert!(!value.is_equivalent(&Value::parse(format!("/ipns/{}/docs", NAME))));
+    assert!(Value::parse("hello/").is_equivalent(&Value::parse("hello")));
+    assert!(!Value::parse("hello").is_equivalent(&Value::parse("Hello")));
+  }
+
+  #[test]
+  fn render_sub_paths() {
+    let value = Value::parse(format!("/ipns/{}/docs/index.html", NAME));
+    assert_eq!(
+      value.to_gateway_url("https://dweb.link/").unwrap(),
+      format!("https://dweb.link/ipns/{}/docs/index.html", NAME)
+    );
+    assert_eq!(
+      value.to_uri().unwrap(),
+      format!("ipns://{}/docs/index.html", NAME)
+    );
+  }
+
+  #[test]
+  fn render_query_unsafe_characters() {
+    let value = Value::parse(format!("/ipfs/{}/my dir/what?#100%.txt", CID));
+    assert_eq!(
+      value.to_gateway_url("https://ipfs.io").unwrap(),
+      format!("https://ipfs.io/ipfs/{}/my%20dir/what%3F%23100%25.txt", CID)
+    );
+    assert_eq!(
+      value.to_uri().unwrap(),
+      format!("ipfs://{}/my%20dir/what%3F%23100%25.txt", CID)
+    );
+  }
+
+  #[test]
+  fn render_keeps_existing_escapes() {
+    let value = Value::parse(format!("/ipfs/{}/a%20b/100%/c%2g%e9", CID));
+    assert_eq!(
+      value.to_gateway_url("https://ipfs.io").unwrap(),
+      format!("https://ipfs.io/ipfs/{}/a%20b/100%25/c%252g%e9", CID)
+    );
+    assert_eq!(
+      value.to_uri().unwrap(),
+      format!("ipfs://{}/a%20b/100%25/c%252g%e9", CID)
+    );
+  }
+
+  #[test]
+  fn check_text_rejects_unusual_characters() {
+    let path = format!("/ipfs/{}/docs/café.html", CID);
+    Value::check_text(&path).unwrap();
+    Value::check_text("ipfs://日本語/ok ").unwrap();
+
+    let cases = [
+      ('\0', UnusualCharacterKind::Control),
+      ('\t', UnusualCharacterKind::Control),
+      ('\n', UnusualCharacterKind::Control),
+      ('\u{7f}', UnusualCharacterKind::Control),
+      ('\u{85}', UnusualCharacterKind::Control),
+      ('\u{200b}', UnusualCharacterKind::ZeroWidth),
+      ('\u{200c}', UnusualCharacterKind::ZeroWidth),
+      ('\u{200d}', UnusualCharacterKind::ZeroWidth),
+      ('\u{2060}', UnusualCharacterKind::ZeroWidth),
+      ('\u{feff}', UnusualCharacterKind::ZeroWidth),
+      ('\u{061c}', UnusualCharacterKind::BidiControl),
+      ('\u{200e}', UnusualCharacterKind::BidiControl),
+      ('\u{200f}', UnusualCharacterKind::BidiControl),
+      ('\u{202a}', UnusualCharacterKind::BidiControl),
+      ('\u{202e}', UnusualCharacterKind::BidiControl),
+      ('\u{2066}', UnusualCharacterKind::BidiControl),
+      ('\u{2069}', UnusualCharacterKind::BidiControl),
+    ];
+    for (character, kind) in cases {
+      // after a multi-byte character, so the offset is in bytes rather than characters
+      let text = format!("/ipns/é{}x", character);
+      let err = Value::check_text(&text).unwrap_err();
+      let unusual = err.downcast_ref::<UnusualCharacter>().unwrap();
+      assert_eq!(unusual.character, character);
+      assert_eq!(unusual.offset, 8, "{:?}", character);
+      assert_eq!(unusual.kind, kind, "{:?}", character);
+    }
+
+    let err = Value::check_text("/ipfs/a\u{200b}b").unwrap_err();
+    assert_eq!(
+      err.current_context().to_string(),
+      "value contains a zero-width character (U+200B) at byte offset 7"
+    );
+  }
+
+  #[test]
+  fn nfc_normalization() {
+    let decomposed = "/ipns/example.com/cafe\u{301}.html";
+    assert_eq!(
+      Value::to_nfc(decomposed),
+      "/ipns/example.com/caf\u{e9}.html"
+    );
+    assert!(matches!(
+      Value::to_nfc("/ipns/example.com/caf\u{e9}.html"),
+      Cow::Borrowed(_)
+    ));
+    assert!(matches!(Value::to_nfc(CID), Cow::Borrowed(_)));
+  }
+}
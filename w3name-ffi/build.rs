@@ -0,0 +1,17 @@
+use std::path::PathBuf;
+
+fn main() {
+  let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+  println!("cargo:rerun-if-changed=src/lib.rs");
+  println!("cargo:rerun-if-changed=cbindgen.toml");
+
+  let config =
+    cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).expect("cbindgen.toml is invalid");
+  // parsing the one source file is enough, and doesn't run `cargo metadata` from a build script
+  cbindgen::Builder::new()
+    .with_config(config)
+    .with_src(crate_dir.join("src/lib.rs"))
+    .generate()
+    .expect("can't generate the C header")
+    .write_to_file(crate_dir.join("include/w3name.h"));
+}
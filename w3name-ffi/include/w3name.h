@@ -0,0 +1,89 @@
+#ifndef W3NAME_H
+#define W3NAME_H
+
+/* Generated by the w3name-ffi build script with cbindgen. Don't edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of a call.
+typedef enum w3name_status {
+  W3NAME_STATUS_OK = 0,
+  // A required argument is NULL, or a string isn't valid UTF-8.
+  W3NAME_STATUS_INVALID_ARGUMENT = 1,
+  // The name isn't a valid name identifier.
+  W3NAME_STATUS_INVALID_NAME = 2,
+  // The record is malformed, its signature is invalid, or it's for another name.
+  W3NAME_STATUS_INVALID_RECORD = 3,
+  // The service has no record for the name.
+  W3NAME_STATUS_NOT_FOUND = 4,
+  // The service couldn't be reached, or failed the request.
+  W3NAME_STATUS_SERVICE = 5,
+  // The library panicked. This is a bug.
+  W3NAME_STATUS_PANIC = 6,
+} w3name_status;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Resolves `name` with the w3name service at `endpoint`, or the public service if `endpoint` is
+// NULL, and writes its current value to `*value_out`.
+//
+// The record is validated against `name` before its value is returned.
+//
+// # Safety
+//
+// `name` and `endpoint` must be NULL or NUL-terminated strings, and `value_out` must be NULL or
+// point to a `char *` to write to. The value written must be freed with [w3name_string_free].
+enum w3name_status w3name_resolve(const char *name, const char *endpoint, char **value_out);
+
+// Verifies that the serialized record in `record`, of `record_len` bytes, is validly signed by
+// the key of `name`.
+//
+// Returns [W3NameStatus::Ok] if it is, and [W3NameStatus::InvalidRecord] if it isn't.
+//
+// # Safety
+//
+// `name` must be NULL or a NUL-terminated string, and `record` must be NULL or point to
+// `record_len` readable bytes.
+enum w3name_status w3name_verify(const char *name, const uint8_t *record, size_t record_len);
+
+// Parses the serialized record in `record`, of `record_len` bytes, and writes its fields to
+// `*json_out` as a JSON object: `name`, `value`, `sequence`, `validity` (an RFC 3339 timestamp)
+// and `ttl_ns`.
+//
+// The record's signature isn't checked; use [w3name_verify] for that. `name` is the name the
+// record is for, and may be NULL if the record embeds its public key.
+//
+// # Safety
+//
+// `name` must be NULL or a NUL-terminated string, `record` must be NULL or point to
+// `record_len` readable bytes, and `json_out` must be NULL or point to a `char *` to write to.
+// The JSON written must be freed with [w3name_string_free].
+enum w3name_status w3name_parse(const uint8_t *record,
+                                size_t record_len,
+                                const char *name,
+                                char **json_out);
+
+// Returns a copy of the message of the last failure on this thread, or NULL if the last call
+// succeeded.
+//
+// The message must be freed with [w3name_string_free].
+char *w3name_last_error_message(void);
+
+// Frees a string returned by this library. Does nothing if `s` is NULL.
+//
+// # Safety
+//
+// `s` must be NULL or a string returned by this library that hasn't been freed yet.
+void w3name_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* W3NAME_H */
@@ -0,0 +1,367 @@
+//! A C interface to w3name, for resolving a name to its value, verifying a record against a
+//! name, and parsing a record into its fields.
+//!
+//! The build script generates the header, `include/w3name.h`, from this file with cbindgen.
+//!
+//! ## Conventions
+//!
+//! - Strings passed in are NUL-terminated UTF-8, and are only read during the call.
+//! - Strings passed out are NUL-terminated UTF-8 too. They belong to the caller, who must free each
+//!   of them with [w3name_string_free], and nothing else.
+//! - Every function returns a [W3NameStatus]. Out parameters are only written on success.
+//! - After a failure, [w3name_last_error_message] describes it. It's kept per thread, until the
+//!   next call on the same thread.
+//! - Panics don't unwind into the caller: they fail the call with [W3NameStatus::Panic].
+
+use std::{
+  cell::RefCell,
+  ffi::{c_char, CStr, CString},
+  panic::{catch_unwind, AssertUnwindSafe},
+  ptr, slice,
+  sync::OnceLock,
+};
+
+use error_stack::{FrameKind, Report};
+use w3name::{
+  error::{APIError, ClientError, IpnsError},
+  ipns::{
+    deserialize_ipns_entry_untrusted, embedded_public_key, revision_from_ipns_entry_unchecked,
+  },
+  Name, Revision, W3NameClient,
+};
+
+/// The result of a call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum W3NameStatus {
+  Ok = 0,
+  /// A required argument is NULL, or a string isn't valid UTF-8.
+  InvalidArgument = 1,
+  /// The name isn't a valid name identifier.
+  InvalidName = 2,
+  /// The record is malformed, its signature is invalid, or it's for another name.
+  InvalidRecord = 3,
+  /// The service has no record for the name.
+  NotFound = 4,
+  /// The service couldn't be reached, or failed the request.
+  Service = 5,
+  /// The library panicked. This is a bug.
+  Panic = 6,
+}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A failed call: its status and what went wrong.
+struct Failure {
+  status: W3NameStatus,
+  message: String,
+}
+
+impl Failure {
+  fn new(status: W3NameStatus, message: impl Into<String>) -> Failure {
+    Failure {
+      status,
+      message: message.into(),
+    }
+  }
+
+  fn from_report<C>(status: W3NameStatus, err_report: &Report<C>) -> Failure {
+    Failure::new(status, describe(err_report))
+  }
+}
+
+/// Describes an error on one line: its contexts and printable attachments, outermost first.
+fn describe<C>(err_report: &Report<C>) -> String {
+  let parts: Vec<String> = err_report
+    .frames()
+    .map(|frame| match frame.kind() {
+      FrameKind::Context(context) => context.to_string(),
+      FrameKind::Attachment(error_stack::AttachmentKind::Printable(printable)) => {
+        printable.to_string()
+      }
+      FrameKind::Attachment(_) => String::new(),
+    })
+    .filter(|part| !part.is_empty())
+    .collect();
+  parts.join(": ")
+}
+
+/// Runs the body of an exported function, recording its failure or panic for
+/// [w3name_last_error_message].
+fn call(body: impl FnOnce() -> Result<(), Failure>) -> W3NameStatus {
+  LAST_ERROR.with(|last| last.borrow_mut().take());
+  let failure = match catch_unwind(AssertUnwindSafe(body)) {
+    Ok(Ok(())) => return W3NameStatus::Ok,
+    Ok(Err(failure)) => failure,
+    Err(panic) => {
+      let message = panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+      Failure::new(W3NameStatus::Panic, format!("panicked: {}", message))
+    }
+  };
+  // messages come from errors, which don't contain NULs, but don't lose one that does
+  let message = CString::new(failure.message.replace('\0', "\\0")).unwrap_or_default();
+  LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+  failure.status
+}
+
+/// Reads the string argument `arg`, called `what` in errors.
+///
+/// # Safety
+///
+/// `arg` must be NULL or point to a NUL-terminated string that outlives the call.
+unsafe fn str_arg<'a>(arg: *const c_char, what: &str) -> Result<&'a str, Failure> {
+  if arg.is_null() {
+    return Err(Failure::new(
+      W3NameStatus::InvalidArgument,
+      format!("{} is NULL", what),
+    ));
+  }
+  CStr::from_ptr(arg).to_str().map_err(|_| {
+    Failure::new(
+      W3NameStatus::InvalidArgument,
+      format!("{} isn't valid UTF-8", what),
+    )
+  })
+}
+
+/// Reads the byte buffer argument `bytes` of `len` bytes.
+///
+/// # Safety
+///
+/// `bytes` must be NULL or point to `len` readable bytes that outlive the call.
+unsafe fn bytes_arg<'a>(bytes: *const u8, len: usize) -> Result<&'a [u8], Failure> {
+  if bytes.is_null() {
+    return Err(Failure::new(
+      W3NameStatus::InvalidArgument,
+      "record is NULL",
+    ));
+  }
+  Ok(slice::from_raw_parts(bytes, len))
+}
+
+/// Checks that the out parameter `out` can be written.
+fn out_arg(out: *mut *mut c_char) -> Result<(), Failure> {
+  if out.is_null() {
+    return Err(Failure::new(
+      W3NameStatus::InvalidArgument,
+      "the out parameter is NULL",
+    ));
+  }
+  Ok(())
+}
+
+/// Hands `s` to the caller through `out`.
+///
+/// # Safety
+///
+/// `out` must be a valid pointer to write a `char *` to.
+unsafe fn string_out(s: String, out: *mut *mut c_char) -> Result<(), Failure> {
+  let s = CString::new(s).map_err(|_| {
+    Failure::new(
+      W3NameStatus::InvalidRecord,
+      "the value contains a NUL byte, so it can't be returned as a C string",
+    )
+  })?;
+  *out = s.into_raw();
+  Ok(())
+}
+
+fn parse_name(name: &str) -> Result<Name, Failure> {
+  Name::parse(name)
+    .map_err(|err_report| Failure::from_report(W3NameStatus::InvalidName, &err_report))
+}
+
+/// The runtime that resolves run on, shared by all calls.
+fn runtime() -> Result<&'static tokio::runtime::Runtime, Failure> {
+  static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+  if let Some(runtime) = RUNTIME.get() {
+    return Ok(runtime);
+  }
+  let runtime = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .map_err(|err| {
+      Failure::new(
+        W3NameStatus::Service,
+        format!("can't start the async runtime: {}", err),
+      )
+    })?;
+  Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// Classifies a failed resolve, like the CLI's exit codes do.
+fn resolve_failure(err_report: &Report<ClientError>) -> Failure {
+  let status = match err_report.downcast_ref::<APIError>() {
+    Some(api_err) if api_err.status_code == 404 => W3NameStatus::NotFound,
+    Some(_) => W3NameStatus::Service,
+    None if err_report.contains::<IpnsError>() => W3NameStatus::InvalidRecord,
+    None => W3NameStatus::Service,
+  };
+  Failure::from_report(status, err_report)
+}
+
+/// Resolves `name` with the w3name service at `endpoint`, or the public service if `endpoint` is
+/// NULL, and writes its current value to `*value_out`.
+///
+/// The record is validated against `name` before its value is returned.
+///
+/// # Safety
+///
+/// `name` and `endpoint` must be NULL or NUL-terminated strings, and `value_out` must be NULL or
+/// point to a `char *` to write to. The value written must be freed with [w3name_string_free].
+#[no_mangle]
+pub unsafe extern "C" fn w3name_resolve(
+  name: *const c_char,
+  endpoint: *const c_char,
+  value_out: *mut *mut c_char,
+) -> W3NameStatus {
+  call(|| {
+    let name = parse_name(str_arg(name, "name")?)?;
+    let client = if endpoint.is_null() {
+      W3NameClient::default()
+    } else {
+      let endpoint = str_arg(endpoint, "endpoint")?;
+      let endpoint = url::Url::parse(endpoint).map_err(|err| {
+        Failure::new(
+          W3NameStatus::InvalidArgument,
+          format!("invalid endpoint {}: {}", endpoint, err),
+        )
+      })?;
+      W3NameClient::new(endpoint)
+    };
+    out_arg(value_out)?;
+    let revision = runtime()?
+      .block_on(client.resolve(&name))
+      .map_err(|err_report| resolve_failure(&err_report))?;
+    string_out(revision.value().to_string(), value_out)
+  })
+}
+
+/// Verifies that the serialized record in `record`, of `record_len` bytes, is validly signed by
+/// the key of `name`.
+///
+/// Returns [W3NameStatus::Ok] if it is, and [W3NameStatus::InvalidRecord] if it isn't.
+///
+/// # Safety
+///
+/// `name` must be NULL or a NUL-terminated string, and `record` must be NULL or point to
+/// `record_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn w3name_verify(
+  name: *const c_char,
+  record: *const u8,
+  record_len: usize,
+) -> W3NameStatus {
+  call(|| {
+    let name = parse_name(str_arg(name, "name")?)?;
+    let record = bytes_arg(record, record_len)?;
+    Revision::try_from_record_bytes(record, &name)
+      .map_err(|err_report| Failure::from_report(W3NameStatus::InvalidRecord, &err_report))?;
+    Ok(())
+  })
+}
+
+/// Parses the serialized record in `record`, of `record_len` bytes, and writes its fields to
+/// `*json_out` as a JSON object: `name`, `value`, `sequence`, `validity` (an RFC 3339 timestamp)
+/// and `ttl_ns`.
+///
+/// The record's signature isn't checked; use [w3name_verify] for that. `name` is the name the
+/// record is for, and may be NULL if the record embeds its public key.
+///
+/// # Safety
+///
+/// `name` must be NULL or a NUL-terminated string, `record` must be NULL or point to
+/// `record_len` readable bytes, and `json_out` must be NULL or point to a `char *` to write to.
+/// The JSON written must be freed with [w3name_string_free].
+#[no_mangle]
+pub unsafe extern "C" fn w3name_parse(
+  record: *const u8,
+  record_len: usize,
+  name: *const c_char,
+  json_out: *mut *mut c_char,
+) -> W3NameStatus {
+  call(|| {
+    let record = bytes_arg(record, record_len)?;
+    let name = if name.is_null() {
+      None
+    } else {
+      Some(parse_name(str_arg(name, "name")?)?)
+    };
+    out_arg(json_out)?;
+
+    let invalid = |err_report: Report<IpnsError>| {
+      Failure::from_report(W3NameStatus::InvalidRecord, &err_report)
+    };
+    let entry = deserialize_ipns_entry_untrusted(record).map_err(invalid)?;
+    let name = match (name, embedded_public_key(&entry).map_err(invalid)?) {
+      (Some(name), _) => name,
+      (None, Some(key)) => Name::from_public_key(&key),
+      (None, None) => {
+        return Err(Failure::new(
+          W3NameStatus::InvalidArgument,
+          "the record doesn't embed its public key, so its name must be given",
+        ))
+      }
+    };
+    let revision = revision_from_ipns_entry_unchecked(&entry, &name).map_err(invalid)?;
+    let fields = serde_json::json!({
+      "name": name.to_string(),
+      "value": revision.value(),
+      "sequence": revision.sequence(),
+      "validity": revision.validity_string(),
+      "ttl_ns": revision.ttl_nanos(),
+    });
+    string_out(fields.to_string(), json_out)
+  })
+}
+
+/// Returns a copy of the message of the last failure on this thread, or NULL if the last call
+/// succeeded.
+///
+/// The message must be freed with [w3name_string_free].
+#[no_mangle]
+pub extern "C" fn w3name_last_error_message() -> *mut c_char {
+  LAST_ERROR.with(|last| match &*last.borrow() {
+    Some(message) => message.clone().into_raw(),
+    None => ptr::null_mut(),
+  })
+}
+
+/// Frees a string returned by this library. Does nothing if `s` is NULL.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string returned by this library that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn w3name_string_free(s: *mut c_char) {
+  if !s.is_null() {
+    drop(CString::from_raw(s));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn panics_become_a_status() {
+    let status = call(|| panic!("boom"));
+    assert_eq!(status, W3NameStatus::Panic);
+    let message = w3name_last_error_message();
+    assert_eq!(
+      unsafe { CStr::from_ptr(message) }.to_str().unwrap(),
+      "panicked: boom"
+    );
+    unsafe { w3name_string_free(message) };
+
+    // a successful call clears the message
+    assert_eq!(call(|| Ok(())), W3NameStatus::Ok);
+    assert!(w3name_last_error_message().is_null());
+  }
+}
@@ -0,0 +1,149 @@
+//! Loads the built library the way a C or Python host would, and calls each function through it.
+
+use std::{
+  ffi::{c_char, CStr, CString},
+  io::{BufRead, BufReader, Write},
+  net::TcpListener,
+  ptr,
+};
+
+use libloading::{Library, Symbol};
+use w3name::{Revision, WritableName};
+use w3name_ffi::W3NameStatus;
+
+const VALUE: &str = "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+type Resolve = unsafe extern "C" fn(*const c_char, *const c_char, *mut *mut c_char) -> W3NameStatus;
+type Verify = unsafe extern "C" fn(*const c_char, *const u8, usize) -> W3NameStatus;
+type Parse =
+  unsafe extern "C" fn(*const u8, usize, *const c_char, *mut *mut c_char) -> W3NameStatus;
+type LastError = unsafe extern "C" fn() -> *mut c_char;
+type StringFree = unsafe extern "C" fn(*mut c_char);
+
+/// Opens the cdylib that cargo built next to this test's executable.
+fn library() -> Library {
+  let deps = std::env::current_exe().unwrap();
+  let dir = deps.parent().unwrap();
+  let file = libloading::library_filename("w3name_ffi");
+  let path = [dir.join(&file), dir.parent().unwrap().join(&file)]
+    .into_iter()
+    .find(|path| path.exists())
+    .expect("the cdylib isn't built");
+  unsafe { Library::new(path).unwrap() }
+}
+
+/// Takes a string returned by the library, and frees it.
+fn take(lib: &Library, s: *mut c_char) -> Option<String> {
+  if s.is_null() {
+    return None;
+  }
+  let owned = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+  unsafe {
+    let free: Symbol<StringFree> = lib.get(b"w3name_string_free").unwrap();
+    free(s);
+  }
+  Some(owned)
+}
+
+fn last_error(lib: &Library) -> Option<String> {
+  let last_error: Symbol<LastError> = unsafe { lib.get(b"w3name_last_error_message").unwrap() };
+  take(lib, unsafe { last_error() })
+}
+
+/// Serves `record` for `name` and 404s for anything else, like the w3name service, until the
+/// test ends.
+fn serve(name: String, record: Vec<u8>) -> String {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+  std::thread::spawn(move || {
+    for stream in listener.incoming() {
+      let mut stream = stream.unwrap();
+      let mut request_line = String::new();
+      let mut reader = BufReader::new(stream.try_clone().unwrap());
+      reader.read_line(&mut request_line).unwrap();
+      // skip the headers
+      let mut line = String::new();
+      while reader.read_line(&mut line).unwrap() > 2 {
+        line.clear();
+      }
+      let (status, body) = if request_line.contains(&format!("/name/{} ", name)) {
+        let body = serde_json::json!({ "value": VALUE, "record": base64::encode(&record) });
+        ("200 OK", body.to_string())
+      } else {
+        ("404 Not Found", r#"{"message":"not found"}"#.to_string())
+      };
+      let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+      );
+    }
+  });
+  endpoint
+}
+
+#[test]
+fn smoke() {
+  let lib = library();
+  let resolve: Symbol<Resolve> = unsafe { lib.get(b"w3name_resolve").unwrap() };
+  let verify: Symbol<Verify> = unsafe { lib.get(b"w3name_verify").unwrap() };
+  let parse: Symbol<Parse> = unsafe { lib.get(b"w3name_parse").unwrap() };
+
+  let key = WritableName::new();
+  let name = CString::new(key.to_string()).unwrap();
+  let record = Revision::v0(&key.to_name(), VALUE)
+    .to_signed_bytes(&key)
+    .unwrap();
+  let endpoint = CString::new(serve(key.to_string(), record.clone())).unwrap();
+
+  // resolve
+  let mut value = ptr::null_mut();
+  let status = unsafe { resolve(name.as_ptr(), endpoint.as_ptr(), &mut value) };
+  assert_eq!(status, W3NameStatus::Ok, "{:?}", last_error(&lib));
+  assert_eq!(take(&lib, value).unwrap(), VALUE);
+  assert_eq!(last_error(&lib), None);
+
+  let other = CString::new(WritableName::new().to_string()).unwrap();
+  let mut value = ptr::null_mut();
+  let status = unsafe { resolve(other.as_ptr(), endpoint.as_ptr(), &mut value) };
+  assert_eq!(status, W3NameStatus::NotFound);
+  assert!(value.is_null());
+  assert!(last_error(&lib).unwrap().contains("not found"));
+
+  // verify
+  let status = unsafe { verify(name.as_ptr(), record.as_ptr(), record.len()) };
+  assert_eq!(status, W3NameStatus::Ok);
+  let status = unsafe { verify(other.as_ptr(), record.as_ptr(), record.len()) };
+  assert_eq!(status, W3NameStatus::InvalidRecord);
+  assert!(last_error(&lib).is_some());
+  let status = unsafe { verify(name.as_ptr(), ptr::null(), 0) };
+  assert_eq!(status, W3NameStatus::InvalidArgument);
+  assert_eq!(last_error(&lib).unwrap(), "record is NULL");
+  let garbage = CString::new("not a name").unwrap();
+  let status = unsafe { verify(garbage.as_ptr(), record.as_ptr(), record.len()) };
+  assert_eq!(status, W3NameStatus::InvalidName);
+
+  // parse
+  let mut json = ptr::null_mut();
+  let status = unsafe { parse(record.as_ptr(), record.len(), name.as_ptr(), &mut json) };
+  assert_eq!(status, W3NameStatus::Ok);
+  let fields: serde_json::Value = serde_json::from_str(&take(&lib, json).unwrap()).unwrap();
+  assert_eq!(fields["name"], key.to_string());
+  assert_eq!(fields["value"], VALUE);
+  assert_eq!(fields["sequence"], 0);
+  // ed25519 records don't embed their key, so the name is needed
+  let mut json = ptr::null_mut();
+  let status = unsafe { parse(record.as_ptr(), record.len(), ptr::null(), &mut json) };
+  assert_eq!(status, W3NameStatus::InvalidArgument);
+  assert!(json.is_null());
+  let status = unsafe { parse(b"garbage".as_ptr(), 7, name.as_ptr(), &mut json) };
+  assert_eq!(status, W3NameStatus::InvalidRecord);
+
+  // freeing NULL is fine
+  unsafe {
+    let free: Symbol<StringFree> = lib.get(b"w3name_string_free").unwrap();
+    free(ptr::null_mut());
+  }
+}
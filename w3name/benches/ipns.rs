@@ -0,0 +1,66 @@
+//! Benchmarks for record creation and validation.
+//!
+//! Run with `cargo bench -p w3name`.
+//!
+//! For reference, `validate_ipns_entry_for_name` used to decode the record's embedded public key on
+//! every call, costing ~48.9µs against ~45.8µs for `validate_ipns_entry`. `Name` now keeps its
+//! encoded key around, so a matching embedded key is recognized without decoding and both measure
+//! ~46.7µs; nearly all of that is the Ed25519 signature check itself.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use w3name::{
+  ipns::{
+    revision_to_ipns_entry, sign_revisions, validate_ipns_entry, validate_ipns_entry_for_name,
+  },
+  Name, Revision, WritableName,
+};
+
+/// How many revisions the batch signing benchmarks sign.
+const BATCH: usize = 100;
+
+const VALUE: &str = "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+fn bench_ipns(c: &mut Criterion) {
+  let writable = WritableName::new();
+  let name = writable.to_name();
+  let name_str = name.to_string();
+  let revision = Revision::v0(&name, VALUE);
+  let mut entry = revision_to_ipns_entry(&revision, writable.keypair()).unwrap();
+
+  c.bench_function("Name::parse", |b| {
+    b.iter(|| Name::parse(black_box(&name_str)).unwrap())
+  });
+
+  c.bench_function("revision_to_ipns_entry", |b| {
+    b.iter(|| revision_to_ipns_entry(black_box(&revision), writable.keypair()).unwrap())
+  });
+
+  c.bench_function("validate_ipns_entry", |b| {
+    b.iter(|| validate_ipns_entry(black_box(&entry), name.public_key()).unwrap())
+  });
+
+  // records fetched from the network usually embed the public key, which is checked against the name
+  entry.pub_key = name.public_key_protobuf_bytes();
+  c.bench_function("validate_ipns_entry_for_name (repeated, same name)", |b| {
+    b.iter(|| validate_ipns_entry_for_name(black_box(&entry), &name).unwrap())
+  });
+
+  let mut batch = vec![revision.clone()];
+  for n in 1..BATCH {
+    batch.push(batch[n - 1].increment(VALUE));
+  }
+  c.bench_function("revision_to_ipns_entry (100 revisions)", |b| {
+    b.iter(|| {
+      black_box(&batch)
+        .iter()
+        .map(|revision| revision_to_ipns_entry(revision, writable.keypair()).unwrap())
+        .collect::<Vec<_>>()
+    })
+  });
+  c.bench_function("sign_revisions (100 revisions)", |b| {
+    b.iter(|| sign_revisions(writable.keypair(), black_box(&batch)))
+  });
+}
+
+criterion_group!(benches, bench_ipns);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+//! Implements the [Resolver] trait for a record source of your own, here an in-memory map of
+//! signed records standing in for e.g. a database or a private gateway.
+//!
+//! ```sh
+//! cargo run --example custom_resolver
+//! ```
+//!
+//! Anything that takes a [Resolver], like [CoalescingResolver] or the fallback resolvers in
+//! [PublishOptions](w3name::PublishOptions), works with it.
+
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+use async_trait::async_trait;
+use error_stack::{report, Result, ResultExt};
+use w3name::{
+  error::ClientError, CoalescingResolver, Name, ResolvedRevision, Resolver, Revision, SourceInfo,
+  SourceKind, WritableName,
+};
+
+/// Serves signed records from memory, keyed by name.
+#[derive(Default)]
+struct MemoryResolver {
+  records: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryResolver {
+  fn insert(&self, name: &Name, record: Vec<u8>) {
+    self
+      .records
+      .lock()
+      .unwrap()
+      .insert(name.to_string(), record);
+  }
+}
+
+#[async_trait]
+impl Resolver for MemoryResolver {
+  async fn resolve(&self, name: &Name) -> Result<ResolvedRevision, ClientError> {
+    let started = Instant::now();
+    let record = self
+      .records
+      .lock()
+      .unwrap()
+      .get(&name.to_string())
+      .cloned()
+      .ok_or_else(|| report!(ClientError).attach_printable(format!("no record for {}", name)))?;
+    // resolvers must only return revisions whose record was validated against the name
+    let revision = Revision::from_signed_bytes(name, &record).change_context(ClientError)?;
+    Ok(ResolvedRevision::fetched(
+      revision,
+      SourceInfo {
+        kind: SourceKind::Cache,
+        url: None,
+        elapsed: started.elapsed(),
+        expiry_checked: false,
+      },
+    ))
+  }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), ClientError> {
+  let key = WritableName::new();
+  let name = key.to_name();
+  let revision = Revision::v0(
+    &name,
+    "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi",
+  )
+  .increment("/ipfs/bafkreigh2akiscaildcqabsyg3dfr6chu3fgpregiymsck7e7aqa4s52zy");
+
+  let records = MemoryResolver::default();
+  records.insert(
+    &name,
+    revision.to_signed_bytes(&key).change_context(ClientError)?,
+  );
+
+  // a custom resolver composes with the ones in the crate
+  let resolver = CoalescingResolver::new(records);
+  let resolved = resolver.resolve(&name).await?;
+  assert_eq!(resolved.revision, revision);
+  println!(
+    "{} resolved to {} (sequence {}) in {:?}",
+    name,
+    resolved.value(),
+    resolved.sequence(),
+    resolved.source.elapsed
+  );
+
+  let unknown = WritableName::new().to_name();
+  assert!(resolver.resolve(&unknown).await.is_err());
+  Ok(())
+}
+
+#[test]
+fn runs() {
+  main().unwrap();
+}
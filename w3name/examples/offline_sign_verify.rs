@@ -0,0 +1,48 @@
+//! Creates a key, signs a record for its name and validates it, without touching the network.
+//!
+//! ```sh
+//! cargo run --example offline_sign_verify
+//! ```
+//!
+//! The base64 record it prints can be published later, e.g. with
+//! [W3NameClient::publish_record](w3name::W3NameClient::publish_record).
+
+use error_stack::Result;
+use w3name::{error::IpnsError, ipns, Revision, WritableName};
+
+fn main() -> Result<(), IpnsError> {
+  let key = WritableName::new();
+  let name = key.to_name();
+  println!("name: {}", name);
+
+  let revision = Revision::v0(
+    &name,
+    "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi",
+  );
+  let record = revision.to_signed_bytes(&key)?;
+
+  // what a resolver does with a record it receives: decode it, check its signature against the
+  // name's public key, and read the revision out of it
+  let entry = ipns::deserialize_ipns_entry(&record)?;
+  ipns::validate_ipns_entry_for_name(&entry, &name)?;
+  let verified = ipns::revision_from_ipns_entry_unchecked(&entry, &name)?;
+  assert_eq!(verified, revision);
+  println!(
+    "verified: {} (sequence {}, valid until {})",
+    verified.value(),
+    verified.sequence(),
+    verified.validity_string()
+  );
+
+  // a record doesn't validate for any other name
+  let other = WritableName::new().to_name();
+  assert!(Revision::from_signed_bytes(&other, &record).is_err());
+
+  println!("record: {}", base64::encode(&record));
+  Ok(())
+}
+
+#[test]
+fn runs() {
+  main().unwrap();
+}
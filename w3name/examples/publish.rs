@@ -0,0 +1,68 @@
+//! Publishes a new value for the name of a key file, e.g. one written by `w3name create`.
+//!
+//! ```sh
+//! cargo run --example publish -- my.key /ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi
+//! ```
+//!
+//! This spells out what [W3NameClient::publish_value] does in one call: resolve the current
+//! revision, increment it with the new value (or start at the first revision if the name has
+//! none), sign it and publish it. Set `W3NAME_ENDPOINT` to use a service other than the public
+//! one.
+
+use std::{env, fs};
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use w3name::{
+  error::{APIError, ClientError},
+  Revision, W3NameClient, WritableName,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), ClientError> {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let [key_file, value] = &args[..] else {
+    return Err(report!(ClientError).attach_printable("usage: publish <key file> <value>"));
+  };
+
+  let key_bytes = fs::read(key_file)
+    .report()
+    .change_context(ClientError)
+    .attach_printable(format!("key file: {}", key_file))?;
+  let key = WritableName::decode(&key_bytes).change_context(ClientError)?;
+  let name = key.to_name();
+
+  let client = match env::var("W3NAME_ENDPOINT") {
+    Ok(endpoint) => W3NameClient::new(endpoint.parse().report().change_context(ClientError)?),
+    Err(_) => W3NameClient::default(),
+  };
+
+  let revision = match client.resolve(&name).await {
+    Ok(current) => {
+      println!(
+        "current value: {} (sequence {})",
+        current.value(),
+        current.sequence()
+      );
+      current.increment(value)
+    }
+    // a name that was never published has no record yet
+    Err(err)
+      if err
+        .downcast_ref::<APIError>()
+        .is_some_and(|err| err.status_code == 404) =>
+    {
+      println!("{} has no record yet", name);
+      Revision::v0(&name, value)
+    }
+    Err(err) => return Err(err),
+  };
+
+  client.publish(&key, &revision).await?;
+  println!(
+    "published {} for {} (sequence {})",
+    value,
+    name,
+    revision.sequence()
+  );
+  Ok(())
+}
@@ -0,0 +1,35 @@
+//! Resolves a name to its current value with the w3name service.
+//!
+//! ```sh
+//! cargo run --example resolve -- k51qzi5uqu5dka3tmn6ipgsrq1u2bkuowdwlqcw0vibledypt1y9y5i8v8xwvu
+//! ```
+//!
+//! Set `W3NAME_ENDPOINT` to use a service other than the public one.
+
+use std::env;
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use w3name::{error::ClientError, Name, W3NameClient};
+
+#[tokio::main]
+async fn main() -> Result<(), ClientError> {
+  let arg = env::args()
+    .nth(1)
+    .ok_or_else(|| report!(ClientError).attach_printable("usage: resolve <name>"))?;
+  let name = Name::parse(&arg).change_context(ClientError)?;
+
+  let client = match env::var("W3NAME_ENDPOINT") {
+    Ok(endpoint) => W3NameClient::new(endpoint.parse().report().change_context(ClientError)?),
+    Err(_) => W3NameClient::default(),
+  };
+
+  // the record is validated against the name's public key before it's returned
+  let revision = client.resolve(&name).await?;
+  println!("{}", revision.value());
+  println!(
+    "sequence {}, valid until {}",
+    revision.sequence(),
+    revision.validity_string()
+  );
+  Ok(())
+}
@@ -4,124 +4,1159 @@ use governor::{
   state::{InMemoryState, NotKeyed},
   Quota, RateLimiter,
 };
+use http::{
+  header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, DATE, ETAG, IF_NONE_MATCH},
+  Method, StatusCode,
+};
 use nonzero_ext::nonzero;
-use reqwest::{Client, Response, Url};
+use std::{
+  num::NonZeroU32,
+  ops::Deref,
+  sync::{Arc, OnceLock},
+  time::Instant,
+};
+use tokio::sync::Semaphore;
+use url::Url;
+use w3name_core::{
+  internal::{checked_add_duration, default_validity_duration, duration_secs, parse_http_date},
+  timestamp::{Duration, Timestamp},
+};
 
+#[cfg(feature = "client")]
+use crate::{error::HttpError, transport::ReqwestTransport};
 use crate::{
-  error::{APIError, ClientError, HttpError, UnexpectedAPIResponse},
-  ipns::{
-    deserialize_ipns_entry, revision_from_ipns_entry, revision_to_ipns_entry, serialize_ipns_entry,
-    validate_ipns_entry,
+  error::{
+    APIError, ClientError, NameMismatch, RecordTooLarge, UnexpectedAPIResponse, ValidityOutOfRange,
   },
-  Name, Revision, WritableName,
+  http_trace,
+  ipns::MAX_RECORD_SIZE,
+  publisher::{SignatureMode, SignatureProbe},
+  transport::{self, HttpRequest, HttpResponse, HttpTransport},
+  Clock, Defaults, IncrementPolicy, Name, ResolvedRevision, Resolver, Revision, RevisionBuilder,
+  SourceInfo, SourceKind, SystemClock, Value, WritableName,
 };
 
 const DEFAULT_ENDPOINT: &str = "https://name.web3.storage";
-const RATE_LIMIT_REQUESTS: u32 = 30;
+/// Resolve responses are JSON objects holding a base64-encoded record, so allow for the encoding overhead.
+const MAX_RESOLVE_RESPONSE_SIZE: usize = MAX_RECORD_SIZE * 4 / 3 + 1024;
+const RATE_LIMIT_REQUESTS: NonZeroU32 = nonzero!(30u32);
+/// How much of an unexpected response body is quoted in errors.
+const BODY_PREVIEW_LEN: usize = 100;
+/// Clock skew beyond this many seconds is logged as a warning.
+const CLOCK_SKEW_WARNING_SECS: i64 = 60;
+/// The maximum number of concurrent requests made by [W3NameClient::resolve_many].
+const RESOLVE_MANY_CONCURRENCY: usize = 8;
+/// The header that lets the service recognize a publish it has already accepted.
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Options for [W3NameClient::publish_value].
+#[derive(Clone, Default)]
+pub struct PublishOptions {
+  /// If `true`, nothing is published when the current value is [equivalent](Value::is_equivalent) to the new one.
+  pub skip_unchanged: bool,
+
+  /// If `true`, the current revision isn't resolved, and an initial (v0) revision is published.
+  ///
+  /// Note that the service will reject a v0 revision if the name already has a record.
+  pub force_v0: bool,
+
+  /// The current revision, if it's already known, e.g. from a record exported elsewhere. The new
+  /// revision is its successor, and nothing is resolved. Takes precedence over `force_v0`.
+  ///
+  /// The revision isn't checked against what the service has: if the service has a newer one,
+  /// it rejects the publish.
+  pub current_revision: Option<Revision>,
+
+  /// Resolvers to try, in order, if the w3name service fails to resolve the current revision.
+  ///
+  /// They are not consulted if the service reports that the name has no record (404).
+  pub fallback_resolvers: Vec<Arc<dyn Resolver>>,
+
+  /// How long the new revision stays valid. Defaults to what `increment_policy` picks, or one
+  /// year for a v0 revision.
+  pub validity: Option<Duration>,
+
+  /// When the new revision stops being valid, exactly, e.g. a cutover instant shared by several
+  /// names. Takes precedence over `validity`, and isn't adjusted by `validity_from_server_time`.
+  pub valid_until: Option<Timestamp>,
+
+  /// The TTL of the new revision. Defaults to what `increment_policy` picks, or 31 days for a v0
+  /// revision.
+  pub ttl: Option<Duration>,
+
+  /// How the validity and TTL of the new revision are derived from the current revision, where
+  /// `validity` and `ttl` aren't set. Defaults to [IncrementPolicy::Defaults].
+  pub increment_policy: IncrementPolicy,
+
+  /// The revision published last for the name, e.g. as kept in local state. If the current
+  /// revision is that one, its validity window is taken from it (see
+  /// [Revision::with_validity_window_of]), since records don't carry it, so that
+  /// [IncrementPolicy::PreserveDurations] keeps it.
+  pub last_published: Option<Revision>,
+
+  /// The validity window and TTL of the new revision where `validity` and `ttl` aren't set, for
+  /// initial revisions and successors alike, instead of the built-in ones. Where they're set,
+  /// they take precedence over `increment_policy`; see [RevisionBuilder::with_defaults].
+  pub defaults: Defaults,
+
+  /// If `true`, the validity is counted from the w3name service's clock rather than the local
+  /// clock, using the [observed clock skew](W3NameClient::observed_clock_skew).
+  ///
+  /// This only has an effect once the client has contacted the service, which it does when
+  /// resolving the current revision (i.e. unless `force_v0` is set).
+  pub validity_from_server_time: bool,
+
+  /// Which signatures the published record carries. Defaults to [SignatureMode::Auto].
+  pub signature_mode: SignatureMode,
+
+  /// The `Idempotency-Key` to send the publish with. Defaults to a new random UUID.
+  ///
+  /// Set it to the key of an earlier attempt (see [PublishReceipt::idempotency_key]) when
+  /// retrying a publish from another process, e.g. after a timeout, so a service that already
+  /// accepted the first attempt can tell it's the same publish. The key should then come with the
+  /// same `current_revision` and `valid_until`, since the retry must upload the same revision.
+  ///
+  /// If the publish falls back to a hybrid record (see [SignatureMode::Auto]), that's uploaded
+  /// with a new key, which is the one in the receipt.
+  pub idempotency_key: Option<String>,
+}
+
+/// The result of [W3NameClient::publish_value].
+#[derive(Clone, Debug)]
+pub enum PublishOutcome {
+  /// A new revision was published. It derefs to the new [Revision], and also has the service's
+  /// [PublishReceipt] and the revision it replaced.
+  Published(PublishedRevision),
+
+  /// The current value was already equivalent to the new one, so nothing was published.
+  /// Contains the existing revision.
+  Unchanged(CurrentRevision),
+}
+
+impl PublishOutcome {
+  /// Returns the published revision, or the existing one if nothing was published.
+  pub fn revision(&self) -> &Revision {
+    match self {
+      PublishOutcome::Published(published) => &published.revision,
+      PublishOutcome::Unchanged(current) => &current.revision,
+    }
+  }
 
+  /// Returns the service's receipt, if a new revision was published.
+  pub fn receipt(&self) -> Option<&PublishReceipt> {
+    match self {
+      PublishOutcome::Published(published) => Some(&published.receipt),
+      PublishOutcome::Unchanged(_) => None,
+    }
+  }
+
+  /// Returns the revision that was current before publishing, and where it was found.
+  ///
+  /// This is `None` if the name had no record, or the current revision wasn't resolved (see [PublishOptions::force_v0]).
+  pub fn previous(&self) -> Option<&CurrentRevision> {
+    match self {
+      PublishOutcome::Published(published) => published.previous(),
+      PublishOutcome::Unchanged(current) => Some(current),
+    }
+  }
+
+  /// Consumes the outcome, returning the published revision, or the existing one if nothing was published.
+  pub fn into_revision(self) -> Revision {
+    match self {
+      PublishOutcome::Published(published) => published.revision,
+      PublishOutcome::Unchanged(current) => current.revision,
+    }
+  }
+}
+
+/// A revision that was published, with the service's response.
+///
+/// It derefs to the [Revision], so it can mostly be used like one.
+#[derive(Clone, Debug)]
+pub struct PublishedRevision {
+  revision: Revision,
+  receipt: PublishReceipt,
+  previous: Option<Box<CurrentRevision>>,
+}
+
+impl PublishedRevision {
+  /// Describes the publish of `revision`, which replaced `previous`, if the name had a revision,
+  /// as in [PreparedRevision::Publish].
+  pub fn new(
+    revision: Revision,
+    receipt: PublishReceipt,
+    previous: Option<Box<CurrentRevision>>,
+  ) -> PublishedRevision {
+    PublishedRevision {
+      revision,
+      receipt,
+      previous,
+    }
+  }
+
+  pub fn revision(&self) -> &Revision {
+    &self.revision
+  }
+
+  /// Returns the service's response to the publish.
+  pub fn receipt(&self) -> &PublishReceipt {
+    &self.receipt
+  }
+
+  /// Returns the revision that was replaced, and where it was found, if there was one.
+  pub fn previous(&self) -> Option<&CurrentRevision> {
+    self.previous.as_deref()
+  }
+
+  /// Unwraps the [Revision], dropping the receipt.
+  pub fn into_revision(self) -> Revision {
+    self.revision
+  }
+}
+
+impl Deref for PublishedRevision {
+  type Target = Revision;
+
+  fn deref(&self) -> &Revision {
+    &self.revision
+  }
+}
+
+/// The revision that [W3NameClient::prepare_publish] built, ready to be published.
+#[derive(Clone, Debug)]
+pub enum PreparedRevision {
+  /// The new revision, to be signed and published. Also contains the revision it replaces, if
+  /// there is one.
+  Publish(Revision, Option<Box<CurrentRevision>>),
+
+  /// The current value is already equivalent to the new one, so there's nothing to publish.
+  /// Contains the existing revision.
+  Unchanged(CurrentRevision),
+}
+
+/// The current revision of a name, as resolved by [W3NameClient::publish_value].
+#[derive(Clone, Debug)]
+pub struct CurrentRevision {
+  pub revision: Revision,
+  pub source: RevisionSource,
+}
+
+/// Where [W3NameClient::publish_value] got the current revision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RevisionSource {
+  /// The w3name service.
+  Service,
+  /// One of the [fallback resolvers](PublishOptions::fallback_resolvers), by index.
+  Fallback(usize),
+  /// The caller, with [PublishOptions::current_revision].
+  Provided,
+}
+
+/// The service's response to a successful [publish](W3NameClient::publish_with_receipt).
+#[derive(Clone, Debug)]
+pub struct PublishReceipt {
+  /// The HTTP status of the response, normally `202 Accepted`.
+  pub status: StatusCode,
+
+  /// The response headers.
+  pub headers: HeaderMap,
+
+  /// The response body, if it was JSON.
+  pub body: Option<serde_json::Value>,
+
+  /// The serialized IPNS record that was uploaded.
+  pub record: Vec<u8>,
+
+  /// The `Idempotency-Key` header the record was uploaded with, if the publisher sends one.
+  pub idempotency_key: Option<String>,
+}
+
+impl PublishReceipt {
+  /// Returns the `id` field of the response body, which the service sets to the published name.
+  pub fn id(&self) -> Option<&str> {
+    self.body.as_ref()?.get("id")?.as_str()
+  }
+}
+
+/// A client for the w3name HTTP API.
+///
+/// `W3NameClient` is `Send + Sync`, and cloning it is cheap: clones are handles to the same
+/// connection pool and rate limiter, so share one client (e.g. in your web framework's state)
+/// rather than creating one per request.
 pub struct W3NameClient {
+  inner: Arc<ClientInner>,
+}
+
+struct ClientInner {
   endpoint: Url,
-  http: Client,
+  transport: Arc<dyn HttpTransport>,
   limiter: RateLimiter<NotKeyed, InMemoryState, DefaultClock>,
+  clock_skew: OnceLock<Duration>,
+  signatures: SignatureProbe,
+  clock: Arc<dyn Clock>,
+  debug_http: bool,
+}
+
+impl Clone for W3NameClient {
+  fn clone(&self) -> Self {
+    W3NameClient {
+      inner: Arc::clone(&self.inner),
+    }
+  }
 }
 
 impl W3NameClient {
+  /// Creates a client for the service at `endpoint` that sends its requests with reqwest.
+  ///
+  /// Requires the `client` feature; without it, use [builder](Self::builder) with a
+  /// [transport](W3NameClientBuilder::transport).
+  #[cfg(feature = "client")]
   pub fn new(endpoint: Url) -> Self {
-    let http = Client::new();
-    let limiter = RateLimiter::direct(Quota::per_second(nonzero!(RATE_LIMIT_REQUESTS)));
-    W3NameClient {
+    Self::with_transport(
       endpoint,
-      http,
-      limiter,
+      Arc::new(ReqwestTransport::default()),
+      false,
+      Arc::new(SystemClock),
+      RATE_LIMIT_REQUESTS,
+    )
+  }
+
+  /// Returns a [W3NameClientBuilder], for tuning the client's HTTP connections.
+  pub fn builder(endpoint: Url) -> W3NameClientBuilder {
+    W3NameClientBuilder::new(endpoint)
+  }
+
+  fn with_transport(
+    endpoint: Url,
+    transport: Arc<dyn HttpTransport>,
+    debug_http: bool,
+    clock: Arc<dyn Clock>,
+    rate_limit: NonZeroU32,
+  ) -> Self {
+    let limiter = RateLimiter::direct(Quota::per_second(rate_limit));
+    W3NameClient {
+      inner: Arc::new(ClientInner {
+        endpoint,
+        transport,
+        limiter,
+        clock_skew: OnceLock::new(),
+        signatures: SignatureProbe::default(),
+        clock,
+        debug_http,
+      }),
+    }
+  }
+
+  /// The base URL of the w3name service used by this client.
+  pub fn endpoint(&self) -> &Url {
+    &self.inner.endpoint
+  }
+
+  /// Returns how far the w3name service's clock is ahead of the local clock, as measured from
+  /// the `Date` header of the first response from the service. A negative skew means the local
+  /// clock is fast.
+  ///
+  /// Returns `None` if the client hasn't contacted the service yet, or the service didn't send a
+  /// usable `Date` header. The measurement is only accurate to a second or so.
+  pub fn observed_clock_skew(&self) -> Option<Duration> {
+    self.inner.clock_skew.get().copied()
+  }
+
+  /// Returns `true` once the service has rejected a V2-only record with 400 Bad Request and
+  /// accepted a hybrid V1+V2 record instead, after which [SignatureMode::Auto] publishes hybrid
+  /// records. Clones of the client share what it has learned.
+  pub fn uses_hybrid_records(&self) -> bool {
+    self.inner.signatures.needs_hybrid()
+  }
+
+  pub(crate) fn signatures(&self) -> &SignatureProbe {
+    &self.inner.signatures
+  }
+
+  /// Sends `req` to the service. With [debug_http](W3NameClientBuilder::debug_http), the request
+  /// and the response are logged in full (see [http_trace]).
+  async fn send(&self, req: HttpRequest) -> Result<HttpResponse, ClientError> {
+    if self.inner.debug_http {
+      http_trace::log_request(&req);
+    }
+    let res = transport::execute(self.inner.transport.as_ref(), req).await?;
+    if self.inner.debug_http {
+      http_trace::log_response(&res);
+    }
+    Ok(res)
+  }
+
+  /// Measures the clock skew from the first response with a `Date` header, and warns if it's large.
+  fn observe_clock_skew(&self, res: &HttpResponse) {
+    if self.inner.clock_skew.get().is_some() {
+      return;
+    }
+    let server_time = match res
+      .headers
+      .get(DATE)
+      .and_then(|date| date.to_str().ok())
+      .and_then(parse_http_date)
+    {
+      Some(server_time) => server_time,
+      None => return,
+    };
+    let skew = server_time - self.inner.clock.now();
+    if self.inner.clock_skew.set(skew).is_err() {
+      return;
+    }
+
+    let skew_secs = duration_secs(&skew);
+    log::debug!("Observed clock skew: {}s", skew_secs);
+    if skew_secs.abs() > CLOCK_SKEW_WARNING_SECS {
+      log::warn!(
+        "the local clock is {} the w3name service's clock by {}s; records may be rejected or look stale",
+        if skew_secs < 0 { "ahead of" } else { "behind" },
+        skew_secs.abs()
+      );
     }
   }
 
+  /// Signs `revision` with the key of `name`, and publishes it to the service.
+  ///
+  /// The URL is always derived from the key, and the revision must be for the key's name.
+  /// If the service's response names a different name than the one published (in its `id`),
+  /// the publish fails with [NameMismatch], since the record can't validate for that name.
+  ///
+  /// The record is signed in [SignatureMode::Auto]; see [publish_with_mode](Self::publish_with_mode).
+  /// Use [publish_with_receipt](Self::publish_with_receipt) to get the service's response.
   pub async fn publish(&self, name: &WritableName, revision: &Revision) -> Result<(), ClientError> {
-    let mut url = self.endpoint.clone();
-    url.set_path(format!("name/{}", name.to_string()).as_str());
+    self.publish_with_receipt(name, revision).await.map(drop)
+  }
 
-    log::debug!("HTTP POST {}", url);
+  /// Like [publish](Self::publish), but returns the service's [PublishReceipt], which includes
+  /// the uploaded record.
+  pub async fn publish_with_receipt(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+  ) -> Result<PublishReceipt, ClientError> {
+    self
+      .publish_with_mode(name, revision, SignatureMode::Auto)
+      .await
+  }
+
+  /// Like [publish_with_receipt](Self::publish_with_receipt), with the signatures that `mode`
+  /// asks for.
+  ///
+  /// In [SignatureMode::Auto], a V2-only record that the service rejects with 400 Bad Request is
+  /// retried once as a hybrid V1+V2 record, and if the service accepts that, this client (and its
+  /// clones) publish hybrid records from then on.
+  ///
+  /// Every publish is sent with a new random `Idempotency-Key` header, and a retry sends the same
+  /// key as the attempt it retries, so a service that deduplicates publishes doesn't apply one
+  /// twice. Services that don't support the header ignore it. The hybrid retry of a rejected
+  /// V2-only record uploads a different record, so it gets a key of its own.
+  pub async fn publish_with_mode(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    mode: SignatureMode,
+  ) -> Result<PublishReceipt, ClientError> {
+    self
+      .publish_keyed(name, revision, mode, &new_idempotency_key())
+      .await
+  }
+
+  /// Like [publish_with_mode](Self::publish_with_mode), sending `key` as the `Idempotency-Key`.
+  async fn publish_keyed(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    mode: SignatureMode,
+    key: &str,
+  ) -> Result<PublishReceipt, ClientError> {
     log::debug!(
-      "Publishing revision with sequence: {}, validity: {}",
+      "Publishing revision with sequence: {}, validity: {}, value length: {} bytes",
       revision.sequence(),
-      revision.validity()
+      revision.validity(),
+      revision.value_len()
     );
 
-    let entry = revision_to_ipns_entry(revision, name.keypair()).change_context(ClientError)?;
-    let encoded = serialize_ipns_entry(&entry).change_context(ClientError)?;
+    let target = name.to_name();
+    let endpoint = self.inner.endpoint.to_string();
+    self
+      .inner
+      .signatures
+      .publish(&endpoint, name, revision, None, mode, |encoded, retry| {
+        // the hybrid retry is a different record, so it's a different publish
+        let key = if retry {
+          new_idempotency_key()
+        } else {
+          key.to_string()
+        };
+        let target = &target;
+        async move { self.publish_record_with_key(target, encoded, &key).await }
+      })
+      .await
+  }
+
+  /// Publishes a record that's already signed with the key of `target`, e.g. by
+  /// [sign_revisions](crate::ipns::sign_revisions), like [publish](Self::publish).
+  ///
+  /// The record isn't checked before it's uploaded, so a record for another name is rejected by
+  /// the service.
+  pub async fn publish_record(
+    &self,
+    target: &Name,
+    encoded: Vec<u8>,
+  ) -> Result<PublishReceipt, ClientError> {
+    self
+      .publish_record_with_key(target, encoded, &new_idempotency_key())
+      .await
+  }
+
+  /// Like [publish_record](Self::publish_record), sending `key` as the `Idempotency-Key` header,
+  /// e.g. to retry the upload of the same record after a timeout.
+  pub async fn publish_record_with_key(
+    &self,
+    target: &Name,
+    encoded: Vec<u8>,
+    key: &str,
+  ) -> Result<PublishReceipt, ClientError> {
+    let key_header = HeaderValue::from_str(key)
+      .report()
+      .change_context(ClientError)
+      .attach_printable_lazy(|| format!("invalid idempotency key {:?}", key))?;
+    let mut url = self.inner.endpoint.clone();
+    url.set_path(&format!("name/{}", target));
 
+    log::debug!("HTTP POST {}", url);
     log::debug!("Encoded IPNS entry size: {} bytes", encoded.len());
 
     let body = base64::encode(&encoded);
 
-    self.limiter.until_ready().await;
+    self.inner.limiter.until_ready().await;
 
     let res = self
-      .http
-      .post(url.clone())
-      .body(body)
-      .send()
-      .await
-      .report()
-      .change_context(HttpError)
-      .change_context(ClientError)?;
+      .send(
+        HttpRequest::new(Method::POST, url)
+          .header(IDEMPOTENCY_KEY, key_header)
+          .body(body),
+      )
+      .await?;
+
+    log::debug!("Response status: {}", res.status);
+    self.observe_clock_skew(&res);
 
-    log::debug!("Response status: {}", res.status());
+    if !res.status.is_success() {
+      return Err(error_from_response(res));
+    }
+
+    let receipt = PublishReceipt {
+      status: res.status,
+      headers: res.headers,
+      body: serde_json::from_slice(&res.body).ok(),
+      record: encoded,
+      idempotency_key: Some(key.to_string()),
+    };
+    if let Some(id) = receipt.id() {
+      if id != target.to_string() {
+        return Err(
+          report!(NameMismatch)
+            .attach_printable(format!(
+              "published {}, but the service acknowledged {}",
+              target, id
+            ))
+            .change_context(ClientError),
+        );
+      }
+    }
+    Ok(receipt)
+  }
 
-    if res.status().is_success() {
-      Ok(())
+  /// Publishes `value` for `name`, taking care of resolving the current revision first.
+  ///
+  /// If the name has a record, the new revision increments its sequence number. If the current
+  /// revision is [given](PublishOptions::current_revision), it isn't resolved.
+  /// If the service has no record for the name (404), an initial (v0) revision is published.
+  /// Other resolve errors are retried with the [fallback resolvers](PublishOptions::fallback_resolvers), if any.
+  /// If those fail too, the service's error is returned, since publishing a v0 revision over an
+  /// existing record would be rejected.
+  pub async fn publish_value(
+    &self,
+    name: &WritableName,
+    value: &str,
+    opts: PublishOptions,
+  ) -> Result<PublishOutcome, ClientError> {
+    let mode = opts.signature_mode;
+    let key = opts
+      .idempotency_key
+      .clone()
+      .unwrap_or_else(new_idempotency_key);
+    match self.prepare_publish(name, value, opts).await? {
+      PreparedRevision::Publish(revision, previous) => {
+        let receipt = self.publish_keyed(name, &revision, mode, &key).await?;
+        Ok(PublishOutcome::Published(PublishedRevision {
+          revision,
+          receipt,
+          previous,
+        }))
+      }
+      PreparedRevision::Unchanged(current) => Ok(PublishOutcome::Unchanged(current)),
+    }
+  }
+
+  /// Does what [publish_value](Self::publish_value) does up to publishing: resolves the current
+  /// revision and builds the new one, but leaves publishing it to the caller, e.g. to publish it
+  /// to several targets with a [FanoutPublisher](crate::FanoutPublisher).
+  pub async fn prepare_publish(
+    &self,
+    name: &WritableName,
+    value: &str,
+    opts: PublishOptions,
+  ) -> Result<PreparedRevision, ClientError> {
+    let current = if let Some(revision) = opts.current_revision.clone() {
+      Some(CurrentRevision {
+        revision,
+        source: RevisionSource::Provided,
+      })
+    } else if opts.force_v0 {
+      None
     } else {
-      Err(error_from_response(res).await)
+      self
+        .resolve_current(&name.to_name(), &opts.fallback_resolvers)
+        .await?
+    };
+
+    let mut current = current;
+    if let (Some(current), Some(published)) = (&mut current, &opts.last_published) {
+      current.revision = current.revision.clone().with_validity_window_of(published);
+    }
+    let mut opts = opts;
+    if let Some(current) = &current {
+      let (validity, ttl) = opts
+        .increment_policy
+        .durations_at(&current.revision, self.inner.clock.now());
+      opts
+        .validity
+        .get_or_insert(opts.defaults.validity.unwrap_or(validity));
+      opts.ttl.get_or_insert(opts.defaults.ttl.unwrap_or(ttl));
+    }
+    if opts.validity_from_server_time {
+      match self.observed_clock_skew() {
+        // counting the validity from the server's "now" is the same as extending it by the skew
+        Some(skew) => {
+          let validity = opts
+            .validity
+            .or(opts.defaults.validity)
+            .unwrap_or_else(default_validity_duration);
+          opts.validity = Some(checked_add_duration(validity, skew).ok_or_else(|| {
+            report!(ValidityOutOfRange)
+              .attach_printable(format!("clock skew: {}s", duration_secs(&skew)))
+              .change_context(ClientError)
+          })?);
+        }
+        None => log::debug!("Clock skew unknown, counting validity from the local clock"),
+      }
+    }
+
+    let current_revision = current.as_ref().map(|current| current.revision.clone());
+    match next_revision(
+      &name.to_name(),
+      current_revision,
+      value,
+      &opts,
+      &self.inner.clock,
+    )? {
+      NextRevision::Publish(revision) => {
+        Ok(PreparedRevision::Publish(revision, current.map(Box::new)))
+      }
+      NextRevision::Unchanged(revision) => Ok(PreparedRevision::Unchanged(CurrentRevision {
+        revision,
+        source: current.map_or(RevisionSource::Service, |current| current.source),
+      })),
+    }
+  }
+
+  /// Resolves the current revision for `name`, returning `None` if the service has no record for it.
+  async fn resolve_current(
+    &self,
+    name: &Name,
+    fallback_resolvers: &[Arc<dyn Resolver>],
+  ) -> Result<Option<CurrentRevision>, ClientError> {
+    let err_report = match self.resolve(name).await {
+      Ok(revision) => {
+        return Ok(Some(CurrentRevision {
+          revision,
+          source: RevisionSource::Service,
+        }))
+      }
+      Err(err_report) => err_report,
+    };
+    if let Some(api_err) = err_report.downcast_ref::<APIError>() {
+      if api_err.status_code == 404 {
+        log::debug!("No existing record found (404)");
+        return Ok(None);
+      }
+    }
+
+    for (i, resolver) in fallback_resolvers.iter().enumerate() {
+      log::warn!("w3name resolve failed, trying fallback resolver");
+      match resolver.resolve(name).await {
+        Ok(resolved) => {
+          return Ok(Some(CurrentRevision {
+            revision: resolved.into_revision(),
+            source: RevisionSource::Fallback(i),
+          }))
+        }
+        Err(fallback_err) => log::debug!("Fallback resolver failed: {:?}", fallback_err),
+      }
     }
+    Err(err_report)
   }
 
   pub async fn resolve(&self, name: &Name) -> Result<Revision, ClientError> {
-    let mut url = self.endpoint.clone();
+    let entry_bytes = self.fetch_record(name).await?;
+    validate_record(name, &entry_bytes)
+  }
+
+  /// Like [resolve](Self::resolve), but also returns where the record came from and how long
+  /// resolving took.
+  pub async fn resolve_detailed(&self, name: &Name) -> Result<ResolvedRevision, ClientError> {
+    let started = Instant::now();
+    let revision = self.resolve(name).await?;
+    let source = SourceInfo::http(SourceKind::Service, self.endpoint().clone(), started);
+    Ok(ResolvedRevision::fetched(revision, source))
+  }
+
+  /// Resolves several names concurrently, returning one result per name, in the same order as `names`.
+  ///
+  /// At most 8 requests are in flight at once, and requests are still subject
+  /// to the client's rate limit. A failure to resolve one name doesn't affect the others.
+  pub async fn resolve_many(&self, names: &[Name]) -> Vec<Result<Revision, ClientError>> {
+    let permits = Arc::new(Semaphore::new(RESOLVE_MANY_CONCURRENCY));
+    let tasks: Vec<_> = names
+      .iter()
+      .map(|name| {
+        let client = self.clone();
+        let name = name.clone();
+        let permits = Arc::clone(&permits);
+        tokio::spawn(async move {
+          let _permit = permits.acquire_owned().await;
+          client.resolve(&name).await
+        })
+      })
+      .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+      let result = match task.await {
+        Ok(result) => result,
+        Err(join_err) => {
+          Err(report!(ClientError).attach_printable(format!("resolve task failed: {}", join_err)))
+        }
+      };
+      results.push(result);
+    }
+    results
+  }
+
+  /// Fetches the serialized IPNS record for `name`, as returned by the service.
+  ///
+  /// The record is **not** validated. Use [resolve](Self::resolve) unless you need the raw record bytes,
+  /// e.g. to store them, and validate them yourself with [validate_ipns_entry_for_name](crate::ipns::validate_ipns_entry_for_name).
+  pub async fn fetch_record(&self, name: &Name) -> Result<Vec<u8>, ClientError> {
+    match self.fetch_record_if_changed(name, None).await? {
+      Fetched::Record { bytes, .. } => Ok(bytes),
+      Fetched::NotModified => Err(
+        report!(UnexpectedAPIResponse)
+          .attach_printable("304 Not Modified for an unconditional request")
+          .change_context(ClientError),
+      ),
+    }
+  }
+
+  /// Like [fetch_record](Self::fetch_record), but if `etag` is the ETag of the record fetched
+  /// before, the service is asked to answer with `304 Not Modified` instead of the record if it
+  /// hasn't changed since.
+  pub(crate) async fn fetch_record_if_changed(
+    &self,
+    name: &Name,
+    etag: Option<&str>,
+  ) -> Result<Fetched, ClientError> {
+    let mut url = self.inner.endpoint.clone();
     url.set_path(format!("name/{}", name.to_string()).as_str());
 
     log::debug!("HTTP GET {}", url);
 
-    self.limiter.until_ready().await;
-    let res = self
-      .http
-      .get(url)
-      .send()
-      .await
-      .report()
-      .change_context(HttpError)
-      .change_context(ClientError)?;
+    self.inner.limiter.until_ready().await;
+    let mut req = HttpRequest::new(Method::GET, url).max_response_size(MAX_RESOLVE_RESPONSE_SIZE);
+    if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(etag).ok()) {
+      req = req.header(IF_NONE_MATCH, etag);
+    }
+    let res = self.send(req).await?;
 
-    log::debug!("Response status: {}", res.status());
+    log::debug!("Response status: {}", res.status);
+    self.observe_clock_skew(&res);
 
-    if res.status().is_success() {
-      parse_resolve_response(&name, res).await
+    if res.status == StatusCode::NOT_MODIFIED {
+      Ok(Fetched::NotModified)
+    } else if res.status.is_success() {
+      let etag = res
+        .headers
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(String::from);
+      let bytes = parse_resolve_response(&res)?;
+      Ok(Fetched::Record { bytes, etag })
     } else {
-      Err(error_from_response(res).await)
+      Err(error_from_response(res))
     }
   }
 }
 
+/// The result of [W3NameClient::fetch_record_if_changed].
+pub(crate) enum Fetched {
+  /// The serialized record, and its ETag if the service sent one.
+  Record {
+    bytes: Vec<u8>,
+    etag: Option<String>,
+  },
+  /// The record hasn't changed since the one with the given ETag.
+  NotModified,
+}
+
+/// `W3NameClientBuilder` creates a [W3NameClient] with tuned HTTP connection settings, e.g. for a
+/// self-hosted service behind a load balancer that supports HTTP/2.
+///
+/// Settings that aren't set use reqwest's defaults, as [W3NameClient::new] does. A client for an
+/// environment without reqwest can be built with another [transport](Self::transport); the
+/// connection settings need the `client` feature, which is on by default.
+///
+/// ## Example
+///
+/// ```rust
+/// # #[cfg(not(feature = "client"))]
+/// # fn main() {}
+/// # #[cfg(feature = "client")]
+/// # fn main() -> error_stack::Result<(), w3name::error::ClientError> {
+/// use std::time::Duration;
+/// use w3name::{Url, W3NameClient};
+///
+/// let client = W3NameClient::builder(Url::parse("http://w3name.internal:8787").unwrap())
+///   .http2_prior_knowledge()
+///   .pool_idle_timeout(Duration::from_secs(300))
+///   .tcp_keepalive(Duration::from_secs(60))
+///   .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct W3NameClientBuilder {
+  endpoint: Url,
+  #[cfg(feature = "client")]
+  http: HttpSettings,
+  debug_http: bool,
+  clock: Arc<dyn Clock>,
+  transport: Option<Arc<dyn HttpTransport>>,
+  rate_limit: NonZeroU32,
+}
+
+impl W3NameClientBuilder {
+  pub fn new(endpoint: Url) -> W3NameClientBuilder {
+    W3NameClientBuilder {
+      endpoint,
+      #[cfg(feature = "client")]
+      http: HttpSettings::default(),
+      debug_http: false,
+      clock: Arc::new(SystemClock),
+      transport: None,
+      rate_limit: RATE_LIMIT_REQUESTS,
+    }
+  }
+
+  /// Talks HTTP/2 to the service without negotiating it first, which is required for plain-text
+  /// (`http://`) HTTP/2 endpoints. The service must support HTTP/2.
+  ///
+  /// Concurrent requests, e.g. from [W3NameClient::resolve_many], are then multiplexed over a
+  /// single connection instead of opening one connection each.
+  #[cfg(feature = "client")]
+  pub fn http2_prior_knowledge(mut self) -> W3NameClientBuilder {
+    self.http.http2_prior_knowledge = true;
+    self
+  }
+
+  /// How long idle connections are kept open for reuse. reqwest's default is 90 seconds.
+  #[cfg(feature = "client")]
+  pub fn pool_idle_timeout(mut self, timeout: std::time::Duration) -> W3NameClientBuilder {
+    self.http.pool_idle_timeout = Some(timeout);
+    self
+  }
+
+  /// The maximum number of idle connections kept open to the service.
+  #[cfg(feature = "client")]
+  pub fn pool_max_idle_per_host(mut self, max: usize) -> W3NameClientBuilder {
+    self.http.pool_max_idle_per_host = Some(max);
+    self
+  }
+
+  /// Enables TCP keepalive probes on connections to the service, sent at this interval.
+  #[cfg(feature = "client")]
+  pub fn tcp_keepalive(mut self, interval: std::time::Duration) -> W3NameClientBuilder {
+    self.http.tcp_keepalive = Some(interval);
+    self
+  }
+
+  /// Fails requests that take longer than `timeout`, from connecting until the response body has
+  /// been read. By default, requests never time out.
+  #[cfg(feature = "client")]
+  pub fn timeout(mut self, timeout: std::time::Duration) -> W3NameClientBuilder {
+    self.http.timeout = Some(timeout);
+    self
+  }
+
+  /// Logs every request to the service and its response in full at debug level: method, URL,
+  /// headers, and bodies, including the base64-encoded records that are published.
+  ///
+  /// Credentials in headers and URLs, and anything that looks like a token or a private key in a
+  /// body, are redacted. This is meant for diagnosing failed requests rather than for production
+  /// use.
+  pub fn debug_http(mut self, enabled: bool) -> W3NameClientBuilder {
+    self.debug_http = enabled;
+    self
+  }
+
+  /// Sets the [Clock] that the client reads the current time from, when it counts the validity
+  /// of new revisions and measures the service's clock skew. Defaults to the [SystemClock].
+  pub fn clock(mut self, clock: Arc<dyn Clock>) -> W3NameClientBuilder {
+    self.clock = clock;
+    self
+  }
+
+  /// Sends requests with `transport` instead of reqwest. The connection settings above only apply
+  /// to the default [ReqwestTransport], so they're ignored.
+  pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> W3NameClientBuilder {
+    self.transport = Some(transport);
+    self
+  }
+
+  /// The most requests per second the client sends to the service, 30 by default. Requests over
+  /// the limit wait for their turn rather than failing.
+  ///
+  /// Raise it only for a service you run yourself, e.g. to load test it: the public service
+  /// rate-limits clients on its end too.
+  pub fn rate_limit(mut self, requests_per_second: NonZeroU32) -> W3NameClientBuilder {
+    self.rate_limit = requests_per_second;
+    self
+  }
+
+  /// Creates the client. Fails if the HTTP client can't be initialized, e.g. because the system's
+  /// TLS configuration can't be loaded, or, without the `client` feature, if no
+  /// [transport](Self::transport) was set.
+  pub fn build(self) -> Result<W3NameClient, ClientError> {
+    let transport = match self.transport {
+      Some(transport) => transport,
+      #[cfg(feature = "client")]
+      None => Arc::new(self.http.build()?),
+      #[cfg(not(feature = "client"))]
+      None => {
+        return Err(
+          report!(ClientError)
+            .attach_printable("no HTTP transport: set one, or enable the `client` feature"),
+        )
+      }
+    };
+    Ok(W3NameClient::with_transport(
+      self.endpoint,
+      transport,
+      self.debug_http,
+      self.clock,
+      self.rate_limit,
+    ))
+  }
+}
+
+/// The reqwest connection settings of a [W3NameClientBuilder].
+#[cfg(feature = "client")]
+#[derive(Clone, Debug, Default)]
+struct HttpSettings {
+  http2_prior_knowledge: bool,
+  pool_idle_timeout: Option<std::time::Duration>,
+  pool_max_idle_per_host: Option<usize>,
+  tcp_keepalive: Option<std::time::Duration>,
+  timeout: Option<std::time::Duration>,
+}
+
+#[cfg(feature = "client")]
+impl HttpSettings {
+  fn build(self) -> Result<ReqwestTransport, ClientError> {
+    let mut http = reqwest::Client::builder().tcp_keepalive(self.tcp_keepalive);
+    if self.http2_prior_knowledge {
+      http = http.http2_prior_knowledge();
+    }
+    if let Some(timeout) = self.pool_idle_timeout {
+      http = http.pool_idle_timeout(timeout);
+    }
+    if let Some(max) = self.pool_max_idle_per_host {
+      http = http.pool_max_idle_per_host(max);
+    }
+    if let Some(timeout) = self.timeout {
+      http = http.timeout(timeout);
+    }
+    let http = http
+      .build()
+      .report()
+      .change_context(HttpError)
+      .change_context(ClientError)?;
+    Ok(ReqwestTransport::new(http))
+  }
+}
+
+/// Returns the URL of the public w3name service, `https://name.web3.storage`, which
+/// [W3NameClient::default] uses.
+pub fn default_endpoint() -> Url {
+  Url::parse(DEFAULT_ENDPOINT).unwrap()
+}
+
+#[cfg(feature = "client")]
 impl Default for W3NameClient {
   fn default() -> Self {
-    let url = Url::parse(DEFAULT_ENDPOINT).unwrap();
-    Self::new(url)
+    Self::new(default_endpoint())
+  }
+}
+
+enum NextRevision {
+  Publish(Revision),
+  Unchanged(Revision),
+}
+
+/// Decides what [W3NameClient::publish_value] should do, given the `current` revision (if any).
+fn next_revision(
+  name: &Name,
+  current: Option<Revision>,
+  value: &str,
+  opts: &PublishOptions,
+  clock: &Arc<dyn Clock>,
+) -> Result<NextRevision, ClientError> {
+  let mut builder = match current {
+    Some(current)
+      if opts.skip_unchanged
+        && Value::parse(current.value()).is_equivalent(&Value::parse(value)) =>
+    {
+      log::debug!("Value is unchanged at sequence {}", current.sequence());
+      return Ok(NextRevision::Unchanged(current));
+    }
+    Some(current) => RevisionBuilder::next(&current, value),
+    None => RevisionBuilder::new(name, value),
   }
+  .clock(Arc::clone(clock));
+  builder = builder.with_defaults(&opts.defaults);
+  if let Some(valid_until) = opts.valid_until {
+    builder = builder.validity_at(valid_until);
+  } else if let Some(validity) = opts.validity {
+    builder = builder.validity_for(validity);
+  }
+  if let Some(ttl) = opts.ttl {
+    builder = builder.ttl(ttl);
+  }
+  let revision = builder.build().change_context(ClientError)?;
+  Ok(NextRevision::Publish(revision))
 }
 
-async fn parse_resolve_response(name: &Name, res: Response) -> Result<Revision, ClientError> {
-  let r = res
-    .json::<ResolveResponse>()
-    .await
+/// Extracts the record from a successful resolve response: a JSON object with the base64-encoded
+/// record in its `record` field.
+///
+/// Responses that can't be one, like the HTML page of a captive portal or an empty body, are
+/// rejected with [UnexpectedAPIResponse] before they're parsed, and the errors quote the content
+/// type and the start of the body, so it's easy to see what answered instead of the service.
+fn parse_resolve_response(res: &HttpResponse) -> Result<Vec<u8>, ClientError> {
+  let content_type = res
+    .headers
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok());
+  let essence = content_type
+    .and_then(|content_type| content_type.split(';').next())
+    .unwrap_or_default()
+    .trim()
+    .to_ascii_lowercase();
+  let describe = || {
+    format!(
+      "content type: {}, body: {}",
+      content_type.map_or("none".to_string(), |content_type| format!(
+        "{:?}",
+        content_type
+      )),
+      preview(&res.body)
+    )
+  };
+  let unexpected = |message: &str| {
+    report!(UnexpectedAPIResponse)
+      .attach_printable(message.to_string())
+      .attach_printable(describe())
+      .change_context(ClientError)
+  };
+
+  let looks_like_html = res.body.trim_ascii_start().starts_with(b"<");
+  if essence == "text/html" || essence == "application/xhtml+xml" || looks_like_html {
+    return Err(unexpected(
+      "the service responded with a web page instead of a record; a captive portal or a proxy may be intercepting requests",
+    ));
+  }
+  if res.body.is_empty() {
+    return Err(unexpected("the service responded with an empty body"));
+  }
+  if !essence.is_empty() && essence != "application/json" && !essence.ends_with("+json") {
+    log::debug!(
+      "Resolve response has content type {:?}, expected JSON",
+      essence
+    );
+  }
+
+  let r: ResolveResponse = serde_json::from_slice(&res.body)
     .report()
+    .change_context(UnexpectedAPIResponse)
+    .attach_printable("the response isn't a JSON object with a record")
+    .attach_printable_lazy(describe)
     .change_context(ClientError)?;
-  let entry_bytes = base64::decode(r.record)
+  let record = base64::decode(r.record)
     .report()
+    .change_context(UnexpectedAPIResponse)
+    .attach_printable("the response's record isn't valid base64")
+    .attach_printable_lazy(describe)
     .change_context(ClientError)?;
-  let entry = deserialize_ipns_entry(&entry_bytes).change_context(ClientError)?;
-  validate_ipns_entry(&entry, name.public_key()).change_context(ClientError)?;
+  if record.len() > MAX_RECORD_SIZE {
+    return Err(record_too_large(MAX_RECORD_SIZE));
+  }
+  Ok(record)
+}
 
-  let revision = revision_from_ipns_entry(&entry, name).change_context(ClientError)?;
-  Ok(revision)
+/// Validates the `entry_bytes` that the service returned for `name`.
+pub(crate) fn validate_record(name: &Name, entry_bytes: &[u8]) -> Result<Revision, ClientError> {
+  Revision::try_from_record_bytes(entry_bytes, name)
+    .change_context(ClientError)
+    .attach_printable_lazy(|| {
+      format!(
+        "the service's record is {} bytes long, starting with {}",
+        entry_bytes.len(),
+        preview(entry_bytes)
+      )
+    })
+}
+
+/// Quotes the start of `body`, lossily decoded as UTF-8, for error messages.
+fn preview(body: &[u8]) -> String {
+  let text = String::from_utf8_lossy(&body[..body.len().min(BODY_PREVIEW_LEN)]);
+  if body.len() > BODY_PREVIEW_LEN {
+    format!("{:?}...", text)
+  } else {
+    format!("{:?}", text)
+  }
+}
+
+fn record_too_large(limit: usize) -> Report<ClientError> {
+  report!(RecordTooLarge)
+    .attach_printable(format!("limit: {} bytes", limit))
+    .change_context(ClientError)
+}
+
+/// Returns a key for a new publish: a random (v4) UUID, like `1b4e28ba-2fa1-41d2-883f-0016d3cca427`.
+pub(crate) fn new_idempotency_key() -> String {
+  uuid::Uuid::new_v4().to_string()
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -134,16 +1169,832 @@ struct ResolveResponse {
   record: String,
 }
 
-async fn error_from_response(res: Response) -> Report<ClientError> {
-  let status = res.status();
-  match res.json::<APIErrorResponse>().await {
+fn error_from_response(res: HttpResponse) -> Report<ClientError> {
+  match serde_json::from_slice::<APIErrorResponse>(&res.body) {
     Ok(json) => report!(APIError {
       message: json.message,
-      status_code: status
+      status_code: res.status
     })
     .change_context(ClientError),
     Err(e) => report!(e)
       .change_context(UnexpectedAPIResponse)
+      .attach_printable(format!(
+        "status: {}, body: {}",
+        res.status,
+        preview(&res.body)
+      ))
       .change_context(ClientError),
   }
 }
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+  use super::*;
+  use crate::{error::NetworkCause, ManualClock};
+  use crate::{
+    ipns::{revision_to_ipns_entry, serialize_ipns_entry},
+    mock_service::MockService,
+  };
+  use async_trait::async_trait;
+  use w3name_core::internal::{parse_rfc3339, zero};
+
+  const VALUE: &str = "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+
+  fn skip_unchanged() -> PublishOptions {
+    PublishOptions {
+      skip_unchanged: true,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn next_revision_unchanged() {
+    let name = WritableName::new().to_name();
+    let current = Revision::v0(&name, VALUE).increment(VALUE);
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
+    let outcome = next_revision(
+      &name,
+      Some(current.clone()),
+      &format!("{}/", VALUE),
+      &skip_unchanged(),
+      &clock,
+    )
+    .unwrap();
+    assert!(matches!(&outcome, NextRevision::Unchanged(r) if r.sequence() == 1));
+
+    // without skip_unchanged, the same value is published again
+    let outcome = next_revision(
+      &name,
+      Some(current),
+      VALUE,
+      &PublishOptions::default(),
+      &clock,
+    )
+    .unwrap();
+    assert!(matches!(&outcome, NextRevision::Publish(r) if r.sequence() == 2));
+  }
+
+  #[test]
+  fn next_revision_changed() {
+    let name = WritableName::new().to_name();
+    let current = Revision::v0(&name, VALUE);
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
+    let outcome = next_revision(
+      &name,
+      Some(current),
+      "/ipfs/other",
+      &skip_unchanged(),
+      &clock,
+    )
+    .unwrap();
+    assert!(
+      matches!(&outcome, NextRevision::Publish(r) if r.sequence() == 1 && r.value() == "/ipfs/other")
+    );
+  }
+
+  #[test]
+  fn next_revision_no_existing_record() {
+    let name = WritableName::new().to_name();
+    let now = parse_rfc3339("2024-01-01T00:00:00Z").unwrap();
+    let clock: Arc<dyn Clock> = Arc::new(ManualClock::new(now));
+    let outcome = next_revision(&name, None, VALUE, &skip_unchanged(), &clock).unwrap();
+    assert!(matches!(&outcome, NextRevision::Publish(r) if r.sequence() == 0));
+    // the validity is counted from the client's clock
+    assert!(
+      matches!(&outcome, NextRevision::Publish(r) if *r.validity() == now + default_validity_duration())
+    );
+  }
+
+  /// A resolver that always returns the same revision.
+  struct StaticResolver(Revision);
+
+  #[async_trait]
+  impl Resolver for StaticResolver {
+    async fn resolve(&self, _name: &Name) -> Result<ResolvedRevision, ClientError> {
+      let source = SourceInfo {
+        kind: SourceKind::Cache,
+        url: None,
+        elapsed: std::time::Duration::ZERO,
+        expiry_checked: false,
+      };
+      Ok(ResolvedRevision::fetched(self.0.clone(), source))
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_value_404_publishes_v0() {
+    for service in MockService::both() {
+      let client = service.client();
+      let name = WritableName::new();
+
+      let outcome = client
+        .publish_value(&name, VALUE, PublishOptions::default())
+        .await
+        .unwrap();
+      assert!(matches!(&outcome, PublishOutcome::Published(r) if r.sequence() == 0));
+      assert!(outcome.previous().is_none());
+      assert!(service.record(&name.to_name()).is_some());
+      assert_eq!(
+        client.resolve(&name.to_name()).await.unwrap().value(),
+        VALUE
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_value_increments_existing() {
+    for service in MockService::both() {
+      let client = service.client();
+      let name = WritableName::new();
+
+      client
+        .publish_value(&name, "/ipfs/first", PublishOptions::default())
+        .await
+        .unwrap();
+      let outcome = client
+        .publish_value(&name, VALUE, PublishOptions::default())
+        .await
+        .unwrap();
+      assert!(matches!(&outcome, PublishOutcome::Published(r) if r.sequence() == 1));
+      assert_eq!(outcome.previous().unwrap().source, RevisionSource::Service);
+
+      let resolved = client.resolve(&name.to_name()).await.unwrap();
+      assert_eq!(resolved.sequence(), 1);
+      assert_eq!(resolved.value(), VALUE);
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_value_resolve_failure() {
+    for service in MockService::both() {
+      let client = service.client();
+      let name = WritableName::new();
+      service.fail_resolves(StatusCode::INTERNAL_SERVER_ERROR);
+
+      let err = client
+        .publish_value(&name, VALUE, PublishOptions::default())
+        .await
+        .unwrap_err();
+      let api_err = err.downcast_ref::<APIError>().unwrap();
+      assert_eq!(api_err.status_code, StatusCode::INTERNAL_SERVER_ERROR);
+      assert!(service.record(&name.to_name()).is_none());
+
+      // a fallback resolver supplies the current revision instead
+      let current = Revision::v0(&name.to_name(), "/ipfs/first").increment("/ipfs/second");
+      let opts = PublishOptions {
+        fallback_resolvers: vec![Arc::new(StaticResolver(current))],
+        ..Default::default()
+      };
+      let outcome = client.publish_value(&name, VALUE, opts).await.unwrap();
+      assert!(matches!(&outcome, PublishOutcome::Published(r) if r.sequence() == 2));
+      let previous = outcome.previous().unwrap();
+      assert_eq!(previous.source, RevisionSource::Fallback(0));
+      assert_eq!(previous.revision.sequence(), 1);
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_value_force_v0_and_ttl() {
+    for service in MockService::both() {
+      let client = service.client();
+      let name = WritableName::new();
+      service.fail_resolves(StatusCode::INTERNAL_SERVER_ERROR);
+
+      let opts = PublishOptions {
+        force_v0: true,
+        ttl: Some(Duration::minutes(5)),
+        ..Default::default()
+      };
+      let outcome = client.publish_value(&name, VALUE, opts).await.unwrap();
+      assert_eq!(outcome.revision().sequence(), 0);
+      assert_eq!(outcome.revision().ttl(), Duration::minutes(5));
+    }
+  }
+
+  #[tokio::test]
+  async fn resolve_rejects_record_for_other_name() {
+    for service in MockService::both() {
+      let client = service.client();
+      let requested = WritableName::new().to_name();
+
+      // a valid record, but signed by (and embedding the key of) a different name
+      let other = WritableName::new();
+      let revision = Revision::v0(&other.to_name(), VALUE);
+      let mut entry = revision_to_ipns_entry(&revision, other.keypair()).unwrap();
+      entry.pub_key = other.to_name().public_key_protobuf_bytes();
+      let record = serialize_ipns_entry(&entry).unwrap();
+      service.set_record(&requested, base64::encode(record));
+
+      let err = client.resolve(&requested).await.unwrap_err();
+      assert!(err.contains::<NameMismatch>());
+    }
+  }
+
+  #[tokio::test]
+  async fn resolve_many_keeps_order_and_failures() {
+    for service in MockService::both() {
+      let client = service.client();
+      let published: Vec<_> = (0..10).map(|_| WritableName::new()).collect();
+      for (i, name) in published.iter().enumerate() {
+        client
+          .publish(name, &Revision::v0(&name.to_name(), format!("/ipfs/{}", i)))
+          .await
+          .unwrap();
+      }
+
+      let mut names: Vec<_> = published.iter().map(WritableName::to_name).collect();
+      names.insert(3, WritableName::new().to_name());
+      let results = client.resolve_many(&names).await;
+      assert_eq!(results.len(), 11);
+      let api_err = results[3].as_ref().unwrap_err().downcast_ref::<APIError>();
+      assert_eq!(api_err.unwrap().status_code, StatusCode::NOT_FOUND);
+      let values: Vec<_> = results
+        .iter()
+        .filter_map(|r| r.as_ref().ok())
+        .map(|r| r.value().to_string())
+        .collect();
+      let expected: Vec<_> = (0..10).map(|i| format!("/ipfs/{}", i)).collect();
+      assert_eq!(values, expected);
+    }
+  }
+
+  #[tokio::test]
+  async fn observes_clock_skew() {
+    let service = MockService::start();
+    service.set_clock_offset(Duration::minutes(10));
+    let client = service.client();
+    assert_eq!(client.observed_clock_skew(), None);
+
+    // any response counts, even a 404
+    let name = WritableName::new();
+    assert!(client.resolve(&name.to_name()).await.is_err());
+    let skew = client.observed_clock_skew().unwrap();
+    assert!(duration_secs(&(skew - Duration::minutes(10))).abs() <= 2);
+
+    // only the first contact is measured
+    service.set_clock_offset(zero());
+    assert!(client.resolve(&name.to_name()).await.is_err());
+    assert_eq!(client.observed_clock_skew(), Some(skew));
+
+    // a real Date header from hyper shows (almost) no skew
+    let service = MockService::start();
+    let client = service.client();
+    assert!(client.resolve(&name.to_name()).await.is_err());
+    assert!(duration_secs(&client.observed_clock_skew().unwrap()).abs() <= 2);
+  }
+
+  #[tokio::test]
+  async fn valid_until_is_exact() {
+    let service = MockService::in_process();
+    service.set_clock_offset(Duration::hours(-2));
+    let client = service.client();
+    let name = WritableName::new();
+    client
+      .publish_value(&name, VALUE, Default::default())
+      .await
+      .unwrap();
+
+    // neither the current revision's window, nor `validity`, nor the server's clock move it
+    let valid_until = parse_rfc3339("2031-12-31T00:00:00.123456789Z").unwrap();
+    let opts = PublishOptions {
+      validity: Some(Duration::days(1)),
+      valid_until: Some(valid_until),
+      increment_policy: IncrementPolicy::PreserveDurations,
+      validity_from_server_time: true,
+      ..Default::default()
+    };
+    let outcome = client.publish_value(&name, VALUE, opts).await.unwrap();
+    assert_eq!(*outcome.revision().validity(), valid_until);
+    let resolved = client.resolve(&name.to_name()).await.unwrap();
+    assert_eq!(resolved.validity_string(), "2031-12-31T00:00:00.123456789Z");
+  }
+
+  #[tokio::test]
+  async fn validity_from_server_time() {
+    for service in MockService::both() {
+      service.set_clock_offset(Duration::hours(-2));
+      let client = service.client();
+      let name = WritableName::new();
+      let opts = PublishOptions {
+        validity: Some(Duration::days(1)),
+        validity_from_server_time: true,
+        ..Default::default()
+      };
+
+      let outcome = client.publish_value(&name, VALUE, opts).await.unwrap();
+      let expected = SystemClock.now() + Duration::hours(22);
+      let validity = *outcome.revision().validity();
+      assert!(duration_secs(&(validity - expected)).abs() <= 5);
+
+      // without the option, the local clock is used
+      let outcome = client
+        .publish_value(
+          &name,
+          VALUE,
+          PublishOptions {
+            validity: Some(Duration::days(1)),
+            ..Default::default()
+          },
+        )
+        .await
+        .unwrap();
+      let expected = SystemClock.now() + Duration::days(1);
+      assert!(duration_secs(&(*outcome.revision().validity() - expected)).abs() <= 5);
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_value_increment_policy() {
+    for service in MockService::both() {
+      // ahead of the real time, so the records aren't expired when they're resolved
+      let start = parse_rfc3339("2040-01-01T00:00:00Z").unwrap();
+      let clock = Arc::new(ManualClock::new(start));
+      let client = service
+        .client_builder()
+        .clock(clock.clone())
+        .build()
+        .unwrap();
+      let name = WritableName::new();
+      let first = PublishOptions {
+        validity: Some(Duration::weeks(1)),
+        ttl: Some(Duration::minutes(5)),
+        ..Default::default()
+      };
+      let outcome = client.publish_value(&name, VALUE, first).await.unwrap();
+      let published = outcome.revision().clone();
+
+      // a few days on, the policy still recognizes the week-long window, given the revision
+      // that was published, since the record doesn't say; without it, the record's end of life
+      // is kept
+      clock.advance(Duration::days(3));
+      let preserve = || PublishOptions {
+        increment_policy: IncrementPolicy::PreserveDurations,
+        ..Default::default()
+      };
+      let prepared = client
+        .prepare_publish(&name, "/ipfs/second", preserve())
+        .await
+        .unwrap();
+      let PreparedRevision::Publish(revision, _) = prepared else {
+        panic!("the value changed");
+      };
+      assert_eq!(*revision.validity(), start + Duration::weeks(1));
+      let outcome = client
+        .publish_value(
+          &name,
+          "/ipfs/second",
+          PublishOptions {
+            last_published: Some(published),
+            ..preserve()
+          },
+        )
+        .await
+        .unwrap();
+      let revision = outcome.revision();
+      assert_eq!(revision.ttl(), Duration::minutes(5));
+      assert_eq!(
+        *revision.validity(),
+        start + Duration::days(3) + Duration::weeks(1)
+      );
+
+      // explicit options win over the policy
+      let explicit = PublishOptions {
+        validity: Some(Duration::days(2)),
+        increment_policy: IncrementPolicy::PreserveDurations,
+        ..Default::default()
+      };
+      let outcome = client
+        .publish_value(&name, "/ipfs/third", explicit)
+        .await
+        .unwrap();
+      let revision = outcome.revision();
+      assert_eq!(revision.ttl(), Duration::minutes(5));
+      assert_eq!(
+        *revision.validity(),
+        start + Duration::days(3) + Duration::days(2)
+      );
+
+      // the default policy resets the validity window
+      let outcome = client
+        .publish_value(&name, "/ipfs/fourth", PublishOptions::default())
+        .await
+        .unwrap();
+      assert_eq!(
+        *outcome.revision().validity(),
+        start + Duration::days(3) + Duration::weeks(52)
+      );
+
+      // configured defaults replace the policy, and the previous TTL
+      let defaults = PublishOptions {
+        defaults: Defaults {
+          validity: Some(Duration::days(30)),
+          ttl: Some(Duration::minutes(10)),
+        },
+        increment_policy: IncrementPolicy::PreserveDurations,
+        ..Default::default()
+      };
+      let outcome = client
+        .publish_value(&name, "/ipfs/fifth", defaults.clone())
+        .await
+        .unwrap();
+      let revision = outcome.revision();
+      assert_eq!(revision.ttl(), Duration::minutes(10));
+      assert_eq!(
+        *revision.validity(),
+        start + Duration::days(3) + Duration::days(30)
+      );
+      let outcome = client
+        .publish_value(
+          &WritableName::new(),
+          VALUE,
+          PublishOptions {
+            ttl: Some(Duration::minutes(1)),
+            ..defaults
+          },
+        )
+        .await
+        .unwrap();
+      assert_eq!(outcome.revision().ttl(), Duration::minutes(1));
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_value_with_current_revision() {
+    for service in MockService::both() {
+      let client = service.client();
+      let name = WritableName::new();
+      let current = Revision::v0(&name.to_name(), VALUE).increment(VALUE);
+
+      // resolving would fail, so the given revision must be used as is
+      service.fail_resolves(StatusCode::INTERNAL_SERVER_ERROR);
+      let opts = PublishOptions {
+        current_revision: Some(current),
+        ..Default::default()
+      };
+      let outcome = client
+        .publish_value(&name, "/ipfs/next", opts)
+        .await
+        .unwrap();
+      assert_eq!(outcome.revision().sequence(), 2);
+      let previous = outcome.previous().unwrap();
+      assert_eq!(previous.source, RevisionSource::Provided);
+      assert_eq!(previous.revision.sequence(), 1);
+      assert!(service.record(&name.to_name()).is_some());
+    }
+  }
+
+  #[tokio::test]
+  async fn resolve_stops_reading_oversized_responses() {
+    let service = MockService::start();
+    service.serve_garbage(100 * 1024 * 1024);
+
+    let err = service
+      .client()
+      .resolve(&WritableName::new().to_name())
+      .await
+      .unwrap_err();
+    assert!(err.contains::<RecordTooLarge>());
+    assert!(service.garbage_sent() < 10 * 1024 * 1024);
+  }
+
+  #[tokio::test]
+  async fn resolve_explains_unexpected_responses() {
+    let service = MockService::in_process();
+    let client = service.client();
+    let key = WritableName::new();
+    let name = key.to_name();
+    let resolve_error = |content_type: Option<&str>, body: &[u8]| {
+      service.serve_resolve_response(content_type, body);
+      let client = client.clone();
+      let name = name.clone();
+      async move {
+        let err = client.resolve(&name).await.unwrap_err();
+        (
+          err.contains::<UnexpectedAPIResponse>(),
+          format!("{:?}", err),
+        )
+      }
+    };
+
+    // a captive portal's login page, with or without saying it's HTML
+    let portal = format!(
+      "<!DOCTYPE html><html><head><title>Hotel Wi-Fi login</title></head><body>{}</body></html>",
+      "x".repeat(200)
+    );
+    for content_type in [Some("text/html; charset=utf-8"), None] {
+      let (unexpected, message) = resolve_error(content_type, portal.as_bytes()).await;
+      assert!(unexpected, "{}", message);
+      assert!(message.contains("captive portal"), "{}", message);
+      assert!(message.contains("Hotel Wi-Fi login"), "{}", message);
+      // only the start of the body is quoted
+      assert!(!message.contains(&"x".repeat(150)), "{}", message);
+    }
+    let (_, message) = resolve_error(Some("text/html"), portal.as_bytes()).await;
+    assert!(
+      message.contains(r#"content type: "text/html""#),
+      "{}",
+      message
+    );
+
+    let (unexpected, message) = resolve_error(Some("application/json"), b"").await;
+    assert!(unexpected, "{}", message);
+    assert!(message.contains("empty body"), "{}", message);
+
+    // a response cut short
+    let record = Revision::v0(&name, VALUE).to_signed_bytes(&key).unwrap();
+    let full = format!(r#"{{"record":"{}"}}"#, base64::encode(&record));
+    let (unexpected, message) =
+      resolve_error(Some("application/json"), &full.as_bytes()[..20]).await;
+    assert!(unexpected, "{}", message);
+    assert!(
+      message.contains("isn't a JSON object with a record"),
+      "{}",
+      message
+    );
+    assert!(message.contains(r#"body: "{\"record\":\"#), "{}", message);
+
+    // a record cut short
+    let truncated = format!(
+      r#"{{"record":"{}"}}"#,
+      base64::encode(&record[..record.len() / 2])
+    );
+    let (_, message) = resolve_error(Some("application/json"), truncated.as_bytes()).await;
+    let expected = format!("the service's record is {} bytes long", record.len() / 2);
+    assert!(message.contains(&expected), "{}", message);
+
+    // while the real thing resolves, whatever the content type
+    for content_type in [Some("application/json"), Some("text/plain"), None] {
+      service.serve_resolve_response(content_type, full.as_bytes());
+      assert_eq!(client.resolve(&name).await.unwrap().value(), VALUE);
+    }
+  }
+
+  #[test]
+  fn client_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<W3NameClient>();
+  }
+
+  #[tokio::test]
+  async fn clones_share_connection_pool() {
+    let service = MockService::start();
+    let client = service.client();
+    let clone = client.clone();
+    let name = WritableName::new();
+
+    client
+      .publish_value(&name, VALUE, PublishOptions::default())
+      .await
+      .unwrap();
+    clone.resolve(&name.to_name()).await.unwrap();
+    client.resolve(&name.to_name()).await.unwrap();
+    assert_eq!(service.connections(), 1);
+  }
+
+  async fn resolve_many_connections(client: W3NameClient, service: &MockService) -> usize {
+    let names: Vec<_> = (0..16).map(|_| WritableName::new()).collect();
+    for name in &names {
+      client
+        .publish(name, &Revision::v0(&name.to_name(), VALUE))
+        .await
+        .unwrap();
+    }
+    let before = service.connections();
+    let names: Vec<_> = names.iter().map(WritableName::to_name).collect();
+    for result in client.resolve_many(&names).await {
+      assert_eq!(result.unwrap().value(), VALUE);
+    }
+    service.connections() - before
+  }
+
+  #[tokio::test]
+  async fn http2_multiplexes_resolve_many() {
+    let service = MockService::start();
+    let client = W3NameClient::builder(service.url())
+      .http2_prior_knowledge()
+      .pool_max_idle_per_host(1)
+      .pool_idle_timeout(std::time::Duration::from_secs(300))
+      .tcp_keepalive(std::time::Duration::from_secs(60))
+      .build()
+      .unwrap();
+    // the connection opened for publishing carries every resolve
+    assert_eq!(resolve_many_connections(client, &service).await, 0);
+    assert_eq!(service.connections(), 1);
+
+    // over HTTP/1, concurrent resolves need connections of their own
+    let service = MockService::start();
+    let opened = resolve_many_connections(service.client(), &service).await;
+    assert!(opened <= RESOLVE_MANY_CONCURRENCY, "{}", opened);
+  }
+
+  #[tokio::test]
+  async fn network_causes() {
+    let name = WritableName::new().to_name();
+
+    // nothing listens on a port once its listener is closed
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap();
+    let client = W3NameClient::new(Url::parse(&format!("http://{}", addr)).unwrap());
+    let err = client.resolve(&name).await.unwrap_err();
+    assert!(err.contains::<HttpError>());
+    assert_eq!(
+      err.downcast_ref::<NetworkCause>(),
+      Some(&NetworkCause::ConnectRefused)
+    );
+
+    // a listener that never accepts lets the connection through, but never responds
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+    let client = W3NameClient::builder(url)
+      .timeout(std::time::Duration::from_millis(200))
+      .build()
+      .unwrap();
+    let err = client.resolve(&name).await.unwrap_err();
+    assert_eq!(
+      err.downcast_ref::<NetworkCause>(),
+      Some(&NetworkCause::Timeout)
+    );
+    assert!(format!("{:?}", err).contains("network cause: timed out"));
+  }
+
+  #[tokio::test]
+  async fn publish_receipt() {
+    for service in MockService::both() {
+      let name = WritableName::new();
+      let revision = Revision::v0(&name.to_name(), VALUE);
+
+      let receipt = service
+        .client()
+        .publish_with_receipt(&name, &revision)
+        .await
+        .unwrap();
+      assert_eq!(receipt.status, StatusCode::ACCEPTED);
+      assert_eq!(receipt.id(), Some(name.to_string().as_str()));
+      assert_eq!(
+        receipt.headers.get("content-type").unwrap(),
+        "application/json"
+      );
+      assert_eq!(
+        base64::encode(&receipt.record),
+        service.record(&name.to_name()).unwrap()
+      );
+      assert_eq!(
+        Revision::from_signed_bytes(&name.to_name(), &receipt.record).unwrap(),
+        revision
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_falls_back_to_hybrid_records() {
+    for service in MockService::both() {
+      service.reject_v2_only();
+      let client = service.client();
+      let name = WritableName::new();
+      let is_hybrid = |record: &[u8]| {
+        let entry = crate::ipns::deserialize_ipns_entry(record).unwrap();
+        crate::ipns::signature_info(&entry).is_hybrid()
+      };
+
+      // the V2-only record is rejected, and the hybrid one accepted
+      let revision = Revision::v0(&name.to_name(), VALUE);
+      let receipt = client.publish_with_receipt(&name, &revision).await.unwrap();
+      assert_eq!(service.publishes(), 2);
+      assert!(is_hybrid(&receipt.record));
+      assert!(client.uses_hybrid_records());
+      assert_eq!(client.resolve(&name.to_name()).await.unwrap(), revision);
+
+      // which is remembered, also by clones
+      let revision = revision.increment("/ipfs/hybrid");
+      let receipt = client
+        .clone()
+        .publish_with_receipt(&name, &revision)
+        .await
+        .unwrap();
+      assert_eq!(service.publishes(), 3);
+      assert!(is_hybrid(&receipt.record));
+
+      // the other modes never retry
+      let err = client
+        .publish_with_mode(&name, &revision, SignatureMode::V2Only)
+        .await
+        .unwrap_err();
+      assert_eq!(err.downcast_ref::<APIError>().unwrap().status_code, 400);
+      assert_eq!(service.publishes(), 4);
+      let opts = PublishOptions {
+        signature_mode: SignatureMode::Hybrid,
+        ..Default::default()
+      };
+      let fresh = service.client();
+      fresh.publish_value(&name, VALUE, opts).await.unwrap();
+      assert_eq!(service.publishes(), 5);
+      assert!(!fresh.uses_hybrid_records());
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_sends_idempotency_keys() {
+    let service = MockService::in_process();
+    service.reject_v2_only();
+    let client = service.client();
+    let name = WritableName::new();
+
+    // the hybrid retry of a rejected V2-only record uploads a different record, so it has a
+    // different key, which a service that replays the 400 for the first key would otherwise do
+    let revision = Revision::v0(&name.to_name(), VALUE);
+    let receipt = client.publish_with_receipt(&name, &revision).await.unwrap();
+    let keys = service.idempotency_keys();
+    assert_eq!(keys.len(), 2);
+    let rejected = keys[0].clone().unwrap();
+    assert_eq!(rejected.len(), 36);
+    let key = keys[1].clone().unwrap();
+    assert_eq!(key.len(), 36);
+    assert_ne!(key, rejected);
+    assert_eq!(receipt.idempotency_key, Some(key.clone()));
+
+    // a new publish has a new key
+    let revision = revision.increment("/ipfs/next");
+    let receipt = client.publish_with_receipt(&name, &revision).await.unwrap();
+    let next = receipt.idempotency_key.unwrap();
+    assert_ne!(next, key);
+    assert_eq!(service.idempotency_keys()[2].as_ref(), Some(&next));
+
+    // a key can be given, e.g. to retry a publish from another process
+    let opts = || PublishOptions {
+      current_revision: Some(revision.clone()),
+      valid_until: Some(*revision.validity()),
+      idempotency_key: Some(key.clone()),
+      ..Default::default()
+    };
+    for _ in 0..2 {
+      let outcome = client.publish_value(&name, "/ipfs/third", opts()).await;
+      let receipt = outcome.unwrap().receipt().cloned().unwrap();
+      assert_eq!(receipt.idempotency_key, Some(key.clone()));
+    }
+    assert_eq!(
+      service.idempotency_keys()[3..],
+      [Some(key.clone()), Some(key.clone())]
+    );
+
+    // but it must be a valid header value
+    let opts = PublishOptions {
+      idempotency_key: Some("line\nbreak".to_string()),
+      ..opts()
+    };
+    assert!(client
+      .publish_value(&name, "/ipfs/fourth", opts)
+      .await
+      .is_err());
+    assert_eq!(service.publishes(), 5);
+  }
+
+  #[tokio::test]
+  async fn debug_http_keeps_responses_intact() {
+    for service in MockService::both() {
+      let client = service.client_builder().debug_http(true).build().unwrap();
+      let name = WritableName::new();
+
+      client
+        .publish_value(&name, VALUE, PublishOptions::default())
+        .await
+        .unwrap();
+      let revision = client.resolve(&name.to_name()).await.unwrap();
+      assert_eq!(revision.value(), VALUE);
+
+      // error responses are still parsed after they've been logged
+      service.fail_resolves(StatusCode::BAD_REQUEST);
+      let err = client.resolve(&name.to_name()).await.unwrap_err();
+      let api_err = err.downcast_ref::<APIError>().unwrap();
+      assert_eq!(api_err.status_code, StatusCode::BAD_REQUEST);
+    }
+  }
+
+  #[tokio::test]
+  async fn publish_checks_names() {
+    let service = MockService::start();
+    let client = service.client();
+    let name = WritableName::new();
+
+    // a revision for another name is refused before anything is sent
+    let other = WritableName::new().to_name();
+    let err = client
+      .publish(&name, &Revision::v0(&other, VALUE))
+      .await
+      .unwrap_err();
+    assert!(err.contains::<NameMismatch>());
+    assert_eq!(service.connections(), 0);
+
+    // the service acknowledging a different name fails the publish
+    service.set_publish_id(&other.to_string());
+    let err = client
+      .publish(&name, &Revision::v0(&name.to_name(), VALUE))
+      .await
+      .unwrap_err();
+    assert!(err.contains::<NameMismatch>());
+    assert!(format!("{:?}", err).contains(&format!("the service acknowledged {}", other)));
+  }
+}
@@ -0,0 +1,152 @@
+//! Decompression of record responses.
+//!
+//! Record fetches ask for an uncompressed body (`Accept-Encoding: identity`), but some gateways
+//! compress records anyway, with or without saying so in `Content-Encoding`. Depending on the
+//! HTTP client, such a body may or may not have been decompressed by the time it's read, so
+//! [decode] decompresses whatever is still compressed, before the body's size is checked and the
+//! record is decoded.
+
+use std::io::Read;
+
+use error_stack::{report, IntoReport, Result, ResultExt};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use http::header::{HeaderMap, CONTENT_ENCODING};
+
+use crate::error::{ClientError, ContentEncodingError, RecordTooLarge};
+
+/// The first two bytes of a gzip stream. No protobuf message can start with them, since `0x1f`
+/// would be field 3 with the invalid wire type 7.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Returns `body` decompressed as its `Content-Encoding` header says, or as a gzip stream if it
+/// starts like one and the header is missing.
+///
+/// Fails with [RecordTooLarge] if the decompressed body is longer than `limit`, without
+/// decompressing more than that, and with [ContentEncodingError] if the encoding isn't gzip or
+/// deflate, or the body isn't valid for it.
+pub(crate) fn decode(
+  headers: &HeaderMap,
+  body: Vec<u8>,
+  limit: usize,
+) -> Result<Vec<u8>, ClientError> {
+  let encoding = headers
+    .get(CONTENT_ENCODING)
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default()
+    .trim()
+    .to_ascii_lowercase();
+  let decoded = match encoding.as_str() {
+    "gzip" | "x-gzip" => inflate(GzDecoder::new(&body[..]), limit),
+    // "deflate" is meant to be a zlib stream, but some servers send raw deflate data
+    "deflate" if is_zlib(&body) => inflate(ZlibDecoder::new(&body[..]), limit),
+    "deflate" => inflate(DeflateDecoder::new(&body[..]), limit),
+    "" | "identity" if body.starts_with(&GZIP_MAGIC) => {
+      log::debug!("Decompressing a gzip response body without a Content-Encoding header");
+      inflate(GzDecoder::new(&body[..]), limit)
+    }
+    "" | "identity" => return Ok(body),
+    _ => Err(
+      report!(ContentEncodingError)
+        .attach_printable(format!("unsupported content encoding {:?}", encoding)),
+    ),
+  };
+  decoded.change_context(ClientError)
+}
+
+/// Reads `decoder` to the end, failing once it has produced more than `limit` bytes, so a small
+/// compressed body can't expand into an unbounded one.
+fn inflate(decoder: impl Read, limit: usize) -> Result<Vec<u8>, ContentEncodingError> {
+  let mut decoded = vec![];
+  decoder
+    .take(limit as u64 + 1)
+    .read_to_end(&mut decoded)
+    .report()
+    .change_context(ContentEncodingError)?;
+  if decoded.len() > limit {
+    return Err(
+      report!(RecordTooLarge)
+        .attach_printable(format!("limit: {} bytes, after decompression", limit))
+        .change_context(ContentEncodingError),
+    );
+  }
+  Ok(decoded)
+}
+
+/// Whether `body` starts with a zlib header: deflate compression, and a valid header checksum.
+fn is_zlib(body: &[u8]) -> bool {
+  match body {
+    [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use flate2::{
+    write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+    Compression,
+  };
+  use std::io::Write;
+
+  fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+  }
+
+  fn encoded(encoding: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_ENCODING, encoding.parse().unwrap());
+    headers
+  }
+
+  #[test]
+  fn decodes_compressed_bodies() {
+    let record = b"\x0a\x0d/ipfs/bafy...".to_vec();
+    let none = HeaderMap::new();
+
+    assert_eq!(decode(&none, record.clone(), 100).unwrap(), record);
+    assert_eq!(
+      decode(&encoded("identity"), record.clone(), 100).unwrap(),
+      record
+    );
+    assert_eq!(
+      decode(&encoded("gzip"), gzip(&record), 100).unwrap(),
+      record
+    );
+    assert_eq!(
+      decode(&encoded("X-Gzip"), gzip(&record), 100).unwrap(),
+      record
+    );
+    // without the header, gzip is recognized by its magic bytes
+    assert_eq!(decode(&none, gzip(&record), 100).unwrap(), record);
+
+    let mut zlib = ZlibEncoder::new(vec![], Compression::default());
+    zlib.write_all(&record).unwrap();
+    let zlib = zlib.finish().unwrap();
+    assert!(is_zlib(&zlib));
+    assert_eq!(decode(&encoded("deflate"), zlib, 100).unwrap(), record);
+    let mut raw = DeflateEncoder::new(vec![], Compression::default());
+    raw.write_all(&record).unwrap();
+    let raw = raw.finish().unwrap();
+    assert_eq!(decode(&encoded("deflate"), raw, 100).unwrap(), record);
+  }
+
+  #[test]
+  fn rejects_bad_bodies() {
+    // a decompression bomb is stopped at the limit
+    let bomb = gzip(&vec![0; 1 << 20]);
+    assert!(bomb.len() < 2048);
+    let err = decode(&encoded("gzip"), bomb.clone(), 10 * 1024).unwrap_err();
+    assert!(err.contains::<RecordTooLarge>());
+    assert!(decode(&HeaderMap::new(), bomb, 10 * 1024)
+      .unwrap_err()
+      .contains::<RecordTooLarge>());
+
+    let err = decode(&encoded("gzip"), b"not gzip".to_vec(), 100).unwrap_err();
+    assert!(err.contains::<ContentEncodingError>());
+    let err = decode(&encoded("br"), b"\x0b\x02\x80".to_vec(), 100).unwrap_err();
+    assert!(err.contains::<ContentEncodingError>());
+  }
+}
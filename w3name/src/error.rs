@@ -1,6 +1,11 @@
+//! The error types of this crate. The errors of names, revisions and records are those of
+//! `w3name-core`, which are re-exported here.
+
 use std::error::Error;
 use std::fmt::Display;
 
+pub use w3name_core::error::*;
+
 #[derive(Debug)]
 pub struct ClientError;
 
@@ -12,6 +17,18 @@ impl Display for ClientError {
 
 impl Error for ClientError {}
 
+/// A [NamePublisher](crate::NamePublisher) failed to publish a record.
+#[derive(Debug)]
+pub struct PublishError;
+
+impl Display for PublishError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "failed to publish record")
+  }
+}
+
+impl Error for PublishError {}
+
 #[derive(Debug)]
 pub struct HttpError;
 
@@ -23,10 +40,100 @@ impl Display for HttpError {
 
 impl Error for HttpError {}
 
-#[derive(Debug)]
-pub struct APIError { 
+/// What kind of network failure caused an [HttpError], as far as can be told from the error.
+///
+/// It's attached to the report of a failed request, so it's printed along with the error, and can
+/// be retrieved with `report.downcast_ref::<NetworkCause>()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkCause {
+  /// The host name couldn't be resolved.
+  Dns,
+  /// The TLS handshake failed, e.g. because the server's certificate isn't trusted.
+  Tls,
+  /// Nothing is listening at the address, or a firewall rejected the connection.
+  ConnectRefused,
+  /// The request or the connection attempt timed out.
+  Timeout,
+  /// Any other failure, e.g. the connection was reset.
+  Other,
+}
+
+impl NetworkCause {
+  /// Classifies `err` by inspecting it and its chain of sources.
+  pub fn of(err: &(dyn Error + 'static)) -> NetworkCause {
+    let mut messages = Vec::new();
+    let mut source = Some(err);
+    while let Some(err) = source {
+      #[cfg(feature = "client")]
+      if let Some(reqwest_err) = err.downcast_ref::<reqwest::Error>() {
+        if reqwest_err.is_timeout() {
+          return NetworkCause::Timeout;
+        }
+      }
+      if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+        match io_err.kind() {
+          std::io::ErrorKind::ConnectionRefused => return NetworkCause::ConnectRefused,
+          std::io::ErrorKind::TimedOut => return NetworkCause::Timeout,
+          _ => {}
+        }
+      }
+      messages.push(err.to_string().to_lowercase());
+      source = err.source();
+    }
+
+    // DNS and TLS failures don't have error types of their own in reqwest's public API, so they're
+    // recognized by the messages of hyper's resolver and the TLS backends
+    let mentions = |needles: &[&str]| {
+      messages
+        .iter()
+        .any(|message| needles.iter().any(|needle| message.contains(needle)))
+    };
+    if mentions(&[
+      "dns error",
+      "failed to lookup address",
+      "name or service not known",
+    ]) {
+      NetworkCause::Dns
+    } else if mentions(&["tls", "ssl", "certificate", "handshake"]) {
+      NetworkCause::Tls
+    } else {
+      NetworkCause::Other
+    }
+  }
+}
+
+impl Display for NetworkCause {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      NetworkCause::Dns => write!(f, "network cause: DNS lookup failed"),
+      NetworkCause::Tls => write!(f, "network cause: TLS handshake failed"),
+      NetworkCause::ConnectRefused => write!(f, "network cause: connection refused"),
+      NetworkCause::Timeout => write!(f, "network cause: timed out"),
+      NetworkCause::Other => write!(f, "network cause: other network error"),
+    }
+  }
+}
+
+/// An [HttpTransport](crate::HttpTransport) couldn't get a response to a request, e.g. because
+/// the connection failed or timed out.
+///
+/// Clients report it inside an [HttpError], with the [NetworkCause] attached if the transport
+/// could tell it.
+#[derive(Debug)]
+pub struct TransportError;
+
+impl Display for TransportError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "http transport failed")
+  }
+}
+
+impl Error for TransportError {}
+
+#[derive(Debug)]
+pub struct APIError {
   pub message: String,
-  pub status_code: reqwest::StatusCode,
+  pub status_code: http::StatusCode,
 }
 
 impl Display for APIError {
@@ -37,160 +144,104 @@ impl Display for APIError {
 
 impl Error for APIError {}
 
+/// The API responded with something it isn't documented to, e.g. an error without a JSON
+/// message, or a web page instead of a record.
 #[derive(Debug)]
 pub struct UnexpectedAPIResponse;
 
 impl Display for UnexpectedAPIResponse {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(
-      f,
-      "unexpected response from API, unable to parse error message"
-    )
+    write!(f, "unexpected response from API")
   }
 }
 
 impl Error for UnexpectedAPIResponse {}
 
+/// A response body was compressed with an unsupported encoding, or couldn't be decompressed.
 #[derive(Debug)]
-pub struct NameError;
+pub struct ContentEncodingError;
 
-impl Display for NameError {
+impl Display for ContentEncodingError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "name error")
+    write!(f, "unable to decode the compressed response body")
   }
 }
 
-impl Error for NameError {}
+impl Error for ContentEncodingError {}
 
 #[derive(Debug)]
-pub struct InvalidCidString;
+pub struct StoreError;
 
-impl Display for InvalidCidString {
+impl Display for StoreError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "invalid cid string")
+    write!(f, "record store error")
   }
 }
 
-impl Error for InvalidCidString {}
+impl Error for StoreError {}
 
-#[derive(Debug)]
-pub struct InvalidMulticodecCode;
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io;
 
-impl Display for InvalidMulticodecCode {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "invalid multicodec code")
-  }
-}
+  /// An error with a message and an optional source, standing in for hyper's and the TLS
+  /// backends' error types.
+  #[derive(Debug)]
+  struct Wrapped(&'static str, Option<Box<dyn Error + Send + Sync>>);
 
-impl Error for InvalidMulticodecCode {}
-
-#[derive(Debug)]
-pub struct InvalidCryptoKey;
-
-impl Display for InvalidCryptoKey {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "invalid crypto key")
+  impl Display for Wrapped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      write!(f, "{}", self.0)
+    }
   }
-}
-
-impl Error for InvalidCryptoKey {}
 
-#[derive(Debug)]
-pub struct SigningError;
-
-impl Display for SigningError {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "signing error")
+  impl Error for Wrapped {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+      self.1.as_deref().map(|err| err as &(dyn Error + 'static))
+    }
   }
-}
-
-impl Error for SigningError {}
-
-#[derive(Debug)]
-pub struct CborError;
 
-impl Display for CborError {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "cbor error")
+  fn wrapped(message: &'static str, source: impl Error + Send + Sync + 'static) -> Wrapped {
+    Wrapped(message, Some(Box::new(source)))
   }
-}
 
-impl Error for CborError {}
+  #[test]
+  fn classifies_network_causes() {
+    let refused = wrapped(
+      "error trying to connect",
+      io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        "Connection refused (os error 111)",
+      ),
+    );
+    assert_eq!(NetworkCause::of(&refused), NetworkCause::ConnectRefused);
 
-#[derive(Debug)]
-pub struct ProtobufError;
+    let timed_out = io::Error::new(io::ErrorKind::TimedOut, "connection timed out");
+    assert_eq!(NetworkCause::of(&timed_out), NetworkCause::Timeout);
 
-impl Display for ProtobufError {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "protobuf error")
-  }
-}
-
-impl Error for ProtobufError {}
+    let dns = wrapped(
+      "error trying to connect",
+      wrapped(
+        "dns error",
+        io::Error::other("failed to lookup address information: Name or service not known"),
+      ),
+    );
+    assert_eq!(NetworkCause::of(&dns), NetworkCause::Dns);
 
-#[derive(Debug)]
-pub struct InvalidIpnsV1Signature;
+    let tls = wrapped(
+      "error trying to connect",
+      Wrapped(
+        "error:0A000086:SSL routines:tls_post_process_server_certificate:certificate verify failed",
+        None,
+      ),
+    );
+    assert_eq!(NetworkCause::of(&tls), NetworkCause::Tls);
 
-impl Display for InvalidIpnsV1Signature {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "invalid IPNS signature (v1)")
+    let reset = wrapped(
+      "connection closed before message completed",
+      io::Error::new(io::ErrorKind::ConnectionReset, "Connection reset by peer"),
+    );
+    assert_eq!(NetworkCause::of(&reset), NetworkCause::Other);
   }
 }
-
-impl Error for InvalidIpnsV1Signature {}
-
-#[derive(Debug)]
-pub struct InvalidIpnsV2Signature;
-
-impl Display for InvalidIpnsV2Signature {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "invalid IPNS signature (v2)")
-  }
-}
-
-impl Error for InvalidIpnsV2Signature {}
-
-#[derive(Debug)]
-pub struct InvalidIpnsV2SignatureData;
-
-impl Display for InvalidIpnsV2SignatureData {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(
-      f,
-      "invalid IPNS v2 signature data (does not match protobuf values)"
-    )
-  }
-}
-
-impl Error for InvalidIpnsV2SignatureData {}
-
-#[derive(Debug)]
-pub struct InvalidUtf8;
-
-impl Display for InvalidUtf8 {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "invalid utf-8")
-  }
-}
-
-impl Error for InvalidUtf8 {}
-
-#[derive(Debug)]
-pub struct InvalidDateString;
-
-impl Display for InvalidDateString {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "invalid RFC-3339 date string")
-  }
-}
-
-#[derive(Debug)]
-pub struct IpnsError;
-
-impl Display for IpnsError {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "IPNS record error")
-  }
-}
-
-impl Error for IpnsError {}
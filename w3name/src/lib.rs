@@ -14,12 +14,45 @@
 //!   `Name`s can be used to fetch and verify the latest published value for a name record.
 //! - [WritableName] contains a private key that can be used to sign and publish name records.
 //! - [Revision] represents an unsigned name record. It contains a string value and some metadata (sequence number, expiration date, etc).
+//!   [RevisionBuilder] can be used to create `Revision`s with custom settings and validation.
+//! - [Value] is a typed view of a record's value, which can render IPFS and IPNS paths as gateway URLs or `ipfs://` URIs.
 //!
 //! The [W3NameClient] type provides a [reqwest](https://docs.rs/reqwest/latest/reqwest/)-based HTTP client
 //! for the w3name service. Using the client, you can [resolve](W3NameClient::resolve) the value for a [Name] and/or
-//! [publish](W3NameClient::publish) a new [Revision] for a [WritableName].
+//! [publish](W3NameClient::publish) a new [Revision] for a [WritableName]. [W3NameClient::publish_value] wraps
+//! the usual resolve-then-increment flow for publishing a new value.
+//!
+//! Other sources of name records, like [GatewayResolver], implement the [Resolver] trait, as does [W3NameClient].
+//! Resolvers return a [ResolvedRevision], which records where the record came from.
+//! [CoalescingResolver] wraps another resolver so that concurrent resolves of the same name share one request,
+//! and [ChainResolver] falls back to other resolvers, e.g. gateways, when the first one fails.
+//!
+//! Likewise, systems that records can be published to implement the [NamePublisher] trait: [W3NameClient] and
+//! [DelegatedRoutingClient] do, and [FanoutPublisher] publishes a revision to several of them at once.
+//!
+//! [WritableName::sign_proof] signs a [Proof] of control of a name, e.g. for a registrar, which
+//! [Name::verify_proof] checks.
+//!
+//! [W3NameClient::watch] polls a name and streams its record whenever it changes, and a [NameTracker]
+//! keeps track of the records seen for names, noticing when they go backwards or flap.
+//!
+//! For long-running services, [Republisher] keeps a set of names alive by republishing them before they expire.
+//!
+//! [RevisionBuilder], [W3NameClient] and [Republisher] read the current time from a [Clock], the
+//! [SystemClock] unless they're given another one, such as a [ManualClock] in tests.
+//!
+//! With the `store` feature, the `RecordStore` trait persists signed records by name, with
+//! in-memory and file-based implementations.
+//!
+//! The names, revisions and record format come from the [w3name-core](https://crates.io/crates/w3name-core)
+//! crate, which has no network code, and are re-exported here at the same paths.
 //!
 //! Note that the client requires a [tokio](https://tokio.rs) runtime, as it uses the async reqwest implementation.
+//! [W3NameClient], [GatewayResolver] and [DelegatedRoutingClient] send their requests through an
+//! [HttpTransport], which is a [ReqwestTransport] unless they're given another one, e.g. for a platform
+//! where reqwest isn't available, or a [ReplayTransport] in tests. `ReqwestTransport` comes with the
+//! `client` feature, which is on by default; without it, reqwest isn't a dependency, and the clients
+//! must be given a transport.
 //! For a real-world example of using the client, see [w3name-cli](https://crates.io/crates/w3name-cli).
 //!
 //! ## Errors
@@ -33,17 +66,49 @@
 //! [`Report::current_context()`](https://docs.rs/error-stack/latest/error_stack/struct.Report.html#method.current_context).
 
 mod client;
+mod content_encoding;
 pub mod error;
-mod hash;
-pub mod ipns;
-mod name;
-mod revision;
+mod http_trace;
+#[cfg(all(test, feature = "client"))]
+mod mock_service;
+mod publisher;
+mod republisher;
+mod resolver;
+mod routing;
+#[cfg(feature = "store")]
+mod store;
+mod tracker;
+mod transport;
+mod watch;
 
-// Include the `ipns_pb` module, which is generated from ipns/ipns_pb.proto.
-mod ipns_pb {
-  include!(concat!(env!("OUT_DIR"), "/ipns_pb.rs"));
-}
+pub use w3name_core::{ipns, json_value, timestamp};
 
-pub use client::W3NameClient;
-pub use name::{Name, WritableName};
-pub use revision::Revision;
+pub use client::{
+  default_endpoint, CurrentRevision, PreparedRevision, PublishOptions, PublishOutcome,
+  PublishReceipt, PublishedRevision, RevisionSource, W3NameClient, W3NameClientBuilder,
+};
+pub use publisher::{
+  FanoutOutcome, FanoutPolicy, FanoutPublisher, NamePublisher, Publisher, SignatureMode,
+  TargetResult,
+};
+pub use republisher::{
+  FailureClass, NameStatus, RepublishEntry, Republisher, RepublisherConfig, RepublisherEvent,
+  RepublisherStatus, ValueProvider,
+};
+pub use resolver::{
+  default_gateways, ChainResolver, CoalescingResolver, GatewayResolver, GatewayStatus,
+  HealthPolicy, ResolvedRevision, Resolver, SourceInfo, SourceKind,
+};
+pub use routing::DelegatedRoutingClient;
+#[cfg(feature = "store")]
+pub use store::{FileRecordStore, MemoryRecordStore, RecordStore, StoredRecord};
+pub use tracker::{NameTracker, TrackedName, TrackerConfig, TrackerEvent};
+#[cfg(feature = "client")]
+pub use transport::ReqwestTransport;
+pub use transport::{HttpRequest, HttpResponse, HttpTransport, ReplayTransport};
+pub use url::Url;
+pub use w3name_core::{
+  Clock, Defaults, IncrementPolicy, ManualClock, Name, Proof, Revision, RevisionBuilder,
+  SystemClock, TtlChecks, TtlNote, ValidityType, Value, WritableName,
+};
+pub use watch::WatchOptions;
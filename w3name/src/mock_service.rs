@@ -0,0 +1,543 @@
+//! A minimal in-process stand-in for the w3name HTTP API, for tests.
+//!
+//! Published records are kept in memory and served back by `GET /name/<name>`, so a
+//! [W3NameClient] pointed at the mock behaves like it would against the real service.
+//! They're also served by `GET /ipns/<name>`, like a trustless gateway would, and records can be
+//! put with `PUT /routing/v1/ipns/<name>`, like on a delegated routing server.
+//!
+//! Resolves are answered with an `ETag`, and with `304 Not Modified` if the request's
+//! `If-None-Match` is the ETag of the current record.
+//!
+//! The mock either runs as an HTTP server, or [in process](MockService::in_process), answering the
+//! requests of clients that use it as their [HttpTransport] the same way. Tests that don't depend
+//! on the network run against [both](MockService::both), which shows that the clients behave the
+//! same whatever the transport.
+
+use std::{
+  collections::HashMap,
+  convert::Infallible,
+  io::Write,
+  net::SocketAddr,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+};
+
+use async_trait::async_trait;
+use flate2::{write::GzEncoder, Compression};
+use hyper::{
+  header::{
+    HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, DATE, ETAG, IF_NONE_MATCH,
+  },
+  service::{make_service_fn, service_fn},
+  Body, HeaderMap, Method, Request, Response, Server, StatusCode,
+};
+use url::Url;
+
+use crate::{
+  error::TransportError, Clock, DelegatedRoutingClient, GatewayResolver, HttpRequest, HttpResponse,
+  HttpTransport, Name, SystemClock, W3NameClient, W3NameClientBuilder,
+};
+use w3name_core::internal::{format_http_date, zero};
+
+#[derive(Debug, Default)]
+struct State {
+  /// base64-encoded records, keyed by name
+  records: HashMap<String, String>,
+  /// if set, every resolve fails with this status
+  resolve_failure: Option<StatusCode>,
+  /// if set, every resolve is answered with `200 OK` and this content type and body instead
+  resolve_response: Option<(Option<String>, Vec<u8>)>,
+  /// if set, every resolve streams this many bytes of garbage instead
+  garbage: Option<usize>,
+  /// the number of garbage bytes sent so far
+  garbage_sent: Arc<AtomicUsize>,
+  /// if set, records from `/ipns/` are served with this content type instead of the IPNS record type
+  gateway_content_type: Option<String>,
+  /// if set, records from `/ipns/` are gzipped, with a `Content-Encoding` header if it's true
+  gateway_gzip: Option<bool>,
+  /// the `Accept-Encoding` header of the last request to `/ipns/`
+  gateway_accept_encoding: Option<String>,
+  /// if set, every request to `/ipns/` fails with this status
+  gateway_failure: Option<StatusCode>,
+  /// the number of requests to `/ipns/` so far
+  gateway_requests: usize,
+  /// if set, the `Date` header of responses is this far ahead of the real time
+  clock_offset: Option<crate::timestamp::Duration>,
+  /// if set, publishes are acknowledged with this `id` instead of the published name
+  publish_id: Option<String>,
+  /// the number of resolves answered with `304 Not Modified` so far
+  not_modified: usize,
+  /// if set, publishes of V2-only records fail with 400, like on services that require V1 fields
+  reject_v2_only: bool,
+  /// the number of publishes (`POST /name/` and `PUT /routing/v1/ipns/`) so far
+  publishes: usize,
+  /// the `Idempotency-Key` header of each `POST /name/` so far, in order
+  idempotency_keys: Vec<Option<String>>,
+}
+
+pub struct MockService {
+  /// the address of the server, unless the mock runs in process
+  addr: Option<SocketAddr>,
+  state: Arc<Mutex<State>>,
+  connections: Arc<AtomicUsize>,
+}
+
+impl MockService {
+  /// Starts the mock service on a random local port. It runs until the test's runtime shuts down.
+  pub fn start() -> MockService {
+    let state = Arc::new(Mutex::new(State::default()));
+    let service_state = state.clone();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    let make_svc = make_service_fn(move |_| {
+      accepted.fetch_add(1, Ordering::SeqCst);
+      let state = service_state.clone();
+      async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+    });
+
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    MockService {
+      addr: Some(addr),
+      state,
+      connections,
+    }
+  }
+
+  /// Creates the mock without a server. The clients it returns send their requests straight to it.
+  pub fn in_process() -> MockService {
+    MockService {
+      addr: None,
+      state: Arc::new(Mutex::new(State::default())),
+      connections: Arc::new(AtomicUsize::new(0)),
+    }
+  }
+
+  /// Returns a mock [server](Self::start) and an [in-process](Self::in_process) mock, for tests
+  /// that should pass with either.
+  pub fn both() -> [MockService; 2] {
+    [MockService::start(), MockService::in_process()]
+  }
+
+  pub fn url(&self) -> Url {
+    match self.addr {
+      Some(addr) => Url::parse(&format!("http://{}", addr)).unwrap(),
+      None => Url::parse("http://mock.invalid").unwrap(),
+    }
+  }
+
+  /// Returns the transport that in-process clients use, or `None` for a server.
+  fn transport(&self) -> Option<Arc<dyn HttpTransport>> {
+    match self.addr {
+      Some(_) => None,
+      None => Some(Arc::new(MockTransport(self.state.clone()))),
+    }
+  }
+
+  /// Returns the number of TCP connections accepted so far.
+  pub fn connections(&self) -> usize {
+    self.connections.load(Ordering::SeqCst)
+  }
+
+  /// Returns a client that talks to this mock service.
+  pub fn client(&self) -> W3NameClient {
+    self.client_builder().build().unwrap()
+  }
+
+  /// Returns a builder for a client that talks to this mock service.
+  pub fn client_builder(&self) -> W3NameClientBuilder {
+    let builder = W3NameClient::builder(self.url());
+    match self.transport() {
+      Some(transport) => builder.transport(transport),
+      None => builder,
+    }
+  }
+
+  /// Returns a delegated routing client that puts records to this mock service.
+  pub fn routing(&self) -> DelegatedRoutingClient {
+    let routing = DelegatedRoutingClient::new(self.url());
+    match self.transport() {
+      Some(transport) => routing.with_transport(transport),
+      None => routing,
+    }
+  }
+
+  /// Returns a resolver that uses this mock service as its only gateway.
+  pub fn gateway(&self) -> GatewayResolver {
+    let gateway = GatewayResolver::new(self.url());
+    match self.transport() {
+      Some(transport) => gateway.with_transport(transport),
+      None => gateway,
+    }
+  }
+
+  /// Makes every subsequent resolve fail with `status`.
+  pub fn fail_resolves(&self, status: StatusCode) {
+    self.state.lock().unwrap().resolve_failure = Some(status);
+  }
+
+  /// Makes every subsequent resolve succeed with `body`, and `content_type` if given, like a
+  /// captive portal or a misconfigured proxy that answers in the service's place.
+  pub fn serve_resolve_response(&self, content_type: Option<&str>, body: &[u8]) {
+    self.state.lock().unwrap().resolve_response =
+      Some((content_type.map(str::to_string), body.to_vec()));
+  }
+
+  /// Makes every subsequent resolve stream `size` bytes of garbage, without a `Content-Length`.
+  pub fn serve_garbage(&self, size: usize) {
+    self.state.lock().unwrap().garbage = Some(size);
+  }
+
+  /// Returns the number of garbage bytes sent so far, which stops growing once clients hang up.
+  pub fn garbage_sent(&self) -> usize {
+    self
+      .state
+      .lock()
+      .unwrap()
+      .garbage_sent
+      .load(Ordering::SeqCst)
+  }
+
+  /// Makes the gateway route serve records with `content_type`.
+  pub fn set_gateway_content_type(&self, content_type: &str) {
+    self.state.lock().unwrap().gateway_content_type = Some(content_type.to_string());
+  }
+
+  /// Makes the gateway route gzip the records it serves, as some gateways do, with or without
+  /// saying so in a `Content-Encoding` header.
+  pub fn gzip_gateway_records(&self, with_header: bool) {
+    self.state.lock().unwrap().gateway_gzip = Some(with_header);
+  }
+
+  /// Returns the `Accept-Encoding` header of the last request to the gateway route.
+  pub fn gateway_accept_encoding(&self) -> Option<String> {
+    self.state.lock().unwrap().gateway_accept_encoding.clone()
+  }
+
+  /// Makes every subsequent request to the gateway route fail with `status`.
+  pub fn fail_gateway(&self, status: StatusCode) {
+    self.state.lock().unwrap().gateway_failure = Some(status);
+  }
+
+  /// Returns the number of requests to the gateway route so far.
+  pub fn gateway_requests(&self) -> usize {
+    self.state.lock().unwrap().gateway_requests
+  }
+
+  /// Makes the `Date` header of every subsequent response `offset` ahead of the real time, as if the
+  /// service's clock (or the client's) were off.
+  pub fn set_clock_offset(&self, offset: crate::timestamp::Duration) {
+    self.state.lock().unwrap().clock_offset = Some(offset);
+  }
+
+  /// Makes every subsequent publish respond with `id` as the published name, like a misbehaving
+  /// service (or proxy) would.
+  pub fn set_publish_id(&self, id: &str) {
+    self.state.lock().unwrap().publish_id = Some(id.to_string());
+  }
+
+  /// Makes every subsequent publish of a V2-only record fail with 400 Bad Request, while hybrid
+  /// V1+V2 records are still accepted.
+  pub fn reject_v2_only(&self) {
+    self.state.lock().unwrap().reject_v2_only = true;
+  }
+
+  /// Returns the number of publishes received so far, whether they were accepted or not.
+  pub fn publishes(&self) -> usize {
+    self.state.lock().unwrap().publishes
+  }
+
+  /// Returns the `Idempotency-Key` header of each `POST /name/` received so far, in order.
+  pub fn idempotency_keys(&self) -> Vec<Option<String>> {
+    self.state.lock().unwrap().idempotency_keys.clone()
+  }
+
+  /// Returns the number of resolves answered with `304 Not Modified` so far.
+  pub fn not_modified_responses(&self) -> usize {
+    self.state.lock().unwrap().not_modified
+  }
+
+  /// Serves `record` (base64-encoded) for `name`, as if it had been published.
+  pub fn set_record(&self, name: &Name, record: String) {
+    self
+      .state
+      .lock()
+      .unwrap()
+      .records
+      .insert(name.to_string(), record);
+  }
+
+  /// Returns the base64-encoded record currently stored for `name`.
+  pub fn record(&self, name: &Name) -> Option<String> {
+    self
+      .state
+      .lock()
+      .unwrap()
+      .records
+      .get(&name.to_string())
+      .cloned()
+  }
+}
+
+/// What the mock answers a request with.
+enum Reply {
+  Full(HttpResponse),
+  /// `size` bytes of garbage, streamed by the server
+  Garbage(usize),
+}
+
+async fn handle(
+  state: Arc<Mutex<State>>,
+  req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+  let (parts, body) = req.into_parts();
+  let body = hyper::body::to_bytes(body).await.unwrap().to_vec();
+  let res = match respond(
+    &state,
+    &parts.method,
+    parts.uri.path(),
+    &parts.headers,
+    body,
+  ) {
+    Reply::Full(res) => {
+      let mut builder = Response::builder().status(res.status);
+      if let Some(headers) = builder.headers_mut() {
+        *headers = res.headers;
+      }
+      builder.body(Body::from(res.body)).unwrap()
+    }
+    Reply::Garbage(size) => {
+      let sent = state.lock().unwrap().garbage_sent.clone();
+      garbage(size, sent)
+    }
+  };
+  Ok(res)
+}
+
+/// Answers a request, however it arrived.
+fn respond(
+  state: &Mutex<State>,
+  method: &Method,
+  path: &str,
+  headers: &HeaderMap,
+  body: Vec<u8>,
+) -> Reply {
+  let mut reply = route(state, method, path, headers, body);
+  if let Reply::Full(res) = &mut reply {
+    // hyper only adds its own Date header if there isn't one already
+    let offset = state.lock().unwrap().clock_offset.unwrap_or_else(zero);
+    let date = format_http_date(&(SystemClock.now() + offset));
+    res.headers.insert(DATE, date.parse().unwrap());
+  }
+  reply
+}
+
+fn route(
+  state: &Mutex<State>,
+  method: &Method,
+  path: &str,
+  headers: &HeaderMap,
+  body: Vec<u8>,
+) -> Reply {
+  if let Some(name) = path.strip_prefix("/routing/v1/ipns/") {
+    return Reply::Full(routing_put(state, name.to_string(), method, body));
+  }
+  let (name, gateway) = match (path.strip_prefix("/name/"), path.strip_prefix("/ipns/")) {
+    (Some(name), _) => (name.to_string(), false),
+    (_, Some(name)) => (name.to_string(), true),
+    _ => {
+      return Reply::Full(json(
+        StatusCode::NOT_FOUND,
+        r#"{"message":"no such route"}"#,
+      ))
+    }
+  };
+
+  let res = match *method {
+    Method::GET => {
+      let mut state = state.lock().unwrap();
+      if gateway {
+        state.gateway_requests += 1;
+        state.gateway_accept_encoding = headers
+          .get(ACCEPT_ENCODING)
+          .and_then(|value| value.to_str().ok())
+          .map(str::to_string);
+      }
+      if let Some(size) = state.garbage {
+        return Reply::Garbage(size);
+      } else if let (true, Some(status)) = (gateway, state.gateway_failure) {
+        json(status, r#"{"message":"mock failure"}"#)
+      } else if gateway {
+        match state.records.get(&name) {
+          Some(record) => {
+            let res = HttpResponse::new(StatusCode::OK).header(
+              CONTENT_TYPE,
+              HeaderValue::from_str(
+                state
+                  .gateway_content_type
+                  .as_deref()
+                  .unwrap_or("application/vnd.ipfs.ipns-record"),
+              )
+              .unwrap(),
+            );
+            let record = base64::decode(record).unwrap();
+            match state.gateway_gzip {
+              Some(with_header) => {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder.write_all(&record).unwrap();
+                let res = res.body(encoder.finish().unwrap());
+                if with_header {
+                  res.header(CONTENT_ENCODING, HeaderValue::from_static("gzip"))
+                } else {
+                  res
+                }
+              }
+              None => res.body(record),
+            }
+          }
+          None => json(StatusCode::NOT_FOUND, r#"{"message":"record not found"}"#),
+        }
+      } else if let Some((content_type, body)) = &state.resolve_response {
+        let res = HttpResponse::new(StatusCode::OK).body(body.clone());
+        match content_type {
+          Some(content_type) => {
+            res.header(CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap())
+          }
+          None => res,
+        }
+      } else if let Some(status) = state.resolve_failure {
+        json(status, r#"{"message":"mock failure"}"#)
+      } else if let Some(record) = state.records.get(&name) {
+        let etag = etag(record);
+        let if_none_match = headers.get(IF_NONE_MATCH);
+        if if_none_match.is_some_and(|value| value.as_bytes() == etag.as_bytes()) {
+          state.not_modified += 1;
+          HttpResponse::new(StatusCode::NOT_MODIFIED).header(ETAG, etag.parse().unwrap())
+        } else {
+          json(StatusCode::OK, &format!(r#"{{"record":"{}"}}"#, record))
+            .header(ETAG, etag.parse().unwrap())
+        }
+      } else {
+        json(StatusCode::NOT_FOUND, r#"{"message":"record not found"}"#)
+      }
+    }
+    Method::POST => {
+      let record = String::from_utf8(body).unwrap();
+      let mut state = state.lock().unwrap();
+      state.publishes += 1;
+      state.idempotency_keys.push(
+        headers
+          .get("idempotency-key")
+          .and_then(|value| value.to_str().ok())
+          .map(str::to_string),
+      );
+      if state.reject_v2_only && is_v2_only(&base64::decode(&record).unwrap()) {
+        return Reply::Full(json(
+          StatusCode::BAD_REQUEST,
+          r#"{"message":"invalid record"}"#,
+        ));
+      }
+      state.records.insert(name.clone(), record);
+      let id = state.publish_id.as_ref().unwrap_or(&name);
+      json(StatusCode::ACCEPTED, &format!(r#"{{"id":"{}"}}"#, id))
+    }
+    _ => json(
+      StatusCode::METHOD_NOT_ALLOWED,
+      r#"{"message":"method not allowed"}"#,
+    ),
+  };
+  Reply::Full(res)
+}
+
+/// Validates and stores a raw record, like a delegated routing server.
+fn routing_put(state: &Mutex<State>, name: String, method: &Method, body: Vec<u8>) -> HttpResponse {
+  if method != Method::PUT {
+    return json(
+      StatusCode::METHOD_NOT_ALLOWED,
+      r#"{"message":"method not allowed"}"#,
+    );
+  }
+  let mut state = state.lock().unwrap();
+  state.publishes += 1;
+  let valid = Name::parse(&name)
+    .is_ok_and(|parsed| crate::Revision::from_signed_bytes(&parsed, &body).is_ok());
+  if !valid || (state.reject_v2_only && is_v2_only(&body)) {
+    return HttpResponse::new(StatusCode::BAD_REQUEST).body("record is not valid for the name\n");
+  }
+  state.records.insert(name, base64::encode(&body));
+  HttpResponse::new(StatusCode::OK)
+}
+
+/// The mock as an [HttpTransport], answering requests without a server in between.
+#[derive(Debug)]
+struct MockTransport(Arc<Mutex<State>>);
+
+#[async_trait]
+impl HttpTransport for MockTransport {
+  async fn execute(&self, req: HttpRequest) -> error_stack::Result<HttpResponse, TransportError> {
+    let body = req.body.unwrap_or_default();
+    match respond(&self.0, &req.method, req.url.path(), &req.headers, body) {
+      Reply::Full(res) => Ok(res),
+      Reply::Garbage(size) => {
+        self
+          .0
+          .lock()
+          .unwrap()
+          .garbage_sent
+          .fetch_add(size, Ordering::SeqCst);
+        Ok(
+          HttpResponse::new(StatusCode::OK)
+            .header(
+              CONTENT_TYPE,
+              HeaderValue::from_static("application/vnd.ipfs.ipns-record"),
+            )
+            .body(vec![b'x'; size]),
+        )
+      }
+    }
+  }
+}
+
+/// Returns `true` if `record` has no V1 signature.
+fn is_v2_only(record: &[u8]) -> bool {
+  crate::ipns::deserialize_ipns_entry(record).is_ok_and(|entry| entry.signature.is_empty())
+}
+
+/// Streams `size` bytes of garbage, counting the bytes sent in `sent`, until the client hangs up.
+fn garbage(size: usize, sent: Arc<AtomicUsize>) -> Response<Body> {
+  const CHUNK_SIZE: usize = 64 * 1024;
+  let (mut sender, body) = Body::channel();
+  tokio::spawn(async move {
+    let mut remaining = size;
+    while remaining > 0 {
+      let len = remaining.min(CHUNK_SIZE);
+      if sender.send_data(vec![b'x'; len].into()).await.is_err() {
+        break;
+      }
+      sent.fetch_add(len, Ordering::SeqCst);
+      remaining -= len;
+    }
+  });
+  Response::builder()
+    .header("Content-Type", "application/vnd.ipfs.ipns-record")
+    .body(body)
+    .unwrap()
+}
+
+/// Returns the ETag of a record, which changes whenever the record does.
+fn etag(record: &str) -> String {
+  use std::hash::{Hash, Hasher};
+
+  let mut hasher = std::collections::hash_map::DefaultHasher::new();
+  record.hash(&mut hasher);
+  format!("\"{:016x}\"", hasher.finish())
+}
+
+fn json(status: StatusCode, body: &str) -> HttpResponse {
+  HttpResponse::new(status)
+    .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+    .body(body)
+}
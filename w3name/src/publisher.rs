@@ -0,0 +1,550 @@
+use std::{
+  future::Future,
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+use async_trait::async_trait;
+use error_stack::{report, FrameKind, Report, Result, ResultExt};
+
+use crate::{
+  client::new_idempotency_key,
+  error::{APIError, ClientError, PublishError},
+  PublishOptions, PublishReceipt, Revision, W3NameClient, WritableName,
+};
+
+/// A destination for name records.
+///
+/// Unlike [W3NameClient::publish], a `Publisher` takes a value rather than a [Revision], and is responsible
+/// for choosing the sequence number, e.g. by incrementing the current revision.
+#[async_trait]
+pub trait Publisher: Send + Sync {
+  /// Publishes `value` for `name`, returning the published [Revision].
+  async fn publish_value(&self, name: &WritableName, value: &str) -> Result<Revision, ClientError>;
+}
+
+#[async_trait]
+impl Publisher for W3NameClient {
+  async fn publish_value(&self, name: &WritableName, value: &str) -> Result<Revision, ClientError> {
+    let outcome = W3NameClient::publish_value(self, name, value, PublishOptions::default()).await?;
+    Ok(outcome.into_revision())
+  }
+}
+
+/// A system that signed records can be published to, like the w3name service, a
+/// [delegated routing](crate::DelegatedRoutingClient) endpoint or an IPFS node.
+///
+/// This is the publishing counterpart of [Resolver](crate::Resolver). Unlike a [Publisher], a
+/// `NamePublisher` publishes the [Revision] it's given, so that the same revision can be published
+/// to several systems; see [FanoutPublisher].
+#[async_trait]
+pub trait NamePublisher: Send + Sync {
+  /// Signs `revision` with `name`'s key and publishes it.
+  async fn publish(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+  ) -> Result<PublishReceipt, PublishError>;
+
+  /// Publishes `record`, which is `revision` already signed with `name`'s key, so that publishing
+  /// the same revision to several targets only signs it once.
+  ///
+  /// The default implementation ignores `record` and calls [publish](Self::publish).
+  async fn publish_record(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    record: &[u8],
+  ) -> Result<PublishReceipt, PublishError> {
+    let _ = record;
+    self.publish(name, revision).await
+  }
+
+  /// Like [publish](Self::publish), with the signatures that `mode` asks for.
+  ///
+  /// The default implementation ignores `mode` and calls [publish](Self::publish).
+  async fn publish_as(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    mode: SignatureMode,
+  ) -> Result<PublishReceipt, PublishError> {
+    let _ = mode;
+    self.publish(name, revision).await
+  }
+
+  /// Describes where this publishes records, e.g. `w3name https://name.web3.storage/`, for
+  /// reporting the results of each target of a [FanoutPublisher].
+  fn target(&self) -> String;
+}
+
+#[async_trait]
+impl NamePublisher for W3NameClient {
+  async fn publish(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+  ) -> Result<PublishReceipt, PublishError> {
+    W3NameClient::publish_with_receipt(self, name, revision)
+      .await
+      .change_context(PublishError)
+  }
+
+  async fn publish_record(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    record: &[u8],
+  ) -> Result<PublishReceipt, PublishError> {
+    let target = name.to_name();
+    let key = new_idempotency_key();
+    self
+      .signatures()
+      .publish(
+        &self.target(),
+        name,
+        revision,
+        Some(record),
+        SignatureMode::Auto,
+        |record, retry| {
+          // the hybrid retry is a different record, so it's a different publish
+          let key = if retry {
+            new_idempotency_key()
+          } else {
+            key.clone()
+          };
+          let target = &target;
+          async move { W3NameClient::publish_record_with_key(self, target, record, &key).await }
+        },
+      )
+      .await
+      .change_context(PublishError)
+  }
+
+  async fn publish_as(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    mode: SignatureMode,
+  ) -> Result<PublishReceipt, PublishError> {
+    W3NameClient::publish_with_mode(self, name, revision, mode)
+      .await
+      .change_context(PublishError)
+  }
+
+  fn target(&self) -> String {
+    format!("w3name {}", self.endpoint())
+  }
+}
+
+/// Which signatures the records that a [NamePublisher] uploads carry.
+///
+/// Records are V2-only by default, with the V1 fields left empty, as the IPNS spec recommends.
+/// Some self-hosted name services and older Kubo nodes reject those with a generic 400, and only
+/// accept hybrid records, which carry the same value in the V1 fields and a V1 signature too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SignatureMode {
+  /// Publish a V2-only record, and if the endpoint rejects it with 400 Bad Request, retry once
+  /// with a hybrid record. If that's accepted, the publisher sends hybrid records from then on.
+  #[default]
+  Auto,
+  /// Only ever publish V2-only records.
+  V2Only,
+  /// Always publish hybrid V1+V2 records.
+  Hybrid,
+}
+
+/// Remembers whether an endpoint needs hybrid records, for [SignatureMode::Auto].
+#[derive(Default)]
+pub(crate) struct SignatureProbe {
+  needs_hybrid: AtomicBool,
+}
+
+impl SignatureProbe {
+  /// Returns `true` once the endpoint has rejected a V2-only record and accepted a hybrid one.
+  pub(crate) fn needs_hybrid(&self) -> bool {
+    self.needs_hybrid.load(Ordering::Relaxed)
+  }
+
+  /// Signs `revision` as `mode` says, and uploads it with `send`. `signed` is the revision's
+  /// V2-only record, if it's already signed.
+  ///
+  /// In [SignatureMode::Auto], a V2-only record that `send` fails with a 400 is retried once as
+  /// a hybrid record, and if that succeeds, later publishes send hybrid records straight away.
+  /// `send` is told whether the record is such a retry: it's a different record from the
+  /// rejected one, so it mustn't be sent with the same `Idempotency-Key`, or a service that
+  /// honours the header would replay the 400, or refuse the key for a different body.
+  pub(crate) async fn publish<F, Fut>(
+    &self,
+    target: &str,
+    name: &WritableName,
+    revision: &Revision,
+    signed: Option<&[u8]>,
+    mode: SignatureMode,
+    send: F,
+  ) -> Result<PublishReceipt, ClientError>
+  where
+    F: Fn(Vec<u8>, bool) -> Fut,
+    Fut: Future<Output = Result<PublishReceipt, ClientError>>,
+  {
+    let hybrid = match mode {
+      SignatureMode::Auto => self.needs_hybrid(),
+      SignatureMode::V2Only => false,
+      SignatureMode::Hybrid => true,
+    };
+    let record = match (hybrid, signed) {
+      (true, _) => revision.to_hybrid_signed_bytes(name),
+      (false, Some(signed)) => Ok(signed.to_vec()),
+      (false, None) => revision.to_signed_bytes(name),
+    }
+    .change_context(ClientError)?;
+
+    let err_report = match send(record, false).await {
+      Ok(receipt) => return Ok(receipt),
+      Err(err_report) => err_report,
+    };
+    let bad_request = err_report
+      .downcast_ref::<APIError>()
+      .is_some_and(|api_err| api_err.status_code == 400);
+    if hybrid || mode != SignatureMode::Auto || !bad_request {
+      return Err(err_report);
+    }
+
+    log::warn!(
+      "{} rejected a V2-only record, retrying with a V1+V2 record",
+      target
+    );
+    let record = revision
+      .to_hybrid_signed_bytes(name)
+      .change_context(ClientError)?;
+    let receipt = send(record, true)
+      .await
+      .attach_printable("a V2-only record was rejected with 400 Bad Request first")?;
+    log::info!(
+      "{} accepts V1+V2 records, so they're published from now on",
+      target
+    );
+    self.needs_hybrid.store(true, Ordering::Relaxed);
+    Ok(receipt)
+  }
+}
+
+/// When a [FanoutPublisher] has published successfully.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FanoutPolicy {
+  /// Every target must accept the record.
+  #[default]
+  All,
+  /// At least one target must accept the record.
+  Any,
+}
+
+/// A [NamePublisher] that publishes each revision to several targets at once, e.g. the w3name
+/// service and the DHT, and succeeds according to its [FanoutPolicy].
+///
+/// Every target is tried, even once the outcome is decided. [publish_each](Self::publish_each)
+/// reports how each of them did; as a `NamePublisher`, the receipt of the first target that
+/// succeeded is returned, and the failed targets are listed in the error if the policy isn't met.
+pub struct FanoutPublisher {
+  targets: Vec<Box<dyn NamePublisher>>,
+  policy: FanoutPolicy,
+  signature_mode: SignatureMode,
+}
+
+impl FanoutPublisher {
+  /// Creates a `FanoutPublisher` for `targets`, which requires all of them to succeed.
+  pub fn new(targets: Vec<Box<dyn NamePublisher>>) -> Self {
+    FanoutPublisher {
+      targets,
+      policy: FanoutPolicy::default(),
+      signature_mode: SignatureMode::default(),
+    }
+  }
+
+  /// Sets how many targets must succeed.
+  pub fn with_policy(mut self, policy: FanoutPolicy) -> Self {
+    self.policy = policy;
+    self
+  }
+
+  /// Sets the signatures of the records, for the targets that support [SignatureMode]s.
+  pub fn with_signature_mode(mut self, mode: SignatureMode) -> Self {
+    self.signature_mode = mode;
+    self
+  }
+
+  pub fn policy(&self) -> FanoutPolicy {
+    self.policy
+  }
+
+  /// Publishes `revision` to every target concurrently, and returns how each of them did.
+  ///
+  /// In [SignatureMode::Auto], the revision is signed once for all the targets. If that fails,
+  /// or another signature mode is set, each target is left to sign it.
+  pub async fn publish_each(&self, name: &WritableName, revision: &Revision) -> FanoutOutcome {
+    let record = match self.signature_mode {
+      SignatureMode::Auto => revision.to_signed_bytes(name).ok(),
+      _ => None,
+    };
+    let record = record.as_deref();
+    let mode = self.signature_mode;
+    let results = futures::future::join_all(self.targets.iter().map(|target| async move {
+      let result = match record {
+        Some(record) => target.publish_record(name, revision, record).await,
+        None => target.publish_as(name, revision, mode).await,
+      };
+      TargetResult {
+        target: target.target(),
+        result,
+      }
+    }))
+    .await;
+    FanoutOutcome {
+      results,
+      policy: self.policy,
+    }
+  }
+}
+
+#[async_trait]
+impl NamePublisher for FanoutPublisher {
+  async fn publish(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+  ) -> Result<PublishReceipt, PublishError> {
+    self.publish_each(name, revision).await.into_receipt()
+  }
+
+  fn target(&self) -> String {
+    let targets: Vec<String> = self.targets.iter().map(|target| target.target()).collect();
+    format!("fanout to {}", targets.join(", "))
+  }
+}
+
+/// The results of publishing to each target of a [FanoutPublisher], in the order of its targets.
+#[derive(Debug)]
+pub struct FanoutOutcome {
+  pub results: Vec<TargetResult>,
+  pub policy: FanoutPolicy,
+}
+
+impl FanoutOutcome {
+  /// Returns `true` if enough targets succeeded to satisfy the policy. With no targets, nothing
+  /// was published, so this is `false`.
+  pub fn succeeded(&self) -> bool {
+    let mut results = self.results.iter();
+    match self.policy {
+      FanoutPolicy::All => !self.results.is_empty() && results.all(|result| result.result.is_ok()),
+      FanoutPolicy::Any => results.any(|result| result.result.is_ok()),
+    }
+  }
+
+  /// Returns the receipt of the first target that succeeded if the policy is satisfied, and
+  /// otherwise an error listing the targets that failed.
+  pub fn into_receipt(self) -> Result<PublishReceipt, PublishError> {
+    let succeeded = self.succeeded();
+    let total = self.results.len();
+    let mut failures = vec![];
+    for result in self.results {
+      match result.result {
+        Ok(receipt) if succeeded => return Ok(receipt),
+        Ok(_) => {}
+        Err(err_report) => failures.push(format!(
+          "{} failed: {}",
+          result.target,
+          error_summary(&err_report)
+        )),
+      }
+    }
+
+    let mut err_report = if total == 0 {
+      report!(PublishError).attach_printable("there are no targets to publish to")
+    } else {
+      report!(PublishError).attach_printable(format!(
+        "{} of {} targets failed",
+        failures.len(),
+        total
+      ))
+    };
+    for failure in failures {
+      err_report = err_report.attach_printable(failure);
+    }
+    Err(err_report)
+  }
+}
+
+/// How publishing to one target of a [FanoutPublisher] went.
+#[derive(Debug)]
+pub struct TargetResult {
+  /// The target, as described by [NamePublisher::target].
+  pub target: String,
+  pub result: Result<PublishReceipt, PublishError>,
+}
+
+impl TargetResult {
+  /// Returns a one-line description of why the target failed, or `None` if it succeeded.
+  pub fn error_message(&self) -> Option<String> {
+    self.result.as_ref().err().map(error_summary)
+  }
+}
+
+/// Describes the causes of a failure in one line, from the outermost to the root cause.
+fn error_summary(err_report: &Report<PublishError>) -> String {
+  let causes: Vec<String> = err_report
+    .frames()
+    .filter_map(|frame| match frame.kind() {
+      FrameKind::Context(context) => Some(context.to_string()),
+      FrameKind::Attachment(_) => None,
+    })
+    // the first context is the PublishError itself
+    .skip(1)
+    .collect();
+  if causes.is_empty() {
+    err_report.current_context().to_string()
+  } else {
+    causes.join(": ")
+  }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+  use super::*;
+  use crate::mock_service::MockService;
+  use http::{header::HeaderMap, StatusCode};
+  use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  };
+
+  /// A target that accepts or rejects every record, and counts the records it's given.
+  struct MockTarget {
+    id: &'static str,
+    fails: bool,
+    published: Arc<AtomicUsize>,
+  }
+
+  impl MockTarget {
+    fn boxed(id: &'static str, fails: bool) -> Box<dyn NamePublisher> {
+      Self::counted(id, fails, Arc::default())
+    }
+
+    fn counted(
+      id: &'static str,
+      fails: bool,
+      published: Arc<AtomicUsize>,
+    ) -> Box<dyn NamePublisher> {
+      Box::new(MockTarget {
+        id,
+        fails,
+        published,
+      })
+    }
+  }
+
+  #[async_trait]
+  impl NamePublisher for MockTarget {
+    async fn publish(
+      &self,
+      name: &WritableName,
+      revision: &Revision,
+    ) -> Result<PublishReceipt, PublishError> {
+      self.published.fetch_add(1, Ordering::SeqCst);
+      if self.fails {
+        return Err(report!(ClientError).change_context(PublishError));
+      }
+      Ok(PublishReceipt {
+        status: StatusCode::OK,
+        headers: HeaderMap::new(),
+        body: Some(serde_json::json!({ "id": self.id })),
+        record: revision.to_signed_bytes(name).unwrap(),
+        idempotency_key: None,
+      })
+    }
+
+    fn target(&self) -> String {
+      format!("mock {}", self.id)
+    }
+  }
+
+  #[tokio::test]
+  async fn fanout_policies() {
+    let name = WritableName::new();
+    let revision = Revision::v0(&name.to_name(), "/ipfs/fanout");
+
+    let all_ok = FanoutPublisher::new(vec![
+      MockTarget::boxed("a", false),
+      MockTarget::boxed("b", false),
+    ]);
+    let receipt = all_ok.publish(&name, &revision).await.unwrap();
+    assert_eq!(receipt.id(), Some("a"));
+
+    let one_fails = || vec![MockTarget::boxed("a", true), MockTarget::boxed("b", false)];
+    let outcome = FanoutPublisher::new(one_fails())
+      .publish_each(&name, &revision)
+      .await;
+    assert!(!outcome.succeeded());
+    assert_eq!(outcome.results.len(), 2);
+    assert_eq!(outcome.results[0].target, "mock a");
+    assert_eq!(
+      outcome.results[0].error_message().as_deref(),
+      Some("w3name client error")
+    );
+    assert!(outcome.results[1].result.is_ok());
+    let err = outcome.into_receipt().unwrap_err();
+    assert!(format!("{:?}", err).contains("1 of 2 targets failed"));
+    assert!(format!("{:?}", err).contains("mock a failed: w3name client error"));
+
+    let any = FanoutPublisher::new(one_fails()).with_policy(FanoutPolicy::Any);
+    let receipt = any.publish(&name, &revision).await.unwrap();
+    assert_eq!(receipt.id(), Some("b"));
+
+    let none_ok = FanoutPublisher::new(vec![
+      MockTarget::boxed("a", true),
+      MockTarget::boxed("b", true),
+    ])
+    .with_policy(FanoutPolicy::Any);
+    let err = none_ok.publish(&name, &revision).await.unwrap_err();
+    assert!(format!("{:?}", err).contains("2 of 2 targets failed"));
+
+    // with no targets, nothing is published, whatever the policy
+    for policy in [FanoutPolicy::All, FanoutPolicy::Any] {
+      let empty = FanoutPublisher::new(vec![]).with_policy(policy);
+      assert!(empty.publish(&name, &revision).await.is_err());
+    }
+  }
+
+  #[tokio::test]
+  async fn every_target_is_tried() {
+    let name = WritableName::new();
+    let revision = Revision::v0(&name.to_name(), "/ipfs/fanout");
+    let published = [Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0))];
+    // "a" alone satisfies the policy, but "b" is still tried
+    let fanout = FanoutPublisher::new(vec![
+      MockTarget::counted("a", false, published[0].clone()),
+      MockTarget::counted("b", true, published[1].clone()),
+    ])
+    .with_policy(FanoutPolicy::Any);
+    fanout.publish(&name, &revision).await.unwrap();
+    for count in &published {
+      assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+    assert_eq!(fanout.target(), "fanout to mock a, mock b");
+  }
+
+  #[tokio::test]
+  async fn w3name_client_as_target() {
+    let service = MockService::start();
+    let client = service.client();
+    let name = WritableName::new();
+    let revision = Revision::v0(&name.to_name(), "/ipfs/fanout");
+
+    let fanout = FanoutPublisher::new(vec![Box::new(client.clone())]);
+    let receipt = fanout.publish(&name, &revision).await.unwrap();
+    assert_eq!(receipt.id(), Some(name.to_string().as_str()));
+    assert_eq!(client.resolve(&name.to_name()).await.unwrap(), revision);
+    assert_eq!(
+      NamePublisher::target(&client),
+      format!("w3name {}", service.url())
+    );
+  }
+}
@@ -0,0 +1,1055 @@
+use std::{collections::BTreeMap, fmt::Display, sync::Arc, time::Duration};
+
+use error_stack::Report;
+use rand::Rng;
+use tokio::{
+  sync::{broadcast, mpsc, watch},
+  task::JoinHandle,
+  time::{sleep_until, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+  error::{APIError, ClientError},
+  timestamp::Timestamp,
+  Clock, Name, NameTracker, Publisher, Revision, SystemClock, WritableName,
+};
+use w3name_core::internal::{from_std, to_std, zero};
+
+/// Supplies the value to publish each time a name is republished.
+///
+/// Implemented for closures, e.g. `move || value.clone()`.
+pub trait ValueProvider: Send + Sync {
+  fn value(&self) -> String;
+}
+
+impl<F: Fn() -> String + Send + Sync> ValueProvider for F {
+  fn value(&self) -> String {
+    self()
+  }
+}
+
+/// A name kept alive by a [Republisher].
+pub struct RepublishEntry {
+  name: WritableName,
+  value: Arc<dyn ValueProvider>,
+  current: Option<Revision>,
+}
+
+impl RepublishEntry {
+  /// Creates an entry that publishes the values supplied by `value` for `name`.
+  pub fn new<V: ValueProvider + 'static>(name: WritableName, value: V) -> RepublishEntry {
+    RepublishEntry {
+      name,
+      value: Arc::new(value),
+      current: None,
+    }
+  }
+
+  /// Sets the currently published revision, so the first republish is scheduled from its validity.
+  ///
+  /// Without it, the name is published as soon as it's added.
+  pub fn with_current(mut self, revision: Revision) -> RepublishEntry {
+    self.current = Some(revision);
+    self
+  }
+}
+
+/// Settings for a [Republisher].
+#[derive(Clone, Debug)]
+pub struct RepublisherConfig {
+  /// How long before a record expires it is republished. Defaults to 7 days.
+  ///
+  /// A record that's published valid for less than this is republished halfway through its
+  /// validity instead, but no sooner than `min_retry_delay`.
+  pub republish_before: Duration,
+
+  /// Republishing happens up to this much earlier, chosen at random, so that many names don't all
+  /// get republished at once. Defaults to 1 hour.
+  pub jitter: Duration,
+
+  /// The delay before retrying a failed publish. It doubles after each consecutive failure, up to `max_retry_delay`.
+  /// Defaults to 10 seconds.
+  pub min_retry_delay: Duration,
+
+  /// The maximum delay between retries. Defaults to 1 hour.
+  pub max_retry_delay: Duration,
+
+  /// If set, every revision published is fed to this tracker.
+  pub tracker: Option<NameTracker>,
+
+  /// How many events a slow [subscriber](Republisher::subscribe) may fall behind before it misses
+  /// some. Defaults to 64.
+  pub capacity: usize,
+
+  /// The clock that record validities are compared with, to decide when to republish. Defaults
+  /// to the [SystemClock](crate::SystemClock).
+  pub clock: Arc<dyn Clock>,
+}
+
+impl Default for RepublisherConfig {
+  fn default() -> Self {
+    RepublisherConfig {
+      republish_before: Duration::from_secs(7 * 24 * 60 * 60),
+      jitter: Duration::from_secs(60 * 60),
+      min_retry_delay: Duration::from_secs(10),
+      max_retry_delay: Duration::from_secs(60 * 60),
+      tracker: None,
+      capacity: 64,
+      clock: Arc::new(SystemClock),
+    }
+  }
+}
+
+/// The state of a single name, as reported in [RepublisherStatus].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NameStatus {
+  /// The sequence number of the last revision published (or given to [RepublishEntry::with_current]).
+  pub sequence: Option<u64>,
+
+  /// The validity (end of life) of the last revision published.
+  pub validity: Option<Timestamp>,
+
+  /// The number of publish attempts that failed since the last success.
+  pub consecutive_failures: u32,
+
+  /// The error from the last failed publish attempt, if the last attempt failed.
+  pub last_error: Option<String>,
+}
+
+/// A snapshot of a [Republisher]'s state, for health reporting.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepublisherStatus {
+  /// The status of each name, keyed by the name's string form.
+  pub names: BTreeMap<String, NameStatus>,
+}
+
+impl RepublisherStatus {
+  /// Returns `true` if every name has a published revision and its last publish attempt succeeded.
+  pub fn is_healthy(&self) -> bool {
+    self
+      .names
+      .values()
+      .all(|status| status.sequence.is_some() && status.consecutive_failures == 0)
+  }
+}
+
+/// Whether a failed publish is likely to succeed when it's retried, as reported in
+/// [RepublisherEvent::Failed].
+///
+/// Either way, the publish is retried with backoff: a permanent failure, like a record the service
+/// rejects, may still go away when the [ValueProvider] supplies another value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureClass {
+  /// A network error, a timeout, or a server error (5xx, 408 or 429).
+  Transient,
+  /// The service rejected the publish (any other 4xx), so retrying the same value will fail again.
+  Permanent,
+}
+
+impl FailureClass {
+  /// Classifies a failed publish by the [APIError] in `err_report`, if any.
+  pub fn of(err_report: &Report<ClientError>) -> FailureClass {
+    match err_report.downcast_ref::<APIError>() {
+      Some(api_err)
+        if api_err.status_code.is_client_error()
+          && !matches!(api_err.status_code.as_u16(), 408 | 429) =>
+      {
+        FailureClass::Permanent
+      }
+      _ => FailureClass::Transient,
+    }
+  }
+}
+
+impl Display for FailureClass {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      FailureClass::Transient => write!(f, "transient"),
+      FailureClass::Permanent => write!(f, "permanent"),
+    }
+  }
+}
+
+/// Something a [Republisher] did, as sent to its [subscribers](Republisher::subscribe).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RepublisherEvent {
+  /// A name was added, with the sequence number of its [current](RepublishEntry::with_current)
+  /// revision if it was given.
+  Added { name: Name, sequence: Option<u64> },
+
+  /// A name was removed.
+  Removed { name: Name },
+
+  /// A name's record is about to expire, so it's being republished.
+  NearingExpiry { name: Name, validity: Timestamp },
+
+  /// A revision was published, replacing the one with sequence number `previous`, if any.
+  Republished {
+    revision: Box<Revision>,
+    previous: Option<u64>,
+  },
+
+  /// Publishing a name failed, for the `failures`th time in a row. It's retried at `retry_at`.
+  Failed {
+    name: Name,
+    class: FailureClass,
+    error: String,
+    failures: u32,
+    retry_at: Timestamp,
+  },
+
+  /// The revision published has a sequence number that's no higher than the one published before,
+  /// e.g. because the publisher resolved a stale record to increment.
+  Regressed {
+    name: Name,
+    previous: u64,
+    sequence: u64,
+  },
+
+  /// The names that were due have been published, or failed to. Also sent when none was
+  /// published, e.g. because the name that was due was removed meanwhile.
+  CycleFinished { republished: usize, failed: usize },
+}
+
+impl RepublisherEvent {
+  /// Returns the name the event is about, or `None` for [CycleFinished](Self::CycleFinished).
+  pub fn name(&self) -> Option<&Name> {
+    match self {
+      RepublisherEvent::Republished { revision, .. } => Some(revision.name()),
+      RepublisherEvent::Added { name, .. }
+      | RepublisherEvent::Removed { name }
+      | RepublisherEvent::NearingExpiry { name, .. }
+      | RepublisherEvent::Failed { name, .. }
+      | RepublisherEvent::Regressed { name, .. } => Some(name),
+      RepublisherEvent::CycleFinished { .. } => None,
+    }
+  }
+}
+
+impl Display for RepublisherEvent {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RepublisherEvent::Added { name, .. } => write!(f, "{} added", name),
+      RepublisherEvent::Removed { name } => write!(f, "{} removed", name),
+      RepublisherEvent::NearingExpiry { name, validity } => {
+        write!(
+          f,
+          "record of {} expires at {}, republishing",
+          name, validity
+        )
+      }
+      RepublisherEvent::Republished { revision, .. } => write!(
+        f,
+        "republished {} (sequence {})",
+        revision.name(),
+        revision.sequence()
+      ),
+      RepublisherEvent::Failed {
+        name,
+        class,
+        error,
+        failures,
+        retry_at,
+      } => write!(
+        f,
+        "failed to republish {} ({}, {} in a row): {}; retrying at {}",
+        name, class, failures, error, retry_at
+      ),
+      RepublisherEvent::Regressed {
+        name,
+        previous,
+        sequence,
+      } => write!(
+        f,
+        "sequence of {} went backwards: published {} after {}",
+        name, sequence, previous
+      ),
+      RepublisherEvent::CycleFinished {
+        republished,
+        failed,
+      } => write!(f, "republished {}, {} failed", republished, failed),
+    }
+  }
+}
+
+enum Command {
+  Add(Box<RepublishEntry>),
+  Remove(Box<Name>),
+}
+
+/// `Republisher` keeps a set of names alive by republishing them before their records expire.
+///
+/// It runs as a tokio task, which stops when the `Republisher` is dropped or its
+/// [cancellation token](Republisher::spawn_with_cancellation) is cancelled.
+///
+/// Each name is republished [`republish_before`](RepublisherConfig::republish_before) its record's
+/// validity ends (minus some random jitter), with the value supplied by the entry's [ValueProvider].
+/// Failed publishes are retried with exponential backoff. What happens is reported as
+/// [RepublisherEvent]s to its [subscribers](Republisher::subscribe).
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # async fn example(client: w3name::W3NameClient) {
+/// use std::sync::Arc;
+/// use w3name::{Republisher, RepublisherConfig, RepublishEntry, WritableName};
+///
+/// let name = WritableName::new();
+/// let republisher = Republisher::spawn(
+///   Arc::new(client),
+///   vec![RepublishEntry::new(name, || "/ipfs/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_string())],
+///   RepublisherConfig::default(),
+/// );
+///
+/// let mut status = republisher.status();
+/// while status.changed().await.is_ok() {
+///   println!("healthy: {}", status.borrow().is_healthy());
+/// }
+/// # }
+/// ```
+pub struct Republisher {
+  commands: mpsc::UnboundedSender<Command>,
+  status: watch::Receiver<RepublisherStatus>,
+  events: broadcast::Sender<RepublisherEvent>,
+  cancel: CancellationToken,
+  task: Option<JoinHandle<()>>,
+}
+
+impl Republisher {
+  /// Starts republishing `entries` with `publisher`. Must be called from within a tokio runtime.
+  pub fn spawn<I>(
+    publisher: Arc<dyn Publisher>,
+    entries: I,
+    config: RepublisherConfig,
+  ) -> Republisher
+  where
+    I: IntoIterator<Item = RepublishEntry>,
+  {
+    Self::spawn_with_cancellation(publisher, entries, config, CancellationToken::new())
+  }
+
+  /// Like [Republisher::spawn], but also stops when `cancel` is cancelled.
+  pub fn spawn_with_cancellation<I>(
+    publisher: Arc<dyn Publisher>,
+    entries: I,
+    config: RepublisherConfig,
+    cancel: CancellationToken,
+  ) -> Republisher
+  where
+    I: IntoIterator<Item = RepublishEntry>,
+  {
+    let (commands, command_rx) = mpsc::unbounded_channel();
+    let (status_tx, status) = watch::channel(RepublisherStatus::default());
+    let (events, _) = broadcast::channel(config.capacity.max(1));
+    for entry in entries {
+      // the receiver is alive, so this can't fail
+      let _ = commands.send(Command::Add(Box::new(entry)));
+    }
+
+    let worker = Worker {
+      publisher,
+      config,
+      scheduled: BTreeMap::new(),
+      status_tx,
+      events: events.clone(),
+    };
+    let task = tokio::spawn(worker.run(command_rx, cancel.clone()));
+    Republisher {
+      commands,
+      status,
+      events,
+      cancel,
+      task: Some(task),
+    }
+  }
+
+  /// Adds a name to the set, replacing any existing entry for the same name.
+  pub fn add(&self, entry: RepublishEntry) {
+    let _ = self.commands.send(Command::Add(Box::new(entry)));
+  }
+
+  /// Stops republishing `name`.
+  pub fn remove(&self, name: &Name) {
+    let _ = self.commands.send(Command::Remove(Box::new(name.clone())));
+  }
+
+  /// Returns a receiver for status updates, which is notified after every change.
+  pub fn status(&self) -> watch::Receiver<RepublisherStatus> {
+    self.status.clone()
+  }
+
+  /// Returns a receiver for the republisher's events, from now on.
+  ///
+  /// The entries given to [spawn](Self::spawn) are added once its task first runs, so subscribing
+  /// right after spawning, before awaiting anything, sees their [Added](RepublisherEvent::Added)
+  /// events on a current-thread runtime.
+  pub fn subscribe(&self) -> broadcast::Receiver<RepublisherEvent> {
+    self.events.subscribe()
+  }
+
+  /// Stops the republisher and waits for its task to finish.
+  ///
+  /// A publish that's in progress is abandoned.
+  pub async fn shutdown(mut self) {
+    self.cancel.cancel();
+    if let Some(task) = self.task.take() {
+      let _ = task.await;
+    }
+  }
+}
+
+impl Drop for Republisher {
+  fn drop(&mut self) {
+    self.cancel.cancel();
+  }
+}
+
+struct Scheduled {
+  entry: RepublishEntry,
+  due: Instant,
+  failures: u32,
+  last_error: Option<String>,
+}
+
+struct Worker {
+  publisher: Arc<dyn Publisher>,
+  config: RepublisherConfig,
+  scheduled: BTreeMap<String, Scheduled>,
+  status_tx: watch::Sender<RepublisherStatus>,
+  events: broadcast::Sender<RepublisherEvent>,
+}
+
+impl Worker {
+  async fn run(
+    mut self,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    cancel: CancellationToken,
+  ) {
+    loop {
+      let next_due = self.scheduled.values().map(|s| s.due).min();
+      let wait_for_next = async {
+        match next_due {
+          Some(due) => sleep_until(due).await,
+          None => std::future::pending().await,
+        }
+      };
+
+      tokio::select! {
+        _ = cancel.cancelled() => break,
+        command = commands.recv() => match command {
+          Some(command) => self.handle(command),
+          None => break,
+        },
+        _ = wait_for_next => {
+          tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = self.publish_due() => {}
+          }
+        }
+      }
+      self.update_status();
+    }
+    log::debug!("Republisher stopped");
+  }
+
+  fn handle(&mut self, command: Command) {
+    match command {
+      Command::Add(entry) => {
+        self.emit(RepublisherEvent::Added {
+          name: entry.name.to_name(),
+          sequence: entry.current.as_ref().map(|revision| revision.sequence()),
+        });
+        let due = match &entry.current {
+          Some(revision) => Instant::now() + self.republish_delay(revision),
+          None => Instant::now(),
+        };
+        let scheduled = Scheduled {
+          entry: *entry,
+          due,
+          failures: 0,
+          last_error: None,
+        };
+        self
+          .scheduled
+          .insert(scheduled.entry.name.to_string(), scheduled);
+      }
+      Command::Remove(name) => {
+        if self.scheduled.remove(&name.to_string()).is_some() {
+          self.emit(RepublisherEvent::Removed { name: *name });
+        }
+      }
+    }
+  }
+
+  async fn publish_due(&mut self) {
+    let now = Instant::now();
+    let (mut republished, mut failed) = (0, 0);
+    for scheduled in self.scheduled.values_mut().filter(|s| s.due <= now) {
+      let name = &scheduled.entry.name;
+      let value = scheduled.entry.value.value();
+      let previous = scheduled.entry.current.as_ref();
+      log::debug!("Republishing {}", name);
+      if let (Some(previous), 0) = (previous, scheduled.failures) {
+        let _ = self.events.send(RepublisherEvent::NearingExpiry {
+          name: name.to_name(),
+          validity: *previous.validity(),
+        });
+      }
+      let previous = previous.map(|revision| revision.sequence());
+
+      match self.publisher.publish_value(name, &value).await {
+        Ok(revision) => {
+          republished += 1;
+          if let Some(tracker) = &self.config.tracker {
+            tracker.observe_revision(&revision, self.config.clock.now());
+          }
+          if let Some(previous) = previous.filter(|previous| revision.sequence() <= *previous) {
+            let _ = self.events.send(RepublisherEvent::Regressed {
+              name: name.to_name(),
+              previous,
+              sequence: revision.sequence(),
+            });
+          }
+          scheduled.due =
+            Instant::now() + delay_after_publish(&revision, &self.config, self.config.clock.now());
+          scheduled.failures = 0;
+          scheduled.last_error = None;
+          let _ = self.events.send(RepublisherEvent::Republished {
+            revision: Box::new(revision.clone()),
+            previous,
+          });
+          scheduled.entry.current = Some(revision);
+        }
+        Err(err_report) => {
+          failed += 1;
+          log::warn!("Failed to republish {}: {:?}", name, err_report);
+          scheduled.failures += 1;
+          let delay = retry_delay(scheduled.failures, &self.config);
+          scheduled.due = Instant::now() + delay;
+          scheduled.last_error = Some(err_report.to_string());
+          let _ = self.events.send(RepublisherEvent::Failed {
+            name: name.to_name(),
+            class: FailureClass::of(&err_report),
+            error: err_report.to_string(),
+            failures: scheduled.failures,
+            retry_at: self.config.clock.now() + from_std(delay).unwrap_or_else(zero),
+          });
+        }
+      }
+    }
+    self.emit(RepublisherEvent::CycleFinished {
+      republished,
+      failed,
+    });
+  }
+
+  fn emit(&self, event: RepublisherEvent) {
+    // there may be no subscribers
+    let _ = self.events.send(event);
+  }
+
+  fn republish_delay(&self, revision: &Revision) -> Duration {
+    republish_delay(revision, &self.config, self.config.clock.now())
+  }
+
+  fn update_status(&self) {
+    let names = self
+      .scheduled
+      .iter()
+      .map(|(name, scheduled)| {
+        let current = scheduled.entry.current.as_ref();
+        let status = NameStatus {
+          sequence: current.map(|r| r.sequence()),
+          validity: current.map(|r| *r.validity()),
+          consecutive_failures: scheduled.failures,
+          last_error: scheduled.last_error.clone(),
+        };
+        (name.clone(), status)
+      })
+      .collect();
+    self.status_tx.send_if_modified(|status| {
+      let new_status = RepublisherStatus { names };
+      if *status == new_status {
+        false
+      } else {
+        *status = new_status;
+        true
+      }
+    });
+  }
+}
+
+/// Returns how long to wait before republishing `revision`: until `republish_before` its validity ends,
+/// minus a random jitter. Returns zero if that time has already passed.
+fn republish_delay(revision: &Revision, config: &RepublisherConfig, now: Timestamp) -> Duration {
+  let jitter = if config.jitter.is_zero() {
+    Duration::ZERO
+  } else {
+    rand::thread_rng().gen_range(Duration::ZERO..=config.jitter)
+  };
+  let until_expiry = to_std(*revision.validity() - now).unwrap_or_default();
+  until_expiry.saturating_sub(config.republish_before + jitter)
+}
+
+/// Returns how long to wait before republishing `revision`, which was just published. That's
+/// the [republish_delay], unless the revision expires too soon for that to be anything but zero,
+/// which would republish it in a tight loop: then it's half the time until it expires, but at
+/// least `min_retry_delay`.
+fn delay_after_publish(
+  revision: &Revision,
+  config: &RepublisherConfig,
+  now: Timestamp,
+) -> Duration {
+  let delay = republish_delay(revision, config, now);
+  if !delay.is_zero() {
+    return delay;
+  }
+  let until_expiry = to_std(*revision.validity() - now).unwrap_or_default();
+  let delay = (until_expiry / 2).max(config.min_retry_delay);
+  log::warn!(
+    "{} was published valid for {:?}, less than republish_before ({:?}); republishing it in {:?}",
+    revision.name(),
+    until_expiry,
+    config.republish_before,
+    delay
+  );
+  delay
+}
+
+/// Returns how long to wait before retrying after `failures` consecutive failures.
+fn retry_delay(failures: u32, config: &RepublisherConfig) -> Duration {
+  let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+  config
+    .min_retry_delay
+    .saturating_mul(factor)
+    .min(config.max_retry_delay)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::timestamp;
+  use crate::{error::ClientError, ManualClock};
+  use async_trait::async_trait;
+  use error_stack::{report, Result};
+  use std::sync::Mutex;
+  use w3name_core::internal::parse_rfc3339;
+
+  const HOUR: Duration = Duration::from_secs(60 * 60);
+
+  /// A publisher that records each call, and fails the first `failures` of them, rejecting the
+  /// record if `permanent` is set.
+  #[derive(Default)]
+  struct MockPublisher {
+    published: Mutex<Vec<(String, String)>>,
+    failures: Mutex<u32>,
+    permanent: bool,
+    /// How long published records are valid for. Defaults to 3 hours.
+    validity: Option<timestamp::Duration>,
+  }
+
+  impl MockPublisher {
+    fn count(&self) -> usize {
+      self.published.lock().unwrap().len()
+    }
+  }
+
+  #[async_trait]
+  impl Publisher for MockPublisher {
+    async fn publish_value(
+      &self,
+      name: &WritableName,
+      value: &str,
+    ) -> Result<Revision, ClientError> {
+      let mut published = self.published.lock().unwrap();
+      published.push((name.to_string(), value.to_string()));
+      let mut failures = self.failures.lock().unwrap();
+      if *failures > 0 {
+        *failures -= 1;
+        if self.permanent {
+          return Err(
+            report!(APIError {
+              message: "invalid record".to_string(),
+              status_code: http::StatusCode::BAD_REQUEST,
+            })
+            .change_context(ClientError),
+          );
+        }
+        return Err(report!(ClientError));
+      }
+      let validity = SystemClock.now()
+        + self
+          .validity
+          .unwrap_or_else(|| timestamp::Duration::hours(3));
+      Ok(Revision::new(
+        &name.to_name(),
+        value,
+        validity,
+        timestamp::Duration::hours(1),
+        published.len() as u64,
+      ))
+    }
+  }
+
+  fn config() -> RepublisherConfig {
+    RepublisherConfig {
+      republish_before: HOUR,
+      jitter: Duration::ZERO,
+      min_retry_delay: Duration::from_secs(10),
+      max_retry_delay: Duration::from_secs(60),
+      tracker: None,
+      capacity: 64,
+      clock: Arc::new(SystemClock),
+    }
+  }
+
+  /// Lets the republisher task run until it's waiting again.
+  async fn settle() {
+    for _ in 0..10 {
+      tokio::task::yield_now().await;
+    }
+  }
+
+  /// Describes the events received so far, without the parts that depend on the wall clock.
+  fn received(events: &mut broadcast::Receiver<RepublisherEvent>) -> Vec<String> {
+    let mut received = vec![];
+    while let Ok(event) = events.try_recv() {
+      received.push(match event {
+        RepublisherEvent::Added { sequence, .. } => format!("added {:?}", sequence),
+        RepublisherEvent::Removed { .. } => "removed".to_string(),
+        RepublisherEvent::NearingExpiry { .. } => "nearing expiry".to_string(),
+        RepublisherEvent::Republished { revision, previous } => {
+          format!("republished {} after {:?}", revision.sequence(), previous)
+        }
+        RepublisherEvent::Failed {
+          class, failures, ..
+        } => format!("failed {} {}", class, failures),
+        RepublisherEvent::Regressed {
+          previous, sequence, ..
+        } => format!("regressed {} after {}", sequence, previous),
+        RepublisherEvent::CycleFinished {
+          republished,
+          failed,
+        } => format!("cycle {} {}", republished, failed),
+      });
+    }
+    received
+  }
+
+  fn spawn_one(publisher: Arc<MockPublisher>, entry: RepublishEntry) -> Republisher {
+    Republisher::spawn(publisher, vec![entry], config())
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn events_of_successful_republishes() {
+    let publisher = Arc::new(MockPublisher::default());
+    let name = WritableName::new();
+    let republisher = spawn_one(
+      publisher,
+      RepublishEntry::new(name.clone(), || "/ipfs/value".to_string()),
+    );
+    let mut events = republisher.subscribe();
+
+    settle().await;
+    assert_eq!(
+      received(&mut events),
+      vec!["added None", "republished 1 after None", "cycle 1 0"]
+    );
+    tokio::time::advance(2 * HOUR).await;
+    settle().await;
+    assert_eq!(
+      received(&mut events),
+      vec!["nearing expiry", "republished 2 after Some(1)", "cycle 1 0"]
+    );
+
+    republisher.remove(&name.to_name());
+    settle().await;
+    assert_eq!(received(&mut events), vec!["removed"]);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn events_of_a_transient_failure() {
+    let publisher = Arc::new(MockPublisher::default());
+    *publisher.failures.lock().unwrap() = 1;
+    let republisher = spawn_one(
+      publisher,
+      RepublishEntry::new(WritableName::new(), || "/ipfs/value".to_string()),
+    );
+    let mut events = republisher.subscribe();
+
+    settle().await;
+    assert_eq!(
+      received(&mut events),
+      vec!["added None", "failed transient 1", "cycle 0 1"]
+    );
+    tokio::time::advance(Duration::from_secs(10)).await;
+    settle().await;
+    assert_eq!(
+      received(&mut events),
+      vec!["republished 2 after None", "cycle 1 0"]
+    );
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn events_of_a_permanent_failure() {
+    let publisher = Arc::new(MockPublisher {
+      failures: Mutex::new(u32::MAX),
+      permanent: true,
+      ..Default::default()
+    });
+    let republisher = spawn_one(
+      publisher,
+      RepublishEntry::new(WritableName::new(), || "/ipfs/value".to_string()),
+    );
+    let mut events = republisher.subscribe();
+
+    settle().await;
+    tokio::time::advance(Duration::from_secs(10)).await;
+    settle().await;
+    assert_eq!(
+      received(&mut events),
+      vec![
+        "added None",
+        "failed permanent 1",
+        "cycle 0 1",
+        "failed permanent 2",
+        "cycle 0 1"
+      ]
+    );
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn events_of_a_regression() {
+    let publisher = Arc::new(MockPublisher::default());
+    let name = WritableName::new();
+    // expires within `republish_before`, so it's republished right away
+    let current = Revision::new(
+      &name.to_name(),
+      "/ipfs/old",
+      SystemClock.now() + timestamp::Duration::minutes(30),
+      timestamp::Duration::hours(1),
+      5,
+    );
+    let republisher = spawn_one(
+      publisher,
+      RepublishEntry::new(name, || "/ipfs/value".to_string()).with_current(current),
+    );
+    let mut events = republisher.subscribe();
+
+    settle().await;
+    assert_eq!(
+      received(&mut events),
+      vec![
+        "added Some(5)",
+        "nearing expiry",
+        "regressed 1 after 5",
+        "republished 1 after Some(5)",
+        "cycle 1 0"
+      ]
+    );
+  }
+
+  #[test]
+  fn scheduling_math() {
+    let name = WritableName::new().to_name();
+    let now = SystemClock.now();
+    let revision = Revision::new(
+      &name,
+      "v",
+      now + timestamp::Duration::hours(3),
+      timestamp::Duration::hours(1),
+      0,
+    );
+    assert_eq!(republish_delay(&revision, &config(), now), 2 * HOUR);
+
+    let jittered = RepublisherConfig {
+      jitter: HOUR / 2,
+      ..config()
+    };
+    for _ in 0..20 {
+      let delay = republish_delay(&revision, &jittered, now);
+      assert!(delay >= 2 * HOUR - HOUR / 2 && delay <= 2 * HOUR);
+    }
+
+    let expired = Revision::new(
+      &name,
+      "v",
+      now - timestamp::Duration::hours(1),
+      timestamp::Duration::hours(1),
+      0,
+    );
+    assert_eq!(republish_delay(&expired, &config(), now), Duration::ZERO);
+    assert_eq!(
+      delay_after_publish(&expired, &config(), now),
+      config().min_retry_delay
+    );
+    let short = Revision::new(
+      &name,
+      "v",
+      now + timestamp::Duration::minutes(30),
+      timestamp::Duration::hours(1),
+      0,
+    );
+    assert_eq!(delay_after_publish(&short, &config(), now), HOUR / 4);
+    assert_eq!(delay_after_publish(&revision, &config(), now), 2 * HOUR);
+
+    let delays: Vec<u64> = (1..=5)
+      .map(|n| retry_delay(n, &config()).as_secs())
+      .collect();
+    assert_eq!(delays, vec![10, 20, 40, 60, 60]);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn republishes_before_expiry() {
+    let publisher = Arc::new(MockPublisher::default());
+    let name = WritableName::new();
+    let republisher = Republisher::spawn(
+      publisher.clone(),
+      vec![RepublishEntry::new(name.clone(), || {
+        "/ipfs/value".to_string()
+      })],
+      config(),
+    );
+
+    settle().await;
+    assert_eq!(publisher.count(), 1);
+    let status = republisher.status().borrow().clone();
+    assert!(status.is_healthy());
+    assert_eq!(status.names[&name.to_string()].sequence, Some(1));
+
+    // records are valid for 3 hours, and republished 1 hour before they expire
+    tokio::time::advance(2 * HOUR - Duration::from_secs(60)).await;
+    settle().await;
+    assert_eq!(publisher.count(), 1);
+    tokio::time::advance(Duration::from_secs(120)).await;
+    settle().await;
+    assert_eq!(publisher.count(), 2);
+
+    republisher.shutdown().await;
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn short_validities_dont_republish_in_a_loop() {
+    // records are valid for 30 minutes, less than `republish_before`
+    let publisher = Arc::new(MockPublisher {
+      validity: Some(timestamp::Duration::minutes(30)),
+      ..Default::default()
+    });
+    let name = WritableName::new();
+    let republisher = spawn_one(
+      publisher.clone(),
+      RepublishEntry::new(name, || "/ipfs/value".to_string()),
+    );
+
+    settle().await;
+    assert_eq!(publisher.count(), 1);
+
+    // so they're republished halfway through it
+    tokio::time::advance(HOUR / 4 - Duration::from_secs(60)).await;
+    settle().await;
+    assert_eq!(publisher.count(), 1);
+    tokio::time::advance(Duration::from_secs(120)).await;
+    settle().await;
+    assert_eq!(publisher.count(), 2);
+
+    republisher.shutdown().await;
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn schedules_by_the_configured_clock() {
+    let publisher = Arc::new(MockPublisher::default());
+    let name = WritableName::new();
+    // by the system clock this expires within `republish_before`, but the republisher's clock
+    // is years behind
+    let current = Revision::new(
+      &name.to_name(),
+      "/ipfs/old",
+      SystemClock.now() + timestamp::Duration::minutes(30),
+      timestamp::Duration::hours(1),
+      5,
+    );
+    let clock = Arc::new(ManualClock::new(
+      parse_rfc3339("2020-01-01T00:00:00Z").unwrap(),
+    ));
+    let republisher = Republisher::spawn(
+      publisher.clone(),
+      vec![RepublishEntry::new(name, || "/ipfs/value".to_string()).with_current(current)],
+      RepublisherConfig { clock, ..config() },
+    );
+
+    settle().await;
+    assert_eq!(publisher.count(), 0);
+
+    republisher.shutdown().await;
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn retries_with_backoff() {
+    let publisher = Arc::new(MockPublisher::default());
+    *publisher.failures.lock().unwrap() = 2;
+    let name = WritableName::new();
+    let republisher = Republisher::spawn(
+      publisher.clone(),
+      vec![RepublishEntry::new(name.clone(), || {
+        "/ipfs/value".to_string()
+      })],
+      config(),
+    );
+
+    settle().await;
+    assert_eq!(publisher.count(), 1);
+    let status = republisher.status().borrow().clone();
+    assert!(!status.is_healthy());
+    assert_eq!(status.names[&name.to_string()].consecutive_failures, 1);
+
+    tokio::time::advance(Duration::from_secs(10)).await;
+    settle().await;
+    assert_eq!(publisher.count(), 2);
+
+    // the second retry waits twice as long
+    tokio::time::advance(Duration::from_secs(15)).await;
+    settle().await;
+    assert_eq!(publisher.count(), 2);
+    tokio::time::advance(Duration::from_secs(5)).await;
+    settle().await;
+    assert_eq!(publisher.count(), 3);
+    assert!(republisher.status().borrow().is_healthy());
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn add_remove_and_cancel() {
+    let publisher = Arc::new(MockPublisher::default());
+    let cancel = CancellationToken::new();
+    let republisher =
+      Republisher::spawn_with_cancellation(publisher.clone(), vec![], config(), cancel.clone());
+
+    let first = WritableName::new();
+    let second = WritableName::new();
+    republisher.add(RepublishEntry::new(first.clone(), || "first".to_string()));
+    let current = Revision::new(
+      &second.to_name(),
+      "second",
+      SystemClock.now() + timestamp::Duration::hours(3),
+      timestamp::Duration::hours(1),
+      5,
+    );
+    republisher
+      .add(RepublishEntry::new(second.clone(), || "second".to_string()).with_current(current));
+    settle().await;
+
+    // only the name without a current revision is published right away
+    assert_eq!(publisher.count(), 1);
+    assert_eq!(republisher.status().borrow().names.len(), 2);
+
+    republisher.remove(&first.to_name());
+    settle().await;
+    assert_eq!(republisher.status().borrow().names.len(), 1);
+
+    cancel.cancel();
+    settle().await;
+    tokio::time::advance(3 * HOUR).await;
+    settle().await;
+    assert_eq!(publisher.count(), 1);
+  }
+}
@@ -0,0 +1,1221 @@
+use std::{
+  collections::HashMap,
+  ops::Deref,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use error_stack::{report, AttachmentKind, FrameKind, Report, Result, ResultExt};
+use http::{
+  header::{HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_TYPE},
+  Method, StatusCode,
+};
+use tokio::sync::watch;
+use url::Url;
+
+#[cfg(feature = "client")]
+use crate::transport::ReqwestTransport;
+use crate::{
+  client, content_encoding,
+  error::{APIError, ClientError, HttpError, NetworkCause, UnexpectedAPIResponse},
+  ipns::MAX_RECORD_SIZE,
+  timestamp::Timestamp,
+  transport::{self, HttpRequest, HttpTransport},
+  Clock, Name, Revision, SystemClock, W3NameClient,
+};
+
+/// The gateways a [GatewayResolver] uses by default, in the order they're tried.
+const DEFAULT_GATEWAYS: &[&str] = &[
+  "https://trustless-gateway.link",
+  "https://ipfs.io",
+  "https://dweb.link",
+];
+const IPNS_RECORD_CONTENT_TYPE: &str = "application/vnd.ipfs.ipns-record";
+
+/// A source of name records.
+///
+/// Implementations must only return revisions whose record was validated against the
+/// name's public key, so callers can trust a resolved [Revision] regardless of where it came from.
+#[async_trait]
+pub trait Resolver: Send + Sync {
+  /// Resolves the latest [Revision] for `name`, along with where it came from.
+  async fn resolve(&self, name: &Name) -> Result<ResolvedRevision, ClientError>;
+
+  /// Like [resolve](Self::resolve), but also returns the serialized, signed record that the
+  /// revision was read from, e.g. to cache or archive it.
+  ///
+  /// The record is `None` if the resolver doesn't keep it, which is what the default
+  /// implementation returns.
+  async fn resolve_with_record(
+    &self,
+    name: &Name,
+  ) -> Result<(ResolvedRevision, Option<Vec<u8>>), ClientError> {
+    Ok((self.resolve(name).await?, None))
+  }
+}
+
+#[async_trait]
+impl Resolver for W3NameClient {
+  async fn resolve(&self, name: &Name) -> Result<ResolvedRevision, ClientError> {
+    W3NameClient::resolve_detailed(self, name).await
+  }
+
+  async fn resolve_with_record(
+    &self,
+    name: &Name,
+  ) -> Result<(ResolvedRevision, Option<Vec<u8>>), ClientError> {
+    let started = Instant::now();
+    let record = self.fetch_record(name).await?;
+    let revision = client::validate_record(name, &record)?;
+    let source = SourceInfo::http(SourceKind::Service, self.endpoint().clone(), started);
+    Ok((ResolvedRevision::fetched(revision, source), Some(record)))
+  }
+}
+
+/// A resolved [Revision], with the provenance of its record, for auditing.
+///
+/// It derefs to the [Revision], so it can mostly be used like one. Fields may be added in later
+/// versions, so outside this crate it's created with [fetched](Self::fetched) or
+/// [cached](Self::cached).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResolvedRevision {
+  pub revision: Revision,
+
+  /// Where the record came from.
+  pub source: SourceInfo,
+
+  /// When the record was fetched from its source. For cached records, this is when it was cached.
+  pub fetched_at: Timestamp,
+
+  /// Whether the record was served from a local cache rather than fetched just now.
+  pub from_cache: bool,
+
+  /// When the revision's sequence was first seen for its name, by this process or a record store
+  /// that remembers it, like the `store` feature's `RecordStore::note_seen`. The resolvers in this
+  /// crate are stateless, so they leave it `None`.
+  pub first_seen_at: Option<Timestamp>,
+}
+
+impl ResolvedRevision {
+  /// Wraps a `revision` that was just fetched from `source`.
+  pub fn fetched(revision: Revision, source: SourceInfo) -> ResolvedRevision {
+    ResolvedRevision {
+      revision,
+      source,
+      fetched_at: SystemClock.now(),
+      from_cache: false,
+      first_seen_at: None,
+    }
+  }
+
+  /// Wraps a `revision` that a local cache served from `source`, having stored it at `fetched_at`.
+  pub fn cached(revision: Revision, source: SourceInfo, fetched_at: Timestamp) -> ResolvedRevision {
+    ResolvedRevision {
+      revision,
+      source,
+      fetched_at,
+      from_cache: true,
+      first_seen_at: None,
+    }
+  }
+
+  /// Sets when the revision's sequence was first seen for its name.
+  pub fn with_first_seen_at(mut self, first_seen_at: Option<Timestamp>) -> ResolvedRevision {
+    self.first_seen_at = first_seen_at;
+    self
+  }
+
+  /// Unwraps the [Revision], dropping the provenance.
+  pub fn into_revision(self) -> Revision {
+    self.revision
+  }
+}
+
+impl Deref for ResolvedRevision {
+  type Target = Revision;
+
+  fn deref(&self) -> &Revision {
+    &self.revision
+  }
+}
+
+/// The kind of source a [ResolvedRevision] came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind {
+  /// A w3name service.
+  Service,
+  /// A trustless HTTP gateway.
+  Gateway,
+  /// A local cache.
+  Cache,
+}
+
+/// Where a [ResolvedRevision] came from, and how it was resolved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceInfo {
+  pub kind: SourceKind,
+
+  /// The service endpoint or gateway that served the record, if it came over HTTP.
+  pub url: Option<Url>,
+
+  /// How long resolving took, including any sources that were tried first and failed.
+  pub elapsed: Duration,
+
+  /// Whether the revision was checked not to have expired. The resolvers in this crate don't check
+  /// expiry, so that callers can decide what to do with expired records.
+  pub expiry_checked: bool,
+}
+
+impl SourceInfo {
+  /// Describes a record fetched from `url` by a resolver that started at `started`.
+  pub fn http(kind: SourceKind, url: Url, started: Instant) -> SourceInfo {
+    SourceInfo {
+      kind,
+      url: Some(url),
+      elapsed: started.elapsed(),
+      expiry_checked: false,
+    }
+  }
+}
+
+/// Returns the public gateways a [GatewayResolver] uses by default: `https://trustless-gateway.link`,
+/// `https://ipfs.io` and `https://dweb.link`, in that order.
+pub fn default_gateways() -> Vec<Url> {
+  DEFAULT_GATEWAYS
+    .iter()
+    .map(|url| Url::parse(url).unwrap())
+    .collect()
+}
+
+/// When a [GatewayResolver] stops asking a failing gateway.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealthPolicy {
+  /// The number of consecutive failures after which a gateway is skipped. Defaults to 3.
+  ///
+  /// Responses saying the gateway has no record for a name (404) don't count as failures.
+  pub max_failures: u32,
+
+  /// How long a gateway is skipped for. Defaults to 5 minutes.
+  ///
+  /// After that, the gateway is tried again, and skipped for another cool-down if it fails again.
+  pub cool_down: Duration,
+}
+
+impl Default for HealthPolicy {
+  fn default() -> Self {
+    HealthPolicy {
+      max_failures: 3,
+      cool_down: Duration::from_secs(5 * 60),
+    }
+  }
+}
+
+/// The health of a single gateway, as reported by [GatewayResolver::gateway_status].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GatewayStatus {
+  /// The gateway's base URL.
+  pub url: Url,
+
+  /// The number of requests that failed since the last success.
+  pub consecutive_failures: u32,
+
+  /// If the gateway is being skipped, how much longer it will be skipped for.
+  pub cooling_down_for: Option<Duration>,
+}
+
+impl GatewayStatus {
+  /// Returns `true` if the gateway isn't being skipped.
+  pub fn is_available(&self) -> bool {
+    self.cooling_down_for.is_none()
+  }
+}
+
+#[derive(Default)]
+struct Health {
+  consecutive_failures: u32,
+  skip_until: Option<Instant>,
+}
+
+struct Gateway {
+  base: Url,
+  health: Mutex<Health>,
+}
+
+impl Gateway {
+  /// Returns how much longer the gateway is skipped for, if it is.
+  fn cooling_down_for(&self) -> Option<Duration> {
+    let health = self.health.lock().unwrap();
+    health
+      .skip_until
+      .and_then(|until| until.checked_duration_since(Instant::now()))
+      .filter(|remaining| !remaining.is_zero())
+  }
+
+  fn succeeded(&self) {
+    *self.health.lock().unwrap() = Health::default();
+  }
+
+  fn failed(&self, policy: &HealthPolicy) {
+    let mut health = self.health.lock().unwrap();
+    health.consecutive_failures += 1;
+    if health.consecutive_failures >= policy.max_failures {
+      log::warn!(
+        "gateway {} failed {} times in a row, skipping it for {:?}",
+        self.base,
+        health.consecutive_failures,
+        policy.cool_down
+      );
+      health.skip_until = Some(Instant::now() + policy.cool_down);
+    }
+  }
+
+  fn status(&self) -> GatewayStatus {
+    let consecutive_failures = self.health.lock().unwrap().consecutive_failures;
+    GatewayStatus {
+      url: self.base.clone(),
+      consecutive_failures,
+      cooling_down_for: self.cooling_down_for(),
+    }
+  }
+}
+
+/// A [Resolver] that fetches records from HTTP gateways that support
+/// [trustless IPNS record retrieval](https://specs.ipfs.tech/http-gateways/trustless-gateway/).
+///
+/// Records are validated locally, so the gateways don't need to be trusted. Responses that aren't
+/// IPNS records, or that exceed the 10 KiB record size limit, are rejected without being downloaded in full.
+///
+/// Gateways are tried in order until one returns a valid record. A gateway that keeps failing is
+/// skipped for a while, according to the [HealthPolicy]; see [gateway_status](Self::gateway_status).
+/// By default, the [default_gateways] are used.
+pub struct GatewayResolver {
+  gateways: Vec<Gateway>,
+  policy: HealthPolicy,
+  transport: Arc<dyn HttpTransport>,
+}
+
+impl GatewayResolver {
+  /// Creates a `GatewayResolver` for the gateway at `base`, e.g. `https://ipfs.io`.
+  #[cfg(feature = "client")]
+  pub fn new(base: Url) -> Self {
+    Self::with_gateways(vec![base])
+  }
+
+  /// Creates a `GatewayResolver` that tries each of `gateways` in order.
+  ///
+  /// The list may be empty, in which case every resolve fails without contacting anything.
+  #[cfg(feature = "client")]
+  pub fn with_gateways(gateways: Vec<Url>) -> Self {
+    Self::with_gateways_and_transport(gateways, Arc::new(ReqwestTransport::default()))
+  }
+
+  /// Creates a `GatewayResolver` that tries each of `gateways` in order, and fetches records with
+  /// `transport`. Unlike [with_gateways](Self::with_gateways), this doesn't need the `client`
+  /// feature.
+  pub fn with_gateways_and_transport(
+    gateways: Vec<Url>,
+    transport: Arc<dyn HttpTransport>,
+  ) -> Self {
+    GatewayResolver {
+      gateways: gateways
+        .into_iter()
+        .map(|base| Gateway {
+          base,
+          health: Mutex::new(Health::default()),
+        })
+        .collect(),
+      policy: HealthPolicy::default(),
+      transport,
+    }
+  }
+
+  /// Sets when failing gateways are skipped.
+  pub fn with_health_policy(mut self, policy: HealthPolicy) -> Self {
+    self.policy = policy;
+    self
+  }
+
+  /// Fetches records with `transport` instead of reqwest.
+  pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+    self.transport = transport;
+    self
+  }
+
+  /// Returns the health of each gateway, in the order they're tried.
+  pub fn gateway_status(&self) -> Vec<GatewayStatus> {
+    self.gateways.iter().map(Gateway::status).collect()
+  }
+
+  /// Fetches the serialized IPNS record for `name` from the first gateway that returns one.
+  ///
+  /// Like [W3NameClient::fetch_record], the record is **not** validated, and unlike
+  /// [resolve](Resolver::resolve), gateways aren't skipped or marked as failing. Use `resolve`
+  /// unless you need the raw record bytes, e.g. to compare what each gateway serves.
+  pub async fn fetch_record(&self, name: &Name) -> Result<Vec<u8>, ClientError> {
+    let mut last_err: Option<error_stack::Report<ClientError>> = None;
+    for gateway in &self.gateways {
+      match self.fetch_from(&gateway.base, name).await {
+        Ok(record) => return Ok(record),
+        Err(err_report) => {
+          log::debug!("Gateway {} failed: {:?}", gateway.base, err_report);
+          last_err = Some(err_report.attach_printable(format!("gateway {} failed", gateway.base)));
+        }
+      }
+    }
+    Err(
+      last_err
+        .unwrap_or_else(|| report!(ClientError).attach_printable("no gateways are configured")),
+    )
+  }
+
+  async fn resolve_from(
+    &self,
+    base: &Url,
+    name: &Name,
+  ) -> Result<(Revision, Vec<u8>), ClientError> {
+    let record_bytes = self.fetch_from(base, name).await?;
+    let revision =
+      Revision::try_from_record_bytes(&record_bytes, name).change_context(ClientError)?;
+    Ok((revision, record_bytes))
+  }
+
+  async fn fetch_from(&self, base: &Url, name: &Name) -> Result<Vec<u8>, ClientError> {
+    let mut url = base.clone();
+    url.set_path(format!("ipns/{}", name.to_string()).as_str());
+
+    log::debug!("HTTP GET {}", url);
+
+    let req = HttpRequest::new(Method::GET, url)
+      .header(ACCEPT, HeaderValue::from_static(IPNS_RECORD_CONTENT_TYPE))
+      .header(ACCEPT_ENCODING, HeaderValue::from_static("identity"))
+      .max_response_size(MAX_RECORD_SIZE);
+    let res = transport::execute(self.transport.as_ref(), req).await?;
+
+    log::debug!("Response status: {}", res.status);
+
+    let status = res.status;
+    if !status.is_success() {
+      return Err(
+        report!(APIError {
+          message: format!("gateway {} returned an error", base),
+          status_code: status,
+        })
+        .change_context(ClientError),
+      );
+    }
+
+    let content_type = res
+      .headers
+      .get(CONTENT_TYPE)
+      .and_then(|value| value.to_str().ok())
+      .unwrap_or_default();
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    if !essence.eq_ignore_ascii_case(IPNS_RECORD_CONTENT_TYPE) {
+      return Err(
+        report!(UnexpectedAPIResponse)
+          .attach_printable(format!(
+            "gateway {} returned content type {:?}, expected {}",
+            base, content_type, IPNS_RECORD_CONTENT_TYPE
+          ))
+          .change_context(ClientError),
+      );
+    }
+
+    content_encoding::decode(&res.headers, res.body, MAX_RECORD_SIZE)
+      .attach_printable(format!("gateway: {}", base))
+  }
+}
+
+#[cfg(feature = "client")]
+impl Default for GatewayResolver {
+  fn default() -> Self {
+    Self::with_gateways(default_gateways())
+  }
+}
+
+#[async_trait]
+impl Resolver for GatewayResolver {
+  /// Resolves `name` from the first gateway that returns a valid record.
+  ///
+  /// If every gateway fails, the last gateway's error is returned, and the others are listed in it.
+  async fn resolve(&self, name: &Name) -> Result<ResolvedRevision, ClientError> {
+    let (resolved, _) = self.resolve_with_record(name).await?;
+    Ok(resolved)
+  }
+
+  async fn resolve_with_record(
+    &self,
+    name: &Name,
+  ) -> Result<(ResolvedRevision, Option<Vec<u8>>), ClientError> {
+    let started = Instant::now();
+    let mut last_err: Option<error_stack::Report<ClientError>> = None;
+    let mut failures = Vec::new();
+
+    for gateway in &self.gateways {
+      if let Some(remaining) = gateway.cooling_down_for() {
+        log::debug!(
+          "Skipping gateway {} for another {:?}",
+          gateway.base,
+          remaining
+        );
+        failures.push(format!(
+          "gateway {} was skipped after repeated failures",
+          gateway.base
+        ));
+        continue;
+      }
+
+      match self.resolve_from(&gateway.base, name).await {
+        Ok((revision, record)) => {
+          gateway.succeeded();
+          let source = SourceInfo::http(SourceKind::Gateway, gateway.base.clone(), started);
+          return Ok((ResolvedRevision::fetched(revision, source), Some(record)));
+        }
+        Err(err_report) => {
+          log::debug!("Gateway {} failed: {:?}", gateway.base, err_report);
+          let not_found = err_report
+            .downcast_ref::<APIError>()
+            .is_some_and(|api_err| api_err.status_code == 404);
+          if !not_found {
+            gateway.failed(&self.policy);
+          }
+          failures.push(format!("gateway {} failed", gateway.base));
+          last_err = Some(err_report);
+        }
+      }
+    }
+
+    let mut err_report = match last_err {
+      Some(err_report) => err_report,
+      None if self.gateways.is_empty() => {
+        report!(ClientError).attach_printable("no gateways are configured")
+      }
+      None => report!(ClientError)
+        .attach_printable("every gateway is cooling down after repeated failures"),
+    };
+    for failure in failures {
+      err_report = err_report.attach_printable(failure);
+    }
+    Err(err_report)
+  }
+}
+
+/// A [Resolver] that tries a primary resolver, e.g. a [W3NameClient], and then each fallback in
+/// order, e.g. a [GatewayResolver], until one of them resolves the name.
+///
+/// The fallbacks are tried when the primary has no record for the name (404) too, since a gateway
+/// may still have a record that the service lost. If every resolver fails, the primary's error is
+/// returned, with the fallbacks' failures listed in it, so a name that no source has a record for
+/// still fails with the primary's 404.
+pub struct ChainResolver {
+  primary: Arc<dyn Resolver>,
+  fallbacks: Vec<Arc<dyn Resolver>>,
+}
+
+impl ChainResolver {
+  /// Creates a chain that only asks `primary`, until fallbacks are added.
+  pub fn new(primary: Arc<dyn Resolver>) -> Self {
+    ChainResolver {
+      primary,
+      fallbacks: vec![],
+    }
+  }
+
+  /// Adds a resolver to try after the primary and the fallbacks added before it.
+  pub fn with_fallback(mut self, fallback: Arc<dyn Resolver>) -> Self {
+    self.fallbacks.push(fallback);
+    self
+  }
+}
+
+#[async_trait]
+impl Resolver for ChainResolver {
+  async fn resolve(&self, name: &Name) -> Result<ResolvedRevision, ClientError> {
+    let (resolved, _) = self.resolve_with_record(name).await?;
+    Ok(resolved)
+  }
+
+  /// Resolves `name` like [resolve](Self::resolve), returning the record of the resolver that
+  /// answered.
+  async fn resolve_with_record(
+    &self,
+    name: &Name,
+  ) -> Result<(ResolvedRevision, Option<Vec<u8>>), ClientError> {
+    let started = Instant::now();
+    let mut err_report = match self.primary.resolve_with_record(name).await {
+      Ok(resolved) => return Ok(resolved),
+      Err(err_report) => err_report,
+    };
+    if self.fallbacks.is_empty() {
+      return Err(err_report);
+    }
+    log::debug!(
+      "Primary resolver failed for {}, trying fallback resolvers",
+      name
+    );
+
+    for (i, fallback) in self.fallbacks.iter().enumerate() {
+      match fallback.resolve_with_record(name).await {
+        Ok((mut resolved, record)) => {
+          log::info!("Fallback resolver {} resolved {}", i, name);
+          resolved.source.elapsed = started.elapsed();
+          return Ok((resolved, record));
+        }
+        Err(fallback_err) => {
+          log::debug!("Fallback resolver {} failed: {:?}", i, fallback_err);
+          err_report = err_report.attach_printable(format!(
+            "fallback resolver {} failed: {}",
+            i,
+            describe_failure(&fallback_err)
+          ));
+        }
+      }
+    }
+    Err(err_report)
+  }
+}
+
+/// A one-line description of a failed resolve, for listing it in another resolver's error.
+fn describe_failure(err_report: &Report<ClientError>) -> String {
+  match (
+    err_report.downcast_ref::<APIError>(),
+    err_report.downcast_ref::<NetworkCause>(),
+  ) {
+    (Some(api_err), _) if api_err.status_code == 404 => "no record (404)".to_string(),
+    (Some(api_err), _) => format!("{} ({})", api_err.message, api_err.status_code),
+    (None, Some(cause)) => cause.to_string(),
+    (None, None) => err_report.current_context().to_string(),
+  }
+}
+
+/// A [Resolver] that coalesces concurrent resolves of the same name into a single call to the
+/// inner resolver (a "singleflight").
+///
+/// The first caller for a name resolves it, and callers that arrive while that's in progress wait
+/// for its result instead of starting their own. Nothing is cached: once the result is in, the next
+/// resolve of the name calls the inner resolver again. If the caller doing the resolve is cancelled,
+/// one of the waiting callers takes over.
+///
+/// Error reports can't be cloned, so waiting callers get a copy of a failure that keeps the
+/// service's [APIError] or the [NetworkCause], and describes the rest of the original report.
+pub struct CoalescingResolver<R> {
+  inner: R,
+  in_flight: Mutex<HashMap<String, Flight>>,
+  next_flight: AtomicU64,
+}
+
+/// A resolve in progress, which other callers can wait for.
+struct Flight {
+  id: u64,
+  result: watch::Receiver<Option<SharedResult>>,
+}
+
+type SharedResult = std::result::Result<ResolvedRevision, Arc<SharedError>>;
+
+/// What's shared of a failed resolve with the callers that waited for it.
+struct SharedError {
+  api_error: Option<(String, StatusCode)>,
+  network_cause: Option<NetworkCause>,
+  details: Vec<String>,
+}
+
+impl SharedError {
+  fn of(err_report: &Report<ClientError>) -> SharedError {
+    let details = err_report
+      .frames()
+      .filter_map(|frame| match frame.kind() {
+        FrameKind::Context(context) => Some(context.to_string()),
+        FrameKind::Attachment(AttachmentKind::Printable(attachment)) => {
+          Some(attachment.to_string())
+        }
+        FrameKind::Attachment(_) => None,
+      })
+      .collect();
+    SharedError {
+      api_error: err_report
+        .downcast_ref::<APIError>()
+        .map(|api_err| (api_err.message.clone(), api_err.status_code)),
+      network_cause: err_report.downcast_ref::<NetworkCause>().copied(),
+      details,
+    }
+  }
+
+  fn to_report(&self) -> Report<ClientError> {
+    let err_report = match (&self.api_error, self.network_cause) {
+      (Some((message, status_code)), _) => report!(APIError {
+        message: message.clone(),
+        status_code: *status_code,
+      })
+      .change_context(ClientError),
+      (None, Some(cause)) => report!(HttpError)
+        .attach_printable(cause)
+        .change_context(ClientError),
+      (None, None) => report!(ClientError),
+    };
+    err_report.attach_printable(format!(
+      "a concurrent resolve of the same name failed: {}",
+      self.details.join(": ")
+    ))
+  }
+}
+
+/// Removes a flight when its leader finishes or is cancelled, so that waiting callers notice.
+struct FlightGuard<'a> {
+  in_flight: &'a Mutex<HashMap<String, Flight>>,
+  key: &'a str,
+  id: u64,
+}
+
+impl Drop for FlightGuard<'_> {
+  fn drop(&mut self) {
+    let mut in_flight = self.in_flight.lock().unwrap();
+    if in_flight
+      .get(self.key)
+      .is_some_and(|flight| flight.id == self.id)
+    {
+      in_flight.remove(self.key);
+    }
+  }
+}
+
+impl<R: Resolver> CoalescingResolver<R> {
+  /// Wraps `inner`, coalescing concurrent resolves of the same name.
+  pub fn new(inner: R) -> Self {
+    CoalescingResolver {
+      inner,
+      in_flight: Mutex::new(HashMap::new()),
+      next_flight: AtomicU64::new(0),
+    }
+  }
+
+  /// Returns the wrapped resolver.
+  pub fn inner(&self) -> &R {
+    &self.inner
+  }
+}
+
+#[async_trait]
+impl<R: Resolver> Resolver for CoalescingResolver<R> {
+  async fn resolve(&self, name: &Name) -> Result<ResolvedRevision, ClientError> {
+    let key = name.to_string();
+    loop {
+      let joined = {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        match in_flight.get(&key) {
+          Some(flight) => Err(flight.result.clone()),
+          None => {
+            let (sender, result) = watch::channel(None);
+            let id = self.next_flight.fetch_add(1, Ordering::Relaxed);
+            in_flight.insert(key.clone(), Flight { id, result });
+            Ok((sender, id))
+          }
+        }
+      };
+
+      match joined {
+        // the first caller resolves the name, and shares the result
+        Ok((sender, id)) => {
+          let guard = FlightGuard {
+            in_flight: &self.in_flight,
+            key: &key,
+            id,
+          };
+          let result = self.inner.resolve(name).await;
+          let shared = match &result {
+            Ok(resolved) => Ok(resolved.clone()),
+            Err(err_report) => Err(Arc::new(SharedError::of(err_report))),
+          };
+          let _ = sender.send(Some(shared));
+          drop(guard);
+          return result;
+        }
+
+        // later callers wait for it
+        Err(mut result) => loop {
+          let current = result.borrow().clone();
+          match current {
+            Some(Ok(resolved)) => return Ok(resolved),
+            Some(Err(shared)) => return Err(shared.to_report()),
+            None => {}
+          }
+          if result.changed().await.is_err() {
+            // the leader was cancelled before finishing, so start over
+            log::debug!("Concurrent resolve of {} was cancelled, retrying", key);
+            break;
+          }
+        },
+      }
+    }
+  }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+  use super::*;
+  use crate::{error::RecordTooLarge, mock_service::MockService, WritableName};
+
+  #[tokio::test]
+  async fn gateway_resolve() {
+    for service in MockService::both() {
+      let gateway = service.gateway();
+      let name = WritableName::new();
+      service
+        .client()
+        .publish_value(&name, "/ipfs/gateway", Default::default())
+        .await
+        .unwrap();
+
+      let revision = gateway.resolve(&name.to_name()).await.unwrap();
+      assert_eq!(revision.value(), "/ipfs/gateway");
+      assert_eq!(revision.source.url, Some(service.url()));
+    }
+  }
+
+  #[tokio::test]
+  async fn gateway_fetch_record() {
+    let service = MockService::start();
+    let name = publish(&service, "/ipfs/gateway").await;
+    let gateway = GatewayResolver::with_gateways(vec![
+      Url::parse("http://127.0.0.1:9").unwrap(),
+      service.url(),
+    ]);
+
+    let record = gateway.fetch_record(&name).await.unwrap();
+    let revision = Revision::from_signed_bytes(&name, &record).unwrap();
+    assert_eq!(revision.value(), "/ipfs/gateway");
+    // fetching doesn't count against the gateways' health
+    assert_eq!(gateway.gateway_status()[0].consecutive_failures, 0);
+
+    let err = GatewayResolver::with_gateways(vec![])
+      .fetch_record(&name)
+      .await
+      .unwrap_err();
+    assert!(format!("{:?}", err).contains("no gateways are configured"));
+  }
+
+  #[tokio::test]
+  async fn service_resolve_provenance() {
+    for service in MockService::both() {
+      let client = service.client();
+      let name = publish(&service, "/ipfs/service").await;
+
+      let before = SystemClock.now();
+      let resolved = Resolver::resolve(&client, &name).await.unwrap();
+      assert_eq!(resolved.value(), "/ipfs/service");
+      assert_eq!(resolved.source.kind, SourceKind::Service);
+      assert_eq!(resolved.source.url.as_ref(), Some(client.endpoint()));
+      assert!(!resolved.source.expiry_checked);
+      assert!(!resolved.from_cache);
+      assert!(resolved.fetched_at >= before);
+      assert_eq!(
+        resolved.into_revision(),
+        client.resolve(&name).await.unwrap()
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn gateway_stops_reading_oversized_responses() {
+    let service = MockService::start();
+    service.serve_garbage(100 * 1024 * 1024);
+
+    let err = GatewayResolver::new(service.url())
+      .resolve(&WritableName::new().to_name())
+      .await
+      .unwrap_err();
+    assert!(err.contains::<RecordTooLarge>());
+    assert!(service.garbage_sent() < 10 * 1024 * 1024);
+  }
+
+  #[tokio::test]
+  async fn gateway_rejects_wrong_content_type() {
+    for service in MockService::both() {
+      let name = WritableName::new();
+      service
+        .client()
+        .publish_value(&name, "/ipfs/gateway", Default::default())
+        .await
+        .unwrap();
+      service.set_gateway_content_type("text/html");
+
+      let err = service
+        .gateway()
+        .resolve(&name.to_name())
+        .await
+        .unwrap_err();
+      assert!(err.contains::<UnexpectedAPIResponse>());
+    }
+  }
+
+  #[tokio::test]
+  async fn gateway_decompresses_records() {
+    for with_header in [true, false] {
+      for service in MockService::both() {
+        let name = publish(&service, "/ipfs/gzipped").await;
+        service.gzip_gateway_records(with_header);
+
+        let revision = service.gateway().resolve(&name).await.unwrap();
+        assert_eq!(revision.value(), "/ipfs/gzipped");
+        let record = service.gateway().fetch_record(&name).await.unwrap();
+        assert!(Revision::from_signed_bytes(&name, &record).is_ok());
+        assert_eq!(
+          service.gateway_accept_encoding().as_deref(),
+          Some("identity")
+        );
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn gateway_limits_decompressed_records() {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    // a small gzip body that decompresses to far more than a record's maximum size
+    let mut bomb = GzEncoder::new(vec![], Compression::best());
+    bomb.write_all(&vec![0; 1024 * 1024]).unwrap();
+    let bomb = bomb.finish().unwrap();
+    assert!(bomb.len() < MAX_RECORD_SIZE);
+
+    for with_header in [true, false] {
+      let transport = Arc::new(crate::ReplayTransport::new());
+      let res = crate::HttpResponse::new(StatusCode::OK)
+        .header(
+          CONTENT_TYPE,
+          HeaderValue::from_static(IPNS_RECORD_CONTENT_TYPE),
+        )
+        .body(bomb.clone());
+      transport.push(if with_header {
+        res.header(
+          http::header::CONTENT_ENCODING,
+          HeaderValue::from_static("gzip"),
+        )
+      } else {
+        res
+      });
+      let err = GatewayResolver::new(Url::parse("https://gateway.example").unwrap())
+        .with_transport(transport)
+        .fetch_record(&WritableName::new().to_name())
+        .await
+        .unwrap_err();
+      assert!(err.contains::<RecordTooLarge>());
+    }
+  }
+
+  /// Publishes a record for a new name to `service`, returning the name.
+  async fn publish(service: &MockService, value: &str) -> Name {
+    let name = WritableName::new();
+    service
+      .client()
+      .publish_value(&name, value, Default::default())
+      .await
+      .unwrap();
+    name.to_name()
+  }
+
+  #[tokio::test]
+  async fn gateways_are_tried_in_order() {
+    let failing = MockService::start();
+    failing.fail_gateway(StatusCode::BAD_GATEWAY);
+    let empty = MockService::start();
+    let working = MockService::start();
+    let name = publish(&working, "/ipfs/fallback").await;
+
+    let gateway = GatewayResolver::with_gateways(vec![failing.url(), empty.url(), working.url()]);
+    let revision = gateway.resolve(&name).await.unwrap();
+    assert_eq!(revision.value(), "/ipfs/fallback");
+    assert_eq!(revision.source.kind, SourceKind::Gateway);
+    assert_eq!(revision.source.url, Some(working.url()));
+    assert!(!revision.from_cache);
+    assert_eq!(failing.gateway_requests(), 1);
+    assert_eq!(empty.gateway_requests(), 1);
+
+    // a 404 isn't a failure, but a 502 is
+    let status = gateway.gateway_status();
+    assert_eq!(status[0].consecutive_failures, 1);
+    assert_eq!(status[1].consecutive_failures, 0);
+    assert_eq!(status[2].consecutive_failures, 0);
+    assert!(status.iter().all(GatewayStatus::is_available));
+  }
+
+  #[tokio::test]
+  async fn chain_falls_back() {
+    let service = MockService::start();
+    let gateway_service = MockService::start();
+    let name = publish(&gateway_service, "/ipfs/chained").await;
+    let chain = ChainResolver::new(Arc::new(service.client()))
+      .with_fallback(Arc::new(gateway_service.gateway()));
+
+    // the service has no record for the name, but the gateway does
+    let resolved = chain.resolve(&name).await.unwrap();
+    assert_eq!(resolved.value(), "/ipfs/chained");
+    assert_eq!(resolved.source.kind, SourceKind::Gateway);
+    assert_eq!(resolved.source.url, Some(gateway_service.url()));
+
+    // the service is down
+    service.fail_resolves(StatusCode::SERVICE_UNAVAILABLE);
+    let resolved = chain.resolve(&name).await.unwrap();
+    assert_eq!(resolved.source.kind, SourceKind::Gateway);
+
+    // the primary answers when it can
+    let primary = publish(&gateway_service, "/ipfs/primary").await;
+    let chain = ChainResolver::new(Arc::new(gateway_service.client()))
+      .with_fallback(Arc::new(service.gateway()));
+    let resolved = chain.resolve(&primary).await.unwrap();
+    assert_eq!(resolved.source.kind, SourceKind::Service);
+    assert_eq!(service.gateway_requests(), 0);
+  }
+
+  #[tokio::test]
+  async fn chain_returns_the_answering_record() {
+    let service = MockService::start();
+    let gateway_service = MockService::start();
+    let name = publish(&gateway_service, "/ipfs/chained").await;
+    let chain = ChainResolver::new(Arc::new(service.client()))
+      .with_fallback(Arc::new(gateway_service.gateway()));
+
+    let (resolved, record) = chain.resolve_with_record(&name).await.unwrap();
+    assert_eq!(resolved.source.kind, SourceKind::Gateway);
+    assert_eq!(record.map(base64::encode), gateway_service.record(&name));
+
+    // the primary's record, when it answers
+    let primary = publish(&service, "/ipfs/primary").await;
+    let (resolved, record) = chain.resolve_with_record(&primary).await.unwrap();
+    assert_eq!(resolved.source.kind, SourceKind::Service);
+    assert_eq!(record.map(base64::encode), service.record(&primary));
+  }
+
+  #[tokio::test]
+  async fn chain_failures_keep_the_primary_error() {
+    let service = MockService::start();
+    let gateway_service = MockService::start();
+    let name = WritableName::new().to_name();
+    let chain = ChainResolver::new(Arc::new(service.client()))
+      .with_fallback(Arc::new(gateway_service.gateway()));
+
+    // no source has a record: still a 404
+    let err = chain.resolve(&name).await.unwrap_err();
+    assert_eq!(
+      err.downcast_ref::<APIError>().map(|err| err.status_code),
+      Some(StatusCode::NOT_FOUND)
+    );
+    assert!(format!("{:?}", err).contains("fallback resolver 0 failed: no record (404)"));
+
+    // everything is down: the service's error, with the gateway's
+    service.fail_resolves(StatusCode::SERVICE_UNAVAILABLE);
+    gateway_service.fail_gateway(StatusCode::BAD_GATEWAY);
+    let err = chain.resolve(&name).await.unwrap_err();
+    assert_eq!(
+      err.downcast_ref::<APIError>().map(|err| err.status_code),
+      Some(StatusCode::SERVICE_UNAVAILABLE)
+    );
+    assert!(
+      format!("{:?}", err).contains("fallback resolver 0 failed: "),
+      "{:?}",
+      err
+    );
+  }
+
+  #[tokio::test]
+  async fn failing_gateways_cool_down() {
+    let failing = MockService::start();
+    failing.fail_gateway(StatusCode::SERVICE_UNAVAILABLE);
+    let working = MockService::start();
+    let name = publish(&working, "/ipfs/healthy").await;
+
+    let cool_down = Duration::from_millis(300);
+    let gateway = GatewayResolver::with_gateways(vec![failing.url(), working.url()])
+      .with_health_policy(HealthPolicy {
+        max_failures: 2,
+        cool_down,
+      });
+
+    for _ in 0..4 {
+      gateway.resolve(&name).await.unwrap();
+    }
+    // skipped after its second failure
+    assert_eq!(failing.gateway_requests(), 2);
+    assert_eq!(working.gateway_requests(), 4);
+    let status = &gateway.gateway_status()[0];
+    assert_eq!(status.consecutive_failures, 2);
+    assert!(!status.is_available());
+    assert!(status.cooling_down_for.unwrap() <= cool_down);
+
+    // tried again after the cool-down, and skipped again straight away when it fails again
+    tokio::time::sleep(cool_down).await;
+    assert!(gateway.gateway_status()[0].is_available());
+    gateway.resolve(&name).await.unwrap();
+    gateway.resolve(&name).await.unwrap();
+    assert_eq!(failing.gateway_requests(), 3);
+    assert_eq!(gateway.gateway_status()[0].consecutive_failures, 3);
+
+    // all gateways cooling down
+    working.fail_gateway(StatusCode::SERVICE_UNAVAILABLE);
+    for _ in 0..2 {
+      assert!(gateway.resolve(&name).await.is_err());
+    }
+    let err = gateway.resolve(&name).await.unwrap_err();
+    assert!(format!("{:?}", err).contains("every gateway is cooling down"));
+    assert_eq!(working.gateway_requests(), 8);
+  }
+
+  #[tokio::test]
+  async fn gateway_list_can_be_cleared() {
+    let gateway = GatewayResolver::with_gateways(vec![]);
+    let err = gateway
+      .resolve(&WritableName::new().to_name())
+      .await
+      .unwrap_err();
+    assert!(format!("{:?}", err).contains("no gateways are configured"));
+    assert!(gateway.gateway_status().is_empty());
+
+    let urls: Vec<Url> = GatewayResolver::default()
+      .gateway_status()
+      .into_iter()
+      .map(|status| status.url)
+      .collect();
+    assert_eq!(urls, default_gateways());
+    assert_eq!(urls[0].as_str(), "https://trustless-gateway.link/");
+  }
+
+  /// A slow resolver that counts its calls, and fails if `not_found` is set.
+  struct CountingResolver {
+    calls: AtomicU64,
+    delay: Duration,
+    not_found: bool,
+  }
+
+  impl CountingResolver {
+    fn new(delay: Duration, not_found: bool) -> Self {
+      CountingResolver {
+        calls: AtomicU64::new(0),
+        delay,
+        not_found,
+      }
+    }
+
+    fn calls(&self) -> u64 {
+      self.calls.load(Ordering::SeqCst)
+    }
+  }
+
+  #[async_trait]
+  impl Resolver for CountingResolver {
+    async fn resolve(&self, name: &Name) -> Result<ResolvedRevision, ClientError> {
+      self.calls.fetch_add(1, Ordering::SeqCst);
+      tokio::time::sleep(self.delay).await;
+      if self.not_found {
+        return Err(
+          report!(APIError {
+            message: "record not found".to_string(),
+            status_code: StatusCode::NOT_FOUND,
+          })
+          .change_context(ClientError),
+        );
+      }
+      let source = SourceInfo::http(
+        SourceKind::Service,
+        Url::parse("https://name.web3.storage").unwrap(),
+        Instant::now(),
+      );
+      Ok(ResolvedRevision::fetched(
+        Revision::v0(name, "/ipfs/coalesced"),
+        source,
+      ))
+    }
+  }
+
+  async fn resolve_concurrently(
+    resolver: &Arc<CoalescingResolver<CountingResolver>>,
+    name: &Name,
+    count: usize,
+  ) -> Vec<Result<ResolvedRevision, ClientError>> {
+    let tasks: Vec<_> = (0..count)
+      .map(|_| {
+        let resolver = resolver.clone();
+        let name = name.clone();
+        tokio::spawn(async move { resolver.resolve(&name).await })
+      })
+      .collect();
+    let mut results = Vec::new();
+    for task in tasks {
+      results.push(task.await.unwrap());
+    }
+    results
+  }
+
+  #[tokio::test]
+  async fn concurrent_resolves_are_coalesced() {
+    let resolver = Arc::new(CoalescingResolver::new(CountingResolver::new(
+      Duration::from_millis(100),
+      false,
+    )));
+    let name = WritableName::new().to_name();
+
+    let results = resolve_concurrently(&resolver, &name, 100).await;
+    assert_eq!(resolver.inner().calls(), 1);
+    for result in results {
+      assert_eq!(result.unwrap().value(), "/ipfs/coalesced");
+    }
+
+    // the result isn't kept once everyone has it
+    resolver.resolve(&name).await.unwrap();
+    assert_eq!(resolver.inner().calls(), 2);
+
+    // other names get their own flights
+    let other = WritableName::new().to_name();
+    let (a, b) = tokio::join!(resolver.resolve(&name), resolver.resolve(&other));
+    assert_eq!(a.unwrap().name(), &name);
+    assert_eq!(b.unwrap().name(), &other);
+    assert_eq!(resolver.inner().calls(), 4);
+  }
+
+  #[tokio::test]
+  async fn coalesced_failures_are_shared() {
+    let resolver = Arc::new(CoalescingResolver::new(CountingResolver::new(
+      Duration::from_millis(100),
+      true,
+    )));
+    let name = WritableName::new().to_name();
+
+    let results = resolve_concurrently(&resolver, &name, 10).await;
+    assert_eq!(resolver.inner().calls(), 1);
+    for result in results {
+      let err = result.unwrap_err();
+      assert_eq!(
+        err.downcast_ref::<APIError>().map(|err| err.status_code),
+        Some(StatusCode::NOT_FOUND)
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn cancelled_leader_is_replaced() {
+    let resolver = Arc::new(CoalescingResolver::new(CountingResolver::new(
+      Duration::from_millis(200),
+      false,
+    )));
+    let name = WritableName::new().to_name();
+
+    let leader = {
+      let (resolver, name) = (resolver.clone(), name.clone());
+      tokio::spawn(async move { resolver.resolve(&name).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let follower = {
+      let (resolver, name) = (resolver.clone(), name.clone());
+      tokio::spawn(async move { resolver.resolve(&name).await })
+    };
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    leader.abort();
+
+    let resolved = follower.await.unwrap().unwrap();
+    assert_eq!(resolved.value(), "/ipfs/coalesced");
+    assert_eq!(resolver.inner().calls(), 2);
+  }
+}
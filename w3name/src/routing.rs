@@ -0,0 +1,225 @@
+//! A client for the IPNS endpoints of the
+//! [Delegated Routing V1 HTTP API](https://specs.ipfs.tech/routing/http-routing-v1/), which lets
+//! clients put records into the DHT through a server that speaks it for them.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use error_stack::{report, Result, ResultExt};
+use http::{
+  header::{HeaderValue, ACCEPT_ENCODING, CONTENT_TYPE},
+  Method,
+};
+use url::Url;
+
+#[cfg(feature = "client")]
+use crate::transport::ReqwestTransport;
+use crate::{
+  content_encoding,
+  error::{APIError, ClientError, PublishError},
+  publisher::SignatureProbe,
+  transport::{self, HttpRequest, HttpTransport},
+  Name, NamePublisher, PublishReceipt, Revision, SignatureMode, WritableName,
+};
+
+const IPNS_RECORD_CONTENT_TYPE: &str = "application/vnd.ipfs.ipns-record";
+/// Error responses are plain text; only this much of them is kept for the error message.
+const MAX_ERROR_BODY_SIZE: usize = 4096;
+
+/// A client for a delegated routing endpoint, e.g. `https://delegated-ipfs.dev`.
+///
+/// As a [NamePublisher], it puts records with `PUT /routing/v1/ipns/<name>`, and remembers if
+/// the server only accepts hybrid V1+V2 records (see [SignatureMode::Auto]).
+pub struct DelegatedRoutingClient {
+  endpoint: Url,
+  transport: Arc<dyn HttpTransport>,
+  signatures: SignatureProbe,
+}
+
+impl DelegatedRoutingClient {
+  /// Creates a client for the delegated routing server at `endpoint`.
+  #[cfg(feature = "client")]
+  pub fn new(endpoint: Url) -> Self {
+    Self::new_with_transport(endpoint, Arc::new(ReqwestTransport::default()))
+  }
+
+  /// Creates a client for the delegated routing server at `endpoint` that puts records with
+  /// `transport`. Unlike [new](Self::new), this doesn't need the `client` feature.
+  pub fn new_with_transport(endpoint: Url, transport: Arc<dyn HttpTransport>) -> Self {
+    DelegatedRoutingClient {
+      endpoint,
+      transport,
+      signatures: SignatureProbe::default(),
+    }
+  }
+
+  /// Puts records with `transport` instead of reqwest.
+  pub fn with_transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+    self.transport = transport;
+    self
+  }
+
+  pub fn endpoint(&self) -> &Url {
+    &self.endpoint
+  }
+
+  /// Puts the serialized, signed `record` for `name`. The server validates it before accepting it.
+  pub async fn put_record(
+    &self,
+    name: &Name,
+    record: Vec<u8>,
+  ) -> Result<PublishReceipt, ClientError> {
+    let mut url = self.endpoint.clone();
+    url.set_path(&format!("routing/v1/ipns/{}", name));
+
+    log::debug!("HTTP PUT {}", url);
+
+    let req = HttpRequest::new(Method::PUT, url)
+      .header(
+        CONTENT_TYPE,
+        HeaderValue::from_static(IPNS_RECORD_CONTENT_TYPE),
+      )
+      .header(ACCEPT_ENCODING, HeaderValue::from_static("identity"))
+      .body(record.clone());
+    let res = transport::execute(self.transport.as_ref(), req).await?;
+
+    log::debug!("Response status: {}", res.status);
+
+    let status = res.status;
+    if !status.is_success() {
+      // a compressed message that can't be decompressed, or is too long, is left out
+      let body =
+        content_encoding::decode(&res.headers, res.body, MAX_ERROR_BODY_SIZE).unwrap_or_default();
+      let body = &body[..body.len().min(MAX_ERROR_BODY_SIZE)];
+      let message = String::from_utf8_lossy(body).trim().to_string();
+      return Err(
+        report!(APIError {
+          message: if message.is_empty() {
+            format!(
+              "delegated routing server {} returned an error",
+              self.endpoint
+            )
+          } else {
+            message
+          },
+          status_code: status,
+        })
+        .change_context(ClientError),
+      );
+    }
+
+    Ok(PublishReceipt {
+      status,
+      headers: res.headers,
+      body: serde_json::from_slice(&res.body).ok(),
+      record,
+      idempotency_key: None,
+    })
+  }
+}
+
+#[async_trait]
+impl NamePublisher for DelegatedRoutingClient {
+  async fn publish(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+  ) -> Result<PublishReceipt, PublishError> {
+    self.publish_as(name, revision, SignatureMode::Auto).await
+  }
+
+  async fn publish_record(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    record: &[u8],
+  ) -> Result<PublishReceipt, PublishError> {
+    let target = name.to_name();
+    self
+      .signatures
+      .publish(
+        &self.target(),
+        name,
+        revision,
+        Some(record),
+        SignatureMode::Auto,
+        |record, _| self.put_record(&target, record),
+      )
+      .await
+      .change_context(PublishError)
+  }
+
+  async fn publish_as(
+    &self,
+    name: &WritableName,
+    revision: &Revision,
+    mode: SignatureMode,
+  ) -> Result<PublishReceipt, PublishError> {
+    let target = name.to_name();
+    self
+      .signatures
+      .publish(&self.target(), name, revision, None, mode, |record, _| {
+        self.put_record(&target, record)
+      })
+      .await
+      .change_context(PublishError)
+  }
+
+  fn target(&self) -> String {
+    format!("routing {}", self.endpoint)
+  }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+  use super::*;
+  use crate::mock_service::MockService;
+
+  #[tokio::test]
+  async fn puts_records() {
+    for service in MockService::both() {
+      let routing = service.routing();
+      let name = WritableName::new();
+      let revision = Revision::v0(&name.to_name(), "/ipfs/routed");
+
+      let receipt = routing.publish(&name, &revision).await.unwrap();
+      assert!(receipt.status.is_success());
+      // the mock serves the records it's given to w3name clients too
+      let resolved = service.client().resolve(&name.to_name()).await.unwrap();
+      assert_eq!(resolved, revision);
+
+      // a record for another name is rejected
+      let other = WritableName::new();
+      let record = revision.to_signed_bytes(&name).unwrap();
+      let err = routing
+        .put_record(&other.to_name(), record)
+        .await
+        .unwrap_err();
+      let api_err = err.downcast_ref::<APIError>().unwrap();
+      assert_eq!(api_err.status_code, 400);
+      assert_eq!(api_err.message, "record is not valid for the name");
+    }
+  }
+
+  #[tokio::test]
+  async fn falls_back_to_hybrid_records() {
+    for service in MockService::both() {
+      service.reject_v2_only();
+      let routing = service.routing();
+      let name = WritableName::new();
+      let revision = Revision::v0(&name.to_name(), "/ipfs/routed");
+
+      let record = revision.to_signed_bytes(&name).unwrap();
+      routing
+        .publish_record(&name, &revision, &record)
+        .await
+        .unwrap();
+      assert_eq!(service.publishes(), 2);
+      assert!(routing.signatures.needs_hybrid());
+
+      let revision = revision.increment("/ipfs/routed-again");
+      routing.publish(&name, &revision).await.unwrap();
+      assert_eq!(service.publishes(), 3);
+    }
+  }
+}
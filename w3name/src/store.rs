@@ -0,0 +1,497 @@
+//! Persistent storage for signed records, keyed by name.
+//!
+//! [RecordStore] is the interface for anything that needs to remember records between runs,
+//! like a disk cache of resolved records or the last record published for each name. Two
+//! implementations are included: [MemoryRecordStore] and [FileRecordStore].
+
+use std::{
+  collections::BTreeMap,
+  fmt::{self, Display},
+  fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+
+use error_stack::{report, Report, Result, ResultExt};
+
+use crate::{
+  error::StoreError, timestamp::Timestamp, Clock, Name, Revision, SystemClock, WritableName,
+};
+use w3name_core::internal::{format_rfc3339, parse_rfc3339};
+
+const RECORD_EXTENSION: &str = "ipns";
+const SEEN_EXTENSION: &str = "seen";
+
+/// A signed record, as kept by a [RecordStore].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredRecord {
+  /// The serialized, signed IPNS record.
+  pub record: Vec<u8>,
+
+  /// When the record was stored.
+  pub stored_at: Timestamp,
+}
+
+/// Stores one signed record per name.
+///
+/// Stores hold the serialized records, and don't validate them: [get_revision](Self::get_revision)
+/// validates a record against its name when it's read.
+pub trait RecordStore: Send + Sync {
+  /// Returns the record stored for `name`, if there is one.
+  fn get(&self, name: &Name) -> Result<Option<StoredRecord>, StoreError>;
+
+  /// Stores `record` for `name`, replacing any record stored before.
+  ///
+  /// If several records are stored for the same name at once, the last one wins.
+  fn put(&self, name: &Name, record: &[u8]) -> Result<(), StoreError>;
+
+  /// Returns the names that have a stored record, sorted by their string form.
+  fn list(&self) -> Result<Vec<Name>, StoreError>;
+
+  /// Removes the record stored for `name`. Returns false if there wasn't one.
+  fn delete(&self, name: &Name) -> Result<bool, StoreError>;
+
+  /// Returns the revision stored for `name`, after validating the record's signature.
+  fn get_revision(&self, name: &Name) -> Result<Option<Revision>, StoreError> {
+    match self.get(name)? {
+      Some(stored) => Revision::try_from_record_bytes(&stored.record, name)
+        .change_context(StoreError)
+        .attach_printable(format!("stored record for {} is invalid", name))
+        .map(Some),
+      None => Ok(None),
+    }
+  }
+
+  /// Notes that `name`'s record with `sequence` was seen at `at`, and returns when a record with
+  /// that sequence was first seen: `at`, unless it was seen before.
+  ///
+  /// This approximates how long a revision has been live, since records don't say when they were
+  /// signed. Only the highest sequence seen for each name is remembered: a higher sequence replaces
+  /// it, and a lower one, as from a service that rolled the name back, is taken as first seen `at`
+  /// without forgetting the higher one.
+  ///
+  /// The default implementation remembers nothing and always returns `at`.
+  fn note_seen(&self, name: &Name, sequence: u64, at: Timestamp) -> Result<Timestamp, StoreError> {
+    let _ = (name, sequence);
+    Ok(at)
+  }
+
+  /// Returns when `name`'s record with `sequence` was first seen, if it's the highest sequence
+  /// [noted](Self::note_seen) for the name, without noting anything.
+  ///
+  /// Names noted before this was kept, or whose notes are unreadable, return `None`, as does the
+  /// default implementation.
+  fn first_seen(&self, name: &Name, sequence: u64) -> Result<Option<Timestamp>, StoreError> {
+    let _ = (name, sequence);
+    Ok(None)
+  }
+
+  /// Signs `revision` with `key`, and stores the record for the revision's name.
+  fn put_revision(&self, key: &WritableName, revision: &Revision) -> Result<(), StoreError> {
+    let record = revision.to_signed_bytes(key).change_context(StoreError)?;
+    self.put(revision.name(), &record)
+  }
+}
+
+/// A [RecordStore] that keeps records in memory, e.g. for tests.
+#[derive(Default)]
+pub struct MemoryRecordStore {
+  records: Mutex<BTreeMap<String, (Name, StoredRecord)>>,
+  seen: Mutex<BTreeMap<String, FirstSeen>>,
+}
+
+impl MemoryRecordStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl RecordStore for MemoryRecordStore {
+  fn get(&self, name: &Name) -> Result<Option<StoredRecord>, StoreError> {
+    let records = self.records.lock().unwrap();
+    Ok(
+      records
+        .get(&name.to_string())
+        .map(|(_, stored)| stored.clone()),
+    )
+  }
+
+  fn put(&self, name: &Name, record: &[u8]) -> Result<(), StoreError> {
+    let stored = StoredRecord {
+      record: record.to_vec(),
+      stored_at: SystemClock.now(),
+    };
+    let mut records = self.records.lock().unwrap();
+    records.insert(name.to_string(), (name.clone(), stored));
+    Ok(())
+  }
+
+  fn list(&self) -> Result<Vec<Name>, StoreError> {
+    let records = self.records.lock().unwrap();
+    Ok(records.values().map(|(name, _)| name.clone()).collect())
+  }
+
+  fn delete(&self, name: &Name) -> Result<bool, StoreError> {
+    let mut records = self.records.lock().unwrap();
+    Ok(records.remove(&name.to_string()).is_some())
+  }
+
+  fn note_seen(&self, name: &Name, sequence: u64, at: Timestamp) -> Result<Timestamp, StoreError> {
+    let mut seen = self.seen.lock().unwrap();
+    let previous = seen.get(&name.to_string()).copied();
+    let (first_seen, update) = FirstSeen::note(previous, sequence, at);
+    if let Some(update) = update {
+      seen.insert(name.to_string(), update);
+    }
+    Ok(first_seen)
+  }
+
+  fn first_seen(&self, name: &Name, sequence: u64) -> Result<Option<Timestamp>, StoreError> {
+    let seen = self.seen.lock().unwrap();
+    Ok(FirstSeen::at(
+      seen.get(&name.to_string()).copied(),
+      sequence,
+    ))
+  }
+}
+
+/// When the highest sequence seen for a name was first seen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FirstSeen {
+  sequence: u64,
+  at: Timestamp,
+}
+
+impl FirstSeen {
+  /// Returns when `sequence` was first seen, given the `previous` entry for its name and that it's
+  /// being seen `at`, and the entry to replace `previous` with, if it should change.
+  fn note(
+    previous: Option<FirstSeen>,
+    sequence: u64,
+    at: Timestamp,
+  ) -> (Timestamp, Option<FirstSeen>) {
+    match previous {
+      Some(previous) if previous.sequence == sequence => (previous.at, None),
+      Some(previous) if previous.sequence > sequence => (at, None),
+      _ => (at, Some(FirstSeen { sequence, at })),
+    }
+  }
+
+  /// Returns when `sequence` was first seen, if it's the sequence of the `noted` entry.
+  fn at(noted: Option<FirstSeen>, sequence: u64) -> Option<Timestamp> {
+    noted
+      .filter(|noted| noted.sequence == sequence)
+      .map(|noted| noted.at)
+  }
+
+  /// Parses the `<sequence> <RFC 3339 timestamp>` form written by [FileRecordStore].
+  fn parse(text: &str) -> Option<FirstSeen> {
+    let (sequence, at) = text.trim().split_once(' ')?;
+    Some(FirstSeen {
+      sequence: sequence.parse().ok()?,
+      at: parse_rfc3339(at).ok()?,
+    })
+  }
+}
+
+impl Display for FirstSeen {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let at = format_rfc3339(&self.at).map_err(|_| fmt::Error)?;
+    writeln!(f, "{} {}", self.sequence, at)
+  }
+}
+
+/// A [RecordStore] that keeps each record in its own file, `<name>.ipns`, in a directory.
+///
+/// The file names double as the store's index, so [list](RecordStore::list) only reads the
+/// directory, and other files in it are ignored. Records are written to a temporary file and then
+/// renamed into place, so readers never see a partially written record, even with several
+/// processes using the same directory.
+///
+/// When the latest sequence of each name was [first seen](RecordStore::note_seen) is kept in
+/// `<name>.seen`, which outlives the record: deleting a record doesn't forget it.
+pub struct FileRecordStore {
+  dir: PathBuf,
+}
+
+impl FileRecordStore {
+  /// Opens the store in `dir`. The directory is created when the first record is stored.
+  pub fn new(dir: impl Into<PathBuf>) -> Self {
+    FileRecordStore { dir: dir.into() }
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Returns the path of the file that holds the record for `name`. The file may not exist.
+  pub fn record_path(&self, name: &Name) -> PathBuf {
+    self
+      .dir
+      .join(format!("{}.{}", name.to_string(), RECORD_EXTENSION))
+  }
+
+  /// Returns the path of the file that holds when `name`'s latest sequence was first seen.
+  pub fn seen_path(&self, name: &Name) -> PathBuf {
+    self
+      .dir
+      .join(format!("{}.{}", name.to_string(), SEEN_EXTENSION))
+  }
+
+  /// Reads what was [noted](RecordStore::note_seen) for `name`. A missing or unreadable file, as
+  /// for names cached before first-seen times were kept, reads as nothing noted.
+  fn read_seen(&self, name: &Name) -> Result<Option<FirstSeen>, StoreError> {
+    let path = self.seen_path(name);
+    match fs::read_to_string(&path) {
+      Ok(text) => {
+        let parsed = FirstSeen::parse(&text);
+        if parsed.is_none() {
+          log::debug!("Ignoring unreadable {}", path.display());
+        }
+        Ok(parsed)
+      }
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(io_error(err, &path)),
+    }
+  }
+
+  /// Writes `bytes` to `path` atomically, through a temporary file.
+  fn write(&self, path: &Path, bytes: &[u8]) -> Result<(), StoreError> {
+    fs::create_dir_all(&self.dir).map_err(|err| io_error(err, &self.dir))?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    // every writer gets its own temporary file, so concurrent writes can't interleave their bytes
+    let tmp_path = self
+      .dir
+      .join(format!(".{}.tmp-{:016x}", file_name, rand::random::<u64>()));
+    // the temporary file is synced before it's renamed, so a crash can't leave a partial file
+    let written = fs::File::create(&tmp_path).and_then(|mut file| {
+      file.write_all(bytes)?;
+      file.sync_all()
+    });
+    if let Err(err) = written.and_then(|_| fs::rename(&tmp_path, path)) {
+      let _ = fs::remove_file(&tmp_path);
+      return Err(io_error(err, path));
+    }
+    Ok(())
+  }
+}
+
+impl RecordStore for FileRecordStore {
+  fn get(&self, name: &Name) -> Result<Option<StoredRecord>, StoreError> {
+    let path = self.record_path(name);
+    let read = fs::read(&path).and_then(|record| {
+      let modified = fs::metadata(&path)?.modified()?;
+      Ok(StoredRecord {
+        record,
+        stored_at: modified.into(),
+      })
+    });
+    match read {
+      Ok(stored) => Ok(Some(stored)),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(io_error(err, &path)),
+    }
+  }
+
+  fn put(&self, name: &Name, record: &[u8]) -> Result<(), StoreError> {
+    self.write(&self.record_path(name), record)
+  }
+
+  fn list(&self) -> Result<Vec<Name>, StoreError> {
+    let entries = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+      Err(err) => return Err(io_error(err, &self.dir)),
+    };
+    let mut names = vec![];
+    for entry in entries {
+      let path = entry.map_err(|err| io_error(err, &self.dir))?.path();
+      if path.extension().is_none_or(|ext| ext != RECORD_EXTENSION) {
+        continue;
+      }
+      let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| Name::parse(stem).ok());
+      match name {
+        Some(name) => names.push(name),
+        None => log::debug!("Ignoring {} in record store", path.display()),
+      }
+    }
+    names.sort_by_key(|name| name.to_string());
+    Ok(names)
+  }
+
+  fn delete(&self, name: &Name) -> Result<bool, StoreError> {
+    let path = self.record_path(name);
+    match fs::remove_file(&path) {
+      Ok(()) => Ok(true),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+      Err(err) => Err(io_error(err, &path)),
+    }
+  }
+
+  fn note_seen(&self, name: &Name, sequence: u64, at: Timestamp) -> Result<Timestamp, StoreError> {
+    let previous = self.read_seen(name)?;
+    let (first_seen, update) = FirstSeen::note(previous, sequence, at);
+    if let Some(update) = update {
+      self.write(&self.seen_path(name), update.to_string().as_bytes())?;
+    }
+    Ok(first_seen)
+  }
+
+  fn first_seen(&self, name: &Name, sequence: u64) -> Result<Option<Timestamp>, StoreError> {
+    Ok(FirstSeen::at(self.read_seen(name)?, sequence))
+  }
+}
+
+fn io_error(err: io::Error, path: &Path) -> Report<StoreError> {
+  report!(err)
+    .change_context(StoreError)
+    .attach_printable(format!("path: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{env, sync::Arc, thread};
+
+  fn temp_store(test: &str) -> FileRecordStore {
+    let dir = env::temp_dir().join(format!("w3name-store-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    FileRecordStore::new(dir)
+  }
+
+  fn check_store(store: &dyn RecordStore) {
+    let key = WritableName::new();
+    let name = key.to_name();
+    assert!(store.get(&name).unwrap().is_none());
+    assert!(store.list().unwrap().is_empty());
+    assert!(!store.delete(&name).unwrap());
+
+    let revision = Revision::v0(&name, "/ipfs/stored");
+    store.put_revision(&key, &revision).unwrap();
+    assert_eq!(store.get_revision(&name).unwrap(), Some(revision.clone()));
+    assert_eq!(store.list().unwrap(), vec![name.clone()]);
+    let stored = store.get(&name).unwrap().unwrap();
+    assert!(stored.stored_at <= SystemClock.now());
+
+    // the store keeps whatever it's given, but get_revision rejects records that don't validate
+    let other = WritableName::new();
+    let forged = Revision::v0(&other.to_name(), "/ipfs/forged")
+      .to_signed_bytes(&other)
+      .unwrap();
+    store.put(&name, &forged).unwrap();
+    assert_eq!(store.get(&name).unwrap().unwrap().record, forged);
+    assert!(store.get_revision(&name).is_err());
+
+    assert!(store.delete(&name).unwrap());
+    assert!(store.get(&name).unwrap().is_none());
+    assert!(store.list().unwrap().is_empty());
+  }
+
+  #[test]
+  fn memory_store() {
+    check_store(&MemoryRecordStore::new());
+  }
+
+  #[test]
+  fn file_store() {
+    let store = temp_store("basics");
+    check_store(&store);
+
+    // files that aren't records are ignored
+    fs::write(store.dir().join("notes.txt"), b"hello").unwrap();
+    fs::write(store.dir().join("not-a-name.ipns"), b"").unwrap();
+    assert!(store.list().unwrap().is_empty());
+    fs::remove_dir_all(store.dir()).unwrap();
+  }
+
+  fn check_first_seen(store: &dyn RecordStore) {
+    let name = WritableName::new().to_name();
+    let start = SystemClock.now() - crate::timestamp::Duration::days(30);
+    let later = |days| start + crate::timestamp::Duration::days(days);
+
+    assert_eq!(store.first_seen(&name, 3).unwrap(), None);
+    assert_eq!(store.note_seen(&name, 3, start).unwrap(), start);
+    assert_eq!(store.note_seen(&name, 3, later(10)).unwrap(), start);
+    assert_eq!(store.first_seen(&name, 3).unwrap(), Some(start));
+    // an older sequence is new to the store, but doesn't replace the newer one
+    assert_eq!(store.note_seen(&name, 2, later(11)).unwrap(), later(11));
+    assert_eq!(store.first_seen(&name, 2).unwrap(), None);
+    assert_eq!(store.note_seen(&name, 3, later(12)).unwrap(), start);
+    // a newer sequence starts over
+    assert_eq!(store.note_seen(&name, 4, later(20)).unwrap(), later(20));
+    assert_eq!(store.note_seen(&name, 4, later(25)).unwrap(), later(20));
+    assert_eq!(store.first_seen(&name, 3).unwrap(), None);
+    assert_eq!(store.first_seen(&name, 4).unwrap(), Some(later(20)));
+  }
+
+  #[test]
+  fn first_seen() {
+    check_first_seen(&MemoryRecordStore::new());
+
+    let store = temp_store("first-seen");
+    check_first_seen(&store);
+    // the first-seen times outlive the records, and aren't records themselves
+    let name = WritableName::new().to_name();
+    let at = SystemClock.now() - crate::timestamp::Duration::days(3);
+    store.note_seen(&name, 1, at).unwrap();
+    assert!(!store.delete(&name).unwrap());
+    assert_eq!(store.note_seen(&name, 1, SystemClock.now()).unwrap(), at);
+    assert!(store.list().unwrap().is_empty());
+
+    // an unreadable file is started over
+    fs::write(store.seen_path(&name), "garbage").unwrap();
+    assert_eq!(store.first_seen(&name, 1).unwrap(), None);
+    let now = SystemClock.now();
+    assert_eq!(store.note_seen(&name, 1, now).unwrap(), now);
+    fs::remove_dir_all(store.dir()).unwrap();
+  }
+
+  fn concurrent_puts(store: Arc<dyn RecordStore>) {
+    let key = WritableName::new();
+    let name = key.to_name();
+    let records: Vec<Vec<u8>> = (0..16)
+      .map(|n| {
+        // large enough to catch torn writes, within the decoding limits for values
+        let value = format!("/ipfs/{}", "x".repeat(250 * n + 1));
+        Revision::v0(&name, value).to_signed_bytes(&key).unwrap()
+      })
+      .collect();
+
+    let records = &records;
+    thread::scope(|scope| {
+      for record in records {
+        for _ in 0..4 {
+          let store = store.clone();
+          let name = &name;
+          scope.spawn(move || {
+            for _ in 0..10 {
+              store.put(name, record).unwrap();
+              // whatever a reader sees is one of the records, in full
+              let stored = store.get(name).unwrap().unwrap();
+              assert!(records.contains(&stored.record));
+            }
+          });
+        }
+      }
+    });
+
+    assert!(store.get_revision(&name).unwrap().is_some());
+    assert_eq!(store.list().unwrap(), vec![name]);
+  }
+
+  #[test]
+  fn concurrent_puts_are_last_writer_wins() {
+    concurrent_puts(Arc::new(MemoryRecordStore::new()));
+
+    let store = temp_store("concurrent");
+    let dir = store.dir().to_path_buf();
+    concurrent_puts(Arc::new(store));
+    // no temporary files are left behind
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,395 @@
+use std::{
+  collections::{BTreeMap, VecDeque},
+  fmt::Display,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use tokio::sync::broadcast;
+
+use crate::{timestamp::Timestamp, Name, ResolvedRevision, Revision};
+use w3name_core::internal::to_std;
+
+/// Settings for a [NameTracker].
+#[derive(Clone, Debug)]
+pub struct TrackerConfig {
+  /// A name is flapping when its record changes this many times within `flap_window`. Defaults to 3.
+  pub flap_changes: usize,
+
+  /// See `flap_changes`. Defaults to 10 minutes.
+  pub flap_window: Duration,
+
+  /// How many events a slow [subscriber](NameTracker::subscribe) may fall behind before it misses
+  /// some. Defaults to 64.
+  pub capacity: usize,
+}
+
+impl Default for TrackerConfig {
+  fn default() -> Self {
+    TrackerConfig {
+      flap_changes: 3,
+      flap_window: Duration::from_secs(10 * 60),
+      capacity: 64,
+    }
+  }
+}
+
+/// What a [NameTracker] knows about a name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackedName {
+  /// The highest sequence number seen.
+  pub sequence: u64,
+
+  /// The value of the record with the highest sequence number.
+  pub value: String,
+
+  /// When the record last changed, i.e. when the highest sequence number (or its value) was first seen.
+  pub changed_at: Timestamp,
+
+  /// When a record for the name was last seen.
+  pub seen_at: Timestamp,
+
+  /// The number of records seen with a lower sequence number than one seen before.
+  pub regressions: u64,
+
+  /// Whether the record changed too often lately (see [TrackerConfig::flap_changes]).
+  pub flapping: bool,
+}
+
+/// Something a [NameTracker] noticed, as sent to its [subscribers](NameTracker::subscribe).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrackerEvent {
+  /// The name's record changed: it has a higher sequence number, or it was seen for the first time.
+  Changed {
+    previous: Option<Box<Revision>>,
+    revision: Box<Revision>,
+  },
+
+  /// A record with a lower sequence number than one seen before, e.g. from a stale replica.
+  /// It's otherwise ignored.
+  Regressed {
+    name: Name,
+    seen: u64,
+    received: u64,
+  },
+
+  /// The name's record changed `changes` times within the flap window.
+  Flapping { name: Name, changes: usize },
+}
+
+impl TrackerEvent {
+  /// Returns the name the event is about.
+  pub fn name(&self) -> &Name {
+    match self {
+      TrackerEvent::Changed { revision, .. } => revision.name(),
+      TrackerEvent::Regressed { name, .. } | TrackerEvent::Flapping { name, .. } => name,
+    }
+  }
+}
+
+impl Display for TrackerEvent {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TrackerEvent::Changed { revision, .. } => write!(
+        f,
+        "{} changed to {} (sequence {})",
+        revision.name(),
+        revision.value(),
+        revision.sequence()
+      ),
+      TrackerEvent::Regressed {
+        name,
+        seen,
+        received,
+      } => write!(
+        f,
+        "sequence of {} went backwards: seen {}, received {}",
+        name, seen, received
+      ),
+      TrackerEvent::Flapping { name, changes } => {
+        write!(f, "{} changed {} times in a short time", name, changes)
+      }
+    }
+  }
+}
+
+struct Entry {
+  revision: Revision,
+  tracked: TrackedName,
+  /// When the recent changes happened, oldest first, within the flap window.
+  changes: VecDeque<Timestamp>,
+}
+
+/// `NameTracker` keeps track of the records seen for any number of names, e.g. by an application
+/// that resolves, watches and publishes them, and notices when they change, go backwards or flap.
+///
+/// Feed it every record with [observe](Self::observe). The latest record of a name is the one with
+/// the highest sequence number seen, so a record from a stale replica doesn't undo a change.
+/// [W3NameClient::watch](crate::W3NameClient::watch) and the [Republisher](crate::Republisher)
+/// feed a tracker themselves if they're given one.
+///
+/// It's cheap to clone, and clones share their state.
+///
+/// ## Example
+///
+/// ```rust
+/// # fn main() {
+/// use w3name::{Clock, NameTracker, Revision, SystemClock, TrackerEvent, WritableName};
+///
+/// let tracker = NameTracker::default();
+/// let mut events = tracker.subscribe();
+///
+/// let name = WritableName::new().to_name();
+/// let v0 = Revision::v0(&name, "/ipfs/first");
+/// tracker.observe_revision(&v0, SystemClock.now());
+/// tracker.observe_revision(&v0.increment("/ipfs/second"), SystemClock.now());
+/// tracker.observe_revision(&v0, SystemClock.now());
+///
+/// assert!(matches!(events.try_recv().unwrap(), TrackerEvent::Changed { previous: None, .. }));
+/// assert!(matches!(events.try_recv().unwrap(), TrackerEvent::Changed { previous: Some(_), .. }));
+/// assert!(matches!(events.try_recv().unwrap(), TrackerEvent::Regressed { seen: 1, received: 0, .. }));
+/// assert_eq!(tracker.get(&name).unwrap().value, "/ipfs/second");
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct NameTracker {
+  config: TrackerConfig,
+  names: Arc<Mutex<BTreeMap<String, Entry>>>,
+  events: broadcast::Sender<TrackerEvent>,
+}
+
+impl NameTracker {
+  pub fn new(config: TrackerConfig) -> NameTracker {
+    let (events, _) = broadcast::channel(config.capacity.max(1));
+    NameTracker {
+      config,
+      names: Arc::new(Mutex::new(BTreeMap::new())),
+      events,
+    }
+  }
+
+  /// Returns a receiver for the events of all tracked names, from now on.
+  pub fn subscribe(&self) -> broadcast::Receiver<TrackerEvent> {
+    self.events.subscribe()
+  }
+
+  /// Records a resolved record, as seen when it was fetched.
+  pub fn observe(&self, resolved: &ResolvedRevision) -> Vec<TrackerEvent> {
+    self.observe_revision(&resolved.revision, resolved.fetched_at)
+  }
+
+  /// Records `revision` as seen at `at`, e.g. right after publishing it. Returns the events it
+  /// caused, which are also sent to the subscribers.
+  pub fn observe_revision(&self, revision: &Revision, at: Timestamp) -> Vec<TrackerEvent> {
+    let events = {
+      let mut names = self.names.lock().unwrap();
+      let key = revision.name().to_string();
+      match names.get_mut(&key) {
+        Some(entry) => self.update(entry, revision, at),
+        None => {
+          names.insert(key, new_entry(revision, at));
+          vec![TrackerEvent::Changed {
+            previous: None,
+            revision: Box::new(revision.clone()),
+          }]
+        }
+      }
+    };
+    for event in &events {
+      // it's fine if nobody is listening
+      let _ = self.events.send(event.clone());
+    }
+    events
+  }
+
+  fn update(&self, entry: &mut Entry, revision: &Revision, at: Timestamp) -> Vec<TrackerEvent> {
+    entry.tracked.seen_at = entry.tracked.seen_at.max(at);
+    let seen = entry.revision.sequence();
+    let received = revision.sequence();
+    if received < seen {
+      entry.tracked.regressions += 1;
+      return vec![TrackerEvent::Regressed {
+        name: revision.name().clone(),
+        seen,
+        received,
+      }];
+    }
+    if received == seen && revision.value() == entry.revision.value() {
+      return vec![];
+    }
+
+    let previous = std::mem::replace(&mut entry.revision, revision.clone());
+    entry.tracked.sequence = received;
+    entry.tracked.value = revision.value().to_string();
+    entry.tracked.changed_at = at;
+    let mut events = vec![TrackerEvent::Changed {
+      previous: Some(Box::new(previous)),
+      revision: Box::new(revision.clone()),
+    }];
+
+    entry.changes.push_back(at);
+    while entry.changes.front().is_some_and(|changed| {
+      to_std(at - *changed).is_some_and(|elapsed| elapsed > self.config.flap_window)
+    }) {
+      entry.changes.pop_front();
+    }
+    let flapping = entry.changes.len() >= self.config.flap_changes.max(1);
+    if flapping && !entry.tracked.flapping {
+      events.push(TrackerEvent::Flapping {
+        name: revision.name().clone(),
+        changes: entry.changes.len(),
+      });
+    }
+    entry.tracked.flapping = flapping;
+    events
+  }
+
+  /// Returns what's known about `name`, or `None` if no record of it was seen.
+  pub fn get(&self, name: &Name) -> Option<TrackedName> {
+    let names = self.names.lock().unwrap();
+    names
+      .get(&name.to_string())
+      .map(|entry| entry.tracked.clone())
+  }
+
+  /// Returns the latest revision seen for `name`.
+  pub fn latest(&self, name: &Name) -> Option<Revision> {
+    let names = self.names.lock().unwrap();
+    names
+      .get(&name.to_string())
+      .map(|entry| entry.revision.clone())
+  }
+
+  /// Returns what's known about every tracked name, keyed by the name's string form.
+  pub fn snapshot(&self) -> BTreeMap<String, TrackedName> {
+    let names = self.names.lock().unwrap();
+    names
+      .iter()
+      .map(|(name, entry)| (name.clone(), entry.tracked.clone()))
+      .collect()
+  }
+}
+
+impl Default for NameTracker {
+  fn default() -> Self {
+    NameTracker::new(TrackerConfig::default())
+  }
+}
+
+impl std::fmt::Debug for NameTracker {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("NameTracker")
+      .field("config", &self.config)
+      .field("names", &self.names.lock().unwrap().len())
+      .finish()
+  }
+}
+
+fn new_entry(revision: &Revision, at: Timestamp) -> Entry {
+  Entry {
+    revision: revision.clone(),
+    tracked: TrackedName {
+      sequence: revision.sequence(),
+      value: revision.value().to_string(),
+      changed_at: at,
+      seen_at: at,
+      regressions: 0,
+      flapping: false,
+    },
+    changes: VecDeque::from([at]),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::WritableName;
+
+  fn at(minutes: i64) -> Timestamp {
+    w3name_core::internal::parse_rfc3339("2025-06-28T12:00:00Z").unwrap()
+      + crate::timestamp::Duration::minutes(minutes)
+  }
+
+  fn drain(events: &mut broadcast::Receiver<TrackerEvent>) -> Vec<TrackerEvent> {
+    std::iter::from_fn(|| events.try_recv().ok()).collect()
+  }
+
+  #[test]
+  fn notifies_of_changes_and_regressions() {
+    let tracker = NameTracker::default();
+    let mut events = tracker.subscribe();
+    let name = WritableName::new().to_name();
+    let v0 = Revision::v0(&name, "/ipfs/first");
+    let v1 = v0.increment("/ipfs/second");
+
+    tracker.observe_revision(&v0, at(0));
+    tracker.observe_revision(&v0, at(1));
+    tracker.observe_revision(&v1, at(20));
+    tracker.observe_revision(&v0, at(21));
+    tracker.observe_revision(&v1, at(22));
+    assert_eq!(
+      drain(&mut events),
+      vec![
+        TrackerEvent::Changed {
+          previous: None,
+          revision: Box::new(v0.clone())
+        },
+        TrackerEvent::Changed {
+          previous: Some(Box::new(v0)),
+          revision: Box::new(v1.clone())
+        },
+        TrackerEvent::Regressed {
+          name: name.clone(),
+          seen: 1,
+          received: 0
+        },
+      ]
+    );
+    assert_eq!(
+      tracker.get(&name),
+      Some(TrackedName {
+        sequence: 1,
+        value: "/ipfs/second".to_string(),
+        changed_at: at(20),
+        seen_at: at(22),
+        regressions: 1,
+        flapping: false,
+      })
+    );
+    assert_eq!(tracker.latest(&name), Some(v1));
+    assert_eq!(tracker.get(&WritableName::new().to_name()), None);
+  }
+
+  #[test]
+  fn detects_flapping() {
+    let tracker = NameTracker::default();
+    let mut events = tracker.subscribe();
+    let name = WritableName::new().to_name();
+    let mut revision = Revision::v0(&name, "/ipfs/a");
+    tracker.observe_revision(&revision, at(0));
+
+    // the first record and three changes within 10 minutes
+    for (minute, value) in [(4, "/ipfs/b"), (8, "/ipfs/a"), (9, "/ipfs/b")] {
+      revision = revision.increment(value);
+      tracker.observe_revision(&revision, at(minute));
+    }
+    let flaps: Vec<_> = drain(&mut events)
+      .into_iter()
+      .filter(|event| matches!(event, TrackerEvent::Flapping { .. }))
+      .collect();
+    assert_eq!(
+      flaps,
+      vec![TrackerEvent::Flapping {
+        name: name.clone(),
+        changes: 3
+      }]
+    );
+    assert!(tracker.get(&name).unwrap().flapping);
+
+    // the next change is far enough from the others
+    revision = revision.increment("/ipfs/c");
+    tracker.observe_revision(&revision, at(30));
+    assert!(!tracker.get(&name).unwrap().flapping);
+    assert_eq!(tracker.snapshot().len(), 1);
+  }
+}
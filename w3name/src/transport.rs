@@ -0,0 +1,313 @@
+//! The HTTP transport that [W3NameClient], [GatewayResolver] and [DelegatedRoutingClient] send
+//! their requests through.
+//!
+//! By default, requests are sent with [reqwest](https://docs.rs/reqwest/latest/reqwest/), by a
+//! [ReqwestTransport]. Environments that can't use reqwest, e.g. because they have their own TLS
+//! stack, can implement [HttpTransport] over their own HTTP client instead, and hand it to
+//! [W3NameClientBuilder::transport], [GatewayResolver::with_transport] or
+//! [DelegatedRoutingClient::with_transport]. Everything above the transport, like building URLs,
+//! rate limiting and validating records, works the same either way.
+//!
+//! [ReplayTransport] answers requests with canned responses, for tests.
+//!
+//! [W3NameClient]: crate::W3NameClient
+//! [W3NameClientBuilder::transport]: crate::W3NameClientBuilder::transport
+//! [GatewayResolver]: crate::GatewayResolver
+//! [GatewayResolver::with_transport]: crate::GatewayResolver::with_transport
+//! [DelegatedRoutingClient]: crate::DelegatedRoutingClient
+//! [DelegatedRoutingClient::with_transport]: crate::DelegatedRoutingClient::with_transport
+
+use std::{collections::VecDeque, fmt::Debug, sync::Mutex};
+
+use async_trait::async_trait;
+#[cfg(feature = "client")]
+use error_stack::Report;
+use error_stack::{report, Result};
+use http::{
+  header::{HeaderMap, HeaderName, HeaderValue},
+  Method, StatusCode,
+};
+use url::Url;
+
+#[cfg(feature = "client")]
+use crate::error::NetworkCause;
+use crate::error::{ClientError, HttpError, RecordTooLarge, TransportError};
+
+/// A request for an [HttpTransport] to send.
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+  pub method: Method,
+  pub url: Url,
+  pub headers: HeaderMap,
+  pub body: Option<Vec<u8>>,
+  /// The longest response body the caller accepts, if there's a limit. Transports should stop
+  /// reading a longer body and fail with [RecordTooLarge]; if they don't, the response is
+  /// rejected once it has been read.
+  pub max_response_size: Option<usize>,
+}
+
+impl HttpRequest {
+  pub fn new(method: Method, url: Url) -> HttpRequest {
+    HttpRequest {
+      method,
+      url,
+      headers: HeaderMap::new(),
+      body: None,
+      max_response_size: None,
+    }
+  }
+
+  pub fn header(mut self, name: HeaderName, value: HeaderValue) -> HttpRequest {
+    self.headers.insert(name, value);
+    self
+  }
+
+  pub fn body(mut self, body: impl Into<Vec<u8>>) -> HttpRequest {
+    self.body = Some(body.into());
+    self
+  }
+
+  pub fn max_response_size(mut self, limit: usize) -> HttpRequest {
+    self.max_response_size = Some(limit);
+    self
+  }
+}
+
+/// A response received by an [HttpTransport], with its whole body.
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+  pub status: StatusCode,
+  pub headers: HeaderMap,
+  pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+  /// Returns an empty response with `status`.
+  pub fn new(status: StatusCode) -> HttpResponse {
+    HttpResponse {
+      status,
+      headers: HeaderMap::new(),
+      body: vec![],
+    }
+  }
+
+  pub fn header(mut self, name: HeaderName, value: HeaderValue) -> HttpResponse {
+    self.headers.insert(name, value);
+    self
+  }
+
+  pub fn body(mut self, body: impl Into<Vec<u8>>) -> HttpResponse {
+    self.body = body.into();
+    self
+  }
+}
+
+/// Sends HTTP requests for the clients in this crate.
+///
+/// A response with an error status is still a response: `execute` only fails if there's no
+/// response at all, e.g. because the connection failed or timed out, or if the response body is
+/// longer than the request's [max_response_size](HttpRequest::max_response_size).
+#[async_trait]
+pub trait HttpTransport: Debug + Send + Sync {
+  async fn execute(&self, req: HttpRequest) -> Result<HttpResponse, TransportError>;
+}
+
+/// The default [HttpTransport], which sends requests with a [reqwest::Client]. Requires the
+/// `client` feature.
+///
+/// Failed requests have their [NetworkCause] attached.
+#[cfg(feature = "client")]
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport {
+  http: reqwest::Client,
+}
+
+#[cfg(feature = "client")]
+impl ReqwestTransport {
+  /// Sends requests with `http`, e.g. one with custom timeouts or proxy settings.
+  pub fn new(http: reqwest::Client) -> ReqwestTransport {
+    ReqwestTransport { http }
+  }
+}
+
+#[cfg(feature = "client")]
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+  async fn execute(&self, req: HttpRequest) -> Result<HttpResponse, TransportError> {
+    let mut builder = self.http.request(req.method, req.url).headers(req.headers);
+    if let Some(body) = req.body {
+      builder = builder.body(body);
+    }
+    let mut res = builder.send().await.map_err(reqwest_error)?;
+
+    // the body is read chunk by chunk, so an oversized response is abandoned without being
+    // downloaded in full
+    let limit = req.max_response_size.unwrap_or(usize::MAX);
+    if res.content_length().unwrap_or(0) > limit as u64 {
+      return Err(too_large(limit));
+    }
+    let mut body = vec![];
+    while let Some(chunk) = res.chunk().await.map_err(reqwest_error)? {
+      if body.len() + chunk.len() > limit {
+        return Err(too_large(limit));
+      }
+      body.extend_from_slice(&chunk);
+    }
+    Ok(HttpResponse {
+      status: res.status(),
+      headers: res.headers().clone(),
+      body,
+    })
+  }
+}
+
+/// Wraps a failed reqwest request in a report, with its [NetworkCause] attached.
+#[cfg(feature = "client")]
+fn reqwest_error(err: reqwest::Error) -> Report<TransportError> {
+  let cause = NetworkCause::of(&err);
+  Report::new(err)
+    .attach_printable(cause)
+    .change_context(TransportError)
+}
+
+#[cfg(feature = "client")]
+fn too_large(limit: usize) -> Report<TransportError> {
+  report!(RecordTooLarge)
+    .attach_printable(format!("limit: {} bytes", limit))
+    .change_context(TransportError)
+}
+
+/// An [HttpTransport] that answers requests with canned responses, in the order they were
+/// [pushed](Self::push), and keeps the requests it was sent, for testing code that uses the
+/// clients without a server.
+///
+/// Requests fail with [TransportError] once the responses run out.
+///
+/// ## Example
+///
+/// ```rust
+/// # #[tokio::main]
+/// # async fn main() {
+/// use std::sync::Arc;
+/// use http::StatusCode;
+/// use w3name::Url;
+/// use w3name::{error::APIError, HttpResponse, ReplayTransport, W3NameClient, WritableName};
+///
+/// let transport = Arc::new(ReplayTransport::new());
+/// transport.push(HttpResponse::new(StatusCode::NOT_FOUND).body(r#"{"message":"not found"}"#));
+/// let client = W3NameClient::builder(Url::parse("https://name.example").unwrap())
+///   .transport(transport.clone())
+///   .build()
+///   .unwrap();
+///
+/// let name = WritableName::new().to_name();
+/// let err = client.resolve(&name).await.unwrap_err();
+/// assert_eq!(err.downcast_ref::<APIError>().unwrap().status_code, StatusCode::NOT_FOUND);
+/// assert_eq!(transport.requests()[0].url.path(), format!("/name/{}", name));
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct ReplayTransport {
+  responses: Mutex<VecDeque<HttpResponse>>,
+  requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl ReplayTransport {
+  pub fn new() -> ReplayTransport {
+    ReplayTransport::default()
+  }
+
+  /// Queues `res` as the answer to a request, after the responses queued before it.
+  pub fn push(&self, res: HttpResponse) {
+    self.responses.lock().unwrap().push_back(res);
+  }
+
+  /// Returns the requests sent so far, in order.
+  pub fn requests(&self) -> Vec<HttpRequest> {
+    self.requests.lock().unwrap().clone()
+  }
+}
+
+#[async_trait]
+impl HttpTransport for ReplayTransport {
+  async fn execute(&self, req: HttpRequest) -> Result<HttpResponse, TransportError> {
+    let description = format!("{} {}", req.method, req.url);
+    self.requests.lock().unwrap().push(req);
+    self.responses.lock().unwrap().pop_front().ok_or_else(|| {
+      report!(TransportError)
+        .attach_printable(format!("no canned response left for {}", description))
+    })
+  }
+}
+
+/// Sends `req` with `transport`, for one of the clients. Failures are reported inside an
+/// [HttpError], except oversized responses, which fail with [RecordTooLarge] whether or not the
+/// transport enforced the limit itself.
+pub(crate) async fn execute(
+  transport: &dyn HttpTransport,
+  req: HttpRequest,
+) -> Result<HttpResponse, ClientError> {
+  let limit = req.max_response_size;
+  let res = transport.execute(req).await.map_err(|err| {
+    if err.contains::<RecordTooLarge>() {
+      err.change_context(ClientError)
+    } else {
+      err.change_context(HttpError).change_context(ClientError)
+    }
+  })?;
+  match limit {
+    Some(limit) if res.body.len() > limit => Err(
+      report!(RecordTooLarge)
+        .attach_printable(format!("limit: {} bytes", limit))
+        .change_context(ClientError),
+    ),
+    _ => Ok(res),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn replays_responses_in_order() {
+    let transport = ReplayTransport::new();
+    transport.push(HttpResponse::new(StatusCode::OK).body("first"));
+    transport.push(HttpResponse::new(StatusCode::ACCEPTED));
+
+    let url = Url::parse("https://name.example/name/k51").unwrap();
+    let res = execute(&transport, HttpRequest::new(Method::GET, url.clone()))
+      .await
+      .unwrap();
+    assert_eq!(res.body, b"first");
+    let req = HttpRequest::new(Method::POST, url.clone()).body("record");
+    let res = execute(&transport, req).await.unwrap();
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+
+    // once they run out, requests fail like network failures do
+    let err = execute(&transport, HttpRequest::new(Method::GET, url))
+      .await
+      .unwrap_err();
+    assert!(err.contains::<HttpError>());
+    assert!(err.contains::<TransportError>());
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[1].method, Method::POST);
+    assert_eq!(requests[1].body.as_deref(), Some(&b"record"[..]));
+  }
+
+  #[tokio::test]
+  async fn enforces_response_limits() {
+    let transport = ReplayTransport::new();
+    transport.push(HttpResponse::new(StatusCode::OK).body(vec![0; 11]));
+    transport.push(HttpResponse::new(StatusCode::OK).body(vec![0; 10]));
+
+    let url = Url::parse("https://name.example/ipns/k51").unwrap();
+    let req = HttpRequest::new(Method::GET, url).max_response_size(10);
+    let err = execute(&transport, req.clone()).await.unwrap_err();
+    assert!(err.contains::<RecordTooLarge>());
+    assert!(!err.contains::<HttpError>());
+    assert_eq!(execute(&transport, req).await.unwrap().body.len(), 10);
+  }
+}
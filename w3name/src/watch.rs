@@ -0,0 +1,326 @@
+use std::time::{Duration, Instant};
+
+use error_stack::{Result, ResultExt};
+use futures::{stream, Stream};
+use rand::Rng;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+  client::Fetched, error::ClientError, Name, NameTracker, ResolvedRevision, Revision, SourceInfo,
+  SourceKind, W3NameClient,
+};
+
+/// Settings for [W3NameClient::watch].
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+  /// The time to wait between polls. Defaults to 60 seconds.
+  pub interval: Duration,
+
+  /// Each wait is up to this much longer, chosen at random, so that many watchers started at once
+  /// don't all poll at once. Defaults to zero.
+  pub jitter: Duration,
+
+  /// If `true`, the stream ends after yielding the first error. Otherwise, errors are yielded and
+  /// polling goes on. Defaults to `false`.
+  pub stop_on_error: bool,
+
+  /// If `true`, the record is yielded after every successful poll, even if it hasn't changed.
+  /// Defaults to `false`.
+  pub yield_unchanged: bool,
+
+  /// If set, the stream ends when this token is cancelled, even while a poll is in flight.
+  pub cancel: Option<CancellationToken>,
+
+  /// If set, every record fetched is fed to this tracker, whether it's yielded or not.
+  pub tracker: Option<NameTracker>,
+}
+
+impl Default for WatchOptions {
+  fn default() -> Self {
+    WatchOptions {
+      interval: Duration::from_secs(60),
+      jitter: Duration::ZERO,
+      stop_on_error: false,
+      yield_unchanged: false,
+      cancel: None,
+      tracker: None,
+    }
+  }
+}
+
+impl W3NameClient {
+  /// Polls the service for `name`'s record, and yields it whenever its sequence number or value
+  /// changes, starting with the record found by the first poll.
+  ///
+  /// A record with a lower sequence number than the last one yielded is a change too, so callers
+  /// that only care about newer records should compare sequence numbers themselves.
+  ///
+  /// Polls only happen while the stream is being consumed: the next poll starts
+  /// [`interval`](WatchOptions::interval) after the consumer asks for the next item, so a slow
+  /// consumer is never handed a backlog. The stream ends when it's dropped, or when the
+  /// [cancellation token](WatchOptions::cancel) is cancelled.
+  ///
+  /// Polls after the first one send the ETag of the last record, so a record that hasn't changed
+  /// costs the service a `304 Not Modified` instead of a full response.
+  ///
+  /// ## Example
+  ///
+  /// ```rust,no_run
+  /// # async fn example(client: w3name::W3NameClient, name: w3name::Name) {
+  /// use futures::StreamExt;
+  /// use w3name::WatchOptions;
+  ///
+  /// let changes = client.watch(&name, WatchOptions::default());
+  /// futures::pin_mut!(changes);
+  /// while let Some(result) = changes.next().await {
+  ///   match result {
+  ///     Ok(resolved) => println!("{}", resolved.value()),
+  ///     Err(err_report) => eprintln!("{err_report:?}"),
+  ///   }
+  /// }
+  /// # }
+  /// ```
+  pub fn watch(
+    &self,
+    name: &Name,
+    opts: WatchOptions,
+  ) -> impl Stream<Item = Result<ResolvedRevision, ClientError>> + Send + 'static {
+    let state = Watch {
+      client: self.clone(),
+      name: name.clone(),
+      opts,
+      last: None,
+      etag: None,
+      polled: false,
+      done: false,
+    };
+    stream::unfold(state, |mut state| async move {
+      let item = match state.opts.cancel.clone() {
+        Some(cancel) => tokio::select! {
+          item = state.next() => item,
+          _ = cancel.cancelled() => None,
+        },
+        None => state.next().await,
+      };
+      item.map(|item| (item, state))
+    })
+  }
+}
+
+/// The state of a [W3NameClient::watch] stream.
+struct Watch {
+  client: W3NameClient,
+  name: Name,
+  opts: WatchOptions,
+  /// The last record fetched.
+  last: Option<Revision>,
+  /// The ETag of `last`, if the service sent one.
+  etag: Option<String>,
+  polled: bool,
+  done: bool,
+}
+
+impl Watch {
+  /// Polls until there's something to yield, or returns `None` if the stream has ended.
+  async fn next(&mut self) -> Option<Result<ResolvedRevision, ClientError>> {
+    loop {
+      if self.done {
+        return None;
+      }
+      if self.polled {
+        tokio::time::sleep(self.delay()).await;
+      }
+      self.polled = true;
+
+      match self.poll().await {
+        Ok(Some(resolved)) => return Some(Ok(resolved)),
+        Ok(None) => continue,
+        Err(err_report) => {
+          self.done = self.opts.stop_on_error;
+          return Some(Err(err_report));
+        }
+      }
+    }
+  }
+
+  fn delay(&self) -> Duration {
+    if self.opts.jitter.is_zero() {
+      self.opts.interval
+    } else {
+      self.opts.interval + rand::thread_rng().gen_range(Duration::ZERO..=self.opts.jitter)
+    }
+  }
+
+  /// Fetches the record, and returns it if it should be yielded.
+  async fn poll(&mut self) -> Result<Option<ResolvedRevision>, ClientError> {
+    let started = Instant::now();
+    let fetched = self
+      .client
+      .fetch_record_if_changed(&self.name, self.etag.as_deref())
+      .await?;
+    let source = SourceInfo::http(SourceKind::Service, self.client.endpoint().clone(), started);
+
+    let changed = match fetched {
+      Fetched::NotModified => false,
+      Fetched::Record { bytes, etag } => {
+        let revision =
+          Revision::try_from_record_bytes(&bytes, &self.name).change_context(ClientError)?;
+        let changed = self.last.as_ref().is_none_or(|last| {
+          last.sequence() != revision.sequence() || last.value() != revision.value()
+        });
+        self.last = Some(revision);
+        self.etag = etag;
+        changed
+      }
+    };
+
+    let Some(last) = &self.last else {
+      return Ok(None);
+    };
+    let resolved = ResolvedRevision::fetched(last.clone(), source);
+    if let Some(tracker) = &self.opts.tracker {
+      tracker.observe(&resolved);
+    }
+    if changed || self.opts.yield_unchanged {
+      Ok(Some(resolved))
+    } else {
+      Ok(None)
+    }
+  }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+  use super::*;
+  use crate::{mock_service::MockService, WritableName};
+  use futures::StreamExt;
+
+  const INTERVAL: Duration = Duration::from_secs(30);
+
+  fn publish(service: &MockService, key: &WritableName, revision: &Revision) {
+    let record = revision.to_signed_bytes(key).unwrap();
+    service.set_record(&key.to_name(), base64::encode(record));
+  }
+
+  fn options() -> WatchOptions {
+    WatchOptions {
+      interval: INTERVAL,
+      ..WatchOptions::default()
+    }
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn yields_changes() {
+    for service in MockService::both() {
+      let key = WritableName::new();
+      let v0 = Revision::v0(&key.to_name(), "/ipfs/first");
+      publish(&service, &key, &v0);
+
+      let mut changes = Box::pin(service.client().watch(&key.to_name(), options()));
+      let first = changes.next().await.unwrap().unwrap();
+      assert_eq!(first.revision, v0);
+      assert_eq!(first.source.kind, SourceKind::Service);
+
+      // the record changes while the next polls are waiting
+      let v1 = v0.increment("/ipfs/second");
+      let next = tokio::spawn(async move { changes.next().await.map(|item| item.unwrap()) });
+      while service.not_modified_responses() < 3 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+      }
+      publish(&service, &key, &v1);
+
+      let second = next.await.unwrap().unwrap();
+      assert_eq!(second.revision, v1);
+      assert_eq!(service.not_modified_responses(), 3);
+    }
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn yields_unchanged_records_if_asked() {
+    let service = MockService::start();
+    let key = WritableName::new();
+    let v0 = Revision::v0(&key.to_name(), "/ipfs/first");
+    publish(&service, &key, &v0);
+
+    let opts = WatchOptions {
+      yield_unchanged: true,
+      ..options()
+    };
+    let polls: Vec<_> = service
+      .client()
+      .watch(&key.to_name(), opts)
+      .take(3)
+      .map(|item| item.unwrap().revision)
+      .collect()
+      .await;
+    assert_eq!(polls, vec![v0.clone(), v0.clone(), v0]);
+    assert_eq!(service.not_modified_responses(), 2);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn feeds_the_tracker() {
+    let service = MockService::start();
+    let key = WritableName::new();
+    let v0 = Revision::v0(&key.to_name(), "/ipfs/first");
+    publish(&service, &key, &v0);
+
+    let tracker = NameTracker::default();
+    let opts = WatchOptions {
+      tracker: Some(tracker.clone()),
+      ..options()
+    };
+    let mut changes = Box::pin(service.client().watch(&key.to_name(), opts));
+    changes.next().await.unwrap().unwrap();
+    let first_seen = tracker.get(&key.to_name()).unwrap().seen_at;
+
+    // unchanged records aren't yielded, but they're seen
+    let next = tokio::spawn(async move { changes.next().await });
+    while service.not_modified_responses() < 1 {
+      tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(tracker.get(&key.to_name()).unwrap().seen_at > first_seen);
+    next.abort();
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn errors_end_the_stream_if_asked() {
+    let service = MockService::start();
+    let name = WritableName::new().to_name();
+
+    let errors: Vec<_> = service
+      .client()
+      .watch(&name, options())
+      .take(2)
+      .collect()
+      .await;
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().all(|item| item.is_err()));
+
+    let opts = WatchOptions {
+      stop_on_error: true,
+      ..options()
+    };
+    let errors: Vec<_> = service.client().watch(&name, opts).collect().await;
+    assert_eq!(errors.len(), 1);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn cancellation_ends_the_stream() {
+    let service = MockService::start();
+    let key = WritableName::new();
+    publish(&service, &key, &Revision::v0(&key.to_name(), "/ipfs/first"));
+
+    let cancel = CancellationToken::new();
+    let opts = WatchOptions {
+      cancel: Some(cancel.clone()),
+      ..options()
+    };
+    let mut changes = Box::pin(service.client().watch(&key.to_name(), opts));
+    assert!(changes.next().await.unwrap().is_ok());
+
+    let next = tokio::spawn(async move { changes.next().await.is_none() });
+    tokio::time::sleep(INTERVAL / 2).await;
+    cancel.cancel();
+    assert!(next.await.unwrap());
+  }
+}